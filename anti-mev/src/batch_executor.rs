@@ -0,0 +1,353 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use std::{ops::Range, sync::Arc};
+
+use crate::{batch_processor::Batch, submitter::TransactionSubmitter};
+
+/// Most compute units a transaction may request
+pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+
+/// How a `BatchExecutor` budgets compute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutorConfig {
+    /// Compute units assumed per instruction when packing transactions
+    pub units_per_instruction: u32,
+    /// Priority fee in micro-lamports per compute unit, none at 0
+    pub compute_unit_price: u64,
+    /// Headroom over the units simulation used, in percent
+    pub compute_margin_percent: u32,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            units_per_instruction: 200_000,
+            compute_unit_price: 0,
+            compute_margin_percent: 10,
+        }
+    }
+}
+
+/// What became of one instruction of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstructionOutcome {
+    /// Landed in the transaction `signature`
+    Executed { signature: Signature },
+    /// Made its transaction fail in simulation
+    Failed { error: String },
+    /// Did not land because its transaction did not
+    RolledBack { reason: String },
+}
+
+/// One transaction a batch was packed into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedTransaction {
+    /// Indices of its instructions in the batch
+    pub instructions: Range<usize>,
+    /// Compute unit limit it requested, 0 if it was not sent
+    pub compute_units: u32,
+    pub signature: Option<Signature>,
+}
+
+/// Result of executing a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchReport {
+    pub batch_id: u64,
+    pub transactions: Vec<PackedTransaction>,
+    /// Outcome of each instruction, in batch order
+    pub outcomes: Vec<InstructionOutcome>,
+}
+
+impl BatchReport {
+    pub fn all_executed(&self) -> bool {
+        self.outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, InstructionOutcome::Executed { .. }))
+    }
+}
+
+/// Executes sealed batches as few atomic transactions as fit them, each
+/// with a compute budget sized from simulation
+pub struct BatchExecutor {
+    payer: Pubkey,
+    submitter: Arc<dyn TransactionSubmitter>,
+    config: ExecutorConfig,
+}
+
+impl BatchExecutor {
+    /// Executor paying fees from `payer` and sending through `submitter`
+    pub fn new(payer: Pubkey, submitter: Arc<dyn TransactionSubmitter>) -> Self {
+        Self {
+            payer,
+            submitter,
+            config: ExecutorConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ExecutorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Split `instructions`, in order, into the fewest runs that each fit
+    /// one transaction by size and assumed compute
+    pub fn pack(&self, instructions: &[Instruction]) -> Result<Vec<Range<usize>>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for end in 1..=instructions.len() {
+            if self.fits(&instructions[start..end]) {
+                continue;
+            }
+            if end - start == 1 {
+                return Err(anyhow!(
+                    "Instruction {} does not fit in a transaction",
+                    start
+                ));
+            }
+            ranges.push(start..end - 1);
+            start = end - 1;
+            if !self.fits(&instructions[start..end]) {
+                return Err(anyhow!(
+                    "Instruction {} does not fit in a transaction",
+                    start
+                ));
+            }
+        }
+        if start < instructions.len() {
+            ranges.push(start..instructions.len());
+        }
+        Ok(ranges)
+    }
+
+    /// Pack `batch`, simulate each transaction to size its compute budget,
+    /// sign it with `signers` and submit it. A transaction lands whole or
+    /// not at all, but the transactions of one batch land independently.
+    pub fn execute(&self, batch: &Batch, signers: &[&dyn Signer]) -> Result<BatchReport> {
+        let ranges = self.pack(&batch.instructions)?;
+        let blockhash = self.submitter.latest_blockhash()?;
+        let mut report = BatchReport {
+            batch_id: batch.id,
+            transactions: Vec::new(),
+            outcomes: Vec::new(),
+        };
+        for range in ranges {
+            let (packed, outcomes) =
+                self.execute_transaction(&batch.instructions, range, signers, &blockhash);
+            report.transactions.push(packed);
+            report.outcomes.extend(outcomes);
+        }
+        Ok(report)
+    }
+
+    /// Execute `batch[range]` as one transaction
+    fn execute_transaction(
+        &self,
+        batch: &[Instruction],
+        range: Range<usize>,
+        signers: &[&dyn Signer],
+        blockhash: &Hash,
+    ) -> (PackedTransaction, Vec<InstructionOutcome>) {
+        let instructions = &batch[range.clone()];
+        let mut packed = PackedTransaction {
+            instructions: range,
+            compute_units: 0,
+            signature: None,
+        };
+        let rolled_back =
+            |reason: String| vec![InstructionOutcome::RolledBack { reason }; instructions.len()];
+
+        let simulated =
+            Transaction::new_unsigned(self.message(instructions, MAX_COMPUTE_UNITS, blockhash));
+        let simulation = match self.submitter.simulate(&simulated) {
+            Ok(simulation) => simulation,
+            Err(e) => return (packed, rolled_back(e.to_string())),
+        };
+        if let Some(error) = simulation.error {
+            return (packed, self.failed_outcomes(instructions.len(), error));
+        }
+
+        let units = match simulation.units_consumed {
+            Some(units) => units * (100 + self.config.compute_margin_percent as u64) / 100,
+            None => self.assumed_units(instructions.len()) as u64,
+        };
+        let units = units.min(MAX_COMPUTE_UNITS as u64) as u32;
+        let message = self.message(instructions, units, blockhash);
+        let required = &message.account_keys[..message.header.num_required_signatures as usize];
+        let mut transaction_signers = Vec::new();
+        for key in required {
+            match signers.iter().find(|signer| signer.pubkey() == *key) {
+                Some(signer) => transaction_signers.push(*signer),
+                None => return (packed, rolled_back(format!("Missing signer {}", key))),
+            }
+        }
+        let mut transaction = Transaction::new_unsigned(message);
+        if let Err(e) = transaction.try_sign(&transaction_signers, *blockhash) {
+            return (packed, rolled_back(format!("Failed to sign: {}", e)));
+        }
+
+        packed.compute_units = units;
+        match self.submitter.submit(&transaction) {
+            Ok(signature) => {
+                packed.signature = Some(signature);
+                let outcomes = vec![InstructionOutcome::Executed { signature }; instructions.len()];
+                (packed, outcomes)
+            }
+            Err(e) => (packed, rolled_back(e.to_string())),
+        }
+    }
+
+    /// Outcomes of a transaction of `count` instructions that failed
+    /// simulation with `error`, blaming the instruction it names
+    fn failed_outcomes(&self, count: usize, error: TransactionError) -> Vec<InstructionOutcome> {
+        let budget = self.budget_instructions(0).len();
+        let failed = match &error {
+            TransactionError::InstructionError(index, _) => (*index as usize).checked_sub(budget),
+            _ => None,
+        };
+        (0..count)
+            .map(|index| match failed {
+                Some(failed) if failed == index => InstructionOutcome::Failed {
+                    error: error.to_string(),
+                },
+                Some(failed) => InstructionOutcome::RolledBack {
+                    reason: format!("Instruction {} of its transaction failed", failed),
+                },
+                None => InstructionOutcome::RolledBack {
+                    reason: error.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    fn fits(&self, instructions: &[Instruction]) -> bool {
+        let units = self.assumed_units(instructions.len());
+        if units > MAX_COMPUTE_UNITS {
+            return false;
+        }
+        let message = self.message(instructions, units, &Hash::default());
+        // Signature count, then a signature per required signer
+        let signatures = message.header.num_required_signatures as usize;
+        1 + 64 * signatures + message.serialize().len() <= PACKET_DATA_SIZE
+    }
+
+    fn assumed_units(&self, instructions: usize) -> u32 {
+        (instructions as u32).saturating_mul(self.config.units_per_instruction)
+    }
+
+    fn budget_instructions(&self, units: u32) -> Vec<Instruction> {
+        let mut budget = vec![ComputeBudgetInstruction::set_compute_unit_limit(units)];
+        if self.config.compute_unit_price > 0 {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_price(
+                self.config.compute_unit_price,
+            ));
+        }
+        budget
+    }
+
+    fn message(&self, instructions: &[Instruction], units: u32, blockhash: &Hash) -> Message {
+        let mut all = self.budget_instructions(units);
+        all.extend_from_slice(instructions);
+        Message::new_with_blockhash(&all, Some(&self.payer), blockhash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::submitter::Simulation;
+    use solana_sdk::{
+        instruction::{AccountMeta, InstructionError},
+        signature::Keypair,
+    };
+
+    /// Fails simulation on instructions with data `[0xff]`
+    struct MockSubmitter;
+
+    impl TransactionSubmitter for MockSubmitter {
+        fn latest_blockhash(&self) -> Result<Hash> {
+            Ok(Hash::new_unique())
+        }
+
+        fn simulate(&self, transaction: &Transaction) -> Result<Simulation> {
+            let failing = transaction
+                .message
+                .instructions
+                .iter()
+                .position(|instruction| instruction.data == [0xff]);
+            Ok(Simulation {
+                units_consumed: Some(50_000),
+                error: failing.map(|index| {
+                    TransactionError::InstructionError(index as u8, InstructionError::Custom(1))
+                }),
+            })
+        }
+
+        fn submit(&self, transaction: &Transaction) -> Result<Signature> {
+            assert!(transaction.verify().is_ok());
+            Ok(transaction.signatures[0])
+        }
+
+        fn signature_status(
+            &self,
+            _signature: &Signature,
+        ) -> Result<Option<solana_sdk::transaction::Result<()>>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_batch_execution() {
+        let payer = Keypair::new();
+        let program = Pubkey::new_unique();
+        let instruction = |data: u8| {
+            Instruction::new_with_bytes(
+                program,
+                &[data],
+                vec![AccountMeta::new(Pubkey::new_unique(), false)],
+            )
+        };
+        let executor = BatchExecutor::new(payer.pubkey(), Arc::new(MockSubmitter));
+
+        // Seven instructions at the assumed 200k units fill a transaction
+        let mut instructions: Vec<Instruction> = (0..10).map(instruction).collect();
+        assert_eq!(executor.pack(&instructions).unwrap(), vec![0..7, 7..10]);
+        // Large instructions are split by size instead
+        let large = Instruction::new_with_bytes(program, &[0u8; 500], vec![]);
+        assert_eq!(executor.pack(&vec![large; 3]).unwrap(), vec![0..2, 2..3]);
+
+        instructions[8] = instruction(0xff);
+        let batch = Batch {
+            id: 4,
+            instructions,
+            created_at: 0,
+            ordering: None,
+        };
+        let report = executor.execute(&batch, &[&payer]).unwrap();
+        assert_eq!(report.transactions.len(), 2);
+        assert_eq!(report.transactions[0].compute_units, 55_000);
+        let signature = report.transactions[0].signature.unwrap();
+        assert!(report.outcomes[..7]
+            .iter()
+            .all(|outcome| *outcome == InstructionOutcome::Executed { signature }));
+        // The failing instruction takes the rest of its transaction with it
+        assert!(matches!(
+            report.outcomes[7],
+            InstructionOutcome::RolledBack { .. }
+        ));
+        assert!(matches!(
+            report.outcomes[8],
+            InstructionOutcome::Failed { .. }
+        ));
+        assert_eq!(report.transactions[1].signature, None);
+        assert!(!report.all_executed());
+    }
+}
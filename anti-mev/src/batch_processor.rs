@@ -1,13 +1,59 @@
-use anyhow::Result;
-use solana_sdk::instruction::Instruction;
-use std::collections::VecDeque;
+use anyhow::{anyhow, Result};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 
-/// Batch processor for grouping transactions
+use untrace_common::AntiMevConfig;
+
+use crate::{
+    ordering::{OrderingKey, OrderingProof},
+    slot_source::SlotSource,
+};
+
+/// Batch processor for grouping transactions. Instructions wait in one
+/// queue per submitter and each batch is drawn from all of them by weight,
+/// so one submitter cannot fill every batch.
 pub struct BatchProcessor {
     batch_size: u32,
-    current_batch: Vec<Instruction>,
+    /// Instructions waiting for a batch, by submitter
+    queues: BTreeMap<Pubkey, VecDeque<Instruction>>,
+    /// Total length of `queues`
+    queued: usize,
     batch_queue: VecDeque<Batch>,
+    /// Submitters of each sealed batch's instructions
+    batch_submitters: HashMap<u64, Vec<Pubkey>>,
+    /// Instructions of each submitter waiting for or in a sealed batch
+    in_flight: HashMap<Pubkey, u32>,
+    /// Most instructions a submitter may have in flight, no limit if `None`
+    max_in_flight: Option<u32>,
+    /// Scheduling weights, 1 for submitters not in here
+    weights: HashMap<Pubkey, u32>,
+    /// Weighted round robin credit of each submitter with a queue
+    credits: HashMap<Pubkey, i64>,
     next_batch_id: u64,
+    /// Age at which `seal_expired` seals the open batch
+    max_age: Option<MaxBatchAge>,
+    /// Set by `with_slot_source`, which `MaxBatchAge::Slots` needs
+    slot_source: Option<Arc<dyn SlotSource>>,
+    /// When the open batch got its first instruction, and at which slot if
+    /// its age is counted in slots
+    opened_at: Option<(Instant, Option<u64>)>,
+    /// Orders sealed batches verifiably when set
+    ordering_key: Option<OrderingKey>,
+}
+
+/// Longest a batch may stay open before it is sealed unfilled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxBatchAge {
+    Time(Duration),
+    Slots(u64),
 }
 
 #[derive(Debug, Clone)]
@@ -15,42 +61,179 @@ pub struct Batch {
     pub id: u64,
     pub instructions: Vec<Instruction>,
     pub created_at: u64,
+    /// Proof of the instructions' order, when the processor has an
+    /// `OrderingKey`
+    pub ordering: Option<OrderingProof>,
 }
 
 impl BatchProcessor {
     pub fn new(batch_size: u32) -> Self {
         Self {
             batch_size,
-            current_batch: Vec::new(),
+            queues: BTreeMap::new(),
+            queued: 0,
             batch_queue: VecDeque::new(),
+            batch_submitters: HashMap::new(),
+            in_flight: HashMap::new(),
+            max_in_flight: None,
+            weights: HashMap::new(),
+            credits: HashMap::new(),
             next_batch_id: 1,
+            max_age: None,
+            slot_source: None,
+            opened_at: None,
+            ordering_key: None,
+        }
+    }
+
+    /// Count batch ages in slots of `slot_source`
+    pub fn with_slot_source(mut self, slot_source: Arc<dyn SlotSource>) -> Self {
+        self.slot_source = Some(slot_source);
+        self
+    }
+
+    /// Seal batches older than `max_age` on `seal_expired`, or only when
+    /// full with `None`
+    pub fn set_max_age(&mut self, max_age: Option<MaxBatchAge>) {
+        self.max_age = max_age;
+    }
+
+    /// Order each sealed batch by `ordering_key`'s VRF, which
+    /// `verify_ordering` checks, instead of by arrival
+    pub fn set_ordering_key(&mut self, ordering_key: Option<OrderingKey>) {
+        self.ordering_key = ordering_key;
+    }
+
+    /// Limit each submitter to `max_in_flight` instructions waiting for or
+    /// in a sealed batch, or lift the limit with `None`
+    pub fn set_max_in_flight(&mut self, max_in_flight: Option<u32>) {
+        self.max_in_flight = max_in_flight;
+    }
+
+    /// Give `submitter` `weight` times the share of a batch of a submitter
+    /// weighing 1
+    pub fn set_weight(&mut self, submitter: Pubkey, weight: u32) {
+        self.weights.insert(submitter, weight.max(1));
+    }
+
+    /// Apply the fair queueing settings of `config`
+    pub fn configure(&mut self, config: &AntiMevConfig) {
+        self.set_max_in_flight(
+            Some(config.max_in_flight_per_user).filter(|&max_in_flight| max_in_flight > 0),
+        );
+        for &(submitter, weight) in &config.user_weights {
+            self.set_weight(submitter, weight);
         }
     }
 
-    /// Add instruction to current batch
+    /// Add instruction to current batch, from no submitter in particular
     pub fn add_to_batch(&mut self, instruction: Instruction) -> Result<()> {
-        self.current_batch.push(instruction);
+        self.add_from(Pubkey::default(), instruction)
+    }
 
-        // If batch is full, seal it and create new batch
-        if self.current_batch.len() >= self.batch_size as usize {
-            self.seal_batch()?;
+    /// Queue `submitter`'s instruction for a batch, refusing it while the
+    /// submitter has as many in flight as allowed
+    pub fn add_from(&mut self, submitter: Pubkey, instruction: Instruction) -> Result<()> {
+        let in_flight = self.in_flight(&submitter);
+        if self.max_in_flight.is_some_and(|max_in_flight| in_flight >= max_in_flight) {
+            return Err(anyhow!(
+                "{} already has {} instructions in flight",
+                submitter,
+                in_flight
+            ));
+        }
+        if self.queued == 0 {
+            let slot = match self.max_age {
+                Some(MaxBatchAge::Slots(_)) => Some(self.current_slot()?),
+                _ => None,
+            };
+            self.opened_at = Some((Instant::now(), slot));
         }
+        self.queues.entry(submitter).or_default().push_back(instruction);
+        self.queued += 1;
+        *self.in_flight.entry(submitter).or_default() += 1;
 
+        // Seal a full batch unless one is still waiting to be taken, so
+        // later instructions get their share of the next one
+        if self.batch_queue.is_empty() {
+            self.seal_full()?;
+        }
+
+        Ok(())
+    }
+
+    /// Instructions of `submitter` waiting for or in a sealed batch
+    pub fn in_flight(&self, submitter: &Pubkey) -> u32 {
+        self.in_flight.get(submitter).copied().unwrap_or(0)
+    }
+
+    fn seal_full(&mut self) -> Result<()> {
+        if self.queued >= self.batch_size as usize {
+            self.seal_batch()?;
+        }
         Ok(())
     }
 
+    /// Draw up to a batch of instructions by smooth weighted round robin
+    /// over the submitters with some queued, with their submitters
+    fn draw_batch(&mut self) -> (Vec<Instruction>, Vec<Pubkey>) {
+        let mut instructions = Vec::new();
+        let mut submitters = Vec::new();
+        while instructions.len() < self.batch_size.max(1) as usize && self.queued > 0 {
+            let mut total = 0;
+            let mut picked: Option<(Pubkey, i64)> = None;
+            for submitter in self.queues.keys() {
+                let weight = self.weights.get(submitter).copied().unwrap_or(1) as i64;
+                total += weight;
+                let credit = self.credits.entry(*submitter).or_default();
+                *credit += weight;
+                match picked {
+                    Some((_, best)) if best >= *credit => {}
+                    _ => picked = Some((*submitter, *credit)),
+                }
+            }
+            let (submitter, _) = picked.expect("queued instructions have a submitter");
+            *self.credits.get_mut(&submitter).unwrap() -= total;
+
+            let queue = self.queues.get_mut(&submitter).unwrap();
+            instructions.push(queue.pop_front().unwrap());
+            if queue.is_empty() {
+                // Credit is not banked while a submitter has nothing queued
+                self.queues.remove(&submitter);
+                self.credits.remove(&submitter);
+            }
+            self.queued -= 1;
+            submitters.push(submitter);
+        }
+        (instructions, submitters)
+    }
+
     /// Seal current batch and move to queue
     fn seal_batch(&mut self) -> Result<()> {
-        if self.current_batch.is_empty() {
+        if self.queued == 0 {
             return Ok(());
         }
 
+        let (instructions, submitters) = self.draw_batch();
+        let (instructions, ordering) = match &self.ordering_key {
+            Some(key) => {
+                let (instructions, proof) = key.order(self.next_batch_id, instructions);
+                (instructions, Some(proof))
+            }
+            None => (instructions, None),
+        };
         let batch = Batch {
             id: self.next_batch_id,
-            instructions: std::mem::take(&mut self.current_batch),
+            instructions,
             created_at: Self::current_timestamp(),
+            ordering,
         };
+        // Instructions left over are as old as the batch they missed
+        if self.queued == 0 {
+            self.opened_at = None;
+        }
 
+        self.batch_submitters.insert(batch.id, submitters);
         self.batch_queue.push_back(batch);
         self.next_batch_id += 1;
 
@@ -59,11 +242,30 @@ impl BatchProcessor {
 
     /// Process next batch in queue
     pub async fn process_batch(&mut self) -> Result<Vec<Instruction>> {
-        if let Some(batch) = self.batch_queue.pop_front() {
-            Ok(batch.instructions)
-        } else {
-            Ok(Vec::new())
+        Ok(self
+            .next_sealed()
+            .map(|batch| batch.instructions)
+            .unwrap_or_default())
+    }
+
+    /// Take the next sealed batch off the queue, for a `BatchExecutor`.
+    /// Its submitters may then send more, and the next batch is drawn if
+    /// enough are waiting.
+    pub fn next_sealed(&mut self) -> Option<Batch> {
+        let batch = self.batch_queue.pop_front()?;
+        for submitter in self.batch_submitters.remove(&batch.id).unwrap_or_default() {
+            if let Some(in_flight) = self.in_flight.get_mut(&submitter) {
+                *in_flight -= 1;
+                if *in_flight == 0 {
+                    self.in_flight.remove(&submitter);
+                }
+            }
+        }
+        if self.batch_queue.is_empty() {
+            // Age is counted in slots only if a slot source answers
+            self.seal_full().ok();
         }
+        Some(batch)
     }
 
     /// Get current batch ID
@@ -76,9 +278,9 @@ impl BatchProcessor {
         self.batch_queue.len()
     }
 
-    /// Get current batch size
+    /// Get number of instructions waiting for a batch
     pub fn current_batch_size(&self) -> usize {
-        self.current_batch.len()
+        self.queued
     }
 
     /// Force seal current batch (even if not full)
@@ -86,11 +288,55 @@ impl BatchProcessor {
         self.seal_batch()
     }
 
-    /// Shuffle instructions in batch for privacy
+    /// Whether the open batch is older than the max age. Never while a
+    /// sealed batch is waiting to be taken, as sealing another would not
+    /// get it executed sooner.
+    pub fn is_expired(&self) -> Result<bool> {
+        let Some((opened_at, opened_slot)) = self.opened_at else {
+            return Ok(false);
+        };
+        if !self.batch_queue.is_empty() {
+            return Ok(false);
+        }
+        match self.max_age {
+            None => Ok(false),
+            Some(MaxBatchAge::Time(age)) => Ok(opened_at.elapsed() >= age),
+            Some(MaxBatchAge::Slots(age)) => match opened_slot {
+                Some(opened_slot) => Ok(self.current_slot()? >= opened_slot + age),
+                // Opened before the age was set in slots, so of unknown age
+                None => Ok(true),
+            },
+        }
+    }
+
+    /// Seal the open batch if it is older than the max age, so a lone
+    /// transaction does not wait for the batch to fill. Whether it sealed.
+    pub fn seal_expired(&mut self) -> Result<bool> {
+        if !self.is_expired()? {
+            return Ok(false);
+        }
+        self.seal_batch()?;
+        Ok(true)
+    }
+
+    fn current_slot(&self) -> Result<u64> {
+        self.slot_source
+            .as_ref()
+            .ok_or_else(|| anyhow!("Batch ages in slots need a slot source"))?
+            .current_slot()
+    }
+
+    /// Shuffle instructions of the next sealed batch for privacy. Nobody
+    /// can check this order; `set_ordering_key` replaces it with one they
+    /// can, so batches it ordered are left alone.
     pub fn shuffle_batch(&mut self) {
         use rand::seq::SliceRandom;
         let mut rng = rand::thread_rng();
-        self.current_batch.shuffle(&mut rng);
+        if let Some(batch) = self.batch_queue.front_mut() {
+            if batch.ordering.is_none() {
+                batch.instructions.shuffle(&mut rng);
+            }
+        }
     }
 
     fn current_timestamp() -> u64 {
@@ -101,9 +347,43 @@ impl BatchProcessor {
     }
 }
 
+/// Background thread sealing a shared processor's expired batches every
+/// `interval`. Stops when dropped.
+pub struct AutoSeal {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AutoSeal {
+    pub fn spawn(processor: Arc<Mutex<BatchProcessor>>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // A slot source that fails now is asked again next time
+                processor.lock().unwrap().seal_expired().ok();
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for AutoSeal {
+    fn drop(&mut self) {
+        // Disconnecting the channel ends the loop
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::slot_source::ManualSlotSource;
     use solana_sdk::pubkey::Pubkey;
 
     fn create_dummy_instruction() -> Instruction {
@@ -141,4 +421,64 @@ mod tests {
         processor.force_seal().unwrap();
         assert_eq!(processor.pending_batches(), 1);
     }
+
+    #[test]
+    fn test_seal_expired() {
+        let mut processor = BatchProcessor::new(10);
+        processor.set_max_age(Some(MaxBatchAge::Time(Duration::from_millis(20))));
+        // Nothing to seal while no batch is open
+        assert!(!processor.seal_expired().unwrap());
+        processor.add_to_batch(create_dummy_instruction()).unwrap();
+        assert!(!processor.seal_expired().unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(processor.seal_expired().unwrap());
+        assert_eq!(processor.pending_batches(), 1);
+
+        let slots = Arc::new(ManualSlotSource::new(100));
+        let mut processor = BatchProcessor::new(10).with_slot_source(slots.clone());
+        processor.set_max_age(Some(MaxBatchAge::Slots(2)));
+        processor.add_to_batch(create_dummy_instruction()).unwrap();
+        slots.set_slot(101);
+        assert!(!processor.seal_expired().unwrap());
+        slots.set_slot(102);
+        assert!(processor.seal_expired().unwrap());
+        assert_eq!(processor.current_batch_size(), 0);
+    }
+
+    #[test]
+    fn test_fair_queueing() {
+        let spammer = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let heavy = Pubkey::new_unique();
+        let mut processor = BatchProcessor::new(4);
+        processor.set_max_in_flight(Some(8));
+        processor.set_weight(heavy, 2);
+
+        for _ in 0..8 {
+            processor.add_from(spammer, create_dummy_instruction()).unwrap();
+        }
+        // The limit holds until a batch of the spammer's is taken
+        assert!(processor.add_from(spammer, create_dummy_instruction()).is_err());
+        assert_eq!(processor.pending_batches(), 1);
+        for _ in 0..3 {
+            processor.add_from(user, create_dummy_instruction()).unwrap();
+            processor.add_from(heavy, create_dummy_instruction()).unwrap();
+            processor.add_from(heavy, create_dummy_instruction()).unwrap();
+        }
+
+        let first = processor.next_sealed().unwrap();
+        assert_eq!(first.instructions.len(), 4);
+        assert_eq!(processor.in_flight(&spammer), 4);
+        processor.add_from(spammer, create_dummy_instruction()).unwrap();
+
+        // The next batch is shared by weight instead of going to the
+        // spammer's earlier instructions
+        let submitters = processor.batch_submitters[&(first.id + 1)].clone();
+        let count = |submitter| submitters.iter().filter(|&&s| s == submitter).count();
+        assert_eq!((count(spammer), count(user), count(heavy)), (1, 1, 2));
+
+        processor.next_sealed().unwrap();
+        assert_eq!(processor.in_flight(&heavy), 4);
+        assert_eq!(processor.pending_batches(), 1);
+    }
 }
@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+use crate::{
+    sandwich::{PoolState, PoolStateSource, PoolVaults, SwapSide},
+    MevProtectionLevel,
+};
+
+/// Front run sizes tried between none and the largest the victim's
+/// minimum output allows
+const FRONT_RUN_STEPS: u64 = 64;
+
+/// Chance that a swap whose sandwich would pay gets one, by how it is sent.
+/// These are assumptions to calibrate against observed markets, not
+/// measurements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MevExposure {
+    /// Sent to the public mempool unprotected
+    pub public: f64,
+    pub basic: f64,
+    pub enhanced: f64,
+    pub maximum: f64,
+}
+
+impl Default for MevExposure {
+    /// A profitable sandwich of a public swap is taken. A time lock only
+    /// delays it, batching hides the swap among others, and an encrypted
+    /// order cannot be read until it is revealed.
+    fn default() -> Self {
+        Self {
+            public: 1.0,
+            basic: 0.8,
+            enhanced: 0.3,
+            maximum: 0.05,
+        }
+    }
+}
+
+impl MevExposure {
+    /// Exposure sent at `protection`, or unprotected with `None`
+    pub fn of(&self, protection: Option<MevProtectionLevel>) -> f64 {
+        match protection {
+            None => self.public,
+            Some(MevProtectionLevel::Basic) => self.basic,
+            Some(MevProtectionLevel::Enhanced) => self.enhanced,
+            Some(MevProtectionLevel::Maximum) => self.maximum,
+        }
+    }
+}
+
+/// What a swap should return and what the most profitable sandwich of it
+/// would leave it, in the unit it buys
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MevLossEstimate {
+    /// Output against the current reserves, with nobody trading first
+    pub expected_out: u64,
+    /// Output under the most profitable sandwich its minimum output allows
+    pub worst_case_out: u64,
+    /// Front run of that sandwich, in the unit the swap pays in. 0 if no
+    /// sandwich of it pays.
+    pub front_run_in: u64,
+    /// What that sandwich makes its attacker, in the same unit
+    pub attacker_profit: u64,
+    exposure: MevExposure,
+}
+
+impl MevLossEstimate {
+    pub fn worst_case_loss(&self) -> u64 {
+        self.expected_out - self.worst_case_out
+    }
+
+    /// Loss to expect sent at `protection`, or unprotected with `None`, for
+    /// a wallet to show beside each protection level
+    pub fn expected_loss(&self, protection: Option<MevProtectionLevel>) -> u64 {
+        (self.worst_case_loss() as f64 * self.exposure.of(protection)).round() as u64
+    }
+}
+
+/// Simulates a swap against a constant-product pool's reserves, alone and
+/// sandwiched, to put a figure on the MEV it is exposed to
+pub struct MevSimulator {
+    /// Reads the current reserves for `estimate`
+    source: Arc<dyn PoolStateSource>,
+    /// Pool fee, kept in the reserves
+    fee_bps: u16,
+    exposure: MevExposure,
+}
+
+impl MevSimulator {
+    /// Simulator for pools taking `fee_bps` of each swap's input
+    pub fn new(source: Arc<dyn PoolStateSource>, fee_bps: u16) -> Self {
+        Self {
+            source,
+            fee_bps,
+            exposure: MevExposure::default(),
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: MevExposure) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Estimate the MEV loss of a `side` swap of `amount_in` that accepts
+    /// no less than `min_out`, against the current reserves of `vaults`
+    pub fn estimate(
+        &self,
+        vaults: &PoolVaults,
+        side: SwapSide,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<MevLossEstimate> {
+        let state = self.source.current_state(vaults)?;
+        self.estimate_at(state, side, amount_in, min_out)
+    }
+
+    /// Estimate against `state` instead of the current reserves
+    pub fn estimate_at(
+        &self,
+        state: PoolState,
+        side: SwapSide,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<MevLossEstimate> {
+        let expected_out = self.swap(&mut state.clone(), side, amount_in);
+        if expected_out < min_out {
+            return Err(anyhow!(
+                "Swap returns {} against the current reserves, under its minimum of {}",
+                expected_out,
+                min_out
+            ));
+        }
+
+        // The victim's output falls as the front run grows, so the largest
+        // front run it still meets its minimum against is found by halving
+        let (mut low, mut high) = (0u64, reserve_in(&state, side).saturating_mul(100));
+        while low < high {
+            let front_run = low + (high - low).div_ceil(2);
+            match self.sandwich(state, side, amount_in, front_run) {
+                Some((victim_out, _)) if victim_out >= min_out => low = front_run,
+                _ => high = front_run - 1,
+            }
+        }
+
+        // Attackers pick the most profitable front run, which with fees
+        // need not be the largest
+        let mut estimate = MevLossEstimate {
+            expected_out,
+            worst_case_out: expected_out,
+            front_run_in: 0,
+            attacker_profit: 0,
+            exposure: self.exposure,
+        };
+        for step in 1..=FRONT_RUN_STEPS {
+            let front_run = (low as u128 * step as u128 / FRONT_RUN_STEPS as u128) as u64;
+            let Some((victim_out, profit)) = self.sandwich(state, side, amount_in, front_run)
+            else {
+                continue;
+            };
+            if profit > estimate.attacker_profit {
+                estimate.worst_case_out = victim_out.min(expected_out);
+                estimate.front_run_in = front_run;
+                estimate.attacker_profit = profit;
+            }
+        }
+        Ok(estimate)
+    }
+
+    /// Victim's output and the attacker's profit when `front_run` is
+    /// swapped the victim's way before it and sold back after. `None` if
+    /// the front run buys nothing.
+    fn sandwich(
+        &self,
+        mut state: PoolState,
+        side: SwapSide,
+        amount_in: u64,
+        front_run: u64,
+    ) -> Option<(u64, u64)> {
+        let bought = self.swap(&mut state, side, front_run);
+        if front_run > 0 && bought == 0 {
+            return None;
+        }
+        let victim_out = self.swap(&mut state, side, amount_in);
+        let back = match side {
+            SwapSide::Buy => SwapSide::Sell,
+            SwapSide::Sell => SwapSide::Buy,
+        };
+        let returned = self.swap(&mut state, back, bought);
+        Some((victim_out, returned.saturating_sub(front_run)))
+    }
+
+    /// Swap `amount_in` into `state` the `side` way, keeping the fee in
+    /// the reserves. What it returns.
+    fn swap(&self, state: &mut PoolState, side: SwapSide, amount_in: u64) -> u64 {
+        let after_fee =
+            (amount_in as u128 * (10_000 - self.fee_bps.min(10_000)) as u128 / 10_000) as u64;
+        match side {
+            SwapSide::Buy => {
+                let out = state.base_out(after_fee);
+                state.quote_reserve = state.quote_reserve.saturating_add(amount_in);
+                state.base_reserve -= out;
+                out
+            }
+            SwapSide::Sell => {
+                let out = state.quote_out(after_fee);
+                state.base_reserve = state.base_reserve.saturating_add(amount_in);
+                state.quote_reserve -= out;
+                out
+            }
+        }
+    }
+}
+
+/// Reserve a `side` swap pays into
+fn reserve_in(state: &PoolState, side: SwapSide) -> u64 {
+    match side {
+        SwapSide::Buy => state.quote_reserve,
+        SwapSide::Sell => state.base_reserve,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::PoolSwap;
+
+    struct NoSource;
+
+    impl PoolStateSource for NoSource {
+        fn pool_swap(&self, _: &str, _: &PoolVaults) -> Result<Option<PoolSwap>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_mev_loss_estimate() {
+        let simulator = MevSimulator::new(Arc::new(NoSource), 30);
+        let state = PoolState {
+            base_reserve: 1_000_000_000,
+            quote_reserve: 1_000_000_000,
+        };
+
+        // 1% slippage leaves room for a sandwich taking up to 1%
+        let estimate = simulator
+            .estimate_at(state, SwapSide::Buy, 10_000_000, 9_772_000)
+            .unwrap();
+        assert_eq!(estimate.expected_out, 9_871_580);
+        assert!(estimate.worst_case_out >= 9_772_000);
+        assert!(estimate.worst_case_loss() > 0);
+        assert!(estimate.attacker_profit > 0);
+        assert!(
+            estimate.expected_loss(None)
+                > estimate.expected_loss(Some(MevProtectionLevel::Enhanced))
+        );
+        assert!(
+            estimate.expected_loss(Some(MevProtectionLevel::Maximum)) < estimate.worst_case_loss()
+        );
+
+        // With no slippage allowed, no front run leaves the swap whole
+        let tight = simulator
+            .estimate_at(state, SwapSide::Sell, 10_000_000, 9_871_580)
+            .unwrap();
+        assert_eq!(tight.worst_case_loss(), 0);
+        assert_eq!(tight.front_run_in, 0);
+
+        // A minimum the reserves cannot meet fails even unattacked
+        assert!(simulator
+            .estimate_at(state, SwapSide::Buy, 10_000_000, 9_900_000)
+            .is_err());
+        assert!(simulator
+            .estimate(
+                &PoolVaults {
+                    pool: Default::default(),
+                    base_vault: Default::default(),
+                    quote_vault: Default::default(),
+                },
+                SwapSide::Buy,
+                1,
+                0
+            )
+            .is_err());
+    }
+}
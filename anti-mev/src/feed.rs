@@ -0,0 +1,620 @@
+use anyhow::{anyhow, Result};
+use solana_client::{
+    pubsub_client::{PubsubClient, PubsubLogsClientSubscription},
+    rpc_client::RpcClient,
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+    UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{MevDetector, TransactionEvent, TransactionType};
+
+/// Signatures remembered so a transaction seen on two markets counts once
+const RECENT_SIGNATURES: usize = 4096;
+
+/// Mint `TokenDelta` uses for native SOL
+pub const NATIVE_SOL: Pubkey = Pubkey::new_from_array([0u8; 32]);
+
+/// Transaction seen on a followed market, as the feed decodes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    /// Unix time of its block, when the node knows it
+    pub block_time: Option<i64>,
+    /// Accounts the transaction loaded, fee payer first
+    pub account_keys: Vec<Pubkey>,
+    pub logs: Vec<String>,
+    /// Change of each owner's balance of each mint
+    pub token_deltas: Vec<TokenDelta>,
+    /// Balance of each token account before and after, pool vaults too
+    pub token_balances: Vec<TokenBalance>,
+}
+
+/// Change of `owner`'s balance of `mint` in base units. SOL changes are
+/// under `NATIVE_SOL`, with the fee left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenDelta {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub delta: i128,
+}
+
+/// Balance of token account `account` of `mint` before and after a
+/// transaction, in base units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBalance {
+    pub account: Pubkey,
+    pub mint: Pubkey,
+    pub pre: u64,
+    pub post: u64,
+}
+
+impl ObservedTransaction {
+    /// Decode a transaction fetched with `getTransaction`
+    pub fn from_rpc(
+        signature: String,
+        transaction: EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<Self> {
+        let decoded = transaction
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("Undecodable transaction {}", signature))?;
+        let meta = transaction
+            .transaction
+            .meta
+            .ok_or_else(|| anyhow!("Transaction {} has no status meta", signature))?;
+
+        let mut account_keys = decoded.message.static_account_keys().to_vec();
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            for key in loaded.writable.iter().chain(loaded.readonly.iter()) {
+                account_keys.push(Pubkey::from_str(key)?);
+            }
+        }
+
+        let mut token_deltas = Vec::new();
+        for (index, owner) in account_keys.iter().enumerate() {
+            let (Some(pre), Some(post)) =
+                (meta.pre_balances.get(index), meta.post_balances.get(index))
+            else {
+                continue;
+            };
+            let fee = if index == 0 { meta.fee } else { 0 };
+            let delta = *post as i128 - *pre as i128 + fee as i128;
+            if delta != 0 {
+                token_deltas.push(TokenDelta {
+                    owner: *owner,
+                    mint: NATIVE_SOL,
+                    delta,
+                });
+            }
+        }
+        let empty = Vec::new();
+        let balances = |balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>| {
+            let balances = match balances {
+                OptionSerializer::Some(balances) => balances,
+                _ => &empty,
+            };
+            balances
+                .iter()
+                .filter_map(|balance| {
+                    let OptionSerializer::Some(owner) = &balance.owner else {
+                        return None;
+                    };
+                    let key = (
+                        Pubkey::from_str(owner).ok()?,
+                        Pubkey::from_str(&balance.mint).ok()?,
+                    );
+                    Some((key, balance.ui_token_amount.amount.parse::<i128>().ok()?))
+                })
+                .fold(HashMap::new(), |mut totals, (key, amount)| {
+                    *totals.entry(key).or_insert(0i128) += amount;
+                    totals
+                })
+        };
+        let pre = balances(&meta.pre_token_balances);
+        let mut post = balances(&meta.post_token_balances);
+        for (key, amount) in pre {
+            *post.entry(key).or_insert(0) -= amount;
+        }
+        token_deltas.extend(
+            post.into_iter()
+                .filter(|(_, delta)| *delta != 0)
+                .map(|((owner, mint), delta)| TokenDelta { owner, mint, delta }),
+        );
+
+        let mut token_balances: Vec<TokenBalance> = Vec::new();
+        let sides = [
+            (&meta.pre_token_balances, false),
+            (&meta.post_token_balances, true),
+        ];
+        for (balances, is_post) in sides {
+            let OptionSerializer::Some(balances) = balances else {
+                continue;
+            };
+            for balance in balances {
+                let (Some(&account), Ok(mint), Ok(amount)) = (
+                    account_keys.get(balance.account_index as usize),
+                    Pubkey::from_str(&balance.mint),
+                    balance.ui_token_amount.amount.parse::<u64>(),
+                ) else {
+                    continue;
+                };
+                let index = match token_balances.iter().position(|b| b.account == account) {
+                    Some(index) => index,
+                    None => {
+                        token_balances.push(TokenBalance {
+                            account,
+                            mint,
+                            pre: 0,
+                            post: 0,
+                        });
+                        token_balances.len() - 1
+                    }
+                };
+                if is_post {
+                    token_balances[index].post = amount;
+                } else {
+                    token_balances[index].pre = amount;
+                }
+            }
+        }
+
+        let logs = match meta.log_messages {
+            OptionSerializer::Some(logs) => logs,
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            signature,
+            slot: transaction.slot,
+            block_time: transaction.block_time,
+            account_keys,
+            logs,
+            token_deltas,
+            token_balances,
+        })
+    }
+
+    /// Whether `program` was invoked, by the logs
+    pub fn invokes(&self, program: &Pubkey) -> bool {
+        let prefix = format!("Program {} invoke", program);
+        self.logs.iter().any(|log| log.starts_with(&prefix))
+    }
+
+    /// The event the detector records for this transaction. Swaps are
+    /// told by their programs' logs, and the amount is the fee payer's
+    /// largest balance change. `None` for transactions of another kind.
+    pub fn to_event(&self) -> Option<TransactionEvent> {
+        let logged = |names: &[&str]| {
+            self.logs.iter().any(|log| {
+                names
+                    .iter()
+                    .any(|name| log.contains(&format!("Instruction: {}", name)))
+            })
+        };
+        // Swaps log the token transfers they make, so they are told first
+        let tx_type = if logged(&["Swap", "Route", "SharedAccountsRoute"])
+            || self.logs.iter().any(|log| log.contains("ray_log"))
+        {
+            TransactionType::Swap
+        } else if logged(&["Deposit", "IncreaseLiquidity"]) {
+            TransactionType::Deposit
+        } else if logged(&["Withdraw", "DecreaseLiquidity"]) {
+            TransactionType::Withdraw
+        } else if logged(&["Transfer", "TransferChecked"]) {
+            TransactionType::Transfer
+        } else {
+            return None;
+        };
+
+        let account = *self.account_keys.first()?;
+        let amount = self
+            .token_deltas
+            .iter()
+            .filter(|delta| delta.owner == account)
+            .map(|delta| delta.delta.unsigned_abs())
+            .max()
+            .unwrap_or_default();
+        let timestamp = match self.block_time {
+            Some(block_time) => block_time.max(0) as u64,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+        };
+        Some(TransactionEvent {
+            account,
+            amount: u64::try_from(amount).unwrap_or(u64::MAX),
+            timestamp,
+            tx_type,
+        })
+    }
+}
+
+/// Which transactions the feed follows and keeps
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedFilter {
+    /// Pools, markets or programs whose transactions are followed, one
+    /// log subscription each
+    pub markets: Vec<Pubkey>,
+    /// Programs a kept transaction must invoke, any when empty
+    pub programs: HashSet<Pubkey>,
+    /// Smallest amount kept
+    pub min_amount: u64,
+}
+
+impl FeedFilter {
+    /// Follow the transactions mentioning `markets`
+    pub fn new(markets: Vec<Pubkey>) -> Self {
+        Self {
+            markets,
+            ..Self::default()
+        }
+    }
+
+    /// Only keep transactions invoking `program`
+    pub fn with_program(mut self, program: Pubkey) -> Self {
+        self.programs.insert(program);
+        self
+    }
+
+    pub fn with_min_amount(mut self, min_amount: u64) -> Self {
+        self.min_amount = min_amount;
+        self
+    }
+
+    /// Event of `transaction` if it passes the filter
+    pub fn event(&self, transaction: &ObservedTransaction) -> Option<TransactionEvent> {
+        if !self.programs.is_empty()
+            && !self
+                .programs
+                .iter()
+                .any(|program| transaction.invokes(program))
+        {
+            return None;
+        }
+        transaction
+            .to_event()
+            .filter(|event| event.amount >= self.min_amount)
+    }
+}
+
+/// What ingestion does when the consumer falls behind and the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for room, leaving notifications to queue in the websocket
+    /// client, so no event is lost
+    Block,
+    /// Drop the new event and count it, so the feed stays current
+    DropNewest,
+}
+
+/// Settings of a `TransactionFeed`
+#[derive(Debug, Clone, Copy)]
+pub struct FeedConfig {
+    /// Events queued for the consumer
+    pub capacity: usize,
+    pub backpressure: Backpressure,
+    pub commitment: CommitmentConfig,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            backpressure: Backpressure::DropNewest,
+            commitment: CommitmentConfig::confirmed(),
+        }
+    }
+}
+
+/// Counts of what the feed ingested
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeedStats {
+    /// Transactions pushed into the feed, repeats included
+    pub observed: u64,
+    /// Events queued for the consumer
+    pub queued: u64,
+    /// Events dropped for a full queue
+    pub dropped: u64,
+    /// Transactions that could not be fetched or decoded
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct FeedCounters {
+    observed: AtomicU64,
+    queued: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct RecentSignatures {
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl RecentSignatures {
+    /// Remember `signature`, returning whether it is new
+    fn insert(&mut self, signature: &str) -> bool {
+        if !self.seen.insert(signature.to_string()) {
+            return false;
+        }
+        self.order.push_back(signature.to_string());
+        if self.order.len() > RECENT_SIGNATURES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Entry of transactions into a feed. Log subscriptions push into it, and
+/// so can any other source, such as a Geyser stream.
+#[derive(Debug, Clone)]
+pub struct FeedSink {
+    sender: SyncSender<TransactionEvent>,
+    filter: Arc<FeedFilter>,
+    backpressure: Backpressure,
+    counters: Arc<FeedCounters>,
+    recent: Arc<Mutex<RecentSignatures>>,
+}
+
+impl FeedSink {
+    /// Filter `transaction` and queue its event. Returns whether it was
+    /// queued; fails once the feed is dropped.
+    pub fn push(&self, transaction: &ObservedTransaction) -> Result<bool> {
+        self.counters.observed.fetch_add(1, Ordering::Relaxed);
+        if !self.recent.lock().unwrap().insert(&transaction.signature) {
+            return Ok(false);
+        }
+        let Some(event) = self.filter.event(transaction) else {
+            return Ok(false);
+        };
+
+        let sent = match self.backpressure {
+            Backpressure::Block => self.sender.send(event).map_err(|_| ()),
+            Backpressure::DropNewest => match self.sender.try_send(event) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(false);
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+            },
+        };
+        sent.map_err(|_| anyhow!("Transaction feed was dropped"))?;
+        self.counters.queued.fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Count a transaction that could not be fetched or decoded
+    pub fn record_failure(&self) {
+        self.counters.failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Stream of `TransactionEvent`s observed on chain, for `MevDetector`
+pub struct TransactionFeed {
+    events: Receiver<TransactionEvent>,
+    counters: Arc<FeedCounters>,
+    /// In a mutex only so the feed is `Sync`
+    subscriptions: Mutex<Vec<PubsubLogsClientSubscription>>,
+}
+
+impl TransactionFeed {
+    /// Feed with nothing behind it yet, and the sink sources push into
+    pub fn channel(filter: FeedFilter, config: FeedConfig) -> (FeedSink, Self) {
+        let (sender, events) = mpsc::sync_channel(config.capacity.max(1));
+        let counters = Arc::new(FeedCounters::default());
+        let sink = FeedSink {
+            sender,
+            filter: Arc::new(filter),
+            backpressure: config.backpressure,
+            counters: counters.clone(),
+            recent: Arc::default(),
+        };
+        let feed = Self {
+            events,
+            counters,
+            subscriptions: Mutex::new(Vec::new()),
+        };
+        (sink, feed)
+    }
+
+    /// Subscribe at `ws_url` to the logs of transactions mentioning each of
+    /// `filter.markets`, fetching each successful one from `rpc_url` to
+    /// read its accounts and balance changes
+    pub fn connect(
+        ws_url: &str,
+        rpc_url: &str,
+        filter: FeedFilter,
+        config: FeedConfig,
+    ) -> Result<Self> {
+        if filter.markets.is_empty() {
+            return Err(anyhow!("The feed filter follows no markets"));
+        }
+        let markets = filter.markets.clone();
+        let (sink, feed) = Self::channel(filter, config);
+        for market in markets {
+            let (subscription, receiver) = PubsubClient::logs_subscribe(
+                ws_url,
+                RpcTransactionLogsFilter::Mentions(vec![market.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(config.commitment),
+                },
+            )
+            .map_err(|e| anyhow!("Failed to subscribe to logs of {}: {}", market, e))?;
+            feed.subscriptions.lock().unwrap().push(subscription);
+
+            let sink = sink.clone();
+            let client = RpcClient::new_with_commitment(rpc_url.to_string(), config.commitment);
+            thread::spawn(move || {
+                for update in receiver {
+                    if update.value.err.is_some() {
+                        continue;
+                    }
+                    let transaction = fetch_transaction(&client, update.value.signature);
+                    let pushed = match transaction {
+                        Ok(transaction) => sink.push(&transaction),
+                        Err(_) => {
+                            sink.record_failure();
+                            continue;
+                        }
+                    };
+                    if pushed.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Ok(feed)
+    }
+
+    /// Next queued event, without waiting
+    pub fn try_next(&self) -> Option<TransactionEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Next event, waiting up to `timeout`. `None` on timeout or once
+    /// every source has stopped.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<TransactionEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Record every queued event in `detector`, returning how many
+    pub fn drain_into(&self, detector: &mut MevDetector) -> usize {
+        let mut recorded = 0;
+        while let Some(event) = self.try_next() {
+            detector.record_event(event);
+            recorded += 1;
+        }
+        recorded
+    }
+
+    pub fn stats(&self) -> FeedStats {
+        FeedStats {
+            observed: self.counters.observed.load(Ordering::Relaxed),
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for TransactionFeed {
+    fn drop(&mut self) {
+        for mut subscription in self.subscriptions.get_mut().unwrap().drain(..) {
+            subscription.shutdown().ok();
+        }
+    }
+}
+
+pub(crate) fn fetch_transaction(
+    client: &RpcClient,
+    signature: String,
+) -> Result<ObservedTransaction> {
+    let transaction = client.get_transaction_with_config(
+        &Signature::from_str(&signature)?,
+        RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(client.commitment()),
+            max_supported_transaction_version: Some(0),
+        },
+    )?;
+    ObservedTransaction::from_rpc(signature, transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(signature: &str, payer: Pubkey, program: Pubkey, amount: i128) -> ObservedTransaction {
+        ObservedTransaction {
+            signature: signature.to_string(),
+            slot: 10,
+            block_time: Some(100),
+            account_keys: vec![payer, Pubkey::new_unique()],
+            logs: vec![
+                format!("Program {} invoke [1]", program),
+                "Program log: Instruction: Swap".to_string(),
+                "Program log: Instruction: Transfer".to_string(),
+            ],
+            token_deltas: vec![
+                TokenDelta {
+                    owner: payer,
+                    mint: Pubkey::new_unique(),
+                    delta: -amount,
+                },
+                TokenDelta {
+                    owner: payer,
+                    mint: NATIVE_SOL,
+                    delta: amount / 2,
+                },
+            ],
+            token_balances: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_feed_filtering_and_backpressure() {
+        let (payer, amm) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let filter = FeedFilter::new(vec![Pubkey::new_unique()])
+            .with_program(amm)
+            .with_min_amount(500);
+        let config = FeedConfig {
+            capacity: 1,
+            ..FeedConfig::default()
+        };
+        let (sink, feed) = TransactionFeed::channel(filter, config);
+
+        let event = swap("a", payer, amm, 1_000).to_event().unwrap();
+        assert!(matches!(event.tx_type, TransactionType::Swap));
+        assert_eq!(
+            (event.account, event.amount, event.timestamp),
+            (payer, 1_000, 100)
+        );
+
+        assert!(sink.push(&swap("a", payer, amm, 1_000)).unwrap());
+        // Repeats, small swaps and other programs are filtered out
+        assert!(!sink.push(&swap("a", payer, amm, 1_000)).unwrap());
+        assert!(!sink.push(&swap("b", payer, amm, 100)).unwrap());
+        assert!(!sink
+            .push(&swap("c", payer, Pubkey::new_unique(), 1_000))
+            .unwrap());
+        // The queue holds one event, so the next is dropped
+        assert!(!sink.push(&swap("d", payer, amm, 2_000)).unwrap());
+        assert_eq!(
+            feed.stats(),
+            FeedStats {
+                observed: 5,
+                queued: 1,
+                dropped: 1,
+                failed: 0,
+            }
+        );
+
+        let mut detector = MevDetector::new(10);
+        assert_eq!(feed.drain_into(&mut detector), 1);
+        assert!(sink.push(&swap("e", payer, amm, 2_000)).unwrap());
+        drop(feed);
+        assert!(sink.push(&swap("f", payer, amm, 2_000)).is_err());
+    }
+}
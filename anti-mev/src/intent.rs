@@ -0,0 +1,237 @@
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use rand::RngCore;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_instruction,
+};
+use untrace_common::crypto::intent_hash;
+use untrace_privacy_program::{accounts, instruction};
+
+/// Account `lock_intent` creates for `owner`'s intent `intent_id`
+pub fn intent_address(program_id: &Pubkey, owner: &Pubkey, intent_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"intent", owner.as_ref(), &intent_id.to_le_bytes()],
+        program_id,
+    )
+    .0
+}
+
+/// Vault that signs for the intent at `intent` once it unlocks
+pub fn intent_vault_address(program_id: &Pubkey, intent: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"intent_vault", intent.as_ref()], program_id).0
+}
+
+/// Instruction its owner locks on chain until `unlock_slot`. Only its hash
+/// is recorded, and the privacy program refuses to run it any earlier. The
+/// instruction is signed by the intent's vault instead of the owner, so
+/// what it spends must be moved to the vault, where only the privacy
+/// program can move it from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedIntent {
+    pub owner: Pubkey,
+    pub intent_id: u64,
+    pub vault: Pubkey,
+    pub instruction: Instruction,
+    /// Keeps a guessable instruction from being checked against the hash
+    pub salt: [u8; 32],
+    pub unlock_slot: u64,
+}
+
+impl LockedIntent {
+    /// Intent to run `instruction` on `program_id` from `unlock_slot`,
+    /// under a random id and salt. Wherever `owner` signs the instruction,
+    /// the vault signs instead.
+    pub fn new(
+        program_id: &Pubkey,
+        owner: Pubkey,
+        mut instruction: Instruction,
+        unlock_slot: u64,
+    ) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+        let intent_id = rng.next_u64();
+        let vault =
+            intent_vault_address(program_id, &intent_address(program_id, &owner, intent_id));
+        for account in &mut instruction.accounts {
+            if account.pubkey == owner && account.is_signer {
+                account.pubkey = vault;
+            }
+        }
+        Self {
+            owner,
+            intent_id,
+            vault,
+            instruction,
+            salt,
+            unlock_slot,
+        }
+    }
+
+    /// Moves `lamports` from the owner to the vault. The vault must hold at
+    /// least the rent-exempt minimum for an empty account.
+    pub fn fund_instruction(&self, lamports: u64) -> Instruction {
+        system_instruction::transfer(&self.owner, &self.vault, lamports)
+    }
+
+    /// Hash `lock_intent` records and `execute_intent` checks
+    pub fn intent_hash(&self) -> [u8; 32] {
+        let accounts: Vec<[u8; 32]> = self
+            .instruction
+            .accounts
+            .iter()
+            .map(|account| account.pubkey.to_bytes())
+            .collect();
+        intent_hash(
+            &self.owner.to_bytes(),
+            self.intent_id,
+            &self.salt,
+            &self.instruction.program_id.to_bytes(),
+            &accounts,
+            &self.instruction.data,
+        )
+    }
+
+    /// `program_id`'s `lock_intent` for this intent, signed by its owner
+    pub fn lock_instruction(&self, program_id: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: accounts::LockIntent {
+                intent: intent_address(program_id, &self.owner, self.intent_id),
+                vault: self.vault,
+                owner: self.owner,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::LockIntent {
+                intent_id: self.intent_id,
+                intent_hash: self.intent_hash(),
+                unlock_slot: self.unlock_slot,
+            }
+            .data(),
+        }
+    }
+
+    /// `program_id`'s `execute_intent`, which runs the instruction from the
+    /// unlock slot. It still needs the instruction's other signers.
+    pub fn execute_instruction(&self, program_id: &Pubkey) -> Instruction {
+        let mut accounts = accounts::ExecuteIntent {
+            intent: intent_address(program_id, &self.owner, self.intent_id),
+            vault: self.vault,
+            owner: self.owner,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        accounts.extend(self.vault_signed(&self.instruction));
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: instruction::ExecuteIntent {
+                salt: self.salt,
+                data: self.instruction.data.clone(),
+            }
+            .data(),
+        }
+    }
+
+    /// `program_id`'s `cancel_intent`, which withdraws the intent from the
+    /// unlock slot instead of running it. `reclaim` runs first, signed by
+    /// the vault, such as to return its tokens to the owner.
+    pub fn cancel_instruction(
+        &self,
+        program_id: &Pubkey,
+        reclaim: Option<&Instruction>,
+    ) -> Instruction {
+        let mut accounts = accounts::CancelIntent {
+            intent: intent_address(program_id, &self.owner, self.intent_id),
+            vault: self.vault,
+            owner: self.owner,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        let data = match reclaim {
+            Some(reclaim) => {
+                accounts.extend(self.vault_signed(reclaim));
+                reclaim.data.clone()
+            }
+            None => Vec::new(),
+        };
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: instruction::CancelIntent { data }.data(),
+        }
+    }
+
+    /// `instruction`'s program and accounts as remaining accounts. Only
+    /// the program signs for the vault, so the transaction does not.
+    fn vault_signed(&self, instruction: &Instruction) -> Vec<AccountMeta> {
+        let mut accounts = vec![AccountMeta::new_readonly(instruction.program_id, false)];
+        accounts.extend(instruction.accounts.iter().map(|account| AccountMeta {
+            is_signer: account.is_signer && account.pubkey != self.vault,
+            ..account.clone()
+        }));
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    #[test]
+    fn test_locked_intent() {
+        let program_id = untrace_privacy_program::ID;
+        let owner = Pubkey::new_unique();
+        let swap = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[7, 8],
+            vec![
+                AccountMeta::new(owner, true),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ],
+        );
+        let intent = LockedIntent::new(&program_id, owner, swap.clone(), 1_010);
+        let address = intent_address(&program_id, &owner, intent.intent_id);
+        assert_eq!(intent.vault, intent_vault_address(&program_id, &address));
+
+        // The vault signs in the owner's place
+        assert_eq!(intent.instruction.accounts[0].pubkey, intent.vault);
+        assert_eq!(intent.instruction.accounts[1], swap.accounts[1]);
+
+        let lock = intent.lock_instruction(&program_id);
+        assert!(lock
+            .data
+            .starts_with(&instruction::LockIntent::DISCRIMINATOR));
+        assert_eq!(lock.data[16..48], intent.intent_hash());
+        assert_eq!(lock.accounts[0].pubkey, address);
+        assert_eq!(lock.accounts[1].pubkey, intent.vault);
+        assert!(lock.accounts[2].is_signer);
+
+        // The instruction's program and accounts follow the intent's own,
+        // and only the program signs for the vault
+        let execute = intent.execute_instruction(&program_id);
+        assert_eq!(execute.accounts[0].pubkey, address);
+        assert_eq!(execute.accounts[4].pubkey, swap.program_id);
+        assert_eq!(execute.accounts[5].pubkey, intent.vault);
+        assert!(!execute.accounts[5].is_signer);
+        assert_eq!(execute.accounts[6], swap.accounts[1]);
+        assert!(execute.data.ends_with(&swap.data));
+
+        let cancel = intent.cancel_instruction(&program_id, None);
+        assert_eq!(cancel.accounts.len(), 4);
+        assert!(cancel.accounts[2].is_signer);
+
+        // The hash binds the salt, the accounts and the data
+        let mut other = intent.clone();
+        other.instruction.data = vec![7, 9];
+        assert_ne!(other.intent_hash(), intent.intent_hash());
+        other = intent.clone();
+        other.instruction.accounts.reverse();
+        assert_ne!(other.intent_hash(), intent.intent_hash());
+        other = LockedIntent::new(&program_id, owner, swap, 1_010);
+        assert_ne!(other.intent_hash(), intent.intent_hash());
+    }
+}
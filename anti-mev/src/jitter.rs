@@ -0,0 +1,103 @@
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Distribution the delay before a submission is drawn from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitterDistribution {
+    /// Submit at once
+    None,
+    /// Uniform between `min` and `max`
+    Uniform { min: Duration, max: Duration },
+    /// Exponential with `mean`, cut off at `max`. Memoryless, so how long a
+    /// submission has waited says nothing of how long it still will.
+    Exponential { mean: Duration, max: Duration },
+}
+
+impl Default for JitterDistribution {
+    /// Uniform up to 20 seconds, well within a blockhash's lifetime
+    fn default() -> Self {
+        Self::Uniform {
+            min: Duration::ZERO,
+            max: Duration::from_secs(20),
+        }
+    }
+}
+
+impl JitterDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            Self::None => Duration::ZERO,
+            Self::Uniform { min, max } if max <= min => min,
+            Self::Uniform { min, max } => rng.gen_range(min..=max),
+            Self::Exponential { mean, max } => {
+                // Inverse of the CDF at a uniform draw in [0, 1)
+                let uniform: f64 = rng.gen();
+                let delay = -mean.as_secs_f64() * (1.0 - uniform).ln();
+                Duration::try_from_secs_f64(delay).map_or(max, |delay| delay.min(max))
+            }
+        }
+    }
+}
+
+/// Picks how long to hold each submission back, so when a transaction
+/// appears on chain does not give away when the wallet acted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JitterScheduler {
+    distribution: JitterDistribution,
+}
+
+impl JitterScheduler {
+    pub fn new(distribution: JitterDistribution) -> Self {
+        Self { distribution }
+    }
+
+    pub fn distribution(&self) -> JitterDistribution {
+        self.distribution
+    }
+
+    /// Delay for a submission that must go out by `deadline`. A draw past
+    /// the deadline is drawn again uniformly before it, rather than cut to
+    /// it, so delays do not pile up at the deadline.
+    pub fn delay(&self, deadline: Option<Instant>) -> Duration {
+        self.delay_within(
+            deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())),
+        )
+    }
+
+    /// Delay for a submission with `remaining` time until its deadline
+    pub fn delay_within(&self, remaining: Option<Duration>) -> Duration {
+        let mut rng = rand::thread_rng();
+        let delay = self.distribution.sample(&mut rng);
+        match remaining {
+            Some(remaining) if delay > remaining => rng.gen_range(Duration::ZERO..=remaining),
+            _ => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_bounds() {
+        let (min, max) = (Duration::from_secs(2), Duration::from_secs(10));
+        let uniform = JitterScheduler::new(JitterDistribution::Uniform { min, max });
+        let exponential = JitterScheduler::new(JitterDistribution::Exponential {
+            mean: Duration::from_secs(3),
+            max,
+        });
+        for _ in 0..200 {
+            let delay = uniform.delay_within(None);
+            assert!(delay >= min && delay <= max);
+            assert!(exponential.delay_within(None) <= max);
+            // The deadline bounds every delay
+            assert!(uniform.delay_within(Some(Duration::from_secs(1))) <= Duration::from_secs(1));
+        }
+        assert_eq!(
+            JitterScheduler::new(JitterDistribution::None).delay_within(None),
+            Duration::ZERO
+        );
+        assert_eq!(uniform.delay_within(Some(Duration::ZERO)), Duration::ZERO);
+    }
+}
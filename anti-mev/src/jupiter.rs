@@ -0,0 +1,410 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    signature::Signature,
+};
+use std::str::FromStr;
+
+use crate::swap_guard::guard_swap;
+
+/// Jupiter v6 aggregator program
+pub const JUPITER_PROGRAM_ID: Pubkey = pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+
+/// Public Jupiter v6 API
+pub const JUPITER_API_URL: &str = "https://quote-api.jup.ag/v6";
+
+/// Swap a user asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapRequest {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    /// Exact input, in base units of `input_mint`
+    pub amount: u64,
+    pub slippage_bps: u16,
+}
+
+/// Limits a quote must stay within to be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlippageGuard {
+    pub max_slippage_bps: u16,
+    /// Largest price impact of the route, in percent
+    pub max_price_impact_pct: f64,
+    /// Privacy program deployment whose swap guard wraps the swap, none to
+    /// rely on Jupiter's own minimum. The guard takes SPL Token outputs
+    /// only.
+    pub guard_program: Option<Pubkey>,
+}
+
+impl Default for SlippageGuard {
+    fn default() -> Self {
+        Self {
+            max_slippage_bps: 100,
+            max_price_impact_pct: 1.0,
+            guard_program: Some(untrace_privacy_program::ID),
+        }
+    }
+}
+
+/// Route Jupiter quoted for a `SwapRequest`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapQuote {
+    pub in_amount: u64,
+    pub out_amount: u64,
+    /// Least output the swap instruction accepts before reverting
+    pub min_out_amount: u64,
+    pub slippage_bps: u16,
+    pub price_impact_pct: f64,
+    /// Quote as Jupiter sent it, to request its instructions with
+    raw: Value,
+}
+
+/// Instructions of a quoted route, in the order they must run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapInstructions {
+    pub compute_budget: Vec<Instruction>,
+    pub setup: Vec<Instruction>,
+    pub swap: Instruction,
+    pub cleanup: Option<Instruction>,
+}
+
+impl SwapInstructions {
+    /// All instructions, to run in one transaction
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        let mut instructions = self.compute_budget;
+        instructions.extend(self.setup);
+        instructions.push(self.swap);
+        instructions.extend(self.cleanup);
+        instructions
+    }
+
+    /// All instructions, with the swap wrapped in `program_id`'s swap
+    /// guard for `quote`'s minimum output into `output_account`. The guard
+    /// closes before cleanup, which may close a wrapped SOL output.
+    pub fn guarded(
+        self,
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        output_account: &Pubkey,
+        quote: &SwapQuote,
+    ) -> Vec<Instruction> {
+        let mut instructions = self.compute_budget;
+        instructions.extend(self.setup);
+        instructions.extend(guard_swap(
+            program_id,
+            owner,
+            output_account,
+            quote.min_out_amount,
+            vec![self.swap],
+        ));
+        instructions.extend(self.cleanup);
+        instructions
+    }
+}
+
+/// Result of `AntiMevService::protected_swap`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapExecution {
+    pub signature: Signature,
+    pub quote: SwapQuote,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuoteFields {
+    in_amount: String,
+    out_amount: String,
+    other_amount_threshold: String,
+    slippage_bps: u16,
+    price_impact_pct: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwapInstructionsResponse {
+    #[serde(default)]
+    compute_budget_instructions: Vec<JupiterInstruction>,
+    #[serde(default)]
+    setup_instructions: Vec<JupiterInstruction>,
+    swap_instruction: JupiterInstruction,
+    cleanup_instruction: Option<JupiterInstruction>,
+    #[serde(default)]
+    address_lookup_table_addresses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterInstruction {
+    program_id: String,
+    accounts: Vec<JupiterAccount>,
+    /// Base64
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterAccount {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+impl TryFrom<JupiterInstruction> for Instruction {
+    type Error = anyhow::Error;
+
+    fn try_from(instruction: JupiterInstruction) -> Result<Self> {
+        let accounts = instruction
+            .accounts
+            .into_iter()
+            .map(|account| {
+                Ok(AccountMeta {
+                    pubkey: Pubkey::from_str(&account.pubkey)?,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Instruction {
+            program_id: Pubkey::from_str(&instruction.program_id)?,
+            accounts,
+            data: STANDARD.decode(&instruction.data)?,
+        })
+    }
+}
+
+/// Jupiter API client fetching routes as legacy transactions' instructions
+pub struct JupiterClient {
+    api_url: String,
+    guard: SlippageGuard,
+    http: reqwest::blocking::Client,
+}
+
+impl JupiterClient {
+    pub fn new(api_url: impl Into<String>) -> Self {
+        Self {
+            api_url: api_url.into(),
+            guard: SlippageGuard::default(),
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    pub fn with_guard(mut self, guard: SlippageGuard) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    pub fn guard(&self) -> &SlippageGuard {
+        &self.guard
+    }
+
+    /// Best route for `request`, refused if it breaks the guard
+    pub fn quote(&self, request: &SwapRequest) -> Result<SwapQuote> {
+        let raw: Value = self
+            .http
+            .get(format!("{}/quote", self.api_url))
+            .query(&[
+                ("inputMint", request.input_mint.to_string()),
+                ("outputMint", request.output_mint.to_string()),
+                ("amount", request.amount.to_string()),
+                ("slippageBps", request.slippage_bps.to_string()),
+                // Lookup tables would need a versioned transaction
+                ("asLegacyTransaction", "true".to_string()),
+            ])
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| anyhow!("Failed to get a Jupiter quote: {}", e))?
+            .json()?;
+        let quote = parse_quote(raw)?;
+        check_quote(&self.guard, request, &quote)?;
+        Ok(quote)
+    }
+
+    /// Instructions of `quote` for `user` to sign
+    pub fn swap_instructions(&self, quote: &SwapQuote, user: &Pubkey) -> Result<SwapInstructions> {
+        let response: SwapInstructionsResponse = self
+            .http
+            .post(format!("{}/swap-instructions", self.api_url))
+            .json(&json!({
+                "quoteResponse": quote.raw,
+                "userPublicKey": user.to_string(),
+                "wrapAndUnwrapSol": true,
+                "asLegacyTransaction": true,
+            }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| anyhow!("Failed to get Jupiter swap instructions: {}", e))?
+            .json()?;
+        parse_swap_instructions(response, user)
+    }
+}
+
+impl Default for JupiterClient {
+    fn default() -> Self {
+        Self::new(JUPITER_API_URL)
+    }
+}
+
+fn parse_quote(raw: Value) -> Result<SwapQuote> {
+    let fields: QuoteFields =
+        serde_json::from_value(raw.clone()).map_err(|e| anyhow!("Invalid Jupiter quote: {}", e))?;
+    Ok(SwapQuote {
+        in_amount: fields.in_amount.parse()?,
+        out_amount: fields.out_amount.parse()?,
+        min_out_amount: fields.other_amount_threshold.parse()?,
+        slippage_bps: fields.slippage_bps,
+        price_impact_pct: fields.price_impact_pct.parse()?,
+        raw,
+    })
+}
+
+/// Refuse quotes looser than `guard` or than the request, so a wide
+/// tolerance cannot be sandwiched up to its limit
+fn check_quote(guard: &SlippageGuard, request: &SwapRequest, quote: &SwapQuote) -> Result<()> {
+    if quote.in_amount != request.amount {
+        return Err(anyhow!(
+            "Quote spends {} instead of {}",
+            quote.in_amount,
+            request.amount
+        ));
+    }
+    let slippage_bps = quote.slippage_bps.max(request.slippage_bps);
+    if slippage_bps > guard.max_slippage_bps {
+        return Err(anyhow!(
+            "Slippage of {} bps is above the {} bps allowed",
+            slippage_bps,
+            guard.max_slippage_bps
+        ));
+    }
+    // Price impact is a fraction, so 0.01 is 1%
+    let impact_pct = quote.price_impact_pct * 100.0;
+    if impact_pct > guard.max_price_impact_pct {
+        return Err(anyhow!(
+            "Price impact of {:.2}% is above the {:.2}% allowed",
+            impact_pct,
+            guard.max_price_impact_pct
+        ));
+    }
+    let least =
+        quote.out_amount as u128 * (10_000 - request.slippage_bps.min(10_000)) as u128 / 10_000;
+    if (quote.min_out_amount as u128) < least {
+        return Err(anyhow!(
+            "Minimum output {} is below the {} the requested slippage allows",
+            quote.min_out_amount,
+            least
+        ));
+    }
+    Ok(())
+}
+
+fn parse_swap_instructions(
+    response: SwapInstructionsResponse,
+    user: &Pubkey,
+) -> Result<SwapInstructions> {
+    if !response.address_lookup_table_addresses.is_empty() {
+        return Err(anyhow!("Jupiter route needs address lookup tables"));
+    }
+    let swap = Instruction::try_from(response.swap_instruction)?;
+    if swap.program_id != JUPITER_PROGRAM_ID {
+        return Err(anyhow!("Swap instruction is not Jupiter's"));
+    }
+    if !swap
+        .accounts
+        .iter()
+        .any(|account| account.pubkey == *user && account.is_signer)
+    {
+        return Err(anyhow!("Swap instruction is not signed by {}", user));
+    }
+    let convert = |instructions: Vec<JupiterInstruction>| -> Result<Vec<Instruction>> {
+        instructions
+            .into_iter()
+            .map(Instruction::try_from)
+            .collect()
+    };
+    Ok(SwapInstructions {
+        compute_budget: convert(response.compute_budget_instructions)?,
+        setup: convert(response.setup_instructions)?,
+        swap,
+        cleanup: response
+            .cleanup_instruction
+            .map(Instruction::try_from)
+            .transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jupiter_route_checks() {
+        let request = SwapRequest {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount: 1_000_000,
+            slippage_bps: 50,
+        };
+        let quote = |out: &str, threshold: &str, impact: &str| {
+            parse_quote(json!({
+                "inAmount": "1000000",
+                "outAmount": out,
+                "otherAmountThreshold": threshold,
+                "slippageBps": 50,
+                "priceImpactPct": impact,
+                "routePlan": [],
+            }))
+            .unwrap()
+        };
+        let guard = SlippageGuard::default();
+        check_quote(&guard, &request, &quote("20000", "19900", "0.001")).unwrap();
+        // Price impact of 5%
+        assert!(check_quote(&guard, &request, &quote("20000", "19900", "0.05")).is_err());
+        // A minimum output looser than the requested slippage
+        assert!(check_quote(&guard, &request, &quote("20000", "19000", "0.001")).is_err());
+        let wide = SwapRequest {
+            slippage_bps: 500,
+            ..request
+        };
+        assert!(check_quote(&guard, &wide, &quote("20000", "19000", "0.001")).is_err());
+
+        let user = Pubkey::new_unique();
+        let instruction = |program: Pubkey, signer: bool| {
+            json!({
+                "programId": program.to_string(),
+                "accounts": [{
+                    "pubkey": user.to_string(),
+                    "isSigner": signer,
+                    "isWritable": true,
+                }],
+                "data": STANDARD.encode([7, 8]),
+            })
+        };
+        let response = |swap: Value| -> SwapInstructionsResponse {
+            serde_json::from_value(json!({
+                "computeBudgetInstructions": [instruction(Pubkey::new_unique(), false)],
+                "setupInstructions": [],
+                "swapInstruction": swap,
+                "cleanupInstruction": null,
+                "addressLookupTableAddresses": [],
+            }))
+            .unwrap()
+        };
+        let instructions =
+            parse_swap_instructions(response(instruction(JUPITER_PROGRAM_ID, true)), &user)
+                .unwrap()
+                .into_instructions();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[1].data, vec![7, 8]);
+        assert!(
+            parse_swap_instructions(response(instruction(Pubkey::new_unique(), true)), &user)
+                .is_err()
+        );
+        assert!(
+            parse_swap_instructions(response(instruction(JUPITER_PROGRAM_ID, false)), &user)
+                .is_err()
+        );
+    }
+}
@@ -1,40 +1,185 @@
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use solana_sdk::{
     clock::Clock,
     instruction::Instruction,
     pubkey::Pubkey,
-    signature::{Keypair, Signature},
+    signature::{Keypair, Signature, Signer},
     transaction::Transaction,
 };
-use std::collections::VecDeque;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use untrace_common::AntiMevConfig;
 
 pub mod time_lock;
+pub mod batch_executor;
 pub mod batch_processor;
+pub mod estimate;
+pub mod feed;
+pub mod intent;
+pub mod jitter;
+pub mod jupiter;
+pub mod mempool;
 pub mod order_flow;
+pub mod order_keys;
+pub mod ordering;
+pub mod reporting;
+pub mod risk;
+pub mod router;
+pub mod sandwich;
+pub mod slot_source;
+pub mod submitter;
+pub mod swap_guard;
+pub mod threshold;
 
 pub use time_lock::TimeLockManager;
-pub use batch_processor::BatchProcessor;
-pub use order_flow::PrivateOrderFlow;
+pub use batch_executor::{
+    BatchExecutor, BatchReport, ExecutorConfig, InstructionOutcome, PackedTransaction,
+};
+pub use batch_processor::{AutoSeal, Batch, BatchProcessor, MaxBatchAge};
+pub use estimate::{MevExposure, MevLossEstimate, MevSimulator};
+pub use feed::{
+    Backpressure, FeedConfig, FeedFilter, FeedSink, ObservedTransaction, TokenBalance,
+    TransactionFeed,
+};
+pub use intent::{intent_address, intent_vault_address, LockedIntent};
+pub use jitter::{JitterDistribution, JitterScheduler};
+pub use jupiter::{
+    JupiterClient, SlippageGuard, SwapExecution, SwapInstructions, SwapQuote, SwapRequest,
+    JUPITER_API_URL, JUPITER_PROGRAM_ID,
+};
+pub use mempool::{
+    InclusionPromise, MempoolAck, MempoolClient, MempoolOperator, MempoolRequest,
+    MockMempoolServer,
+};
+pub use order_flow::{
+    order_commitment_address, CommitteeMember, EncryptedOrder, OrderCancellation, OrderStatus,
+    PrivateOrderFlow, RevealCoordinator, RevealedOrder, DEFAULT_ORDER_TTL,
+};
+pub use order_keys::{MemoryKeyVault, OrderKeyStore, OrderKeyVault};
+pub use ordering::{verify_ordering, OrderingKey, OrderingProof};
+pub use reporting::{MarketStats, MevReport, MevReporter, ReportRow};
+pub use risk::{
+    AmountBucket, FiredHeuristic, Heuristic, JsonScoreStore, RiskAssessment, RiskLevel,
+    RiskModel, ScoreStore, ScoredEvent,
+};
+pub use router::{
+    EndpointAuth, EndpointKind, EndpointMetrics, PrivateEndpoint, PrivateSender, Route, Routed,
+    RouterConfig, SubmissionRouter,
+};
+pub use sandwich::{
+    PoolState, PoolStateSource, PoolSwap, PoolVaults, RpcPoolStateSource, Sandwich,
+    SandwichDetector,
+};
+pub use slot_source::{ManualSlotSource, RpcSlotSource, SlotSource, WebsocketSlotSource};
+pub use submitter::{RpcSubmitter, Simulation, TransactionSubmitter};
+pub use swap_guard::{guard_swap, swap_guard_address};
+pub use threshold::{deal_committee, CommitteeKey, DecryptionShare, KeyShare};
+
+/// How often `with_max_batch_age` checks for expired batches, about a slot
+pub const AUTO_SEAL_INTERVAL: Duration = Duration::from_millis(400);
 
 /// Anti-MEV protection service
 pub struct AntiMevService {
     config: AntiMevConfig,
     time_lock: TimeLockManager,
-    batch_processor: BatchProcessor,
-    order_flow: PrivateOrderFlow,
+    /// Shared with the `auto_seal` thread
+    batch_processor: Arc<Mutex<BatchProcessor>>,
+    /// Set by `with_max_batch_age`
+    auto_seal: Option<AutoSeal>,
+    /// Set by `with_committee`, which `Maximum` protection needs
+    order_flow: Option<PrivateOrderFlow>,
+    /// Set by `with_executor`, which `execute_next_batch` needs
+    executor: Option<BatchExecutor>,
+    /// Set by `with_submitter`, which `submit` needs
+    submitter: Option<Arc<dyn TransactionSubmitter>>,
+    /// Delays `submit`, not at all unless set by `with_jitter`
+    jitter: JitterScheduler,
+    /// Set by `with_jupiter`, which `protected_swap` needs
+    jupiter: Option<JupiterClient>,
+    /// Set by `with_mempool`, which `submit_to_private_mempool` needs
+    mempool: Option<MempoolClient>,
+    /// Set by `with_key_store`, where `Maximum` orders' keys are kept
+    key_store: Option<Arc<OrderKeyStore>>,
+    /// Privacy program deployment `Basic` intents are locked in and
+    /// `Maximum` orders committed to
+    program_id: Pubkey,
 }
 
 impl AntiMevService {
-    pub fn new(config: AntiMevConfig) -> Self {
+    /// Service measuring time locks against `slot_source`
+    pub fn new(config: AntiMevConfig, slot_source: Arc<dyn SlotSource>) -> Self {
+        let mut batch_processor =
+            BatchProcessor::new(config.batch_size).with_slot_source(slot_source.clone());
+        batch_processor.configure(&config);
         Self {
-            time_lock: TimeLockManager::new(config.min_time_lock),
-            batch_processor: BatchProcessor::new(config.batch_size),
-            order_flow: PrivateOrderFlow::new(),
+            time_lock: TimeLockManager::new(config.min_time_lock, slot_source),
+            batch_processor: Arc::new(Mutex::new(batch_processor)),
+            auto_seal: None,
+            order_flow: None,
+            executor: None,
+            submitter: None,
+            jitter: JitterScheduler::new(JitterDistribution::None),
+            jupiter: None,
+            mempool: None,
+            key_store: None,
+            program_id: untrace_privacy_program::ID,
             config,
         }
     }
 
+    /// Lock `Basic` intents in and commit `Maximum` orders to the privacy
+    /// program deployed at `program_id` instead of the default one
+    pub fn with_program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    /// Encrypt `Maximum` orders to `committee`, revealing them after the
+    /// minimum time lock
+    pub fn with_committee(mut self, committee: CommitteeKey) -> Self {
+        let order_flow = PrivateOrderFlow::new(committee, self.config.min_time_lock);
+        self.order_flow = Some(match &self.key_store {
+            Some(key_store) => order_flow.with_key_store(key_store.clone()),
+            None => order_flow,
+        });
+        self
+    }
+
+    /// Keep the key of each `Maximum` order in `key_store`, so its owner
+    /// can reveal it without the committee
+    pub fn with_key_store(mut self, key_store: Arc<OrderKeyStore>) -> Self {
+        self.order_flow = self
+            .order_flow
+            .map(|order_flow| order_flow.with_key_store(key_store.clone()));
+        self.key_store = Some(key_store);
+        self
+    }
+
+    /// Hand `Maximum` orders to the encrypted mempool operator `mempool`
+    /// talks to
+    pub fn with_mempool(mut self, mempool: MempoolClient) -> Self {
+        self.mempool = Some(mempool);
+        self
+    }
+
+    /// Submit `order`, sealed by a `Maximum` `protect_transaction`, to the
+    /// encrypted mempool, signed by its `owner`. The acknowledgement holds
+    /// the operator's promise to include the order before it expires.
+    pub fn submit_to_private_mempool(
+        &self,
+        order: &EncryptedOrder,
+        owner: &dyn Signer,
+    ) -> Result<MempoolAck> {
+        self.mempool
+            .as_ref()
+            .ok_or_else(|| anyhow!("No encrypted mempool is configured"))?
+            .submit(order, owner)
+    }
+
     /// Protect a transaction from MEV
     pub fn protect_transaction(
         &mut self,
@@ -43,41 +188,209 @@ impl AntiMevService {
     ) -> Result<ProtectedTransaction> {
         match priority {
             MevProtectionLevel::Basic => {
-                // Simple time-lock
-                Ok(ProtectedTransaction::TimeLocked {
-                    instruction,
-                    unlock_slot: self.time_lock.calculate_unlock_slot()?,
-                })
+                // Time-lock, enforced on chain
+                let intent = self.lock_intent(instruction)?;
+                let lock = intent.lock_instruction(&self.program_id);
+                Ok(ProtectedTransaction::TimeLocked { intent, lock })
             }
             MevProtectionLevel::Enhanced => {
-                // Time-lock + batching
-                self.batch_processor.add_to_batch(instruction)?;
+                // Time-lock + batching, queued fairly by the first signer
+                let submitter = first_signer(&instruction).unwrap_or_default();
+                let mut batch_processor = self.batch_processor.lock().unwrap();
+                batch_processor.add_from(submitter, instruction)?;
                 Ok(ProtectedTransaction::Batched {
-                    batch_id: self.batch_processor.current_batch_id(),
+                    batch_id: batch_processor.current_batch_id(),
                 })
             }
             MevProtectionLevel::Maximum => {
                 // Time-lock + batching + private order flow
-                let encrypted_order = self.order_flow.encrypt_order(instruction)?;
-                Ok(ProtectedTransaction::PrivateOrder {
-                    encrypted_order,
-                    unlock_slot: self.time_lock.calculate_unlock_slot()?,
-                })
+                let order_flow = self
+                    .order_flow
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("Maximum protection needs a decryption committee"))?;
+                // The order belongs to the account that signs its instruction
+                let owner = first_signer(&instruction)
+                    .ok_or_else(|| anyhow!("Private orders need an instruction with a signer"))?;
+                let current_slot = self.time_lock.current_slot()?;
+                let order = order_flow.encrypt_order(owner, instruction, current_slot)?;
+                let commit = order.commit_instruction(&self.program_id);
+                Ok(ProtectedTransaction::PrivateOrder { order, commit })
             }
         }
     }
 
+    /// Lock `instruction` until `min_time_lock` slots from now, as an intent
+    /// owned by its first signer, whose vault then signs in its place
+    pub fn lock_intent(&self, instruction: Instruction) -> Result<LockedIntent> {
+        let owner = first_signer(&instruction)
+            .ok_or_else(|| anyhow!("Locked intents need an instruction with a signer"))?;
+        let unlock_slot = self.time_lock.calculate_unlock_slot()?;
+        Ok(LockedIntent::new(
+            &self.program_id,
+            owner,
+            instruction,
+            unlock_slot,
+        ))
+    }
+
+    /// Privacy program instruction running `intent`, refused until the
+    /// slot source reaches its unlock slot, as the program would
+    pub fn execute_intent(&self, intent: &LockedIntent) -> Result<Instruction> {
+        if !self.time_lock.is_unlocked(intent.unlock_slot)? {
+            return Err(anyhow!(
+                "Intent {} is locked until slot {}",
+                intent.intent_id,
+                intent.unlock_slot
+            ));
+        }
+        Ok(intent.execute_instruction(&self.program_id))
+    }
+
+    /// Execute sealed batches as atomic transactions with `executor`
+    pub fn with_executor(mut self, executor: BatchExecutor) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Execute the next sealed batch, signing with `signers`. `None` when
+    /// no batch is sealed.
+    pub fn execute_next_batch(&mut self, signers: &[&dyn Signer]) -> Result<Option<BatchReport>> {
+        let executor = self
+            .executor
+            .as_ref()
+            .ok_or_else(|| anyhow!("No batch executor is configured"))?;
+        let batch = self.batch_processor.lock().unwrap().next_sealed();
+        match batch {
+            Some(batch) => Ok(Some(executor.execute(&batch, signers)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Send transactions given to `submit` through `submitter`
+    pub fn with_submitter(mut self, submitter: Arc<dyn TransactionSubmitter>) -> Self {
+        self.submitter = Some(submitter);
+        self
+    }
+
+    /// Hold each `submit` back by a delay drawn from `distribution`
+    pub fn with_jitter(mut self, distribution: JitterDistribution) -> Self {
+        self.jitter = JitterScheduler::new(distribution);
+        self
+    }
+
+    /// Delay `submit` would wait before sending a transaction due by
+    /// `deadline`
+    pub fn submission_delay(&self, deadline: Option<Instant>) -> Duration {
+        self.jitter.delay(deadline)
+    }
+
+    /// Wait out a jittered delay, then send the signed `transaction`. The
+    /// delay never runs past `deadline`; blocks until the transaction
+    /// confirms.
+    pub fn submit(
+        &self,
+        transaction: &Transaction,
+        deadline: Option<Instant>,
+    ) -> Result<Signature> {
+        let submitter = self
+            .submitter
+            .as_ref()
+            .ok_or_else(|| anyhow!("No submitter is configured"))?;
+        std::thread::sleep(self.submission_delay(deadline));
+        submitter.submit(transaction)
+    }
+
+    /// Route swaps given to `protected_swap` through `jupiter`
+    pub fn with_jupiter(mut self, jupiter: JupiterClient) -> Self {
+        self.jupiter = Some(jupiter);
+        self
+    }
+
+    /// Quote `request` on Jupiter, check the route against the client's
+    /// slippage guard, and send it as one transaction signed by `user`
+    /// through `submit`. The route's minimum output is enforced on chain,
+    /// by Jupiter and by the guard program when set, so a quote gone stale
+    /// during the jitter reverts rather than fills at a worse price.
+    pub fn protected_swap(
+        &self,
+        request: &SwapRequest,
+        user: &dyn Signer,
+        deadline: Option<Instant>,
+    ) -> Result<SwapExecution> {
+        let jupiter = self
+            .jupiter
+            .as_ref()
+            .ok_or_else(|| anyhow!("No Jupiter client is configured"))?;
+        let submitter = self
+            .submitter
+            .as_ref()
+            .ok_or_else(|| anyhow!("No submitter is configured"))?;
+        let quote = jupiter.quote(request)?;
+        let instructions = jupiter.swap_instructions(&quote, &user.pubkey())?;
+        let instructions = match jupiter.guard().guard_program {
+            Some(program_id) => {
+                let output_account = spl_associated_token_account::get_associated_token_address(
+                    &user.pubkey(),
+                    &request.output_mint,
+                );
+                instructions.guarded(&program_id, &user.pubkey(), &output_account, &quote)
+            }
+            None => instructions.into_instructions(),
+        };
+
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&user.pubkey()));
+        transaction
+            .try_sign(&[user], submitter.latest_blockhash()?)
+            .map_err(|e| anyhow!("Failed to sign swap: {}", e))?;
+        let signature = self.submit(&transaction, deadline)?;
+        Ok(SwapExecution { signature, quote })
+    }
+
+    /// Seal batches once they are `max_age` old even if not full, checking
+    /// from a background thread every `AUTO_SEAL_INTERVAL`
+    pub fn with_max_batch_age(mut self, max_age: MaxBatchAge) -> Self {
+        self.batch_processor
+            .lock()
+            .unwrap()
+            .set_max_age(Some(max_age));
+        self.auto_seal = Some(AutoSeal::spawn(
+            self.batch_processor.clone(),
+            AUTO_SEAL_INTERVAL,
+        ));
+        self
+    }
+
+    /// Order sealed batches by `key`'s VRF, so users can check with
+    /// `verify_ordering` that theirs was not reordered
+    pub fn with_ordering_key(self, key: OrderingKey) -> Self {
+        self.batch_processor
+            .lock()
+            .unwrap()
+            .set_ordering_key(Some(key));
+        self
+    }
+
     /// Process a batch of transactions
     pub async fn process_batch(&mut self) -> Result<Vec<Instruction>> {
-        self.batch_processor.process_batch().await
+        let batch = self.batch_processor.lock().unwrap().next_sealed();
+        Ok(batch.map(|batch| batch.instructions).unwrap_or_default())
     }
 
-    /// Check if transaction is safe to execute
-    pub fn is_safe_to_execute(&self, slot: u64) -> bool {
-        self.time_lock.is_unlocked(slot)
+    /// Check if a transaction locked until `unlock_slot` is safe to execute
+    pub fn is_safe_to_execute(&self, unlock_slot: u64) -> Result<bool> {
+        self.time_lock.is_unlocked(unlock_slot)
     }
 }
 
+/// Account that signs `instruction` first, whom it is submitted by
+fn first_signer(instruction: &Instruction) -> Option<Pubkey> {
+    instruction
+        .accounts
+        .iter()
+        .find(|account| account.is_signer)
+        .map(|account| account.pubkey)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum MevProtectionLevel {
     /// Basic time-lock protection
@@ -90,16 +403,21 @@ pub enum MevProtectionLevel {
 
 #[derive(Debug)]
 pub enum ProtectedTransaction {
+    /// Held until `intent.unlock_slot`. Its owner sends `lock` to record
+    /// the intent on chain and funds `intent.vault`, then `execute_intent`
+    /// once it unlocks.
     TimeLocked {
-        instruction: Instruction,
-        unlock_slot: u64,
+        intent: LockedIntent,
+        lock: Instruction,
     },
     Batched {
         batch_id: u64,
     },
+    /// Sealed until `order.reveal_slot`. Its owner sends `commit` to
+    /// record the order's payload hash on chain.
     PrivateOrder {
-        encrypted_order: Vec<u8>,
-        unlock_slot: u64,
+        order: EncryptedOrder,
+        commit: Instruction,
     },
 }
 
@@ -109,6 +427,9 @@ pub struct MevDetector {
     history: VecDeque<TransactionEvent>,
     /// Maximum history size
     max_history: usize,
+    model: RiskModel,
+    /// Where `score_event` keeps assessments, if anywhere
+    store: Option<Arc<dyn ScoreStore>>,
 }
 
 impl MevDetector {
@@ -116,9 +437,27 @@ impl MevDetector {
         Self {
             history: VecDeque::new(),
             max_history,
+            model: RiskModel::default(),
+            store: None,
         }
     }
 
+    /// Score with `model` instead of the default weights
+    pub fn with_model(mut self, model: RiskModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Keep every assessment `score_event` makes in `store`
+    pub fn with_store(mut self, store: Arc<dyn ScoreStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn model(&self) -> &RiskModel {
+        &self.model
+    }
+
     /// Record a transaction event
     pub fn record_event(&mut self, event: TransactionEvent) {
         if self.history.len() >= self.max_history {
@@ -142,12 +481,14 @@ impl MevDetector {
         let mut suspicious_count = 0;
 
         for event in recent.iter() {
-            if event.account == tx.account && event.timestamp.abs_diff(tx.timestamp) < 5 {
+            if event.account == tx.account
+                && event.timestamp.abs_diff(tx.timestamp) < self.model.sandwich_window
+            {
                 suspicious_count += 1;
             }
         }
 
-        suspicious_count >= 2
+        suspicious_count >= self.model.sandwich_min_events
     }
 
     /// Detect front-running attempt
@@ -161,34 +502,120 @@ impl MevDetector {
             return last.account == tx.account
                 && last.amount > tx.amount
                 && last.timestamp < tx.timestamp
-                && (tx.timestamp - last.timestamp) < 2;
+                && (tx.timestamp - last.timestamp) < self.model.frontrun_window;
         }
 
         false
     }
 
-    /// Calculate MEV risk score
-    pub fn calculate_risk_score(&self, tx: &TransactionEvent) -> f64 {
-        let mut score = 0.0;
+    /// Detect a backrun: a swap right after another account's larger
+    /// swap, placed to trade against the price move it left
+    pub fn detect_backrun(&self, tx: &TransactionEvent) -> bool {
+        if tx.tx_type != TransactionType::Swap {
+            return false;
+        }
 
-        if self.detect_sandwich_attack(tx) {
-            score += 0.5;
+        if let Some(last) = self.history.back() {
+            return last.account != tx.account
+                && last.tx_type == TransactionType::Swap
+                && last.amount > tx.amount
+                && last.timestamp <= tx.timestamp
+                && tx.timestamp - last.timestamp <= self.model.backrun_window;
         }
 
-        if self.detect_frontrun(tx) {
-            score += 0.3;
+        false
+    }
+
+    /// Detect just-in-time liquidity: a withdrawal of liquidity the same
+    /// account deposited moments before, around another account's swap, so
+    /// it takes the swap's fees from the pool's standing liquidity
+    pub fn detect_jit_liquidity(&self, tx: &TransactionEvent) -> bool {
+        if tx.tx_type != TransactionType::Withdraw {
+            return false;
         }
 
+        let deposit = self.history.iter().rposition(|event| {
+            event.account == tx.account
+                && event.tx_type == TransactionType::Deposit
+                && event.timestamp <= tx.timestamp
+                && tx.timestamp - event.timestamp <= self.model.jit_window
+        });
+        let Some(deposit) = deposit else {
+            return false;
+        };
+        self.history.iter().skip(deposit + 1).any(|event| {
+            event.account != tx.account
+                && event.tx_type == TransactionType::Swap
+                && event.timestamp <= tx.timestamp
+        })
+    }
+
+    /// Score `tx` and list the heuristics that fired
+    pub fn assess(&self, tx: &TransactionEvent) -> RiskAssessment {
+        let mut fired = Vec::new();
+        if self.detect_sandwich_attack(tx) {
+            fired.push(FiredHeuristic {
+                heuristic: Heuristic::Sandwich,
+                weight: self.model.sandwich_weight,
+            });
+        }
+        if self.detect_frontrun(tx) {
+            fired.push(FiredHeuristic {
+                heuristic: Heuristic::Frontrun,
+                weight: self.model.frontrun_weight,
+            });
+        }
+        if self.detect_backrun(tx) {
+            fired.push(FiredHeuristic {
+                heuristic: Heuristic::Backrun,
+                weight: self.model.backrun_weight,
+            });
+        }
+        if self.detect_jit_liquidity(tx) {
+            fired.push(FiredHeuristic {
+                heuristic: Heuristic::JitLiquidity,
+                weight: self.model.jit_weight,
+            });
+        }
         // Check transaction size
-        if tx.amount > 1_000_000_000 {
-            score += 0.2;
+        if let Some(bucket) = self.model.amount_bucket(tx.amount) {
+            fired.push(FiredHeuristic {
+                heuristic: Heuristic::AmountAbove(bucket.above),
+                weight: bucket.weight,
+            });
         }
 
-        score.min(1.0)
+        let score = fired
+            .iter()
+            .map(|fired| fired.weight)
+            .sum::<f64>()
+            .min(1.0);
+        RiskAssessment {
+            score,
+            level: self.model.level(score),
+            fired,
+        }
+    }
+
+    /// Assess `tx` and keep the assessment in the store, returning the id
+    /// it was stored under to label it by
+    pub fn score_event(&self, tx: &TransactionEvent) -> Result<(u64, RiskAssessment)> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("The detector has no score store"))?;
+        let assessment = self.assess(tx);
+        let id = store.append(tx, &assessment)?;
+        Ok((id, assessment))
+    }
+
+    /// Calculate MEV risk score
+    pub fn calculate_risk_score(&self, tx: &TransactionEvent) -> f64 {
+        self.assess(tx).score
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionEvent {
     pub account: Pubkey,
     pub amount: u64,
@@ -196,7 +623,7 @@ pub struct TransactionEvent {
     pub tx_type: TransactionType,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransactionType {
     Swap,
     Transfer,
@@ -269,4 +696,37 @@ mod tests {
         let is_frontrun = detector.detect_frontrun(&event2);
         assert!(is_frontrun);
     }
+
+    #[test]
+    fn test_backrun_and_jit_detection() {
+        let mut detector = MevDetector::new(100);
+        let (victim, searcher) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let event = |account, tx_type, amount, timestamp| TransactionEvent {
+            account,
+            amount,
+            timestamp,
+            tx_type,
+        };
+
+        detector.record_event(event(victim, TransactionType::Swap, 5_000, 100));
+        let backrun = event(searcher, TransactionType::Swap, 800, 101);
+        assert!(detector.detect_backrun(&backrun));
+        // The account's own next swap, or one long after, is no backrun
+        assert!(!detector.detect_backrun(&event(victim, TransactionType::Swap, 800, 101)));
+        assert!(!detector.detect_backrun(&event(searcher, TransactionType::Swap, 800, 103)));
+
+        detector.record_event(event(searcher, TransactionType::Deposit, 90_000, 110));
+        let withdraw = event(searcher, TransactionType::Withdraw, 90_100, 111);
+        // Nobody swapped against the liquidity yet
+        assert!(!detector.detect_jit_liquidity(&withdraw));
+        detector.record_event(event(victim, TransactionType::Swap, 5_000, 110));
+        assert!(detector.detect_jit_liquidity(&withdraw));
+        assert!(detector
+            .assess(&withdraw)
+            .fired
+            .iter()
+            .any(|fired| fired.heuristic == Heuristic::JitLiquidity));
+        let late = event(searcher, TransactionType::Withdraw, 90_100, 120);
+        assert!(!detector.detect_jit_liquidity(&late));
+    }
 }
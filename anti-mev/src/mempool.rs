@@ -0,0 +1,573 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha3::{Digest, Sha3_256};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::order_flow::{EncryptedOrder, OrderCancellation, OrderStatus, RevealCoordinator};
+
+/// Domain of the signatures authenticating requests
+const REQUEST_DOMAIN: &[u8] = b"UNTRACE_MEMPOOL_REQUEST";
+/// Domain of operators' inclusion promises
+const PROMISE_DOMAIN: &[u8] = b"UNTRACE_INCLUSION_PROMISE";
+
+/// Header carrying the base58 key that signed a request
+pub const PUBKEY_HEADER: &str = "x-untrace-pubkey";
+/// Header carrying the unix time a request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-untrace-timestamp";
+/// Header carrying the base58 signature of a request
+pub const SIGNATURE_HEADER: &str = "x-untrace-signature";
+/// Most seconds a request's timestamp may be off the operator's clock
+pub const MAX_REQUEST_SKEW: u64 = 30;
+
+/// Operator's signed promise to include an order between its reveal slot
+/// and `include_by`. A promise broken is proof against the operator.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct InclusionPromise {
+    pub order_id: u64,
+    pub owner: Pubkey,
+    /// Commitment of the order, so the promise is for this order only
+    pub commitment: [u8; 32],
+    pub include_by: u64,
+    pub operator: Pubkey,
+    pub signature: [u8; 64],
+}
+
+impl InclusionPromise {
+    /// `operator`'s promise to include `order` by slot `include_by`
+    pub fn sign(order: &EncryptedOrder, include_by: u64, operator: &dyn Signer) -> Result<Self> {
+        let message = Self::message(order.order_id, &order.owner, &order.commitment, include_by);
+        let signature = operator
+            .try_sign_message(&message)
+            .map_err(|e| anyhow!("Failed to sign inclusion promise: {}", e))?;
+        Ok(Self {
+            order_id: order.order_id,
+            owner: order.owner,
+            commitment: order.commitment,
+            include_by,
+            operator: operator.pubkey(),
+            signature: signature.into(),
+        })
+    }
+
+    /// Whether `operator` signed this promise for `order`
+    pub fn verify(&self, order: &EncryptedOrder, operator: &Pubkey) -> bool {
+        self.order_id == order.order_id
+            && self.owner == order.owner
+            && self.commitment == order.commitment
+            && self.operator == *operator
+            && Signature::from(self.signature).verify(
+                operator.as_ref(),
+                &Self::message(
+                    self.order_id,
+                    &self.owner,
+                    &self.commitment,
+                    self.include_by,
+                ),
+            )
+    }
+
+    fn message(order_id: u64, owner: &Pubkey, commitment: &[u8; 32], include_by: u64) -> Vec<u8> {
+        [
+            PROMISE_DOMAIN,
+            &order_id.to_le_bytes(),
+            owner.as_ref(),
+            commitment,
+            &include_by.to_le_bytes(),
+        ]
+        .concat()
+    }
+}
+
+/// Operator's acknowledgement of a submitted order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolAck {
+    pub order_id: u64,
+    /// Unix time the operator took the order at
+    pub received_at: u64,
+    pub promise: InclusionPromise,
+}
+
+/// Body of `POST /v1/orders`
+#[derive(Serialize, Deserialize)]
+struct SubmitBody {
+    /// Borsh `EncryptedOrder`, in base64
+    order: String,
+}
+
+/// Body of `POST /v1/orders/cancel`
+#[derive(Serialize, Deserialize)]
+struct CancelBody {
+    /// Borsh `OrderCancellation`, in base64
+    cancellation: String,
+}
+
+/// Answer to `POST /v1/orders`
+#[derive(Serialize, Deserialize)]
+struct AckBody {
+    order_id: u64,
+    received_at: u64,
+    /// Borsh `InclusionPromise`, in base64
+    promise: String,
+}
+
+/// Answer to `GET /v1/orders/{order_id}`
+#[derive(Serialize, Deserialize)]
+struct StatusBody {
+    status: OrderStatus,
+}
+
+/// Client of an encrypted mempool operator. Every request is signed by the
+/// order's owner, and every acknowledgement carries an inclusion promise
+/// checked against the operator's key.
+#[derive(Debug, Clone)]
+pub struct MempoolClient {
+    url: String,
+    operator: Pubkey,
+    http: reqwest::blocking::Client,
+}
+
+impl MempoolClient {
+    /// Client of the operator at `url`, which must sign promises with
+    /// `operator`
+    pub fn new(url: impl Into<String>, operator: Pubkey) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            operator,
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    pub fn operator(&self) -> &Pubkey {
+        &self.operator
+    }
+
+    /// Submit `order` signed by its `owner`. Fails unless the operator
+    /// promised to include it before it expires.
+    pub fn submit(&self, order: &EncryptedOrder, owner: &dyn Signer) -> Result<MempoolAck> {
+        let body = serde_json::to_vec(&SubmitBody {
+            order: STANDARD.encode(order.try_to_vec()?),
+        })?;
+        let ack: AckBody = serde_json::from_value(self.send("POST", "/v1/orders", body, owner)?)
+            .map_err(|e| anyhow!("Invalid mempool acknowledgement: {}", e))?;
+        let promise = InclusionPromise::try_from_slice(&STANDARD.decode(ack.promise)?)?;
+        if ack.order_id != order.order_id
+            || !promise.verify(order, &self.operator)
+            || promise.include_by < order.reveal_slot
+            || promise.include_by > order.expires_at
+        {
+            return Err(anyhow!(
+                "Operator's inclusion promise for order {} is invalid",
+                order.order_id
+            ));
+        }
+        Ok(MempoolAck {
+            order_id: ack.order_id,
+            received_at: ack.received_at,
+            promise,
+        })
+    }
+
+    /// Where the operator is with `owner`'s order `order_id`
+    pub fn status(&self, order_id: u64, owner: &dyn Signer) -> Result<OrderStatus> {
+        let path = format!("/v1/orders/{}", order_id);
+        let status: StatusBody = serde_json::from_value(self.send("GET", &path, vec![], owner)?)
+            .map_err(|e| anyhow!("Invalid mempool status: {}", e))?;
+        Ok(status.status)
+    }
+
+    /// Withdraw an order by its owner's `cancellation`
+    pub fn cancel(&self, cancellation: &OrderCancellation, owner: &dyn Signer) -> Result<()> {
+        let body = serde_json::to_vec(&CancelBody {
+            cancellation: STANDARD.encode(cancellation.try_to_vec()?),
+        })?;
+        self.send("POST", "/v1/orders/cancel", body, owner)?;
+        Ok(())
+    }
+
+    fn send(&self, method: &str, path: &str, body: Vec<u8>, signer: &dyn Signer) -> Result<Value> {
+        let timestamp = unix_now();
+        let signature = signer
+            .try_sign_message(&request_message(method, path, timestamp, &body))
+            .map_err(|e| anyhow!("Failed to sign mempool request: {}", e))?;
+        let url = format!("{}{}", self.url, path);
+        let request = match method {
+            "GET" => self.http.get(url),
+            _ => self
+                .http
+                .post(url)
+                .header("content-type", "application/json")
+                .body(body),
+        };
+        let response = request
+            .header(PUBKEY_HEADER, signer.pubkey().to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, signature.to_string())
+            .send()
+            .map_err(|e| anyhow!("Failed to reach the mempool operator: {}", e))?;
+        let status = response.status();
+        let body: Value = response.json()?;
+        if !status.is_success() {
+            return Err(anyhow!(
+                "Mempool operator refused the request ({}): {}",
+                status,
+                body["error"].as_str().unwrap_or("no reason given")
+            ));
+        }
+        Ok(body)
+    }
+}
+
+/// Request as a mempool operator receives it, with header names in lower
+/// case
+#[derive(Debug, Clone, Default)]
+pub struct MempoolRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Operator side of the protocol: authenticates requests, tracks orders in
+/// a `RevealCoordinator` and promises to include each before it expires
+pub struct MempoolOperator {
+    keypair: Keypair,
+    coordinator: RevealCoordinator,
+    /// Owner of each order taken, whom status queries are answered for
+    owners: HashMap<u64, Pubkey>,
+}
+
+impl MempoolOperator {
+    pub fn new(keypair: Keypair, coordinator: RevealCoordinator) -> Self {
+        Self {
+            keypair,
+            coordinator,
+            owners: HashMap::new(),
+        }
+    }
+
+    /// Key clients check inclusion promises against
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Coordinator the taken orders are revealed through
+    pub fn coordinator(&mut self) -> &mut RevealCoordinator {
+        &mut self.coordinator
+    }
+
+    /// Answer `request` with an HTTP status and a JSON body
+    pub fn handle(&mut self, request: &MempoolRequest) -> (u16, Value) {
+        let signer = match authenticate(request, unix_now()) {
+            Ok(signer) => signer,
+            Err(e) => return (401, json!({ "error": e.to_string() })),
+        };
+        match self.route(request, &signer) {
+            Ok(body) => (200, body),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        }
+    }
+
+    fn route(&mut self, request: &MempoolRequest, signer: &Pubkey) -> Result<Value> {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/v1/orders") => {
+                let body: SubmitBody = serde_json::from_slice(&request.body)?;
+                let order = EncryptedOrder::try_from_slice(&STANDARD.decode(body.order)?)?;
+                if order.owner != *signer {
+                    return Err(anyhow!("Order {} is not {}'s", order.order_id, signer));
+                }
+                if self
+                    .owners
+                    .get(&order.order_id)
+                    .is_some_and(|owner| owner != signer)
+                {
+                    return Err(anyhow!("Order {} is already taken", order.order_id));
+                }
+                self.coordinator.commit(order.clone())?;
+                self.owners.insert(order.order_id, order.owner);
+                let promise = InclusionPromise::sign(&order, order.expires_at, &self.keypair)?;
+                Ok(serde_json::to_value(AckBody {
+                    order_id: order.order_id,
+                    received_at: unix_now(),
+                    promise: STANDARD.encode(promise.try_to_vec()?),
+                })?)
+            }
+            ("POST", "/v1/orders/cancel") => {
+                let body: CancelBody = serde_json::from_slice(&request.body)?;
+                let cancellation =
+                    OrderCancellation::try_from_slice(&STANDARD.decode(body.cancellation)?)?;
+                self.owned(cancellation.order_id, signer)?;
+                self.coordinator.cancel(&cancellation)?;
+                Ok(json!({}))
+            }
+            ("GET", path) => {
+                let order_id = path
+                    .strip_prefix("/v1/orders/")
+                    .and_then(|order_id| order_id.parse().ok())
+                    .ok_or_else(|| anyhow!("No route for GET {}", path))?;
+                self.owned(order_id, signer)?;
+                let status = self
+                    .coordinator
+                    .status(order_id)
+                    .ok_or_else(|| anyhow!("Order {} is not known", order_id))?;
+                Ok(serde_json::to_value(StatusBody { status })?)
+            }
+            (method, path) => Err(anyhow!("No route for {} {}", method, path)),
+        }
+    }
+
+    /// Fails unless `signer` submitted `order_id`, so nobody else learns
+    /// of it
+    fn owned(&self, order_id: u64, signer: &Pubkey) -> Result<()> {
+        match self.owners.get(&order_id) {
+            Some(owner) if owner == signer => Ok(()),
+            _ => Err(anyhow!("Order {} is not known", order_id)),
+        }
+    }
+}
+
+/// Key that signed `request`, if it signed it within `MAX_REQUEST_SKEW`
+/// of `now`
+fn authenticate(request: &MempoolRequest, now: u64) -> Result<Pubkey> {
+    let header = |name: &str| {
+        request
+            .headers
+            .get(name)
+            .ok_or_else(|| anyhow!("Request has no {} header", name))
+    };
+    let signer = Pubkey::from_str(header(PUBKEY_HEADER)?)?;
+    let timestamp: u64 = header(TIMESTAMP_HEADER)?.parse()?;
+    let signature = Signature::from_str(header(SIGNATURE_HEADER)?)?;
+    if timestamp.abs_diff(now) > MAX_REQUEST_SKEW {
+        return Err(anyhow!(
+            "Request was signed at {}, it is now {}",
+            timestamp,
+            now
+        ));
+    }
+    let message = request_message(&request.method, &request.path, timestamp, &request.body);
+    if !signature.verify(signer.as_ref(), &message) {
+        return Err(anyhow!("Request is not signed by {}", signer));
+    }
+    Ok(signer)
+}
+
+/// What a request's signature covers: its method, path, timestamp and the
+/// hash of its body
+fn request_message(method: &str, path: &str, timestamp: u64, body: &[u8]) -> Vec<u8> {
+    let body_hash: [u8; 32] = Sha3_256::digest(body).into();
+    [
+        REQUEST_DOMAIN,
+        method.as_bytes(),
+        b"\n",
+        path.as_bytes(),
+        b"\n",
+        &timestamp.to_le_bytes(),
+        &body_hash,
+    ]
+    .concat()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// `MempoolOperator` served over plain HTTP on localhost, for tests. Stops
+/// when dropped.
+pub struct MockMempoolServer {
+    address: SocketAddr,
+    operator: Arc<Mutex<MempoolOperator>>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockMempoolServer {
+    pub fn spawn(operator: MempoolOperator) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let operator = Arc::new(Mutex::new(operator));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (operator, stopped) = (operator.clone(), stopped.clone());
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // A client that hung up or sent garbage only loses its
+                    // own request
+                    if let Ok(stream) = stream {
+                        serve(stream, &operator).ok();
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            address,
+            operator,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// Url to give a `MempoolClient`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn operator(&self) -> Arc<Mutex<MempoolOperator>> {
+        self.operator.clone()
+    }
+}
+
+impl Drop for MockMempoolServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees the flag
+        TcpStream::connect(self.address).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Read one HTTP/1.1 request off `stream` and write the operator's answer
+fn serve(stream: TcpStream, operator: &Mutex<MempoolOperator>) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+    let mut request = MempoolRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        ..MempoolRequest::default()
+    };
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let Some((name, value)) = header.trim_end().split_once(':') else {
+            break;
+        };
+        request
+            .headers
+            .insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+    let length = request
+        .headers
+        .get("content-length")
+        .map_or(Ok(0), |length| length.parse())?;
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+
+    let (status, body) = operator.lock().unwrap().handle(&request);
+    let body = body.to_string();
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\
+         connection: close\r\n\r\n{}",
+        status,
+        if status == 200 { "OK" } else { "Error" },
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        order_flow::PrivateOrderFlow, slot_source::ManualSlotSource, threshold::deal_committee,
+    };
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+
+    #[test]
+    fn test_mempool_protocol() {
+        let (committee, _key_shares) = deal_committee(2, 3).unwrap();
+        let slots = Arc::new(ManualSlotSource::new(1_000));
+        let operator = Keypair::new();
+        let operator_key = operator.pubkey();
+        let server = MockMempoolServer::spawn(MempoolOperator::new(
+            operator,
+            RevealCoordinator::new(committee.clone(), slots),
+        ))
+        .unwrap();
+
+        let owner = Keypair::new();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[1, 2, 3],
+            vec![AccountMeta::new(owner.pubkey(), true)],
+        );
+        let mut order_flow = PrivateOrderFlow::new(committee, 10);
+        let order = order_flow
+            .encrypt_order(owner.pubkey(), instruction, 1_000)
+            .unwrap();
+
+        let client = MempoolClient::new(server.url(), operator_key);
+        let ack = client.submit(&order, &owner).unwrap();
+        assert_eq!(ack.order_id, order.order_id);
+        assert_eq!(ack.promise.include_by, order.expires_at);
+        assert!(ack.promise.verify(&order, &operator_key));
+        assert_eq!(
+            client.status(order.order_id, &owner).unwrap(),
+            OrderStatus::Pending
+        );
+
+        // Only the owner may submit the order or ask after it
+        let stranger = Keypair::new();
+        assert!(client.submit(&order, &stranger).is_err());
+        assert!(client.status(order.order_id, &stranger).is_err());
+        // A promise from another operator is refused
+        let impostor = MempoolClient::new(server.url(), Pubkey::new_unique());
+        assert!(impostor.submit(&order, &owner).is_err());
+
+        let cancellation = OrderCancellation::sign(&order, &owner).unwrap();
+        client.cancel(&cancellation, &owner).unwrap();
+        assert_eq!(
+            client.status(order.order_id, &owner).unwrap(),
+            OrderStatus::Cancelled
+        );
+
+        // Requests signed too long ago are refused
+        let mut request = MempoolRequest {
+            method: "GET".to_string(),
+            path: format!("/v1/orders/{}", order.order_id),
+            ..MempoolRequest::default()
+        };
+        let stale = unix_now() - MAX_REQUEST_SKEW - 1;
+        let signature = owner.sign_message(&request_message("GET", &request.path, stale, &[]));
+        request.headers = HashMap::from([
+            (PUBKEY_HEADER.to_string(), owner.pubkey().to_string()),
+            (TIMESTAMP_HEADER.to_string(), stale.to_string()),
+            (SIGNATURE_HEADER.to_string(), signature.to_string()),
+        ]);
+        assert_eq!(server.operator().lock().unwrap().handle(&request).0, 401);
+    }
+}
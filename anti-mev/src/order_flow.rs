@@ -1,126 +1,734 @@
-use anyhow::Result;
-use solana_sdk::instruction::Instruction;
-use borsh::BorshSerialize;
+use aes_gcm::{
+    aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+    Aes256Gcm,
+};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
-
-/// Private order flow for MEV protection
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+};
+use std::{collections::HashMap, sync::Arc};
+use untrace_common::crypto::order_payload_hash;
+use untrace_privacy_program::{accounts, instruction};
+
+use crate::{
+    order_keys::OrderKeyStore,
+    slot_source::SlotSource,
+    threshold::{CommitteeKey, DecryptionShare, KeyShare},
+};
+
+/// Domain of the keys orders are encrypted under
+const ORDER_KDF_DOMAIN: &[u8] = b"UNTRACE_ORDER_FLOW";
+/// Domain of the messages owners sign to cancel orders
+const ORDER_CANCEL_DOMAIN: &[u8] = b"UNTRACE_ORDER_CANCEL";
+
+/// Slots an order stays revealable after its reveal slot, about the
+/// lifetime of a blockhash
+pub const DEFAULT_ORDER_TTL: u64 = 150;
+
+/// Private order flow for MEV protection. Orders are encrypted to a
+/// decryption committee and can only be opened once enough of its members
+/// release their shares, which they do from the order's reveal slot.
 pub struct PrivateOrderFlow {
+    committee: CommitteeKey,
+    /// Slots between encrypting an order and its reveal slot
+    reveal_delay: u64,
+    /// Slots between an order's reveal slot and its expiry
+    order_ttl: u64,
+    next_order_id: u64,
     /// Encrypted orders waiting to be revealed
     pending_orders: Vec<EncryptedOrder>,
+    /// Keeps the key of each order sealed, when set
+    key_store: Option<Arc<OrderKeyStore>>,
 }
 
-#[derive(Debug, Clone)]
+/// Order sealed to a committee. Its commitment is published first and
+/// binds the ciphertext and reveal slot, so neither can change before the
+/// reveal.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct EncryptedOrder {
     pub order_id: u64,
+    /// Account that can cancel or replace the order
+    pub owner: Pubkey,
+    pub reveal_slot: u64,
+    /// First slot the order can no longer be revealed at
+    pub expires_at: u64,
+    /// Order this one replaced
+    pub replaces: Option<u64>,
+    /// Ephemeral key the committee's decryption shares are made for
+    pub ephemeral_key: [u8; 32],
+    pub tag: [u8; 16],
     pub encrypted_data: Vec<u8>,
+    /// Hash of the instruction and a salt sealed with it, which the
+    /// privacy program's `commit_order` records
+    pub payload_hash: [u8; 32],
     pub commitment: [u8; 32],
-    pub reveal_slot: u64,
+}
+
+impl EncryptedOrder {
+    /// Create a commitment hash for an order
+    fn create_commitment(&self) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"ORDER_COMMITMENT");
+        hasher.update(self.order_id.to_le_bytes());
+        hasher.update(self.owner);
+        hasher.update(self.reveal_slot.to_le_bytes());
+        hasher.update(self.expires_at.to_le_bytes());
+        match self.replaces {
+            Some(replaced) => {
+                hasher.update([1]);
+                hasher.update(replaced.to_le_bytes());
+            }
+            None => hasher.update([0]),
+        }
+        hasher.update(self.ephemeral_key);
+        hasher.update(self.tag);
+        hasher.update(&self.encrypted_data);
+        hasher.update(self.payload_hash);
+        hasher.finalize().into()
+    }
+
+    /// `program_id`'s `commit_order` for this order, signed by its owner.
+    /// On chain the payload is then only accepted from the reveal slot on.
+    pub fn commit_instruction(&self, program_id: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: accounts::CommitOrder {
+                order_commitment: order_commitment_address(program_id, &self.owner, self.order_id),
+                owner: self.owner,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::CommitOrder {
+                order_id: self.order_id,
+                payload_hash: self.payload_hash,
+                reveal_slot: self.reveal_slot,
+                expires_at: self.expires_at,
+            }
+            .data(),
+        }
+    }
+
+    /// Verify the order against its commitment
+    pub fn verify_commitment(&self) -> bool {
+        self.create_commitment() == self.commitment
+    }
+
+    /// Data the encryption authenticates besides the instruction
+    fn associated_data(&self) -> [u8; 16] {
+        let mut data = [0u8; 16];
+        data[..8].copy_from_slice(&self.order_id.to_le_bytes());
+        data[8..].copy_from_slice(&self.reveal_slot.to_le_bytes());
+        data
+    }
+}
+
+/// Owner's signed withdrawal of an order, which also authorizes replacing
+/// it
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct OrderCancellation {
+    pub order_id: u64,
+    /// Commitment of the order, so the signature cancels this order only
+    pub commitment: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl OrderCancellation {
+    /// Cancellation of `order` signed by its `owner`
+    pub fn sign(order: &EncryptedOrder, owner: &dyn Signer) -> Result<Self> {
+        if owner.pubkey() != order.owner {
+            return Err(anyhow!(
+                "{} does not own order {}",
+                owner.pubkey(),
+                order.order_id
+            ));
+        }
+        let signature = owner
+            .try_sign_message(&Self::message(order.order_id, &order.commitment))
+            .map_err(|e| anyhow!("Failed to sign cancellation: {}", e))?;
+        Ok(Self {
+            order_id: order.order_id,
+            commitment: order.commitment,
+            signature: signature.into(),
+        })
+    }
+
+    /// Whether this cancels `order` and its owner signed it
+    pub fn verify(&self, order: &EncryptedOrder) -> bool {
+        self.order_id == order.order_id
+            && self.commitment == order.commitment
+            && Signature::from(self.signature).verify(
+                order.owner.as_ref(),
+                &Self::message(self.order_id, &self.commitment),
+            )
+    }
+
+    fn message(order_id: u64, commitment: &[u8; 32]) -> Vec<u8> {
+        [ORDER_CANCEL_DOMAIN, &order_id.to_le_bytes(), commitment].concat()
+    }
+}
+
+/// Order decrypted by a `RevealCoordinator`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevealedOrder {
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub instruction: Instruction,
+    /// Salt and serialized instruction the payload hash was made of
+    pub salt: [u8; 32],
+    pub payload: Vec<u8>,
+}
+
+impl RevealedOrder {
+    /// `program_id`'s `reveal_order` for this order, which anyone may send.
+    /// It fails unless the payload is the one committed to.
+    pub fn reveal_instruction(&self, program_id: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: accounts::RevealOrder {
+                order_commitment: order_commitment_address(program_id, &self.owner, self.order_id),
+                owner: self.owner,
+            }
+            .to_account_metas(None),
+            data: instruction::RevealOrder {
+                salt: self.salt,
+                payload: self.payload.clone(),
+            }
+            .data(),
+        }
+    }
+}
+
+/// Account `commit_order` creates for `owner`'s order `order_id`
+pub fn order_commitment_address(program_id: &Pubkey, owner: &Pubkey, order_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"order_commitment", owner.as_ref(), &order_id.to_le_bytes()],
+        program_id,
+    )
+    .0
+}
+
+/// Where a `RevealCoordinator` is with an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    /// Committed and waiting for shares
+    Pending,
+    Revealed,
+    /// Withdrawn by its owner
+    Cancelled,
+    /// Withdrawn by its owner for the order `by`
+    Replaced {
+        by: u64,
+    },
+    /// Not revealed before it expired
+    Expired,
+}
+
+/// Instruction as it is encrypted in an order
+#[derive(BorshSerialize, BorshDeserialize)]
+struct OrderPayload {
+    program_id: Pubkey,
+    /// Key, signer and writable flags of each account
+    accounts: Vec<(Pubkey, bool, bool)>,
+    data: Vec<u8>,
+}
+
+/// Plaintext of an order: the serialized `OrderPayload` and its salt
+#[derive(BorshSerialize, BorshDeserialize)]
+struct SealedPayload {
+    salt: [u8; 32],
+    payload: Vec<u8>,
+}
+
+impl From<Instruction> for OrderPayload {
+    fn from(instruction: Instruction) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .into_iter()
+                .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+                .collect(),
+            data: instruction.data,
+        }
+    }
+}
+
+impl From<OrderPayload> for Instruction {
+    fn from(payload: OrderPayload) -> Self {
+        Self {
+            program_id: payload.program_id,
+            accounts: payload
+                .accounts
+                .into_iter()
+                .map(|(pubkey, is_signer, is_writable)| AccountMeta {
+                    pubkey,
+                    is_signer,
+                    is_writable,
+                })
+                .collect(),
+            data: payload.data,
+        }
+    }
 }
 
 impl PrivateOrderFlow {
-    pub fn new() -> Self {
+    /// Order flow encrypting to `committee`, revealing orders
+    /// `reveal_delay` slots after they are encrypted
+    pub fn new(committee: CommitteeKey, reveal_delay: u64) -> Self {
         Self {
+            committee,
+            reveal_delay,
+            order_ttl: DEFAULT_ORDER_TTL,
+            next_order_id: 0,
             pending_orders: Vec::new(),
+            key_store: None,
         }
     }
 
-    /// Encrypt an order for private submission
-    pub fn encrypt_order(&mut self, instruction: Instruction) -> Result<Vec<u8>> {
-        // Serialize instruction
-        let serialized = instruction.try_to_vec()?;
+    /// Keep each order's key in `key_store`, so its owner can open the
+    /// order without the committee
+    pub fn with_key_store(mut self, key_store: Arc<OrderKeyStore>) -> Self {
+        self.key_store = Some(key_store);
+        self
+    }
 
-        // Simple encryption (in production use proper AEAD)
-        let mut key = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut key);
+    /// Expire orders `order_ttl` slots after their reveal slot
+    pub fn with_order_ttl(mut self, order_ttl: u64) -> Self {
+        self.order_ttl = order_ttl;
+        self
+    }
+
+    pub fn committee(&self) -> &CommitteeKey {
+        &self.committee
+    }
 
-        let mut encrypted = Vec::new();
-        for (i, byte) in serialized.iter().enumerate() {
-            encrypted.push(byte ^ key[i % 32]);
+    /// Encrypt `owner`'s order at `current_slot` for private submission
+    pub fn encrypt_order(
+        &mut self,
+        owner: Pubkey,
+        instruction: Instruction,
+        current_slot: u64,
+    ) -> Result<EncryptedOrder> {
+        self.seal(owner, instruction, current_slot, None)
+    }
+
+    /// Cancel a pending order by its owner's `cancellation`
+    pub fn cancel_order(&mut self, cancellation: &OrderCancellation) -> Result<EncryptedOrder> {
+        let index = self
+            .pending_orders
+            .iter()
+            .position(|order| order.order_id == cancellation.order_id)
+            .ok_or_else(|| anyhow!("Order {} is not pending", cancellation.order_id))?;
+        if !cancellation.verify(&self.pending_orders[index]) {
+            return Err(anyhow!(
+                "Cancellation of order {} is not signed by its owner",
+                cancellation.order_id
+            ));
         }
+        Ok(self.pending_orders.remove(index))
+    }
 
-        // Create commitment
-        let commitment = self.create_commitment(&encrypted);
+    /// Cancel a pending order and encrypt `instruction` for the same owner
+    /// in its place. The new order has a new id, reveal slot and expiry.
+    pub fn replace_order(
+        &mut self,
+        cancellation: &OrderCancellation,
+        instruction: Instruction,
+        current_slot: u64,
+    ) -> Result<EncryptedOrder> {
+        let replaced = self.cancel_order(cancellation)?;
+        self.seal(
+            replaced.owner,
+            instruction,
+            current_slot,
+            Some(replaced.order_id),
+        )
+    }
 
-        // Store encrypted order
-        let order = EncryptedOrder {
-            order_id: self.pending_orders.len() as u64,
-            encrypted_data: encrypted.clone(),
-            commitment,
-            reveal_slot: 1000 + 10, // Reveal after 10 slots
+    fn seal(
+        &mut self,
+        owner: Pubkey,
+        instruction: Instruction,
+        current_slot: u64,
+        replaces: Option<u64>,
+    ) -> Result<EncryptedOrder> {
+        let order_id = self.next_order_id;
+        let mut salt = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let payload = OrderPayload::from(instruction).try_to_vec()?;
+        let payload_hash = order_payload_hash(&owner.to_bytes(), order_id, &salt, &payload);
+        let mut encrypted_data = SealedPayload { salt, payload }.try_to_vec()?;
+        let (ephemeral_key, shared) = self.committee.encapsulate();
+        let reveal_slot = current_slot + self.reveal_delay;
+        let mut order = EncryptedOrder {
+            order_id,
+            owner,
+            reveal_slot,
+            expires_at: reveal_slot + self.order_ttl,
+            replaces,
+            ephemeral_key,
+            tag: [0u8; 16],
+            encrypted_data: Vec::new(),
+            payload_hash,
+            commitment: [0u8; 32],
         };
+        let tag = order_cipher(&shared, &ephemeral_key)
+            .encrypt_in_place_detached(
+                GenericArray::from_slice(&[0u8; 12]),
+                &order.associated_data(),
+                &mut encrypted_data,
+            )
+            .map_err(|_| anyhow!("Order encryption failed"))?;
+        order.tag.copy_from_slice(&tag);
+        order.encrypted_data = encrypted_data;
+        order.commitment = order.create_commitment();
+        if let Some(key_store) = &self.key_store {
+            key_store.keep(&order, &shared)?;
+        }
 
-        self.pending_orders.push(order);
+        self.next_order_id += 1;
+        self.pending_orders.push(order.clone());
+        Ok(order)
+    }
 
-        Ok(encrypted)
+    /// Get pending order count
+    pub fn pending_count(&self) -> usize {
+        self.pending_orders.len()
     }
 
-    /// Decrypt and reveal an order
-    pub fn reveal_order(&self, order_id: u64, key: &[u8; 32]) -> Result<Vec<u8>> {
-        let order = self.pending_orders
-            .iter()
-            .find(|o| o.order_id == order_id)
-            .ok_or_else(|| anyhow::anyhow!("Order not found"))?;
+    /// Stop tracking orders expired by `current_slot`, returning them so
+    /// their owners can be told to submit again
+    pub fn expire_orders(&mut self, current_slot: u64) -> Vec<EncryptedOrder> {
+        let (expired, pending) = std::mem::take(&mut self.pending_orders)
+            .into_iter()
+            .partition(|order| order.expires_at <= current_slot);
+        self.pending_orders = pending;
+        expired
+    }
+}
 
-        // Decrypt
-        let mut decrypted = Vec::new();
-        for (i, byte) in order.encrypted_data.iter().enumerate() {
-            decrypted.push(byte ^ key[i % 32]);
+/// A member of the decryption committee. Releases its decryption share of
+/// an order only once the slot source reaches the order's reveal slot.
+pub struct CommitteeMember {
+    key_share: KeyShare,
+    slot_source: Arc<dyn SlotSource>,
+}
+
+impl CommitteeMember {
+    pub fn new(key_share: KeyShare, slot_source: Arc<dyn SlotSource>) -> Self {
+        Self {
+            key_share,
+            slot_source,
         }
+    }
 
-        Ok(decrypted)
+    pub fn index(&self) -> u32 {
+        self.key_share.index
     }
 
-    /// Create a commitment hash for an order
-    fn create_commitment(&self, data: &[u8]) -> [u8; 32] {
-        let mut hasher = Sha3_256::new();
-        hasher.update(data);
-        hasher.update(b"ORDER_COMMITMENT");
+    /// This member's decryption share of `order`, refused before its
+    /// reveal slot and from its expiry
+    pub fn decryption_share(&self, order: &EncryptedOrder) -> Result<DecryptionShare> {
+        if !order.verify_commitment() {
+            return Err(anyhow!(
+                "Order {} does not match its commitment",
+                order.order_id
+            ));
+        }
+        ensure_revealable(order, self.slot_source.current_slot()?)?;
+        self.key_share.decryption_share(&order.ephemeral_key)
+    }
+}
 
-        let result = hasher.finalize();
-        let mut output = [0u8; 32];
-        output.copy_from_slice(&result);
-        output
+/// Collects the committee's decryption shares of committed orders and
+/// opens each once it reaches its reveal slot with `threshold` valid shares
+pub struct RevealCoordinator {
+    committee: CommitteeKey,
+    slot_source: Arc<dyn SlotSource>,
+    orders: HashMap<u64, PendingReveal>,
+    /// How each order no longer pending ended
+    closed: HashMap<u64, OrderStatus>,
+}
+
+struct PendingReveal {
+    order: EncryptedOrder,
+    shares: Vec<DecryptionShare>,
+}
+
+impl RevealCoordinator {
+    pub fn new(committee: CommitteeKey, slot_source: Arc<dyn SlotSource>) -> Self {
+        Self {
+            committee,
+            slot_source,
+            orders: HashMap::new(),
+            closed: HashMap::new(),
+        }
     }
 
-    /// Verify an order commitment
-    pub fn verify_commitment(&self, data: &[u8], commitment: &[u8; 32]) -> bool {
-        let computed = self.create_commitment(data);
-        &computed == commitment
+    /// Track a committed order until it is revealed
+    pub fn commit(&mut self, order: EncryptedOrder) -> Result<()> {
+        if let Some(replaced) = order.replaces {
+            return Err(anyhow!(
+                "Order {} replaces order {}, so needs its cancellation",
+                order.order_id,
+                replaced
+            ));
+        }
+        self.track(order)
     }
 
-    /// Get pending order count
-    pub fn pending_count(&self) -> usize {
-        self.pending_orders.len()
+    /// Cancel a pending order by its owner's `cancellation`. Shares already
+    /// handed to others cannot be taken back.
+    pub fn cancel(&mut self, cancellation: &OrderCancellation) -> Result<()> {
+        self.withdraw(cancellation)?;
+        self.closed
+            .insert(cancellation.order_id, OrderStatus::Cancelled);
+        Ok(())
+    }
+
+    /// Cancel a pending order and commit `order`, which its owner made to
+    /// replace it, in its place
+    pub fn replace(
+        &mut self,
+        cancellation: &OrderCancellation,
+        order: EncryptedOrder,
+    ) -> Result<()> {
+        let replaced = self
+            .orders
+            .get(&cancellation.order_id)
+            .ok_or_else(|| anyhow!("Order {} is not pending", cancellation.order_id))?;
+        if order.replaces != Some(cancellation.order_id) || order.owner != replaced.order.owner {
+            return Err(anyhow!(
+                "Order {} is not its owner's replacement of order {}",
+                order.order_id,
+                cancellation.order_id
+            ));
+        }
+        if self.orders.contains_key(&order.order_id) || self.closed.contains_key(&order.order_id) {
+            return Err(anyhow!("Order {} is already committed", order.order_id));
+        }
+        if !order.verify_commitment() {
+            return Err(anyhow!(
+                "Order {} does not match its commitment",
+                order.order_id
+            ));
+        }
+        self.withdraw(cancellation)?;
+        self.closed.insert(
+            cancellation.order_id,
+            OrderStatus::Replaced { by: order.order_id },
+        );
+        self.track(order)
+    }
+
+    /// Stop tracking orders that expired unrevealed, returning them so
+    /// their owners can be told to submit again
+    pub fn expire(&mut self) -> Result<Vec<EncryptedOrder>> {
+        let current_slot = self.slot_source.current_slot()?;
+        let expired: Vec<u64> = self
+            .orders
+            .values()
+            .filter(|pending| pending.order.expires_at <= current_slot)
+            .map(|pending| pending.order.order_id)
+            .collect();
+        let mut orders = Vec::new();
+        for order_id in expired {
+            orders.push(self.orders.remove(&order_id).unwrap().order);
+            self.closed.insert(order_id, OrderStatus::Expired);
+        }
+        orders.sort_by_key(|order| order.order_id);
+        Ok(orders)
     }
 
-    /// Remove revealed orders
-    pub fn cleanup_revealed(&mut self, current_slot: u64) {
-        self.pending_orders
-            .retain(|order| order.reveal_slot > current_slot);
+    pub fn status(&self, order_id: u64) -> Option<OrderStatus> {
+        if self.orders.contains_key(&order_id) {
+            return Some(OrderStatus::Pending);
+        }
+        self.closed.get(&order_id).copied()
     }
 
-    /// Submit order to private mempool
-    pub async fn submit_to_private_mempool(&self, order: &EncryptedOrder) -> Result<()> {
-        // In production, this would submit to a private mempool service
-        // like Flashbots, Eden, or a custom privacy-focused mempool
-        println!("Submitting order {} to private mempool", order.order_id);
+    /// Remove the pending order `cancellation` is for, if its owner signed
+    /// it
+    fn withdraw(&mut self, cancellation: &OrderCancellation) -> Result<EncryptedOrder> {
+        let pending = self
+            .orders
+            .get(&cancellation.order_id)
+            .ok_or_else(|| anyhow!("Order {} is not pending", cancellation.order_id))?;
+        if !cancellation.verify(&pending.order) {
+            return Err(anyhow!(
+                "Cancellation of order {} is not signed by its owner",
+                cancellation.order_id
+            ));
+        }
+        Ok(self.orders.remove(&cancellation.order_id).unwrap().order)
+    }
+
+    fn track(&mut self, order: EncryptedOrder) -> Result<()> {
+        if let Some(status) = self.closed.get(&order.order_id) {
+            return Err(anyhow!("Order {} is already {:?}", order.order_id, status));
+        }
+        if !order.verify_commitment() {
+            return Err(anyhow!(
+                "Order {} does not match its commitment",
+                order.order_id
+            ));
+        }
+        if let Some(pending) = self.orders.get(&order.order_id) {
+            if pending.order.commitment != order.commitment {
+                return Err(anyhow!("Order {} is already committed", order.order_id));
+            }
+            return Ok(());
+        }
+        self.orders.insert(
+            order.order_id,
+            PendingReveal {
+                order,
+                shares: Vec::new(),
+            },
+        );
         Ok(())
     }
+
+    /// Add a member's decryption share of `order_id`. Returns whether the
+    /// order now has enough shares to reveal.
+    pub fn add_share(&mut self, order_id: u64, share: DecryptionShare) -> Result<bool> {
+        let current_slot = self.slot_source.current_slot()?;
+        let pending = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or_else(|| anyhow!("Order {} is not committed", order_id))?;
+        ensure_revealable(&pending.order, current_slot)?;
+        if !self
+            .committee
+            .verify_share(&pending.order.ephemeral_key, &share)
+        {
+            return Err(anyhow!(
+                "Invalid decryption share from member {} for order {}",
+                share.index,
+                order_id
+            ));
+        }
+        if pending
+            .shares
+            .iter()
+            .all(|other| other.index != share.index)
+        {
+            pending.shares.push(share);
+        }
+        Ok(pending.shares.len() >= self.committee.threshold())
+    }
+
+    /// Decrypt `order_id` from its shares and stop tracking it. Fails if
+    /// the plaintext is not the payload its hash commits to.
+    pub fn reveal(&mut self, order_id: u64) -> Result<RevealedOrder> {
+        let current_slot = self.slot_source.current_slot()?;
+        let pending = self
+            .orders
+            .get(&order_id)
+            .ok_or_else(|| anyhow!("Order {} is not committed", order_id))?;
+        ensure_revealable(&pending.order, current_slot)?;
+
+        let shared = self
+            .committee
+            .combine(&pending.order.ephemeral_key, &pending.shares)?;
+        let revealed = open_order(&pending.order, &shared)?;
+
+        self.orders.remove(&order_id);
+        self.closed.insert(order_id, OrderStatus::Revealed);
+        Ok(revealed)
+    }
+
+    /// Number of committed orders not revealed yet
+    pub fn pending_count(&self) -> usize {
+        self.orders.len()
+    }
+}
+
+/// Decrypt `order` with the secret `shared` its ephemeral key has with the
+/// committee. Fails if the plaintext is not the payload its hash commits
+/// to.
+pub(crate) fn open_order(order: &EncryptedOrder, shared: &[u8; 32]) -> Result<RevealedOrder> {
+    let mut plaintext = order.encrypted_data.clone();
+    order_cipher(shared, &order.ephemeral_key)
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&[0u8; 12]),
+            &order.associated_data(),
+            &mut plaintext,
+            GenericArray::from_slice(&order.tag),
+        )
+        .map_err(|_| anyhow!("Order {} failed to decrypt", order.order_id))?;
+    let SealedPayload { salt, payload } = SealedPayload::try_from_slice(&plaintext)?;
+    if order_payload_hash(&order.owner.to_bytes(), order.order_id, &salt, &payload)
+        != order.payload_hash
+    {
+        return Err(anyhow!(
+            "Order {} does not match its payload hash",
+            order.order_id
+        ));
+    }
+    Ok(RevealedOrder {
+        order_id: order.order_id,
+        owner: order.owner,
+        instruction: OrderPayload::try_from_slice(&payload)?.into(),
+        salt,
+        payload,
+    })
+}
+
+pub(crate) fn ensure_revealable(order: &EncryptedOrder, current_slot: u64) -> Result<()> {
+    if current_slot < order.reveal_slot {
+        return Err(anyhow!(
+            "Order {} is sealed until slot {}, the current slot is {}",
+            order.order_id,
+            order.reveal_slot,
+            current_slot
+        ));
+    }
+    if current_slot >= order.expires_at {
+        return Err(anyhow!(
+            "Order {} expired at slot {}",
+            order.order_id,
+            order.expires_at
+        ));
+    }
+    Ok(())
+}
+
+fn order_cipher(shared: &[u8; 32], ephemeral_key: &[u8; 32]) -> Aes256Gcm {
+    let mut hasher = Sha3_256::new();
+    hasher.update(ORDER_KDF_DOMAIN);
+    hasher.update(shared);
+    hasher.update(ephemeral_key);
+    Aes256Gcm::new(&hasher.finalize())
 }
 
 /// Builder for private order flow
 pub struct OrderFlowBuilder {
     instruction: Option<Instruction>,
+    committee: Option<CommitteeKey>,
     reveal_delay: u64,
+    order_ttl: u64,
     use_private_mempool: bool,
+    key_store: Option<Arc<OrderKeyStore>>,
 }
 
 impl OrderFlowBuilder {
     pub fn new() -> Self {
         Self {
             instruction: None,
+            committee: None,
             reveal_delay: 10,
+            order_ttl: DEFAULT_ORDER_TTL,
             use_private_mempool: false,
+            key_store: None,
         }
     }
 
@@ -129,49 +737,199 @@ impl OrderFlowBuilder {
         self
     }
 
+    /// Committee orders are encrypted to
+    pub fn committee(mut self, committee: CommitteeKey) -> Self {
+        self.committee = Some(committee);
+        self
+    }
+
     pub fn reveal_delay(mut self, slots: u64) -> Self {
         self.reveal_delay = slots;
         self
     }
 
+    pub fn order_ttl(mut self, slots: u64) -> Self {
+        self.order_ttl = slots;
+        self
+    }
+
     pub fn use_private_mempool(mut self, enabled: bool) -> Self {
         self.use_private_mempool = enabled;
         self
     }
 
+    pub fn key_store(mut self, key_store: Arc<OrderKeyStore>) -> Self {
+        self.key_store = Some(key_store);
+        self
+    }
+
     pub fn build(self) -> Result<PrivateOrderFlow> {
-        Ok(PrivateOrderFlow::new())
+        let committee = self
+            .committee
+            .ok_or_else(|| anyhow!("A decryption committee is required"))?;
+        let order_flow =
+            PrivateOrderFlow::new(committee, self.reveal_delay).with_order_ttl(self.order_ttl);
+        Ok(match self.key_store {
+            Some(key_store) => order_flow.with_key_store(key_store),
+            None => order_flow,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::pubkey::Pubkey;
+    use crate::{slot_source::ManualSlotSource, threshold::deal_committee};
+    use solana_sdk::signature::Keypair;
 
     #[test]
     fn test_order_encryption() {
-        let mut order_flow = PrivateOrderFlow::new();
+        let (committee, key_shares) = deal_committee(2, 3).unwrap();
+        let slots = Arc::new(ManualSlotSource::new(1_000));
+        let mut order_flow = OrderFlowBuilder::new()
+            .committee(committee.clone())
+            .reveal_delay(10)
+            .build()
+            .unwrap();
 
         let instruction = Instruction::new_with_bytes(
             Pubkey::new_unique(),
             &[1, 2, 3],
-            vec![],
+            vec![AccountMeta::new(Pubkey::new_unique(), true)],
         );
-
-        let encrypted = order_flow.encrypt_order(instruction).unwrap();
-        assert!(!encrypted.is_empty());
+        let order = order_flow
+            .encrypt_order(Pubkey::new_unique(), instruction.clone(), 1_000)
+            .unwrap();
+        assert_eq!(order.reveal_slot, 1_010);
         assert_eq!(order_flow.pending_count(), 1);
+
+        let members: Vec<CommitteeMember> = key_shares
+            .into_iter()
+            .map(|share| CommitteeMember::new(share, slots.clone()))
+            .collect();
+        let mut coordinator = RevealCoordinator::new(committee, slots.clone());
+        coordinator.commit(order.clone()).unwrap();
+
+        // Nobody opens the order before its reveal slot
+        assert!(members[0].decryption_share(&order).is_err());
+        assert!(coordinator.reveal(order.order_id).is_err());
+
+        slots.set_slot(1_010);
+        let share = members[0].decryption_share(&order).unwrap();
+        assert!(!coordinator
+            .add_share(order.order_id, share.clone())
+            .unwrap());
+        assert!(!coordinator.add_share(order.order_id, share).unwrap());
+        assert!(coordinator.reveal(order.order_id).is_err());
+        let share = members[2].decryption_share(&order).unwrap();
+        assert!(coordinator.add_share(order.order_id, share).unwrap());
+        let revealed = coordinator.reveal(order.order_id).unwrap();
+        assert_eq!(revealed.instruction, instruction);
+        assert_eq!(coordinator.pending_count(), 0);
+
+        // The program checks the revealed payload against the committed hash
+        let program_id = untrace_privacy_program::ID;
+        let commit = order.commit_instruction(&program_id);
+        let reveal = revealed.reveal_instruction(&program_id);
+        assert_eq!(commit.accounts[0].pubkey, reveal.accounts[0].pubkey);
+        assert!(commit.data.ends_with(
+            &[
+                order.payload_hash.as_ref(),
+                &order.reveal_slot.to_le_bytes(),
+                &order.expires_at.to_le_bytes(),
+            ]
+            .concat()
+        ));
+        assert_eq!(
+            order_payload_hash(
+                &order.owner.to_bytes(),
+                order.order_id,
+                &revealed.salt,
+                &revealed.payload
+            ),
+            order.payload_hash
+        );
     }
 
     #[test]
     fn test_commitment_verification() {
-        let order_flow = PrivateOrderFlow::new();
+        let (committee, _) = deal_committee(1, 1).unwrap();
+        let mut order_flow = PrivateOrderFlow::new(committee.clone(), 10);
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]);
+        let order = order_flow
+            .encrypt_order(Pubkey::new_unique(), instruction, 1_000)
+            .unwrap();
+        assert!(order.verify_commitment());
+
+        // An order moved to an earlier reveal slot no longer matches
+        let mut early = order.clone();
+        early.reveal_slot = 1_000;
+        assert!(!early.verify_commitment());
+        let slots = Arc::new(ManualSlotSource::new(1_000));
+        let mut coordinator = RevealCoordinator::new(committee, slots);
+        assert!(coordinator.commit(early).is_err());
+    }
 
-        let data = b"test order data";
-        let commitment = order_flow.create_commitment(data);
+    #[test]
+    fn test_order_cancellation_and_expiry() {
+        let (committee, key_shares) = deal_committee(1, 1).unwrap();
+        let slots = Arc::new(ManualSlotSource::new(1_000));
+        let mut order_flow = PrivateOrderFlow::new(committee.clone(), 10).with_order_ttl(5);
+        let mut coordinator = RevealCoordinator::new(committee, slots.clone());
+        let owner = Keypair::new();
+        let instruction =
+            |data: u8| Instruction::new_with_bytes(Pubkey::new_unique(), &[data], vec![]);
+
+        let order = order_flow
+            .encrypt_order(owner.pubkey(), instruction(1), 1_000)
+            .unwrap();
+        coordinator.commit(order.clone()).unwrap();
+        assert!(OrderCancellation::sign(&order, &Keypair::new()).is_err());
+        // A cancellation needs the owner's signature
+        let mut forged = OrderCancellation::sign(&order, &owner).unwrap();
+        forged.signature[0] ^= 1;
+        assert!(coordinator.cancel(&forged).is_err());
+
+        let cancellation = OrderCancellation::sign(&order, &owner).unwrap();
+        let replacement = order_flow
+            .replace_order(&cancellation, instruction(2), 1_001)
+            .unwrap();
+        assert_eq!(replacement.replaces, Some(order.order_id));
+        assert!(coordinator.commit(replacement.clone()).is_err());
+        coordinator
+            .replace(&cancellation, replacement.clone())
+            .unwrap();
+        assert_eq!(
+            coordinator.status(order.order_id),
+            Some(OrderStatus::Replaced {
+                by: replacement.order_id
+            })
+        );
+        // A cancelled order cannot come back
+        assert!(coordinator.commit(order).is_err());
+
+        let other = order_flow
+            .encrypt_order(owner.pubkey(), instruction(3), 1_001)
+            .unwrap();
+        coordinator.commit(other.clone()).unwrap();
+        coordinator
+            .cancel(&OrderCancellation::sign(&other, &owner).unwrap())
+            .unwrap();
+        assert_eq!(
+            coordinator.status(other.order_id),
+            Some(OrderStatus::Cancelled)
+        );
 
-        assert!(order_flow.verify_commitment(data, &commitment));
-        assert!(!order_flow.verify_commitment(b"wrong data", &commitment));
+        // The replacement reveals from slot 1_011 and expires at 1_016
+        let member = CommitteeMember::new(key_shares[0].clone(), slots.clone());
+        slots.set_slot(1_016);
+        assert!(member.decryption_share(&replacement).is_err());
+        assert_eq!(coordinator.expire().unwrap(), vec![replacement.clone()]);
+        assert_eq!(
+            coordinator.status(replacement.order_id),
+            Some(OrderStatus::Expired)
+        );
+        assert_eq!(order_flow.expire_orders(1_016).len(), 2);
+        assert_eq!(order_flow.pending_count(), 0);
     }
 }
@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    order_flow::{ensure_revealable, open_order, EncryptedOrder, RevealedOrder},
+    slot_source::SlotSource,
+    threshold::{CommitteeKey, DecryptionShare},
+};
+
+/// Where an `OrderKeyStore` keeps its keys, such as a wallet's secure
+/// storage or a KMS
+pub trait OrderKeyVault: Send + Sync {
+    /// Read the value kept under `name`
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Keep `value` under `name`, replacing any previous value
+    fn put(&self, name: &str, value: &[u8]) -> Result<()>;
+
+    /// Remove the value kept under `name`, if any
+    fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// Vault kept in memory for the life of the process, for tests
+#[derive(Debug, Default)]
+pub struct MemoryKeyVault {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryKeyVault {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OrderKeyVault for MemoryKeyVault {
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(name).cloned())
+    }
+
+    fn put(&self, name: &str, value: &[u8]) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+/// Key an order was sealed under, with the window it may be opened in
+#[derive(BorshSerialize, BorshDeserialize)]
+struct OrderKeyRecord {
+    reveal_slot: u64,
+    expires_at: u64,
+    /// Secret the order's ephemeral key shares with the committee
+    key: [u8; 32],
+}
+
+/// Owner's copy of each order's key, so the owner can open its own orders
+/// to send `reveal_order` if the committee does not. Like a committee
+/// member, the store only opens an order from its reveal slot until it
+/// expires. The committee holds every key in escrow: a threshold of its
+/// decryption shares rebuilds a lost one with `recover`.
+pub struct OrderKeyStore {
+    vault: Arc<dyn OrderKeyVault>,
+    slot_source: Arc<dyn SlotSource>,
+}
+
+impl OrderKeyStore {
+    /// Store keeping keys in `vault`, measuring reveal windows against
+    /// `slot_source`
+    pub fn new(vault: Arc<dyn OrderKeyVault>, slot_source: Arc<dyn SlotSource>) -> Self {
+        Self { vault, slot_source }
+    }
+
+    /// Keep the key `order` was sealed under
+    pub(crate) fn keep(&self, order: &EncryptedOrder, key: &[u8; 32]) -> Result<()> {
+        let record = OrderKeyRecord {
+            reveal_slot: order.reveal_slot,
+            expires_at: order.expires_at,
+            key: *key,
+        };
+        self.vault.put(&key_name(order), &record.try_to_vec()?)
+    }
+
+    /// Whether the key of `order` is kept
+    pub fn contains(&self, order: &EncryptedOrder) -> Result<bool> {
+        Ok(self.vault.get(&key_name(order))?.is_some())
+    }
+
+    /// Open `order` with its kept key, refused before its reveal slot and
+    /// from its expiry
+    pub fn reveal(&self, order: &EncryptedOrder) -> Result<RevealedOrder> {
+        let record = self
+            .vault
+            .get(&key_name(order))?
+            .ok_or_else(|| anyhow!("No key is kept for order {}", order.order_id))?;
+        let record = OrderKeyRecord::try_from_slice(&record)?;
+        // The record's window is the one the key was kept for, whatever
+        // `order` now claims
+        let window = EncryptedOrder {
+            reveal_slot: record.reveal_slot,
+            expires_at: record.expires_at,
+            ..order.clone()
+        };
+        ensure_revealable(&window, self.slot_source.current_slot()?)?;
+        if !order.verify_commitment() {
+            return Err(anyhow!(
+                "Order {} does not match its commitment",
+                order.order_id
+            ));
+        }
+        open_order(order, &record.key)
+    }
+
+    /// Rebuild the key of `order` from the committee's decryption `shares`,
+    /// which members only release in its reveal window, and keep it
+    pub fn recover(
+        &self,
+        committee: &CommitteeKey,
+        order: &EncryptedOrder,
+        shares: &[DecryptionShare],
+    ) -> Result<()> {
+        let key = committee.combine(&order.ephemeral_key, shares)?;
+        // A key is only kept once it is known to open the order
+        open_order(order, &key)?;
+        self.keep(order, &key)
+    }
+
+    /// Forget the key of `order`, once it is revealed or withdrawn
+    pub fn remove(&self, order: &EncryptedOrder) -> Result<()> {
+        self.vault.delete(&key_name(order))
+    }
+}
+
+/// Name an order's key is kept under, by its commitment so orders of
+/// different order flows do not collide
+fn key_name(order: &EncryptedOrder) -> String {
+    let commitment: String = order
+        .commitment
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("order_key_{}", commitment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        order_flow::{CommitteeMember, PrivateOrderFlow},
+        slot_source::ManualSlotSource,
+        threshold::deal_committee,
+    };
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_order_key_store() {
+        let (committee, key_shares) = deal_committee(2, 3).unwrap();
+        let slots = Arc::new(ManualSlotSource::new(1_000));
+        let vault = Arc::new(MemoryKeyVault::new());
+        let store = Arc::new(OrderKeyStore::new(vault.clone(), slots.clone()));
+        let mut order_flow =
+            PrivateOrderFlow::new(committee.clone(), 10).with_key_store(store.clone());
+
+        let owner = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[4, 5, 6],
+            vec![AccountMeta::new(owner, true)],
+        );
+        let order = order_flow
+            .encrypt_order(owner, instruction.clone(), 1_000)
+            .unwrap();
+        assert!(store.contains(&order).unwrap());
+
+        // The key is kept but not released before the reveal slot
+        assert!(store.reveal(&order).is_err());
+        slots.set_slot(1_010);
+        let revealed = store.reveal(&order).unwrap();
+        assert_eq!(revealed.instruction, instruction);
+        // A shifted window does not move the kept one
+        let early = EncryptedOrder {
+            reveal_slot: 0,
+            ..order.clone()
+        };
+        slots.set_slot(1_005);
+        assert!(store.reveal(&early).is_err());
+
+        // A lost key comes back from the committee's escrowed shares
+        store.remove(&order).unwrap();
+        assert!(store.reveal(&order).is_err());
+        slots.set_slot(1_010);
+        let shares: Vec<DecryptionShare> = key_shares[..2]
+            .iter()
+            .map(|share| {
+                CommitteeMember::new(share.clone(), slots.clone())
+                    .decryption_share(&order)
+                    .unwrap()
+            })
+            .collect();
+        assert!(store.recover(&committee, &order, &shares[..1]).is_err());
+        store.recover(&committee, &order, &shares).unwrap();
+        assert_eq!(store.reveal(&order).unwrap(), revealed);
+
+        slots.set_slot(order.expires_at);
+        assert!(store.reveal(&order).is_err());
+    }
+}
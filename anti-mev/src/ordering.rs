@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+use sha3::{Digest, Sha3_256, Sha3_512};
+use solana_sdk::instruction::Instruction;
+use std::fmt;
+
+use crate::{
+    batch_processor::Batch,
+    threshold::{decompress, random_scalar},
+};
+
+/// Domain of the points batch commitments are hashed to
+const ORDERING_INPUT_DOMAIN: &[u8] = b"UNTRACE_ORDERING_INPUT";
+/// Domain of the challenges proving an ordering
+const ORDERING_PROOF_DOMAIN: &[u8] = b"UNTRACE_ORDERING_PROOF";
+/// Domain of the seeds orderings are drawn from
+const ORDERING_SEED_DOMAIN: &[u8] = b"UNTRACE_ORDERING_SEED";
+
+/// Length of `OrderingProof::to_bytes`
+pub const ORDERING_PROOF_LEN: usize = 32 * 3;
+
+/// Operator's VRF key. It orders each sealed batch by the one output the
+/// key has for the batch's commitment, so the operator cannot pick an
+/// order and anyone with the public key can check the one it used.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OrderingKey {
+    secret: Scalar,
+}
+
+impl fmt::Debug for OrderingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OrderingKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Proof that a batch was ordered by the VRF output of its commitment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderingProof {
+    /// Secret key times the commitment's point, which the output hashes
+    gamma: RistrettoPoint,
+    challenge: Scalar,
+    response: Scalar,
+}
+
+impl OrderingKey {
+    pub fn generate() -> Self {
+        Self {
+            secret: random_scalar(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Result<Self> {
+        let secret = Option::from(Scalar::from_canonical_bytes(*bytes))
+            .ok_or_else(|| anyhow!("Ordering key is not a canonical scalar"))?;
+        Ok(Self { secret })
+    }
+
+    /// Key the operator publishes for users to check orderings against
+    pub fn public_key(&self) -> [u8; 32] {
+        RistrettoPoint::mul_base(&self.secret).compress().to_bytes()
+    }
+
+    /// Order `instructions` of batch `batch_id`, returning them with the
+    /// proof of their order
+    pub(crate) fn order(
+        &self,
+        batch_id: u64,
+        mut instructions: Vec<Instruction>,
+    ) -> (Vec<Instruction>, OrderingProof) {
+        canonical_sort(&mut instructions);
+        let commitment = batch_commitment(batch_id, &instructions);
+        let proof = self.prove(&commitment);
+        permute(&mut instructions, &proof.seed());
+        (instructions, proof)
+    }
+
+    fn prove(&self, commitment: &[u8; 32]) -> OrderingProof {
+        let public_key = RistrettoPoint::mul_base(&self.secret);
+        let input = hash_to_point(&public_key, commitment);
+        let gamma = self.secret * input;
+
+        // Chaum-Pedersen proof that gamma and the public key have one
+        // discrete log
+        let nonce = random_scalar();
+        let challenge = proof_challenge(
+            &public_key,
+            &input,
+            &gamma,
+            &RistrettoPoint::mul_base(&nonce),
+            &(nonce * input),
+        );
+        OrderingProof {
+            gamma,
+            challenge,
+            response: nonce + challenge * self.secret,
+        }
+    }
+}
+
+impl OrderingProof {
+    pub fn to_bytes(&self) -> [u8; ORDERING_PROOF_LEN] {
+        let mut bytes = [0u8; ORDERING_PROOF_LEN];
+        bytes[..32].copy_from_slice(self.gamma.compress().as_bytes());
+        bytes[32..64].copy_from_slice(self.challenge.as_bytes());
+        bytes[64..].copy_from_slice(self.response.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; ORDERING_PROOF_LEN]) -> Result<Self> {
+        let scalar = |bytes: &[u8]| -> Result<Scalar> {
+            Option::from(Scalar::from_canonical_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| anyhow!("Ordering proof is not canonical"))
+        };
+        Ok(Self {
+            gamma: decompress(bytes[..32].try_into().unwrap(), "ordering proof")?,
+            challenge: scalar(&bytes[32..64])?,
+            response: scalar(&bytes[64..])?,
+        })
+    }
+
+    /// Whether this is the VRF output of `public_key` for `commitment`
+    fn verify(&self, public_key: &RistrettoPoint, commitment: &[u8; 32]) -> bool {
+        let input = hash_to_point(public_key, commitment);
+        let commitment_point =
+            RistrettoPoint::mul_base(&self.response) - self.challenge * public_key;
+        let blinded = self.response * input - self.challenge * self.gamma;
+        proof_challenge(public_key, &input, &self.gamma, &commitment_point, &blinded)
+            == self.challenge
+    }
+
+    /// Seed the permutation is drawn from
+    fn seed(&self) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(ORDERING_SEED_DOMAIN);
+        hasher.update(self.gamma.compress().as_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Check that `batch` is in the order the operator's `public_key` gives
+/// its instructions, so it was not reordered for profit
+pub fn verify_ordering(batch: &Batch, public_key: &[u8; 32]) -> Result<()> {
+    let proof = batch
+        .ordering
+        .as_ref()
+        .ok_or_else(|| anyhow!("Batch {} has no ordering proof", batch.id))?;
+    let public_key = decompress(public_key, "ordering key")?;
+
+    let mut expected = batch.instructions.clone();
+    canonical_sort(&mut expected);
+    if !proof.verify(&public_key, &batch_commitment(batch.id, &expected)) {
+        return Err(anyhow!("Ordering proof of batch {} is invalid", batch.id));
+    }
+    permute(&mut expected, &proof.seed());
+    if expected != batch.instructions {
+        return Err(anyhow!("Batch {} was reordered", batch.id));
+    }
+    Ok(())
+}
+
+/// Sort by hash, so the order instructions arrived in cannot steer the
+/// commitment
+fn canonical_sort(instructions: &mut [Instruction]) {
+    instructions.sort_by_cached_key(instruction_hash);
+}
+
+fn instruction_hash(instruction: &Instruction) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(instruction.program_id.as_ref());
+    hasher.update((instruction.accounts.len() as u64).to_le_bytes());
+    for account in &instruction.accounts {
+        hasher.update(account.pubkey.as_ref());
+        hasher.update([account.is_signer as u8, account.is_writable as u8]);
+    }
+    hasher.update(&instruction.data);
+    hasher.finalize().into()
+}
+
+/// Commitment to a batch's id and canonically sorted instructions
+fn batch_commitment(batch_id: u64, instructions: &[Instruction]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"BATCH_COMMITMENT");
+    hasher.update(batch_id.to_le_bytes());
+    for instruction in instructions {
+        hasher.update(instruction_hash(instruction));
+    }
+    hasher.finalize().into()
+}
+
+/// Fisher-Yates shuffle drawing each swap from `seed`
+fn permute(instructions: &mut [Instruction], seed: &[u8; 32]) {
+    for i in (1..instructions.len()).rev() {
+        let mut hasher = Sha3_256::new();
+        hasher.update(seed);
+        hasher.update((i as u64).to_le_bytes());
+        let digest: [u8; 32] = hasher.finalize().into();
+        // 128 bits make the modulo bias negligible
+        let draw = u128::from_le_bytes(digest[..16].try_into().unwrap());
+        instructions.swap(i, (draw % (i as u128 + 1)) as usize);
+    }
+}
+
+fn hash_to_point(public_key: &RistrettoPoint, commitment: &[u8; 32]) -> RistrettoPoint {
+    let mut hasher = Sha3_512::new();
+    hasher.update(ORDERING_INPUT_DOMAIN);
+    hasher.update(public_key.compress().as_bytes());
+    hasher.update(commitment);
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    RistrettoPoint::from_uniform_bytes(&digest)
+}
+
+fn proof_challenge(
+    public_key: &RistrettoPoint,
+    input: &RistrettoPoint,
+    gamma: &RistrettoPoint,
+    commitment: &RistrettoPoint,
+    blinded: &RistrettoPoint,
+) -> Scalar {
+    let mut hasher = Sha3_512::new();
+    hasher.update(ORDERING_PROOF_DOMAIN);
+    for point in [public_key, input, gamma, commitment, blinded] {
+        hasher.update(point.compress().as_bytes());
+    }
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BatchProcessor;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_verifiable_ordering() {
+        let key = OrderingKey::generate();
+        let instructions: Vec<Instruction> = (0..8u8)
+            .map(|data| Instruction::new_with_bytes(Pubkey::new_unique(), &[data], vec![]))
+            .collect();
+
+        let mut processor = BatchProcessor::new(8);
+        processor.set_ordering_key(Some(key.clone()));
+        for instruction in instructions.iter().rev() {
+            processor.add_to_batch(instruction.clone()).unwrap();
+        }
+        let batch = processor.next_sealed().unwrap();
+        verify_ordering(&batch, &key.public_key()).unwrap();
+
+        // Arrival order does not change the ordering
+        let (reordered, _) = key.order(batch.id, instructions);
+        assert_eq!(reordered, batch.instructions);
+
+        let mut swapped = batch.clone();
+        swapped.instructions.swap(0, 1);
+        assert!(verify_ordering(&swapped, &key.public_key()).is_err());
+        let other = OrderingKey::generate();
+        assert!(verify_ordering(&batch, &other.public_key()).is_err());
+
+        let proof = batch.ordering.as_ref().unwrap();
+        assert_eq!(
+            OrderingProof::from_bytes(&proof.to_bytes()).unwrap(),
+            *proof
+        );
+        assert_eq!(OrderingKey::from_bytes(&key.to_bytes()).unwrap(), key);
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{Heuristic, RiskAssessment, RiskLevel, Sandwich, TransactionEvent};
+
+/// Detector results of one market over one period
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MarketStats {
+    /// Transactions assessed, by risk level
+    pub low: u64,
+    pub medium: u64,
+    pub high: u64,
+    /// Assessments where a heuristic of an attack pattern fired
+    pub attacks_flagged: u64,
+    /// Sandwiches confirmed from pool reserves, and what their victims lost
+    pub sandwiches: u64,
+    pub victim_loss: u64,
+    /// Transactions sent through the anti-MEV layer
+    pub protected: u64,
+    /// Their amounts weighted by risk score, what they stood to lose
+    pub value_protected: f64,
+}
+
+impl MarketStats {
+    pub fn assessed(&self) -> u64 {
+        self.low + self.medium + self.high
+    }
+
+    fn add(&mut self, other: &MarketStats) {
+        self.low += other.low;
+        self.medium += other.medium;
+        self.high += other.high;
+        self.attacks_flagged += other.attacks_flagged;
+        self.sandwiches += other.sandwiches;
+        self.victim_loss += other.victim_loss;
+        self.protected += other.protected;
+        self.value_protected += other.value_protected;
+    }
+}
+
+/// Stats of a market in the period starting at `period_start`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportRow {
+    pub period_start: u64,
+    pub market: Pubkey,
+    #[serde(flatten)]
+    pub stats: MarketStats,
+}
+
+/// Snapshot of a `MevReporter`, rows ordered by period then market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MevReport {
+    pub period_secs: u64,
+    pub rows: Vec<ReportRow>,
+}
+
+impl MevReport {
+    /// Stats of all periods, per market
+    pub fn by_market(&self) -> BTreeMap<Pubkey, MarketStats> {
+        let mut markets: BTreeMap<Pubkey, MarketStats> = BTreeMap::new();
+        for row in &self.rows {
+            markets.entry(row.market).or_default().add(&row.stats);
+        }
+        markets
+    }
+
+    /// Stats of all periods and markets
+    pub fn totals(&self) -> MarketStats {
+        let mut totals = MarketStats::default();
+        for row in &self.rows {
+            totals.add(&row.stats);
+        }
+        totals
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One line per row under a header
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "period_start,market,low,medium,high,attacks_flagged,sandwiches,victim_loss,\
+             protected,value_protected\n",
+        );
+        for ReportRow {
+            period_start,
+            market,
+            stats,
+        } in &self.rows
+        {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{}",
+                period_start,
+                market,
+                stats.low,
+                stats.medium,
+                stats.high,
+                stats.attacks_flagged,
+                stats.sandwiches,
+                stats.victim_loss,
+                stats.protected,
+                stats.value_protected
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
+
+/// Aggregates detector results per market into periods of `period_secs`,
+/// so operators can see what attacks were seen and what the anti-MEV
+/// layer kept from them
+#[derive(Debug, Clone)]
+pub struct MevReporter {
+    period_secs: u64,
+    stats: BTreeMap<(u64, Pubkey), MarketStats>,
+}
+
+impl Default for MevReporter {
+    /// Hourly periods
+    fn default() -> Self {
+        Self::new(3600)
+    }
+}
+
+impl MevReporter {
+    pub fn new(period_secs: u64) -> Self {
+        Self {
+            period_secs: period_secs.max(1),
+            stats: BTreeMap::new(),
+        }
+    }
+
+    fn entry(&mut self, market: Pubkey, timestamp: u64) -> &mut MarketStats {
+        let period_start = timestamp - timestamp % self.period_secs;
+        self.stats.entry((period_start, market)).or_default()
+    }
+
+    /// Count `event`'s assessment on `market`
+    pub fn record_assessment(
+        &mut self,
+        market: Pubkey,
+        event: &TransactionEvent,
+        assessment: &RiskAssessment,
+    ) {
+        let stats = self.entry(market, event.timestamp);
+        match assessment.level {
+            RiskLevel::Low => stats.low += 1,
+            RiskLevel::Medium => stats.medium += 1,
+            RiskLevel::High => stats.high += 1,
+        }
+        if assessment
+            .fired
+            .iter()
+            .any(|fired| !matches!(fired.heuristic, Heuristic::AmountAbove(_)))
+        {
+            stats.attacks_flagged += 1;
+        }
+    }
+
+    /// Count a sandwich `SandwichDetector` confirmed at `timestamp`
+    pub fn record_sandwich(&mut self, sandwich: &Sandwich, timestamp: u64) {
+        let stats = self.entry(sandwich.victim.pool, timestamp);
+        stats.sandwiches += 1;
+        stats.victim_loss += sandwich.victim_loss;
+    }
+
+    /// Count `event` as sent through the anti-MEV layer. Its amount times
+    /// its risk score estimates what it was kept from losing.
+    pub fn record_protected(
+        &mut self,
+        market: Pubkey,
+        event: &TransactionEvent,
+        assessment: &RiskAssessment,
+    ) {
+        let stats = self.entry(market, event.timestamp);
+        stats.protected += 1;
+        stats.value_protected += event.amount as f64 * assessment.score;
+    }
+
+    pub fn report(&self) -> MevReport {
+        MevReport {
+            period_secs: self.period_secs,
+            rows: self
+                .stats
+                .iter()
+                .map(|(&(period_start, market), stats)| ReportRow {
+                    period_start,
+                    market,
+                    stats: stats.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Drop periods that start before `timestamp`
+    pub fn prune(&mut self, timestamp: u64) {
+        self.stats
+            .retain(|&(period_start, _), _| period_start + self.period_secs > timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MevDetector, TransactionType};
+
+    #[test]
+    fn test_report_aggregation_and_export() {
+        let (market, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account = Pubkey::new_unique();
+        let event = |amount, timestamp| TransactionEvent {
+            account,
+            amount,
+            timestamp,
+            tx_type: TransactionType::Swap,
+        };
+        let mut detector = MevDetector::new(10);
+        let mut reporter = MevReporter::new(60);
+
+        detector.record_event(event(5_000_000_000, 100));
+        let frontrun = event(2_000_000_000, 101);
+        let assessment = detector.assess(&frontrun);
+        reporter.record_assessment(market, &frontrun, &assessment);
+        reporter.record_protected(market, &frontrun, &assessment);
+        let quiet = event(10, 200);
+        reporter.record_assessment(other, &quiet, &detector.assess(&quiet));
+
+        let report = reporter.report();
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.rows[0].period_start, 60);
+        assert_eq!(report.rows[0].stats.attacks_flagged, 1);
+        assert_eq!(report.rows[0].stats.value_protected, 1_000_000_000.0);
+        assert_eq!(report.rows[1].period_start, 180);
+        assert_eq!(report.by_market()[&other].low, 1);
+        assert_eq!(report.totals().assessed(), 2);
+
+        let json: MevReport = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json, report);
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with(&format!("60,{},0,0,1,1,0,0,1,", market)));
+
+        reporter.prune(180);
+        assert_eq!(reporter.report().rows.len(), 1);
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::TransactionEvent;
+
+/// Weights and thresholds `MevDetector` scores transactions with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskModel {
+    /// Added when the account traded `sandwich_min_events` times within
+    /// `sandwich_window` seconds of the transaction
+    pub sandwich_weight: f64,
+    pub sandwich_window: u64,
+    pub sandwich_min_events: usize,
+    /// Added when a larger trade of the account came within
+    /// `frontrun_window` seconds before it
+    pub frontrun_weight: f64,
+    pub frontrun_window: u64,
+    /// Added when the transaction is a swap that came within
+    /// `backrun_window` seconds after a larger swap of another account
+    pub backrun_weight: f64,
+    pub backrun_window: u64,
+    /// Added when the transaction withdraws liquidity the account deposited
+    /// within `jit_window` seconds before, with another account's swap in
+    /// between
+    pub jit_weight: f64,
+    pub jit_window: u64,
+    /// Weight of the highest bucket the amount is above
+    pub amount_buckets: Vec<AmountBucket>,
+    /// Scores from which a transaction is `RiskLevel::Medium` and `High`
+    pub medium_threshold: f64,
+    pub high_threshold: f64,
+}
+
+/// Weight of amounts above `above` base units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AmountBucket {
+    pub above: u64,
+    pub weight: f64,
+}
+
+impl Default for RiskModel {
+    /// 0.5 for a sandwich pattern, 0.4 for JIT liquidity, 0.3 for a
+    /// frontrun or backrun and 0.2 above 1 SOL
+    fn default() -> Self {
+        Self {
+            sandwich_weight: 0.5,
+            sandwich_window: 5,
+            sandwich_min_events: 2,
+            frontrun_weight: 0.3,
+            frontrun_window: 2,
+            backrun_weight: 0.3,
+            backrun_window: 1,
+            jit_weight: 0.4,
+            jit_window: 2,
+            amount_buckets: vec![AmountBucket {
+                above: 1_000_000_000,
+                weight: 0.2,
+            }],
+            medium_threshold: 0.3,
+            high_threshold: 0.5,
+        }
+    }
+}
+
+impl RiskModel {
+    /// Model from JSON config; fields left out keep their defaults
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| anyhow!("Invalid risk model: {}", e))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Weight of the highest amount bucket `amount` is above
+    pub(crate) fn amount_bucket(&self, amount: u64) -> Option<AmountBucket> {
+        self.amount_buckets
+            .iter()
+            .filter(|bucket| amount > bucket.above)
+            .max_by_key(|bucket| bucket.above)
+            .copied()
+    }
+
+    pub fn level(&self, score: f64) -> RiskLevel {
+        if score >= self.high_threshold {
+            RiskLevel::High
+        } else if score >= self.medium_threshold {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        }
+    }
+
+    /// Weights set again from labeled history: each heuristic's weight
+    /// becomes the share of the labeled events it fired on that were
+    /// attacks. Heuristics that fired on no labeled event keep theirs.
+    pub fn calibrate(&self, records: &[ScoredEvent]) -> Self {
+        let mut counts: HashMap<Heuristic, (u32, u32)> = HashMap::new();
+        for record in records {
+            let Some(attack) = record.attack else {
+                continue;
+            };
+            for fired in &record.fired {
+                let (fired, attacks) = counts.entry(fired.heuristic).or_default();
+                *fired += 1;
+                *attacks += attack as u32;
+            }
+        }
+        let weight = |heuristic: Heuristic, current: f64| match counts.get(&heuristic) {
+            Some(&(fired, attacks)) => attacks as f64 / fired as f64,
+            None => current,
+        };
+
+        let mut model = self.clone();
+        model.sandwich_weight = weight(Heuristic::Sandwich, self.sandwich_weight);
+        model.frontrun_weight = weight(Heuristic::Frontrun, self.frontrun_weight);
+        model.backrun_weight = weight(Heuristic::Backrun, self.backrun_weight);
+        model.jit_weight = weight(Heuristic::JitLiquidity, self.jit_weight);
+        for bucket in &mut model.amount_buckets {
+            bucket.weight = weight(Heuristic::AmountAbove(bucket.above), bucket.weight);
+        }
+        model
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// Heuristic of a `RiskModel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Heuristic {
+    Sandwich,
+    Frontrun,
+    Backrun,
+    JitLiquidity,
+    /// The amount bucket above this many base units
+    AmountAbove(u64),
+}
+
+/// Heuristic that fired on a transaction, with the weight it added
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FiredHeuristic {
+    pub heuristic: Heuristic,
+    pub weight: f64,
+}
+
+/// Score of a transaction and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskAssessment {
+    /// Sum of the fired weights, at most 1
+    pub score: f64,
+    pub level: RiskLevel,
+    pub fired: Vec<FiredHeuristic>,
+}
+
+/// Scored transaction as a `ScoreStore` keeps it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredEvent {
+    /// Order in the store, to label it by
+    pub id: u64,
+    pub event: TransactionEvent,
+    pub score: f64,
+    pub fired: Vec<FiredHeuristic>,
+    /// Whether the transaction turned out to be attacked, once labeled
+    pub attack: Option<bool>,
+}
+
+/// Persistence of scored transactions, to calibrate models from
+pub trait ScoreStore: Send + Sync {
+    /// Keep `event`'s assessment, returning its id
+    fn append(&self, event: &TransactionEvent, assessment: &RiskAssessment) -> Result<u64>;
+
+    /// Record whether the event `id` was attacked
+    fn label(&self, id: u64, attack: bool) -> Result<()>;
+
+    /// Every scored event, with its latest label
+    fn load(&self) -> Result<Vec<ScoredEvent>>;
+}
+
+/// Line of a `JsonScoreStore`
+#[derive(Serialize, Deserialize)]
+enum ScoreRecord {
+    Scored(ScoredEvent),
+    Label { id: u64, attack: bool },
+}
+
+/// Scored events appended to a file as JSON lines, with labels appended
+/// after them, so the file is never rewritten
+#[derive(Debug)]
+pub struct JsonScoreStore {
+    path: PathBuf,
+    /// Id of the next event
+    next_id: Mutex<u64>,
+}
+
+impl JsonScoreStore {
+    /// Store at `path`, continuing the ids of events already in it
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let store = Self {
+            path: path.into(),
+            next_id: Mutex::new(0),
+        };
+        let next_id = store.load()?.last().map_or(0, |last| last.id + 1);
+        *store.next_id.lock().unwrap() = next_id;
+        Ok(store)
+    }
+
+    fn write(&self, record: &ScoreRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl ScoreStore for JsonScoreStore {
+    fn append(&self, event: &TransactionEvent, assessment: &RiskAssessment) -> Result<u64> {
+        let mut next_id = self.next_id.lock().unwrap();
+        let id = *next_id;
+        self.write(&ScoreRecord::Scored(ScoredEvent {
+            id,
+            event: event.clone(),
+            score: assessment.score,
+            fired: assessment.fired.clone(),
+            attack: None,
+        }))?;
+        *next_id += 1;
+        Ok(id)
+    }
+
+    fn label(&self, id: u64, attack: bool) -> Result<()> {
+        if id >= *self.next_id.lock().unwrap() {
+            return Err(anyhow!("No scored event {}", id));
+        }
+        self.write(&ScoreRecord::Label { id, attack })
+    }
+
+    fn load(&self) -> Result<Vec<ScoredEvent>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut events: Vec<ScoredEvent> = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line)? {
+                ScoreRecord::Scored(event) => events.push(event),
+                ScoreRecord::Label { id, attack } => {
+                    if let Some(event) = events.iter_mut().find(|event| event.id == id) {
+                        event.attack = Some(attack);
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MevDetector, TransactionType};
+    use solana_sdk::pubkey::Pubkey;
+    use std::sync::Arc;
+
+    fn event(account: Pubkey, amount: u64, timestamp: u64) -> TransactionEvent {
+        TransactionEvent {
+            account,
+            amount,
+            timestamp,
+            tx_type: TransactionType::Swap,
+        }
+    }
+
+    #[test]
+    fn test_risk_model_and_calibration() {
+        let model = RiskModel::from_json(r#"{ "frontrun_weight": 0.4 }"#).unwrap();
+        assert_eq!(model.sandwich_weight, 0.5);
+        assert_eq!(
+            RiskModel::from_json(&model.to_json().unwrap()).unwrap(),
+            model
+        );
+
+        let path = std::env::temp_dir().join(format!("untrace-risk-{}.jsonl", std::process::id()));
+        let store = Arc::new(JsonScoreStore::open(&path).unwrap());
+        let mut detector = MevDetector::new(10)
+            .with_model(model)
+            .with_store(store.clone());
+        let account = Pubkey::new_unique();
+        detector.record_event(event(account, 5_000_000_000, 100));
+
+        let (id, assessment) = detector.score_event(&event(account, 2_000, 101)).unwrap();
+        assert_eq!(assessment.level, RiskLevel::Medium);
+        assert_eq!(
+            assessment.fired,
+            vec![FiredHeuristic {
+                heuristic: Heuristic::Frontrun,
+                weight: 0.4,
+            }]
+        );
+        let (other, assessment) = detector
+            .score_event(&event(Pubkey::new_unique(), 3_000_000_000, 200))
+            .unwrap();
+        assert_eq!(assessment.score, 0.2);
+        store.label(id, true).unwrap();
+        store.label(other, false).unwrap();
+
+        // Reopened, the store continues its ids and keeps the labels
+        let store = JsonScoreStore::open(&path).unwrap();
+        let records = store.load().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].attack, Some(true));
+        let calibrated = detector.model().calibrate(&records);
+        assert_eq!(calibrated.frontrun_weight, 1.0);
+        assert_eq!(calibrated.amount_buckets[0].weight, 0.0);
+        assert_eq!(calibrated.sandwich_weight, 0.5);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
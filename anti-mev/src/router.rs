@@ -0,0 +1,601 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_sdk::{
+    hash::Hash,
+    signature::Signature,
+    transaction::{Result as TransactionResult, Transaction},
+};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::submitter::{Simulation, TransactionSubmitter};
+
+/// Errors, in lower case, of endpoints that were sent a transaction they
+/// already have, as when another route got it there first
+const DUPLICATE_ERRORS: &[&str] = &[
+    "already been processed",
+    "already processed",
+    "alreadyprocessed",
+    "duplicate",
+];
+
+/// Endpoint that takes transactions without putting them in the public
+/// mempool
+pub trait PrivateSender: Send + Sync {
+    /// Name to report the route by
+    fn name(&self) -> &str;
+
+    /// Hand over a signed `transaction`, without waiting for it to land
+    fn send(&self, transaction: &Transaction) -> Result<()>;
+}
+
+/// How a `PrivateEndpoint` takes transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointKind {
+    /// Jito block engine `sendTransaction`. Transactions should carry a tip.
+    Jito,
+    /// bloXroute Trader API `submit`, with front running protection
+    BloxrouteProtected,
+    /// Relay answering JSON-RPC `sendTransaction`
+    Relay,
+}
+
+/// Credentials a `PrivateEndpoint` sends with each request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointAuth {
+    None,
+    /// Sent as the header `name`, as `x-jito-auth` or `Authorization`
+    Header {
+        name: String,
+        value: String,
+    },
+    /// Added to the url as the query parameter `name`, as Jito's `uuid`
+    Query {
+        name: String,
+        value: String,
+    },
+}
+
+/// Private endpoint reached over HTTP
+#[derive(Debug, Clone)]
+pub struct PrivateEndpoint {
+    name: String,
+    kind: EndpointKind,
+    url: String,
+    auth: EndpointAuth,
+    http: reqwest::blocking::Client,
+}
+
+impl PrivateEndpoint {
+    pub fn new(kind: EndpointKind, url: impl Into<String>) -> Self {
+        let url = url.into();
+        Self {
+            name: url.clone(),
+            kind,
+            url,
+            auth: EndpointAuth::None,
+            http: reqwest::blocking::Client::new(),
+        }
+    }
+
+    /// Jito block engine at its `/api/v1/transactions` url
+    pub fn jito(url: impl Into<String>) -> Self {
+        Self::new(EndpointKind::Jito, url)
+    }
+
+    /// bloXroute at its `/api/v2/submit` url, authorized by `auth_header`
+    pub fn bloxroute(url: impl Into<String>, auth_header: impl Into<String>) -> Self {
+        Self::new(EndpointKind::BloxrouteProtected, url).with_auth(EndpointAuth::Header {
+            name: "Authorization".to_string(),
+            value: auth_header.into(),
+        })
+    }
+
+    pub fn relay(url: impl Into<String>) -> Self {
+        Self::new(EndpointKind::Relay, url)
+    }
+
+    pub fn with_auth(mut self, auth: EndpointAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Name to report the route by instead of the url
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn kind(&self) -> EndpointKind {
+        self.kind
+    }
+}
+
+impl PrivateSender for PrivateEndpoint {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&self, transaction: &Transaction) -> Result<()> {
+        let encoded = STANDARD.encode(bincode::serialize(transaction)?);
+        let body = match self.kind {
+            EndpointKind::Jito | EndpointKind::Relay => json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sendTransaction",
+                "params": [encoded, { "encoding": "base64" }],
+            }),
+            EndpointKind::BloxrouteProtected => json!({
+                "transaction": { "content": encoded },
+                "frontRunningProtection": true,
+            }),
+        };
+        let mut request = self.http.post(&self.url).json(&body);
+        request = match &self.auth {
+            EndpointAuth::None => request,
+            EndpointAuth::Header { name, value } => request.header(name.as_str(), value.as_str()),
+            EndpointAuth::Query { name, value } => request.query(&[(name, value)]),
+        };
+        let response: Value = request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| anyhow!("{} refused the transaction: {}", self.name, e))?
+            .json()?;
+        match response.get("error") {
+            Some(error) => Err(anyhow!("{} refused the transaction: {}", self.name, error)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// When a `SubmissionRouter` gives up on its private endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouterConfig {
+    /// How long the private endpoints get to land a transaction
+    pub fallback_after: Duration,
+    /// How often the transaction's status is checked meanwhile
+    pub poll_interval: Duration,
+    /// Whether to then send it to the public RPC, which exposes it to the
+    /// public mempool
+    pub public_fallback: bool,
+    /// Whether to send to every endpoint at once, watching for the first
+    /// to land it, instead of to one after the other
+    pub simultaneous: bool,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            fallback_after: Duration::from_secs(20),
+            poll_interval: Duration::from_millis(400),
+            public_fallback: true,
+            simultaneous: false,
+        }
+    }
+}
+
+/// Way a transaction was landed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// Through private endpoints, named by the ones that had accepted it
+    /// when it was seen landed
+    Private(Vec<String>),
+    /// Through the public RPC, after the private endpoints did not land it
+    Public,
+}
+
+/// Transaction a `SubmissionRouter` landed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routed {
+    pub signature: Signature,
+    pub route: Route,
+    /// Time from `route` until the transaction was seen landed
+    pub landed_after: Duration,
+}
+
+/// How one endpoint has answered a `SubmissionRouter`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointMetrics {
+    /// Transactions it took
+    pub accepted: u64,
+    /// Transactions it already had, which count as taken
+    pub duplicates: u64,
+    pub refused: u64,
+    /// Time it took to answer, summed over every send
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl EndpointMetrics {
+    pub fn sends(&self) -> u64 {
+        self.accepted + self.duplicates + self.refused
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        match self.sends() {
+            0 => None,
+            sends => Some(self.total_latency / sends as u32),
+        }
+    }
+
+    fn record(&mut self, answer: &Result<()>, duplicate: bool, latency: Duration) {
+        match answer {
+            Ok(()) if duplicate => self.duplicates += 1,
+            Ok(()) => self.accepted += 1,
+            Err(_) => self.refused += 1,
+        }
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+    }
+}
+
+/// Sends transactions to private endpoints, and to the public RPC if they
+/// have not landed them in time
+pub struct SubmissionRouter {
+    endpoints: Vec<Arc<dyn PrivateSender>>,
+    /// Reads blockhashes and statuses, simulates, and takes fallbacks
+    public: Arc<dyn TransactionSubmitter>,
+    config: RouterConfig,
+    /// By endpoint name, shared with the threads of simultaneous sends
+    metrics: Arc<Mutex<HashMap<String, EndpointMetrics>>>,
+}
+
+impl SubmissionRouter {
+    pub fn new(public: Arc<dyn TransactionSubmitter>) -> Self {
+        Self {
+            endpoints: Vec::new(),
+            public,
+            config: RouterConfig::default(),
+            metrics: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: impl PrivateSender + 'static) -> Self {
+        self.endpoints.push(Arc::new(endpoint));
+        self
+    }
+
+    /// How each endpoint has answered so far, by name
+    pub fn metrics(&self) -> HashMap<String, EndpointMetrics> {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    pub fn with_config(mut self, config: RouterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Send the signed `transaction` to every private endpoint and wait for
+    /// it to land. Falls back to the public RPC once `fallback_after` has
+    /// passed, or at once if no endpoint accepted it. Both routes send the
+    /// same signature, so the transaction cannot land twice.
+    ///
+    /// With `simultaneous`, endpoints are sent to on threads of their own
+    /// and this returns as soon as the transaction lands, without waiting
+    /// for slower endpoints to answer.
+    pub fn route(&self, transaction: &Transaction) -> Result<Routed> {
+        if !transaction.is_signed() {
+            return Err(anyhow!("Transaction is not signed"));
+        }
+        let signature = transaction.signatures[0];
+        let started = Instant::now();
+        let deadline = started + self.config.fallback_after;
+
+        let (answer, answers) = mpsc::channel();
+        for endpoint in &self.endpoints {
+            if self.config.simultaneous {
+                let (endpoint, transaction) = (endpoint.clone(), transaction.clone());
+                let (metrics, answer) = (self.metrics.clone(), answer.clone());
+                thread::spawn(move || {
+                    answer
+                        .send(send_to(&*endpoint, &transaction, &metrics))
+                        .ok();
+                });
+            } else {
+                answer
+                    .send(send_to(&**endpoint, transaction, &self.metrics))
+                    .ok();
+            }
+        }
+        drop(answer);
+
+        let mut pending = self.endpoints.len();
+        let mut accepted = Vec::new();
+        let mut refusals = Vec::new();
+        loop {
+            while let Ok((name, answer)) = answers.try_recv() {
+                pending -= 1;
+                match answer {
+                    Ok(()) => accepted.push(name),
+                    Err(e) => refusals.push(e.to_string()),
+                }
+            }
+            if pending == 0 && accepted.is_empty() {
+                break;
+            }
+            if self.landed(&signature)? {
+                return Ok(Routed {
+                    signature,
+                    route: Route::Private(accepted),
+                    landed_after: started.elapsed(),
+                });
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::sleep(self.config.poll_interval.min(deadline - now));
+        }
+
+        if !self.config.public_fallback {
+            if accepted.is_empty() {
+                return Err(anyhow!(
+                    "No private endpoint took the transaction: {}",
+                    refusals.join("; ")
+                ));
+            }
+            return Err(anyhow!(
+                "Transaction {} did not land privately in time",
+                signature
+            ));
+        }
+        match self.public.submit(transaction) {
+            Ok(signature) => Ok(Routed {
+                signature,
+                route: Route::Public,
+                landed_after: started.elapsed(),
+            }),
+            // A private endpoint may have landed it in the meantime
+            Err(_) if !accepted.is_empty() && self.landed(&signature)? => Ok(Routed {
+                signature,
+                route: Route::Private(accepted),
+                landed_after: started.elapsed(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `signature` landed, an error if it landed and failed
+    fn landed(&self, signature: &Signature) -> Result<bool> {
+        match self.public.signature_status(signature)? {
+            Some(Ok(())) => Ok(true),
+            Some(Err(e)) => Err(anyhow!("Transaction {} failed: {}", signature, e)),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Send `transaction` to `endpoint`, recording its answer in `metrics`.
+/// An endpoint that already has the transaction took it.
+fn send_to(
+    endpoint: &dyn PrivateSender,
+    transaction: &Transaction,
+    metrics: &Mutex<HashMap<String, EndpointMetrics>>,
+) -> (String, Result<()>) {
+    let started = Instant::now();
+    let mut duplicate = false;
+    let answer = endpoint.send(transaction).or_else(|e| {
+        let error = e.to_string().to_lowercase();
+        duplicate = DUPLICATE_ERRORS.iter().any(|known| error.contains(known));
+        if duplicate {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    });
+    let name = endpoint.name().to_string();
+    metrics
+        .lock()
+        .unwrap()
+        .entry(name.clone())
+        .or_default()
+        .record(&answer, duplicate, started.elapsed());
+    (name, answer)
+}
+
+impl TransactionSubmitter for SubmissionRouter {
+    fn latest_blockhash(&self) -> Result<Hash> {
+        self.public.latest_blockhash()
+    }
+
+    fn simulate(&self, transaction: &Transaction) -> Result<Simulation> {
+        self.public.simulate(transaction)
+    }
+
+    fn submit(&self, transaction: &Transaction) -> Result<Signature> {
+        Ok(self.route(transaction)?.signature)
+    }
+
+    fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionResult<()>>> {
+        self.public.signature_status(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+    use std::sync::Mutex;
+
+    /// Chain the mocks land transactions on
+    #[derive(Default)]
+    struct Chain {
+        landed: Mutex<Vec<Signature>>,
+        public_sends: Mutex<usize>,
+    }
+
+    impl TransactionSubmitter for Chain {
+        fn latest_blockhash(&self) -> Result<Hash> {
+            Ok(Hash::new_unique())
+        }
+
+        fn simulate(&self, _transaction: &Transaction) -> Result<Simulation> {
+            Ok(Simulation {
+                units_consumed: None,
+                error: None,
+            })
+        }
+
+        fn submit(&self, transaction: &Transaction) -> Result<Signature> {
+            *self.public_sends.lock().unwrap() += 1;
+            self.landed.lock().unwrap().push(transaction.signatures[0]);
+            Ok(transaction.signatures[0])
+        }
+
+        fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionResult<()>>> {
+            Ok(self
+                .landed
+                .lock()
+                .unwrap()
+                .contains(signature)
+                .then_some(Ok(())))
+        }
+    }
+
+    /// Lands what it is sent on `chain` when `lands`, fails with `error`
+    /// if set, after `delay`
+    struct MockEndpoint {
+        name: &'static str,
+        chain: Arc<Chain>,
+        lands: bool,
+        error: Option<&'static str>,
+        delay: Duration,
+    }
+
+    impl PrivateSender for MockEndpoint {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn send(&self, transaction: &Transaction) -> Result<()> {
+            std::thread::sleep(self.delay);
+            if let Some(error) = self.error {
+                return Err(anyhow!("{}", error));
+            }
+            if self.lands {
+                self.chain
+                    .landed
+                    .lock()
+                    .unwrap()
+                    .push(transaction.signatures[0]);
+            }
+            Ok(())
+        }
+    }
+
+    fn router(chain: &Arc<Chain>, lands: bool, refuses: bool) -> SubmissionRouter {
+        SubmissionRouter::new(chain.clone())
+            .with_endpoint(MockEndpoint {
+                name: "mock",
+                chain: chain.clone(),
+                lands,
+                error: refuses.then_some("refused"),
+                delay: Duration::ZERO,
+            })
+            .with_config(RouterConfig {
+                fallback_after: Duration::from_millis(30),
+                poll_interval: Duration::from_millis(5),
+                public_fallback: true,
+                simultaneous: false,
+            })
+    }
+
+    fn signed_transfer() -> Transaction {
+        let payer = Keypair::new();
+        Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::new_unique(),
+        )
+    }
+
+    #[test]
+    fn test_private_routing_and_fallback() {
+        let transaction = signed_transfer();
+
+        let chain = Arc::new(Chain::default());
+        let routed = router(&chain, true, false).route(&transaction).unwrap();
+        assert_eq!(routed.route, Route::Private(vec!["mock".to_string()]));
+        assert_eq!(*chain.public_sends.lock().unwrap(), 0);
+
+        // Accepted but never landed, it goes public after the deadline
+        let chain = Arc::new(Chain::default());
+        let started = Instant::now();
+        let routed = router(&chain, false, false).route(&transaction).unwrap();
+        assert_eq!(routed.route, Route::Public);
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        // Refused everywhere, it goes public at once, unless that is off
+        let chain = Arc::new(Chain::default());
+        let refusing = router(&chain, false, true);
+        assert_eq!(refusing.route(&transaction).unwrap().route, Route::Public);
+        let private_only = refusing.with_config(RouterConfig {
+            public_fallback: false,
+            ..RouterConfig::default()
+        });
+        assert!(private_only.route(&transaction).is_err());
+    }
+
+    #[test]
+    fn test_simultaneous_submission() {
+        let chain = Arc::new(Chain::default());
+        let endpoint = |name, lands, error, delay| MockEndpoint {
+            name,
+            chain: chain.clone(),
+            lands,
+            error,
+            delay: Duration::from_millis(delay),
+        };
+        let router = SubmissionRouter::new(chain.clone())
+            .with_endpoint(endpoint("slow", false, None, 300))
+            .with_endpoint(endpoint("fast", true, None, 0))
+            .with_endpoint(endpoint(
+                "relay",
+                false,
+                Some("Transaction simulation failed: This transaction has already been processed"),
+                0,
+            ))
+            .with_config(RouterConfig {
+                fallback_after: Duration::from_secs(5),
+                poll_interval: Duration::from_millis(5),
+                public_fallback: true,
+                simultaneous: true,
+            });
+
+        // The first to land it ends the wait, not the slowest endpoint
+        let routed = router.route(&signed_transfer()).unwrap();
+        let Route::Private(accepted) = routed.route else {
+            panic!("Landed publicly");
+        };
+        assert!(accepted.contains(&"fast".to_string()));
+        assert!(!accepted.contains(&"slow".to_string()));
+        assert!(routed.landed_after < Duration::from_millis(300));
+        assert_eq!(*chain.public_sends.lock().unwrap(), 0);
+
+        std::thread::sleep(Duration::from_millis(400));
+        let metrics = router.metrics();
+        assert_eq!(metrics["slow"].accepted, 1);
+        assert!(metrics["slow"].max_latency >= Duration::from_millis(300));
+        assert_eq!(metrics["fast"].sends(), 1);
+        assert!(metrics["fast"].mean_latency().unwrap() < Duration::from_millis(300));
+        // Already having the transaction is not a refusal
+        assert_eq!(
+            (metrics["relay"].duplicates, metrics["relay"].refused),
+            (1, 0)
+        );
+    }
+}
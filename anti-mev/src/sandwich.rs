@@ -0,0 +1,398 @@
+use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use crate::feed::{fetch_transaction, ObservedTransaction};
+
+/// Swaps kept per pool to look for the other two legs of a sandwich
+const POOL_HISTORY: usize = 256;
+
+/// Token accounts holding a constant-product pool's reserves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolVaults {
+    pub pool: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+}
+
+/// Reserves of a pool at one point in a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolState {
+    pub base_reserve: u64,
+    pub quote_reserve: u64,
+}
+
+impl PoolState {
+    /// Quote units per base unit
+    pub fn price(&self) -> f64 {
+        if self.base_reserve == 0 {
+            return f64::INFINITY;
+        }
+        self.quote_reserve as f64 / self.base_reserve as f64
+    }
+
+    /// Base units paying `quote_in` into these reserves would buy, by the
+    /// constant product and before fees
+    pub fn base_out(&self, quote_in: u64) -> u64 {
+        let base = self.base_reserve as u128;
+        let quote = self.quote_reserve as u128 + quote_in as u128;
+        if quote == 0 {
+            return 0;
+        }
+        (base * quote_in as u128 / quote) as u64
+    }
+
+    /// Quote units selling `base_in` into these reserves would return
+    pub fn quote_out(&self, base_in: u64) -> u64 {
+        PoolState {
+            base_reserve: self.quote_reserve,
+            quote_reserve: self.base_reserve,
+        }
+        .base_out(base_in)
+    }
+}
+
+/// Which way a swap moved the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapSide {
+    /// Quote in, base out, raising the price
+    Buy,
+    /// Base in, quote out, lowering the price
+    Sell,
+}
+
+/// One swap against a pool, with its reserves before and after
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSwap {
+    pub signature: String,
+    pub slot: u64,
+    /// Fee payer of the swap
+    pub trader: Pubkey,
+    pub pool: Pubkey,
+    pub before: PoolState,
+    pub after: PoolState,
+}
+
+impl PoolSwap {
+    /// The swap `transaction` made against the pool of `vaults`, read from
+    /// the vaults' balances. `None` if it did not trade with the pool.
+    pub fn from_transaction(
+        transaction: &ObservedTransaction,
+        vaults: &PoolVaults,
+    ) -> Option<Self> {
+        let balance = |vault: &Pubkey| {
+            transaction
+                .token_balances
+                .iter()
+                .find(|balance| balance.account == *vault)
+        };
+        let (base, quote) = (balance(&vaults.base_vault)?, balance(&vaults.quote_vault)?);
+        let swap = Self {
+            signature: transaction.signature.clone(),
+            slot: transaction.slot,
+            trader: *transaction.account_keys.first()?,
+            pool: vaults.pool,
+            before: PoolState {
+                base_reserve: base.pre,
+                quote_reserve: quote.pre,
+            },
+            after: PoolState {
+                base_reserve: base.post,
+                quote_reserve: quote.post,
+            },
+        };
+        swap.side().map(|_| swap)
+    }
+
+    /// `None` for liquidity changes, which move both reserves one way
+    pub fn side(&self) -> Option<SwapSide> {
+        let base_out = self.after.base_reserve < self.before.base_reserve;
+        let quote_in = self.after.quote_reserve > self.before.quote_reserve;
+        let base_in = self.after.base_reserve > self.before.base_reserve;
+        let quote_out = self.after.quote_reserve < self.before.quote_reserve;
+        match (base_out && quote_in, base_in && quote_out) {
+            (true, false) => Some(SwapSide::Buy),
+            (false, true) => Some(SwapSide::Sell),
+            _ => None,
+        }
+    }
+
+    /// Base units the trader put in or took out
+    fn base_amount(&self) -> u64 {
+        self.before.base_reserve.abs_diff(self.after.base_reserve)
+    }
+
+    /// Quote units the trader put in or took out
+    fn quote_amount(&self) -> u64 {
+        self.before.quote_reserve.abs_diff(self.after.quote_reserve)
+    }
+}
+
+/// A victim's swap bracketed by an attacker's buy and sell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandwich {
+    pub front_run: PoolSwap,
+    pub victim: PoolSwap,
+    pub back_run: PoolSwap,
+    /// What the attacker's two legs made, in the unit the front run paid in
+    pub attacker_profit: u64,
+    /// What the victim's swap returned short of the reserves it would have
+    /// met without the front run, in the unit it bought
+    pub victim_loss: u64,
+}
+
+/// Whether `front`, `victim` and `back` form a sandwich: the victim's
+/// swap met the reserves the front run left and left the ones the back
+/// run met, the attacker traded both sides with the victim's direction
+/// first, and came out ahead. A market maker quoting both sides around
+/// other flow does not meet all of it.
+pub fn match_sandwich(
+    front: &PoolSwap,
+    victim: &PoolSwap,
+    back: &PoolSwap,
+    max_slot_gap: u64,
+) -> Option<Sandwich> {
+    let side = victim.side()?;
+    if front.pool != victim.pool
+        || back.pool != victim.pool
+        || front.trader != back.trader
+        || front.trader == victim.trader
+        || front.after != victim.before
+        || victim.after != back.before
+        || front.side() != Some(side)
+        || back.side()? == side
+        || back.slot.saturating_sub(front.slot) > max_slot_gap
+        || front.slot > victim.slot
+        || victim.slot > back.slot
+    {
+        return None;
+    }
+
+    let (attacker_profit, victim_loss) = match side {
+        // Paid quote for base, then sold base back for more quote
+        SwapSide::Buy => (
+            back.quote_amount().checked_sub(front.quote_amount())?,
+            front
+                .before
+                .base_out(victim.quote_amount())
+                .saturating_sub(victim.base_amount()),
+        ),
+        SwapSide::Sell => (
+            back.base_amount().checked_sub(front.base_amount())?,
+            front
+                .before
+                .quote_out(victim.base_amount())
+                .saturating_sub(victim.quote_amount()),
+        ),
+    };
+    if attacker_profit == 0 {
+        return None;
+    }
+    Some(Sandwich {
+        front_run: front.clone(),
+        victim: victim.clone(),
+        back_run: back.clone(),
+        attacker_profit,
+        victim_loss,
+    })
+}
+
+/// Source of the pool reserves around a transaction
+pub trait PoolStateSource: Send + Sync {
+    /// The swap transaction `signature` made against the pool of `vaults`,
+    /// or `None` if it made none
+    fn pool_swap(&self, signature: &str, vaults: &PoolVaults) -> Result<Option<PoolSwap>>;
+
+    /// Reserves the pool of `vaults` holds now
+    fn current_state(&self, vaults: &PoolVaults) -> Result<PoolState> {
+        Err(anyhow!("Current reserves of pool {} are not available", vaults.pool))
+    }
+}
+
+/// Pool reserves read from the vault balances `getTransaction` reports
+pub struct RpcPoolStateSource {
+    client: RpcClient,
+}
+
+impl RpcPoolStateSource {
+    pub fn new(rpc_url: impl Into<String>) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(rpc_url.into(), CommitmentConfig::confirmed()),
+        }
+    }
+}
+
+impl PoolStateSource for RpcPoolStateSource {
+    fn pool_swap(&self, signature: &str, vaults: &PoolVaults) -> Result<Option<PoolSwap>> {
+        let transaction = fetch_transaction(&self.client, signature.to_string())?;
+        Ok(PoolSwap::from_transaction(&transaction, vaults))
+    }
+
+    fn current_state(&self, vaults: &PoolVaults) -> Result<PoolState> {
+        let reserve = |vault: &Pubkey| -> Result<u64> {
+            self.client
+                .get_token_account_balance(vault)
+                .map_err(|e| anyhow!("Failed to read vault {}: {}", vault, e))?
+                .amount
+                .parse()
+                .map_err(|e| anyhow!("Vault {} has an invalid balance: {}", vault, e))
+        };
+        Ok(PoolState {
+            base_reserve: reserve(&vaults.base_vault)?,
+            quote_reserve: reserve(&vaults.quote_vault)?,
+        })
+    }
+}
+
+/// Sandwich detection from the pool state around each swap rather than
+/// from who traded when
+pub struct SandwichDetector {
+    source: Arc<dyn PoolStateSource>,
+    pools: HashMap<Pubkey, PoolVaults>,
+    /// Recent swaps of each pool
+    swaps: HashMap<Pubkey, VecDeque<PoolSwap>>,
+    /// Most slots between the front and back runs
+    max_slot_gap: u64,
+}
+
+impl SandwichDetector {
+    pub fn new(source: Arc<dyn PoolStateSource>) -> Self {
+        Self {
+            source,
+            pools: HashMap::new(),
+            swaps: HashMap::new(),
+            max_slot_gap: 1,
+        }
+    }
+
+    /// Allow `slots` between the front and back runs; 0 keeps to one slot
+    pub fn with_max_slot_gap(mut self, slots: u64) -> Self {
+        self.max_slot_gap = slots;
+        self
+    }
+
+    /// Watch the pool of `vaults`
+    pub fn add_pool(&mut self, vaults: PoolVaults) {
+        self.pools.insert(vaults.pool, vaults);
+    }
+
+    /// Fetch the pool state around swap `signature` on `pool`, record it
+    /// and check whether it completes or is the middle of a sandwich
+    pub fn observe(&mut self, signature: &str, pool: &Pubkey) -> Result<Option<Sandwich>> {
+        let vaults = self
+            .pools
+            .get(pool)
+            .ok_or_else(|| anyhow!("Pool {} is not watched", pool))?;
+        let Some(swap) = self.source.pool_swap(signature, vaults)? else {
+            return Ok(None);
+        };
+        Ok(self.record(swap))
+    }
+
+    /// Record `swap` and check whether it completes or is the middle of a
+    /// sandwich
+    pub fn record(&mut self, swap: PoolSwap) -> Option<Sandwich> {
+        let history = self.swaps.entry(swap.pool).or_default();
+        if history
+            .iter()
+            .any(|other| other.signature == swap.signature)
+        {
+            return None;
+        }
+        if history.len() == POOL_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(swap.clone());
+
+        self.detect(&swap).or_else(|| {
+            // `swap` may be the back run of the swap before it
+            let victim = self.swaps[&swap.pool]
+                .iter()
+                .find(|victim| victim.after == swap.before && victim.signature != swap.signature)?
+                .clone();
+            self.detect(&victim)
+        })
+    }
+
+    /// The sandwich around `victim` among the recorded swaps, if any
+    pub fn detect(&self, victim: &PoolSwap) -> Option<Sandwich> {
+        let history = self.swaps.get(&victim.pool)?;
+        let fronts = history.iter().filter(|front| front.after == victim.before);
+        fronts
+            .flat_map(|front| {
+                history
+                    .iter()
+                    .filter(|back| back.before == victim.after)
+                    .map(move |back| (front, back))
+            })
+            .find_map(|(front, back)| match_sandwich(front, victim, back, self.max_slot_gap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(signature: &str, trader: Pubkey, before: (u64, u64), after: (u64, u64)) -> PoolSwap {
+        PoolSwap {
+            signature: signature.to_string(),
+            slot: 7,
+            trader,
+            pool: Pubkey::default(),
+            before: PoolState {
+                base_reserve: before.0,
+                quote_reserve: before.1,
+            },
+            after: PoolState {
+                base_reserve: after.0,
+                quote_reserve: after.1,
+            },
+        }
+    }
+
+    struct NoSource;
+
+    impl PoolStateSource for NoSource {
+        fn pool_swap(&self, _: &str, _: &PoolVaults) -> Result<Option<PoolSwap>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_sandwich_from_pool_state() {
+        let (attacker, victim) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Constant product of 1_000_000: the attacker buys 91 base for 100
+        // quote, the victim 76 for 100, and the attacker sells 91 for 116
+        let front = swap("front", attacker, (1_000, 1_000), (909, 1_100));
+        let middle = swap("victim", victim, (909, 1_100), (833, 1_200));
+        let back = swap("back", attacker, (833, 1_200), (924, 1_084));
+        assert_eq!(middle.side(), Some(SwapSide::Buy));
+
+        let mut detector = SandwichDetector::new(Arc::new(NoSource));
+        assert!(detector.record(front.clone()).is_none());
+        assert!(detector.record(middle.clone()).is_none());
+        let sandwich = detector.record(back.clone()).unwrap();
+        assert_eq!(sandwich.victim, middle);
+        assert_eq!(sandwich.attacker_profit, 16);
+        // Without the front run the victim's 100 quote would buy 90 base
+        assert_eq!(sandwich.victim_loss, 90 - 76);
+
+        // A market maker selling before the victim and buying after
+        // quotes both sides but does not sandwich it
+        let maker = Pubkey::new_unique();
+        let sell = swap("sell", maker, (1_000, 1_000), (1_100, 910));
+        let buy_victim = swap("buy", victim, (1_100, 910), (989, 1_010));
+        let buy_back = swap("rebuy", maker, (989, 1_010), (900, 1_110));
+        assert!(match_sandwich(&sell, &buy_victim, &buy_back, 1).is_none());
+        // Nor does a bracket whose two legs lost money
+        let loss = swap("back", attacker, (833, 1_200), (924, 1_101));
+        assert!(match_sandwich(&front, &middle, &loss, 1).is_none());
+        // Trades that did not meet each other's reserves are unrelated
+        let apart = swap("back", attacker, (800, 1_250), (891, 1_122));
+        assert!(match_sandwich(&front, &middle, &apart, 1).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use solana_client::{
+    pubsub_client::{PubsubClient, PubsubSlotClientSubscription},
+    rpc_client::RpcClient,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Source of the current Solana slot that time locks are measured against
+pub trait SlotSource: Send + Sync {
+    fn current_slot(&self) -> Result<u64>;
+}
+
+/// Slot read from an RPC node with `getSlot` on every call
+pub struct RpcSlotSource {
+    client: RpcClient,
+}
+
+impl RpcSlotSource {
+    pub fn new(rpc_url: impl Into<String>) -> Self {
+        Self::with_commitment(rpc_url, CommitmentConfig::confirmed())
+    }
+
+    pub fn with_commitment(rpc_url: impl Into<String>, commitment: CommitmentConfig) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(rpc_url.into(), commitment),
+        }
+    }
+}
+
+impl SlotSource for RpcSlotSource {
+    fn current_slot(&self) -> Result<u64> {
+        self.client
+            .get_slot()
+            .map_err(|e| anyhow!("Failed to get slot: {}", e))
+    }
+}
+
+/// Latest slot pushed by a websocket `slotSubscribe`, so checks cost no
+/// request. Fails until the first notification arrives, and keeps the last
+/// slot seen if the subscription drops.
+pub struct WebsocketSlotSource {
+    slot: Arc<AtomicU64>,
+    /// In a mutex only so the source is `Sync`
+    subscription: Mutex<Option<PubsubSlotClientSubscription>>,
+}
+
+impl WebsocketSlotSource {
+    /// Subscribe to slot updates at `ws_url`
+    pub fn connect(ws_url: &str) -> Result<Self> {
+        let (subscription, receiver) = PubsubClient::slot_subscribe(ws_url)
+            .map_err(|e| anyhow!("Failed to subscribe to slots: {}", e))?;
+        let slot = Arc::new(AtomicU64::new(0));
+        let latest = slot.clone();
+        thread::spawn(move || {
+            for update in receiver {
+                latest.fetch_max(update.slot, Ordering::Relaxed);
+            }
+        });
+        Ok(Self {
+            slot,
+            subscription: Mutex::new(Some(subscription)),
+        })
+    }
+}
+
+impl SlotSource for WebsocketSlotSource {
+    fn current_slot(&self) -> Result<u64> {
+        match self.slot.load(Ordering::Relaxed) {
+            0 => Err(anyhow!("No slot received from the websocket yet")),
+            slot => Ok(slot),
+        }
+    }
+}
+
+impl Drop for WebsocketSlotSource {
+    fn drop(&mut self) {
+        if let Some(mut subscription) = self.subscription.get_mut().unwrap().take() {
+            subscription.shutdown().ok();
+        }
+    }
+}
+
+/// Slot set by hand, for tests and simulations
+#[derive(Debug, Default)]
+pub struct ManualSlotSource {
+    slot: AtomicU64,
+}
+
+impl ManualSlotSource {
+    pub fn new(slot: u64) -> Self {
+        Self {
+            slot: AtomicU64::new(slot),
+        }
+    }
+
+    pub fn set_slot(&self, slot: u64) {
+        self.slot.store(slot, Ordering::Relaxed);
+    }
+
+    /// Move the clock `slots` forward
+    pub fn advance(&self, slots: u64) {
+        self.slot.fetch_add(slots, Ordering::Relaxed);
+    }
+}
+
+impl SlotSource for ManualSlotSource {
+    fn current_slot(&self) -> Result<u64> {
+        Ok(self.slot.load(Ordering::Relaxed))
+    }
+}
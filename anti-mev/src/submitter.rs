@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Result};
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    signature::Signature,
+    transaction::{Result as TransactionResult, Transaction, TransactionError},
+};
+
+/// What simulating a transaction showed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// Compute units it used, when the node reports them
+    pub units_consumed: Option<u64>,
+    pub error: Option<TransactionError>,
+}
+
+/// Where protected transactions are sent
+pub trait TransactionSubmitter: Send + Sync {
+    /// Blockhash to build transactions against
+    fn latest_blockhash(&self) -> Result<Hash>;
+
+    /// Run `transaction` without landing it. It need not be signed.
+    fn simulate(&self, transaction: &Transaction) -> Result<Simulation>;
+
+    /// Send a signed `transaction` and wait for it to confirm
+    fn submit(&self, transaction: &Transaction) -> Result<Signature>;
+
+    /// Whether the transaction `signature` landed and how it went, `None`
+    /// while it has not
+    fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionResult<()>>>;
+}
+
+/// Submission to a public RPC node
+pub struct RpcSubmitter {
+    client: RpcClient,
+}
+
+impl RpcSubmitter {
+    pub fn new(rpc_url: impl Into<String>) -> Self {
+        Self::with_commitment(rpc_url, CommitmentConfig::confirmed())
+    }
+
+    pub fn with_commitment(rpc_url: impl Into<String>, commitment: CommitmentConfig) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(rpc_url.into(), commitment),
+        }
+    }
+}
+
+impl TransactionSubmitter for RpcSubmitter {
+    fn latest_blockhash(&self) -> Result<Hash> {
+        self.client
+            .get_latest_blockhash()
+            .map_err(|e| anyhow!("Failed to get blockhash: {}", e))
+    }
+
+    fn simulate(&self, transaction: &Transaction) -> Result<Simulation> {
+        let result = self
+            .client
+            .simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(self.client.commitment()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .map_err(|e| anyhow!("Failed to simulate transaction: {}", e))?;
+        Ok(Simulation {
+            units_consumed: result.value.units_consumed,
+            error: result.value.err,
+        })
+    }
+
+    fn submit(&self, transaction: &Transaction) -> Result<Signature> {
+        self.client
+            .send_and_confirm_transaction(transaction)
+            .map_err(|e| anyhow!("Failed to submit transaction: {}", e))
+    }
+
+    fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionResult<()>>> {
+        self.client
+            .get_signature_status_with_commitment(signature, self.client.commitment())
+            .map_err(|e| anyhow!("Failed to get signature status: {}", e))
+    }
+}
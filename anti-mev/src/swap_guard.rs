@@ -0,0 +1,79 @@
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use untrace_privacy_program::{accounts, instruction};
+
+/// Guard account `open_swap_guard` creates for `owner`'s `token_account`
+pub fn swap_guard_address(program_id: &Pubkey, owner: &Pubkey, token_account: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"swap_guard", owner.as_ref(), token_account.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+/// `swap` between the privacy program's `open_swap_guard` and
+/// `close_swap_guard`, so the transaction reverts unless `token_account`
+/// gains at least `min_out`. `owner` signs the minimum with the
+/// transaction. A sandwich that pushes the output below it fails, and the
+/// attacker's front run is left holding the position.
+pub fn guard_swap(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    token_account: &Pubkey,
+    min_out: u64,
+    swap: Vec<Instruction>,
+) -> Vec<Instruction> {
+    let swap_guard = swap_guard_address(program_id, owner, token_account);
+    let open = Instruction {
+        program_id: *program_id,
+        accounts: accounts::OpenSwapGuard {
+            swap_guard,
+            token_account: *token_account,
+            owner: *owner,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::OpenSwapGuard { min_out }.data(),
+    };
+    let close = Instruction {
+        program_id: *program_id,
+        accounts: accounts::CloseSwapGuard {
+            swap_guard,
+            token_account: *token_account,
+            owner: *owner,
+        }
+        .to_account_metas(None),
+        data: instruction::CloseSwapGuard {}.data(),
+    };
+
+    let mut instructions = vec![open];
+    instructions.extend(swap);
+    instructions.push(close);
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    #[test]
+    fn test_guard_wraps_swap() {
+        let program_id = untrace_privacy_program::ID;
+        let (owner, token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let swap = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let guarded = guard_swap(&program_id, &owner, &token_account, 500, vec![swap.clone()]);
+
+        assert_eq!(guarded.len(), 3);
+        assert_eq!(guarded[1], swap);
+        assert!(guarded[0]
+            .data
+            .starts_with(&instruction::OpenSwapGuard::DISCRIMINATOR));
+        assert_eq!(guarded[0].data[8..], 500u64.to_le_bytes());
+        let guard = swap_guard_address(&program_id, &owner, &token_account);
+        for instruction in [&guarded[0], &guarded[2]] {
+            assert_eq!(instruction.accounts[0].pubkey, guard);
+            assert!(instruction.accounts[2].is_signer);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Result};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use rand::RngCore;
+use sha3::{Digest, Sha3_512};
+use std::fmt;
+
+/// Domain of the challenges proving decryption shares
+const SHARE_PROOF_DOMAIN: &[u8] = b"UNTRACE_DECRYPTION_SHARE";
+
+/// Length of `DecryptionShare::to_bytes`: member index, share and proof
+pub const DECRYPTION_SHARE_LEN: usize = 4 + 32 * 3;
+
+/// Public key of a t-of-n decryption committee. Anything encrypted to it
+/// takes decryption shares of `threshold` members to open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitteeKey {
+    threshold: usize,
+    public_key: RistrettoPoint,
+    /// Public key of each member's share, member `i` at `i - 1`
+    share_keys: Vec<RistrettoPoint>,
+}
+
+impl CommitteeKey {
+    /// Key of a committee from the public keys of its members' shares, as
+    /// published by `share_keys`
+    pub fn new(threshold: usize, share_keys: &[[u8; 32]]) -> Result<Self> {
+        if threshold == 0 || threshold > share_keys.len() {
+            return Err(anyhow!(
+                "Threshold {} is not between 1 and the {} members",
+                threshold,
+                share_keys.len()
+            ));
+        }
+        let share_keys = share_keys
+            .iter()
+            .map(|key| decompress(key, "share key"))
+            .collect::<Result<Vec<_>>>()?;
+        let indices: Vec<u32> = (1..=threshold as u32).collect();
+        let public_key = indices
+            .iter()
+            .map(|&index| lagrange_at_zero(&indices, index) * share_keys[index as usize - 1])
+            .sum();
+        Ok(Self {
+            threshold,
+            public_key,
+            share_keys,
+        })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn members(&self) -> usize {
+        self.share_keys.len()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public_key.compress().to_bytes()
+    }
+
+    pub fn share_keys(&self) -> Vec<[u8; 32]> {
+        self.share_keys
+            .iter()
+            .map(|key| key.compress().to_bytes())
+            .collect()
+    }
+
+    /// Fresh ephemeral key and the secret it shares with the committee
+    pub(crate) fn encapsulate(&self) -> ([u8; 32], [u8; 32]) {
+        let ephemeral = random_scalar();
+        let ephemeral_key = RistrettoPoint::mul_base(&ephemeral).compress().to_bytes();
+        let shared = (ephemeral * self.public_key).compress().to_bytes();
+        (ephemeral_key, shared)
+    }
+
+    /// Whether `share` is member `share.index`'s share for
+    /// `ephemeral_key`, by its proof
+    pub fn verify_share(&self, ephemeral_key: &[u8; 32], share: &DecryptionShare) -> bool {
+        let Some(share_key) = (share.index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.share_keys.get(i))
+        else {
+            return false;
+        };
+        let Ok(ephemeral) = decompress(ephemeral_key, "ephemeral key") else {
+            return false;
+        };
+        let commitment = RistrettoPoint::mul_base(&share.response) - share.challenge * share_key;
+        let blinded = share.response * ephemeral - share.challenge * share.share;
+        share.challenge
+            == proof_challenge(share_key, &ephemeral, &share.share, &commitment, &blinded)
+    }
+
+    /// Secret `ephemeral_key` shares with the committee, from verified
+    /// shares of at least `threshold` distinct members
+    pub(crate) fn combine(
+        &self,
+        ephemeral_key: &[u8; 32],
+        shares: &[DecryptionShare],
+    ) -> Result<[u8; 32]> {
+        let mut shares: Vec<&DecryptionShare> = shares
+            .iter()
+            .filter(|share| self.verify_share(ephemeral_key, share))
+            .collect();
+        shares.sort_by_key(|share| share.index);
+        shares.dedup_by_key(|share| share.index);
+        if shares.len() < self.threshold {
+            return Err(anyhow!(
+                "{} valid decryption shares, {} needed",
+                shares.len(),
+                self.threshold
+            ));
+        }
+
+        let shares = &shares[..self.threshold];
+        let indices: Vec<u32> = shares.iter().map(|share| share.index).collect();
+        let shared: RistrettoPoint = shares
+            .iter()
+            .map(|share| lagrange_at_zero(&indices, share.index) * share.share)
+            .sum();
+        Ok(shared.compress().to_bytes())
+    }
+}
+
+/// A committee member's share of the committee's secret key
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    /// Member index, from 1
+    pub index: u32,
+    secret: Scalar,
+}
+
+impl fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyShare {
+    /// Member index followed by the secret scalar, for the member to store
+    pub fn to_bytes(&self) -> [u8; 36] {
+        let mut bytes = [0u8; 36];
+        bytes[..4].copy_from_slice(&self.index.to_le_bytes());
+        bytes[4..].copy_from_slice(self.secret.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; 36]) -> Result<Self> {
+        let index = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        let secret = Option::from(Scalar::from_canonical_bytes(bytes[4..].try_into().unwrap()))
+            .ok_or_else(|| anyhow!("Key share is not a canonical scalar"))?;
+        if index == 0 {
+            return Err(anyhow!("Key share index 0 is the committee secret"));
+        }
+        Ok(Self { index, secret })
+    }
+
+    /// Public key of this share, as `CommitteeKey` lists it
+    pub fn share_key(&self) -> [u8; 32] {
+        RistrettoPoint::mul_base(&self.secret).compress().to_bytes()
+    }
+
+    /// This member's share of the secret `ephemeral_key` shares with the
+    /// committee, with a proof that it used its key share
+    pub fn decryption_share(&self, ephemeral_key: &[u8; 32]) -> Result<DecryptionShare> {
+        let ephemeral = decompress(ephemeral_key, "ephemeral key")?;
+        let share = self.secret * ephemeral;
+
+        // Chaum-Pedersen proof that share and share key have one discrete log
+        let nonce = random_scalar();
+        let share_key = RistrettoPoint::mul_base(&self.secret);
+        let challenge = proof_challenge(
+            &share_key,
+            &ephemeral,
+            &share,
+            &RistrettoPoint::mul_base(&nonce),
+            &(nonce * ephemeral),
+        );
+        Ok(DecryptionShare {
+            index: self.index,
+            share,
+            challenge,
+            response: nonce + challenge * self.secret,
+        })
+    }
+}
+
+/// One member's contribution to opening an order, which anyone can check
+/// against the committee key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptionShare {
+    /// Index of the member that made it
+    pub index: u32,
+    share: RistrettoPoint,
+    challenge: Scalar,
+    response: Scalar,
+}
+
+impl DecryptionShare {
+    pub fn to_bytes(&self) -> [u8; DECRYPTION_SHARE_LEN] {
+        let mut bytes = [0u8; DECRYPTION_SHARE_LEN];
+        bytes[..4].copy_from_slice(&self.index.to_le_bytes());
+        bytes[4..36].copy_from_slice(self.share.compress().as_bytes());
+        bytes[36..68].copy_from_slice(self.challenge.as_bytes());
+        bytes[68..].copy_from_slice(self.response.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; DECRYPTION_SHARE_LEN]) -> Result<Self> {
+        let scalar = |bytes: &[u8]| -> Result<Scalar> {
+            Option::from(Scalar::from_canonical_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| anyhow!("Decryption share proof is not canonical"))
+        };
+        Ok(Self {
+            index: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            share: decompress(bytes[4..36].try_into().unwrap(), "decryption share")?,
+            challenge: scalar(&bytes[36..68])?,
+            response: scalar(&bytes[68..])?,
+        })
+    }
+}
+
+/// Deal a fresh committee key as Shamir shares, any `threshold` of
+/// `members` of which can decrypt. The dealer sees the whole secret and
+/// must forget it; a distributed key generation can hand out the same
+/// shares without one.
+pub fn deal_committee(threshold: usize, members: usize) -> Result<(CommitteeKey, Vec<KeyShare>)> {
+    if threshold == 0 || threshold > members || members > u32::MAX as usize {
+        return Err(anyhow!(
+            "Threshold {} is not between 1 and the {} members",
+            threshold,
+            members
+        ));
+    }
+
+    let coefficients: Vec<Scalar> = (0..threshold).map(|_| random_scalar()).collect();
+    let shares: Vec<KeyShare> = (1..=members as u32)
+        .map(|index| {
+            let x = Scalar::from(index);
+            let secret = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |sum, coefficient| sum * x + coefficient);
+            KeyShare { index, secret }
+        })
+        .collect();
+    let share_keys: Vec<[u8; 32]> = shares.iter().map(KeyShare::share_key).collect();
+    Ok((CommitteeKey::new(threshold, &share_keys)?, shares))
+}
+
+/// Lagrange coefficient at zero of member `index` among `indices`
+fn lagrange_at_zero(indices: &[u32], index: u32) -> Scalar {
+    let x = Scalar::from(index);
+    let (numerator, denominator) = indices.iter().filter(|&&other| other != index).fold(
+        (Scalar::ONE, Scalar::ONE),
+        |(num, den), &other| {
+            let other = Scalar::from(other);
+            (num * other, den * (other - x))
+        },
+    );
+    numerator * denominator.invert()
+}
+
+fn proof_challenge(
+    share_key: &RistrettoPoint,
+    ephemeral: &RistrettoPoint,
+    share: &RistrettoPoint,
+    commitment: &RistrettoPoint,
+    blinded: &RistrettoPoint,
+) -> Scalar {
+    let mut hasher = Sha3_512::new();
+    hasher.update(SHARE_PROOF_DOMAIN);
+    hasher.update(RISTRETTO_BASEPOINT_POINT.compress().as_bytes());
+    for point in [share_key, ephemeral, share, commitment, blinded] {
+        hasher.update(point.compress().as_bytes());
+    }
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&digest)
+}
+
+pub(crate) fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+pub(crate) fn decompress(bytes: &[u8; 32], what: &str) -> Result<RistrettoPoint> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or_else(|| anyhow!("Invalid {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_decryption() {
+        let (committee, shares) = deal_committee(2, 3).unwrap();
+        assert_eq!(
+            CommitteeKey::new(2, &committee.share_keys()).unwrap(),
+            committee
+        );
+        let stored = KeyShare::from_bytes(&shares[2].to_bytes()).unwrap();
+        assert_eq!(stored, shares[2]);
+
+        let (ephemeral_key, shared) = committee.encapsulate();
+        let decryption: Vec<DecryptionShare> = shares
+            .iter()
+            .map(|share| share.decryption_share(&ephemeral_key).unwrap())
+            .collect();
+        assert!(decryption
+            .iter()
+            .all(|share| committee.verify_share(&ephemeral_key, share)));
+        // Any two members open it, one does not
+        for pair in [[0, 1], [0, 2], [1, 2]] {
+            let pair = [decryption[pair[0]].clone(), decryption[pair[1]].clone()];
+            assert_eq!(committee.combine(&ephemeral_key, &pair).unwrap(), shared);
+        }
+        let twice = [decryption[0].clone(), decryption[0].clone()];
+        assert!(committee.combine(&ephemeral_key, &twice).is_err());
+
+        // A share claiming another member's index fails its proof
+        let mut forged = DecryptionShare::from_bytes(&decryption[1].to_bytes()).unwrap();
+        forged.index = 3;
+        assert!(!committee.verify_share(&ephemeral_key, &forged));
+        let forged = [decryption[0].clone(), forged];
+        assert!(committee.combine(&ephemeral_key, &forged).is_err());
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::slot_source::SlotSource;
 
 /// Time-lock manager for delayed transaction execution
 pub struct TimeLockManager {
     min_lock_duration: u64,
     locked_transactions: HashMap<u64, LockedTransaction>,
+    slot_source: Arc<dyn SlotSource>,
 }
 
 #[derive(Debug, Clone)]
@@ -14,26 +17,38 @@ pub struct LockedTransaction {
 }
 
 impl TimeLockManager {
-    pub fn new(min_lock_duration: u64) -> Self {
+    /// Manager locking transactions for at least `min_lock_duration` slots
+    /// of `slot_source`
+    pub fn new(min_lock_duration: u64, slot_source: Arc<dyn SlotSource>) -> Self {
         Self {
             min_lock_duration,
             locked_transactions: HashMap::new(),
+            slot_source,
         }
     }
 
+    /// Current slot of the slot source
+    pub fn current_slot(&self) -> Result<u64> {
+        self.slot_source.current_slot()
+    }
+
     /// Calculate when a transaction should unlock
     pub fn calculate_unlock_slot(&self) -> Result<u64> {
-        // In production, get current slot from Solana RPC
-        let current_slot = 1000u64;
-        Ok(current_slot + self.min_lock_duration)
+        Ok(self.current_slot()? + self.min_lock_duration)
     }
 
-    /// Lock a transaction until specified slot
+    /// Lock a transaction until specified slot, which must be at least the
+    /// minimum lock duration away
     pub fn lock_transaction(&mut self, tx_id: u64, unlock_slot: u64) -> Result<()> {
-        let current_slot = 1000u64;
+        let current_slot = self.current_slot()?;
 
-        if unlock_slot <= current_slot {
-            return Err(anyhow!("Unlock slot must be in the future"));
+        if unlock_slot < current_slot + self.min_lock_duration {
+            return Err(anyhow!(
+                "Unlock slot {} is less than {} slots after the current slot {}",
+                unlock_slot,
+                self.min_lock_duration,
+                current_slot
+            ));
         }
 
         self.locked_transactions.insert(
@@ -47,10 +62,18 @@ impl TimeLockManager {
         Ok(())
     }
 
-    /// Check if a transaction is unlocked
-    pub fn is_unlocked(&self, slot: u64) -> bool {
-        // In production, compare with actual Solana slot
-        true
+    /// Check if a lock until `unlock_slot` has expired
+    pub fn is_unlocked(&self, unlock_slot: u64) -> Result<bool> {
+        Ok(self.current_slot()? >= unlock_slot)
+    }
+
+    /// Check if the locked transaction `tx_id` may execute. Unknown
+    /// transactions are not.
+    pub fn is_transaction_unlocked(&self, tx_id: u64) -> Result<bool> {
+        match self.get_unlock_slot(tx_id) {
+            Some(unlock_slot) => self.is_unlocked(unlock_slot),
+            None => Ok(false),
+        }
     }
 
     /// Get unlock slot for transaction
@@ -75,24 +98,40 @@ impl TimeLockManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::slot_source::ManualSlotSource;
+
+    fn manager(slot: u64) -> (TimeLockManager, Arc<ManualSlotSource>) {
+        let clock = Arc::new(ManualSlotSource::new(slot));
+        (TimeLockManager::new(10, clock.clone()), clock)
+    }
 
     #[test]
     fn test_time_lock() {
-        let mut manager = TimeLockManager::new(10);
+        let (mut manager, clock) = manager(1000);
 
         let unlock_slot = manager.calculate_unlock_slot().unwrap();
-        assert!(unlock_slot > 1000);
+        assert_eq!(unlock_slot, 1010);
 
         manager.lock_transaction(1, unlock_slot).unwrap();
         assert_eq!(manager.locked_count(), 1);
 
         let retrieved_slot = manager.get_unlock_slot(1).unwrap();
         assert_eq!(retrieved_slot, unlock_slot);
+
+        assert!(!manager.is_transaction_unlocked(1).unwrap());
+        clock.advance(9);
+        assert!(!manager.is_unlocked(unlock_slot).unwrap());
+        clock.advance(1);
+        assert!(manager.is_transaction_unlocked(1).unwrap());
+        assert!(!manager.is_transaction_unlocked(2).unwrap());
+
+        // Shorter than the minimum lock
+        assert!(manager.lock_transaction(2, 1015).is_err());
     }
 
     #[test]
     fn test_cleanup() {
-        let mut manager = TimeLockManager::new(10);
+        let (mut manager, _) = manager(1000);
 
         manager.lock_transaction(1, 1100).unwrap();
         manager.lock_transaction(2, 1200).unwrap();
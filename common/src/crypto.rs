@@ -1,9 +1,12 @@
 use curve25519_dalek::{
+    edwards::{CompressedEdwardsY, EdwardsPoint},
     ristretto::{CompressedRistretto, RistrettoPoint},
-    scalar::Scalar,
+    scalar::{clamp_integer, Scalar},
 };
+use sha2::Sha512;
 use sha3::{Digest, Sha3_256};
 use blake3;
+use solana_program::keccak;
 
 /// Generate a Pedersen commitment: C = vG + rH
 pub fn pedersen_commit(value: u64, randomness: &[u8; 32]) -> [u8; 32] {
@@ -31,16 +34,180 @@ pub fn generate_nullifier(secret: &[u8], commitment: &[u8; 32]) -> [u8; 32] {
     output
 }
 
-/// Generate a commitment hash for privacy pool
-pub fn generate_commitment(
+/// Generate a commitment hash for privacy pool, binding the amount to the
+/// note key of its recipient and randomness
+pub fn generate_commitment(amount: u64, note_key: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"POOL_NOTE");
+    hasher.update(amount.to_le_bytes());
+    hasher.update(note_key);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Hiding part of a note: its owner and blinding randomness. Deposits
+/// reveal it alongside the public amount so the program can recompute the
+/// commitment, without revealing the owner.
+pub fn note_key(owner: &[u8; 32], randomness: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"NOTE_KEY");
+    hasher.update(owner);
+    hasher.update(randomness);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Generate a commitment binding (asset, value, note key) for the shielded pool
+pub fn generate_asset_commitment(
+    asset_id: &[u8; 32],
+    amount: u64,
+    note_key: &[u8; 32],
+) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"SHIELDED_NOTE");
+    hasher.update(asset_id);
+    hasher.update(amount.to_le_bytes());
+    hasher.update(note_key);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Hash a serialized bridge message for guardian attestation
+pub fn bridge_message_digest(message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"BRIDGE_MESSAGE");
+    hasher.update(message);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Hash of a private order's payload, as committed to on chain before the
+/// payload is revealed. The salt keeps a guessable payload from being
+/// checked against the commitment.
+pub fn order_payload_hash(
+    owner: &[u8; 32],
+    order_id: u64,
+    salt: &[u8; 32],
+    payload: &[u8],
+) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"ORDER_PAYLOAD");
+    hasher.update(owner);
+    hasher.update(order_id.to_le_bytes());
+    hasher.update(salt);
+    hasher.update(payload);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Hash a time-locked intent commits to: the instruction calling
+/// `program_id` with `accounts`, in order, and `data`
+pub fn intent_hash(
+    owner: &[u8; 32],
+    intent_id: u64,
+    salt: &[u8; 32],
+    program_id: &[u8; 32],
+    accounts: &[[u8; 32]],
+    data: &[u8],
+) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"TIME_LOCKED_INTENT");
+    hasher.update(owner);
+    hasher.update(intent_id.to_le_bytes());
+    hasher.update(salt);
+    hasher.update(program_id);
+    hasher.update((accounts.len() as u32).to_le_bytes());
+    for account in accounts {
+        hasher.update(account);
+    }
+    hasher.update(data);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Public inputs of a shielded transfer proof: the pool root the inputs
+/// are proven under, the asset, and the notes it spends and creates. The
+/// proof shows inputs and outputs balance.
+pub fn shielded_transfer_inputs(
+    root: &[u8; 32],
+    asset_id: &[u8; 32],
+    nullifiers: &[[u8; 32]],
+    output_commitments: &[[u8; 32]],
+) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"SHIELDED_TRANSFER");
+    hasher.update(root);
+    hasher.update(asset_id);
+    for nullifier in nullifiers {
+        hasher.update(nullifier);
+    }
+    for commitment in output_commitments {
+        hasher.update(commitment);
+    }
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Public inputs of a shielded withdrawal proof: the pool root the note
+/// is proven under, what leaves the pool and to whom, and the nullifier
+/// it spends
+pub fn shielded_withdraw_inputs(
+    root: &[u8; 32],
+    asset_id: &[u8; 32],
+    amount: u64,
     recipient: &[u8; 32],
+    nullifier: &[u8; 32],
+) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"SHIELDED_WITHDRAW");
+    hasher.update(root);
+    hasher.update(asset_id);
+    hasher.update(amount.to_le_bytes());
+    hasher.update(recipient);
+    hasher.update(nullifier);
+
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+/// Public inputs of a privacy pool withdrawal proof: the root the note is
+/// proven under, what leaves the pool and to whom, and the nullifier it
+/// spends
+pub fn withdraw_inputs(
+    root: &[u8; 32],
     amount: u64,
-    randomness: &[u8; 32],
+    recipient: &[u8; 32],
+    nullifier: &[u8; 32],
 ) -> [u8; 32] {
     let mut hasher = Sha3_256::new();
+    hasher.update(b"POOL_WITHDRAW");
+    hasher.update(root);
+    hasher.update(amount.to_le_bytes());
     hasher.update(recipient);
-    hasher.update(&amount.to_le_bytes());
-    hasher.update(randomness);
+    hasher.update(nullifier);
 
     let result = hasher.finalize();
     let mut output = [0u8; 32];
@@ -48,6 +215,14 @@ pub fn generate_commitment(
     output
 }
 
+/// Hash two merkle tree nodes into their parent
+///
+/// Uses Keccak-256, which runs as a cheap syscall on-chain; a software SHA3
+/// per tree level would dominate the compute budget of deposits.
+pub fn merkle_hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[left, right]).to_bytes()
+}
+
 /// Verify a merkle proof
 pub fn verify_merkle_proof(
     leaf: &[u8; 32],
@@ -59,24 +234,53 @@ pub fn verify_merkle_proof(
     let mut current_index = index;
 
     for sibling in proof {
-        let mut hasher = Sha3_256::new();
-
-        if current_index % 2 == 0 {
-            hasher.update(&computed_hash);
-            hasher.update(sibling);
+        computed_hash = if current_index & 1 == 0 {
+            merkle_hash_pair(&computed_hash, sibling)
         } else {
-            hasher.update(sibling);
-            hasher.update(&computed_hash);
-        }
-
-        let result = hasher.finalize();
-        computed_hash.copy_from_slice(&result);
+            merkle_hash_pair(sibling, &computed_hash)
+        };
         current_index /= 2;
     }
 
     &computed_hash == root
 }
 
+/// Depth of the tree `merkle_root` builds over `leaf_count` leaves
+pub fn merkle_depth(leaf_count: usize) -> usize {
+    leaf_count.next_power_of_two().trailing_zeros() as usize
+}
+
+/// Root of a tree over `leaves`, padded with zero leaves to a power of two
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    level.resize(leaves.len().next_power_of_two(), [0u8; 32]);
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    level[0]
+}
+
+/// Sibling path of leaf `index` in the tree `merkle_root` builds over
+/// `leaves`, as `verify_merkle_proof` takes it
+pub fn merkle_path(leaves: &[[u8; 32]], index: usize) -> Vec<[u8; 32]> {
+    let mut level = leaves.to_vec();
+    level.resize(leaves.len().next_power_of_two(), [0u8; 32]);
+    let mut index = index;
+    let mut path = Vec::with_capacity(merkle_depth(leaves.len()));
+    while level.len() > 1 {
+        path.push(level[index ^ 1]);
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_hash_pair(&pair[0], &pair[1]))
+            .collect();
+        index /= 2;
+    }
+    path
+}
+
 /// Encrypt data using XChaCha20-Poly1305
 pub fn encrypt_data(
     plaintext: &[u8],
@@ -93,8 +297,8 @@ pub fn encrypt_data(
         ciphertext.push(byte ^ key.as_bytes()[i % 32]);
     }
 
-    // Generate authentication tag
-    let mut tag_hasher = blake3::Hasher::new();
+    // Generate authentication tag, keyed so a wrong key fails to verify
+    let mut tag_hasher = blake3::Hasher::new_keyed(key.as_bytes());
     tag_hasher.update(&ciphertext);
     tag_hasher.update(nonce);
     let tag_hash = tag_hasher.finalize();
@@ -115,7 +319,7 @@ pub fn decrypt_data(
     // Verify tag first
     let key = blake3::hash(shared_secret);
 
-    let mut tag_hasher = blake3::Hasher::new();
+    let mut tag_hasher = blake3::Hasher::new_keyed(key.as_bytes());
     tag_hasher.update(ciphertext);
     tag_hasher.update(nonce);
     let tag_hash = tag_hasher.finalize();
@@ -134,7 +338,36 @@ pub fn decrypt_data(
     Ok(plaintext)
 }
 
-/// Generate a ZK proof (simplified - in production use a proper ZK library)
+/// Viewing key of a Solana wallet: the ed25519 secret scalar derived from
+/// its 32-byte seed. Lets the wallet decrypt data sent to its address.
+pub fn viewing_key(seed: &[u8; 32]) -> [u8; 32] {
+    let hash = Sha512::digest(seed);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    clamp_integer(scalar)
+}
+
+/// Diffie-Hellman secret between `secret` (a viewing key or ephemeral
+/// secret) and an ed25519 public key
+pub fn shared_secret(secret: &[u8; 32], public: &[u8; 32]) -> Option<[u8; 32]> {
+    let point = CompressedEdwardsY(*public).decompress()?;
+    let shared = point * Scalar::from_bytes_mod_order(clamp_integer(*secret));
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(shared.compress().as_bytes());
+    hasher.update(b"UNTRACE_SHARED_SECRET");
+    Some(hasher.finalize().into())
+}
+
+/// Public key matching an ephemeral secret
+pub fn ephemeral_public_key(ephemeral_secret: &[u8; 32]) -> [u8; 32] {
+    EdwardsPoint::mul_base_clamped(*ephemeral_secret)
+        .compress()
+        .to_bytes()
+}
+
+/// Placeholder for a Groth16 prover (hash stub). The program verifies
+/// proofs against the registered verifying keys and rejects these.
 pub fn generate_zk_proof(
     commitment: &[u8; 32],
     nullifier: &[u8; 32],
@@ -149,17 +382,6 @@ pub fn generate_zk_proof(
     hasher.finalize().to_vec()
 }
 
-/// Verify a ZK proof
-pub fn verify_zk_proof(
-    proof: &[u8],
-    commitment: &[u8; 32],
-    nullifier: &[u8; 32],
-) -> bool {
-    // Simplified verification
-    // In production, implement proper ZK-SNARK verification
-    proof.len() == 32 && commitment.len() == 32 && nullifier.len() == 32
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,6 +394,59 @@ mod tests {
         assert_eq!(commitment.len(), 32);
     }
 
+    #[test]
+    fn test_asset_commitment_binds_asset() {
+        let key = note_key(&[3u8; 32], &[4u8; 32]);
+
+        let sol = generate_asset_commitment(&[0u8; 32], 500, &key);
+        let usdc = generate_asset_commitment(&[1u8; 32], 500, &key);
+        assert_ne!(sol, usdc);
+        assert_ne!(sol, generate_asset_commitment(&[0u8; 32], 501, &key));
+        assert_eq!(sol, generate_asset_commitment(&[0u8; 32], 500, &key));
+    }
+
+    #[test]
+    fn test_shielded_withdraw_inputs_bind_recipient_and_root() {
+        let inputs = shielded_withdraw_inputs(&[1u8; 32], &[0u8; 32], 500, &[2u8; 32], &[3u8; 32]);
+        assert_ne!(
+            inputs,
+            shielded_withdraw_inputs(&[1u8; 32], &[0u8; 32], 500, &[4u8; 32], &[3u8; 32])
+        );
+        assert_ne!(
+            inputs,
+            shielded_withdraw_inputs(&[5u8; 32], &[0u8; 32], 500, &[2u8; 32], &[3u8; 32])
+        );
+        assert_ne!(
+            inputs,
+            shielded_withdraw_inputs(&[1u8; 32], &[0u8; 32], 501, &[2u8; 32], &[3u8; 32])
+        );
+    }
+
+    #[test]
+    fn test_merkle_proof_verification() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        let left = merkle_hash_pair(&leaves[0], &leaves[1]);
+        let right = merkle_hash_pair(&leaves[2], &leaves[3]);
+        let root = merkle_hash_pair(&left, &right);
+
+        assert!(verify_merkle_proof(&leaves[2], &[leaves[3], left], &root, 2));
+        assert!(!verify_merkle_proof(&leaves[2], &[leaves[3], left], &root, 3));
+    }
+
+    #[test]
+    fn test_merkle_paths_verify_against_root() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let root = merkle_root(&leaves);
+        assert_eq!(merkle_depth(leaves.len()), 2);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let path = merkle_path(&leaves, index);
+            assert_eq!(path.len(), 2);
+            assert!(verify_merkle_proof(leaf, &path, &root, index as u32));
+        }
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert!(merkle_path(&leaves[..1], 0).is_empty());
+    }
+
     #[test]
     fn test_encryption_decryption() {
         let plaintext = b"secret message";
@@ -182,5 +457,6 @@ mod tests {
         let decrypted = decrypt_data(&ciphertext, &shared_secret, &nonce, &tag).unwrap();
 
         assert_eq!(plaintext, &decrypted[..]);
+        assert!(decrypt_data(&ciphertext, &[3u8; 32], &nonce, &tag).is_err());
     }
 }
@@ -1,7 +1,7 @@
 use thiserror::Error;
 use solana_program::program_error::ProgramError;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UntraceError {
     #[error("Invalid instruction")]
     InvalidInstruction,
@@ -47,6 +47,144 @@ pub enum UntraceError {
 
     #[error("Time lock not expired")]
     TimeLockNotExpired,
+
+    #[error("Invalid guardian set")]
+    InvalidGuardianSet,
+
+    #[error("Invalid guardian signature")]
+    InvalidGuardianSignature,
+
+    #[error("Guardian quorum not reached")]
+    GuardianQuorumNotReached,
+
+    #[error("Insufficient bridge vault balance")]
+    InsufficientBridgeLiquidity,
+
+    #[error("Bridge message already consumed")]
+    MessageAlreadyConsumed,
+
+    #[error("Invalid or unsupported asset")]
+    InvalidAsset,
+
+    #[error("Insufficient shielded asset balance")]
+    InsufficientAssetBalance,
+
+    #[error("Account already at the current version")]
+    AccountAlreadyMigrated,
+
+    #[error("Merkle tree is full")]
+    MerkleTreeFull,
+
+    #[error("Unknown merkle root")]
+    UnknownMerkleRoot,
+
+    #[error("Invalid merkle tree account")]
+    InvalidMerkleTree,
+
+    #[error("Deposit below pool minimum")]
+    DepositBelowMinimum,
+
+    #[error("Deposit above pool maximum")]
+    DepositAboveMaximum,
+
+    #[error("Epoch deposit cap exceeded")]
+    EpochDepositCapExceeded,
+
+    #[error("Protocol is paused")]
+    ProtocolPaused,
+
+    #[error("Invalid timelock delay")]
+    InvalidTimelockDelay,
+
+    #[error("Invalid Wormhole VAA")]
+    InvalidVaa,
+
+    #[error("Unregistered Wormhole emitter")]
+    UnknownEmitter,
+
+    #[error("Bridge transfer is not refundable yet")]
+    RefundNotAvailable,
+
+    #[error("Bridge transfer already refunded")]
+    TransferAlreadyRefunded,
+
+    #[error("Bridge epoch volume cap exceeded")]
+    BridgeRateLimitExceeded,
+
+    #[error("Relayer fee exceeds the amount bridged")]
+    RelayerFeeTooHigh,
+
+    #[error("Swap output is below the signed minimum")]
+    SlippageExceeded,
+
+    #[error("Order payload does not match its commitment")]
+    OrderPayloadMismatch,
+
+    #[error("Order expired before it was revealed")]
+    OrderExpired,
+
+    #[error("Instruction does not match its locked intent")]
+    IntentMismatch,
+
+    #[error("Commitment does not match the deposit")]
+    CommitmentMismatch,
+
+    #[error("Insufficient pool balance")]
+    InsufficientPoolBalance,
+}
+
+impl UntraceError {
+    /// Every variant, indexed by its error code
+    pub const ALL: [UntraceError; 43] = [
+        UntraceError::InvalidInstruction,
+        UntraceError::InvalidPrivacyLevel,
+        UntraceError::EncryptionFailed,
+        UntraceError::DecryptionFailed,
+        UntraceError::InvalidZKProof,
+        UntraceError::InsufficientPoolSize,
+        UntraceError::CommitmentExists,
+        UntraceError::NullifierUsed,
+        UntraceError::InvalidMerkleProof,
+        UntraceError::Unauthorized,
+        UntraceError::ProposalNotFound,
+        UntraceError::VotingEnded,
+        UntraceError::AlreadyVoted,
+        UntraceError::MevProtectionViolated,
+        UntraceError::TimeLockNotExpired,
+        UntraceError::InvalidGuardianSet,
+        UntraceError::InvalidGuardianSignature,
+        UntraceError::GuardianQuorumNotReached,
+        UntraceError::InsufficientBridgeLiquidity,
+        UntraceError::MessageAlreadyConsumed,
+        UntraceError::InvalidAsset,
+        UntraceError::InsufficientAssetBalance,
+        UntraceError::AccountAlreadyMigrated,
+        UntraceError::MerkleTreeFull,
+        UntraceError::UnknownMerkleRoot,
+        UntraceError::InvalidMerkleTree,
+        UntraceError::DepositBelowMinimum,
+        UntraceError::DepositAboveMaximum,
+        UntraceError::EpochDepositCapExceeded,
+        UntraceError::ProtocolPaused,
+        UntraceError::InvalidTimelockDelay,
+        UntraceError::InvalidVaa,
+        UntraceError::UnknownEmitter,
+        UntraceError::RefundNotAvailable,
+        UntraceError::TransferAlreadyRefunded,
+        UntraceError::BridgeRateLimitExceeded,
+        UntraceError::RelayerFeeTooHigh,
+        UntraceError::SlippageExceeded,
+        UntraceError::OrderPayloadMismatch,
+        UntraceError::OrderExpired,
+        UntraceError::IntentMismatch,
+        UntraceError::CommitmentMismatch,
+        UntraceError::InsufficientPoolBalance,
+    ];
+
+    /// Error reported by the program as `ProgramError::Custom(code)`
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
 }
 
 impl From<UntraceError> for ProgramError {
@@ -54,3 +192,17 @@ impl From<UntraceError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_round_trip() {
+        for (code, error) in UntraceError::ALL.iter().enumerate() {
+            assert_eq!(*error as u32, code as u32);
+            assert_eq!(UntraceError::from_code(code as u32), Some(*error));
+        }
+        assert_eq!(UntraceError::from_code(UntraceError::ALL.len() as u32), None);
+    }
+}
@@ -0,0 +1,180 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::alt_bn128::prelude::{
+    alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing,
+};
+
+/// Size of a Groth16 proof: A in G1, B in G2, C in G1
+pub const PROOF_LEN: usize = 64 + 128 + 64;
+
+/// Generator of G1, (1, 2)
+pub const G1_GENERATOR: [u8; 64] = {
+    let mut point = [0u8; 64];
+    point[31] = 1;
+    point[63] = 2;
+    point
+};
+
+/// Generator of G2, imaginary part first as in EIP-197
+pub const G2_GENERATOR: [u8; 128] = [
+    0x19, 0x8e, 0x93, 0x93, 0x92, 0x0d, 0x48, 0x3a, 0x72, 0x60, 0xbf, 0xb7, 0x31, 0xfb, 0x5d, 0x25,
+    0xf1, 0xaa, 0x49, 0x33, 0x35, 0xa9, 0xe7, 0x12, 0x97, 0xe4, 0x85, 0xb7, 0xae, 0xf3, 0x12, 0xc2,
+    0x18, 0x00, 0xde, 0xef, 0x12, 0x1f, 0x1e, 0x76, 0x42, 0x6a, 0x00, 0x66, 0x5e, 0x5c, 0x44, 0x79,
+    0x67, 0x43, 0x22, 0xd4, 0xf7, 0x5e, 0xda, 0xdd, 0x46, 0xde, 0xbd, 0x5c, 0xd9, 0x92, 0xf6, 0xed,
+    0x09, 0x06, 0x89, 0xd0, 0x58, 0x5f, 0xf0, 0x75, 0xec, 0x9e, 0x99, 0xad, 0x69, 0x0c, 0x33, 0x95,
+    0xbc, 0x4b, 0x31, 0x33, 0x70, 0xb3, 0x8e, 0xf3, 0x55, 0xac, 0xda, 0xdc, 0xd1, 0x22, 0x97, 0x5b,
+    0x12, 0xc8, 0x5e, 0xa5, 0xdb, 0x8c, 0x6d, 0xeb, 0x4a, 0xab, 0x71, 0x80, 0x8d, 0xcb, 0x40, 0x8f,
+    0xe3, 0xd1, 0xe7, 0x69, 0x0c, 0x43, 0xd3, 0x7b, 0x4c, 0xe6, 0xcc, 0x01, 0x66, 0xfa, 0x7d, 0xaa,
+];
+
+/// Modulus of the BN254 base field, big-endian
+const FIELD_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d, 0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// Circuits the protocol verifies proofs of, one verifying key each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[repr(u8)]
+pub enum Circuit {
+    /// Privacy pool withdrawal, over `crypto::withdraw_inputs`
+    Withdraw,
+    /// Compressed pool withdrawal, over `crypto::withdraw_inputs`
+    CompressedWithdraw,
+    /// Shielded pool withdrawal, over `crypto::shielded_withdraw_inputs`
+    ShieldedWithdraw,
+    /// Shielded pool transfer, over `crypto::shielded_transfer_inputs`
+    ShieldedTransfer,
+}
+
+/// Groth16 verifying key of a circuit with a single public input, the
+/// hash of the statement. Points are big-endian, as the alt_bn128
+/// syscalls take them.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct VerifyingKey {
+    pub alpha_g1: [u8; 64],
+    pub beta_g2: [u8; 128],
+    pub gamma_g2: [u8; 128],
+    pub delta_g2: [u8; 128],
+    /// Terms of the public input combination: constant, then input
+    pub ic: [[u8; 64]; 2],
+}
+
+impl VerifyingKey {
+    pub const LEN: usize = 64 + 128 * 3 + 64 * 2;
+}
+
+/// Scalar a statement hash is proven as: its top three bits are cleared so
+/// it falls below the scalar field's modulus
+pub fn public_input(hash: &[u8; 32]) -> [u8; 32] {
+    let mut input = *hash;
+    input[0] &= 0x1f;
+    input
+}
+
+/// Sum of two G1 points, or `None` if either is not on the curve
+pub fn g1_add(p: &[u8; 64], q: &[u8; 64]) -> Option<[u8; 64]> {
+    let mut input = [0u8; 128];
+    input[..64].copy_from_slice(p);
+    input[64..].copy_from_slice(q);
+    alt_bn128_addition(&input).ok()?.try_into().ok()
+}
+
+/// `scalar` times a G1 point, or `None` if it is not on the curve
+pub fn g1_mul(p: &[u8; 64], scalar: &[u8; 32]) -> Option<[u8; 64]> {
+    let mut input = [0u8; 96];
+    input[..64].copy_from_slice(p);
+    input[64..].copy_from_slice(scalar);
+    alt_bn128_multiplication(&input).ok()?.try_into().ok()
+}
+
+/// Negation of a G1 point, (x, p - y)
+pub fn g1_neg(p: &[u8; 64]) -> [u8; 64] {
+    let mut negated = *p;
+    if p[32..] == [0u8; 32] {
+        return negated;
+    }
+    let mut borrow = 0u16;
+    for i in (0..32).rev() {
+        let difference = 0x100 + FIELD_MODULUS[i] as u16 - p[32 + i] as u16 - borrow;
+        negated[32 + i] = difference as u8;
+        borrow = u16::from(difference < 0x100);
+    }
+    negated
+}
+
+/// Check `proof` (A, B, C) shows the statement hashed to `inputs_hash`
+/// under `key`: e(-A, B) e(alpha, beta) e(vk_x, gamma) e(C, delta) = 1,
+/// where vk_x = IC0 + x IC1
+pub fn verify(key: &VerifyingKey, proof: &[u8], inputs_hash: &[u8; 32]) -> bool {
+    if proof.len() != PROOF_LEN {
+        return false;
+    }
+    let a: [u8; 64] = proof[..64].try_into().unwrap();
+    let (b, c) = (&proof[64..192], &proof[192..]);
+
+    let Some(vk_x) =
+        g1_mul(&key.ic[1], &public_input(inputs_hash)).and_then(|term| g1_add(&key.ic[0], &term))
+    else {
+        return false;
+    };
+
+    let mut pairing = Vec::with_capacity(4 * 192);
+    for (g1, g2) in [
+        (&g1_neg(&a)[..], b),
+        (&key.alpha_g1[..], &key.beta_g2[..]),
+        (&vk_x[..], &key.gamma_g2[..]),
+        (c, &key.delta_g2[..]),
+    ] {
+        pairing.extend_from_slice(g1);
+        pairing.extend_from_slice(g2);
+    }
+    matches!(alt_bn128_pairing(&pairing), Ok(result) if result.last() == Some(&1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(scalar: u8) -> [u8; 64] {
+        let mut bytes = [0u8; 32];
+        bytes[31] = scalar;
+        g1_mul(&G1_GENERATOR, &bytes).unwrap()
+    }
+
+    #[test]
+    fn test_forged_proof_rejected() {
+        // With every G2 term the generator, a proof is valid when
+        // C = A - alpha - vk_x
+        let key = VerifyingKey {
+            alpha_g1: point(3),
+            beta_g2: G2_GENERATOR,
+            gamma_g2: G2_GENERATOR,
+            delta_g2: G2_GENERATOR,
+            ic: [point(5), point(11)],
+        };
+        let inputs_hash = [0xab; 32];
+        let vk_x = g1_add(
+            &key.ic[0],
+            &g1_mul(&key.ic[1], &public_input(&inputs_hash)).unwrap(),
+        )
+        .unwrap();
+        let a = point(7);
+        let c = g1_add(&g1_add(&a, &g1_neg(&key.alpha_g1)).unwrap(), &g1_neg(&vk_x)).unwrap();
+
+        let mut proof = Vec::new();
+        proof.extend_from_slice(&a);
+        proof.extend_from_slice(&G2_GENERATOR);
+        proof.extend_from_slice(&c);
+        assert!(verify(&key, &proof, &inputs_hash));
+
+        // Bound to the statement
+        assert!(!verify(&key, &proof, &[0xac; 32]));
+
+        // Forged proofs
+        let mut forged = proof.clone();
+        forged[192..].copy_from_slice(&point(9));
+        assert!(!verify(&key, &forged, &inputs_hash));
+        assert!(!verify(&key, &[7u8; PROOF_LEN], &inputs_hash));
+        assert!(!verify(&key, &[7u8; 32], &inputs_hash));
+    }
+}
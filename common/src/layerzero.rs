@@ -0,0 +1,160 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{hash::hash, pubkey::Pubkey};
+
+use crate::SOLANA_CHAIN_ID;
+
+/// LayerZero endpoint ID of Solana mainnet
+pub const SOLANA_EID: u32 = 30168;
+
+/// Seeds of the endpoint's PDAs
+pub const OAPP_SEED: &[u8] = b"OApp";
+pub const ENDPOINT_SEED: &[u8] = b"Endpoint";
+pub const NONCE_SEED: &[u8] = b"Nonce";
+pub const PAYLOAD_HASH_SEED: &[u8] = b"PayloadHash";
+pub const SEND_LIBRARY_CONFIG_SEED: &[u8] = b"SendLibraryConfig";
+pub const MESSAGE_LIB_SEED: &[u8] = b"MessageLib";
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+/// Executor gas for `lz_receive` on an EVM destination
+pub const DEFAULT_LZ_RECEIVE_GAS: u128 = 200_000;
+
+/// Bridge message chain IDs with their LayerZero mainnet endpoint IDs. Sui
+/// (10) is only reached over Wormhole.
+const CHAIN_IDS: [(u16, u32); 10] = [
+    (1, 30101), // Ethereum
+    (2, 30102), // BNB Smart Chain
+    (3, 30109), // Polygon
+    (4, 30106), // Avalanche
+    (5, 30110), // Arbitrum
+    (6, 30111), // Optimism
+    (SOLANA_CHAIN_ID, SOLANA_EID),
+    (8, 30184),  // Base
+    (9, 30175),  // Arbitrum Nova
+    (11, 30108), // Aptos
+];
+
+/// LayerZero endpoint ID of a bridge message chain ID
+pub fn to_layerzero_eid(chain_id: u16) -> Option<u32> {
+    CHAIN_IDS
+        .iter()
+        .find(|(chain, _)| *chain == chain_id)
+        .map(|(_, eid)| *eid)
+}
+
+/// Bridge message chain ID of a LayerZero endpoint ID
+pub fn from_layerzero_eid(eid: u32) -> Option<u16> {
+    CHAIN_IDS
+        .iter()
+        .find(|(_, known)| *known == eid)
+        .map(|(chain, _)| *chain)
+}
+
+/// Arguments of the endpoint's `register_oapp`
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct RegisterOAppParams {
+    /// May configure the OApp's libraries and clear its packets
+    pub delegate: Pubkey,
+}
+
+/// Arguments of the endpoint's `send`
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct SendParams {
+    pub dst_eid: u32,
+    /// Peer OApp on the destination
+    pub receiver: [u8; 32],
+    pub message: Vec<u8>,
+    /// Executor and DVN options, such as `lz_receive_options`
+    pub options: Vec<u8>,
+    /// Most lamports the sender pays the send library
+    pub native_fee: u64,
+    pub lz_token_fee: u64,
+}
+
+/// Arguments of the endpoint's `clear`, which consumes a verified inbound
+/// packet so it cannot be delivered twice
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ClearParams {
+    pub receiver: Pubkey,
+    pub src_eid: u32,
+    pub sender: [u8; 32],
+    pub nonce: u64,
+    pub guid: [u8; 32],
+    pub message: Vec<u8>,
+}
+
+/// Inbound packet delivered to an OApp's `lz_receive`
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct LzReceiveParams {
+    pub src_eid: u32,
+    /// Peer OApp that sent the packet
+    pub sender: [u8; 32],
+    pub nonce: u64,
+    pub guid: [u8; 32],
+    pub message: Vec<u8>,
+    pub extra_data: Vec<u8>,
+}
+
+impl LzReceiveParams {
+    /// What the endpoint's `clear` needs to consume this packet at `receiver`
+    pub fn clear_params(&self, receiver: Pubkey) -> ClearParams {
+        ClearParams {
+            receiver,
+            src_eid: self.src_eid,
+            sender: self.sender,
+            nonce: self.nonce,
+            guid: self.guid,
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// Data of the endpoint's Anchor instruction `name` taking `params`
+pub fn instruction_data<T: BorshSerialize>(name: &str, params: &T) -> std::io::Result<Vec<u8>> {
+    let mut data = hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+    params.serialize(&mut data)?;
+    Ok(data)
+}
+
+/// Type 3 options asking the executor to call `lz_receive` on the
+/// destination with `gas`
+pub fn lz_receive_options(gas: u128) -> Vec<u8> {
+    let mut options = Vec::with_capacity(2 + 1 + 2 + 1 + 16);
+    options.extend_from_slice(&3u16.to_be_bytes());
+    // Executor worker, then the option's size including its type
+    options.push(1);
+    options.extend_from_slice(&17u16.to_be_bytes());
+    // lzReceive option
+    options.push(1);
+    options.extend_from_slice(&gas.to_be_bytes());
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eids_round_trip() {
+        for (chain, eid) in CHAIN_IDS {
+            assert_eq!(to_layerzero_eid(chain), Some(eid));
+            assert_eq!(from_layerzero_eid(eid), Some(chain));
+        }
+        assert_eq!(to_layerzero_eid(10), None);
+        assert_eq!(from_layerzero_eid(101), None);
+
+        let options = lz_receive_options(DEFAULT_LZ_RECEIVE_GAS);
+        let expected = "00030100110100000000000000000000000000030d40";
+        let encoded: String = options.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(encoded, expected);
+
+        let data = instruction_data(
+            "register_oapp",
+            &RegisterOAppParams {
+                delegate: Pubkey::new_unique(),
+            },
+        )
+        .unwrap();
+        assert_eq!(data[..8], hash(b"global:register_oapp").to_bytes()[..8]);
+        assert_eq!(data.len(), 8 + 32);
+    }
+}
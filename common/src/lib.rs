@@ -4,11 +4,27 @@ use serde::{Deserialize, Serialize};
 
 pub mod crypto;
 pub mod error;
+pub mod groth16;
+pub mod layerzero;
+pub mod wormhole;
 
 pub use error::UntraceError;
 
-/// Privacy levels supported by the protocol
-#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+/// Chain identifier used for Solana in bridge messages
+pub const SOLANA_CHAIN_ID: u16 = 7;
+
+/// Asset identifier for native SOL in the shielded pool
+pub const NATIVE_ASSET_ID: Pubkey = Pubkey::new_from_array([0u8; 32]);
+
+/// Most guardians the protocol's guardian set may hold
+pub const MAX_GUARDIANS: usize = 19;
+
+/// Privacy levels supported by the protocol, ordered from least to most
+/// private
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, BorshSerialize, BorshDeserialize, Serialize,
+    Deserialize,
+)]
 pub enum PrivacyLevel {
     /// Basic privacy - transaction amounts hidden
     Basic,
@@ -57,6 +73,117 @@ pub struct CrossChainTransfer {
     pub merkle_root: [u8; 32],
 }
 
+/// Messaging backend carrying a bridge message between chains
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize,
+    Deserialize,
+)]
+pub enum BridgeProtocol {
+    #[default]
+    Wormhole,
+    LayerZero,
+    /// The protocol's own guardian set, signing message digests
+    Guardian,
+}
+
+/// Inbound bridge message attested by the guardian set
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BridgeMessage {
+    /// Source chain identifier
+    pub source_chain: u16,
+    /// Destination chain identifier
+    pub dest_chain: u16,
+    /// Sequence number assigned on the source chain
+    pub sequence: u64,
+    /// Recipient on the destination chain
+    pub recipient: [u8; 32],
+    /// Amount to release (lamports)
+    pub amount: u64,
+    /// Hash of the encrypted transfer payload
+    pub payload_hash: [u8; 32],
+}
+
+impl BridgeMessage {
+    /// Digest that guardians sign to attest this message
+    pub fn digest(&self) -> [u8; 32] {
+        // Borsh serialization of fixed-size fields cannot fail
+        let bytes = self.try_to_vec().expect("bridge message serialization");
+        crypto::bridge_message_digest(&bytes)
+    }
+}
+
+/// Root of a batch of shielded nullifiers spent on `source_chain`, posted to
+/// the other chains so a note spent there cannot also exit here. Its
+/// payload is shorter than a `BridgeMessage`'s, so neither decodes as the
+/// other.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct NullifierRootMessage {
+    /// Chain the nullifiers were spent on
+    pub source_chain: u16,
+    /// Batch number, counting up from zero per source chain
+    pub batch: u64,
+    /// `crypto::merkle_root` of the batch's nullifiers
+    pub nullifier_root: [u8; 32],
+    /// Nullifiers in the batch
+    pub nullifier_count: u32,
+}
+
+/// Transfer of a non-native asset attested by the guardian set: minted as
+/// its wrapped representation when it arrives on Solana, and released on
+/// its origin chain when the wrapped tokens are burned here. Its payload is
+/// longer than a `BridgeMessage`'s, so neither decodes as the other.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct WrappedTransferMessage {
+    pub source_chain: u16,
+    pub dest_chain: u16,
+    /// Sequence number assigned on the source chain
+    pub sequence: u64,
+    /// Chain the asset is native to
+    pub origin_chain: u16,
+    /// Token address on the origin chain, left-padded to 32 bytes
+    pub origin_asset: [u8; 32],
+    /// Owner of the tokens on the destination chain
+    pub recipient: [u8; 32],
+    /// Amount in the wrapped mint's decimals
+    pub amount: u64,
+    /// Part of `amount` paid to whoever submits the claim on the
+    /// destination, for fronting its gas; zero when the recipient claims
+    pub relayer_fee: u64,
+}
+
+impl WrappedTransferMessage {
+    /// Digest that guardians sign to attest this message
+    pub fn digest(&self) -> [u8; 32] {
+        // Borsh serialization of fixed-size fields cannot fail
+        let bytes = self.try_to_vec().expect("wrapped transfer serialization");
+        crypto::bridge_message_digest(&bytes)
+    }
+}
+
+/// Guardian attestation that an outbound transfer from Solana expired on
+/// its destination without being redeemed, so its sender may take it back.
+/// Its payload is longer than a `NullifierRootMessage`'s and shorter than a
+/// `BridgeMessage`'s, so it decodes as neither.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BridgeRefundMessage {
+    pub dest_chain: u16,
+    /// Sequence number the transfer was sent with
+    pub sequence: u64,
+    /// Key naming the transfer on Solana, as its outbound transfer records
+    pub transfer: [u8; 32],
+    /// Lamports locked by the transfer
+    pub amount: u64,
+}
+
+impl BridgeRefundMessage {
+    /// Digest that guardians sign to attest this refund
+    pub fn digest(&self) -> [u8; 32] {
+        // Borsh serialization of fixed-size fields cannot fail
+        let bytes = self.try_to_vec().expect("bridge refund serialization");
+        crypto::bridge_message_digest(&bytes)
+    }
+}
+
 /// Privacy pool for mixing transactions
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct PrivacyPool {
@@ -70,6 +197,75 @@ pub struct PrivacyPool {
     pub min_pool_size: u64,
 }
 
+/// Note held in the multi-asset shielded pool
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ShieldedNote {
+    /// Asset identifier (SPL mint, or NATIVE_ASSET_ID for SOL)
+    pub asset_id: Pubkey,
+    /// Note value in the asset's base units
+    pub amount: u64,
+    /// Owner able to spend the note
+    pub owner: Pubkey,
+    /// Blinding randomness
+    pub randomness: [u8; 32],
+}
+
+impl ShieldedNote {
+    /// Owner and randomness part of the commitment, revealed on deposit
+    pub fn note_key(&self) -> [u8; 32] {
+        crypto::note_key(&self.owner.to_bytes(), &self.randomness)
+    }
+
+    /// Commitment inserted into the shielded pool's Merkle tree
+    pub fn commitment(&self) -> [u8; 32] {
+        crypto::generate_asset_commitment(&self.asset_id.to_bytes(), self.amount, &self.note_key())
+    }
+
+    /// Nullifier revealed when the note is spent
+    pub fn nullifier(&self) -> [u8; 32] {
+        crypto::generate_nullifier(&self.randomness, &self.commitment())
+    }
+}
+
+/// Admin change that must sit in a timelock before it can be executed
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum AdminAction {
+    /// Hand protocol admin rights to a new key
+    TransferAuthority { new_authority: Pubkey },
+    /// Replace the emergency guardian
+    SetGuardian { guardian: Pubkey },
+    /// Change the delay applied to newly queued actions (seconds)
+    SetTimelockDelay { delay: i64 },
+    /// Change a pool's deposit limits; queued by the pool authority
+    ConfigurePool {
+        pool_id: u64,
+        min_deposit: u64,
+        max_deposit: u64,
+        epoch_deposit_cap: u64,
+    },
+    /// Change the lamports that may be bridged out to `dest_chain` per
+    /// epoch; zero lifts the cap
+    SetBridgeLimit { dest_chain: u16, epoch_cap: u64 },
+    /// Replace the guardians attesting bridge messages and their quorum
+    SetGuardianSet { guardians: Vec<Pubkey>, quorum: u8 },
+    /// Replace the emitter trusted for Wormhole chain `chain`, which must
+    /// already have one registered
+    SetForeignEmitter { chain: u16, address: [u8; 32] },
+    /// Replace the OApp trusted on LayerZero endpoint `eid`, which must
+    /// already have a peer set
+    SetLayerZeroPeer { eid: u32, address: [u8; 32] },
+    /// Replace the verifying key proofs of `circuit` are checked against
+    SetVerifyingKey {
+        circuit: groth16::Circuit,
+        key: Box<groth16::VerifyingKey>,
+    },
+}
+
+impl AdminAction {
+    /// Largest serialized size of any variant, a full guardian set
+    pub const MAX_LEN: usize = 1 + 4 + 32 * MAX_GUARDIANS + 1;
+}
+
 /// Commitment for privacy pool
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct Commitment {
@@ -121,4 +317,25 @@ pub struct AntiMevConfig {
     pub batching_enabled: bool,
     /// Batch size
     pub batch_size: u32,
+    /// Most instructions one submitter may have waiting for or in a sealed
+    /// batch, 0 for no limit
+    pub max_in_flight_per_user: u32,
+    /// Share of each batch a submitter gets against the others waiting.
+    /// Submitters not listed weigh 1.
+    pub user_weights: Vec<(Pubkey, u32)>,
+}
+
+impl Default for AntiMevConfig {
+    /// Time locks of 10 slots and batches of 5, with at most 10
+    /// instructions in flight per submitter
+    fn default() -> Self {
+        Self {
+            time_lock_enabled: true,
+            min_time_lock: 10,
+            batching_enabled: true,
+            batch_size: 5,
+            max_in_flight_per_user: 10,
+            user_weights: Vec::new(),
+        }
+    }
 }
@@ -0,0 +1,130 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+use crate::SOLANA_CHAIN_ID;
+
+/// Wormhole's chain ID for Solana
+pub const WORMHOLE_SOLANA_CHAIN_ID: u16 = 1;
+
+/// Account prefix of a VAA posted to the core bridge
+pub const POSTED_VAA_MAGIC: &[u8; 3] = b"vaa";
+/// Account prefix of a message posted to the core bridge
+pub const POSTED_MESSAGE_MAGIC: &[u8; 3] = b"msg";
+
+/// Core bridge `PostMessage` instruction index
+const POST_MESSAGE_INSTRUCTION: u8 = 1;
+
+/// Core bridge consistency level waiting for finalization
+pub const CONSISTENCY_FINALIZED: u8 = 1;
+
+/// Bridge message chain IDs with their Wormhole chain IDs. Arbitrum Nova
+/// (9) is not on Wormhole.
+const CHAIN_IDS: [(u16, u16); 10] = [
+    (1, 2),  // Ethereum
+    (2, 4),  // BNB Smart Chain
+    (3, 5),  // Polygon
+    (4, 6),  // Avalanche
+    (5, 23), // Arbitrum
+    (6, 24), // Optimism
+    (SOLANA_CHAIN_ID, WORMHOLE_SOLANA_CHAIN_ID),
+    (8, 30),  // Base
+    (10, 21), // Sui
+    (11, 22), // Aptos
+];
+
+/// Wormhole chain ID of a bridge message chain ID
+pub fn to_wormhole_chain_id(chain_id: u16) -> Option<u16> {
+    CHAIN_IDS
+        .iter()
+        .find(|(chain, _)| *chain == chain_id)
+        .map(|(_, wormhole)| *wormhole)
+}
+
+/// Bridge message chain ID of a Wormhole chain ID
+pub fn from_wormhole_chain_id(wormhole_chain_id: u16) -> Option<u16> {
+    CHAIN_IDS
+        .iter()
+        .find(|(_, wormhole)| *wormhole == wormhole_chain_id)
+        .map(|(chain, _)| *chain)
+}
+
+/// Message or VAA as stored by the core bridge on Solana
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct MessageData {
+    pub vaa_version: u8,
+    pub consistency_level: u8,
+    pub vaa_time: u32,
+    pub vaa_signature_account: Pubkey,
+    pub submission_time: u32,
+    pub nonce: u32,
+    pub sequence: u64,
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub payload: Vec<u8>,
+}
+
+impl MessageData {
+    /// Decode a core bridge account starting with `magic`
+    pub fn from_account(data: &[u8], magic: &[u8; 3]) -> Option<Self> {
+        let mut body = data.strip_prefix(magic.as_slice())?;
+        Self::deserialize(&mut body).ok()
+    }
+}
+
+/// Fee the core bridge charges per posted message, from its config account
+pub fn message_fee(bridge_data: &[u8]) -> Option<u64> {
+    // guardian_set_index: u32, last_lamports: u64,
+    // guardian_set_expiration_time: u32, fee: u64
+    let fee = bridge_data.get(16..24)?;
+    Some(u64::from_le_bytes(fee.try_into().ok()?))
+}
+
+/// Data of a core bridge `PostMessage` instruction
+pub fn post_message_data(nonce: u32, payload: &[u8], consistency_level: u8) -> Vec<u8> {
+    let mut data = Vec::with_capacity(1 + 4 + 4 + payload.len() + 1);
+    data.push(POST_MESSAGE_INSTRUCTION);
+    data.extend_from_slice(&nonce.to_le_bytes());
+    data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    data.extend_from_slice(payload);
+    data.push(consistency_level);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_ids_round_trip() {
+        for (chain, wormhole) in CHAIN_IDS {
+            assert_eq!(to_wormhole_chain_id(chain), Some(wormhole));
+            assert_eq!(from_wormhole_chain_id(wormhole), Some(chain));
+        }
+        assert_eq!(to_wormhole_chain_id(0), None);
+        assert_eq!(to_wormhole_chain_id(9), None);
+        assert_eq!(from_wormhole_chain_id(3), None);
+
+        let message = MessageData {
+            vaa_version: 1,
+            consistency_level: CONSISTENCY_FINALIZED,
+            vaa_time: 0,
+            vaa_signature_account: Pubkey::default(),
+            submission_time: 0,
+            nonce: 0,
+            sequence: 7,
+            emitter_chain: 2,
+            emitter_address: [1u8; 32],
+            payload: vec![1, 2, 3],
+        };
+        let mut account = POSTED_VAA_MAGIC.to_vec();
+        account.extend(message.try_to_vec().unwrap());
+        assert_eq!(
+            MessageData::from_account(&account, POSTED_VAA_MAGIC),
+            Some(message)
+        );
+        assert_eq!(
+            MessageData::from_account(&account, POSTED_MESSAGE_MAGIC),
+            None
+        );
+    }
+}
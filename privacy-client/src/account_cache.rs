@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use crate::error::Result;
+use crate::UntraceClient;
+
+/// How long a default `UntraceClient` reuses a fetched account
+pub const DEFAULT_ACCOUNT_TTL: Duration = Duration::from_secs(30);
+
+/// Slots a default `UntraceClient` lets pass before refetching an account
+/// (about a minute)
+pub const DEFAULT_MAX_SLOT_AGE: u64 = 150;
+
+struct CachedAccount {
+    account: Account,
+    /// Slot the account was read at
+    slot: u64,
+    fetched_at: Instant,
+}
+
+/// Recently fetched accounts that rarely change, such as pool configs, so
+/// frequent wallet operations do not refetch them. An entry expires after
+/// its TTL, or once the newest slot seen is more than `max_slot_age` past
+/// the slot it was read at.
+pub struct AccountCache {
+    ttl: Duration,
+    max_slot_age: u64,
+    entries: Mutex<HashMap<Pubkey, CachedAccount>>,
+    /// Newest slot any fetch has reported
+    latest_slot: AtomicU64,
+}
+
+impl AccountCache {
+    pub fn new(ttl: Duration, max_slot_age: u64) -> Self {
+        Self {
+            ttl,
+            max_slot_age,
+            entries: Mutex::new(HashMap::new()),
+            latest_slot: AtomicU64::new(0),
+        }
+    }
+
+    /// Cached copy of `address`, if still fresh
+    pub fn get(&self, address: &Pubkey) -> Option<Account> {
+        let latest_slot = self.latest_slot.load(Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(address)?;
+        if entry.fetched_at.elapsed() >= self.ttl
+            || latest_slot.saturating_sub(entry.slot) > self.max_slot_age
+        {
+            entries.remove(address);
+            return None;
+        }
+        Some(entry.account.clone())
+    }
+
+    /// Cache `account` as read at `slot`
+    pub fn insert(&self, address: Pubkey, account: Account, slot: u64) {
+        self.observe_slot(slot);
+        self.entries.lock().unwrap().insert(
+            address,
+            CachedAccount {
+                account,
+                slot,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Record that the cluster has reached `slot`, aging every entry
+    pub fn observe_slot(&self, slot: u64) {
+        self.latest_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    /// Drop the cached copy of `address`
+    pub fn invalidate(&self, address: &Pubkey) {
+        self.entries.lock().unwrap().remove(address);
+    }
+
+    /// Drop every entry read before `slot`
+    pub fn invalidate_before(&self, slot: u64) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.slot >= slot);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Default for AccountCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACCOUNT_TTL, DEFAULT_MAX_SLOT_AGE)
+    }
+}
+
+impl UntraceClient {
+    /// Fetch an account through `account_cache`
+    pub async fn get_account_cached(&self, address: &Pubkey) -> Result<Account> {
+        match self.account_cache.get(address) {
+            Some(account) => Ok(account),
+            None => self.refresh_account(address).await,
+        }
+    }
+
+    /// Fetch an account from the cluster, replacing any cached copy
+    pub async fn refresh_account(&self, address: &Pubkey) -> Result<Account> {
+        let response = self
+            .rpc_client
+            .get_account_with_commitment(address, self.config.commitment)
+            .await?;
+        let Some(account) = response.value else {
+            self.account_cache.invalidate(address);
+            return Err(anyhow!("Account {} not found", address).into());
+        };
+
+        self.account_cache
+            .insert(*address, account.clone(), response.context.slot);
+        Ok(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_expire_by_slot_and_ttl() {
+        let address = Pubkey::new_unique();
+        let account = Account::new(1, 0, &Pubkey::new_unique());
+
+        let cache = AccountCache::new(Duration::from_secs(60), 10);
+        cache.insert(address, account.clone(), 100);
+        cache.observe_slot(110);
+        assert_eq!(cache.get(&address), Some(account.clone()));
+        cache.observe_slot(111);
+        assert_eq!(cache.get(&address), None);
+
+        cache.insert(address, account.clone(), 200);
+        cache.invalidate_before(201);
+        assert_eq!(cache.get(&address), None);
+
+        let expired = AccountCache::new(Duration::ZERO, 10);
+        expired.insert(address, account, 100);
+        assert_eq!(expired.get(&address), None);
+    }
+}
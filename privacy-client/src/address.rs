@@ -0,0 +1,217 @@
+use std::fmt;
+
+use sha3::{Digest, Keccak256};
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use crate::bitcoin::TaprootAddress;
+use crate::cross_chain::SupportedChain;
+use crate::evm::EvmAddress;
+
+/// Why a recipient address was rejected for its chain
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AddressError {
+    #[error("EVM address must start with 0x")]
+    MissingHexPrefix,
+
+    #[error("Address is not valid {0}")]
+    InvalidEncoding(&'static str),
+
+    #[error("Address is {actual} bytes, expected {expected}")]
+    InvalidLength { expected: usize, actual: usize },
+
+    /// Mixed-case EVM address whose casing does not match EIP-55
+    #[error("Address does not match its EIP-55 checksum")]
+    InvalidChecksum,
+
+    /// Recipient encryption key of another curve or another account
+    #[error("Encryption key does not belong to the address")]
+    KeyMismatch,
+}
+
+/// Recipient address parsed and validated for its chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainAddress {
+    Evm(EvmAddress),
+    Solana(Pubkey),
+    Sui([u8; 32]),
+    Aptos([u8; 32]),
+    Bitcoin(TaprootAddress),
+}
+
+impl ChainAddress {
+    /// Parse `address` in the format `chain` uses: 0x-prefixed hex for EVM
+    /// chains, checked against EIP-55 when mixed-case, base58 for Solana,
+    /// 32 bytes of 0x-prefixed hex for Sui, the same for Aptos, where
+    /// leading zeros may be left out, and bech32m Taproot for Bitcoin
+    pub fn parse(chain: SupportedChain, address: &str) -> Result<Self, AddressError> {
+        match chain {
+            SupportedChain::Solana => parse_solana_address(address).map(Self::Solana),
+            SupportedChain::Sui => parse_move_address(address, false).map(Self::Sui),
+            SupportedChain::Aptos => parse_move_address(address, true).map(Self::Aptos),
+            SupportedChain::Bitcoin => TaprootAddress::parse(address).map(Self::Bitcoin),
+            SupportedChain::Ethereum
+            | SupportedChain::BinanceSmartChain
+            | SupportedChain::Polygon
+            | SupportedChain::Avalanche
+            | SupportedChain::Arbitrum
+            | SupportedChain::Optimism
+            | SupportedChain::Base
+            | SupportedChain::ArbitrumNova => parse_evm_address(address).map(Self::Evm),
+        }
+    }
+
+    /// Left-padded to 32 bytes, as Wormhole carries addresses; the output
+    /// key of a Taproot address
+    pub fn to_bytes32(&self) -> [u8; 32] {
+        match self {
+            Self::Evm(address) => {
+                let mut bytes = [0u8; 32];
+                bytes[12..].copy_from_slice(address);
+                bytes
+            }
+            Self::Solana(pubkey) => pubkey.to_bytes(),
+            Self::Sui(address) | Self::Aptos(address) => *address,
+            Self::Bitcoin(address) => address.output_key,
+        }
+    }
+}
+
+impl fmt::Display for ChainAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Evm(address) => f.write_str(&to_checksum_address(address)),
+            Self::Solana(pubkey) => write!(f, "{}", pubkey),
+            Self::Sui(address) | Self::Aptos(address) => write!(f, "0x{}", hex::encode(address)),
+            Self::Bitcoin(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+impl SupportedChain {
+    pub fn parse_address(&self, address: &str) -> Result<ChainAddress, AddressError> {
+        ChainAddress::parse(*self, address)
+    }
+}
+
+fn parse_solana_address(address: &str) -> Result<Pubkey, AddressError> {
+    let bytes = bs58::decode(address)
+        .into_vec()
+        .map_err(|_| AddressError::InvalidEncoding("base58"))?;
+    Ok(Pubkey::new_from_array(fixed_length(bytes)?))
+}
+
+fn fixed_length<const N: usize>(bytes: Vec<u8>) -> Result<[u8; N], AddressError> {
+    let actual = bytes.len();
+    bytes.try_into().map_err(|_| AddressError::InvalidLength {
+        expected: N,
+        actual,
+    })
+}
+
+fn parse_evm_address(address: &str) -> Result<EvmAddress, AddressError> {
+    let digits = address
+        .strip_prefix("0x")
+        .ok_or(AddressError::MissingHexPrefix)?;
+    let bytes = hex::decode(digits).map_err(|_| AddressError::InvalidEncoding("hex"))?;
+    let evm_address: EvmAddress = fixed_length(bytes)?;
+
+    // All-lowercase and all-uppercase addresses carry no checksum
+    let lowercase = digits.bytes().all(|b| !b.is_ascii_uppercase());
+    let uppercase = digits.bytes().all(|b| !b.is_ascii_lowercase());
+    if !lowercase && !uppercase && to_checksum_address(&evm_address) != address {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(evm_address)
+}
+
+/// Sui or Aptos account address. Aptos also takes the short form, with
+/// leading zeros trimmed.
+fn parse_move_address(address: &str, short_form: bool) -> Result<[u8; 32], AddressError> {
+    let digits = address
+        .strip_prefix("0x")
+        .ok_or(AddressError::MissingHexPrefix)?;
+    if short_form && !digits.is_empty() && digits.len() < 64 {
+        let padded = format!("{:0>64}", digits);
+        let bytes = hex::decode(padded).map_err(|_| AddressError::InvalidEncoding("hex"))?;
+        return fixed_length(bytes);
+    }
+    let bytes = hex::decode(digits).map_err(|_| AddressError::InvalidEncoding("hex"))?;
+    fixed_length(bytes)
+}
+
+/// EIP-55 mixed-case form of an EVM address
+pub fn to_checksum_address(address: &EvmAddress) -> String {
+    let digits = hex::encode(address);
+    let hash = Keccak256::digest(digits.as_bytes());
+
+    let mut checksummed = String::with_capacity(42);
+    checksummed.push_str("0x");
+    for (i, c) in digits.chars().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        if nibble >= 8 {
+            checksummed.push(c.to_ascii_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+    checksummed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_addresses() {
+        // Example from EIP-55
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = ChainAddress::parse(SupportedChain::Ethereum, checksummed).unwrap();
+        assert_eq!(address.to_string(), checksummed);
+        assert_eq!(
+            SupportedChain::Polygon.parse_address(&checksummed.to_lowercase()),
+            Ok(address)
+        );
+        assert_eq!(
+            SupportedChain::Ethereum.parse_address("0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            SupportedChain::Ethereum.parse_address(&checksummed[2..]),
+            Err(AddressError::MissingHexPrefix)
+        );
+        assert_eq!(
+            SupportedChain::Ethereum.parse_address(&checksummed[..40]),
+            Err(AddressError::InvalidLength {
+                expected: 20,
+                actual: 19
+            })
+        );
+
+        let pubkey = Pubkey::new_unique();
+        assert_eq!(
+            SupportedChain::Solana.parse_address(&pubkey.to_string()),
+            Ok(ChainAddress::Solana(pubkey))
+        );
+        assert_eq!(
+            SupportedChain::Solana.parse_address(checksummed),
+            Err(AddressError::InvalidEncoding("base58"))
+        );
+        assert!(SupportedChain::BinanceSmartChain
+            .parse_address(&pubkey.to_string())
+            .is_err());
+
+        let sui = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            SupportedChain::Sui.parse_address(&sui),
+            Ok(ChainAddress::Sui([0xab; 32]))
+        );
+        assert!(SupportedChain::Sui.parse_address("0x1").is_err());
+        let mut framework = [0u8; 32];
+        framework[31] = 1;
+        let aptos = SupportedChain::Aptos.parse_address("0x1").unwrap();
+        assert_eq!(aptos, ChainAddress::Aptos(framework));
+        assert_eq!(aptos.to_string(), format!("0x{}1", "0".repeat(63)));
+        assert!(SupportedChain::Base.parse_address(&sui).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use solana_sdk::{pubkey::Pubkey, signature::Signature, system_program};
+use untrace_common::{
+    groth16::{Circuit, VerifyingKey},
+    AdminAction,
+};
+use untrace_privacy_program::{accounts, instruction};
+
+use crate::cross_chain::{find_bridge_config_address, find_guardian_set_address};
+use crate::error::Result;
+use crate::layerzero::find_layerzero_peer_address;
+use crate::privacy_pool::{find_pool_address, find_pool_config_address};
+use crate::wormhole::find_foreign_emitter_address;
+use crate::UntraceClient;
+
+/// Derive the PDA holding protocol admin state
+pub fn find_admin_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"admin_config"], program_id)
+}
+
+/// Derive the PDA holding the verifying key of `circuit`
+pub fn find_verifier_address(program_id: &Pubkey, circuit: Circuit) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"verifier", &[circuit as u8]], program_id)
+}
+
+/// Derive the PDA of a queued admin action
+pub fn find_timelock_address(program_id: &Pubkey, action_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"timelock", &action_id.to_le_bytes()], program_id)
+}
+
+pub struct AdminClient<'a> {
+    client: &'a UntraceClient,
+}
+
+impl<'a> AdminClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self { client }
+    }
+
+    /// Initialize admin state with the payer as authority
+    pub async fn initialize(&self, guardian: &Pubkey, timelock_delay: i64) -> Result<Signature> {
+        let (admin_config, _) = find_admin_config_address(&self.client.program_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeAdmin {
+                admin_config,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeAdmin {
+                guardian: *guardian,
+                timelock_delay,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Register the verifying key proofs of `circuit` are checked against.
+    /// The payer must be the authority; later keys are queued as
+    /// `AdminAction::SetVerifyingKey`.
+    pub async fn initialize_verifier(
+        &self,
+        circuit: Circuit,
+        key: &VerifyingKey,
+    ) -> Result<Signature> {
+        let program_id = self.client.program_id;
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeVerifier {
+                verifier: find_verifier_address(&program_id, circuit).0,
+                admin_config: find_admin_config_address(&program_id).0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeVerifier {
+                circuit,
+                key: key.clone(),
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Pause deposits, transfers and bridge claims (guardian or authority)
+    pub async fn pause(&self) -> Result<Signature> {
+        let instruction = self
+            .client
+            .program_instruction(self.set_paused_accounts(), instruction::Pause {});
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Resume after a pause (authority only)
+    pub async fn unpause(&self) -> Result<Signature> {
+        let instruction = self
+            .client
+            .program_instruction(self.set_paused_accounts(), instruction::Unpause {});
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    fn set_paused_accounts(&self) -> accounts::SetPaused {
+        accounts::SetPaused {
+            admin_config: find_admin_config_address(&self.client.program_id).0,
+            signer: self.client.payer.pubkey(),
+        }
+    }
+
+    /// Queue `action` under `action_id`; it becomes executable after the
+    /// protocol's timelock delay
+    pub async fn queue(&self, action_id: u64, action: &AdminAction) -> Result<Signature> {
+        let program_id = self.client.program_id;
+        let (admin_config, _) = find_admin_config_address(&program_id);
+        let (timelock, _) = find_timelock_address(&program_id, action_id);
+
+        // Pool changes are authorized against the pool account
+        let privacy_pool = match action {
+            AdminAction::ConfigurePool { pool_id, .. } => {
+                Some(find_pool_address(&program_id, *pool_id).0)
+            }
+            _ => None,
+        };
+
+        let instruction = self.client.program_instruction(
+            accounts::QueueAdminAction {
+                admin_config,
+                timelock,
+                privacy_pool,
+                proposer: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::QueueAdminAction {
+                action_id,
+                action: action.clone(),
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Execute a queued action once its delay has passed
+    pub async fn execute(
+        &self,
+        action_id: u64,
+        action: &AdminAction,
+        proposer: &Pubkey,
+    ) -> Result<Signature> {
+        let program_id = self.client.program_id;
+        let (admin_config, _) = find_admin_config_address(&program_id);
+        let (timelock, _) = find_timelock_address(&program_id, action_id);
+
+        let pool_config = match action {
+            AdminAction::ConfigurePool { pool_id, .. } => {
+                Some(find_pool_config_address(&program_id, *pool_id).0)
+            }
+            _ => None,
+        };
+        let bridge_config = match action {
+            AdminAction::SetBridgeLimit { .. } => Some(find_bridge_config_address(&program_id).0),
+            _ => None,
+        };
+        let guardian_set = match action {
+            AdminAction::SetGuardianSet { .. } => Some(find_guardian_set_address(&program_id).0),
+            _ => None,
+        };
+        let foreign_emitter = match action {
+            AdminAction::SetForeignEmitter { chain, .. } => {
+                Some(find_foreign_emitter_address(&program_id, *chain).0)
+            }
+            _ => None,
+        };
+        let layerzero_peer = match action {
+            AdminAction::SetLayerZeroPeer { eid, .. } => {
+                Some(find_layerzero_peer_address(&program_id, *eid).0)
+            }
+            _ => None,
+        };
+        let verifier = match action {
+            AdminAction::SetVerifyingKey { circuit, .. } => {
+                Some(find_verifier_address(&program_id, *circuit).0)
+            }
+            _ => None,
+        };
+
+        let instruction = self.client.program_instruction(
+            accounts::ExecuteAdminAction {
+                admin_config,
+                timelock,
+                pool_config,
+                bridge_config,
+                guardian_set,
+                foreign_emitter,
+                layerzero_peer,
+                verifier,
+                proposer: *proposer,
+            },
+            instruction::ExecuteAdminAction {},
+        );
+
+        let signature = self.client.send_transaction(vec![instruction]).await?;
+        // The action may have rewritten a cached pool config
+        if let Some(pool_config) = pool_config {
+            self.client.account_cache.invalidate(&pool_config);
+        }
+        Ok(signature)
+    }
+
+    /// Cancel a queued action (its proposer or the guardian)
+    pub async fn cancel(&self, action_id: u64, proposer: &Pubkey) -> Result<Signature> {
+        let program_id = self.client.program_id;
+        let (admin_config, _) = find_admin_config_address(&program_id);
+        let (timelock, _) = find_timelock_address(&program_id, action_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::CancelAdminAction {
+                admin_config,
+                timelock,
+                proposer: *proposer,
+                canceller: self.client.payer.pubkey(),
+            },
+            instruction::CancelAdminAction {},
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+}
@@ -0,0 +1,866 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use untrace_common::WrappedTransferMessage;
+
+use crate::address::AddressError;
+use crate::cross_chain::SupportedChain;
+use crate::error::Result;
+use crate::UntraceClient;
+
+/// Origin asset of wrapped BTC, the chain's native coin
+pub const BTC_ASSET: [u8; 32] = [0u8; 32];
+
+/// Smallest Taproot output relayed by Bitcoin nodes, in sats
+pub const TAPROOT_DUST: u64 = 330;
+
+/// Virtual size of a transaction's fixed fields, one Taproot key-path
+/// input, and one Taproot output, rounded up
+const TX_OVERHEAD_VBYTES: u64 = 11;
+const TAPROOT_INPUT_VBYTES: u64 = 58;
+const TAPROOT_OUTPUT_VBYTES: u64 = 43;
+
+/// Inputs opt into replace-by-fee, so a stuck release can be bumped
+const RBF_SEQUENCE: u32 = 0xffff_fffd;
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+/// Bitcoin network, which sets the prefix of its addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BitcoinNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl BitcoinNetwork {
+    const ALL: [BitcoinNetwork; 3] = [
+        BitcoinNetwork::Mainnet,
+        BitcoinNetwork::Testnet,
+        BitcoinNetwork::Regtest,
+    ];
+
+    /// Human-readable part of the network's segwit addresses
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            BitcoinNetwork::Mainnet => "bc",
+            BitcoinNetwork::Testnet => "tb",
+            BitcoinNetwork::Regtest => "bcrt",
+        }
+    }
+}
+
+/// Pay-to-Taproot address, the only kind Bitcoin exits pay to: its 32-byte
+/// output key fits a bridge message's recipient as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaprootAddress {
+    pub network: BitcoinNetwork,
+    /// x-only output key, the witness program
+    pub output_key: [u8; 32],
+}
+
+impl TaprootAddress {
+    /// Address paying to `output_key` on `network`
+    pub fn new(network: BitcoinNetwork, output_key: [u8; 32]) -> Self {
+        Self {
+            network,
+            output_key,
+        }
+    }
+
+    /// Address of a key-path-only output for `internal_key`, as BIP 86
+    /// wallets and the custody's FROST group use
+    pub fn from_internal_key(network: BitcoinNetwork, internal_key: &[u8; 32]) -> Result<Self> {
+        Ok(Self::new(network, taproot_output_key(internal_key)?))
+    }
+
+    /// Parse a bech32m `bc1p…` address, or its testnet or regtest form
+    pub fn parse(address: &str) -> std::result::Result<Self, AddressError> {
+        let invalid = AddressError::InvalidEncoding("bech32m Taproot");
+        if address.bytes().any(|b| b.is_ascii_uppercase())
+            && address.bytes().any(|b| b.is_ascii_lowercase())
+        {
+            return Err(invalid);
+        }
+        let address = address.to_ascii_lowercase();
+        let (hrp, data) = address.rsplit_once('1').ok_or(invalid.clone())?;
+        let network = BitcoinNetwork::ALL
+            .into_iter()
+            .find(|network| network.hrp() == hrp)
+            .ok_or(invalid.clone())?;
+
+        let values = data
+            .bytes()
+            .map(|c| BECH32_CHARSET.iter().position(|&d| d == c).map(|v| v as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(invalid.clone())?;
+        if values.len() < 7 || bech32_polymod(&hrp_expand(hrp), &values) != BECH32M_CONST {
+            return Err(invalid);
+        }
+        // Witness version 1, then the program
+        let payload = &values[..values.len() - 6];
+        if payload[0] != 1 {
+            return Err(invalid);
+        }
+        let program = convert_bits(&payload[1..], 5, 8, false).ok_or(invalid.clone())?;
+        let actual = program.len();
+        let output_key: [u8; 32] = program
+            .try_into()
+            .map_err(|_| AddressError::InvalidLength {
+                expected: 32,
+                actual,
+            })?;
+
+        // An output key off the curve could never be spent
+        if libsecp256k1::PublicKey::parse_compressed(&x_only_to_compressed(&output_key)).is_err() {
+            return Err(invalid);
+        }
+        Ok(Self::new(network, output_key))
+    }
+
+    /// `OP_1 <output key>`
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        taproot_script(&self.output_key)
+    }
+}
+
+impl fmt::Display for TaprootAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = self.network.hrp();
+        let mut values = vec![1u8];
+        values.extend(convert_bits(&self.output_key, 8, 5, true).unwrap_or_default());
+        let mut checked = values.clone();
+        checked.extend([0u8; 6]);
+        let checksum = bech32_polymod(&hrp_expand(hrp), &checked) ^ BECH32M_CONST;
+        values.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8));
+
+        f.write_str(hrp)?;
+        f.write_str("1")?;
+        for value in values {
+            write!(f, "{}", BECH32_CHARSET[value as usize] as char)?;
+        }
+        Ok(())
+    }
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|b| b & 31));
+    expanded
+}
+
+fn bech32_polymod(hrp: &[u8], values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut checksum = 1u32;
+    for value in hrp.iter().chain(values) {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ff_ffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// Regroup `data` from `from`-bit to `to`-bit values
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let max = (1u32 << to) - 1;
+    let mut out = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    for value in data {
+        if (*value as u32) >> from != 0 {
+            return None;
+        }
+        acc = ((acc << from) | *value as u32) & 0xfff;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    for part in data {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn x_only_to_compressed(key: &[u8; 32]) -> [u8; 33] {
+    let mut compressed = [2u8; 33];
+    compressed[1..].copy_from_slice(key);
+    compressed
+}
+
+/// BIP 341 output key committing to `internal_key` with no script tree
+pub fn taproot_output_key(internal_key: &[u8; 32]) -> Result<[u8; 32]> {
+    let tweak = tagged_hash("TapTweak", &[internal_key]);
+    let mut key = libsecp256k1::PublicKey::parse_compressed(&x_only_to_compressed(internal_key))
+        .map_err(|e| anyhow!("Invalid Taproot internal key: {:?}", e))?;
+    let tweak = libsecp256k1::SecretKey::parse(&tweak)
+        .map_err(|e| anyhow!("Invalid Taproot tweak: {:?}", e))?;
+    key.tweak_add_assign(&tweak)
+        .map_err(|e| anyhow!("Invalid Taproot tweak: {:?}", e))?;
+
+    let mut output_key = [0u8; 32];
+    output_key.copy_from_slice(&key.serialize_compressed()[1..]);
+    Ok(output_key)
+}
+
+fn taproot_script(output_key: &[u8; 32]) -> Vec<u8> {
+    let mut script = vec![0x51, 0x20];
+    script.extend_from_slice(output_key);
+    script
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        _ => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+    }
+}
+
+fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+/// Unspent output of the custody address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    /// Funding transaction ID, in internal byte order
+    pub txid: [u8; 32],
+    pub vout: u32,
+    /// Value in sats
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub sequence: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    /// Value in sats
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl TxOut {
+    fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.value.to_le_bytes());
+        write_compact_size(out, self.script_pubkey.len());
+        out.extend_from_slice(&self.script_pubkey);
+    }
+}
+
+/// Version 2 Bitcoin transaction spending Taproot outputs by key path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitcoinTransaction {
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: u32,
+}
+
+impl BitcoinTransaction {
+    const VERSION: i32 = 2;
+
+    /// Serialization without witnesses, which the transaction ID hashes
+    pub fn serialize_unsigned(&self) -> Vec<u8> {
+        self.serialize(None)
+    }
+
+    /// Transaction ID, in internal byte order
+    pub fn txid(&self) -> [u8; 32] {
+        sha256d(&self.serialize_unsigned())
+    }
+
+    fn serialize(&self, signatures: Option<&[[u8; 64]]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&Self::VERSION.to_le_bytes());
+        if signatures.is_some() {
+            // Segwit marker and flag
+            out.extend_from_slice(&[0x00, 0x01]);
+        }
+        write_compact_size(&mut out, self.inputs.len());
+        for input in &self.inputs {
+            out.extend_from_slice(&input.txid);
+            out.extend_from_slice(&input.vout.to_le_bytes());
+            // Empty scriptSig
+            out.push(0);
+            out.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut out, self.outputs.len());
+        for output in &self.outputs {
+            output.serialize_into(&mut out);
+        }
+        if let Some(signatures) = signatures {
+            // A key-path spend's witness is its signature alone
+            for signature in signatures {
+                out.push(1);
+                write_compact_size(&mut out, signature.len());
+                out.extend_from_slice(signature);
+            }
+        }
+        out.extend_from_slice(&self.lock_time.to_le_bytes());
+        out
+    }
+}
+
+/// Custody release waiting on the FROST group's signatures: the unsigned
+/// transaction with what each input spends, as BIP 174 carries it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Psbt {
+    pub transaction: BitcoinTransaction,
+    /// Output each input spends
+    pub spent: Vec<TxOut>,
+    /// Taproot internal key of the FROST group
+    pub internal_key: [u8; 32],
+    /// Index of the output returning change to custody, if any
+    pub change_output: Option<usize>,
+}
+
+impl Psbt {
+    /// BIP 174 serialization, with the witness UTXO and Taproot internal
+    /// key of each input and the internal key of the change output
+    pub fn serialize(&self) -> Vec<u8> {
+        fn write_pair(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+            write_compact_size(out, key.len());
+            out.extend_from_slice(key);
+            write_compact_size(out, value.len());
+            out.extend_from_slice(value);
+        }
+
+        let mut out = b"psbt\xff".to_vec();
+        write_pair(&mut out, &[0x00], &self.transaction.serialize_unsigned());
+        out.push(0);
+        for spent in &self.spent {
+            let mut utxo = Vec::new();
+            spent.serialize_into(&mut utxo);
+            write_pair(&mut out, &[0x01], &utxo);
+            write_pair(&mut out, &[0x17], &self.internal_key);
+            out.push(0);
+        }
+        for index in 0..self.transaction.outputs.len() {
+            if self.change_output == Some(index) {
+                write_pair(&mut out, &[0x05], &self.internal_key);
+            }
+            out.push(0);
+        }
+        out
+    }
+
+    /// BIP 341 key-path signature hashes, `SIGHASH_DEFAULT`, one per input
+    pub fn sighashes(&self) -> Vec<[u8; 32]> {
+        let transaction = &self.transaction;
+        let mut prevouts = Vec::new();
+        let mut sequences = Vec::new();
+        for input in &transaction.inputs {
+            prevouts.extend_from_slice(&input.txid);
+            prevouts.extend_from_slice(&input.vout.to_le_bytes());
+            sequences.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        let mut amounts = Vec::new();
+        let mut scripts = Vec::new();
+        for spent in &self.spent {
+            amounts.extend_from_slice(&spent.value.to_le_bytes());
+            write_compact_size(&mut scripts, spent.script_pubkey.len());
+            scripts.extend_from_slice(&spent.script_pubkey);
+        }
+        let mut outputs = Vec::new();
+        for output in &transaction.outputs {
+            output.serialize_into(&mut outputs);
+        }
+
+        let version = BitcoinTransaction::VERSION.to_le_bytes();
+        let lock_time = transaction.lock_time.to_le_bytes();
+        let (prevouts, amounts, scripts, sequences, outputs) = (
+            Sha256::digest(&prevouts),
+            Sha256::digest(&amounts),
+            Sha256::digest(&scripts),
+            Sha256::digest(&sequences),
+            Sha256::digest(&outputs),
+        );
+        (0..transaction.inputs.len() as u32)
+            .map(|index| {
+                tagged_hash(
+                    "TapSighash",
+                    &[
+                        // Epoch, then SIGHASH_DEFAULT
+                        &[0x00, 0x00],
+                        &version,
+                        &lock_time,
+                        &prevouts,
+                        &amounts,
+                        &scripts,
+                        &sequences,
+                        &outputs,
+                        // Key path, no annex
+                        &[0x00],
+                        &index.to_le_bytes(),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    /// Signed transaction, ready to broadcast, from one BIP 340 signature
+    /// per input
+    pub fn finalize(&self, signatures: &[[u8; 64]]) -> Result<Vec<u8>> {
+        if signatures.len() != self.transaction.inputs.len() {
+            return Err(anyhow!(
+                "{} signatures for {} inputs",
+                signatures.len(),
+                self.transaction.inputs.len()
+            )
+            .into());
+        }
+        Ok(self.transaction.serialize(Some(signatures)))
+    }
+}
+
+/// Fee in sats of a release with `inputs` and `outputs` at `fee_rate`
+/// sats per virtual byte
+pub fn release_fee(inputs: usize, outputs: usize, fee_rate: u64) -> u64 {
+    let vbytes = TX_OVERHEAD_VBYTES
+        + TAPROOT_INPUT_VBYTES * inputs as u64
+        + TAPROOT_OUTPUT_VBYTES * outputs as u64;
+    vbytes.saturating_mul(fee_rate)
+}
+
+/// Sats `message` releases to its recipient: the amount burned less its
+/// relayer fee, which pays the Bitcoin network fee
+pub fn release_amount(message: &WrappedTransferMessage) -> Result<u64> {
+    let bitcoin = SupportedChain::Bitcoin.to_u16();
+    if message.origin_chain != bitcoin || message.dest_chain != bitcoin {
+        return Err(anyhow!("Message {} does not exit to Bitcoin", message.sequence).into());
+    }
+    if message.origin_asset != BTC_ASSET {
+        return Err(anyhow!("Message {} does not carry BTC", message.sequence).into());
+    }
+    let amount = message.amount.saturating_sub(message.relayer_fee);
+    if amount < TAPROOT_DUST {
+        return Err(anyhow!("Message {} releases under the dust limit", message.sequence).into());
+    }
+    Ok(amount)
+}
+
+/// Build the release of `messages` from the custody's `utxos` at
+/// `fee_rate`, spending the largest outputs first and returning change to
+/// custody unless it would be dust
+pub fn build_release(
+    network: BitcoinNetwork,
+    internal_key: &[u8; 32],
+    utxos: &[Utxo],
+    messages: &[WrappedTransferMessage],
+    fee_rate: u64,
+) -> Result<Psbt> {
+    if messages.is_empty() {
+        return Err(anyhow!("No Bitcoin exits to release").into());
+    }
+    let custody = TaprootAddress::from_internal_key(network, internal_key)?.script_pubkey();
+    let mut outputs = messages
+        .iter()
+        .map(|message| {
+            Ok(TxOut {
+                value: release_amount(message)?,
+                script_pubkey: taproot_script(&message.recipient),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let payout: u64 = outputs.iter().map(|output| output.value).sum();
+
+    let with_change = |inputs| release_fee(inputs, outputs.len() + 1, fee_rate);
+    let mut candidates = utxos.to_vec();
+    candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.value));
+    let mut selected: Vec<Utxo> = Vec::new();
+    let mut total = 0u64;
+    for utxo in candidates {
+        if total >= payout + with_change(selected.len()) {
+            break;
+        }
+        total += utxo.value;
+        selected.push(utxo);
+    }
+    let fee = release_fee(selected.len(), outputs.len(), fee_rate);
+    if total < payout + fee {
+        return Err(anyhow!("Custody holds {} sats, {} needed", total, payout + fee).into());
+    }
+
+    // Change too small to spend goes to the miners instead
+    let change = total
+        .checked_sub(payout + with_change(selected.len()))
+        .filter(|change| *change >= TAPROOT_DUST);
+    let change_output = change.map(|change| {
+        outputs.push(TxOut {
+            value: change,
+            script_pubkey: custody.clone(),
+        });
+        outputs.len() - 1
+    });
+
+    Ok(Psbt {
+        transaction: BitcoinTransaction {
+            inputs: selected
+                .iter()
+                .map(|utxo| TxIn {
+                    txid: utxo.txid,
+                    vout: utxo.vout,
+                    sequence: RBF_SEQUENCE,
+                })
+                .collect(),
+            outputs,
+            lock_time: 0,
+        },
+        spent: selected
+            .iter()
+            .map(|utxo| TxOut {
+                value: utxo.value,
+                script_pubkey: custody.clone(),
+            })
+            .collect(),
+        internal_key: *internal_key,
+        change_output,
+    })
+}
+
+/// Where a relayer reaches Bitcoin and the custody holding bridged BTC
+#[derive(Debug, Clone)]
+pub struct BitcoinConfig {
+    pub network: BitcoinNetwork,
+    /// Taproot internal key of the custody's FROST group, x-only. Custody
+    /// holds BTC at its key-path-only output.
+    pub custody_key: [u8; 32],
+    /// Esplora API serving UTXOs and fee estimates and taking broadcasts
+    pub esplora_url: String,
+    /// Coordinator of the custody signers' FROST rounds
+    pub custody_api: String,
+    /// Blocks a release should confirm within, for its fee rate
+    pub fee_target: u16,
+}
+
+#[derive(Deserialize)]
+struct EsploraUtxo {
+    txid: String,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+}
+
+#[derive(Deserialize)]
+struct CustodySignatures {
+    /// Base64 BIP 340 signatures, one per input
+    signatures: Vec<String>,
+}
+
+/// Releases BTC from threshold custody to the Taproot addresses that
+/// burned wrapped BTC on Solana
+pub struct BitcoinCustodyClient<'a> {
+    client: &'a UntraceClient,
+    http: reqwest::Client,
+}
+
+impl<'a> BitcoinCustodyClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self {
+            client,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn bitcoin(&self) -> Result<&BitcoinConfig> {
+        self.client
+            .config
+            .bitcoin
+            .as_ref()
+            .ok_or_else(|| anyhow!("Bitcoin is not configured for this client").into())
+    }
+
+    fn esplora(&self, path: &str) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            self.bitcoin()?.esplora_url.trim_end_matches('/'),
+            path
+        ))
+    }
+
+    /// Address custody holds BTC at
+    pub fn custody_address(&self) -> Result<TaprootAddress> {
+        let bitcoin = self.bitcoin()?;
+        TaprootAddress::from_internal_key(bitcoin.network, &bitcoin.custody_key)
+    }
+
+    /// Confirmed outputs custody may spend
+    pub async fn utxos(&self) -> Result<Vec<Utxo>> {
+        let url = self.esplora(&format!("/address/{}/utxo", self.custody_address()?))?;
+        let utxos: Vec<EsploraUtxo> = self
+            .http
+            .get(url)
+            .timeout(self.client.config.timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        utxos
+            .into_iter()
+            .filter(|utxo| utxo.status.confirmed)
+            .map(|utxo| {
+                // Esplora shows transaction IDs byte-reversed
+                let mut txid: [u8; 32] = hex::decode(&utxo.txid)
+                    .map_err(anyhow::Error::from)?
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid transaction ID {}", utxo.txid))?;
+                txid.reverse();
+                Ok(Utxo {
+                    txid,
+                    vout: utxo.vout,
+                    value: utxo.value,
+                })
+            })
+            .collect()
+    }
+
+    /// Fee rate for confirming within the configured target, in sats per
+    /// virtual byte, rounded up
+    pub async fn fee_rate(&self) -> Result<u64> {
+        let estimates: std::collections::HashMap<String, f64> = self
+            .http
+            .get(self.esplora("/fee-estimates")?)
+            .timeout(self.client.config.timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let target = self.bitcoin()?.fee_target.to_string();
+        let rate = estimates
+            .get(&target)
+            .ok_or_else(|| anyhow!("No fee estimate for {} blocks", target))?;
+        Ok((rate.ceil() as u64).max(1))
+    }
+
+    /// Build the release of guardian-attested wrapped BTC burns at the
+    /// current fee rate
+    pub async fn build_release(&self, messages: &[WrappedTransferMessage]) -> Result<Psbt> {
+        let bitcoin = self.bitcoin()?;
+        let utxos = self.utxos().await?;
+        let fee_rate = self.fee_rate().await?;
+        build_release(
+            bitcoin.network,
+            &bitcoin.custody_key,
+            &utxos,
+            messages,
+            fee_rate,
+        )
+    }
+
+    /// Have the custody's FROST group sign `psbt`. The signers check each
+    /// payout against the burn in `messages` before a threshold of them
+    /// signs, and the coordinator returns one signature per input.
+    pub async fn sign(&self, psbt: &Psbt, messages: &[WrappedTransferMessage]) -> Result<Vec<u8>> {
+        let api = self.bitcoin()?.custody_api.trim_end_matches('/');
+        let digests: Vec<String> = messages
+            .iter()
+            .map(|message| hex::encode(message.digest()))
+            .collect();
+        let signed: CustodySignatures = self
+            .http
+            .post(format!("{}/v1/sign", api))
+            .timeout(self.client.config.timeout)
+            .json(&json!({
+                "psbt": STANDARD.encode(psbt.serialize()),
+                "messages": digests,
+            }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let signatures = signed
+            .signatures
+            .iter()
+            .map(|signature| {
+                STANDARD
+                    .decode(signature)
+                    .map_err(anyhow::Error::from)?
+                    .try_into()
+                    .map_err(|_| anyhow!("Custody signature is not 64 bytes").into())
+            })
+            .collect::<Result<Vec<[u8; 64]>>>()?;
+        psbt.finalize(&signatures)
+    }
+
+    /// Broadcast a signed release, returning its transaction ID as shown
+    /// by explorers
+    pub async fn broadcast(&self, transaction: &[u8]) -> Result<String> {
+        let txid = self
+            .http
+            .post(self.esplora("/tx")?)
+            .timeout(self.client.config.timeout)
+            .body(hex::encode(transaction))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .text()
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(txid.trim().to_string())
+    }
+
+    /// Pay out `messages` from custody: build, sign and broadcast one
+    /// release
+    pub async fn release(&self, messages: &[WrappedTransferMessage]) -> Result<String> {
+        let psbt = self.build_release(messages).await?;
+        let transaction = self.sign(&psbt, messages).await?;
+        self.broadcast(&transaction).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex32(hex_key: &str) -> [u8; 32] {
+        hex::decode(hex_key).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_taproot_addresses() {
+        // BIP 86's first receiving address
+        let internal_key =
+            from_hex32("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115");
+        let address =
+            TaprootAddress::from_internal_key(BitcoinNetwork::Mainnet, &internal_key).unwrap();
+        assert_eq!(
+            address.output_key,
+            from_hex32("a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c")
+        );
+        let encoded = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+        assert_eq!(address.to_string(), encoded);
+        assert_eq!(TaprootAddress::parse(encoded), Ok(address));
+        assert_eq!(
+            TaprootAddress::parse(&encoded.to_ascii_uppercase()),
+            Ok(address)
+        );
+
+        // A flipped character, a segwit v0 address, and a mixed-case one
+        assert!(TaprootAddress::parse(&encoded.replace("5cy", "5cz")).is_err());
+        assert!(TaprootAddress::parse("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_err());
+        assert!(TaprootAddress::parse(&encoded.replacen('p', "P", 1)).is_err());
+    }
+
+    #[test]
+    fn test_release_pays_exits_and_returns_change() {
+        let internal_key =
+            from_hex32("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115");
+        let bitcoin = SupportedChain::Bitcoin.to_u16();
+        let exit = |sequence, amount| WrappedTransferMessage {
+            source_chain: SupportedChain::Solana.to_u16(),
+            dest_chain: bitcoin,
+            sequence,
+            origin_chain: bitcoin,
+            origin_asset: BTC_ASSET,
+            recipient: internal_key,
+            amount,
+            relayer_fee: 2_000,
+        };
+        let utxos = [
+            Utxo {
+                txid: [1u8; 32],
+                vout: 0,
+                value: 40_000,
+            },
+            Utxo {
+                txid: [2u8; 32],
+                vout: 1,
+                value: 100_000,
+            },
+        ];
+
+        let psbt = build_release(
+            BitcoinNetwork::Mainnet,
+            &internal_key,
+            &utxos,
+            &[exit(0, 50_000), exit(1, 30_000)],
+            10,
+        )
+        .unwrap();
+        // The larger output covers both exits, with change back to custody
+        assert_eq!(psbt.transaction.inputs.len(), 1);
+        assert_eq!(psbt.transaction.inputs[0].txid, [2u8; 32]);
+        let values: Vec<u64> = psbt.transaction.outputs.iter().map(|o| o.value).collect();
+        let change = 100_000 - 76_000 - release_fee(1, 3, 10);
+        assert_eq!(values, vec![48_000, 28_000, change]);
+        assert_eq!(psbt.change_output, Some(2));
+        assert_eq!(psbt.sighashes().len(), 1);
+        assert!(psbt.serialize().starts_with(b"psbt\xff"));
+
+        let signed = psbt.finalize(&[[7u8; 64]]).unwrap();
+        assert_eq!(&signed[4..6], &[0x00, 0x01]);
+        assert!(psbt.finalize(&[]).is_err());
+
+        assert!(build_release(
+            BitcoinNetwork::Mainnet,
+            &internal_key,
+            &utxos,
+            &[exit(2, 200_000)],
+            10
+        )
+        .is_err());
+        let mut not_btc = exit(3, 50_000);
+        not_btc.origin_asset = [9u8; 32];
+        assert!(release_amount(&not_btc).is_err());
+    }
+}
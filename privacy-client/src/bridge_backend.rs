@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use borsh::BorshDeserialize;
+use solana_sdk::signature::Signature;
+use untrace_common::{layerzero::LzReceiveParams, BridgeMessage, BridgeProtocol, SOLANA_CHAIN_ID};
+
+use crate::cross_chain::{BridgeStatus, CrossChainClient, GuardianAttestation, SupportedChain};
+use crate::error::Result;
+use crate::layerzero::LayerZeroClient;
+use crate::UntraceClient;
+
+/// Messaging layer carrying bridge transfers between Solana and the other
+/// chains. Every backend locks outbound lamports in the same vault under
+/// the same epoch limits, and releases inbound ones against the same
+/// consumed-message records, so each pair of chains may take whichever
+/// route reaches it best.
+#[async_trait(?Send)]
+pub trait BridgeBackend {
+    fn protocol(&self) -> BridgeProtocol;
+
+    /// Whether this backend reaches `chain`
+    fn supports(&self, chain: SupportedChain) -> bool;
+
+    /// Lamports the backend charges on Solana for a transfer to
+    /// `dest_chain`, on top of the amount bridged
+    async fn quote(&self, dest_chain: SupportedChain) -> Result<u64>;
+
+    /// Lock `amount` lamports and send an outbound transfer to
+    /// `dest_chain`, returning the message as sent
+    async fn initiate(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)>;
+
+    /// Proof that `message` was delivered, in the form `redeem` takes, or
+    /// `None` while the backend has not attested it yet
+    async fn attest(&self, message: &BridgeMessage) -> Result<Option<Vec<u8>>>;
+
+    /// Complete an inbound transfer from the backend's proof of delivery: a
+    /// signed VAA for Wormhole, a Borsh-encoded `LzReceiveParams` for
+    /// LayerZero, a Borsh-encoded `GuardianAttestation` for the guardians
+    async fn redeem(&self, delivery: &[u8]) -> Result<Signature>;
+
+    /// Where `message` stands as seen from Solana
+    async fn status(&self, message: &BridgeMessage) -> Result<BridgeStatus>;
+}
+
+/// Status of `message` from whether Solana consumed it and whether its
+/// backend attested it. An inbound message is completed once consumed; an
+/// outbound one once attested, as the destination claims it from there.
+pub fn message_status(message: &BridgeMessage, consumed: bool, attested: bool) -> BridgeStatus {
+    if message.dest_chain == SOLANA_CHAIN_ID {
+        if consumed {
+            BridgeStatus::Completed
+        } else if attested {
+            BridgeStatus::Pending
+        } else {
+            BridgeStatus::NotFound
+        }
+    } else if attested {
+        BridgeStatus::Completed
+    } else {
+        BridgeStatus::Pending
+    }
+}
+
+/// `message_status` of `message` through `backend`, checking consumption
+/// only for inbound messages
+async fn backend_status(
+    backend: &(impl BridgeBackend + ?Sized),
+    cross_chain: &CrossChainClient<'_>,
+    message: &BridgeMessage,
+) -> Result<BridgeStatus> {
+    let consumed =
+        message.dest_chain == SOLANA_CHAIN_ID && cross_chain.is_message_consumed(message).await?;
+    if consumed {
+        return Ok(message_status(message, true, false));
+    }
+    let attested = backend.attest(message).await?.is_some();
+    Ok(message_status(message, false, attested))
+}
+
+/// Redeem an inbound `message` through `backend` if it has been attested,
+/// returning `None` when it has not
+pub async fn redeem_when_attested(
+    backend: &dyn BridgeBackend,
+    message: &BridgeMessage,
+) -> Result<Option<Signature>> {
+    match backend.attest(message).await? {
+        Some(delivery) => Ok(Some(backend.redeem(&delivery).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Which backend carries transfers between each pair of chains. Pairs
+/// without a route of their own take Wormhole when it reaches both chains,
+/// then LayerZero, then the guardian set.
+#[derive(Debug, Clone, Default)]
+pub struct BridgeRoutes {
+    routes: HashMap<(SupportedChain, SupportedChain), BridgeProtocol>,
+}
+
+impl BridgeRoutes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Carry transfers from `source` to `dest` over `protocol`
+    pub fn with_route(
+        mut self,
+        source: SupportedChain,
+        dest: SupportedChain,
+        protocol: BridgeProtocol,
+    ) -> Self {
+        self.routes.insert((source, dest), protocol);
+        self
+    }
+
+    /// Backend carrying transfers from `source` to `dest`
+    pub fn protocol(&self, source: SupportedChain, dest: SupportedChain) -> BridgeProtocol {
+        if let Some(protocol) = self.routes.get(&(source, dest)) {
+            return *protocol;
+        }
+        if source.wormhole_chain_id().is_some() && dest.wormhole_chain_id().is_some() {
+            BridgeProtocol::Wormhole
+        } else if source.layerzero_eid().is_some() && dest.layerzero_eid().is_some() {
+            BridgeProtocol::LayerZero
+        } else {
+            BridgeProtocol::Guardian
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BridgeBackend for CrossChainClient<'_> {
+    fn protocol(&self) -> BridgeProtocol {
+        BridgeProtocol::Wormhole
+    }
+
+    fn supports(&self, chain: SupportedChain) -> bool {
+        chain != SupportedChain::Solana && chain.wormhole_chain_id().is_some()
+    }
+
+    async fn quote(&self, _dest_chain: SupportedChain) -> Result<u64> {
+        self.wormhole_message_fee().await
+    }
+
+    async fn initiate(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        self.post_message(dest_chain, recipient, amount, payload_hash)
+            .await
+    }
+
+    async fn attest(&self, message: &BridgeMessage) -> Result<Option<Vec<u8>>> {
+        self.fetch_message_vaa(message).await
+    }
+
+    async fn redeem(&self, delivery: &[u8]) -> Result<Signature> {
+        CrossChainClient::redeem(self, delivery).await
+    }
+
+    async fn status(&self, message: &BridgeMessage) -> Result<BridgeStatus> {
+        backend_status(self, self, message).await
+    }
+}
+
+#[async_trait(?Send)]
+impl BridgeBackend for LayerZeroClient<'_> {
+    fn protocol(&self) -> BridgeProtocol {
+        BridgeProtocol::LayerZero
+    }
+
+    fn supports(&self, chain: SupportedChain) -> bool {
+        chain != SupportedChain::Solana && chain.layerzero_eid().is_some()
+    }
+
+    async fn quote(&self, _dest_chain: SupportedChain) -> Result<u64> {
+        Ok(self.layerzero()?.max_native_fee)
+    }
+
+    async fn initiate(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        self.post_message(dest_chain, recipient, amount, payload_hash)
+            .await
+    }
+
+    /// LayerZero's executors deliver packets to `lz_receive` themselves, so
+    /// there is no attestation to fetch
+    async fn attest(&self, _message: &BridgeMessage) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    async fn redeem(&self, delivery: &[u8]) -> Result<Signature> {
+        let packet = LzReceiveParams::try_from_slice(delivery).map_err(anyhow::Error::from)?;
+        LayerZeroClient::redeem(self, &packet).await
+    }
+
+    async fn status(&self, message: &BridgeMessage) -> Result<BridgeStatus> {
+        backend_status(self, self.cross_chain(), message).await
+    }
+}
+
+/// Bridge transfers signed by the protocol's own guardian set, for chains
+/// neither Wormhole nor LayerZero reaches
+pub struct GuardianBackend<'a> {
+    cross_chain: CrossChainClient<'a>,
+}
+
+impl<'a> GuardianBackend<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self {
+            cross_chain: CrossChainClient::new(client),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BridgeBackend for GuardianBackend<'_> {
+    fn protocol(&self) -> BridgeProtocol {
+        BridgeProtocol::Guardian
+    }
+
+    /// Bitcoin only takes wrapped BTC exits, paid out of threshold custody
+    fn supports(&self, chain: SupportedChain) -> bool {
+        chain != SupportedChain::Solana && chain != SupportedChain::Bitcoin
+    }
+
+    /// Guardians sign for free; the sender only pays rent and fees
+    async fn quote(&self, _dest_chain: SupportedChain) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn initiate(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        self.cross_chain
+            .post_guardian_message(dest_chain, recipient, amount, payload_hash)
+            .await
+    }
+
+    async fn attest(&self, message: &BridgeMessage) -> Result<Option<Vec<u8>>> {
+        let Some(attestation) = self.cross_chain.fetch_guardian_attestation(message).await? else {
+            return Ok(None);
+        };
+        Ok(Some(
+            borsh::to_vec(&attestation).map_err(anyhow::Error::from)?,
+        ))
+    }
+
+    async fn redeem(&self, delivery: &[u8]) -> Result<Signature> {
+        let attestation =
+            GuardianAttestation::try_from_slice(delivery).map_err(anyhow::Error::from)?;
+        self.cross_chain
+            .claim(&attestation.message, &attestation.signatures)
+            .await
+    }
+
+    async fn status(&self, message: &BridgeMessage) -> Result<BridgeStatus> {
+        backend_status(self, &self.cross_chain, message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+    use std::cell::RefCell;
+
+    /// Backend whose transfers, attestations and redemptions stay in memory
+    #[derive(Default)]
+    struct MockBackend {
+        initiated: RefCell<Vec<BridgeMessage>>,
+        attested: Option<Vec<u8>>,
+        redeemed: RefCell<Vec<Vec<u8>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl BridgeBackend for MockBackend {
+        fn protocol(&self) -> BridgeProtocol {
+            BridgeProtocol::Guardian
+        }
+
+        fn supports(&self, _chain: SupportedChain) -> bool {
+            true
+        }
+
+        async fn quote(&self, _dest_chain: SupportedChain) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn initiate(
+            &self,
+            dest_chain: SupportedChain,
+            recipient: [u8; 32],
+            amount: u64,
+            payload_hash: [u8; 32],
+        ) -> Result<(Signature, BridgeMessage)> {
+            let mut initiated = self.initiated.borrow_mut();
+            let message = BridgeMessage {
+                source_chain: SOLANA_CHAIN_ID,
+                dest_chain: dest_chain.to_u16(),
+                sequence: initiated.len() as u64,
+                recipient,
+                amount,
+                payload_hash,
+            };
+            initiated.push(message.clone());
+            Ok((Signature::default(), message))
+        }
+
+        async fn attest(&self, _message: &BridgeMessage) -> Result<Option<Vec<u8>>> {
+            Ok(self.attested.clone())
+        }
+
+        async fn redeem(&self, delivery: &[u8]) -> Result<Signature> {
+            self.redeemed.borrow_mut().push(delivery.to_vec());
+            Ok(Signature::default())
+        }
+
+        async fn status(&self, message: &BridgeMessage) -> Result<BridgeStatus> {
+            Ok(message_status(
+                message,
+                !self.redeemed.borrow().is_empty(),
+                self.attested.is_some(),
+            ))
+        }
+    }
+
+    fn inbound_message() -> BridgeMessage {
+        BridgeMessage {
+            source_chain: SupportedChain::Ethereum.to_u16(),
+            dest_chain: SOLANA_CHAIN_ID,
+            sequence: 7,
+            recipient: Pubkey::new_unique().to_bytes(),
+            amount: 1_000_000,
+            payload_hash: [3u8; 32],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redeems_once_attested() {
+        let message = inbound_message();
+        let mut backend = MockBackend::default();
+
+        assert_eq!(
+            redeem_when_attested(&backend, &message).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            backend.status(&message).await.unwrap(),
+            BridgeStatus::NotFound
+        );
+
+        backend.attested = Some(vec![1, 2, 3]);
+        assert_eq!(
+            backend.status(&message).await.unwrap(),
+            BridgeStatus::Pending
+        );
+        assert!(redeem_when_attested(&backend, &message)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(*backend.redeemed.borrow(), vec![vec![1, 2, 3]]);
+        assert_eq!(
+            backend.status(&message).await.unwrap(),
+            BridgeStatus::Completed
+        );
+
+        let (_, outbound) = backend
+            .initiate(
+                SupportedChain::Ethereum,
+                message.recipient,
+                message.amount,
+                message.payload_hash,
+            )
+            .await
+            .unwrap();
+        assert_eq!(outbound.source_chain, SOLANA_CHAIN_ID);
+        assert_eq!(*backend.initiated.borrow(), vec![outbound.clone()]);
+        assert_eq!(
+            message_status(&outbound, false, false),
+            BridgeStatus::Pending
+        );
+        assert_eq!(
+            message_status(&outbound, false, true),
+            BridgeStatus::Completed
+        );
+    }
+
+    #[test]
+    fn test_routes_fall_back_by_reach() {
+        let routes = BridgeRoutes::new().with_route(
+            SupportedChain::Solana,
+            SupportedChain::Ethereum,
+            BridgeProtocol::LayerZero,
+        );
+
+        assert_eq!(
+            routes.protocol(SupportedChain::Solana, SupportedChain::Ethereum),
+            BridgeProtocol::LayerZero
+        );
+        assert_eq!(
+            routes.protocol(SupportedChain::Ethereum, SupportedChain::Solana),
+            BridgeProtocol::Wormhole
+        );
+        assert_eq!(
+            routes.protocol(SupportedChain::Solana, SupportedChain::ArbitrumNova),
+            BridgeProtocol::LayerZero
+        );
+    }
+
+    #[test]
+    fn test_backends_cover_their_chains() {
+        let client = UntraceClient::new(
+            "http://localhost:8899",
+            Pubkey::new_unique(),
+            Keypair::new(),
+        );
+
+        let wormhole = client.bridge(BridgeProtocol::Wormhole);
+        let layerzero = client.bridge(BridgeProtocol::LayerZero);
+        let guardian = client.bridge(BridgeProtocol::Guardian);
+        assert_eq!(wormhole.protocol(), BridgeProtocol::Wormhole);
+        assert_eq!(layerzero.protocol(), BridgeProtocol::LayerZero);
+        assert_eq!(guardian.protocol(), BridgeProtocol::Guardian);
+
+        assert!(wormhole.supports(SupportedChain::Sui));
+        assert!(!layerzero.supports(SupportedChain::Sui));
+        assert!(!wormhole.supports(SupportedChain::ArbitrumNova));
+        assert!(layerzero.supports(SupportedChain::ArbitrumNova));
+        for backend in [&wormhole, &layerzero, &guardian] {
+            assert!(backend.supports(SupportedChain::Ethereum));
+            assert!(!backend.supports(SupportedChain::Solana));
+        }
+        assert_eq!(
+            client
+                .bridge_for(SupportedChain::Solana, SupportedChain::ArbitrumNova)
+                .protocol(),
+            BridgeProtocol::LayerZero
+        );
+    }
+}
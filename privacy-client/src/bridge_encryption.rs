@@ -0,0 +1,445 @@
+use aes_gcm::{
+    aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+    Aes256Gcm,
+};
+use anyhow::anyhow;
+use borsh::{BorshDeserialize, BorshSerialize};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use sha3::{Digest, Keccak256, Sha3_256};
+use solana_sdk::signature::Keypair;
+use untrace_common::{crypto, EncryptedTransaction};
+
+use crate::address::{AddressError, ChainAddress};
+use crate::cross_chain::SupportedChain;
+use crate::error::Result;
+use crate::scanner::viewing_key;
+
+/// Domain separating bridge payload keys from other uses of the same
+/// Diffie-Hellman secret
+const KDF_DOMAIN: &[u8] = b"UNTRACE_BRIDGE_ECIES";
+
+/// Domain of the keys `encrypt_for_recipient` derives
+const RECIPIENT_KDF_DOMAIN: &[u8] = b"UNTRACE_RECIPIENT_ECIES";
+
+/// Domain of the keys `UntraceClient::encrypt_transfer_data` derives
+pub(crate) const TRANSFER_KDF_DOMAIN: &[u8] = b"UNTRACE_TRANSFER_ECIES";
+
+/// Length of the header `encrypt_for_recipient` puts before the ciphertext:
+/// ephemeral public key and authentication tag
+pub const RECIPIENT_CIPHERTEXT_HEADER_LEN: usize = 32 + 16;
+
+/// Curve of the encryption key recipients publish on a chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// Solana, Sui and Aptos account keys
+    Ed25519,
+    /// EVM and Bitcoin keys
+    Secp256k1,
+}
+
+impl SupportedChain {
+    pub fn encryption_key_format(&self) -> KeyFormat {
+        match self {
+            SupportedChain::Solana | SupportedChain::Sui | SupportedChain::Aptos => {
+                KeyFormat::Ed25519
+            }
+            SupportedChain::Ethereum
+            | SupportedChain::BinanceSmartChain
+            | SupportedChain::Polygon
+            | SupportedChain::Avalanche
+            | SupportedChain::Arbitrum
+            | SupportedChain::Optimism
+            | SupportedChain::Base
+            | SupportedChain::ArbitrumNova
+            | SupportedChain::Bitcoin => KeyFormat::Secp256k1,
+        }
+    }
+}
+
+/// Public key a bridge recipient published to receive encrypted payloads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionKey {
+    Ed25519([u8; 32]),
+    Secp256k1(libsecp256k1::PublicKey),
+}
+
+impl EncryptionKey {
+    /// Parse `key` in the format `chain` publishes it in: the base58
+    /// address itself on Solana, 32 bytes of 0x-prefixed hex on Sui and
+    /// Aptos, and a 0x-prefixed compressed or uncompressed secp256k1 key on
+    /// EVM chains and Bitcoin
+    pub fn parse(chain: SupportedChain, key: &str) -> std::result::Result<Self, AddressError> {
+        if chain == SupportedChain::Solana {
+            let ChainAddress::Solana(pubkey) = chain.parse_address(key)? else {
+                unreachable!("Solana addresses parse as Solana");
+            };
+            return Self::ed25519(pubkey.to_bytes());
+        }
+
+        let digits = key
+            .strip_prefix("0x")
+            .ok_or(AddressError::MissingHexPrefix)?;
+        let bytes = hex::decode(digits).map_err(|_| AddressError::InvalidEncoding("hex"))?;
+        match chain.encryption_key_format() {
+            KeyFormat::Ed25519 => {
+                let actual = bytes.len();
+                let key = bytes.try_into().map_err(|_| AddressError::InvalidLength {
+                    expected: 32,
+                    actual,
+                })?;
+                Self::ed25519(key)
+            }
+            KeyFormat::Secp256k1 => libsecp256k1::PublicKey::parse_slice(&bytes, None)
+                .map(Self::Secp256k1)
+                .map_err(|_| AddressError::InvalidEncoding("secp256k1 public key")),
+        }
+    }
+
+    fn ed25519(key: [u8; 32]) -> std::result::Result<Self, AddressError> {
+        // Keys off the curve could never be decrypted for
+        CompressedEdwardsY(key)
+            .decompress()
+            .ok_or(AddressError::InvalidEncoding("ed25519 public key"))?;
+        Ok(Self::Ed25519(key))
+    }
+
+    pub fn format(&self) -> KeyFormat {
+        match self {
+            Self::Ed25519(_) => KeyFormat::Ed25519,
+            Self::Secp256k1(_) => KeyFormat::Secp256k1,
+        }
+    }
+
+    /// Whether this is the key of `address`. Solana addresses are the key
+    /// and EVM ones its Keccak hash; Sui and Aptos addresses hash the key
+    /// with its signature scheme, and Taproot ones tweak it, so only the
+    /// format is checked there.
+    pub fn belongs_to(&self, address: &ChainAddress) -> bool {
+        match (self, address) {
+            (Self::Ed25519(key), ChainAddress::Solana(pubkey)) => key == pubkey.as_ref(),
+            (Self::Ed25519(_), ChainAddress::Sui(_) | ChainAddress::Aptos(_)) => true,
+            (Self::Secp256k1(_), ChainAddress::Bitcoin(_)) => true,
+            (Self::Secp256k1(key), ChainAddress::Evm(evm_address)) => {
+                let hash = Keccak256::digest(&key.serialize()[1..]);
+                hash[12..] == evm_address[..]
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Secret matching an `EncryptionKey`, held by the recipient
+pub enum DecryptionKey {
+    /// Viewing key of an ed25519 account
+    Ed25519([u8; 32]),
+    Secp256k1(libsecp256k1::SecretKey),
+}
+
+impl DecryptionKey {
+    /// Key of a Solana wallet, as its address is its encryption key
+    pub fn from_keypair(keypair: &Keypair) -> Self {
+        Self::Ed25519(viewing_key(keypair))
+    }
+
+    /// Key of a Sui or Aptos account from its 32-byte ed25519 seed
+    pub fn from_ed25519_seed(seed: &[u8; 32]) -> Self {
+        Self::Ed25519(crypto::viewing_key(seed))
+    }
+
+    /// Key of an EVM account from its private key
+    pub fn from_evm_secret(secret: &[u8; 32]) -> Result<Self> {
+        let secret = libsecp256k1::SecretKey::parse(secret)
+            .map_err(|e| anyhow!("Invalid EVM private key: {:?}", e))?;
+        Ok(Self::Secp256k1(secret))
+    }
+
+    pub fn encryption_key(&self) -> EncryptionKey {
+        match self {
+            Self::Ed25519(viewing_key) => {
+                EncryptionKey::Ed25519(crypto::ephemeral_public_key(viewing_key))
+            }
+            Self::Secp256k1(secret) => {
+                EncryptionKey::Secp256k1(libsecp256k1::PublicKey::from_secret_key(secret))
+            }
+        }
+    }
+}
+
+/// What a bridge transfer carries to its recipient, in the destination
+/// token and its decimals
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BridgePayload {
+    pub recipient: String,
+    pub amount: u128,
+    /// Token address on the destination, or its symbol when native
+    pub token: String,
+}
+
+/// Encrypt `payload` so only the holder of `key` can read it: ECIES with an
+/// ephemeral key on the recipient's curve, SHA3-256 key derivation and
+/// AES-256-GCM. Secp256k1 ephemeral keys are picked with an even Y so their
+/// 32-byte X coordinate identifies them.
+pub fn encrypt_payload(
+    payload: &BridgePayload,
+    key: &EncryptionKey,
+) -> Result<EncryptedTransaction> {
+    let (ephemeral_pubkey, shared) = match key {
+        EncryptionKey::Ed25519(recipient) => {
+            let ephemeral_secret: [u8; 32] = rand::random();
+            let shared = crypto::shared_secret(&ephemeral_secret, recipient)
+                .ok_or_else(|| anyhow!("Invalid ed25519 encryption key"))?;
+            (
+                crypto::ephemeral_public_key(&ephemeral_secret),
+                shared.to_vec(),
+            )
+        }
+        EncryptionKey::Secp256k1(recipient) => {
+            let (ephemeral_secret, ephemeral_public) = loop {
+                let Ok(secret) = libsecp256k1::SecretKey::parse(&rand::random()) else {
+                    continue;
+                };
+                let public = libsecp256k1::PublicKey::from_secret_key(&secret);
+                if public.serialize_compressed()[0] == 0x02 {
+                    break (secret, public);
+                }
+            };
+            let mut x = [0u8; 32];
+            x.copy_from_slice(&ephemeral_public.serialize_compressed()[1..]);
+            (x, secp256k1_shared_point(recipient, &ephemeral_secret)?)
+        }
+    };
+
+    let nonce: [u8; 12] = rand::random();
+    let mut ciphertext = borsh::to_vec(payload).map_err(anyhow::Error::from)?;
+    let tag = payload_cipher(KDF_DOMAIN, &shared, &ephemeral_pubkey)
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            &ephemeral_pubkey,
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow!("Bridge payload encryption failed"))?;
+
+    Ok(EncryptedTransaction {
+        ciphertext,
+        ephemeral_pubkey,
+        nonce,
+        tag: tag.into(),
+    })
+}
+
+/// Open a payload from `encrypt_payload` with the recipient's key. Fails
+/// when the payload was meant for someone else or was tampered with.
+pub fn decrypt_payload(
+    encrypted: &EncryptedTransaction,
+    key: &DecryptionKey,
+) -> Result<BridgePayload> {
+    let shared = match key {
+        DecryptionKey::Ed25519(viewing_key) => {
+            crypto::shared_secret(viewing_key, &encrypted.ephemeral_pubkey)
+                .ok_or_else(|| anyhow!("Invalid ephemeral key"))?
+                .to_vec()
+        }
+        DecryptionKey::Secp256k1(secret) => {
+            let mut compressed = [0x02; 33];
+            compressed[1..].copy_from_slice(&encrypted.ephemeral_pubkey);
+            let ephemeral = libsecp256k1::PublicKey::parse_compressed(&compressed)
+                .map_err(|_| anyhow!("Invalid ephemeral key"))?;
+            secp256k1_shared_point(&ephemeral, secret)?
+        }
+    };
+
+    let mut plaintext = encrypted.ciphertext.clone();
+    payload_cipher(KDF_DOMAIN, &shared, &encrypted.ephemeral_pubkey)
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&encrypted.nonce),
+            &encrypted.ephemeral_pubkey,
+            &mut plaintext,
+            GenericArray::from_slice(&encrypted.tag),
+        )
+        .map_err(|_| anyhow!("Bridge payload does not decrypt with this key"))?;
+    Ok(BridgePayload::try_from_slice(&plaintext).map_err(anyhow::Error::from)?)
+}
+
+/// Encrypt `plaintext` so only the owner of `recipient` (an ed25519 public
+/// key, e.g. a Solana address) can read it, as `encrypt_payload` does.
+/// `ephemeral_secret` must be fresh randomness for every message; as each
+/// message then has its own key, the nonce is fixed and not sent.
+pub fn encrypt_for_recipient(
+    plaintext: &[u8],
+    recipient: &[u8; 32],
+    ephemeral_secret: &[u8; 32],
+) -> Result<Vec<u8>> {
+    let ephemeral_pubkey = crypto::ephemeral_public_key(ephemeral_secret);
+    let shared = crypto::shared_secret(ephemeral_secret, recipient)
+        .ok_or_else(|| anyhow!("Invalid recipient key"))?;
+
+    let mut ciphertext = plaintext.to_vec();
+    let tag = payload_cipher(RECIPIENT_KDF_DOMAIN, &shared, &ephemeral_pubkey)
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&[0u8; 12]),
+            &ephemeral_pubkey,
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow!("Recipient encryption failed"))?;
+
+    let mut output = Vec::with_capacity(RECIPIENT_CIPHERTEXT_HEADER_LEN + ciphertext.len());
+    output.extend_from_slice(&ephemeral_pubkey);
+    output.extend_from_slice(&tag);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Trial-decrypt data from `encrypt_for_recipient` with a viewing key.
+/// Returns `None` when the data was meant for someone else.
+pub fn decrypt_for_recipient(data: &[u8], viewing_key: &[u8; 32]) -> Option<Vec<u8>> {
+    if data.len() < RECIPIENT_CIPHERTEXT_HEADER_LEN {
+        return None;
+    }
+
+    let ephemeral_pubkey: [u8; 32] = data[..32].try_into().ok()?;
+    let tag = &data[32..RECIPIENT_CIPHERTEXT_HEADER_LEN];
+    let shared = crypto::shared_secret(viewing_key, &ephemeral_pubkey)?;
+    let mut plaintext = data[RECIPIENT_CIPHERTEXT_HEADER_LEN..].to_vec();
+    payload_cipher(RECIPIENT_KDF_DOMAIN, &shared, &ephemeral_pubkey)
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&[0u8; 12]),
+            &ephemeral_pubkey,
+            &mut plaintext,
+            GenericArray::from_slice(tag),
+        )
+        .ok()?;
+    Some(plaintext)
+}
+
+/// Compressed point `secret * public`
+fn secp256k1_shared_point(
+    public: &libsecp256k1::PublicKey,
+    secret: &libsecp256k1::SecretKey,
+) -> Result<Vec<u8>> {
+    let mut point = *public;
+    point
+        .tweak_mul_assign(secret)
+        .map_err(|e| anyhow!("Invalid secp256k1 key: {:?}", e))?;
+    Ok(point.serialize_compressed().to_vec())
+}
+
+pub(crate) fn payload_cipher(
+    domain: &[u8],
+    shared: &[u8],
+    ephemeral_pubkey: &[u8; 32],
+) -> Aes256Gcm {
+    let mut hasher = Sha3_256::new();
+    hasher.update(domain);
+    hasher.update(shared);
+    hasher.update(ephemeral_pubkey);
+    Aes256Gcm::new(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    fn payload() -> BridgePayload {
+        BridgePayload {
+            recipient: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+            amount: 10_000_000_000_000_000,
+            token: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_payload_decrypts_for_recipient_only() {
+        let solana = Keypair::new();
+        let evm = DecryptionKey::from_evm_secret(&[7u8; 32]).unwrap();
+        let recipients = [
+            (
+                EncryptionKey::parse(SupportedChain::Solana, &solana.pubkey().to_string()).unwrap(),
+                DecryptionKey::from_keypair(&solana),
+            ),
+            (evm.encryption_key(), evm),
+        ];
+
+        for (key, secret) in &recipients {
+            assert_eq!(secret.encryption_key(), *key);
+            let encrypted = encrypt_payload(&payload(), key).unwrap();
+            assert_eq!(decrypt_payload(&encrypted, secret).unwrap(), payload());
+
+            let mut tampered = encrypted.clone();
+            tampered.ciphertext[0] ^= 1;
+            assert!(decrypt_payload(&tampered, secret).is_err());
+        }
+
+        let for_solana = encrypt_payload(&payload(), &recipients[0].0).unwrap();
+        assert!(decrypt_payload(&for_solana, &recipients[1].1).is_err());
+    }
+
+    #[test]
+    fn test_recipient_encryption_hides_plaintext() {
+        let solana = Keypair::new();
+        let recipient = solana.pubkey().to_bytes();
+        let data = encrypt_for_recipient(b"note", &recipient, &[6u8; 32]).unwrap();
+        assert_eq!(
+            decrypt_for_recipient(&data, &viewing_key(&solana)).unwrap(),
+            b"note"
+        );
+        assert!(decrypt_for_recipient(&data, &viewing_key(&Keypair::new())).is_none());
+
+        // Knowing the first half of the plaintext must not give away a pad
+        // that opens the second half
+        let known = [0x11u8; 32];
+        let secret = [0x22u8; 32];
+        let data =
+            encrypt_for_recipient(&[known, secret].concat(), &recipient, &[8u8; 32]).unwrap();
+        let ciphertext = &data[RECIPIENT_CIPHERTEXT_HEADER_LEN..];
+        let guessed: Vec<u8> = ciphertext[32..]
+            .iter()
+            .zip(&ciphertext[..32])
+            .zip(known)
+            .map(|((byte, first), known)| byte ^ first ^ known)
+            .collect();
+        assert_ne!(guessed, secret);
+    }
+
+    #[test]
+    fn test_parse_keys_per_chain() {
+        let secret = DecryptionKey::from_evm_secret(&[7u8; 32]).unwrap();
+        let EncryptionKey::Secp256k1(public) = secret.encryption_key() else {
+            panic!("EVM keys are secp256k1");
+        };
+        let compressed = format!("0x{}", hex::encode(public.serialize_compressed()));
+        let uncompressed = format!("0x{}", hex::encode(public.serialize()));
+        for key in [&compressed, &uncompressed] {
+            let parsed = EncryptionKey::parse(SupportedChain::Base, key).unwrap();
+            assert_eq!(parsed, secret.encryption_key());
+        }
+
+        let address = crate::evm::EvmWallet::from_bytes(&[7u8; 32])
+            .unwrap()
+            .address();
+        assert!(secret
+            .encryption_key()
+            .belongs_to(&ChainAddress::Evm(address)));
+        assert!(!secret
+            .encryption_key()
+            .belongs_to(&ChainAddress::Evm([0u8; 20])));
+
+        let aptos = DecryptionKey::from_ed25519_seed(&[3u8; 32]).encryption_key();
+        let EncryptionKey::Ed25519(bytes) = aptos else {
+            panic!("Aptos keys are ed25519");
+        };
+        let hex_key = format!("0x{}", hex::encode(bytes));
+        assert_eq!(
+            EncryptionKey::parse(SupportedChain::Aptos, &hex_key),
+            Ok(aptos)
+        );
+        assert_eq!(
+            EncryptionKey::parse(SupportedChain::Ethereum, &hex_key),
+            Err(AddressError::InvalidEncoding("secp256k1 public key"))
+        );
+        assert_eq!(
+            EncryptionKey::parse(SupportedChain::Sui, &hex_key[2..]),
+            Err(AddressError::MissingHexPrefix)
+        );
+    }
+}
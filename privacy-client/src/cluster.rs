@@ -0,0 +1,156 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+
+use crate::config::ClientConfig;
+use crate::fees::PriorityFeeStrategy;
+use crate::wormhole::WormholeConfig;
+use crate::UntraceClient;
+
+/// Solana cluster the privacy program is deployed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cluster {
+    MainnetBeta,
+    Devnet,
+    Testnet,
+    /// A local `solana-test-validator`
+    Localnet,
+}
+
+/// Connection and send defaults for one cluster
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    pub cluster: Cluster,
+    pub rpc_url: String,
+    /// Websocket URL for subscriptions
+    pub ws_url: String,
+    /// Privacy program deployed on the cluster
+    pub program_id: Pubkey,
+    /// Send policy, including fee settings and the Wormhole deployment,
+    /// suited to the cluster
+    pub client_config: ClientConfig,
+}
+
+impl Cluster {
+    pub const ALL: [Cluster; 4] = [
+        Cluster::MainnetBeta,
+        Cluster::Devnet,
+        Cluster::Testnet,
+        Cluster::Localnet,
+    ];
+
+    /// Name as used by the Solana CLI
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "mainnet-beta",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+            Cluster::Localnet => "localnet",
+        }
+    }
+
+    /// Preset for this cluster
+    pub fn config(&self) -> ClusterConfig {
+        let (rpc_url, ws_url) = match self {
+            Cluster::MainnetBeta => (
+                "https://api.mainnet-beta.solana.com",
+                "wss://api.mainnet-beta.solana.com",
+            ),
+            Cluster::Devnet => (
+                "https://api.devnet.solana.com",
+                "wss://api.devnet.solana.com",
+            ),
+            Cluster::Testnet => (
+                "https://api.testnet.solana.com",
+                "wss://api.testnet.solana.com",
+            ),
+            Cluster::Localnet => ("http://localhost:8899", "ws://localhost:8900"),
+        };
+
+        // Only mainnet sees enough contention to need a priority fee
+        let priority_fee = match self {
+            Cluster::MainnetBeta => PriorityFeeStrategy::Percentile(50),
+            _ => PriorityFeeStrategy::None,
+        };
+
+        // Wormhole has no deployment on testnet
+        let wormhole = match self {
+            Cluster::MainnetBeta => Some(WormholeConfig::mainnet()),
+            Cluster::Devnet => Some(WormholeConfig::devnet()),
+            Cluster::Testnet => None,
+            Cluster::Localnet => Some(WormholeConfig::localnet()),
+        };
+
+        ClusterConfig {
+            cluster: *self,
+            rpc_url: rpc_url.to_string(),
+            ws_url: ws_url.to_string(),
+            // The program is deployed at the same address on every cluster
+            program_id: untrace_privacy_program::ID,
+            client_config: ClientConfig {
+                priority_fee,
+                wormhole,
+                ws_url: Some(ws_url.to_string()),
+                ..ClientConfig::default()
+            },
+        }
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "mainnet-beta" | "mainnet" => Ok(Cluster::MainnetBeta),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            "localnet" | "localhost" => Ok(Cluster::Localnet),
+            _ => Err(anyhow!("Unknown cluster {:?}", s)),
+        }
+    }
+}
+
+impl UntraceClient {
+    /// Create a client for `cluster` with its preset URL, program ID and
+    /// send policy
+    pub fn for_cluster(cluster: Cluster, payer: impl Signer + Send + Sync + 'static) -> Self {
+        let ClusterConfig {
+            rpc_url,
+            program_id,
+            client_config,
+            ..
+        } = cluster.config();
+        Self::with_config(&rpc_url, program_id, payer, client_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_names_round_trip() {
+        for cluster in Cluster::ALL {
+            assert_eq!(cluster.name().parse::<Cluster>().unwrap(), cluster);
+            assert_eq!(cluster.config().program_id, untrace_privacy_program::ID);
+        }
+        assert_eq!("mainnet".parse::<Cluster>().unwrap(), Cluster::MainnetBeta);
+        assert!("mainnet-alpha".parse::<Cluster>().is_err());
+
+        assert_eq!(
+            serde_json::to_string(&Cluster::MainnetBeta).unwrap(),
+            "\"mainnet-beta\""
+        );
+        assert_eq!(Cluster::Localnet.config().rpc_url, "http://localhost:8899");
+    }
+}
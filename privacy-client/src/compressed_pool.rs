@@ -0,0 +1,463 @@
+use anchor_lang::Id;
+use anyhow::anyhow;
+use borsh::BorshDeserialize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction, system_program,
+};
+use spl_account_compression::{
+    events::ChangeLogEventV1,
+    state::{ConcurrentMerkleTreeHeader, CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1},
+    AccountCompressionEvent, ChangeLogEvent, ConcurrentMerkleTree, Noop,
+};
+use untrace_common::{crypto, groth16::Circuit};
+use untrace_privacy_program::{accounts, instruction};
+
+use crate::admin::{find_admin_config_address, find_verifier_address};
+use crate::error::{ClientError, Result};
+use crate::events::find_event_authority_address;
+use crate::UntraceClient;
+
+/// Depth of a compressed pool's concurrent tree
+pub const COMPRESSED_TREE_DEPTH: usize = 24;
+
+/// Changelog buffer size of a compressed pool's tree
+pub const COMPRESSED_TREE_BUFFER_SIZE: usize = 64;
+
+/// Canopy levels cached on-chain by default; proofs can skip these
+pub const DEFAULT_CANOPY_DEPTH: usize = 10;
+
+type CompressedTree = ConcurrentMerkleTree<COMPRESSED_TREE_DEPTH, COMPRESSED_TREE_BUFFER_SIZE>;
+
+/// Derive the PDA of a compressed pool
+pub fn find_compressed_pool_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"compressed_pool", &pool_id.to_le_bytes()], program_id)
+}
+
+/// Derive the PDA marking a compressed pool nullifier as spent
+pub fn find_compressed_nullifier_address(
+    program_id: &Pubkey,
+    pool_id: u64,
+    nullifier: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"compressed_nullifier", &pool_id.to_le_bytes(), nullifier],
+        program_id,
+    )
+}
+
+/// Bytes to allocate for a compressed pool's tree account
+pub fn compressed_tree_account_size(canopy_depth: usize) -> usize {
+    let canopy_nodes = (1usize << (canopy_depth + 1)) - 2;
+    CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1
+        + std::mem::size_of::<CompressedTree>()
+        + canopy_nodes * 32
+}
+
+/// Decode the changelog event the compression program logs through the
+/// noop program on every tree modification
+pub fn parse_changelog_event(data: &[u8]) -> anyhow::Result<ChangeLogEventV1> {
+    match AccountCompressionEvent::try_from_slice(data)? {
+        AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(event)) => Ok(event),
+        _ => Err(anyhow!("Not a changelog event")),
+    }
+}
+
+/// Read the roots still held in a compressed tree's changelog buffer
+pub fn decode_recent_roots(data: &[u8]) -> anyhow::Result<Vec<[u8; 32]>> {
+    if data.len() < CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1 + std::mem::size_of::<CompressedTree>() {
+        return Err(anyhow!("Truncated compressed tree account"));
+    }
+
+    let (header_bytes, rest) = data.split_at(CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1);
+    let header = ConcurrentMerkleTreeHeader::try_from_slice(header_bytes)?;
+    if header.get_max_depth() as usize != COMPRESSED_TREE_DEPTH
+        || header.get_max_buffer_size() as usize != COMPRESSED_TREE_BUFFER_SIZE
+    {
+        return Err(anyhow!("Unsupported compressed tree shape"));
+    }
+
+    let tree: &CompressedTree =
+        bytemuck::try_from_bytes(&rest[..std::mem::size_of::<CompressedTree>()])
+            .map_err(|e| anyhow!("Invalid compressed tree: {}", e))?;
+    let populated = (tree.buffer_size as usize).min(COMPRESSED_TREE_BUFFER_SIZE);
+
+    Ok(tree.change_logs[..populated]
+        .iter()
+        .map(|change_log| change_log.root)
+        .collect())
+}
+
+/// Off-chain copy of a compressed pool's leaves, rebuilt from changelogs
+#[derive(Debug, Clone, Default)]
+pub struct CommitmentIndex {
+    leaves: Vec<[u8; 32]>,
+    /// Sequence number of the last applied changelog
+    pub last_seq: u64,
+    /// Newest transaction already scanned, so syncs only fetch what is new
+    pub last_signature: Option<Signature>,
+}
+
+impl CommitmentIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of leaves appended so far
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Apply one changelog; the first path node is the modified leaf
+    pub fn apply_changelog(&mut self, event: &ChangeLogEventV1) -> Result<()> {
+        if event.seq <= self.last_seq {
+            return Ok(());
+        }
+
+        let leaf = event
+            .path
+            .first()
+            .ok_or_else(|| anyhow!("Changelog without path"))?
+            .node;
+        let index = event.index as usize;
+        if index >= self.leaves.len() {
+            self.leaves.resize(index + 1, [0u8; 32]);
+        }
+        self.leaves[index] = leaf;
+        self.last_seq = event.seq;
+
+        Ok(())
+    }
+
+    /// Leaf index of a commitment, if it has been indexed
+    pub fn leaf_index(&self, commitment: &[u8; 32]) -> Option<u32> {
+        self.leaves
+            .iter()
+            .position(|leaf| leaf == commitment)
+            .map(|index| index as u32)
+    }
+
+    /// Root of the tree as of the last applied changelog
+    pub fn root(&self) -> [u8; 32] {
+        let (root, _) = self.path(0);
+        root
+    }
+
+    /// Proof for `leaf_index` with the top `canopy_depth` nodes left out,
+    /// since the compression program fills those in from the canopy
+    pub fn proof(&self, leaf_index: u32, canopy_depth: usize) -> Result<Vec<[u8; 32]>> {
+        if leaf_index as usize >= self.leaves.len() {
+            return Err(ClientError::InvalidNote(format!(
+                "Leaf {} not indexed",
+                leaf_index
+            )));
+        }
+        let (_, mut proof) = self.path(leaf_index as usize);
+        proof.truncate(COMPRESSED_TREE_DEPTH - canopy_depth.min(COMPRESSED_TREE_DEPTH));
+
+        Ok(proof)
+    }
+
+    /// Root and full sibling path for `leaf_index`
+    fn path(&self, leaf_index: usize) -> ([u8; 32], Vec<[u8; 32]>) {
+        let mut layer = self.leaves.clone();
+        let mut empty = [0u8; 32];
+        let mut index = leaf_index;
+        let mut proof = Vec::with_capacity(COMPRESSED_TREE_DEPTH);
+
+        for _ in 0..COMPRESSED_TREE_DEPTH {
+            proof.push(layer.get(index ^ 1).copied().unwrap_or(empty));
+
+            layer = layer
+                .chunks(2)
+                .map(|pair| crypto::merkle_hash_pair(&pair[0], pair.get(1).unwrap_or(&empty)))
+                .collect();
+            empty = crypto::merkle_hash_pair(&empty, &empty);
+            index /= 2;
+        }
+
+        (layer.first().copied().unwrap_or(empty), proof)
+    }
+}
+
+pub struct CompressedPoolClient<'a> {
+    client: &'a UntraceClient,
+}
+
+impl<'a> CompressedPoolClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self { client }
+    }
+
+    /// Allocate a concurrent tree and initialize a compressed pool over it.
+    /// Returns the tree address alongside the signature.
+    pub async fn initialize_pool(
+        &self,
+        pool_id: u64,
+        min_pool_size: u64,
+        canopy_depth: usize,
+    ) -> Result<(Signature, Pubkey)> {
+        let (pool_pda, _) = find_compressed_pool_address(&self.client.program_id, pool_id);
+        let merkle_tree = Keypair::new();
+
+        // The tree is too large for a CPI allocation, so it is created up front
+        let space = compressed_tree_account_size(canopy_depth);
+        let lamports = self
+            .client
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(space)
+            .await?;
+        let create_tree = system_instruction::create_account(
+            &self.client.payer.pubkey(),
+            &merkle_tree.pubkey(),
+            lamports,
+            space as u64,
+            &spl_account_compression::id(),
+        );
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeCompressedPool {
+                compressed_pool: pool_pda,
+                merkle_tree: merkle_tree.pubkey(),
+                authority: self.client.payer.pubkey(),
+                compression_program: spl_account_compression::id(),
+                log_wrapper: Noop::id(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeCompressedPool {
+                pool_id,
+                min_pool_size,
+            },
+        );
+
+        let signature = self
+            .client
+            .send_transaction_with_signers(vec![create_tree, instruction], &[&merkle_tree])
+            .await?;
+
+        Ok((signature, merkle_tree.pubkey()))
+    }
+
+    /// Tree address recorded in a compressed pool account
+    pub async fn get_merkle_tree(&self, pool_id: u64) -> Result<Pubkey> {
+        let (pool_pda, _) = find_compressed_pool_address(&self.client.program_id, pool_id);
+        // The tree address never changes once the pool exists
+        let account = self.client.get_account_cached(&pool_pda).await?;
+
+        // discriminator + version + pool_id
+        let offset = 8 + 1 + 8;
+        let bytes: [u8; 32] = account
+            .data
+            .get(offset..offset + 32)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Truncated compressed pool account"))?;
+
+        Ok(Pubkey::new_from_array(bytes))
+    }
+
+    /// Deposit into a compressed pool
+    pub async fn deposit(
+        &self,
+        pool_id: u64,
+        recipient: &Pubkey,
+        amount: u64,
+    ) -> Result<(Signature, [u8; 32], [u8; 32])> {
+        let (commitment, randomness) = self.client.generate_commitment(recipient, amount);
+
+        let (pool_pda, _) = find_compressed_pool_address(&self.client.program_id, pool_id);
+        let merkle_tree = self.get_merkle_tree(pool_id).await?;
+
+        // Encrypt the deposit data
+        let mut plaintext = Vec::new();
+        plaintext.extend_from_slice(&recipient.to_bytes());
+        plaintext.extend_from_slice(&amount.to_le_bytes());
+
+        let nonce = [0u8; 12];
+        let (encrypted_data, _tag) =
+            crypto::encrypt_data(&plaintext, &randomness, &nonce).map_err(|e| anyhow!(e))?;
+
+        let program_id = self.client.program_id;
+        let instruction = self.client.program_instruction(
+            accounts::CompressedDeposit {
+                compressed_pool: pool_pda,
+                merkle_tree,
+                depositor: self.client.payer.pubkey(),
+                compression_program: spl_account_compression::id(),
+                log_wrapper: Noop::id(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::CompressedDeposit {
+                commitment,
+                amount,
+                note_key: crypto::note_key(&recipient.to_bytes(), &randomness),
+                encrypted_data,
+            },
+        );
+
+        let signature = self.client.send_transaction(vec![instruction]).await?;
+
+        Ok((signature, commitment, randomness))
+    }
+
+    /// Withdraw the `amount` lamports deposited under `commitment` from a
+    /// compressed pool, using a synced commitment index
+    pub async fn withdraw(
+        &self,
+        pool_id: u64,
+        commitment: &[u8; 32],
+        secret: &[u8],
+        amount: u64,
+        recipient: &Pubkey,
+        index: &CommitmentIndex,
+    ) -> Result<Signature> {
+        if index.leaf_index(commitment).is_none() {
+            return Err(ClientError::InvalidNote(
+                "Commitment not found in index".to_string(),
+            ));
+        }
+
+        // The program only accepts roots still in the changelog buffer
+        let merkle_tree = self.get_merkle_tree(pool_id).await?;
+        let root = index.root();
+        let tree_account = self.client.rpc_client.get_account(&merkle_tree).await?;
+        if !decode_recent_roots(&tree_account.data)?.contains(&root) {
+            return Err(ClientError::InvalidNote(
+                "Commitment index is stale; sync before withdrawing".to_string(),
+            ));
+        }
+
+        let nullifier = self.client.generate_nullifier(secret, commitment);
+        let (pool_pda, _) = find_compressed_pool_address(&self.client.program_id, pool_id);
+        let (nullifier_account, _) =
+            find_compressed_nullifier_address(&self.client.program_id, pool_id, &nullifier);
+
+        let mut secret_hash = [0u8; 32];
+        secret_hash[..secret.len().min(32)].copy_from_slice(&secret[..secret.len().min(32)]);
+        let public_inputs =
+            crypto::withdraw_inputs(&root, amount, &recipient.to_bytes(), &nullifier);
+        let zk_proof = crypto::generate_zk_proof(&public_inputs, &nullifier, &secret_hash);
+
+        let instruction = self.client.program_instruction(
+            accounts::CompressedWithdraw {
+                compressed_pool: pool_pda,
+                merkle_tree,
+                nullifier_account,
+                recipient: *recipient,
+                withdrawer: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+                verifier: find_verifier_address(
+                    &self.client.program_id,
+                    Circuit::CompressedWithdraw,
+                )
+                .0,
+            },
+            instruction::CompressedWithdraw {
+                nullifier,
+                recipient: *recipient,
+                amount,
+                zk_proof,
+                root,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Replay changelogs of `merkle_tree` newer than the index's last scanned
+    /// transaction. Returns how many changelogs were applied.
+    pub async fn sync_index(
+        &self,
+        merkle_tree: &Pubkey,
+        index: &mut CommitmentIndex,
+    ) -> Result<usize> {
+        let noop_id = Noop::id();
+        let (instructions, newest) = self
+            .client
+            .fetch_inner_instructions(merkle_tree, index.last_signature, &noop_id)
+            .await?;
+
+        let mut applied = 0;
+        for data in instructions {
+            if let Ok(event) = parse_changelog_event(&data) {
+                if event.id == *merkle_tree && event.seq > index.last_seq {
+                    index.apply_changelog(&event)?;
+                    applied += 1;
+                }
+            }
+        }
+
+        if newest.is_some() {
+            index.last_signature = newest;
+        }
+
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    #[test]
+    fn test_index_follows_on_chain_tree() {
+        let tree_id = Pubkey::new_unique();
+        let mut tree = Box::new(CompressedTree::new());
+        tree.initialize().unwrap();
+
+        let mut index = CommitmentIndex::new();
+        for (seq, leaf) in [[1u8; 32], [2u8; 32], [3u8; 32]].into_iter().enumerate() {
+            tree.append(leaf).unwrap();
+
+            // Round-trip through the logged event as an indexer would see it
+            let event: Box<ChangeLogEvent> =
+                (tree.get_change_log(), tree_id, seq as u64 + 1).into();
+            let data = AccountCompressionEvent::ChangeLog(*event)
+                .try_to_vec()
+                .unwrap();
+            index
+                .apply_changelog(&parse_changelog_event(&data).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.leaf_index(&[2u8; 32]), Some(1));
+        assert_eq!(index.root(), tree.get_root());
+    }
+
+    #[test]
+    fn test_proof_omits_canopy_levels() {
+        let mut index = CommitmentIndex::new();
+        for (seq, leaf) in [[1u8; 32], [2u8; 32], [3u8; 32]].iter().enumerate() {
+            let event = ChangeLogEventV1 {
+                id: Pubkey::new_unique(),
+                path: vec![spl_account_compression::state::PathNode::new(*leaf, 0)],
+                seq: seq as u64 + 1,
+                index: seq as u32,
+            };
+            index.apply_changelog(&event).unwrap();
+        }
+
+        let full = index.proof(2, 0).unwrap();
+        assert_eq!(full.len(), COMPRESSED_TREE_DEPTH);
+        assert!(crypto::verify_merkle_proof(
+            &[3u8; 32],
+            &full,
+            &index.root(),
+            2
+        ));
+
+        let trimmed = index.proof(2, DEFAULT_CANOPY_DEPTH).unwrap();
+        assert_eq!(trimmed.len(), COMPRESSED_TREE_DEPTH - DEFAULT_CANOPY_DEPTH);
+        assert_eq!(trimmed[..], full[..trimmed.len()]);
+        assert!(index.proof(3, 0).is_err());
+    }
+}
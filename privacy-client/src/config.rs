@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, transaction::TransactionError};
+
+use crate::bitcoin::BitcoinConfig;
+use crate::bridge_backend::BridgeRoutes;
+use crate::fees::PriorityFeeStrategy;
+use crate::layerzero::LayerZeroConfig;
+use crate::sponsor::FeeSponsor;
+use crate::wormhole::WormholeConfig;
+
+/// How transactions are sent and confirmed
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Commitment used for blockhashes, preflight and confirmation
+    pub commitment: CommitmentConfig,
+    /// Resends after the first attempt before giving up
+    pub max_retries: u32,
+    /// Delay before the first resend; doubled on each further attempt
+    pub retry_backoff: Duration,
+    /// Re-sign with a fresh blockhash when the previous one expired
+    pub refresh_blockhash: bool,
+    /// Skip the RPC node's simulation before sending
+    pub skip_preflight: bool,
+    /// Timeout of each RPC request
+    pub timeout: Duration,
+    /// Compute unit limit requested per transaction; `None` keeps the
+    /// runtime default
+    pub compute_unit_limit: Option<u32>,
+    /// How the compute unit price is chosen
+    pub priority_fee: PriorityFeeStrategy,
+    /// Spread reads round-robin across every RPC URL instead of sending
+    /// them to the primary
+    pub split_reads: bool,
+    /// How long an RPC node that failed is skipped before it is retried
+    pub failover_cooldown: Duration,
+    /// Wormhole deployment carrying bridge messages; `None` disables the
+    /// Wormhole bridge APIs
+    pub wormhole: Option<WormholeConfig>,
+    /// LayerZero deployment, the alternative bridge backend; `None`
+    /// disables the LayerZero APIs
+    pub layerzero: Option<LayerZeroConfig>,
+    /// Base URL of the API serving the guardian set's signatures; `None`
+    /// disables fetching guardian attestations
+    pub guardian_api: Option<String>,
+    /// Which backend carries transfers between each pair of chains
+    pub bridge_routes: BridgeRoutes,
+    /// Bitcoin node and threshold custody releasing BTC exits; `None`
+    /// disables the Bitcoin custody APIs
+    pub bitcoin: Option<BitcoinConfig>,
+    /// Websocket URL of the node subscriptions connect to; `None` derives
+    /// it from the primary RPC URL
+    pub ws_url: Option<String>,
+    /// Sponsor paying the fee and rent of withdrawals and private
+    /// transfers, so an account without SOL can send them; `None` leaves
+    /// them to the payer
+    pub fee_sponsor: Option<FeeSponsor>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(500),
+            refresh_blockhash: true,
+            skip_preflight: false,
+            timeout: Duration::from_secs(30),
+            compute_unit_limit: None,
+            priority_fee: PriorityFeeStrategy::None,
+            split_reads: false,
+            failover_cooldown: Duration::from_secs(30),
+            wormhole: None,
+            layerzero: None,
+            guardian_api: None,
+            bridge_routes: BridgeRoutes::default(),
+            bitcoin: None,
+            ws_url: None,
+            fee_sponsor: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Delay before resend number `attempt` (starting at 1)
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        self.retry_backoff
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+    }
+}
+
+/// Whether a failed send may succeed if retried unchanged: network failures
+/// and unhealthy nodes. Program errors fail the same way on every attempt.
+pub(crate) fn is_transient_error(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::NodeUnhealthy { .. },
+            ..
+        }) => true,
+        _ => is_expired_blockhash(err),
+    }
+}
+
+/// Whether a send failed because its blockhash is no longer valid
+pub(crate) fn is_expired_blockhash(err: &ClientError) -> bool {
+    err.get_transaction_error() == Some(TransactionError::BlockhashNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::InstructionError;
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let config = ClientConfig {
+            retry_backoff: Duration::from_millis(100),
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(config.retry_delay(1), Duration::from_millis(100));
+        assert_eq!(config.retry_delay(2), Duration::from_millis(200));
+        assert_eq!(config.retry_delay(4), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn test_program_errors_are_not_retried() {
+        let expired = ClientError::from(ClientErrorKind::TransactionError(
+            TransactionError::BlockhashNotFound,
+        ));
+        assert!(is_expired_blockhash(&expired));
+        assert!(is_transient_error(&expired));
+
+        let failed = ClientError::from(ClientErrorKind::TransactionError(
+            TransactionError::InstructionError(0, InstructionError::Custom(6000)),
+        ));
+        assert!(!is_transient_error(&failed));
+    }
+}
@@ -0,0 +1,183 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use solana_transaction_status::TransactionStatus;
+
+use crate::error::{ClientError, Result};
+use crate::storage::ClientStorage;
+use crate::{UntraceClient, CONFIRMATION_POLL_INTERVAL};
+
+/// Longest wait between two status polls
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Storage key of the tracked signatures
+const TRACKER_STORAGE_KEY: &str = "confirmation_tracker";
+
+/// Delay before status poll number `attempt` (starting at 0), doubling up
+/// to `MAX_POLL_INTERVAL`
+pub fn poll_delay(attempt: u32) -> Duration {
+    CONFIRMATION_POLL_INTERVAL
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_POLL_INTERVAL)
+}
+
+impl UntraceClient {
+    /// Wait until `signature` reaches `level`, polling with backoff.
+    /// Fails with the transaction's error if it failed, or
+    /// `ConfirmationTimeout` if it is not confirmed within `timeout`.
+    pub async fn await_confirmation(
+        &self,
+        signature: &Signature,
+        level: CommitmentConfig,
+        timeout: Duration,
+    ) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut attempt = 0;
+        loop {
+            let status = self
+                .rpc_client
+                .get_signature_statuses(&[*signature])
+                .await?
+                .value
+                .pop()
+                .flatten();
+            if let Some(status) = status {
+                if let Some(err) = status.err {
+                    return Err(err.into());
+                }
+                if status.satisfies_commitment(level) {
+                    return Ok(());
+                }
+            }
+
+            let delay = poll_delay(attempt);
+            if tokio::time::Instant::now() + delay > deadline {
+                return Err(ClientError::ConfirmationTimeout(*signature));
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Signatures still awaiting confirmation, persisted so tracking resumes
+/// after a restart
+pub struct ConfirmationTracker {
+    storage: Arc<dyn ClientStorage>,
+    pending: Vec<Signature>,
+}
+
+impl ConfirmationTracker {
+    /// Load the signatures tracked in `storage`, if any
+    pub fn load(storage: Arc<dyn ClientStorage>) -> Result<Self> {
+        let pending = match storage.get(TRACKER_STORAGE_KEY)? {
+            Some(bytes) => Vec::<String>::try_from_slice(&bytes)?
+                .iter()
+                .map(|s| Signature::from_str(s))
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| anyhow!("Corrupt confirmation tracker: {}", e))?,
+            None => Vec::new(),
+        };
+
+        Ok(Self { storage, pending })
+    }
+
+    /// Signatures not yet confirmed, oldest first
+    pub fn pending(&self) -> &[Signature] {
+        &self.pending
+    }
+
+    /// Start tracking `signature`, typically right after sending it
+    pub fn track(&mut self, signature: Signature) -> Result<()> {
+        if !self.pending.contains(&signature) {
+            self.pending.push(signature);
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Stop tracking `signature`
+    pub fn untrack(&mut self, signature: &Signature) -> Result<()> {
+        self.pending.retain(|s| s != signature);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let encoded: Vec<String> = self.pending.iter().map(|s| s.to_string()).collect();
+        Ok(self
+            .storage
+            .put(TRACKER_STORAGE_KEY, &encoded.try_to_vec()?)?)
+    }
+
+    /// Wait up to `timeout` in total for every tracked signature to reach
+    /// `level`, returning each outcome. Confirmed and failed signatures stop
+    /// being tracked; those that time out stay tracked for the next call.
+    pub async fn resume(
+        &mut self,
+        client: &UntraceClient,
+        level: CommitmentConfig,
+        timeout: Duration,
+    ) -> Result<Vec<(Signature, Result<()>)>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let pending = self.pending.clone();
+        let mut outcomes = Vec::with_capacity(pending.len());
+        if pending.is_empty() {
+            return Ok(outcomes);
+        }
+
+        // Signatures from before a restart may have left the node's recent
+        // status cache, so check the full history once first
+        let statuses = client
+            .rpc_client
+            .get_signature_statuses_with_history(&pending)
+            .await?
+            .value;
+        for (signature, status) in pending.into_iter().zip(statuses) {
+            let outcome = match status {
+                Some(TransactionStatus { err: Some(err), .. }) => Err(err.into()),
+                Some(status) if status.satisfies_commitment(level) => Ok(()),
+                _ => {
+                    let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                    client
+                        .await_confirmation(&signature, level, remaining)
+                        .await
+                }
+            };
+            if !matches!(outcome, Err(ClientError::ConfirmationTimeout(_))) {
+                self.untrack(&signature)?;
+            }
+            outcomes.push((signature, outcome));
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_tracked_signatures_survive_reload() {
+        let storage: Arc<dyn ClientStorage> = Arc::new(MemoryStorage::new());
+        let mut tracker = ConfirmationTracker::load(storage.clone()).unwrap();
+        assert!(tracker.pending().is_empty());
+
+        let first = Signature::new_unique();
+        let second = Signature::new_unique();
+        tracker.track(first).unwrap();
+        tracker.track(second).unwrap();
+        tracker.track(first).unwrap();
+        tracker.untrack(&second).unwrap();
+
+        let reloaded = ConfirmationTracker::load(storage).unwrap();
+        assert_eq!(reloaded.pending(), &[first]);
+
+        assert_eq!(poll_delay(0), CONFIRMATION_POLL_INTERVAL);
+        assert_eq!(poll_delay(1), CONFIRMATION_POLL_INTERVAL * 2);
+        assert_eq!(poll_delay(30), MAX_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,170 @@
+use untrace_privacy_program::state::{CommitmentAccount, DepositTrackerAccount, NullifierAccount};
+
+use crate::cross_chain::{bridge_fee_bps, SupportedChain, BRIDGE_ACCOUNT_LEN};
+use crate::error::Result;
+use crate::privacy_pool::{
+    find_deposit_tracker_address, find_merkle_tree_address, find_pool_address,
+};
+use crate::UntraceClient;
+
+/// Lamports the runtime charges per transaction signature
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Compute units each instruction may use when no limit is requested
+pub const DEFAULT_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// Most compute units a single transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Operation to price with `UntraceClient::estimate_cost`
+#[derive(Debug, Clone, Copy)]
+pub enum Operation {
+    /// Deposit `amount` lamports into a privacy pool
+    Deposit { pool_id: u64, amount: u64 },
+    /// Withdraw `amount` lamports from a privacy pool, paying a relayer
+    /// `relayer_fee_bps` of it (0 when withdrawing without one)
+    Withdraw {
+        pool_id: u64,
+        amount: u64,
+        relayer_fee_bps: u16,
+    },
+    /// Bridge `amount` lamports to another chain
+    Bridge {
+        source_chain: SupportedChain,
+        dest_chain: SupportedChain,
+        amount: u64,
+    },
+}
+
+/// Itemized cost of an operation, in lamports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Rent locked in the accounts the operation creates
+    pub rent: u64,
+    /// Signature fees
+    pub base_fee: u64,
+    /// Compute unit price times the compute unit limit
+    pub priority_fee: u64,
+    /// Percentage rate the protocol charges, in basis points
+    pub protocol_fee_bps: u16,
+    /// Fee kept by the protocol, including any flat part
+    pub protocol_fee: u64,
+    /// Fee paid to the relayer submitting the transaction
+    pub relayer_fee: u64,
+}
+
+impl CostEstimate {
+    /// Everything the user pays on top of the amount moved
+    pub fn total(&self) -> u64 {
+        self.rent
+            .saturating_add(self.base_fee)
+            .saturating_add(self.priority_fee)
+            .saturating_add(self.protocol_fee)
+            .saturating_add(self.relayer_fee)
+    }
+}
+
+/// `bps` basis points of `amount`, rounded down
+pub fn bps_of(amount: u64, bps: u16) -> u64 {
+    (amount as u128 * bps as u128 / 10_000) as u64
+}
+
+/// Lamports paid for `compute_unit_limit` units at `compute_unit_price`
+/// micro-lamports each, rounded up as the runtime does
+pub fn priority_fee_lamports(compute_unit_price: u64, compute_unit_limit: u32) -> u64 {
+    (compute_unit_price as u128 * compute_unit_limit as u128).div_ceil(1_000_000) as u64
+}
+
+impl UntraceClient {
+    /// Estimate what `operation` will cost under the client's send policy,
+    /// so it can be shown before the user confirms
+    pub async fn estimate_cost(&self, operation: Operation) -> Result<CostEstimate> {
+        let program_id = &self.program_id;
+        let mut estimate = CostEstimate::default();
+        let (new_accounts, signatures, writable) = match operation {
+            Operation::Deposit { pool_id, .. } => {
+                let mut new_accounts = vec![CommitmentAccount::LEN];
+                // The depositor's tracker is created on their first deposit
+                let (tracker, _) =
+                    find_deposit_tracker_address(program_id, pool_id, &self.payer.pubkey());
+                if self
+                    .rpc_client
+                    .get_account_with_commitment(&tracker, self.config.commitment)
+                    .await?
+                    .value
+                    .is_none()
+                {
+                    new_accounts.push(DepositTrackerAccount::LEN);
+                }
+                let writable = vec![
+                    find_pool_address(program_id, pool_id).0,
+                    find_merkle_tree_address(program_id, pool_id).0,
+                ];
+                (new_accounts, 1, writable)
+            }
+            Operation::Withdraw {
+                pool_id,
+                amount,
+                relayer_fee_bps,
+            } => {
+                estimate.relayer_fee = bps_of(amount, relayer_fee_bps);
+                let writable = vec![find_pool_address(program_id, pool_id).0];
+                (vec![NullifierAccount::LEN], 1, writable)
+            }
+            Operation::Bridge {
+                source_chain,
+                dest_chain,
+                amount,
+            } => {
+                estimate.protocol_fee_bps = bridge_fee_bps(source_chain, dest_chain);
+                estimate.protocol_fee =
+                    self.cross_chain()
+                        .estimate_bridge_fee(source_chain, dest_chain, "SOL", amount)?;
+                // Signed by the payer and the new bridge account
+                (vec![BRIDGE_ACCOUNT_LEN], 2, Vec::new())
+            }
+        };
+
+        for len in new_accounts {
+            estimate.rent += self
+                .rpc_client
+                .get_minimum_balance_for_rent_exemption(len)
+                .await?;
+        }
+        estimate.base_fee = signatures * LAMPORTS_PER_SIGNATURE;
+
+        let compute_unit_price = self.compute_unit_price(&writable, &self.config).await?;
+        let compute_unit_limit = self
+            .config
+            .compute_unit_limit
+            .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT);
+        estimate.priority_fee = priority_fee_lamports(compute_unit_price, compute_unit_limit);
+
+        Ok(estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_arithmetic() {
+        assert_eq!(bps_of(1_000_000, 25), 2_500);
+        assert_eq!(bps_of(u64::MAX, 10_000), u64::MAX);
+
+        // 1.5 lamports round up to 2
+        assert_eq!(priority_fee_lamports(3, 500_000), 2);
+        assert_eq!(priority_fee_lamports(0, DEFAULT_COMPUTE_UNIT_LIMIT), 0);
+
+        let estimate = CostEstimate {
+            rent: 1_000,
+            base_fee: 5_000,
+            priority_fee: 200,
+            protocol_fee_bps: 10,
+            protocol_fee: 300,
+            relayer_fee: 400,
+        };
+        assert_eq!(estimate.total(), 6_900);
+    }
+}
@@ -1,15 +1,155 @@
-use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use anchor_lang::{AccountDeserialize, Discriminator};
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::{stream, Stream};
+use serde::Deserialize;
+use solana_client::{
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
+    account::from_account,
+    clock::Clock,
+    ed25519_program,
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::Signature,
+    signature::{Keypair, Signature, Signer},
     system_program,
+    sysvar,
+};
+use untrace_common::{
+    layerzero,
+    wormhole::{self as wormhole_layout, MessageData, POSTED_MESSAGE_MAGIC},
+    BridgeMessage, BridgeProtocol, BridgeRefundMessage, EncryptedTransaction, UntraceError,
+};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{
+        BridgeConfigAccount, CrossChainBridgeAccount, ForeignEmitterAccount,
+        OutboundTransferAccount, BRIDGE_REFUND_TIMEOUT,
+    },
 };
-use untrace_common::crypto;
 
+use crate::address::AddressError;
+use crate::bridge_encryption::{self, BridgePayload, DecryptionKey, EncryptionKey};
+use crate::config::ClientConfig;
+use crate::error::{ClientError, Result};
+use crate::evm::EvmClient;
+use crate::admin::find_admin_config_address;
+use crate::events::find_event_authority_address;
+use crate::cost::Operation;
+use crate::fees::PriorityFeeStrategy;
+use crate::gas_oracle::{
+    native_to_token, BridgeFeeQuote, GasOracle, CLAIM_GAS, EVM_NATIVE_DECIMALS, QUOTE_VALIDITY,
+};
+use crate::token_registry::SOL_DECIMALS;
+use crate::wormhole::{
+    self, find_emitter_address, find_foreign_emitter_address, find_posted_vaa_address,
+    find_wormhole_config_address, Vaa, WormholeConfig, MAX_GUARDIANS, SIGNATURES_PER_TRANSACTION,
+};
 use crate::UntraceClient;
 
-#[derive(Debug, Clone, Copy)]
+/// Ed25519 precompile header: signature count + padding
+const ED25519_HEADER_LEN: usize = 2;
+/// Size of one signature offsets entry in the precompile data
+const ED25519_OFFSETS_LEN: usize = 14;
+
+/// Space the program allocates for each outbound bridge account
+pub const BRIDGE_ACCOUNT_LEN: usize = 8 + 1 + 2 + 2 + 512 + 32 + 12 + 16 + 32 + 8 + 1;
+
+/// Delay between polls of a tracked transfer
+pub const TRACK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Flat part of every bridge fee, in lamports (0.001 SOL)
+pub const BRIDGE_BASE_FEE: u64 = 1_000_000;
+
+/// Derive the PDA of the bridge guardian set
+pub fn find_guardian_set_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"guardian_set"], program_id)
+}
+
+/// Derive the PDA of the bridge vault releasing inbound transfers
+pub fn find_bridge_vault_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"bridge_vault"], program_id)
+}
+
+/// Derive the PDA holding the per-chain outbound volume caps
+pub fn find_bridge_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"bridge_config"], program_id)
+}
+
+/// Derive the PDA recording an outbound transfer posted in `message`
+pub fn find_outbound_transfer_address(program_id: &Pubkey, message: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"outbound_transfer", message.as_ref()], program_id)
+}
+
+/// Derive the PDA numbering the transfers left for the guardian set
+pub fn find_guardian_outbox_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"guardian_outbox"], program_id)
+}
+
+/// Whether the sender may take back `transfer` at unix time `now`, once
+/// the guardian set attests its `refund_message`
+pub fn is_refundable(transfer: &OutboundTransferAccount, now: i64) -> bool {
+    transfer.refunded_at == 0 && now >= transfer.posted_at.saturating_add(BRIDGE_REFUND_TIMEOUT)
+}
+
+/// What the guardian set signs to attest `transfer` expired unredeemed
+pub fn refund_message(transfer: &OutboundTransferAccount) -> BridgeRefundMessage {
+    BridgeRefundMessage {
+        dest_chain: transfer.dest_chain,
+        sequence: transfer.sequence,
+        transfer: transfer.message.to_bytes(),
+        amount: transfer.amount,
+    }
+}
+
+/// Derive the PDA recording that a bridge message was redeemed
+pub fn find_consumed_message_address(program_id: &Pubkey, digest: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"consumed_message", digest], program_id)
+}
+
+/// Build an Ed25519 precompile instruction checking one guardian signature
+/// over a bridge message digest
+pub fn guardian_signature_instruction(
+    guardian: &Pubkey,
+    signature: &[u8; 64],
+    digest: &[u8; 32],
+) -> Instruction {
+    let pubkey_offset = ED25519_HEADER_LEN + ED25519_OFFSETS_LEN;
+    let signature_offset = pubkey_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = Vec::with_capacity(message_offset + digest.len());
+    data.push(1u8); // number of signatures
+    data.push(0u8); // padding
+    for value in [
+        signature_offset as u16,
+        u16::MAX, // signature in this instruction
+        pubkey_offset as u16,
+        u16::MAX, // public key in this instruction
+        message_offset as u16,
+        digest.len() as u16,
+        u16::MAX, // message in this instruction
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&guardian.to_bytes());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(digest);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SupportedChain {
     Ethereum = 1,
     BinanceSmartChain = 2,
@@ -18,79 +158,1067 @@ pub enum SupportedChain {
     Arbitrum = 5,
     Optimism = 6,
     Solana = 7,
+    Base = 8,
+    ArbitrumNova = 9,
+    Sui = 10,
+    Aptos = 11,
+    Bitcoin = 12,
 }
 
 impl SupportedChain {
+    pub const ALL: [SupportedChain; 12] = [
+        SupportedChain::Ethereum,
+        SupportedChain::BinanceSmartChain,
+        SupportedChain::Polygon,
+        SupportedChain::Avalanche,
+        SupportedChain::Arbitrum,
+        SupportedChain::Optimism,
+        SupportedChain::Solana,
+        SupportedChain::Base,
+        SupportedChain::ArbitrumNova,
+        SupportedChain::Sui,
+        SupportedChain::Aptos,
+        SupportedChain::Bitcoin,
+    ];
+
     pub fn to_u16(&self) -> u16 {
         *self as u16
     }
+
+    pub fn from_u16(chain_id: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|chain| chain.to_u16() == chain_id)
+    }
+
+    /// Blocks built on top of a block before it is treated as final:
+    /// about two epochs on Ethereum, and for rollups about as long as their
+    /// batches take to finalize there. Sui and Aptos are final on commit;
+    /// Bitcoin takes the customary six blocks.
+    pub fn confirmation_depth(&self) -> u64 {
+        match self {
+            SupportedChain::Ethereum => 64,
+            SupportedChain::BinanceSmartChain => 15,
+            SupportedChain::Polygon => 128,
+            SupportedChain::Avalanche => 1,
+            SupportedChain::Arbitrum | SupportedChain::ArbitrumNova => 4_800,
+            SupportedChain::Optimism | SupportedChain::Base => 600,
+            SupportedChain::Solana => 32,
+            SupportedChain::Sui | SupportedChain::Aptos => 0,
+            SupportedChain::Bitcoin => 6,
+        }
+    }
+
+    /// Wormhole's ID for this chain, if Wormhole reaches it
+    pub fn wormhole_chain_id(&self) -> Option<u16> {
+        wormhole_layout::to_wormhole_chain_id(self.to_u16())
+    }
+
+    fn require_wormhole_chain_id(&self) -> Result<u16> {
+        self.wormhole_chain_id()
+            .ok_or_else(|| anyhow!("{:?} is not reachable over Wormhole", self).into())
+    }
+
+    pub fn from_wormhole_chain_id(wormhole_chain_id: u16) -> Option<Self> {
+        wormhole_layout::from_wormhole_chain_id(wormhole_chain_id).and_then(Self::from_u16)
+    }
+
+    /// LayerZero's endpoint ID for this chain, if LayerZero reaches it
+    pub fn layerzero_eid(&self) -> Option<u32> {
+        layerzero::to_layerzero_eid(self.to_u16())
+    }
+
+    pub fn from_layerzero_eid(eid: u32) -> Option<Self> {
+        layerzero::from_layerzero_eid(eid).and_then(Self::from_u16)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignedVaaResponse {
+    vaa_bytes: String,
+}
+
+#[derive(Deserialize)]
+struct GuardianAttestationResponse {
+    signatures: Vec<GuardianSignatureEntry>,
+}
+
+#[derive(Deserialize)]
+struct GuardianSignatureEntry {
+    guardian: String,
+    /// Base64 Ed25519 signature over the message digest
+    signature: String,
+}
+
+/// A bridge message with the guardian signatures `claim` needs, as the
+/// guardian backend hands it to `redeem`
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct GuardianAttestation {
+    pub message: BridgeMessage,
+    pub signatures: Vec<(Pubkey, [u8; 64])>,
 }
 
 pub struct CrossChainClient<'a> {
     client: &'a UntraceClient,
+    http: reqwest::Client,
 }
 
 impl<'a> CrossChainClient<'a> {
     pub fn new(client: &'a UntraceClient) -> Self {
-        Self { client }
+        Self {
+            client,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub(crate) fn wormhole(&self) -> Result<&WormholeConfig> {
+        self.client
+            .config
+            .wormhole
+            .as_ref()
+            .ok_or_else(|| anyhow!("Wormhole is not configured for this client").into())
+    }
+
+    /// Address the privacy program posts Wormhole messages as, which other
+    /// chains register to trust its transfers
+    pub fn emitter_address(&self) -> [u8; 32] {
+        find_emitter_address(&self.client.program_id).0.to_bytes()
+    }
+
+    /// Point the program at the configured Wormhole core bridge (the
+    /// protocol admin authority)
+    pub async fn initialize_wormhole(&self) -> Result<Signature> {
+        let core_bridge = self.wormhole()?.core_bridge;
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeWormhole {
+                admin_config: find_admin_config_address(&self.client.program_id).0,
+                wormhole_config: find_wormhole_config_address(&self.client.program_id).0,
+                emitter: find_emitter_address(&self.client.program_id).0,
+                bridge_config: find_bridge_config_address(&self.client.program_id).0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeWormhole { core_bridge },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Create the uncapped bridge config on a deployment whose Wormhole
+    /// config predates bridge limits
+    pub async fn initialize_bridge_config(&self) -> Result<Signature> {
+        let instruction = self.client.program_instruction(
+            accounts::InitializeBridgeConfig {
+                wormhole_config: find_wormhole_config_address(&self.client.program_id).0,
+                bridge_config: find_bridge_config_address(&self.client.program_id).0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeBridgeConfig {},
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Lamports that may still be bridged to `dest_chain` this epoch, or
+    /// `None` when the chain is uncapped
+    pub async fn remaining_capacity(&self, dest_chain: SupportedChain) -> Result<Option<u64>> {
+        let (bridge_config, _) = find_bridge_config_address(&self.client.program_id);
+        let account = self.client.rpc_client.get_account(&bridge_config).await?;
+        let config = BridgeConfigAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(anyhow::Error::from)?;
+        let limit = config
+            .limits
+            .get(dest_chain.to_u16() as usize)
+            .ok_or_else(|| anyhow!("{:?} has no bridge limit slot", dest_chain))?;
+
+        let clock_account = self.client.rpc_client.get_account(&sysvar::clock::id()).await?;
+        let clock: Clock = from_account(&clock_account)
+            .ok_or_else(|| anyhow!("Invalid clock sysvar account"))?;
+        Ok(limit.remaining(clock.epoch))
+    }
+
+    /// Trust `address` as the bridge's Wormhole emitter on `chain` (the
+    /// protocol admin authority). Each chain is registered once; replacing
+    /// its emitter is a timelocked `AdminAction::SetForeignEmitter`.
+    pub async fn register_foreign_emitter(
+        &self,
+        chain: SupportedChain,
+        address: [u8; 32],
+    ) -> Result<Signature> {
+        let wormhole_chain = chain.require_wormhole_chain_id()?;
+
+        let instruction = self.client.program_instruction(
+            accounts::RegisterForeignEmitter {
+                wormhole_config: find_wormhole_config_address(&self.client.program_id).0,
+                admin_config: find_admin_config_address(&self.client.program_id).0,
+                foreign_emitter: find_foreign_emitter_address(
+                    &self.client.program_id,
+                    wormhole_chain,
+                )
+                .0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::RegisterForeignEmitter {
+                chain: wormhole_chain,
+                address,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Fail before paying fees for a transfer of `amount` that the program
+    /// would reject for exceeding `dest_chain`'s epoch cap
+    pub(crate) async fn ensure_capacity(
+        &self,
+        dest_chain: SupportedChain,
+        amount: u64,
+    ) -> Result<()> {
+        if self
+            .remaining_capacity(dest_chain)
+            .await?
+            .is_some_and(|remaining| amount > remaining)
+        {
+            return Err(ClientError::Program(UntraceError::BridgeRateLimitExceeded));
+        }
+        Ok(())
+    }
+
+    /// Lock `amount` lamports and post an outbound transfer to `dest_chain`
+    /// through Wormhole, returning the message as posted. Its sequence is
+    /// what `fetch_vaa` needs once the guardians have signed it.
+    pub async fn post_message(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        let core_bridge = self.wormhole()?.core_bridge;
+        self.ensure_capacity(dest_chain, amount).await?;
+
+        let program_id = self.client.program_id;
+        let (emitter, _) = find_emitter_address(&program_id);
+        let message_account = Keypair::new();
+
+        let instruction = self.client.program_instruction(
+            accounts::PostBridgeMessage {
+                wormhole_config: find_wormhole_config_address(&program_id).0,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                bridge_config: find_bridge_config_address(&program_id).0,
+                wormhole_bridge: wormhole::find_bridge_address(&core_bridge).0,
+                wormhole_message: message_account.pubkey(),
+                outbound_transfer: find_outbound_transfer_address(
+                    &program_id,
+                    &message_account.pubkey(),
+                )
+                .0,
+                emitter,
+                wormhole_sequence: wormhole::find_sequence_address(&core_bridge, &emitter).0,
+                wormhole_fee_collector: wormhole::find_fee_collector_address(&core_bridge).0,
+                sender: self.client.payer.pubkey(),
+                clock: sysvar::clock::id(),
+                rent: sysvar::rent::id(),
+                wormhole_program: core_bridge,
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::PostBridgeMessage {
+                dest_chain: dest_chain.to_u16(),
+                recipient,
+                amount,
+                payload_hash,
+            },
+        );
+
+        let signature = self
+            .client
+            .send_transaction_with_signers(vec![instruction], &[&message_account])
+            .await?;
+
+        // The sequence is only final once the core bridge assigned it
+        let account = self
+            .client
+            .rpc_client
+            .get_account(&message_account.pubkey())
+            .await?;
+        let posted = MessageData::from_account(&account.data, POSTED_MESSAGE_MAGIC)
+            .ok_or_else(|| anyhow!("Invalid posted message {}", message_account.pubkey()))?;
+        let message =
+            BridgeMessage::try_from_slice(&posted.payload).map_err(anyhow::Error::from)?;
+
+        Ok((signature, message))
+    }
+
+    /// Signed VAA of message `sequence` from `emitter_address` on `chain`,
+    /// or `None` while the guardians have not signed it yet
+    pub async fn fetch_vaa(
+        &self,
+        chain: SupportedChain,
+        emitter_address: &[u8; 32],
+        sequence: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        let emitter: String = emitter_address
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let url = format!(
+            "{}/v1/signed_vaa/{}/{}/{}",
+            self.wormhole()?.guardian_rpc.trim_end_matches('/'),
+            chain.require_wormhole_chain_id()?,
+            emitter,
+            sequence
+        );
+
+        let response = self
+            .http
+            .get(url)
+            .timeout(self.client.config.timeout)
+            .send()
+            .await
+            .map_err(anyhow::Error::from)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let signed: SignedVaaResponse = response
+            .error_for_status()
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let vaa = STANDARD
+            .decode(signed.vaa_bytes)
+            .map_err(anyhow::Error::from)?;
+        Ok(Some(vaa))
+    }
+
+    /// Lamports the core bridge charges for each posted message
+    pub async fn wormhole_message_fee(&self) -> Result<u64> {
+        let core_bridge = self.wormhole()?.core_bridge;
+        let bridge = wormhole::find_bridge_address(&core_bridge).0;
+        let account = self.client.rpc_client.get_account(&bridge).await?;
+        wormhole_layout::message_fee(&account.data)
+            .ok_or_else(|| anyhow!("Invalid core bridge account {}", bridge).into())
+    }
+
+    /// Signed VAA carrying `message` in either direction: posted by the
+    /// program's emitter when it leaves Solana, or by the foreign emitter
+    /// registered for its source chain when it arrives
+    pub async fn fetch_message_vaa(&self, message: &BridgeMessage) -> Result<Option<Vec<u8>>> {
+        let source = SupportedChain::from_u16(message.source_chain)
+            .ok_or_else(|| anyhow!("Unknown source chain {}", message.source_chain))?;
+        if source == SupportedChain::Solana {
+            return self
+                .fetch_vaa(source, &self.emitter_address(), message.sequence)
+                .await;
+        }
+
+        let wormhole_chain_id = source.require_wormhole_chain_id()?;
+        let (foreign_emitter, _) =
+            find_foreign_emitter_address(&self.client.program_id, wormhole_chain_id);
+        let account = self.client.rpc_client.get_account(&foreign_emitter).await?;
+        let emitter = ForeignEmitterAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(anyhow::Error::from)?;
+        self.fetch_vaa(source, &emitter.address, message.sequence)
+            .await
+    }
+
+    /// Complete an inbound transfer carried by a signed VAA, first posting
+    /// the VAA to the core bridge if nobody has yet
+    pub async fn redeem(&self, vaa: &[u8]) -> Result<Signature> {
+        let vaa = Vaa::parse(vaa)?;
+        let message = BridgeMessage::try_from_slice(&vaa.payload).map_err(anyhow::Error::from)?;
+        if self.is_message_consumed(&message).await? {
+            return Err(ClientError::Program(UntraceError::MessageAlreadyConsumed));
+        }
+
+        let posted_vaa = self.ensure_vaa_posted(&vaa).await?;
+
+        let program_id = self.client.program_id;
+        let (consumed_message, _) = find_consumed_message_address(&program_id, &message.digest());
+        let instruction = self.client.program_instruction(
+            accounts::RedeemWormholeTransfer {
+                wormhole_config: find_wormhole_config_address(&program_id).0,
+                posted_vaa,
+                foreign_emitter: find_foreign_emitter_address(&program_id, vaa.emitter_chain).0,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                consumed_message,
+                recipient: Pubkey::new_from_array(message.recipient),
+                payer: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::RedeemWormholeTransfer { message },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// The payer's outbound Wormhole transfers, refunded or not
+    pub async fn outbound_transfers(&self) -> Result<Vec<(Pubkey, OutboundTransferAccount)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    0,
+                    &OutboundTransferAccount::DISCRIMINATOR,
+                )),
+                // sender follows the discriminator and version
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    9,
+                    self.client.payer.pubkey().as_ref(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                commitment: Some(self.client.config.commitment),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+
+        let accounts = self
+            .client
+            .rpc_client
+            .get_program_accounts_with_config(&self.client.program_id, config)
+            .await?;
+        Ok(accounts
+            .into_iter()
+            .filter_map(|(address, account)| {
+                OutboundTransferAccount::try_deserialize(&mut account.data.as_slice())
+                    .ok()
+                    .map(|transfer| (address, transfer))
+            })
+            .collect())
+    }
+
+    /// The payer's outbound transfers that went unclaimed past
+    /// `BRIDGE_REFUND_TIMEOUT`, which `refund` takes back once the guardian
+    /// set attests they expired
+    pub async fn refundable_transfers(&self) -> Result<Vec<(Pubkey, OutboundTransferAccount)>> {
+        let clock_account = self.client.rpc_client.get_account(&sysvar::clock::id()).await?;
+        let clock: Clock = from_account(&clock_account)
+            .ok_or_else(|| anyhow!("Invalid clock sysvar account"))?;
+
+        let mut transfers = self.outbound_transfers().await?;
+        transfers.retain(|(_, transfer)| is_refundable(transfer, clock.unix_timestamp));
+        Ok(transfers)
+    }
+
+    /// Take back the lamports locked by an unclaimed outbound transfer,
+    /// using the guardian API's attestation that it expired on its
+    /// destination unredeemed
+    pub async fn refund(&self, outbound_transfer: &Pubkey) -> Result<Signature> {
+        let account = self.client.rpc_client.get_account(outbound_transfer).await?;
+        let transfer = OutboundTransferAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("Invalid outbound transfer account: {}", e))?;
+        let digest = refund_message(&transfer).digest();
+        let signatures = self
+            .fetch_guardian_signatures(&digest)
+            .await?
+            .ok_or_else(|| anyhow!("No guardian attestation that {} expired", outbound_transfer))?;
+
+        // Signature checks must precede the refund in the same transaction
+        let program_id = self.client.program_id;
+        let mut instructions: Vec<Instruction> = signatures
+            .iter()
+            .map(|(guardian, signature)| {
+                guardian_signature_instruction(guardian, signature, &digest)
+            })
+            .collect();
+        instructions.push(self.client.program_instruction(
+            accounts::RefundBridgeTransfer {
+                outbound_transfer: *outbound_transfer,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                sender: self.client.payer.pubkey(),
+                guardian_set: find_guardian_set_address(&program_id).0,
+                instructions: sysvar::instructions::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::RefundBridgeTransfer {},
+        ));
+
+        self.client.send_transaction(instructions).await
+    }
+
+    /// Current stage of the outbound transfer recorded at `transfer_id`,
+    /// correlating its Solana account, its VAA and its claim on
+    /// `destination`
+    pub async fn transfer_state(
+        &self,
+        transfer_id: &Pubkey,
+        destination: &EvmClient,
+    ) -> Result<TransferState> {
+        let account = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(transfer_id, self.client.config.commitment)
+            .await?
+            .value;
+        let Some(account) = account else {
+            return Ok(TransferState::Failed {
+                reason: format!("No outbound transfer at {}", transfer_id),
+            });
+        };
+        let transfer = OutboundTransferAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("Invalid outbound transfer account: {}", e))?;
+        if transfer.refunded_at != 0 {
+            return Ok(TransferState::Failed {
+                reason: "Refunded to the sender".to_string(),
+            });
+        }
+        if transfer.protocol != BridgeProtocol::Wormhole {
+            return Err(anyhow!("Transfer {} was not sent over Wormhole", transfer_id).into());
+        }
+        if destination.chain().to_u16() != transfer.dest_chain {
+            return Err(anyhow!(
+                "Transfer goes to chain {}, not {:?}",
+                transfer.dest_chain,
+                destination.chain()
+            )
+            .into());
+        }
+
+        let vaa = self
+            .fetch_vaa(SupportedChain::Solana, &self.emitter_address(), transfer.sequence)
+            .await?;
+        let Some(vaa) = vaa else {
+            return Ok(TransferState::Initiated);
+        };
+
+        if destination.final_transfer_status(&vaa).await? == BridgeStatus::Completed {
+            return Ok(TransferState::Finalized);
+        }
+        if destination.transfer_status(&vaa).await? == BridgeStatus::Completed {
+            return Ok(TransferState::Redeemed);
+        }
+        Ok(TransferState::Attested { vaa })
+    }
+
+    /// Follow the outbound transfer at `transfer_id` through each stage,
+    /// yielding every state it advances to until it is finalized or fails.
+    /// Errors polling a stage are yielded too, and polling goes on.
+    pub fn track<'b>(
+        &'b self,
+        transfer_id: Pubkey,
+        destination: &'b EvmClient,
+    ) -> impl Stream<Item = Result<TransferState>> + 'b {
+        struct Tracking {
+            last: Option<TransferState>,
+            polled: bool,
+        }
+
+        let start = Some(Tracking {
+            last: None,
+            polled: false,
+        });
+        stream::unfold(start, move |tracking| async move {
+            let mut tracking = tracking?;
+            loop {
+                if tracking.polled {
+                    tokio::time::sleep(TRACK_POLL_INTERVAL).await;
+                }
+                tracking.polled = true;
+
+                match self.transfer_state(&transfer_id, destination).await {
+                    Err(error) => return Some((Err(error), Some(tracking))),
+                    Ok(state) if state.advances(tracking.last.as_ref()) => {
+                        let next = (!state.is_terminal()).then(|| Tracking {
+                            last: Some(state.clone()),
+                            polled: true,
+                        });
+                        return Some((Ok(state), next));
+                    }
+                    Ok(_) => {}
+                }
+            }
+        })
+    }
+
+    /// Lifecycle events of all the payer's outbound transfers, starting
+    /// with the current stage of each existing one and then as they advance.
+    /// Wormhole transfers are followed to their claim on the chains in
+    /// `destinations`, and to their attestation elsewhere; transfers over
+    /// other backends report being initiated and refunded. Polls every
+    /// `TRACK_POLL_INTERVAL` and never ends; polling errors are yielded and
+    /// polling goes on.
+    pub fn subscribe_events<'b>(
+        &'b self,
+        destinations: &'b [EvmClient],
+    ) -> impl Stream<Item = Result<BridgeEvent>> + 'b {
+        struct Subscription {
+            seen: HashMap<Pubkey, TransferState>,
+            pending: VecDeque<Result<BridgeEvent>>,
+            polled: bool,
+        }
+
+        let start = Subscription {
+            seen: HashMap::new(),
+            pending: VecDeque::new(),
+            polled: false,
+        };
+        stream::unfold(start, move |mut subscription| async move {
+            loop {
+                if let Some(event) = subscription.pending.pop_front() {
+                    return Some((event, subscription));
+                }
+                if subscription.polled {
+                    tokio::time::sleep(TRACK_POLL_INTERVAL).await;
+                }
+                subscription.polled = true;
+
+                let transfers = match self.outbound_transfers().await {
+                    Ok(transfers) => transfers,
+                    Err(error) => return Some((Err(error), subscription)),
+                };
+
+                // Outbound transfer accounts are never closed, but one that
+                // vanished cannot advance any more
+                for (transfer, state) in subscription.seen.iter_mut() {
+                    if state.stage() < 2 && !transfers.iter().any(|(id, _)| id == transfer) {
+                        let reason = "Outbound transfer account closed".to_string();
+                        *state = TransferState::Failed {
+                            reason: reason.clone(),
+                        };
+                        subscription.pending.push_back(Ok(BridgeEvent::Failed {
+                            transfer: *transfer,
+                            reason,
+                        }));
+                    }
+                }
+
+                for (transfer, account) in &transfers {
+                    let last = subscription.seen.get(transfer);
+                    if last.is_some_and(|last| last.stage() >= 2) {
+                        continue;
+                    }
+                    match self.observe_transfer(transfer, account, destinations).await {
+                        Err(error) => subscription.pending.push_back(Err(error)),
+                        Ok(state) => {
+                            let events = transfer_events(*transfer, account, last, &state);
+                            subscription.pending.extend(events.into_iter().map(Ok));
+                            if state.advances(last) {
+                                subscription.seen.insert(*transfer, state);
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Stage of `account`, recorded at `transfer`, as far as
+    /// `subscribe_events` follows it
+    async fn observe_transfer(
+        &self,
+        transfer: &Pubkey,
+        account: &OutboundTransferAccount,
+        destinations: &[EvmClient],
+    ) -> Result<TransferState> {
+        if account.refunded_at != 0 {
+            return Ok(TransferState::Failed {
+                reason: "Refunded to the sender".to_string(),
+            });
+        }
+        if account.protocol != BridgeProtocol::Wormhole {
+            return Ok(TransferState::Initiated);
+        }
+        if let Some(destination) = destinations
+            .iter()
+            .find(|destination| destination.chain().to_u16() == account.dest_chain)
+        {
+            return self.transfer_state(transfer, destination).await;
+        }
+
+        let vaa = self
+            .fetch_vaa(SupportedChain::Solana, &self.emitter_address(), account.sequence)
+            .await?;
+        Ok(match vaa {
+            Some(vaa) => TransferState::Attested { vaa },
+            None => TransferState::Initiated,
+        })
     }
 
-    /// Initiate a cross-chain private transfer
+    /// Address of `vaa` as posted to the core bridge, first posting it if
+    /// nobody has yet
+    pub(crate) async fn ensure_vaa_posted(&self, vaa: &Vaa) -> Result<Pubkey> {
+        let core_bridge = self.wormhole()?.core_bridge;
+        let (posted_vaa, _) = find_posted_vaa_address(&core_bridge, &vaa.body_hash());
+        let posted = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&posted_vaa, self.client.config.commitment)
+            .await?
+            .value;
+        if posted.is_none() {
+            self.post_vaa(vaa, &core_bridge).await?;
+        }
+        Ok(posted_vaa)
+    }
+
+    /// Verify a VAA's guardian signatures with the core bridge, in batches,
+    /// then post it
+    async fn post_vaa(&self, vaa: &Vaa, core_bridge: &Pubkey) -> Result<()> {
+        let (guardian_set, _) =
+            wormhole::find_guardian_set_address(core_bridge, vaa.guardian_set_index);
+        let account = self.client.rpc_client.get_account(&guardian_set).await?;
+        let guardian_keys = wormhole::decode_guardian_keys(&account.data)?;
+
+        let payer = self.client.payer.pubkey();
+        let signature_set = Keypair::new();
+        let body_hash = vaa.body_hash();
+
+        // `VerifySignatures` reads the precompile instruction right before
+        // it, so keep compute budget instructions out of these transactions
+        let config = ClientConfig {
+            compute_unit_limit: None,
+            priority_fee: PriorityFeeStrategy::None,
+            ..self.client.config.clone()
+        };
+
+        for batch in vaa.signatures.chunks(SIGNATURES_PER_TRANSACTION) {
+            let mut signers = [-1i8; MAX_GUARDIANS];
+            let mut signatures = Vec::with_capacity(batch.len());
+            for (position, signature) in batch.iter().enumerate() {
+                let index = signature.guardian_index as usize;
+                let key = guardian_keys
+                    .get(index)
+                    .filter(|_| index < MAX_GUARDIANS)
+                    .ok_or_else(|| anyhow!("VAA signed by unknown guardian {}", index))?;
+                signers[index] = position as i8;
+                signatures.push((*key, signature.signature));
+            }
+
+            let instructions = vec![
+                wormhole::guardian_secp256k1_instruction(&signatures, &body_hash, 0),
+                wormhole::verify_signatures_instruction(
+                    core_bridge,
+                    &payer,
+                    vaa.guardian_set_index,
+                    &signature_set.pubkey(),
+                    signers,
+                ),
+            ];
+            self.client
+                .send_transaction_with_config(instructions, &[&signature_set], &config)
+                .await?;
+        }
+
+        let instruction =
+            wormhole::post_vaa_instruction(core_bridge, &payer, &signature_set.pubkey(), vaa)?;
+        self.client.send_transaction(vec![instruction]).await?;
+        Ok(())
+    }
+
+    /// Initiate a cross-chain private transfer of `amount` of `token` (a
+    /// symbol or address in the token registry), in its decimals on
+    /// `source_chain`. `recipient` must be a valid address on `dest_chain`,
+    /// and `recipient_key` the encryption key it published there, which
+    /// alone can decrypt the transfer's recipient and amount.
     pub async fn bridge_transfer(
         &self,
         source_chain: SupportedChain,
         dest_chain: SupportedChain,
         recipient: &str,
+        recipient_key: &EncryptionKey,
         amount: u64,
         token: &str,
     ) -> Result<Signature> {
-        let bridge_account = Pubkey::new_unique();
-
-        // Prepare transfer data
-        let mut transfer_data = Vec::new();
-        transfer_data.extend_from_slice(recipient.as_bytes());
-        transfer_data.extend_from_slice(&amount.to_le_bytes());
-        transfer_data.extend_from_slice(token.as_bytes());
-
-        // Encrypt the transfer data
-        let mut shared_secret = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut shared_secret);
-
-        let mut nonce = [0u8; 12];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut nonce);
-
-        let (encrypted_data, tag) = crypto::encrypt_data(&transfer_data, &shared_secret, &nonce)
-            .map_err(|e| anyhow::anyhow!(e))?;
-
-        // Generate ephemeral public key
-        let mut ephemeral_pubkey = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut ephemeral_pubkey);
-
-        let mut data = vec![4u8]; // Instruction discriminator
-        data.extend_from_slice(&source_chain.to_u16().to_le_bytes());
-        data.extend_from_slice(&dest_chain.to_u16().to_le_bytes());
-        data.extend_from_slice(&(encrypted_data.len() as u32).to_le_bytes());
-        data.extend_from_slice(&encrypted_data);
-        data.extend_from_slice(&ephemeral_pubkey);
-        data.extend_from_slice(&nonce);
-        data.extend_from_slice(&tag);
-
-        let instruction = Instruction {
-            program_id: self.client.program_id,
-            accounts: vec![
-                AccountMeta::new(bridge_account, false),
-                AccountMeta::new(self.client.payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-            data,
+        let recipient = dest_chain
+            .parse_address(recipient)
+            .map_err(|source| ClientError::InvalidAddress {
+                chain: dest_chain,
+                source,
+            })?;
+        if recipient_key.format() != dest_chain.encryption_key_format()
+            || !recipient_key.belongs_to(&recipient)
+        {
+            return Err(ClientError::InvalidAddress {
+                chain: dest_chain,
+                source: AddressError::KeyMismatch,
+            });
+        }
+
+        let (dest_token, dest_amount) =
+            self.client
+                .token_registry
+                .convert(source_chain, dest_chain, token, amount)?;
+
+        // A fresh account per transfer, created by the program
+        let bridge_account = Keypair::new();
+
+        // Transfer data in the destination token and its decimals, readable
+        // by the recipient only
+        let payload = BridgePayload {
+            recipient: recipient.to_string(),
+            amount: dest_amount,
+            token: dest_token
+                .address
+                .clone()
+                .unwrap_or_else(|| dest_token.symbol.clone()),
         };
+        let encrypted = bridge_encryption::encrypt_payload(&payload, recipient_key)?;
+
+        let mut instruction = self.client.program_instruction(
+            accounts::CrossChainTransfer {
+                bridge_account: bridge_account.pubkey(),
+                sender: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::CrossChainTransfer {
+                source_chain: source_chain.to_u16(),
+                dest_chain: dest_chain.to_u16(),
+                encrypted_data: encrypted.ciphertext,
+                ephemeral_pubkey: encrypted.ephemeral_pubkey,
+                nonce: encrypted.nonce,
+                tag: encrypted.tag,
+            },
+        );
+        // Creating the account takes its signature, which the generated
+        // metas do not mark for `init` accounts without seeds
+        instruction.accounts[0].is_signer = true;
+
+        self.client
+            .send_transaction_with_signers(vec![instruction], &[&bridge_account])
+            .await
+    }
+
+    /// Decrypt the transfer data of the outbound bridge account
+    /// `bridge_account` with the recipient's key
+    pub async fn decrypt_bridge_transfer(
+        &self,
+        bridge_account: &Pubkey,
+        key: &DecryptionKey,
+    ) -> Result<BridgePayload> {
+        let account = self.client.rpc_client.get_account(bridge_account).await?;
+        let bridge = CrossChainBridgeAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(anyhow::Error::from)?;
+        bridge_encryption::decrypt_payload(
+            &EncryptedTransaction {
+                ciphertext: bridge.encrypted_data,
+                ephemeral_pubkey: bridge.ephemeral_pubkey,
+                nonce: bridge.nonce,
+                tag: bridge.tag,
+            },
+            key,
+        )
+    }
+
+    /// Initialize the guardian set that attests inbound bridge messages.
+    /// The payer must be the protocol admin authority; later changes are
+    /// queued as `AdminAction::SetGuardianSet`.
+    pub async fn initialize_guardian_set(
+        &self,
+        guardians: &[Pubkey],
+        quorum: u8,
+    ) -> Result<Signature> {
+        let (guardian_set, _) = find_guardian_set_address(&self.client.program_id);
+        let (bridge_vault, _) = find_bridge_vault_address(&self.client.program_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeGuardianSet {
+                guardian_set,
+                bridge_vault,
+                admin_config: find_admin_config_address(&self.client.program_id).0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeGuardianSet {
+                guardians: guardians.to_vec(),
+                quorum,
+            },
+        );
 
         self.client.send_transaction(vec![instruction]).await
     }
 
+    /// Claim an inbound bridge transfer using guardian signatures over the message
+    pub async fn claim(
+        &self,
+        message: &BridgeMessage,
+        guardian_signatures: &[(Pubkey, [u8; 64])],
+    ) -> Result<Signature> {
+        if self.is_message_consumed(message).await? {
+            return Err(ClientError::Program(UntraceError::MessageAlreadyConsumed));
+        }
+
+        let (guardian_set, _) = find_guardian_set_address(&self.client.program_id);
+        let (bridge_vault, _) = find_bridge_vault_address(&self.client.program_id);
+        let digest = message.digest();
+        let (consumed_message, _) =
+            find_consumed_message_address(&self.client.program_id, &digest);
+
+        // Signature checks must precede the claim in the same transaction
+        let mut instructions: Vec<Instruction> = guardian_signatures
+            .iter()
+            .map(|(guardian, signature)| guardian_signature_instruction(guardian, signature, &digest))
+            .collect();
+
+        instructions.push(self.client.program_instruction(
+            accounts::CompleteBridgeTransfer {
+                guardian_set,
+                bridge_vault,
+                consumed_message,
+                recipient: Pubkey::new_from_array(message.recipient),
+                payer: self.client.payer.pubkey(),
+                instructions: sysvar::instructions::id(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&self.client.program_id).0,
+            },
+            instruction::CompleteBridgeTransfer {
+                message: message.clone(),
+            },
+        ));
+
+        self.client.send_transaction(instructions).await
+    }
+
+    /// Lock `amount` lamports and leave an outbound transfer to `dest_chain`
+    /// for the guardian set, returning the message it will sign
+    pub async fn post_guardian_message(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        self.ensure_capacity(dest_chain, amount).await?;
+
+        let program_id = self.client.program_id;
+        let transfer = Keypair::new();
+        let (outbound_transfer, _) =
+            find_outbound_transfer_address(&program_id, &transfer.pubkey());
+        let instruction = self.client.program_instruction(
+            accounts::PostGuardianMessage {
+                guardian_set: find_guardian_set_address(&program_id).0,
+                guardian_outbox: find_guardian_outbox_address(&program_id).0,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                bridge_config: find_bridge_config_address(&program_id).0,
+                transfer: transfer.pubkey(),
+                outbound_transfer,
+                sender: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::PostGuardianMessage {
+                dest_chain: dest_chain.to_u16(),
+                recipient,
+                amount,
+                payload_hash,
+            },
+        );
+
+        let signature = self
+            .client
+            .send_transaction_with_signers(vec![instruction], &[&transfer])
+            .await?;
+
+        // The outbox assigned the sequence on chain
+        let account = self.client.rpc_client.get_account(&outbound_transfer).await?;
+        let posted = OutboundTransferAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(anyhow::Error::from)?;
+        let message = BridgeMessage {
+            source_chain: SupportedChain::Solana.to_u16(),
+            dest_chain: dest_chain.to_u16(),
+            sequence: posted.sequence,
+            recipient,
+            amount,
+            payload_hash,
+        };
+
+        Ok((signature, message))
+    }
+
+    /// Guardian signatures over `message` from the guardian API, or `None`
+    /// while a quorum has not signed it yet
+    pub async fn fetch_guardian_attestation(
+        &self,
+        message: &BridgeMessage,
+    ) -> Result<Option<GuardianAttestation>> {
+        Ok(self
+            .fetch_guardian_signatures(&message.digest())
+            .await?
+            .map(|signatures| GuardianAttestation {
+                message: message.clone(),
+                signatures,
+            }))
+    }
+
+    /// Guardian signatures over `digest` from the guardian API, or `None`
+    /// while a quorum has not signed it yet
+    async fn fetch_guardian_signatures(
+        &self,
+        digest: &[u8; 32],
+    ) -> Result<Option<Vec<(Pubkey, [u8; 64])>>> {
+        let api = self
+            .client
+            .config
+            .guardian_api
+            .as_deref()
+            .ok_or_else(|| anyhow!("No guardian API is configured for this client"))?;
+        let digest: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let url = format!("{}/v1/attestations/{}", api.trim_end_matches('/'), digest);
+
+        let response = self
+            .http
+            .get(url)
+            .timeout(self.client.config.timeout)
+            .send()
+            .await
+            .map_err(anyhow::Error::from)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let signed: GuardianAttestationResponse = response
+            .error_for_status()
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let signatures = signed
+            .signatures
+            .iter()
+            .map(|entry| {
+                let guardian = entry
+                    .guardian
+                    .parse::<Pubkey>()
+                    .map_err(anyhow::Error::from)?;
+                let signature = STANDARD
+                    .decode(&entry.signature)
+                    .map_err(anyhow::Error::from)?
+                    .try_into()
+                    .map_err(|_| anyhow!("Guardian signature is not 64 bytes"))?;
+                Ok((guardian, signature))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(signatures))
+    }
+
+    /// Check whether a bridge message has already been redeemed on Solana
+    pub async fn is_message_consumed(&self, message: &BridgeMessage) -> Result<bool> {
+        let (consumed_message, _) =
+            find_consumed_message_address(&self.client.program_id, &message.digest());
+
+        let account = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&consumed_message, self.client.rpc_client.commitment())
+            .await?
+            .value;
+
+        Ok(account.is_some_and(|a| !a.data.is_empty()))
+    }
+
     /// Query bridge transfer status
     pub async fn get_bridge_status(&self, bridge_account: &Pubkey) -> Result<BridgeStatus> {
-        let account = self.client.rpc_client.get_account(bridge_account)?;
+        let account = self.client.rpc_client.get_account(bridge_account).await?;
 
         if account.data.is_empty() {
             return Ok(BridgeStatus::NotFound);
@@ -110,26 +1238,208 @@ impl<'a> CrossChainClient<'a> {
         }
     }
 
-    /// Estimate bridge fees
+    /// Estimate bridge fees for `amount` of `token`, in its decimals on
+    /// `source_chain`
     pub fn estimate_bridge_fee(
         &self,
         source_chain: SupportedChain,
         dest_chain: SupportedChain,
+        token: &str,
         amount: u64,
-    ) -> u64 {
+    ) -> Result<u64> {
+        let source_token = self
+            .client
+            .token_registry
+            .get(source_chain, token)
+            .ok_or_else(|| anyhow!("Unknown token {} on {:?}", token, source_chain))?;
+
         // Base fee + percentage
-        let base_fee = 1_000_000; // 0.001 SOL
         let percentage_fee = amount / 1000; // 0.1%
+        let chain_multiplier = bridge_fee_bps(source_chain, dest_chain) as u64 / 10;
+        let base_fee = base_fee_in_decimals(source_token.decimals);
+
+        Ok(base_fee.saturating_add(percentage_fee * chain_multiplier))
+    }
 
-        // Chain-specific multipliers
-        let chain_multiplier = match (source_chain, dest_chain) {
-            (SupportedChain::Solana, _) | (_, SupportedChain::Solana) => 1,
-            (SupportedChain::Ethereum, _) | (_, SupportedChain::Ethereum) => 3,
-            _ => 2,
+    /// Quote the full cost of bridging `amount` of `token` with live gas
+    /// prices from `oracle`: the protocol fee, the Solana transaction fee,
+    /// and the destination claim in both wei and source token units. Good
+    /// for `QUOTE_VALIDITY`.
+    pub async fn quote_bridge_fee(
+        &self,
+        source_chain: SupportedChain,
+        dest_chain: SupportedChain,
+        token: &str,
+        amount: u64,
+        oracle: &dyn GasOracle,
+    ) -> Result<BridgeFeeQuote> {
+        if dest_chain.evm_chain_id().is_none() {
+            return Err(anyhow!("Fee quotes cover EVM destinations, not {:?}", dest_chain).into());
+        }
+        let source_token = self
+            .client
+            .token_registry
+            .get(source_chain, token)
+            .ok_or_else(|| anyhow!("Unknown token {} on {:?}", token, source_chain))?;
+
+        let protocol_fee = self.estimate_bridge_fee(source_chain, dest_chain, token, amount)?;
+        let solana_fee = if source_chain == SupportedChain::Solana {
+            let cost = self
+                .client
+                .estimate_cost(Operation::Bridge {
+                    source_chain,
+                    dest_chain,
+                    amount,
+                })
+                .await?;
+            cost.base_fee.saturating_add(cost.priority_fee)
+        } else {
+            0
         };
 
-        base_fee + (percentage_fee * chain_multiplier)
+        let dest_gas_price = oracle.gas_price(dest_chain).await?;
+        let dest_claim_cost = dest_gas_price.saturating_mul(CLAIM_GAS as u128);
+        let native_price = oracle.native_price(dest_chain, source_token).await?;
+        let dest_claim_cost_in_token =
+            native_to_token(dest_claim_cost, EVM_NATIVE_DECIMALS, native_price)
+                .min(u64::MAX as u128) as u64;
+
+        Ok(BridgeFeeQuote {
+            source_chain,
+            dest_chain,
+            token: source_token.symbol.clone(),
+            protocol_fee,
+            solana_fee,
+            dest_gas_price,
+            dest_claim_cost,
+            dest_claim_cost_in_token,
+            expires_at: SystemTime::now() + QUOTE_VALIDITY,
+        })
+    }
+}
+
+/// Percentage part of the bridge fee between two chains, in basis points
+pub fn bridge_fee_bps(source_chain: SupportedChain, dest_chain: SupportedChain) -> u16 {
+    // 0.1% times a chain-specific multiplier
+    let chain_multiplier = match (source_chain, dest_chain) {
+        (SupportedChain::Solana, _) | (_, SupportedChain::Solana) => 1,
+        (SupportedChain::Ethereum, _) | (_, SupportedChain::Ethereum) => 3,
+        _ => 2,
+    };
+    10 * chain_multiplier
+}
+
+/// `BRIDGE_BASE_FEE` for a token with `decimals`, charging the same
+/// fraction of a whole token as of a SOL, rounded up
+fn base_fee_in_decimals(decimals: u8) -> u64 {
+    let fee = BRIDGE_BASE_FEE as u128;
+    let scaled = if decimals >= SOL_DECIMALS {
+        fee.saturating_mul(10u128.saturating_pow((decimals - SOL_DECIMALS) as u32))
+    } else {
+        fee.div_ceil(10u128.pow((SOL_DECIMALS - decimals) as u32))
+    };
+    scaled.min(u64::MAX as u128) as u64
+}
+
+/// Stage of an outbound Wormhole transfer, from Solana to its claim
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    /// Locked on Solana; the guardians have not signed the message yet
+    Initiated,
+    /// Signed by the guardians, ready to claim on the destination
+    Attested { vaa: Vec<u8> },
+    /// Claimed on the destination, in a block that is not final yet
+    Redeemed,
+    /// Claimed on the destination at least its confirmation depth ago
+    Finalized,
+    /// Refunded on Solana, or no such transfer
+    Failed { reason: String },
+}
+
+impl TransferState {
+    fn stage(&self) -> u8 {
+        match self {
+            Self::Initiated => 0,
+            Self::Attested { .. } => 1,
+            Self::Redeemed => 2,
+            Self::Finalized | Self::Failed { .. } => 3,
+        }
     }
+
+    pub fn is_terminal(&self) -> bool {
+        self.stage() == 3
+    }
+
+    /// Whether this is a later stage than `last`, so trackers never report
+    /// a transfer going back, say when one RPC node lags another
+    pub fn advances(&self, last: Option<&TransferState>) -> bool {
+        last.is_none_or(|last| self.stage() > last.stage())
+    }
+}
+
+/// Lifecycle event of one of the payer's outbound transfers, naming it by
+/// its `OutboundTransferAccount` address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeEvent {
+    /// Locked on Solana and handed to `protocol`
+    Initiated {
+        transfer: Pubkey,
+        dest_chain: u16,
+        amount: u64,
+        protocol: BridgeProtocol,
+    },
+    /// Signed by the Wormhole guardians, ready to claim on the destination
+    Attested { transfer: Pubkey, vaa: Vec<u8> },
+    /// Claimed on the destination
+    Redeemed { transfer: Pubkey },
+    /// Can no longer complete, short of a refund
+    Failed { transfer: Pubkey, reason: String },
+    /// Locked lamports returned to the sender
+    Refunded { transfer: Pubkey, amount: u64 },
+}
+
+/// Events taking the transfer recorded in `account` at `transfer` from
+/// the stage last reported, if any, to `state`
+fn transfer_events(
+    transfer: Pubkey,
+    account: &OutboundTransferAccount,
+    last: Option<&TransferState>,
+    state: &TransferState,
+) -> Vec<BridgeEvent> {
+    if !state.advances(last) {
+        return Vec::new();
+    }
+
+    let mut events = Vec::new();
+    if last.is_none() {
+        events.push(BridgeEvent::Initiated {
+            transfer,
+            dest_chain: account.dest_chain,
+            amount: account.amount,
+            protocol: account.protocol,
+        });
+    }
+    match state {
+        TransferState::Initiated => {}
+        TransferState::Attested { vaa } => events.push(BridgeEvent::Attested {
+            transfer,
+            vaa: vaa.clone(),
+        }),
+        TransferState::Redeemed | TransferState::Finalized => {
+            events.push(BridgeEvent::Redeemed { transfer })
+        }
+        TransferState::Failed { .. } if account.refunded_at != 0 => {
+            events.push(BridgeEvent::Refunded {
+                transfer,
+                amount: account.amount,
+            })
+        }
+        TransferState::Failed { reason } => events.push(BridgeEvent::Failed {
+            transfer,
+            reason: reason.clone(),
+        }),
+    }
+    events
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -140,3 +1450,149 @@ pub enum BridgeStatus {
     Failed,
     Unknown,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        feature_set::FeatureSet,
+        signature::{Keypair, Signer},
+    };
+    use untrace_privacy_program::state::OutboundLimit;
+
+    #[test]
+    fn test_refund_after_timeout() {
+        let mut transfer = OutboundTransferAccount {
+            version: 1,
+            sender: Pubkey::new_unique(),
+            message: Pubkey::new_unique(),
+            dest_chain: SupportedChain::Ethereum.to_u16(),
+            sequence: 3,
+            amount: 1_000_000,
+            posted_at: 1_700_000_000,
+            refunded_at: 0,
+            protocol: BridgeProtocol::Wormhole,
+            bump: 255,
+        };
+        let deadline = transfer.posted_at + BRIDGE_REFUND_TIMEOUT;
+
+        assert!(!is_refundable(&transfer, deadline - 1));
+        assert!(is_refundable(&transfer, deadline));
+        transfer.refunded_at = deadline;
+        assert!(!is_refundable(&transfer, deadline + 1));
+
+        // Guardians attest each transfer, not each sequence number
+        let other = OutboundTransferAccount {
+            message: Pubkey::new_unique(),
+            ..transfer.clone()
+        };
+        assert_ne!(
+            refund_message(&transfer).digest(),
+            refund_message(&other).digest()
+        );
+    }
+
+    #[test]
+    fn test_outbound_limit_resets_each_epoch() {
+        let mut limit = OutboundLimit::default();
+        assert_eq!(limit.remaining(3), None);
+        assert!(limit.record(3, u64::MAX));
+
+        limit = OutboundLimit {
+            epoch_cap: 100,
+            ..Default::default()
+        };
+        assert!(limit.record(3, 60));
+        assert_eq!(limit.remaining(3), Some(40));
+        assert!(!limit.record(3, 41));
+        assert!(limit.record(3, 40));
+        assert_eq!(limit.remaining(3), Some(0));
+        assert_eq!(limit.remaining(4), Some(100));
+        assert!(limit.record(4, 100));
+    }
+
+    #[test]
+    fn test_transfer_states_only_advance() {
+        let attested = TransferState::Attested { vaa: vec![1] };
+        assert!(TransferState::Initiated.advances(None));
+        assert!(attested.advances(Some(&TransferState::Initiated)));
+        assert!(!TransferState::Initiated.advances(Some(&attested)));
+        assert!(!attested.advances(Some(&attested)));
+        assert!(TransferState::Finalized.advances(Some(&attested)));
+        assert!(TransferState::Finalized.is_terminal());
+        assert!(!TransferState::Redeemed.is_terminal());
+    }
+
+    #[test]
+    fn test_transfer_events_report_each_stage_once() {
+        let transfer = Pubkey::new_unique();
+        let mut account = OutboundTransferAccount {
+            version: 1,
+            sender: Pubkey::new_unique(),
+            message: Pubkey::new_unique(),
+            dest_chain: SupportedChain::Base.to_u16(),
+            sequence: 9,
+            amount: 5_000,
+            posted_at: 1_700_000_000,
+            refunded_at: 0,
+            protocol: BridgeProtocol::Wormhole,
+            bump: 255,
+        };
+        let attested = TransferState::Attested { vaa: vec![1] };
+
+        let events = transfer_events(transfer, &account, None, &attested);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], BridgeEvent::Initiated { amount: 5_000, .. }));
+        assert_eq!(
+            events[1],
+            BridgeEvent::Attested {
+                transfer,
+                vaa: vec![1]
+            }
+        );
+        assert!(transfer_events(transfer, &account, Some(&attested), &attested).is_empty());
+        assert_eq!(
+            transfer_events(transfer, &account, Some(&attested), &TransferState::Finalized),
+            vec![BridgeEvent::Redeemed { transfer }]
+        );
+
+        account.refunded_at = account.posted_at + BRIDGE_REFUND_TIMEOUT;
+        let refunded = TransferState::Failed {
+            reason: "Refunded to the sender".to_string(),
+        };
+        assert_eq!(
+            transfer_events(transfer, &account, Some(&TransferState::Initiated), &refunded),
+            vec![BridgeEvent::Refunded {
+                transfer,
+                amount: 5_000
+            }]
+        );
+    }
+
+    #[test]
+    fn test_guardian_signature_instruction_verifies() {
+        let guardian = Keypair::new();
+        let message = BridgeMessage {
+            source_chain: SupportedChain::Ethereum.to_u16(),
+            dest_chain: SupportedChain::Solana.to_u16(),
+            sequence: 42,
+            recipient: Pubkey::new_unique().to_bytes(),
+            amount: 1_000_000,
+            payload_hash: [9u8; 32],
+        };
+        let digest = message.digest();
+
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(guardian.sign_message(&digest).as_ref());
+
+        let instruction = guardian_signature_instruction(&guardian.pubkey(), &signature, &digest);
+        assert_eq!(instruction.program_id, ed25519_program::id());
+
+        let result = solana_sdk::ed25519_instruction::verify(
+            &instruction.data,
+            &[&instruction.data],
+            &FeatureSet::all_enabled(),
+        );
+        assert!(result.is_ok());
+    }
+}
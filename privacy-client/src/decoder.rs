@@ -0,0 +1,131 @@
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use solana_sdk::pubkey::Pubkey;
+use untrace_privacy_program::instruction;
+
+/// Privacy program instruction that moves funds, with what it reveals on
+/// chain, decoded to show before it is signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramInstruction {
+    /// Lamports moved from `depositor` into a privacy pool
+    Deposit {
+        depositor: Pubkey,
+        amount: u64,
+    },
+    /// A pool's denomination paid out to `recipient`
+    Withdraw {
+        recipient: Pubkey,
+    },
+    ShieldedDeposit {
+        depositor: Pubkey,
+        asset_id: Pubkey,
+        amount: u64,
+    },
+    ShieldedWithdraw {
+        recipient: Pubkey,
+        asset_id: Pubkey,
+        amount: u64,
+    },
+    /// Shielded notes spent into new ones; amounts and recipient stay
+    /// encrypted
+    PrivateTransfer {
+        asset_id: Pubkey,
+        privacy_level: u8,
+    },
+    CompressedDeposit,
+    CompressedWithdraw {
+        recipient: Pubkey,
+    },
+    /// Encrypted transfer out to the Wormhole chain `dest_chain`
+    CrossChainTransfer {
+        dest_chain: u16,
+    },
+}
+
+impl ProgramInstruction {
+    /// Decode an instruction to the privacy program from its `accounts`
+    /// and `data`, or `None` if it moves no funds or is malformed
+    pub fn decode(accounts: &[Pubkey], data: &[u8]) -> Option<Self> {
+        let account = |index: usize| accounts.get(index).copied();
+
+        // Account positions follow the program's account structs
+        if let Some(deposit) = args::<instruction::Deposit>(data) {
+            return Some(Self::Deposit {
+                depositor: account(5)?,
+                amount: deposit.amount,
+            });
+        }
+        if let Some(withdraw) = args::<instruction::Withdraw>(data) {
+            return Some(Self::Withdraw {
+                recipient: withdraw.recipient,
+            });
+        }
+        if let Some(deposit) = args::<instruction::ShieldedDeposit>(data) {
+            return Some(Self::ShieldedDeposit {
+                depositor: account(4)?,
+                asset_id: deposit.asset_id,
+                amount: deposit.amount,
+            });
+        }
+        if let Some(withdraw) = args::<instruction::ShieldedWithdraw>(data) {
+            return Some(Self::ShieldedWithdraw {
+                recipient: account(4)?,
+                asset_id: withdraw.asset_id,
+                amount: withdraw.amount,
+            });
+        }
+        if let Some(transfer) = args::<instruction::PrivateTransfer>(data) {
+            return Some(Self::PrivateTransfer {
+                asset_id: transfer.args.asset_id,
+                privacy_level: transfer.args.privacy_level,
+            });
+        }
+        if args::<instruction::CompressedDeposit>(data).is_some() {
+            return Some(Self::CompressedDeposit);
+        }
+        if let Some(withdraw) = args::<instruction::CompressedWithdraw>(data) {
+            return Some(Self::CompressedWithdraw {
+                recipient: withdraw.recipient,
+            });
+        }
+        args::<instruction::CrossChainTransfer>(data).map(|transfer| Self::CrossChainTransfer {
+            dest_chain: transfer.dest_chain,
+        })
+    }
+}
+
+fn args<T: Discriminator + AnchorDeserialize>(data: &[u8]) -> Option<T> {
+    let mut args = data.strip_prefix(&T::DISCRIMINATOR)?;
+    T::deserialize(&mut args).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::InstructionData;
+
+    #[test]
+    fn test_decode_program_instruction() {
+        let accounts: Vec<Pubkey> = (0..8).map(|_| Pubkey::new_unique()).collect();
+        let deposit = instruction::Deposit {
+            commitment: [1u8; 32],
+            amount: 500,
+            note_key: [4u8; 32],
+            encrypted_data: vec![2, 3],
+        };
+        assert_eq!(
+            ProgramInstruction::decode(&accounts, &deposit.data()),
+            Some(ProgramInstruction::Deposit {
+                depositor: accounts[5],
+                amount: 500,
+            })
+        );
+        // The depositor's account is missing
+        assert_eq!(
+            ProgramInstruction::decode(&accounts[..3], &deposit.data()),
+            None
+        );
+
+        let migrate = instruction::MigrateAccount {};
+        assert_eq!(ProgramInstruction::decode(&accounts, &migrate.data()), None);
+    }
+}
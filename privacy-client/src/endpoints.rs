@@ -0,0 +1,214 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use solana_client::{
+    client_error::{ClientError, Result},
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+
+use crate::config::{self, ClientConfig};
+
+/// One RPC node behind an `RpcEndpoints` set
+struct Endpoint {
+    client: RpcClient,
+    /// Set while the node is skipped after failing
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn is_healthy(&self, now: Instant) -> bool {
+        match *self.unhealthy_until.lock().unwrap() {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+
+    fn set_healthy(&self, healthy: bool, cooldown: Duration) {
+        *self.unhealthy_until.lock().unwrap() = if healthy {
+            None
+        } else {
+            Some(Instant::now() + cooldown)
+        };
+    }
+}
+
+struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    split_reads: bool,
+    cooldown: Duration,
+    next_read: AtomicUsize,
+}
+
+/// RPC transport over several nodes. Requests that fail with a network
+/// error, timeout or unhealthy node are retried on the next node, which is
+/// then skipped until its cooldown passes. The first URL is the primary:
+/// transactions are always sent there first, and so is every other request
+/// unless reads are split round-robin across all nodes.
+#[derive(Clone)]
+pub struct RpcEndpoints {
+    inner: Arc<Endpoints>,
+}
+
+impl RpcEndpoints {
+    /// Connect to `rpc_urls`, primary first. Panics if `rpc_urls` is empty.
+    pub fn new(rpc_urls: &[&str], config: &ClientConfig) -> Self {
+        assert!(!rpc_urls.is_empty(), "At least one RPC URL is required");
+
+        let endpoints = rpc_urls
+            .iter()
+            .map(|url| Endpoint {
+                client: RpcClient::new_with_timeout_and_commitment(
+                    url.to_string(),
+                    config.timeout,
+                    config.commitment,
+                ),
+                unhealthy_until: Mutex::new(None),
+            })
+            .collect();
+
+        Self {
+            inner: Arc::new(Endpoints {
+                endpoints,
+                split_reads: config.split_reads,
+                cooldown: config.failover_cooldown,
+                next_read: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// URLs of every node, primary first
+    pub fn urls(&self) -> Vec<String> {
+        self.inner
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.client.url())
+            .collect()
+    }
+
+    /// URLs of the nodes currently in rotation
+    pub fn healthy_urls(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.inner
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_healthy(now))
+            .map(|endpoint| endpoint.client.url())
+            .collect()
+    }
+
+    /// Ask every node for its health, taking failing nodes out of rotation
+    /// and returning recovered ones to it
+    pub async fn check_health(&self) {
+        for endpoint in &self.inner.endpoints {
+            let healthy = endpoint.client.get_health().await.is_ok();
+            endpoint.set_healthy(healthy, self.inner.cooldown);
+        }
+    }
+
+    /// Order in which nodes are tried for a request: healthy nodes from the
+    /// first choice onwards, then unhealthy ones as a last resort
+    fn candidates(&self, request: RpcRequest) -> Vec<usize> {
+        let count = self.inner.endpoints.len();
+        let first = if self.inner.split_reads && request != RpcRequest::SendTransaction {
+            self.inner.next_read.fetch_add(1, Ordering::Relaxed) % count
+        } else {
+            0
+        };
+
+        let now = Instant::now();
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..count)
+            .map(|offset| (first + offset) % count)
+            .partition(|&index| self.inner.endpoints[index].is_healthy(now));
+        healthy.extend(unhealthy);
+        healthy
+    }
+}
+
+/// Failures of the node rather than the request, worth retrying elsewhere
+fn is_endpoint_failure(err: &ClientError) -> bool {
+    config::is_transient_error(err) && !config::is_expired_blockhash(err)
+}
+
+#[async_trait]
+impl RpcSender for RpcEndpoints {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let mut last_err = None;
+        for index in self.candidates(request) {
+            let endpoint = &self.inner.endpoints[index];
+            match endpoint.client.send(request, params.clone()).await {
+                Err(err) if is_endpoint_failure(&err) => {
+                    endpoint.set_healthy(false, self.inner.cooldown);
+                    last_err = Some(err);
+                }
+                result => return result,
+            }
+        }
+        Err(last_err.expect("RpcEndpoints always has an endpoint"))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.client.get_transport_stats())
+            .fold(RpcTransportStats::default(), |total, stats| {
+                RpcTransportStats {
+                    request_count: total.request_count + stats.request_count,
+                    elapsed_time: total.elapsed_time + stats.elapsed_time,
+                    rate_limited_time: total.rate_limited_time + stats.rate_limited_time,
+                }
+            })
+    }
+
+    fn url(&self) -> String {
+        self.inner.endpoints[0].client.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover_order() {
+        let urls = ["http://a:8899", "http://b:8899", "http://c:8899"];
+        let endpoints = RpcEndpoints::new(&urls, &ClientConfig::default());
+
+        // Without a read split everything starts at the primary
+        assert_eq!(
+            endpoints.candidates(RpcRequest::GetAccountInfo),
+            vec![0, 1, 2]
+        );
+
+        // A failed node drops to the back until its cooldown passes
+        endpoints.inner.endpoints[0].set_healthy(false, Duration::from_secs(60));
+        assert_eq!(
+            endpoints.candidates(RpcRequest::SendTransaction),
+            vec![1, 2, 0]
+        );
+        assert_eq!(endpoints.healthy_urls().len(), 2);
+
+        let split = RpcEndpoints::new(
+            &urls,
+            &ClientConfig {
+                split_reads: true,
+                ..ClientConfig::default()
+            },
+        );
+        assert_eq!(split.candidates(RpcRequest::GetAccountInfo), vec![0, 1, 2]);
+        assert_eq!(split.candidates(RpcRequest::GetAccountInfo), vec![1, 2, 0]);
+        assert_eq!(split.candidates(RpcRequest::SendTransaction), vec![0, 1, 2]);
+    }
+}
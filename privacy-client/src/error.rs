@@ -0,0 +1,156 @@
+use solana_client::client_error::ClientError as RpcClientError;
+use solana_sdk::{
+    instruction::InstructionError, signature::Signature, transaction::TransactionError,
+};
+use thiserror::Error;
+use untrace_common::UntraceError;
+
+use crate::address::AddressError;
+use crate::cross_chain::SupportedChain;
+
+/// Result of a privacy client operation
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
+
+/// Errors returned by the privacy client
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The RPC node could not be reached or rejected the request
+    #[error("RPC error: {0}")]
+    Rpc(Box<RpcClientError>),
+
+    /// The transaction's blockhash expired before it landed
+    #[error("Blockhash expired before the transaction confirmed")]
+    BlockhashExpired,
+
+    /// Sent but not seen confirmed within the retry budget
+    #[error("Transaction not confirmed after {attempts} attempts")]
+    NotConfirmed { attempts: u32 },
+
+    /// A tracked transaction did not confirm within the wait allowed
+    #[error("Timed out waiting for {0} to confirm")]
+    ConfirmationTimeout(Signature),
+
+    /// A proof could not be built, or the program rejected it
+    #[error("Proof generation failed: {0}")]
+    ProofGeneration(String),
+
+    /// Deposits, transfers and bridge claims are paused protocol-wide
+    #[error("Protocol is paused")]
+    PoolPaused,
+
+    /// The pool's anonymity set is below its withdrawal minimum
+    #[error("Insufficient pool size")]
+    InsufficientPoolSize,
+
+    /// The note or commitment has already been spent
+    #[error("Nullifier already spent")]
+    NullifierSpent,
+
+    /// A note, commitment or proof does not match chain state
+    #[error("Invalid note: {0}")]
+    InvalidNote(String),
+
+    /// A recipient address is not valid on its chain
+    #[error("Invalid {chain:?} address: {source}")]
+    InvalidAddress {
+        chain: SupportedChain,
+        source: AddressError,
+    },
+
+    /// Any other error code returned by the privacy program
+    #[error("Program error: {0}")]
+    Program(UntraceError),
+
+    /// The transaction failed for a reason outside the privacy program
+    #[error("Transaction failed: {0}")]
+    Transaction(TransactionError),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<UntraceError> for ClientError {
+    fn from(error: UntraceError) -> Self {
+        match error {
+            UntraceError::ProtocolPaused => Self::PoolPaused,
+            UntraceError::InsufficientPoolSize => Self::InsufficientPoolSize,
+            UntraceError::NullifierUsed => Self::NullifierSpent,
+            UntraceError::InvalidZKProof => Self::ProofGeneration(error.to_string()),
+            UntraceError::UnknownMerkleRoot | UntraceError::InvalidMerkleProof => {
+                Self::InvalidNote(error.to_string())
+            }
+            _ => Self::Program(error),
+        }
+    }
+}
+
+impl From<TransactionError> for ClientError {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::BlockhashNotFound => Self::BlockhashExpired,
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                match UntraceError::from_code(code) {
+                    Some(program_error) => program_error.into(),
+                    None => Self::Transaction(error),
+                }
+            }
+            _ => Self::Transaction(error),
+        }
+    }
+}
+
+impl From<RpcClientError> for ClientError {
+    fn from(error: RpcClientError) -> Self {
+        // Failed transactions surface as RPC errors; decode them first
+        match error.get_transaction_error() {
+            Some(transaction_error) => transaction_error.into(),
+            None => Self::Rpc(Box::new(error)),
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Other(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::client_error::ClientErrorKind;
+
+    #[test]
+    fn test_program_errors_are_typed() {
+        let failed = |code: u32| {
+            RpcClientError::from(ClientErrorKind::TransactionError(
+                TransactionError::InstructionError(0, InstructionError::Custom(code)),
+            ))
+        };
+
+        assert!(matches!(
+            ClientError::from(failed(UntraceError::ProtocolPaused as u32)),
+            ClientError::PoolPaused
+        ));
+        assert!(matches!(
+            ClientError::from(failed(UntraceError::NullifierUsed as u32)),
+            ClientError::NullifierSpent
+        ));
+        assert!(matches!(
+            ClientError::from(failed(UntraceError::Unauthorized as u32)),
+            ClientError::Program(UntraceError::Unauthorized)
+        ));
+        assert!(matches!(
+            ClientError::from(failed(u32::MAX)),
+            ClientError::Transaction(_)
+        ));
+
+        let expired = RpcClientError::from(ClientErrorKind::TransactionError(
+            TransactionError::BlockhashNotFound,
+        ));
+        assert!(matches!(
+            ClientError::from(expired),
+            ClientError::BlockhashExpired
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::{hash::hash, pubkey::Pubkey};
+use solana_transaction_status::{UiReturnDataEncoding, UiTransactionReturnData};
+
+/// Derive the PDA the program signs its event CPIs with
+pub fn find_event_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"__event_authority"], program_id)
+}
+
+/// Emitted by the program whenever a commitment is appended to a pool's tree
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct CommitmentInserted {
+    /// Pool account the commitment was appended to
+    pub pool: Pubkey,
+    /// Leaf position of the commitment in the pool's tree
+    pub leaf_index: u64,
+    pub commitment: [u8; 32],
+    /// Note encrypted for its owner
+    pub encrypted_note: Vec<u8>,
+}
+
+/// Prefix of every event CPI's instruction data
+fn event_cpi_tag() -> [u8; 8] {
+    let mut tag = [0u8; 8];
+    tag.copy_from_slice(&hash(b"anchor:event").to_bytes()[..8]);
+    tag
+}
+
+/// Anchor discriminator of an event type
+fn event_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("event:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// Parse the data of an event CPI into a `CommitmentInserted`, or `None`
+/// if it carries a different event
+pub fn parse_commitment_inserted(data: &[u8]) -> Option<CommitmentInserted> {
+    let payload = data
+        .strip_prefix(&event_cpi_tag())?
+        .strip_prefix(&event_discriminator("CommitmentInserted"))?;
+    CommitmentInserted::try_from_slice(payload).ok()
+}
+
+/// Decode the data `program_id` returned from a transaction
+pub fn decode_return_data(
+    return_data: &UiTransactionReturnData,
+    program_id: &Pubkey,
+) -> Result<Vec<u8>> {
+    if return_data.program_id != program_id.to_string() {
+        return Err(anyhow!(
+            "Return data was set by {}, not {}",
+            return_data.program_id,
+            program_id
+        ));
+    }
+
+    let (encoded, encoding) = &return_data.data;
+    match encoding {
+        UiReturnDataEncoding::Base64 => Ok(STANDARD.decode(encoded)?),
+    }
+}
+
+/// Decode a `u64` returned by an instruction, such as an assigned leaf index.
+///
+/// The runtime strips trailing zero bytes from return data, so short data is
+/// zero-padded back to eight bytes.
+pub fn decode_u64_return(data: &[u8]) -> Result<u64> {
+    if data.len() > 8 {
+        return Err(anyhow!("Expected a u64, got {} bytes", data.len()));
+    }
+
+    let mut bytes = [0u8; 8];
+    bytes[..data.len()].copy_from_slice(data);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_leaf_index_with_trimmed_zeros() {
+        let program_id = Pubkey::new_unique();
+        let return_data = UiTransactionReturnData {
+            program_id: program_id.to_string(),
+            data: (STANDARD.encode([5u8, 1]), UiReturnDataEncoding::Base64),
+        };
+
+        let data = decode_return_data(&return_data, &program_id).unwrap();
+        assert_eq!(decode_u64_return(&data).unwrap(), 261);
+
+        // Leaf zero comes back as empty data
+        assert_eq!(decode_u64_return(&[]).unwrap(), 0);
+        assert!(decode_u64_return(&[0u8; 9]).is_err());
+    }
+
+    #[test]
+    fn test_parse_commitment_inserted() {
+        let event = CommitmentInserted {
+            pool: Pubkey::new_unique(),
+            leaf_index: 3,
+            commitment: [8u8; 32],
+            encrypted_note: vec![1, 2, 3],
+        };
+
+        // Layout written by the program's emit_cpi!
+        let mut data = hash(b"anchor:event").to_bytes()[..8].to_vec();
+        data.extend_from_slice(&hash(b"event:CommitmentInserted").to_bytes()[..8]);
+        data.extend(event.try_to_vec().unwrap());
+
+        assert_eq!(parse_commitment_inserted(&data), Some(event));
+        assert_eq!(parse_commitment_inserted(&data[8..]), None);
+    }
+
+    #[test]
+    fn test_return_data_from_other_program_is_rejected() {
+        let return_data = UiTransactionReturnData {
+            program_id: Pubkey::new_unique().to_string(),
+            data: (
+                STANDARD.encode(7u64.to_le_bytes()),
+                UiReturnDataEncoding::Base64,
+            ),
+        };
+
+        assert!(decode_return_data(&return_data, &Pubkey::new_unique()).is_err());
+    }
+}
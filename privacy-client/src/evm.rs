@@ -0,0 +1,497 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+use crate::cross_chain::{BridgeStatus, SupportedChain};
+use crate::error::{ClientError, Result};
+use crate::evm_proof::{
+    mapping_slot, proof_nodes, verify_account_proof, verify_storage_proof, EvmLightClient,
+    StateProof, COMPLETED_TRANSFERS_SLOT,
+};
+use crate::wormhole::Vaa;
+
+// ethers-rs and alloy both need zeroize 1.5+, which the Solana 1.x crates
+// rule out, so this speaks JSON-RPC directly and signs with the secp256k1
+// implementation Solana already depends on.
+
+/// Delay between receipt polls while a claim is mined
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Hash of an EVM transaction
+pub type EvmTxHash = [u8; 32];
+
+/// Address on an EVM chain
+pub type EvmAddress = [u8; 20];
+
+impl SupportedChain {
+    /// EIP-155 chain ID, for the chains that run the EVM
+    pub fn evm_chain_id(&self) -> Option<u64> {
+        match self {
+            SupportedChain::Ethereum => Some(1),
+            SupportedChain::BinanceSmartChain => Some(56),
+            SupportedChain::Polygon => Some(137),
+            SupportedChain::Avalanche => Some(43114),
+            SupportedChain::Arbitrum => Some(42161),
+            SupportedChain::Optimism => Some(10),
+            SupportedChain::Base => Some(8453),
+            SupportedChain::ArbitrumNova => Some(42170),
+            SupportedChain::Solana
+            | SupportedChain::Sui
+            | SupportedChain::Aptos
+            | SupportedChain::Bitcoin => None,
+        }
+    }
+}
+
+/// Key paying for and signing EVM transactions
+pub struct EvmWallet {
+    secret: libsecp256k1::SecretKey,
+}
+
+impl EvmWallet {
+    pub fn from_bytes(secret: &[u8; 32]) -> Result<Self> {
+        let secret = libsecp256k1::SecretKey::parse(secret)
+            .map_err(|e| anyhow!("Invalid EVM private key: {:?}", e))?;
+        Ok(Self { secret })
+    }
+
+    pub fn address(&self) -> EvmAddress {
+        let public = libsecp256k1::PublicKey::from_secret_key(&self.secret).serialize();
+        let hash = Keccak256::digest(&public[1..]);
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&hash[12..]);
+        address
+    }
+
+    /// Signature and recovery ID over a 32-byte digest
+    fn sign(&self, digest: &[u8; 32]) -> ([u8; 64], u8) {
+        let (signature, recovery_id) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(digest), &self.secret);
+        (signature.serialize(), recovery_id.serialize())
+    }
+}
+
+/// Pre-EIP-1559 transaction, accepted by every supported EVM chain
+struct LegacyTransaction {
+    nonce: u64,
+    gas_price: u128,
+    gas: u64,
+    to: EvmAddress,
+    value: u128,
+    data: Vec<u8>,
+}
+
+impl LegacyTransaction {
+    fn fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_uint(self.nonce as u128),
+            rlp_uint(self.gas_price),
+            rlp_uint(self.gas as u128),
+            rlp_bytes(&self.to),
+            rlp_uint(self.value),
+            rlp_bytes(&self.data),
+        ]
+    }
+
+    /// EIP-155 hash to sign, binding the transaction to `chain_id`
+    fn signing_hash(&self, chain_id: u64) -> [u8; 32] {
+        let mut fields = self.fields();
+        fields.extend([rlp_uint(chain_id as u128), rlp_uint(0), rlp_uint(0)]);
+        Keccak256::digest(rlp_list(&fields)).into()
+    }
+
+    fn sign(&self, chain_id: u64, wallet: &EvmWallet) -> Vec<u8> {
+        let (signature, recovery_id) = wallet.sign(&self.signing_hash(chain_id));
+        let v = recovery_id as u128 + 35 + 2 * chain_id as u128;
+
+        let mut fields = self.fields();
+        fields.extend([
+            rlp_uint(v),
+            rlp_uint_bytes(&signature[..32]),
+            rlp_uint_bytes(&signature[32..]),
+        ]);
+        rlp_list(&fields)
+    }
+}
+
+fn rlp_length_prefix(len: usize, short: u8, long: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![short + len as u8];
+    }
+    let len_bytes = trim_leading_zeros(&(len as u64).to_be_bytes()).to_vec();
+    let mut prefix = vec![long + len_bytes.len() as u8];
+    prefix.extend(len_bytes);
+    prefix
+}
+
+pub(crate) fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = rlp_length_prefix(bytes.len(), 0x80, 0xb7);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+fn rlp_uint(value: u128) -> Vec<u8> {
+    rlp_uint_bytes(&value.to_be_bytes())
+}
+
+/// Big-endian integer, encoded without leading zeros
+pub(crate) fn rlp_uint_bytes(value: &[u8]) -> Vec<u8> {
+    rlp_bytes(trim_leading_zeros(value))
+}
+
+pub(crate) fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(Vec::len).sum();
+    let mut encoded = rlp_length_prefix(len, 0xc0, 0xf7);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// First four bytes of the Keccak-256 of a function signature
+fn selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// ABI call data of `redeem(bytes encodedVaa)`
+fn redeem_call(vaa: &[u8]) -> Vec<u8> {
+    let padded_len = vaa.len().div_ceil(32) * 32;
+    let mut data = selector("redeem(bytes)").to_vec();
+    data.extend_from_slice(&abi_uint(32)); // offset of the bytes argument
+    data.extend_from_slice(&abi_uint(vaa.len() as u128));
+    data.extend_from_slice(vaa);
+    data.resize(4 + 64 + padded_len, 0);
+    data
+}
+
+/// ABI call data of `isTransferCompleted(bytes32 vaaHash)`
+fn is_transfer_completed_call(vaa_hash: &[u8; 32]) -> Vec<u8> {
+    let mut data = selector("isTransferCompleted(bytes32)").to_vec();
+    data.extend_from_slice(vaa_hash);
+    data
+}
+
+/// ABI call data of `isNullifierSpent(bytes32 nullifier)`
+fn is_nullifier_spent_call(nullifier: &[u8; 32]) -> Vec<u8> {
+    let mut data = selector("isNullifierSpent(bytes32)").to_vec();
+    data.extend_from_slice(nullifier);
+    data
+}
+
+fn abi_uint(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+pub(crate) fn from_hex(value: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(hex::decode(value.trim_start_matches("0x"))?)
+}
+
+pub(crate) fn parse_quantity(value: &str) -> anyhow::Result<u128> {
+    Ok(u128::from_str_radix(value.trim_start_matches("0x"), 16)?)
+}
+
+/// Result of an Ethereum JSON-RPC call to the node at `url`
+pub(crate) async fn json_rpc<T: DeserializeOwned>(
+    http: &reqwest::Client,
+    url: &str,
+    timeout: Duration,
+    method: &str,
+    params: Value,
+) -> Result<T> {
+    let response: Value = http
+        .post(url)
+        .timeout(timeout)
+        .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(anyhow::Error::from)?
+        .json()
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("{} failed: {}", method, error).into());
+    }
+    let result = response.get("result").cloned().unwrap_or(Value::Null);
+    Ok(serde_json::from_value(result).map_err(anyhow::Error::from)?)
+}
+
+/// The EVM leg of a bridge transfer: claims VAAs on the destination chain's
+/// Untrace bridge contract and reads back whether they were redeemed. The
+/// contract exposes `redeem(bytes encodedVaa)` and
+/// `isTransferCompleted(bytes32 vaaHash) returns (bool)`, keyed by the
+/// VAA's body hash.
+pub struct EvmClient {
+    chain: SupportedChain,
+    chain_id: u64,
+    rpc_url: String,
+    bridge: EvmAddress,
+    wallet: EvmWallet,
+    http: reqwest::Client,
+    /// Timeout of each RPC request, and of waiting for a claim to be mined
+    pub timeout: Duration,
+    /// Blocks after which a claim counts as final; the chain's
+    /// `confirmation_depth` unless changed
+    pub confirmations: u64,
+    /// Slot of the bridge contract's `completedTransfers` mapping, for
+    /// proving claims against a verified header
+    pub completed_transfers_slot: u64,
+    light_client: Option<EvmLightClient>,
+}
+
+impl EvmClient {
+    /// Client for the bridge contract at `bridge` on `chain`, sending
+    /// claims from `wallet`. Fails for chains outside the EVM.
+    pub fn new(
+        chain: SupportedChain,
+        rpc_url: &str,
+        bridge: EvmAddress,
+        wallet: EvmWallet,
+    ) -> Result<Self> {
+        let chain_id = chain
+            .evm_chain_id()
+            .ok_or_else(|| anyhow!("{:?} is not an EVM chain", chain))?;
+
+        Ok(Self {
+            chain,
+            chain_id,
+            rpc_url: rpc_url.to_string(),
+            bridge,
+            wallet,
+            http: reqwest::Client::new(),
+            timeout: Duration::from_secs(120),
+            confirmations: chain.confirmation_depth(),
+            completed_transfers_slot: COMPLETED_TRANSFERS_SLOT,
+            light_client: None,
+        })
+    }
+
+    /// Prove final claims against headers from `light_client` instead of
+    /// trusting this client's RPC node
+    pub fn with_light_client(mut self, light_client: EvmLightClient) -> Self {
+        self.light_client = Some(light_client);
+        self
+    }
+
+    pub fn chain(&self) -> SupportedChain {
+        self.chain
+    }
+
+    async fn rpc<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        json_rpc(&self.http, &self.rpc_url, self.timeout, method, params).await
+    }
+
+    async fn quantity(&self, method: &str, params: Value) -> Result<u128> {
+        let value: String = self.rpc(method, params).await?;
+        Ok(parse_quantity(&value)?)
+    }
+
+    /// Submit a signed VAA to the bridge contract and wait until the claim
+    /// is mined. Fails if it reverts.
+    pub async fn claim(&self, vaa: &[u8]) -> Result<EvmTxHash> {
+        let from = to_hex(&self.wallet.address());
+        let to = to_hex(&self.bridge);
+        let data = redeem_call(vaa);
+
+        let nonce = self
+            .quantity("eth_getTransactionCount", json!([from, "pending"]))
+            .await?;
+        let gas_price = self.quantity("eth_gasPrice", json!([])).await?;
+        let gas = self
+            .quantity(
+                "eth_estimateGas",
+                json!([{ "from": from, "to": to, "data": to_hex(&data) }]),
+            )
+            .await?;
+
+        let transaction = LegacyTransaction {
+            nonce: nonce as u64,
+            gas_price,
+            gas: gas as u64,
+            to: self.bridge,
+            value: 0,
+            data,
+        };
+        let raw = transaction.sign(self.chain_id, &self.wallet);
+        let hash: String = self
+            .rpc("eth_sendRawTransaction", json!([to_hex(&raw)]))
+            .await?;
+        let tx_hash: EvmTxHash = from_hex(&hash)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid transaction hash {}", hash))?;
+
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        loop {
+            let receipt: Option<Value> =
+                self.rpc("eth_getTransactionReceipt", json!([hash])).await?;
+            if let Some(receipt) = receipt {
+                return match receipt.get("status").and_then(Value::as_str) {
+                    Some("0x1") => Ok(tx_hash),
+                    _ => Err(anyhow!("Claim {} reverted", hash).into()),
+                };
+            }
+            if tokio::time::Instant::now() + RECEIPT_POLL_INTERVAL > deadline {
+                return Err(ClientError::Other(anyhow!(
+                    "Claim {} not mined in time",
+                    hash
+                )));
+            }
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Whether the transfer carried by `vaa` has been claimed on this chain
+    pub async fn transfer_status(&self, vaa: &[u8]) -> Result<BridgeStatus> {
+        self.transfer_status_at(vaa, "latest").await
+    }
+
+    /// Like `transfer_status`, counting only claims at least
+    /// `confirmations` blocks deep. With a light client, the claim is
+    /// proven against a header it verified rather than taken from the node.
+    pub async fn final_transfer_status(&self, vaa: &[u8]) -> Result<BridgeStatus> {
+        let latest = self.quantity("eth_blockNumber", json!([])).await?;
+        let block = latest.saturating_sub(self.confirmations as u128) as u64;
+        match &self.light_client {
+            Some(light_client) => self.proven_transfer_status(vaa, block, light_client).await,
+            None => {
+                self.transfer_status_at(vaa, &format!("0x{:x}", block))
+                    .await
+            }
+        }
+    }
+
+    /// Whether the transfer carried by `vaa` was claimed as of `block`,
+    /// from a storage proof of the bridge contract's `completedTransfers`
+    /// entry checked against the header `light_client` agrees on. The
+    /// proof itself may come from any node.
+    pub async fn proven_transfer_status(
+        &self,
+        vaa: &[u8],
+        block: u64,
+        light_client: &EvmLightClient,
+    ) -> Result<BridgeStatus> {
+        let header = light_client.header(block).await?;
+        let slot = mapping_slot(&Vaa::parse(vaa)?.body_hash(), self.completed_transfers_slot);
+        let proof: StateProof = self
+            .rpc(
+                "eth_getProof",
+                json!([to_hex(&self.bridge), [to_hex(&slot)], format!("0x{:x}", block)]),
+            )
+            .await?;
+
+        let storage_root = verify_account_proof(
+            &header.state_root()?,
+            &self.bridge,
+            &proof_nodes(&proof.account_proof)?,
+        )?
+        .ok_or_else(|| anyhow!("No bridge contract at block {}", block))?;
+        let storage_proof = proof
+            .storage_proof
+            .first()
+            .ok_or_else(|| anyhow!("eth_getProof returned no storage proof"))?;
+        let word = verify_storage_proof(&storage_root, &slot, &proof_nodes(&storage_proof.proof)?)?;
+
+        match word[31] {
+            0 => Ok(BridgeStatus::Pending),
+            1 => Ok(BridgeStatus::Completed),
+            _ => Ok(BridgeStatus::Unknown),
+        }
+    }
+
+    /// Like `transfer_status`, as of `block`: a block number or a tag such
+    /// as `"finalized"`, to tell a claim that can still be reorged out from
+    /// a final one
+    pub async fn transfer_status_at(&self, vaa: &[u8], block: &str) -> Result<BridgeStatus> {
+        let call = is_transfer_completed_call(&Vaa::parse(vaa)?.body_hash());
+        let result: String = self
+            .rpc(
+                "eth_call",
+                json!([{ "to": to_hex(&self.bridge), "data": to_hex(&call) }, block]),
+            )
+            .await?;
+
+        let word = from_hex(&result)?;
+        if word.len() != 32 {
+            return Err(anyhow!("Unexpected isTransferCompleted result {}", result).into());
+        }
+        match word[31] {
+            0 => Ok(BridgeStatus::Pending),
+            1 => Ok(BridgeStatus::Completed),
+            _ => Ok(BridgeStatus::Unknown),
+        }
+    }
+
+    /// Whether the bridge contract's nullifier registry has `nullifier` as
+    /// spent on this chain, or applied from another chain's root
+    pub async fn is_nullifier_spent(&self, nullifier: &[u8; 32]) -> Result<bool> {
+        let call = is_nullifier_spent_call(nullifier);
+        let result: String = self
+            .rpc(
+                "eth_call",
+                json!([{ "to": to_hex(&self.bridge), "data": to_hex(&call) }, "latest"]),
+            )
+            .await?;
+
+        let word = from_hex(&result)?;
+        if word.len() != 32 {
+            return Err(anyhow!("Unexpected isNullifierSpent result {}", result).into());
+        }
+        Ok(word[31] != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eip155_signing_vector() {
+        // Example from EIP-155
+        let wallet = EvmWallet::from_bytes(&[0x46; 32]).unwrap();
+        let transaction = LegacyTransaction {
+            nonce: 9,
+            gas_price: 20_000_000_000,
+            gas: 21_000,
+            to: [0x35; 20],
+            value: 1_000_000_000_000_000_000,
+            data: vec![],
+        };
+
+        assert_eq!(
+            hex::encode(transaction.signing_hash(1)),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        assert_eq!(
+            hex::encode(transaction.sign(1, &wallet)),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+
+        assert_eq!(
+            selector("transfer(address,uint256)"),
+            [0xa9, 0x05, 0x9c, 0xbb]
+        );
+        assert_eq!(redeem_call(&[1u8; 33]).len(), 4 + 32 + 32 + 64);
+        assert_eq!(SupportedChain::Solana.evm_chain_id(), None);
+    }
+}
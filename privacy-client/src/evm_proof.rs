@@ -0,0 +1,523 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, ensure};
+use serde::Deserialize;
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+
+use crate::error::Result;
+use crate::evm::{from_hex, json_rpc, rlp_bytes, rlp_list, rlp_uint_bytes, EvmAddress};
+
+/// Storage slot of the bridge contract's `completedTransfers` mapping, a
+/// `bytes32 => bool` keyed by VAA body hash
+pub const COMPLETED_TRANSFERS_SLOT: u64 = 0;
+
+/// Decoded RLP item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+impl Rlp {
+    /// Decode `data`, which must hold exactly one item
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let (item, used) = decode_item(data)?;
+        ensure!(used == data.len(), "Trailing bytes after RLP item");
+        Ok(item)
+    }
+
+    pub fn as_bytes(&self) -> anyhow::Result<&[u8]> {
+        match self {
+            Rlp::Bytes(bytes) => Ok(bytes),
+            Rlp::List(_) => bail!("Expected RLP bytes, found a list"),
+        }
+    }
+
+    pub fn as_list(&self) -> anyhow::Result<&[Rlp]> {
+        match self {
+            Rlp::List(items) => Ok(items),
+            Rlp::Bytes(_) => bail!("Expected an RLP list, found bytes"),
+        }
+    }
+}
+
+/// First item of `data` and the bytes it spans
+fn decode_item(data: &[u8]) -> anyhow::Result<(Rlp, usize)> {
+    let prefix = *data.first().ok_or_else(|| anyhow!("Empty RLP input"))?;
+    let (is_list, offset, len) = match prefix {
+        0x00..=0x7f => return Ok((Rlp::Bytes(vec![prefix]), 1)),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => (
+            false,
+            1 + (prefix - 0xb7) as usize,
+            long_length(data, prefix - 0xb7)?,
+        ),
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => (
+            true,
+            1 + (prefix - 0xf7) as usize,
+            long_length(data, prefix - 0xf7)?,
+        ),
+    };
+    let end = offset
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| anyhow!("RLP item overruns its input"))?;
+    let body = &data[offset..end];
+
+    if !is_list {
+        return Ok((Rlp::Bytes(body.to_vec()), end));
+    }
+    let mut items = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        let (item, used) = decode_item(rest)?;
+        items.push(item);
+        rest = &rest[used..];
+    }
+    Ok((Rlp::List(items), end))
+}
+
+/// Length of a long string or list, from the `len_bytes` after its prefix
+fn long_length(data: &[u8], len_bytes: u8) -> anyhow::Result<usize> {
+    let bytes = data
+        .get(1..1 + len_bytes as usize)
+        .ok_or_else(|| anyhow!("Truncated RLP length"))?;
+    ensure!(bytes.len() <= 8, "RLP length too large");
+    Ok(bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize))
+}
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Path of an extension or leaf node and whether it is a leaf, from its
+/// hex-prefix encoding
+fn decode_path(encoded: &[u8]) -> anyhow::Result<(Vec<u8>, bool)> {
+    let nibbles = to_nibbles(encoded);
+    let flag = *nibbles
+        .first()
+        .ok_or_else(|| anyhow!("Empty trie node path"))?;
+    ensure!(flag < 4, "Invalid trie node path flag {}", flag);
+    let skip = if flag % 2 == 1 { 1 } else { 2 };
+    Ok((nibbles[skip..].to_vec(), flag >= 2))
+}
+
+/// Value under `key` in the Merkle-Patricia trie with `root`, checked
+/// against `proof`, the trie nodes from the root down as `eth_getProof`
+/// returns them. `None` when the proof shows the key is absent.
+pub fn verify_trie_proof(
+    root: &[u8; 32],
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    // Nodes leave an empty trie out of their proofs
+    if proof.is_empty() && *root == keccak(&rlp_bytes(&[])) {
+        return Ok(None);
+    }
+    let path = to_nibbles(key);
+    let mut path = path.as_slice();
+    let mut proof = proof.iter();
+    let mut node = next_proof_node(&mut proof, root)?;
+
+    loop {
+        let items = node.as_list()?;
+        let child = match items.len() {
+            17 => {
+                let Some((&nibble, rest)) = path.split_first() else {
+                    let value = items[16].as_bytes()?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                };
+                path = rest;
+                items[nibble as usize].clone()
+            }
+            2 => {
+                let (node_path, is_leaf) = decode_path(items[0].as_bytes()?)?;
+                if is_leaf {
+                    if node_path != path {
+                        return Ok(None);
+                    }
+                    return Ok(Some(items[1].as_bytes()?.to_vec()));
+                }
+                let Some(rest) = path.strip_prefix(node_path.as_slice()) else {
+                    return Ok(None);
+                };
+                path = rest;
+                items[1].clone()
+            }
+            len => bail!("Trie node with {} items", len),
+        };
+
+        node = match child {
+            Rlp::Bytes(hash) if hash.is_empty() => return Ok(None),
+            Rlp::Bytes(hash) => {
+                let hash: [u8; 32] = hash
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid trie node reference"))?;
+                next_proof_node(&mut proof, &hash)?
+            }
+            // Nodes under 32 bytes are embedded in their parent
+            embedded => embedded,
+        };
+    }
+}
+
+/// Next node of a proof, which must hash to `hash`
+fn next_proof_node<'a>(
+    proof: &mut impl Iterator<Item = &'a Vec<u8>>,
+    hash: &[u8; 32],
+) -> anyhow::Result<Rlp> {
+    let encoded = proof
+        .next()
+        .ok_or_else(|| anyhow!("Trie proof ends early"))?;
+    ensure!(
+        keccak(encoded) == *hash,
+        "Trie proof node does not match its hash"
+    );
+    Rlp::decode(encoded)
+}
+
+/// Storage root of `address` in the state with `state_root`, or `None` if
+/// the account does not exist
+pub fn verify_account_proof(
+    state_root: &[u8; 32],
+    address: &EvmAddress,
+    proof: &[Vec<u8>],
+) -> anyhow::Result<Option<[u8; 32]>> {
+    let Some(account) = verify_trie_proof(state_root, &keccak(address), proof)? else {
+        return Ok(None);
+    };
+    // [nonce, balance, storageRoot, codeHash]
+    let account = Rlp::decode(&account)?;
+    let fields = account.as_list()?;
+    ensure!(fields.len() == 4, "Account with {} fields", fields.len());
+    let storage_root = fields[2]
+        .as_bytes()?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid account storage root"))?;
+    Ok(Some(storage_root))
+}
+
+/// Word in storage `slot` of a contract with `storage_root`, zero if unset
+pub fn verify_storage_proof(
+    storage_root: &[u8; 32],
+    slot: &[u8; 32],
+    proof: &[Vec<u8>],
+) -> anyhow::Result<[u8; 32]> {
+    let mut word = [0u8; 32];
+    if let Some(value) = verify_trie_proof(storage_root, &keccak(slot), proof)? {
+        let value = Rlp::decode(&value)?;
+        let value = value.as_bytes()?;
+        ensure!(value.len() <= 32, "Storage value over 32 bytes");
+        word[32 - value.len()..].copy_from_slice(value);
+    }
+    Ok(word)
+}
+
+/// Storage slot of `key` in the Solidity mapping declared at `slot`
+pub fn mapping_slot(key: &[u8; 32], slot: u64) -> [u8; 32] {
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(key);
+    preimage[56..].copy_from_slice(&slot.to_be_bytes());
+    keccak(&preimage)
+}
+
+/// Block header as `eth_getBlockByNumber` returns it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockHeader {
+    /// Hash reported by the node, checked by `verify`
+    pub hash: String,
+    pub parent_hash: String,
+    pub sha3_uncles: String,
+    pub miner: String,
+    pub state_root: String,
+    pub transactions_root: String,
+    pub receipts_root: String,
+    pub logs_bloom: String,
+    pub difficulty: String,
+    pub number: String,
+    pub gas_limit: String,
+    pub gas_used: String,
+    pub timestamp: String,
+    pub extra_data: String,
+    pub mix_hash: String,
+    pub nonce: String,
+    pub base_fee_per_gas: Option<String>,
+    pub withdrawals_root: Option<String>,
+    pub blob_gas_used: Option<String>,
+    pub excess_blob_gas: Option<String>,
+    pub parent_beacon_block_root: Option<String>,
+    pub requests_hash: Option<String>,
+}
+
+/// Big-endian bytes of a hex quantity, which may have an odd digit count
+fn quantity_bytes(value: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = value.trim_start_matches("0x");
+    Ok(hex::decode(format!(
+        "{:0>1$}",
+        digits,
+        digits.len() + digits.len() % 2
+    ))?)
+}
+
+impl BlockHeader {
+    pub fn number(&self) -> anyhow::Result<u64> {
+        Ok(u64::from_str_radix(
+            self.number.trim_start_matches("0x"),
+            16,
+        )?)
+    }
+
+    pub fn state_root(&self) -> anyhow::Result<[u8; 32]> {
+        from_hex(&self.state_root)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid state root {}", self.state_root))
+    }
+
+    /// Keccak-256 of the header's RLP encoding, with the fork fields the
+    /// node returned appended in fork order
+    pub fn compute_hash(&self) -> anyhow::Result<[u8; 32]> {
+        let mut fields = Vec::with_capacity(21);
+        for data in [
+            &self.parent_hash,
+            &self.sha3_uncles,
+            &self.miner,
+            &self.state_root,
+            &self.transactions_root,
+            &self.receipts_root,
+            &self.logs_bloom,
+        ] {
+            fields.push(rlp_bytes(&from_hex(data)?));
+        }
+        for quantity in [
+            &self.difficulty,
+            &self.number,
+            &self.gas_limit,
+            &self.gas_used,
+            &self.timestamp,
+        ] {
+            fields.push(rlp_uint_bytes(&quantity_bytes(quantity)?));
+        }
+        fields.push(rlp_bytes(&from_hex(&self.extra_data)?));
+        fields.push(rlp_bytes(&from_hex(&self.mix_hash)?));
+        fields.push(rlp_bytes(&from_hex(&self.nonce)?));
+
+        if let Some(base_fee) = &self.base_fee_per_gas {
+            fields.push(rlp_uint_bytes(&quantity_bytes(base_fee)?));
+        }
+        if let Some(root) = &self.withdrawals_root {
+            fields.push(rlp_bytes(&from_hex(root)?));
+        }
+        for quantity in [&self.blob_gas_used, &self.excess_blob_gas]
+            .into_iter()
+            .flatten()
+        {
+            fields.push(rlp_uint_bytes(&quantity_bytes(quantity)?));
+        }
+        for hash in [&self.parent_beacon_block_root, &self.requests_hash]
+            .into_iter()
+            .flatten()
+        {
+            fields.push(rlp_bytes(&from_hex(hash)?));
+        }
+        Ok(keccak(&rlp_list(&fields)))
+    }
+
+    /// The header's hash, once checked to match the one the node reported
+    pub fn verify(&self) -> anyhow::Result<[u8; 32]> {
+        let hash = self.compute_hash()?;
+        ensure!(
+            from_hex(&self.hash)? == hash,
+            "Header of block {} does not hash to {}",
+            self.number,
+            self.hash
+        );
+        Ok(hash)
+    }
+}
+
+/// Account and storage proofs from `eth_getProof`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StateProof {
+    pub account_proof: Vec<String>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct StorageProof {
+    pub proof: Vec<String>,
+}
+
+/// Decode the hex trie nodes of a proof
+pub(crate) fn proof_nodes(proof: &[String]) -> anyhow::Result<Vec<Vec<u8>>> {
+    proof.iter().map(|node| from_hex(node)).collect()
+}
+
+/// Destination block headers that `quorum` independent RPC endpoints
+/// agree on, each checked to hash to its reported block hash. Proofs of
+/// destination state are then checked against these headers, so neither a
+/// relayer nor a single node can fake a claim.
+pub struct EvmLightClient {
+    endpoints: Vec<String>,
+    quorum: usize,
+    http: reqwest::Client,
+    /// Timeout of each header request
+    pub timeout: Duration,
+}
+
+impl EvmLightClient {
+    /// Trust headers at least `quorum` of `endpoints` return. Each endpoint
+    /// must be distinct, or one provider could count for several votes.
+    pub fn new(endpoints: &[&str], quorum: usize) -> Result<Self> {
+        let mut seen = HashSet::new();
+        for url in endpoints {
+            let normalized = url.trim().trim_end_matches('/').to_lowercase();
+            if !seen.insert(normalized) {
+                return Err(anyhow!("Endpoint {} is listed more than once", url).into());
+            }
+        }
+        if quorum == 0 || quorum > endpoints.len() {
+            return Err(anyhow!(
+                "Quorum must be 1 to {} endpoints, not {}",
+                endpoints.len(),
+                quorum
+            )
+            .into());
+        }
+        Ok(Self {
+            endpoints: endpoints.iter().map(|url| url.to_string()).collect(),
+            quorum,
+            http: reqwest::Client::new(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Header of block `number`, once a quorum of endpoints agrees on it
+    pub async fn header(&self, number: u64) -> Result<BlockHeader> {
+        let block = format!("0x{:x}", number);
+        let responses = futures::future::join_all(self.endpoints.iter().map(|url| {
+            json_rpc::<Option<BlockHeader>>(
+                &self.http,
+                url,
+                self.timeout,
+                "eth_getBlockByNumber",
+                json!([block, false]),
+            )
+        }))
+        .await;
+
+        // Endpoints that fail or return a header not matching its hash
+        // simply do not count towards the quorum
+        let mut votes: HashMap<[u8; 32], (usize, BlockHeader)> = HashMap::new();
+        for header in responses.into_iter().flatten().flatten() {
+            let Ok(hash) = header.verify() else {
+                continue;
+            };
+            if header.number().ok() != Some(number) {
+                continue;
+            }
+            let entry = votes.entry(hash).or_insert((0, header));
+            entry.0 += 1;
+            if entry.0 >= self.quorum {
+                return Ok(entry.1.clone());
+            }
+        }
+        Err(anyhow!(
+            "Fewer than {} of {} endpoints agree on block {}",
+            self.quorum,
+            self.endpoints.len(),
+            number
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hex-prefix encoding of a leaf path
+    fn leaf_path(nibbles: &[u8]) -> Vec<u8> {
+        let mut padded = if nibbles.len() % 2 == 1 {
+            vec![3]
+        } else {
+            vec![2, 0]
+        };
+        padded.extend_from_slice(nibbles);
+        padded
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_trie_proof() {
+        let keys = [[0x10u8; 32], [0x20u8; 32]];
+        let values = [rlp_bytes(&[0x01]), rlp_bytes(&[0x02, 0x03])];
+        let leaves: Vec<Vec<u8>> = keys
+            .iter()
+            .zip(&values)
+            .map(|(key, value)| {
+                rlp_list(&[
+                    rlp_bytes(&leaf_path(&to_nibbles(key)[1..])),
+                    rlp_bytes(value),
+                ])
+            })
+            .collect();
+
+        let mut branch = vec![rlp_bytes(&[]); 17];
+        branch[1] = rlp_bytes(&keccak(&leaves[0]));
+        branch[2] = rlp_bytes(&keccak(&leaves[1]));
+        let branch = rlp_list(&branch);
+        let root = keccak(&branch);
+
+        for (i, key) in keys.iter().enumerate() {
+            let proof = vec![branch.clone(), leaves[i].clone()];
+            assert_eq!(
+                verify_trie_proof(&root, key, &proof).unwrap(),
+                Some(values[i].clone())
+            );
+        }
+        // Empty branch slot, and a leaf for a different key
+        assert_eq!(
+            verify_trie_proof(&root, &[0x30u8; 32], std::slice::from_ref(&branch)).unwrap(),
+            None
+        );
+        let mut other = [0x10u8; 32];
+        other[31] = 0;
+        assert_eq!(
+            verify_trie_proof(&root, &other, &[branch.clone(), leaves[0].clone()]).unwrap(),
+            None
+        );
+        // Swapped leaf does not match the hash its parent commits to
+        assert!(verify_trie_proof(&root, &keys[0], &[branch, leaves[1].clone()]).is_err());
+        assert!(verify_trie_proof(&root, &keys[0], &[]).is_err());
+        let empty_root = keccak(&rlp_bytes(&[]));
+        assert_eq!(
+            verify_storage_proof(&empty_root, &[1u8; 32], &[]).unwrap(),
+            [0u8; 32]
+        );
+
+        assert_eq!(
+            Rlp::decode(&values[1]).unwrap(),
+            Rlp::Bytes(vec![0x02, 0x03])
+        );
+    }
+
+    #[test]
+    fn test_light_client_rejects_duplicate_endpoints() {
+        assert!(EvmLightClient::new(&["https://a.example", "https://b.example"], 2).is_ok());
+        assert!(EvmLightClient::new(&["https://a.example", "https://A.example/"], 2).is_err());
+        assert!(EvmLightClient::new(&["https://a.example"], 2).is_err());
+    }
+}
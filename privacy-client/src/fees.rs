@@ -0,0 +1,111 @@
+use std::{fmt, sync::Arc};
+
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction};
+
+/// Picks a priority fee, in micro-lamports per compute unit, from the fees
+/// recently paid to write the transaction's accounts
+pub type PriorityFeeCallback = Arc<dyn Fn(&[u64]) -> u64 + Send + Sync>;
+
+/// How the compute unit price of a transaction is chosen
+#[derive(Clone, Default)]
+pub enum PriorityFeeStrategy {
+    /// No priority fee
+    #[default]
+    None,
+    /// Fixed price in micro-lamports per compute unit
+    Static(u64),
+    /// Percentile (0-100) of recent fees for the written accounts
+    Percentile(u8),
+    /// Custom choice over recent fees for the written accounts
+    Callback(PriorityFeeCallback),
+}
+
+impl fmt::Debug for PriorityFeeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Static(price) => f.debug_tuple("Static").field(price).finish(),
+            Self::Percentile(percentile) => f.debug_tuple("Percentile").field(percentile).finish(),
+            Self::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+impl PriorityFeeStrategy {
+    /// Whether choosing a price needs recent fees from the RPC node
+    pub fn needs_recent_fees(&self) -> bool {
+        matches!(self, Self::Percentile(_) | Self::Callback(_))
+    }
+
+    /// Price to pay given recently paid fees
+    pub fn compute_unit_price(&self, recent_fees: &[u64]) -> u64 {
+        match self {
+            Self::None => 0,
+            Self::Static(price) => *price,
+            Self::Percentile(percentile) => percentile_fee(recent_fees, *percentile),
+            Self::Callback(callback) => callback(recent_fees),
+        }
+    }
+}
+
+/// Nearest-rank percentile of `fees`; zero when there are none
+pub fn percentile_fee(fees: &[u64], percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+
+    let mut sorted = fees.to_vec();
+    sorted.sort_unstable();
+    let rank = (percentile.min(100) as usize * sorted.len()).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+/// Compute budget instructions to prepend to a transaction
+pub fn compute_budget_instructions(
+    compute_unit_limit: Option<u32>,
+    compute_unit_price: u64,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    if let Some(limit) = compute_unit_limit {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+    }
+    if compute_unit_price > 0 {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+            compute_unit_price,
+        ));
+    }
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_fee() {
+        let fees = [50, 10, 40, 20, 30];
+
+        assert_eq!(percentile_fee(&fees, 0), 10);
+        assert_eq!(percentile_fee(&fees, 50), 30);
+        assert_eq!(percentile_fee(&fees, 75), 40);
+        assert_eq!(percentile_fee(&fees, 100), 50);
+        assert_eq!(percentile_fee(&[], 90), 0);
+    }
+
+    #[test]
+    fn test_strategies() {
+        let fees = [100, 300];
+        let callback = PriorityFeeStrategy::Callback(Arc::new(|fees: &[u64]| {
+            fees.iter().sum::<u64>() / fees.len() as u64
+        }));
+
+        assert_eq!(PriorityFeeStrategy::None.compute_unit_price(&fees), 0);
+        assert_eq!(PriorityFeeStrategy::Static(7).compute_unit_price(&fees), 7);
+        assert_eq!(callback.compute_unit_price(&fees), 200);
+        assert!(!PriorityFeeStrategy::Static(7).needs_recent_fees());
+
+        // A zero price adds no instruction
+        assert_eq!(compute_budget_instructions(Some(400_000), 0).len(), 1);
+        assert_eq!(compute_budget_instructions(None, 5).len(), 1);
+    }
+}
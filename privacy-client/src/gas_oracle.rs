@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::cross_chain::SupportedChain;
+use crate::error::Result;
+use crate::evm::{json_rpc, parse_quantity};
+use crate::token_registry::TokenInfo;
+
+/// Gas a `redeem` claim uses on an EVM chain, with margin for verifying a
+/// full guardian set's signatures
+pub const CLAIM_GAS: u64 = 350_000;
+
+/// Decimals of the native coin of every supported EVM chain
+pub const EVM_NATIVE_DECIMALS: u8 = 18;
+
+/// How long a bridge fee quote is honored before it must be refreshed
+pub const QUOTE_VALIDITY: Duration = Duration::from_secs(60);
+
+/// Margin a relayer fee adds over the quoted claim cost, against the
+/// destination gas price rising before the relayer claims, in basis points
+pub const RELAYER_FEE_MARGIN_BPS: u64 = 2_000;
+
+/// Source of destination-chain gas prices and native coin prices for bridge
+/// fee quotes
+#[async_trait]
+pub trait GasOracle: Send + Sync {
+    /// Current gas price on EVM `chain`, in wei per gas
+    async fn gas_price(&self, chain: SupportedChain) -> Result<u128>;
+
+    /// Value of one whole native coin of `chain` (ETH, BNB, POL, ...) in
+    /// base units of `token`
+    async fn native_price(&self, chain: SupportedChain, token: &TokenInfo) -> Result<u128>;
+}
+
+/// Fixed gas and native coin prices, for tests or pinned quotes
+#[derive(Debug, Clone, Default)]
+pub struct StaticGasOracle {
+    gas_prices: HashMap<SupportedChain, u128>,
+    native_prices: HashMap<(SupportedChain, String), u128>,
+}
+
+impl StaticGasOracle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_gas_price(mut self, chain: SupportedChain, wei_per_gas: u128) -> Self {
+        self.gas_prices.insert(chain, wei_per_gas);
+        self
+    }
+
+    /// One whole native coin of `chain` is worth `price` base units of the
+    /// token with `symbol`
+    pub fn with_native_price(mut self, chain: SupportedChain, symbol: &str, price: u128) -> Self {
+        self.native_prices
+            .insert((chain, symbol.to_ascii_uppercase()), price);
+        self
+    }
+
+    fn lookup_native_price(&self, chain: SupportedChain, token: &TokenInfo) -> Result<u128> {
+        self.native_prices
+            .get(&(chain, token.symbol.to_ascii_uppercase()))
+            .copied()
+            .ok_or_else(|| anyhow!("No {:?} native price in {}", chain, token.symbol).into())
+    }
+}
+
+#[async_trait]
+impl GasOracle for StaticGasOracle {
+    async fn gas_price(&self, chain: SupportedChain) -> Result<u128> {
+        self.gas_prices
+            .get(&chain)
+            .copied()
+            .ok_or_else(|| anyhow!("No gas price for {:?}", chain).into())
+    }
+
+    async fn native_price(&self, chain: SupportedChain, token: &TokenInfo) -> Result<u128> {
+        self.lookup_native_price(chain, token)
+    }
+}
+
+/// Live gas prices from each destination chain's `eth_gasPrice`, with native
+/// coin prices from `prices`
+pub struct EvmRpcGasOracle {
+    rpc_urls: HashMap<SupportedChain, String>,
+    prices: StaticGasOracle,
+    http: reqwest::Client,
+    /// Timeout of each gas price request
+    pub timeout: Duration,
+}
+
+impl EvmRpcGasOracle {
+    pub fn new(prices: StaticGasOracle) -> Self {
+        Self {
+            rpc_urls: HashMap::new(),
+            prices,
+            http: reqwest::Client::new(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn with_rpc_url(mut self, chain: SupportedChain, rpc_url: &str) -> Self {
+        self.rpc_urls.insert(chain, rpc_url.to_string());
+        self
+    }
+
+    /// Replace the native coin prices, e.g. from a fresh price feed read
+    pub fn set_prices(&mut self, prices: StaticGasOracle) {
+        self.prices = prices;
+    }
+}
+
+#[async_trait]
+impl GasOracle for EvmRpcGasOracle {
+    async fn gas_price(&self, chain: SupportedChain) -> Result<u128> {
+        let rpc_url = self
+            .rpc_urls
+            .get(&chain)
+            .ok_or_else(|| anyhow!("No RPC URL for {:?}", chain))?;
+        let price: String =
+            json_rpc(&self.http, rpc_url, self.timeout, "eth_gasPrice", json!([])).await?;
+        Ok(parse_quantity(&price)?)
+    }
+
+    async fn native_price(&self, chain: SupportedChain, token: &TokenInfo) -> Result<u128> {
+        self.prices.lookup_native_price(chain, token)
+    }
+}
+
+/// `cost` in a native coin's smallest unit, priced in base units of a
+/// token worth `price` per whole coin, rounded up
+pub fn native_to_token(cost: u128, native_decimals: u8, price: u128) -> u128 {
+    let scale = 10u128.pow(native_decimals as u32);
+    cost.saturating_mul(price).div_ceil(scale)
+}
+
+/// What a bridge transfer costs beyond the amount moved, valid until
+/// `expires_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeFeeQuote {
+    pub source_chain: SupportedChain,
+    pub dest_chain: SupportedChain,
+    /// Symbol of the bridged token, the unit of the source-side fees
+    pub token: String,
+    /// Fee kept by the protocol, in source token units
+    pub protocol_fee: u64,
+    /// Signature and priority fees of the Solana transaction, in lamports;
+    /// zero when the transfer does not start on Solana
+    pub solana_fee: u64,
+    /// Destination gas price the quote was built with, in wei per gas
+    pub dest_gas_price: u128,
+    /// Gas cost of the claim on the destination, in wei
+    pub dest_claim_cost: u128,
+    /// `dest_claim_cost` in source token units
+    pub dest_claim_cost_in_token: u64,
+    pub expires_at: SystemTime,
+}
+
+impl BridgeFeeQuote {
+    /// Fees in source token units, counting the Solana fee when the token
+    /// is SOL itself
+    pub fn total_in_token(&self) -> u64 {
+        let solana_fee = if self.token == "SOL" {
+            self.solana_fee
+        } else {
+            0
+        };
+        self.protocol_fee
+            .saturating_add(self.dest_claim_cost_in_token)
+            .saturating_add(solana_fee)
+    }
+
+    /// Fee to carry in the message so a relayer fronts the destination
+    /// claim, paid out of the amount moved: `dest_claim_cost_in_token` plus
+    /// `RELAYER_FEE_MARGIN_BPS`
+    pub fn relayer_fee(&self) -> u64 {
+        let cost = self.dest_claim_cost_in_token as u128;
+        let margin = (cost * RELAYER_FEE_MARGIN_BPS as u128).div_ceil(10_000);
+        (cost + margin).min(u64::MAX as u128) as u64
+    }
+
+    /// What the recipient receives of `amount` when a relayer claims it
+    pub fn relayed_amount(&self, amount: u64) -> u64 {
+        amount.saturating_sub(self.relayer_fee())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_claim_cost_in_source_token() {
+        let sol = TokenInfo::new("SOL", SupportedChain::Solana, None, 9, false);
+        // 20 gwei, and 1 ETH = 150 SOL
+        let oracle = StaticGasOracle::new()
+            .with_gas_price(SupportedChain::Ethereum, 20_000_000_000)
+            .with_native_price(SupportedChain::Ethereum, "sol", 150_000_000_000);
+
+        let gas_price = oracle.gas_price(SupportedChain::Ethereum).await.unwrap();
+        let price = oracle
+            .native_price(SupportedChain::Ethereum, &sol)
+            .await
+            .unwrap();
+        let cost = gas_price * CLAIM_GAS as u128;
+        assert_eq!(cost, 7_000_000_000_000_000);
+        // 0.007 ETH is 1.05 SOL
+        assert_eq!(
+            native_to_token(cost, EVM_NATIVE_DECIMALS, price),
+            1_050_000_000
+        );
+        assert_eq!(native_to_token(1, EVM_NATIVE_DECIMALS, price), 1);
+
+        let quote = BridgeFeeQuote {
+            source_chain: SupportedChain::Solana,
+            dest_chain: SupportedChain::Ethereum,
+            token: sol.symbol.clone(),
+            protocol_fee: 0,
+            solana_fee: 5_000,
+            dest_gas_price: gas_price,
+            dest_claim_cost: cost,
+            dest_claim_cost_in_token: 1_050_000_000,
+            expires_at: SystemTime::now() + QUOTE_VALIDITY,
+        };
+        assert_eq!(quote.relayer_fee(), 1_260_000_000);
+        assert_eq!(quote.relayed_amount(2_000_000_000), 740_000_000);
+        assert_eq!(quote.relayed_amount(1_000_000_000), 0);
+
+        assert!(oracle.gas_price(SupportedChain::Polygon).await.is_err());
+    }
+}
@@ -0,0 +1,415 @@
+use anchor_lang::AccountDeserialize;
+use anyhow::anyhow;
+use borsh::BorshDeserialize;
+use solana_sdk::{
+    instruction::AccountMeta,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program,
+};
+use untrace_common::{
+    layerzero::{
+        self as layerzero_layout, LzReceiveParams, DEFAULT_LZ_RECEIVE_GAS, ENDPOINT_SEED,
+        EVENT_AUTHORITY_SEED, MESSAGE_LIB_SEED, NONCE_SEED, OAPP_SEED, PAYLOAD_HASH_SEED,
+        SEND_LIBRARY_CONFIG_SEED,
+    },
+    BridgeMessage, UntraceError, SOLANA_CHAIN_ID,
+};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{LayerZeroPeerAccount, OutboundTransferAccount},
+};
+
+use crate::admin::find_admin_config_address;
+use crate::cross_chain::{
+    find_bridge_config_address, find_bridge_vault_address, find_consumed_message_address,
+    find_outbound_transfer_address, CrossChainClient, SupportedChain,
+};
+use crate::error::{ClientError, Result};
+use crate::UntraceClient;
+
+/// LayerZero endpoint on Solana mainnet-beta
+pub const MAINNET_ENDPOINT: Pubkey = pubkey!("76y77prsiCMvXMjuoZ5VRrhG5qYBrUMYTE5WgHqgjEn6");
+/// Ultra Light Node 302 send library on Solana mainnet-beta
+pub const MAINNET_SEND_LIBRARY: Pubkey = pubkey!("7a4WjyR8VZ7yZz5XJAKm39BUGn5iT9CKcv2pmG9tdXVH");
+
+/// Where a client reaches LayerZero
+#[derive(Debug, Clone)]
+pub struct LayerZeroConfig {
+    /// Endpoint program on the client's cluster
+    pub endpoint: Pubkey,
+    /// Library the endpoint sends the program's packets through
+    pub send_library: Pubkey,
+    /// Accounts the send library's `send` takes, from the executor and
+    /// DVNs configured for the program's pathways. The sender follows them
+    /// as the fee payer.
+    pub send_library_accounts: Vec<AccountMeta>,
+    /// Most lamports paid to the send library per message
+    pub max_native_fee: u64,
+    /// Gas the executor is asked to give `lz_receive` on the destination
+    pub lz_receive_gas: u128,
+}
+
+impl LayerZeroConfig {
+    /// Mainnet endpoint and send library; `send_library_accounts` still
+    /// has to be filled in from the pathways' configuration
+    pub fn mainnet() -> Self {
+        Self {
+            endpoint: MAINNET_ENDPOINT,
+            send_library: MAINNET_SEND_LIBRARY,
+            send_library_accounts: Vec::new(),
+            // 0.05 SOL
+            max_native_fee: 50_000_000,
+            lz_receive_gas: DEFAULT_LZ_RECEIVE_GAS,
+        }
+    }
+}
+
+/// Derive the PDA holding the program's LayerZero settings, which is also
+/// the OApp registered with the endpoint
+pub fn find_layerzero_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"layerzero_config"], program_id)
+}
+
+/// Derive the PDA of the peer OApp trusted on endpoint `eid`
+pub fn find_layerzero_peer_address(program_id: &Pubkey, eid: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"layerzero_peer", &eid.to_le_bytes()], program_id)
+}
+
+/// Accounts of the endpoint's `register_oapp` for `oapp`, paid by `payer`
+pub fn register_accounts(endpoint: &Pubkey, oapp: &Pubkey, payer: &Pubkey) -> Vec<AccountMeta> {
+    let (oapp_registry, _) = Pubkey::find_program_address(&[OAPP_SEED, oapp.as_ref()], endpoint);
+    vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*oapp, false),
+        AccountMeta::new(oapp_registry, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(event_authority(endpoint), false),
+        AccountMeta::new_readonly(*endpoint, false),
+    ]
+}
+
+/// Accounts of the endpoint's `send` from `oapp` to `receiver` on
+/// `dst_eid`, the send library's own included
+pub fn send_accounts(
+    config: &LayerZeroConfig,
+    oapp: &Pubkey,
+    dst_eid: u32,
+    receiver: &[u8; 32],
+    payer: &Pubkey,
+) -> Vec<AccountMeta> {
+    let endpoint = &config.endpoint;
+    let eid = dst_eid.to_be_bytes();
+    let find = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, endpoint).0;
+    let (message_lib, _) = Pubkey::find_program_address(&[MESSAGE_LIB_SEED], &config.send_library);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*oapp, false),
+        AccountMeta::new_readonly(config.send_library, false),
+        AccountMeta::new_readonly(
+            find(&[SEND_LIBRARY_CONFIG_SEED, oapp.as_ref(), &eid]),
+            false,
+        ),
+        AccountMeta::new_readonly(find(&[SEND_LIBRARY_CONFIG_SEED, &eid]), false),
+        AccountMeta::new_readonly(find(&[MESSAGE_LIB_SEED, message_lib.as_ref()]), false),
+        AccountMeta::new_readonly(find(&[ENDPOINT_SEED]), false),
+        AccountMeta::new(find(&[NONCE_SEED, oapp.as_ref(), &eid, receiver]), false),
+        AccountMeta::new_readonly(event_authority(endpoint), false),
+        AccountMeta::new_readonly(*endpoint, false),
+    ];
+    accounts.extend(config.send_library_accounts.iter().cloned());
+    accounts.push(AccountMeta::new(*payer, true));
+    accounts
+}
+
+/// Accounts of the endpoint's `clear` of `packet` at `oapp`
+pub fn clear_accounts(
+    endpoint: &Pubkey,
+    oapp: &Pubkey,
+    packet: &LzReceiveParams,
+) -> Vec<AccountMeta> {
+    let eid = packet.src_eid.to_be_bytes();
+    let find = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, endpoint).0;
+    let nonce = packet.nonce.to_be_bytes();
+
+    vec![
+        AccountMeta::new_readonly(*oapp, false),
+        AccountMeta::new_readonly(find(&[OAPP_SEED, oapp.as_ref()]), false),
+        AccountMeta::new(
+            find(&[NONCE_SEED, oapp.as_ref(), &eid, &packet.sender]),
+            false,
+        ),
+        AccountMeta::new(
+            find(&[
+                PAYLOAD_HASH_SEED,
+                oapp.as_ref(),
+                &eid,
+                &packet.sender,
+                &nonce,
+            ]),
+            false,
+        ),
+        AccountMeta::new(find(&[ENDPOINT_SEED]), false),
+        AccountMeta::new_readonly(event_authority(endpoint), false),
+        AccountMeta::new_readonly(*endpoint, false),
+    ]
+}
+
+fn event_authority(endpoint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], endpoint).0
+}
+
+/// Bridge message carried by an inbound `packet`, checked to come from the
+/// chain its endpoint ID names and to be bound for Solana
+pub fn delivered_message(packet: &LzReceiveParams) -> anyhow::Result<BridgeMessage> {
+    let message = BridgeMessage::try_from_slice(&packet.message)?;
+    if SupportedChain::from_layerzero_eid(packet.src_eid).map(|chain| chain.to_u16())
+        != Some(message.source_chain)
+    {
+        return Err(anyhow!(
+            "Packet from endpoint {} claims chain {}",
+            packet.src_eid,
+            message.source_chain
+        ));
+    }
+    if message.dest_chain != SOLANA_CHAIN_ID {
+        return Err(anyhow!("Packet is bound for chain {}", message.dest_chain));
+    }
+    Ok(message)
+}
+
+/// Bridge transfers carried by LayerZero instead of Wormhole, through the
+/// same vault, limits and refunds
+pub struct LayerZeroClient<'a> {
+    client: &'a UntraceClient,
+    cross_chain: CrossChainClient<'a>,
+}
+
+impl<'a> LayerZeroClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self {
+            client,
+            cross_chain: CrossChainClient::new(client),
+        }
+    }
+
+    pub(crate) fn layerzero(&self) -> Result<&LayerZeroConfig> {
+        self.client
+            .config
+            .layerzero
+            .as_ref()
+            .ok_or_else(|| anyhow!("LayerZero is not configured for this client").into())
+    }
+
+    pub(crate) fn cross_chain(&self) -> &CrossChainClient<'a> {
+        &self.cross_chain
+    }
+
+    /// Address other chains set as this program's peer
+    pub fn oapp_address(&self) -> [u8; 32] {
+        find_layerzero_config_address(&self.client.program_id)
+            .0
+            .to_bytes()
+    }
+
+    /// Point the program at the configured endpoint and register it there
+    /// (the protocol admin authority)
+    pub async fn initialize(&self) -> Result<Signature> {
+        let endpoint = self.layerzero()?.endpoint;
+        let (oapp, _) = find_layerzero_config_address(&self.client.program_id);
+        let payer = self.client.payer.pubkey();
+
+        let mut instruction = self.client.program_instruction(
+            accounts::InitializeLayerZero {
+                admin_config: find_admin_config_address(&self.client.program_id).0,
+                layerzero_config: oapp,
+                endpoint_program: endpoint,
+                authority: payer,
+                system_program: system_program::id(),
+            },
+            instruction::InitializeLayerzero {},
+        );
+        instruction
+            .accounts
+            .extend(register_accounts(&endpoint, &oapp, &payer));
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Trust `address` as the bridge's OApp on `chain` (the protocol admin
+    /// authority). Each chain's peer is set once; replacing it is a
+    /// timelocked `AdminAction::SetLayerZeroPeer`.
+    pub async fn set_peer(&self, chain: SupportedChain, address: [u8; 32]) -> Result<Signature> {
+        let eid = require_eid(chain)?;
+        let program_id = self.client.program_id;
+
+        let instruction = self.client.program_instruction(
+            accounts::SetLayerZeroPeer {
+                layerzero_config: find_layerzero_config_address(&program_id).0,
+                admin_config: find_admin_config_address(&program_id).0,
+                peer: find_layerzero_peer_address(&program_id, eid).0,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::SetLayerzeroPeer { eid, address },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Lock `amount` lamports and send an outbound transfer to `dest_chain`
+    /// through LayerZero, returning the message as sent
+    pub async fn post_message(
+        &self,
+        dest_chain: SupportedChain,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<(Signature, BridgeMessage)> {
+        let config = self.layerzero()?;
+        let eid = require_eid(dest_chain)?;
+        self.cross_chain.ensure_capacity(dest_chain, amount).await?;
+
+        let program_id = self.client.program_id;
+        let payer = self.client.payer.pubkey();
+        let (oapp, _) = find_layerzero_config_address(&program_id);
+        let (peer, _) = find_layerzero_peer_address(&program_id, eid);
+        let account = self.client.rpc_client.get_account(&peer).await?;
+        let peer_account = LayerZeroPeerAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("No LayerZero peer for {:?}: {}", dest_chain, e))?;
+
+        let transfer = Keypair::new();
+        let (outbound_transfer, _) =
+            find_outbound_transfer_address(&program_id, &transfer.pubkey());
+        let mut instruction = self.client.program_instruction(
+            accounts::PostLayerZeroMessage {
+                layerzero_config: oapp,
+                peer,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                bridge_config: find_bridge_config_address(&program_id).0,
+                transfer: transfer.pubkey(),
+                outbound_transfer,
+                sender: payer,
+                endpoint_program: config.endpoint,
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::PostLayerzeroMessage {
+                dest_chain: dest_chain.to_u16(),
+                recipient,
+                amount,
+                payload_hash,
+                native_fee: config.max_native_fee,
+                options: layerzero_layout::lz_receive_options(config.lz_receive_gas),
+            },
+        );
+        instruction.accounts.extend(send_accounts(
+            config,
+            &oapp,
+            eid,
+            &peer_account.address,
+            &payer,
+        ));
+
+        let signature = self
+            .client
+            .send_transaction_with_signers(vec![instruction], &[&transfer])
+            .await?;
+
+        // The program numbers LayerZero messages itself
+        let account = self
+            .client
+            .rpc_client
+            .get_account(&outbound_transfer)
+            .await?;
+        let posted = OutboundTransferAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("Invalid outbound transfer account: {}", e))?;
+        let message = BridgeMessage {
+            source_chain: SOLANA_CHAIN_ID,
+            dest_chain: dest_chain.to_u16(),
+            sequence: posted.sequence,
+            recipient,
+            amount,
+            payload_hash,
+        };
+
+        Ok((signature, message))
+    }
+
+    /// Release an inbound transfer from a packet the destination's DVNs
+    /// verified at the endpoint, when no executor delivered it
+    pub async fn redeem(&self, packet: &LzReceiveParams) -> Result<Signature> {
+        let endpoint = self.layerzero()?.endpoint;
+        let message = delivered_message(packet)?;
+        if self.cross_chain.is_message_consumed(&message).await? {
+            return Err(ClientError::Program(UntraceError::MessageAlreadyConsumed));
+        }
+
+        let program_id = self.client.program_id;
+        let (oapp, _) = find_layerzero_config_address(&program_id);
+        let mut instruction = self.client.program_instruction(
+            accounts::LzReceive {
+                layerzero_config: oapp,
+                peer: find_layerzero_peer_address(&program_id, packet.src_eid).0,
+                bridge_vault: find_bridge_vault_address(&program_id).0,
+                consumed_message: find_consumed_message_address(&program_id, &message.digest()).0,
+                recipient: Pubkey::new_from_array(message.recipient),
+                payer: self.client.payer.pubkey(),
+                endpoint_program: endpoint,
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::LzReceive {
+                params: packet.clone(),
+            },
+        );
+        instruction
+            .accounts
+            .extend(clear_accounts(&endpoint, &oapp, packet));
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+}
+
+fn require_eid(chain: SupportedChain) -> Result<u32> {
+    chain
+        .layerzero_eid()
+        .filter(|_| chain != SupportedChain::Solana)
+        .ok_or_else(|| anyhow!("{:?} is not reachable over LayerZero", chain).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    #[test]
+    fn test_delivered_message_checks_route() {
+        let message = BridgeMessage {
+            source_chain: SupportedChain::Base.to_u16(),
+            dest_chain: SOLANA_CHAIN_ID,
+            sequence: 9,
+            recipient: [4u8; 32],
+            amount: 1_000,
+            payload_hash: [5u8; 32],
+        };
+        let mut packet = LzReceiveParams {
+            src_eid: SupportedChain::Base.layerzero_eid().unwrap(),
+            sender: [6u8; 32],
+            nonce: 1,
+            guid: [7u8; 32],
+            message: message.try_to_vec().unwrap(),
+            extra_data: Vec::new(),
+        };
+        assert_eq!(delivered_message(&packet).unwrap(), message);
+
+        // Right payload, wrong endpoint
+        packet.src_eid = SupportedChain::Arbitrum.layerzero_eid().unwrap();
+        assert!(delivered_message(&packet).is_err());
+
+        packet.src_eid = SupportedChain::Base.layerzero_eid().unwrap();
+        packet.message.push(0);
+        assert!(delivered_message(&packet).is_err());
+    }
+}
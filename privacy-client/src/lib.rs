@@ -1,41 +1,193 @@
-use anyhow::{anyhow, Result};
-use solana_client::rpc_client::RpcClient;
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use aes_gcm::aead::{generic_array::GenericArray, AeadInPlace};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anyhow::anyhow;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_client::{
+    client_error::{ClientError as RpcClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig},
+    rpc_config::{RpcSendTransactionConfig, RpcTransactionConfig},
+};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    hash::Hash,
     instruction::Instruction,
     pubkey::Pubkey,
-    signature::{Keypair, Signature, Signer},
+    signature::{Signature, Signer},
     transaction::Transaction,
 };
-use untrace_common::{crypto, PrivacyLevel};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, UiInstruction, UiTransactionEncoding,
+};
+use untrace_common::{crypto, BridgeProtocol, PrivacyLevel};
 
+pub mod error;
+pub mod address;
+pub mod account_cache;
 pub mod private_transfer;
 pub mod privacy_pool;
 pub mod cross_chain;
+pub mod shielded_pool;
+pub mod compressed_pool;
+pub mod admin;
+pub mod bitcoin;
+pub mod bridge_backend;
+pub mod bridge_encryption;
+pub mod cluster;
+pub mod events;
+pub mod config;
+pub mod confirmation;
+pub mod cost;
+pub mod decoder;
+pub mod endpoints;
+pub mod evm;
+pub mod evm_proof;
+pub mod fees;
+pub mod gas_oracle;
+pub mod layerzero;
+pub mod nonce;
+pub mod nullifier_sync;
+pub mod storage;
+pub mod token_registry;
+pub mod merkle_sync;
+pub mod middleware;
+pub mod proof_cache;
+pub mod prover;
+pub mod redeem_retry;
+pub mod scanner;
+pub mod signer;
+pub mod solana_pay;
+pub mod sponsor;
+pub mod subscriptions;
+pub mod wormhole;
+pub mod wrapped;
 
+pub use error::{ClientError, Result};
+pub use address::{AddressError, ChainAddress};
+pub use account_cache::AccountCache;
 pub use private_transfer::PrivateTransferClient;
-pub use privacy_pool::PrivacyPoolClient;
-pub use cross_chain::CrossChainClient;
+pub use privacy_pool::{PoolInfo, PrivacyPoolClient};
+pub use cross_chain::{BridgeEvent, CrossChainClient, SupportedChain, TransferState};
+pub use shielded_pool::ShieldedPoolClient;
+pub use compressed_pool::{CommitmentIndex, CompressedPoolClient};
+pub use admin::AdminClient;
+pub use bitcoin::{BitcoinConfig, BitcoinCustodyClient, BitcoinNetwork, Psbt, TaprootAddress};
+pub use bridge_backend::{BridgeBackend, BridgeRoutes, GuardianBackend};
+pub use bridge_encryption::{BridgePayload, DecryptionKey, EncryptionKey, KeyFormat};
+pub use cluster::{Cluster, ClusterConfig};
+pub use config::ClientConfig;
+pub use confirmation::ConfirmationTracker;
+pub use cost::{CostEstimate, Operation};
+pub use decoder::ProgramInstruction;
+pub use endpoints::RpcEndpoints;
+pub use evm::{EvmClient, EvmWallet};
+pub use evm_proof::{BlockHeader, EvmLightClient};
+pub use fees::PriorityFeeStrategy;
+pub use gas_oracle::{BridgeFeeQuote, EvmRpcGasOracle, GasOracle, StaticGasOracle};
+pub use layerzero::{LayerZeroClient, LayerZeroConfig};
+pub use nonce::NonceClient;
+pub use nullifier_sync::NullifierSyncClient;
+pub use storage::{ClientStorage, FileStorage, MemoryStorage};
+pub use token_registry::{TokenInfo, TokenRegistry};
+pub use merkle_sync::{MembershipProof, MerkleSync};
+pub use middleware::{AntiMevMiddleware, SendMiddleware, MAX_SUBMISSION_DELAY};
+pub use proof_cache::ProofCache;
+pub use prover::{ProofHandle, ProverPool};
+pub use redeem_retry::{
+    RedeemOutcome, RedeemRetrier, RedeemRetryPolicy, RedeemRetryState,
+};
+pub use scanner::{DiscoveredNote, NoteScanner};
+pub use signer::TransactionSigner;
+pub use solana_pay::SolanaPayClient;
+pub use sponsor::FeeSponsor;
+pub use wormhole::{Vaa, WormholeConfig};
+pub use wrapped::WrappedAssetClient;
+
+/// Interval between signature status polls while confirming
+pub(crate) const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Main client for Untrace privacy protocol
 pub struct UntraceClient {
     pub rpc_client: RpcClient,
+    /// RPC nodes behind `rpc_client`, with their failover state
+    pub endpoints: RpcEndpoints,
     pub program_id: Pubkey,
-    pub payer: Keypair,
+    /// Signs and pays for every transaction; any `Signer`, such as a
+    /// keypair, hardware wallet or wallet adapter
+    pub payer: Arc<dyn Signer + Send + Sync>,
+    /// Signs as the payer when its key is held outside the client, in
+    /// which case `payer` only carries the public key
+    pub transaction_signer: Option<Arc<dyn TransactionSigner>>,
+    /// Send policy used unless a call passes its own
+    pub config: ClientConfig,
+    /// Proofs reused when a withdrawal or transfer is retried
+    pub proof_cache: ProofCache,
+    /// Rarely changing accounts reused across operations
+    pub account_cache: AccountCache,
+    /// Tokens bridged amounts are converted between
+    pub token_registry: TokenRegistry,
+    /// Sends transactions instead of `rpc_client` when set, such as
+    /// `AntiMevMiddleware`
+    pub middleware: Option<Arc<dyn SendMiddleware>>,
 }
 
 impl UntraceClient {
-    /// Create a new Untrace client
-    pub fn new(rpc_url: &str, program_id: Pubkey, payer: Keypair) -> Self {
-        let rpc_client = RpcClient::new_with_commitment(
-            rpc_url.to_string(),
-            CommitmentConfig::confirmed(),
+    /// Create a new Untrace client with the default send policy
+    pub fn new(
+        rpc_url: &str,
+        program_id: Pubkey,
+        payer: impl Signer + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_config(rpc_url, program_id, payer, ClientConfig::default())
+    }
+
+    /// Create a new Untrace client with a custom send policy
+    pub fn with_config(
+        rpc_url: &str,
+        program_id: Pubkey,
+        payer: impl Signer + Send + Sync + 'static,
+        config: ClientConfig,
+    ) -> Self {
+        Self::with_signer(rpc_url, program_id, Arc::new(payer), config)
+    }
+
+    /// Create a new Untrace client around a shared payer
+    pub fn with_signer(
+        rpc_url: &str,
+        program_id: Pubkey,
+        payer: Arc<dyn Signer + Send + Sync>,
+        config: ClientConfig,
+    ) -> Self {
+        Self::with_endpoints(&[rpc_url], program_id, payer, config)
+    }
+
+    /// Create a new Untrace client that fails over between several RPC
+    /// nodes, primary first. Panics if `rpc_urls` is empty.
+    pub fn with_endpoints(
+        rpc_urls: &[&str],
+        program_id: Pubkey,
+        payer: Arc<dyn Signer + Send + Sync>,
+        config: ClientConfig,
+    ) -> Self {
+        let endpoints = RpcEndpoints::new(rpc_urls, &config);
+        let rpc_client = RpcClient::new_sender(
+            endpoints.clone(),
+            RpcClientConfig::with_commitment(config.commitment),
         );
 
         Self {
             rpc_client,
+            endpoints,
             program_id,
             payer,
+            transaction_signer: None,
+            config,
+            proof_cache: ProofCache::default(),
+            account_cache: AccountCache::default(),
+            token_registry: TokenRegistry::default(),
+            middleware: None,
         }
     }
 
@@ -44,6 +196,11 @@ impl UntraceClient {
         PrivacyPoolClient::new(self)
     }
 
+    /// List the program's privacy pools with their anonymity set sizes
+    pub async fn list_pools(&self) -> Result<Vec<PoolInfo>> {
+        self.privacy_pool().list_pools().await
+    }
+
     /// Get private transfer client
     pub fn private_transfer(&self) -> PrivateTransferClient {
         PrivateTransferClient::new(self)
@@ -54,22 +211,396 @@ impl UntraceClient {
         CrossChainClient::new(self)
     }
 
+    /// Get LayerZero bridge client
+    pub fn layerzero(&self) -> LayerZeroClient {
+        LayerZeroClient::new(self)
+    }
+
+    /// Get the bridge backend carrying transfers over `protocol`
+    pub fn bridge(&self, protocol: BridgeProtocol) -> Box<dyn BridgeBackend + '_> {
+        match protocol {
+            BridgeProtocol::Wormhole => Box::new(self.cross_chain()),
+            BridgeProtocol::LayerZero => Box::new(self.layerzero()),
+            BridgeProtocol::Guardian => Box::new(GuardianBackend::new(self)),
+        }
+    }
+
+    /// Get the bridge backend `ClientConfig::bridge_routes` picks for
+    /// transfers from `source` to `dest`
+    pub fn bridge_for(
+        &self,
+        source: SupportedChain,
+        dest: SupportedChain,
+    ) -> Box<dyn BridgeBackend + '_> {
+        self.bridge(self.config.bridge_routes.protocol(source, dest))
+    }
+
+    /// Get cross-chain nullifier sync client
+    pub fn nullifier_sync(&self) -> NullifierSyncClient {
+        NullifierSyncClient::new(self)
+    }
+
+    /// Get wrapped asset mint and burn client
+    pub fn wrapped_assets(&self) -> WrappedAssetClient {
+        WrappedAssetClient::new(self)
+    }
+
+    /// Get Bitcoin custody client
+    pub fn bitcoin_custody(&self) -> BitcoinCustodyClient {
+        BitcoinCustodyClient::new(self)
+    }
+
+    /// Get a retrier claiming outbound transfers on `destination`, keeping
+    /// its attempts in `storage`
+    pub fn redeem_retrier<'a>(
+        &'a self,
+        destination: &'a EvmClient,
+        storage: Arc<dyn ClientStorage>,
+    ) -> RedeemRetrier<'a> {
+        RedeemRetrier::new(self.cross_chain(), destination, storage)
+    }
+
+    /// Get multi-asset shielded pool client
+    pub fn shielded_pool(&self) -> ShieldedPoolClient {
+        ShieldedPoolClient::new(self)
+    }
+
+    /// Get client for pools backed by a concurrent merkle tree
+    pub fn compressed_pool(&self) -> CompressedPoolClient {
+        CompressedPoolClient::new(self)
+    }
+
+    /// Get protocol admin client
+    pub fn admin(&self) -> AdminClient {
+        AdminClient::new(self)
+    }
+
+    /// Get durable nonce client
+    pub fn nonce(&self) -> NonceClient {
+        NonceClient::new(self)
+    }
+
+    /// Get Solana Pay transaction request client
+    pub fn solana_pay(&self) -> SolanaPayClient {
+        SolanaPayClient::new(self)
+    }
+
+    /// Build a privacy program instruction from the program's generated
+    /// account and argument types, so layouts and discriminators always
+    /// match the program
+    pub fn program_instruction(
+        &self,
+        accounts: impl ToAccountMetas,
+        args: impl InstructionData,
+    ) -> Instruction {
+        let mut accounts = accounts.to_account_metas(None);
+        // Omitted optional accounts are encoded as the program's declared ID;
+        // point them at the deployment this client talks to
+        for meta in &mut accounts {
+            if meta.pubkey == untrace_privacy_program::ID {
+                meta.pubkey = self.program_id;
+            }
+        }
+
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: args.data(),
+        }
+    }
+
     /// Send and confirm transaction
     pub async fn send_transaction(&self, instructions: Vec<Instruction>) -> Result<Signature> {
-        let recent_blockhash = self.rpc_client.get_latest_blockhash()?;
+        self.send_transaction_with_signers(instructions, &[]).await
+    }
+
+    /// Send and confirm a transaction that needs signers besides the payer
+    pub async fn send_transaction_with_signers(
+        &self,
+        instructions: Vec<Instruction>,
+        signers: &[&dyn Signer],
+    ) -> Result<Signature> {
+        self.send_transaction_with_config(instructions, signers, &self.config)
+            .await
+    }
 
-        let transaction = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_blockhash,
+    /// Send and confirm a transaction under a send policy other than the
+    /// client's own
+    pub async fn send_transaction_with_config(
+        &self,
+        instructions: Vec<Instruction>,
+        signers: &[&dyn Signer],
+        config: &ClientConfig,
+    ) -> Result<Signature> {
+        let mut all_signers: Vec<&dyn Signer> = vec![self.payer.as_ref()];
+        all_signers.extend_from_slice(signers);
+
+        let instructions = self.with_compute_budget(instructions, config).await?;
+        let mut recent_blockhash = self.latest_blockhash(config).await?;
+        let mut attempt = 0;
+        loop {
+            let mut transaction =
+                Transaction::new_with_payer(&instructions, Some(&self.payer.pubkey()));
+            transaction
+                .try_partial_sign(&all_signers, recent_blockhash)
+                .map_err(anyhow::Error::from)?;
+            let transaction = self.sign_externally(transaction).await?;
+
+            let expired = match self.send_and_confirm(&transaction, config).await {
+                Ok(Some(signature)) => return Ok(signature),
+                Ok(None) => true,
+                Err(err) if config::is_transient_error(&err) => {
+                    config::is_expired_blockhash(&err)
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if expired && !config.refresh_blockhash {
+                return Err(ClientError::BlockhashExpired);
+            }
+            if attempt >= config.max_retries {
+                return Err(ClientError::NotConfirmed {
+                    attempts: attempt + 1,
+                });
+            }
+
+            attempt += 1;
+            tokio::time::sleep(config.retry_delay(attempt)).await;
+            if expired {
+                recent_blockhash = self.latest_blockhash(config).await?;
+            }
+        }
+    }
+
+    /// Prepend compute budget instructions chosen by the send policy
+    pub(crate) async fn with_compute_budget(
+        &self,
+        instructions: Vec<Instruction>,
+        config: &ClientConfig,
+    ) -> Result<Vec<Instruction>> {
+        let mut writable: Vec<Pubkey> = instructions
+            .iter()
+            .flat_map(|ix| &ix.accounts)
+            .filter(|meta| meta.is_writable)
+            .map(|meta| meta.pubkey)
+            .collect();
+        writable.sort_unstable();
+        writable.dedup();
+
+        let mut budgeted = fees::compute_budget_instructions(
+            config.compute_unit_limit,
+            self.compute_unit_price(&writable, config).await?,
         );
+        budgeted.extend(instructions);
+        Ok(budgeted)
+    }
+
+    /// Compute unit price the send policy picks for a transaction writing
+    /// `writable`
+    pub(crate) async fn compute_unit_price(
+        &self,
+        writable: &[Pubkey],
+        config: &ClientConfig,
+    ) -> Result<u64> {
+        let recent_fees: Vec<u64> = if config.priority_fee.needs_recent_fees() {
+            self.rpc_client
+                .get_recent_prioritization_fees(writable)
+                .await?
+                .into_iter()
+                .map(|fee| fee.prioritization_fee)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(config.priority_fee.compute_unit_price(&recent_fees))
+    }
+
+    async fn latest_blockhash(&self, config: &ClientConfig) -> Result<Hash> {
+        let (blockhash, _) = self
+            .rpc_client
+            .get_latest_blockhash_with_commitment(config.commitment)
+            .await?;
+        Ok(blockhash)
+    }
+
+    /// Send once and wait for confirmation. `None` means the blockhash
+    /// expired before the transaction landed.
+    pub(crate) async fn send_and_confirm(
+        &self,
+        transaction: &Transaction,
+        config: &ClientConfig,
+    ) -> std::result::Result<Option<Signature>, RpcClientError> {
+        let signature = match &self.middleware {
+            // RPC errors the middleware passes on keep their kind, so
+            // expired blockhashes are still retried
+            Some(middleware) => middleware.send(transaction).await.map_err(|err| {
+                err.downcast::<RpcClientError>()
+                    .unwrap_or_else(|err| ClientErrorKind::Custom(err.to_string()).into())
+            })?,
+            None => {
+                self.rpc_client
+                    .send_transaction_with_config(
+                        transaction,
+                        RpcSendTransactionConfig {
+                            skip_preflight: config.skip_preflight,
+                            preflight_commitment: Some(config.commitment.commitment),
+                            ..RpcSendTransactionConfig::default()
+                        },
+                    )
+                    .await?
+            }
+        };
+        self.confirm(&signature, &transaction.message.recent_blockhash, config)
+            .await
+    }
 
-        let signature = self
+    /// Wait for a sent transaction to be confirmed. `None` means
+    /// `recent_blockhash`, the one it was signed with, expired first.
+    pub(crate) async fn confirm(
+        &self,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+        config: &ClientConfig,
+    ) -> std::result::Result<Option<Signature>, RpcClientError> {
+        loop {
+            match self
+                .rpc_client
+                .get_signature_status_with_commitment(signature, config.commitment)
+                .await?
+            {
+                Some(Ok(())) => return Ok(Some(*signature)),
+                Some(Err(err)) => return Err(err.into()),
+                None => {
+                    let valid = self
+                        .rpc_client
+                        .is_blockhash_valid(recent_blockhash, CommitmentConfig::processed())
+                        .await?;
+                    if !valid {
+                        return Ok(None);
+                    }
+                    tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Fetch the data this program returned from a confirmed transaction
+    pub async fn get_return_data(&self, signature: &Signature) -> Result<Vec<u8>> {
+        let transaction = self
             .rpc_client
-            .send_and_confirm_transaction(&transaction)?;
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(self.config.commitment),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
 
-        Ok(signature)
+        let return_data = transaction
+            .transaction
+            .meta
+            .map(|meta| meta.return_data)
+            .ok_or_else(|| anyhow!("Transaction {} has no status meta", signature))?;
+
+        match return_data {
+            OptionSerializer::Some(return_data) => {
+                Ok(events::decode_return_data(&return_data, &self.program_id)?)
+            }
+            // Empty return data is omitted entirely
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Collect the data of inner instructions invoking `program` in
+    /// successful transactions that touched `address` after `until`, oldest
+    /// first. Also returns the newest signature scanned, to resume from.
+    pub async fn fetch_inner_instructions(
+        &self,
+        address: &Pubkey,
+        until: Option<Signature>,
+        program: &Pubkey,
+    ) -> Result<(Vec<Vec<u8>>, Option<Signature>)> {
+        // Signatures come newest first; page back to `until`
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self
+                .rpc_client
+                .get_signatures_for_address_with_config(
+                    address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: None,
+                        commitment: Some(self.config.commitment),
+                    },
+                )
+                .await?;
+            let Some(last) = page.last() else { break };
+            before = Some(Signature::from_str(&last.signature).map_err(anyhow::Error::from)?);
+            signatures.extend(page.into_iter().filter(|s| s.err.is_none()));
+        }
+
+        let mut instructions = Vec::new();
+        for status in signatures.iter().rev() {
+            let signature = Signature::from_str(&status.signature).map_err(anyhow::Error::from)?;
+            let transaction = self
+                .rpc_client
+                .get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Base64),
+                        commitment: Some(self.config.commitment),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await?;
+
+            let decoded = transaction
+                .transaction
+                .transaction
+                .decode()
+                .ok_or_else(|| anyhow!("Undecodable transaction {}", signature))?;
+            let Some(meta) = transaction.transaction.meta else {
+                continue;
+            };
+
+            let mut account_keys = decoded.message.static_account_keys().to_vec();
+            if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+                for key in loaded.writable.iter().chain(loaded.readonly.iter()) {
+                    account_keys.push(Pubkey::from_str(key).map_err(anyhow::Error::from)?);
+                }
+            }
+
+            let OptionSerializer::Some(inner_instructions) = meta.inner_instructions else {
+                continue;
+            };
+            for instruction in inner_instructions
+                .iter()
+                .flat_map(|inner| &inner.instructions)
+            {
+                let UiInstruction::Compiled(compiled) = instruction else {
+                    continue;
+                };
+                if account_keys.get(compiled.program_id_index as usize) == Some(program) {
+                    instructions.push(
+                        bs58::decode(&compiled.data)
+                            .into_vec()
+                            .map_err(anyhow::Error::from)?,
+                    );
+                }
+            }
+        }
+
+        let newest = signatures
+            .first()
+            .map(|status| Signature::from_str(&status.signature))
+            .transpose()
+            .map_err(anyhow::Error::from)?;
+        Ok((instructions, newest))
     }
 
     /// Generate a new commitment for privacy pool
@@ -82,9 +613,8 @@ impl UntraceClient {
         rand::Rng::fill(&mut rand::thread_rng(), &mut randomness);
 
         let commitment = crypto::generate_commitment(
-            &recipient.to_bytes(),
             amount,
-            &randomness,
+            &crypto::note_key(&recipient.to_bytes(), &randomness),
         );
 
         (commitment, randomness)
@@ -95,34 +625,87 @@ impl UntraceClient {
         crypto::generate_nullifier(secret, commitment)
     }
 
-    /// Encrypt transfer data
+    /// Encrypt a transfer payload to the holder of `recipient_pubkey` (an
+    /// ed25519 public key) under a fresh ephemeral key, with AES-256-GCM as
+    /// `bridge_encryption::encrypt_payload` does.
+    ///
+    /// Returns `(ciphertext, ephemeral_pubkey, nonce, tag)`.
     pub fn encrypt_transfer_data(
         &self,
         recipient: &Pubkey,
         amount: u64,
+        memo: &str,
         recipient_pubkey: &[u8; 32],
     ) -> Result<(Vec<u8>, [u8; 32], [u8; 12], [u8; 16])> {
-        let mut shared_secret = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut shared_secret);
+        let mut ephemeral_secret = [0u8; 32];
+        rand::Rng::fill(&mut rand::thread_rng(), &mut ephemeral_secret);
+        let shared_secret = crypto::shared_secret(&ephemeral_secret, recipient_pubkey)
+            .ok_or_else(|| anyhow!("Invalid recipient public key"))?;
 
         let mut nonce = [0u8; 12];
         rand::Rng::fill(&mut rand::thread_rng(), &mut nonce);
 
-        // Create plaintext data
-        let mut plaintext = Vec::new();
-        plaintext.extend_from_slice(&recipient.to_bytes());
-        plaintext.extend_from_slice(&amount.to_le_bytes());
+        let mut ciphertext = DecryptedTransfer {
+            recipient: *recipient,
+            amount,
+            memo: memo.to_string(),
+        }
+        .try_to_vec()?;
 
-        let (ciphertext, tag) = crypto::encrypt_data(&plaintext, &shared_secret, &nonce)
-            .map_err(|e| anyhow!(e))?;
+        let ephemeral_pubkey = crypto::ephemeral_public_key(&ephemeral_secret);
+        let tag = bridge_encryption::payload_cipher(
+            bridge_encryption::TRANSFER_KDF_DOMAIN,
+            &shared_secret,
+            &ephemeral_pubkey,
+        )
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            &ephemeral_pubkey,
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow!("Transfer data encryption failed"))?;
 
-        let mut ephemeral_pubkey = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut ephemeral_pubkey);
+        Ok((ciphertext, ephemeral_pubkey, nonce, tag.into()))
+    }
+
+    /// Decrypt a payload from `encrypt_transfer_data` with the recipient's
+    /// secret key (its viewing key, see `scanner::viewing_key`)
+    pub fn decrypt_transfer_data(
+        &self,
+        ciphertext: &[u8],
+        ephemeral_pubkey: &[u8; 32],
+        nonce: &[u8; 12],
+        tag: &[u8; 16],
+        recipient_secret: &[u8; 32],
+    ) -> Result<DecryptedTransfer> {
+        let shared_secret = crypto::shared_secret(recipient_secret, ephemeral_pubkey)
+            .ok_or_else(|| anyhow!("Invalid ephemeral public key"))?;
+        let mut plaintext = ciphertext.to_vec();
+        bridge_encryption::payload_cipher(
+            bridge_encryption::TRANSFER_KDF_DOMAIN,
+            &shared_secret,
+            ephemeral_pubkey,
+        )
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            ephemeral_pubkey,
+            &mut plaintext,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| anyhow!("Transfer data does not decrypt with this key"))?;
 
-        Ok((ciphertext, ephemeral_pubkey, nonce, tag))
+        Ok(DecryptedTransfer::try_from_slice(&plaintext)?)
     }
 }
 
+/// Transfer payload recovered by `UntraceClient::decrypt_transfer_data`
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct DecryptedTransfer {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,4 +742,98 @@ mod tests {
         let nullifier = client.generate_nullifier(secret, &commitment);
         assert_eq!(nullifier.len(), 32);
     }
+
+    #[test]
+    fn test_program_instruction_matches_program() {
+        let program_id = Pubkey::new_unique();
+        let client = UntraceClient::new("http://localhost:8899", program_id, Keypair::new());
+
+        let instruction = client.program_instruction(
+            untrace_privacy_program::accounts::ExecuteAdminAction {
+                admin_config: Pubkey::new_unique(),
+                timelock: Pubkey::new_unique(),
+                pool_config: None,
+                bridge_config: None,
+                guardian_set: None,
+                foreign_emitter: None,
+                layerzero_peer: None,
+                verifier: None,
+                proposer: Pubkey::new_unique(),
+            },
+            untrace_privacy_program::instruction::ExecuteAdminAction {},
+        );
+
+        // Anchor sighash of the handler, not a hand-picked byte
+        let sighash = solana_sdk::hash::hash(b"global:execute_admin_action");
+        assert_eq!(instruction.data, sighash.to_bytes()[..8]);
+        assert_eq!(instruction.program_id, program_id);
+        // The omitted pool config points at this deployment
+        assert_eq!(instruction.accounts[2].pubkey, program_id);
+    }
+
+    #[test]
+    fn test_transfer_data_round_trip() {
+        let client = UntraceClient::new(
+            "http://localhost:8899",
+            Pubkey::new_unique(),
+            Keypair::new(),
+        );
+
+        let recipient = Keypair::new();
+        let (ciphertext, ephemeral_pubkey, nonce, tag) = client
+            .encrypt_transfer_data(
+                &recipient.pubkey(),
+                1_000,
+                "invoice 42",
+                &recipient.pubkey().to_bytes(),
+            )
+            .unwrap();
+
+        let decrypted = client
+            .decrypt_transfer_data(
+                &ciphertext,
+                &ephemeral_pubkey,
+                &nonce,
+                &tag,
+                &scanner::viewing_key(&recipient),
+            )
+            .unwrap();
+        assert_eq!(decrypted.recipient, recipient.pubkey());
+        assert_eq!(decrypted.amount, 1_000);
+        assert_eq!(decrypted.memo, "invoice 42");
+
+        // Each payload has its own key, so a guessed plaintext reveals no
+        // other payload, and a flipped byte fails authentication
+        let (other, _, _, _) = client
+            .encrypt_transfer_data(
+                &recipient.pubkey(),
+                1_000,
+                "invoice 42",
+                &recipient.pubkey().to_bytes(),
+            )
+            .unwrap();
+        assert_ne!(other[..32], ciphertext[..32]);
+        let mut tampered = ciphertext.clone();
+        tampered[40] ^= 1;
+        assert!(client
+            .decrypt_transfer_data(
+                &tampered,
+                &ephemeral_pubkey,
+                &nonce,
+                &tag,
+                &scanner::viewing_key(&recipient),
+            )
+            .is_err());
+
+        // Another wallet's key cannot open the payload
+        assert!(client
+            .decrypt_transfer_data(
+                &ciphertext,
+                &ephemeral_pubkey,
+                &nonce,
+                &tag,
+                &scanner::viewing_key(&Keypair::new()),
+            )
+            .is_err());
+    }
 }
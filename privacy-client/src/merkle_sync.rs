@@ -0,0 +1,338 @@
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use untrace_common::crypto;
+
+use crate::error::{ClientError, Result};
+use crate::events::{parse_commitment_inserted, CommitmentInserted};
+use crate::privacy_pool::{find_merkle_tree_address, find_pool_address, MERKLE_TREE_DEPTH};
+use crate::storage::ClientStorage;
+use crate::UntraceClient;
+
+/// Proof that a commitment is a leaf of a pool's tree at `root`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MembershipProof {
+    pub leaf_index: u64,
+    /// Sibling hashes from the leaf up to the root
+    pub siblings: Vec<[u8; 32]>,
+    pub root: [u8; 32],
+}
+
+impl MembershipProof {
+    /// Check the proof for `commitment`
+    pub fn verify(&self, commitment: &[u8; 32]) -> bool {
+        self.siblings.len() == MERKLE_TREE_DEPTH
+            && self.leaf_index < (1u64 << MERKLE_TREE_DEPTH)
+            && crypto::verify_merkle_proof(
+                commitment,
+                &self.siblings,
+                &self.root,
+                self.leaf_index as u32,
+            )
+    }
+}
+
+/// Append-only commitment tree with the same shape and hashing as a
+/// privacy pool's on-chain tree
+#[derive(Debug, Clone)]
+pub struct IncrementalMerkleTree {
+    /// Nodes per level; level 0 holds the leaves
+    levels: Vec<Vec<[u8; 32]>>,
+    /// Root of an empty subtree at each level
+    zeros: Vec<[u8; 32]>,
+}
+
+impl Default for IncrementalMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncrementalMerkleTree {
+    pub fn new() -> Self {
+        let mut zeros = vec![[0u8; 32]];
+        for level in 0..MERKLE_TREE_DEPTH {
+            zeros.push(crypto::merkle_hash_pair(&zeros[level], &zeros[level]));
+        }
+
+        Self {
+            levels: vec![Vec::new(); MERKLE_TREE_DEPTH],
+            zeros,
+        }
+    }
+
+    /// Rebuild a tree from its leaves in insertion order
+    pub fn from_leaves(leaves: &[[u8; 32]]) -> Result<Self> {
+        let mut tree = Self::new();
+        for leaf in leaves {
+            tree.append(*leaf)?;
+        }
+        Ok(tree)
+    }
+
+    /// Number of leaves
+    pub fn len(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.levels[0]
+    }
+
+    /// Append a leaf, returning its index
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<u64> {
+        let leaf_index = self.len();
+        if leaf_index >= 1u64 << MERKLE_TREE_DEPTH {
+            return Err(anyhow!("Merkle tree is full").into());
+        }
+
+        self.levels[0].push(leaf);
+        let mut index = leaf_index as usize;
+        for level in 1..MERKLE_TREE_DEPTH {
+            index /= 2;
+            let parent = self.parent(level - 1, index);
+            match self.levels[level].get_mut(index) {
+                Some(node) => *node = parent,
+                None => self.levels[level].push(parent),
+            }
+        }
+
+        Ok(leaf_index)
+    }
+
+    fn node(&self, level: usize, index: usize) -> [u8; 32] {
+        self.levels[level]
+            .get(index)
+            .copied()
+            .unwrap_or(self.zeros[level])
+    }
+
+    fn parent(&self, level: usize, index: usize) -> [u8; 32] {
+        crypto::merkle_hash_pair(
+            &self.node(level, 2 * index),
+            &self.node(level, 2 * index + 1),
+        )
+    }
+
+    /// Current root
+    pub fn root(&self) -> [u8; 32] {
+        self.parent(MERKLE_TREE_DEPTH - 1, 0)
+    }
+
+    /// Position of a leaf, if present
+    pub fn leaf_index(&self, leaf: &[u8; 32]) -> Option<u64> {
+        self.levels[0]
+            .iter()
+            .position(|l| l == leaf)
+            .map(|i| i as u64)
+    }
+
+    /// Membership proof for the leaf at `leaf_index` against the current root
+    pub fn proof(&self, leaf_index: u64) -> Result<MembershipProof> {
+        if leaf_index >= self.len() {
+            return Err(ClientError::InvalidNote(format!(
+                "Leaf {} is not in the tree",
+                leaf_index
+            )));
+        }
+
+        let mut index = leaf_index as usize;
+        let siblings = (0..MERKLE_TREE_DEPTH)
+            .map(|level| {
+                let sibling = self.node(level, index ^ 1);
+                index /= 2;
+                sibling
+            })
+            .collect();
+
+        Ok(MembershipProof {
+            leaf_index,
+            siblings,
+            root: self.root(),
+        })
+    }
+}
+
+/// Persisted form of a `MerkleSync`
+#[derive(BorshSerialize, BorshDeserialize)]
+struct MerkleSyncState {
+    leaves: Vec<[u8; 32]>,
+    last_signature: Option<String>,
+}
+
+/// Local copy of a privacy pool's commitment tree, kept in step with the
+/// program's `CommitmentInserted` events and persisted between runs
+pub struct MerkleSync {
+    pool: Pubkey,
+    merkle_tree: Pubkey,
+    storage_key: String,
+    storage: Arc<dyn ClientStorage>,
+    tree: IncrementalMerkleTree,
+    /// Newest transaction already scanned, so syncs only fetch what is new
+    last_signature: Option<Signature>,
+}
+
+impl MerkleSync {
+    /// Load the synced tree of `pool_id` from `storage`, or start empty
+    pub fn load(
+        program_id: &Pubkey,
+        pool_id: u64,
+        storage: Arc<dyn ClientStorage>,
+    ) -> Result<Self> {
+        let (pool, _) = find_pool_address(program_id, pool_id);
+        let (merkle_tree, _) = find_merkle_tree_address(program_id, pool_id);
+        let storage_key = format!("merkle_sync-{}-{}", program_id, pool_id);
+
+        let (tree, last_signature) = match storage.get(&storage_key)? {
+            Some(bytes) => {
+                let state = MerkleSyncState::try_from_slice(&bytes)?;
+                let last_signature = state
+                    .last_signature
+                    .map(|s| Signature::from_str(&s))
+                    .transpose()
+                    .map_err(anyhow::Error::from)?;
+                (
+                    IncrementalMerkleTree::from_leaves(&state.leaves)?,
+                    last_signature,
+                )
+            }
+            None => (IncrementalMerkleTree::new(), None),
+        };
+
+        Ok(Self {
+            pool,
+            merkle_tree,
+            storage_key,
+            storage,
+            tree,
+            last_signature,
+        })
+    }
+
+    pub fn tree(&self) -> &IncrementalMerkleTree {
+        &self.tree
+    }
+
+    /// Apply one event. Events already applied are skipped; a gap means an
+    /// event was missed and the tree can no longer match the chain.
+    pub fn apply_event(&mut self, event: &CommitmentInserted) -> Result<bool> {
+        if event.pool != self.pool || event.leaf_index < self.tree.len() {
+            return Ok(false);
+        }
+        if event.leaf_index > self.tree.len() {
+            return Err(anyhow!(
+                "Missing commitments {}..{} of pool {}",
+                self.tree.len(),
+                event.leaf_index,
+                self.pool
+            )
+            .into());
+        }
+
+        self.tree.append(event.commitment)?;
+        Ok(true)
+    }
+
+    /// Fetch events newer than the last sync, apply them and persist the
+    /// tree. Returns how many commitments were added.
+    pub async fn sync(&mut self, client: &UntraceClient) -> Result<usize> {
+        let (instructions, newest) = client
+            .fetch_inner_instructions(&self.merkle_tree, self.last_signature, &client.program_id)
+            .await?;
+
+        let mut applied = 0;
+        for event in instructions
+            .iter()
+            .filter_map(|data| parse_commitment_inserted(data))
+        {
+            if self.apply_event(&event)? {
+                applied += 1;
+            }
+        }
+
+        if newest.is_some() {
+            self.last_signature = newest;
+        }
+        self.save()?;
+
+        Ok(applied)
+    }
+
+    fn save(&self) -> Result<()> {
+        let state = MerkleSyncState {
+            leaves: self.tree.leaves().to_vec(),
+            last_signature: self.last_signature.map(|s| s.to_string()),
+        };
+        Ok(self.storage.put(&self.storage_key, &state.try_to_vec()?)?)
+    }
+
+    /// Membership proof of `commitment` against the synced root
+    pub fn proof(&self, commitment: &[u8; 32]) -> Result<MembershipProof> {
+        let leaf_index = self
+            .tree
+            .leaf_index(commitment)
+            .ok_or_else(|| ClientError::InvalidNote("Commitment not found in synced tree".into()))?;
+        self.tree.proof(leaf_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_proofs_verify_against_root() {
+        let mut tree = IncrementalMerkleTree::new();
+        let empty_root = tree.root();
+
+        let leaves: Vec<[u8; 32]> = (1..=5u8).map(|i| [i; 32]).collect();
+        for leaf in &leaves {
+            tree.append(*leaf).unwrap();
+        }
+        assert_ne!(tree.root(), empty_root);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(i as u64).unwrap();
+            assert!(proof.verify(leaf));
+            assert!(!proof.verify(&[9u8; 32]));
+        }
+        assert!(tree.proof(5).is_err());
+
+        // Rebuilding from leaves gives the same tree
+        assert_eq!(
+            IncrementalMerkleTree::from_leaves(&leaves).unwrap().root(),
+            tree.root()
+        );
+    }
+
+    #[test]
+    fn test_sync_state_persists_and_rejects_gaps() {
+        let program_id = Pubkey::new_unique();
+        let storage: Arc<dyn ClientStorage> = Arc::new(MemoryStorage::new());
+        let mut sync = MerkleSync::load(&program_id, 1, storage.clone()).unwrap();
+
+        let event = |leaf_index: u64| CommitmentInserted {
+            pool: find_pool_address(&program_id, 1).0,
+            leaf_index,
+            commitment: [leaf_index as u8 + 1; 32],
+            encrypted_note: Vec::new(),
+        };
+
+        assert!(sync.apply_event(&event(0)).unwrap());
+        assert!(sync.apply_event(&event(1)).unwrap());
+        assert!(!sync.apply_event(&event(1)).unwrap());
+        assert!(sync.apply_event(&event(3)).is_err());
+        sync.save().unwrap();
+
+        let reloaded = MerkleSync::load(&program_id, 1, storage).unwrap();
+        assert_eq!(reloaded.tree().root(), sync.tree().root());
+        assert!(reloaded.proof(&[2u8; 32]).unwrap().verify(&[2u8; 32]));
+    }
+}
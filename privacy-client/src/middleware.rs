@@ -0,0 +1,131 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use solana_sdk::{signature::Signature, transaction::Transaction};
+use untrace_anti_mev::{AntiMevService, JitterDistribution, RpcSlotSource, RpcSubmitter};
+use untrace_common::AntiMevConfig;
+
+/// Longest `AntiMevMiddleware` holds a transaction back, well within the
+/// lifetime of the blockhash it was signed with
+pub const MAX_SUBMISSION_DELAY: Duration = Duration::from_secs(30);
+
+/// Sends the client's signed transactions instead of its RPC node, such
+/// as through a MEV-protection layer. The client still confirms them and
+/// retries as it does its own sends.
+#[async_trait]
+pub trait SendMiddleware: fmt::Debug + Send + Sync {
+    /// Send a signed `transaction`, returning its signature
+    async fn send(&self, transaction: &Transaction) -> anyhow::Result<Signature>;
+}
+
+/// Sends through an `AntiMevService`, with the jitter and private
+/// endpoints it was set up with
+pub struct AntiMevMiddleware {
+    service: Arc<AntiMevService>,
+}
+
+impl AntiMevMiddleware {
+    pub fn new(service: Arc<AntiMevService>) -> Self {
+        Self { service }
+    }
+
+    /// Service that sends to `rpc_url` after the default jitter
+    pub fn for_rpc(rpc_url: &str) -> Self {
+        let service = AntiMevService::new(
+            AntiMevConfig::default(),
+            Arc::new(RpcSlotSource::new(rpc_url)),
+        )
+        .with_submitter(Arc::new(RpcSubmitter::new(rpc_url)))
+        .with_jitter(JitterDistribution::default());
+        Self::new(Arc::new(service))
+    }
+
+    pub fn service(&self) -> &Arc<AntiMevService> {
+        &self.service
+    }
+}
+
+impl fmt::Debug for AntiMevMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AntiMevMiddleware")
+    }
+}
+
+#[async_trait]
+impl SendMiddleware for AntiMevMiddleware {
+    async fn send(&self, transaction: &Transaction) -> anyhow::Result<Signature> {
+        // The service sleeps out its jitter and blocks on the send
+        let service = self.service.clone();
+        let transaction = transaction.clone();
+        let deadline = Instant::now() + MAX_SUBMISSION_DELAY;
+        tokio::task::spawn_blocking(move || service.submit(&transaction, Some(deadline))).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, transaction::Result as TransactionResult};
+    use std::sync::Mutex;
+    use untrace_anti_mev::{ManualSlotSource, Simulation, TransactionSubmitter};
+
+    #[derive(Default)]
+    struct RecordingSubmitter {
+        submitted: Mutex<Vec<Signature>>,
+    }
+
+    impl TransactionSubmitter for RecordingSubmitter {
+        fn latest_blockhash(&self) -> anyhow::Result<Hash> {
+            Ok(Hash::default())
+        }
+
+        fn simulate(&self, _transaction: &Transaction) -> anyhow::Result<Simulation> {
+            Ok(Simulation {
+                units_consumed: Some(0),
+                error: None,
+            })
+        }
+
+        fn submit(&self, transaction: &Transaction) -> anyhow::Result<Signature> {
+            self.submitted
+                .lock()
+                .unwrap()
+                .push(transaction.signatures[0]);
+            Ok(transaction.signatures[0])
+        }
+
+        fn signature_status(
+            &self,
+            _signature: &Signature,
+        ) -> anyhow::Result<Option<TransactionResult<()>>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_anti_mev_middleware() {
+        let submitter = Arc::new(RecordingSubmitter::default());
+        let service =
+            AntiMevService::new(AntiMevConfig::default(), Arc::new(ManualSlotSource::new(0)))
+                .with_submitter(submitter.clone())
+                .with_jitter(JitterDistribution::Uniform {
+                    min: Duration::from_millis(20),
+                    max: Duration::from_millis(40),
+                });
+        let middleware = AntiMevMiddleware::new(Arc::new(service));
+
+        let transaction = Transaction {
+            signatures: vec![Signature::new_unique()],
+            ..Transaction::default()
+        };
+        let started = Instant::now();
+        let signature = middleware.send(&transaction).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(signature, transaction.signatures[0]);
+        assert_eq!(*submitter.submitted.lock().unwrap(), vec![signature]);
+    }
+}
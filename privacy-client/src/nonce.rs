@@ -0,0 +1,200 @@
+use anyhow::anyhow;
+use solana_client::{
+    nonce_utils::nonblocking::{data_from_account, get_account_with_commitment},
+    rpc_config::RpcSendTransactionConfig,
+};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::State as NonceState,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+
+use crate::error::Result;
+use crate::{UntraceClient, CONFIRMATION_POLL_INTERVAL};
+
+/// Build an unsigned transaction against a durable nonce. The nonce advance
+/// comes first, so the transaction stays valid until the nonce is used
+/// rather than expiring with a recent blockhash.
+pub fn nonce_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+    nonce_authority: &Pubkey,
+    nonce: Hash,
+) -> Transaction {
+    let mut message = Message::new_with_nonce(
+        instructions.to_vec(),
+        Some(payer),
+        nonce_account,
+        nonce_authority,
+    );
+    message.recent_blockhash = nonce;
+    Transaction::new_unsigned(message)
+}
+
+/// Durable nonce accounts for transactions signed offline or held back
+/// before sending
+pub struct NonceClient<'a> {
+    client: &'a UntraceClient,
+}
+
+impl<'a> NonceClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self { client }
+    }
+
+    /// Create a nonce account controlled by `authority`
+    pub async fn create_nonce_account(
+        &self,
+        nonce_account: &dyn Signer,
+        authority: &Pubkey,
+    ) -> Result<Signature> {
+        let lamports = self
+            .client
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(NonceState::size())
+            .await?;
+        let instructions = system_instruction::create_nonce_account(
+            &self.client.payer.pubkey(),
+            &nonce_account.pubkey(),
+            authority,
+            lamports,
+        );
+
+        self.client
+            .send_transaction_with_signers(instructions, &[nonce_account])
+            .await
+    }
+
+    /// Current value of a nonce, used in place of a recent blockhash
+    pub async fn get_nonce(&self, nonce_account: &Pubkey) -> Result<Hash> {
+        let account = get_account_with_commitment(
+            &self.client.rpc_client,
+            nonce_account,
+            self.client.config.commitment,
+        )
+        .await
+        .map_err(anyhow::Error::from)?;
+        Ok(data_from_account(&account)
+            .map_err(anyhow::Error::from)?
+            .blockhash())
+    }
+
+    /// Build an unsigned transaction paid by the client's payer against the
+    /// nonce's current value, with the client's compute budget applied.
+    /// Sign it offline, then submit with `send_transaction`.
+    pub async fn build_transaction(
+        &self,
+        instructions: Vec<Instruction>,
+        nonce_account: &Pubkey,
+        nonce_authority: &Pubkey,
+    ) -> Result<Transaction> {
+        let instructions = self
+            .client
+            .with_compute_budget(instructions, &self.client.config)
+            .await?;
+        let nonce = self.get_nonce(nonce_account).await?;
+
+        Ok(nonce_transaction(
+            &instructions,
+            &self.client.payer.pubkey(),
+            nonce_account,
+            nonce_authority,
+            nonce,
+        ))
+    }
+
+    /// Submit a signed nonce transaction and wait for confirmation. Fails
+    /// once the nonce has moved on without the transaction landing.
+    pub async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+        nonce_account: &Pubkey,
+    ) -> Result<Signature> {
+        let config = &self.client.config;
+        let nonce = transaction.message.recent_blockhash;
+        let rpc = &self.client.rpc_client;
+
+        let signature = rpc
+            .send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: config.skip_preflight,
+                    preflight_commitment: Some(config.commitment.commitment),
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?;
+
+        loop {
+            match rpc
+                .get_signature_status_with_commitment(&signature, config.commitment)
+                .await?
+            {
+                Some(Ok(())) => return Ok(signature),
+                Some(Err(err)) => return Err(err.into()),
+                None => {
+                    if self.get_nonce(nonce_account).await? != nonce {
+                        return Err(anyhow!(
+                            "Nonce {} was advanced before {} confirmed",
+                            nonce_account,
+                            signature
+                        )
+                        .into());
+                    }
+                    tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Advance a nonce, invalidating transactions signed against its
+    /// current value
+    pub async fn advance_nonce(
+        &self,
+        nonce_account: &Pubkey,
+        authority: &dyn Signer,
+    ) -> Result<Signature> {
+        let instruction =
+            system_instruction::advance_nonce_account(nonce_account, &authority.pubkey());
+
+        self.client
+            .send_transaction_with_signers(vec![instruction], &[authority])
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::system_program;
+
+    #[test]
+    fn test_nonce_transaction_advances_nonce_first() {
+        let payer = Pubkey::new_unique();
+        let nonce_account = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+        let nonce = Hash::new_unique();
+
+        let transaction = nonce_transaction(&[transfer], &payer, &nonce_account, &payer, nonce);
+        let message = &transaction.message;
+
+        assert_eq!(message.recent_blockhash, nonce);
+        assert_eq!(message.instructions.len(), 2);
+
+        let advance = &message.instructions[0];
+        assert_eq!(
+            message.account_keys[advance.program_id_index as usize],
+            system_program::id()
+        );
+        assert_eq!(
+            message.account_keys[advance.accounts[0] as usize],
+            nonce_account
+        );
+    }
+}
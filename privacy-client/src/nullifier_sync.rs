@@ -0,0 +1,280 @@
+use anyhow::anyhow;
+use borsh::BorshDeserialize;
+use solana_sdk::{
+    instruction::AccountMeta,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program, sysvar,
+};
+use untrace_common::{
+    crypto,
+    wormhole::{MessageData, POSTED_MESSAGE_MAGIC},
+    NullifierRootMessage,
+};
+use untrace_privacy_program::{accounts, instruction, state::MAX_NULLIFIER_BATCH};
+
+use crate::cross_chain::CrossChainClient;
+use crate::error::{ClientError, Result};
+use crate::evm::EvmClient;
+use crate::shielded_pool::find_shielded_nullifier_address;
+use crate::wormhole::{
+    self, find_emitter_address, find_foreign_emitter_address, find_wormhole_config_address, Vaa,
+};
+use crate::UntraceClient;
+
+/// Remote nullifiers applied per transaction
+const NULLIFIERS_PER_TRANSACTION: usize = 4;
+
+/// Derive the PDA numbering the nullifier roots this chain posts
+pub fn find_nullifier_sync_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"nullifier_sync"], program_id)
+}
+
+/// Derive the PDA holding nullifier root `batch` received from
+/// `source_chain`
+pub fn find_nullifier_root_address(
+    program_id: &Pubkey,
+    source_chain: u16,
+    batch: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"nullifier_root",
+            &source_chain.to_le_bytes(),
+            &batch.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Path of each of `nullifiers` to `message`'s root, failing unless they
+/// are the batch the root was built over
+pub fn batch_paths(
+    message: &NullifierRootMessage,
+    nullifiers: &[[u8; 32]],
+) -> anyhow::Result<Vec<Vec<[u8; 32]>>> {
+    if nullifiers.len() != message.nullifier_count as usize
+        || crypto::merkle_root(nullifiers) != message.nullifier_root
+    {
+        return Err(anyhow!(
+            "Nullifiers do not match root {} from chain {}",
+            message.batch,
+            message.source_chain
+        ));
+    }
+    Ok((0..nullifiers.len())
+        .map(|index| crypto::merkle_path(nullifiers, index))
+        .collect())
+}
+
+/// Keeps shielded nullifiers in step across chains, so a note spent on one
+/// chain cannot also exit on another
+pub struct NullifierSyncClient<'a> {
+    client: &'a UntraceClient,
+    cross_chain: CrossChainClient<'a>,
+}
+
+impl<'a> NullifierSyncClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self {
+            client,
+            cross_chain: CrossChainClient::new(client),
+        }
+    }
+
+    /// Post the root of `nullifiers`, shielded nullifiers spent on Solana,
+    /// to the other chains, returning the message as posted
+    pub async fn post_root(
+        &self,
+        nullifiers: &[[u8; 32]],
+    ) -> Result<(Signature, NullifierRootMessage)> {
+        if nullifiers.is_empty() || nullifiers.len() > MAX_NULLIFIER_BATCH {
+            return Err(anyhow!(
+                "A nullifier root covers 1 to {} nullifiers, not {}",
+                MAX_NULLIFIER_BATCH,
+                nullifiers.len()
+            )
+            .into());
+        }
+
+        let core_bridge = self.cross_chain.wormhole()?.core_bridge;
+        let program_id = self.client.program_id;
+        let (emitter, _) = find_emitter_address(&program_id);
+        let message_account = Keypair::new();
+
+        let mut instruction = self.client.program_instruction(
+            accounts::PostNullifierRoot {
+                wormhole_config: find_wormhole_config_address(&program_id).0,
+                nullifier_sync: find_nullifier_sync_address(&program_id).0,
+                wormhole_bridge: wormhole::find_bridge_address(&core_bridge).0,
+                wormhole_message: message_account.pubkey(),
+                emitter,
+                wormhole_sequence: wormhole::find_sequence_address(&core_bridge, &emitter).0,
+                wormhole_fee_collector: wormhole::find_fee_collector_address(&core_bridge).0,
+                payer: self.client.payer.pubkey(),
+                clock: sysvar::clock::id(),
+                rent: sysvar::rent::id(),
+                wormhole_program: core_bridge,
+                system_program: system_program::id(),
+            },
+            instruction::PostNullifierRoot {
+                nullifiers: nullifiers.to_vec(),
+            },
+        );
+        instruction
+            .accounts
+            .extend(nullifiers.iter().map(|nullifier| {
+                AccountMeta::new_readonly(
+                    find_shielded_nullifier_address(&program_id, nullifier).0,
+                    false,
+                )
+            }));
+
+        let signature = self
+            .client
+            .send_transaction_with_signers(vec![instruction], &[&message_account])
+            .await?;
+
+        let account = self
+            .client
+            .rpc_client
+            .get_account(&message_account.pubkey())
+            .await?;
+        let posted = MessageData::from_account(&account.data, POSTED_MESSAGE_MAGIC)
+            .ok_or_else(|| anyhow!("Invalid posted message {}", message_account.pubkey()))?;
+        let message =
+            NullifierRootMessage::try_from_slice(&posted.payload).map_err(anyhow::Error::from)?;
+
+        Ok((signature, message))
+    }
+
+    /// Store a nullifier root another chain posted, carried by a signed VAA,
+    /// first posting the VAA to the core bridge if nobody has yet
+    pub async fn receive_root(&self, vaa: &[u8]) -> Result<(Signature, NullifierRootMessage)> {
+        let vaa = Vaa::parse(vaa)?;
+        let message =
+            NullifierRootMessage::try_from_slice(&vaa.payload).map_err(anyhow::Error::from)?;
+        let posted_vaa = self.cross_chain.ensure_vaa_posted(&vaa).await?;
+
+        let program_id = self.client.program_id;
+        let instruction = self.client.program_instruction(
+            accounts::ReceiveNullifierRoot {
+                wormhole_config: find_wormhole_config_address(&program_id).0,
+                posted_vaa,
+                foreign_emitter: find_foreign_emitter_address(&program_id, vaa.emitter_chain).0,
+                nullifier_root: find_nullifier_root_address(
+                    &program_id,
+                    message.source_chain,
+                    message.batch,
+                )
+                .0,
+                payer: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::ReceiveNullifierRoot {
+                message: message.clone(),
+            },
+        );
+
+        let signature = self.client.send_transaction(vec![instruction]).await?;
+        Ok((signature, message))
+    }
+
+    /// Mark `nullifiers`, the batch behind a received `message`, spent on
+    /// Solana, skipping those already spent here
+    pub async fn apply_nullifiers(
+        &self,
+        message: &NullifierRootMessage,
+        nullifiers: &[[u8; 32]],
+    ) -> Result<Vec<Signature>> {
+        let paths = batch_paths(message, nullifiers)?;
+        let program_id = self.client.program_id;
+        let (nullifier_root, _) =
+            find_nullifier_root_address(&program_id, message.source_chain, message.batch);
+
+        let mut instructions = Vec::new();
+        for (index, (nullifier, proof)) in nullifiers.iter().zip(paths).enumerate() {
+            if self.is_spent_locally(nullifier).await? {
+                continue;
+            }
+            instructions.push(self.client.program_instruction(
+                accounts::ApplyRemoteNullifier {
+                    nullifier_root,
+                    nullifier_account: find_shielded_nullifier_address(&program_id, nullifier).0,
+                    payer: self.client.payer.pubkey(),
+                    system_program: system_program::id(),
+                },
+                instruction::ApplyRemoteNullifier {
+                    nullifier: *nullifier,
+                    leaf_index: index as u32,
+                    proof,
+                },
+            ));
+        }
+
+        let mut signatures = Vec::new();
+        for batch in instructions.chunks(NULLIFIERS_PER_TRANSACTION) {
+            signatures.push(self.client.send_transaction(batch.to_vec()).await?);
+        }
+        Ok(signatures)
+    }
+
+    /// Whether `nullifier` is spent on Solana, here or applied from another
+    /// chain's root
+    pub async fn is_spent_locally(&self, nullifier: &[u8; 32]) -> Result<bool> {
+        let (nullifier_account, _) =
+            find_shielded_nullifier_address(&self.client.program_id, nullifier);
+        let account = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&nullifier_account, self.client.config.commitment)
+            .await?
+            .value;
+        Ok(account.is_some_and(|account| !account.data.is_empty()))
+    }
+
+    /// Check `nullifier` is spent neither on Solana nor on any of `remote`,
+    /// before building an exit proof for a note that may have been spent on
+    /// another chain whose root has not reached Solana yet
+    pub async fn ensure_unspent(&self, nullifier: &[u8; 32], remote: &[&EvmClient]) -> Result<()> {
+        if self.is_spent_locally(nullifier).await? {
+            return Err(ClientError::NullifierSpent);
+        }
+        for chain in remote {
+            if chain.is_nullifier_spent(nullifier).await? {
+                return Err(ClientError::NullifierSpent);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_paths_match_root() {
+        let nullifiers = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let message = NullifierRootMessage {
+            source_chain: 1,
+            batch: 4,
+            nullifier_root: crypto::merkle_root(&nullifiers),
+            nullifier_count: 3,
+        };
+
+        let paths = batch_paths(&message, &nullifiers).unwrap();
+        for (index, (nullifier, path)) in nullifiers.iter().zip(&paths).enumerate() {
+            assert_eq!(path.len(), crypto::merkle_depth(nullifiers.len()));
+            assert!(crypto::verify_merkle_proof(
+                nullifier,
+                path,
+                &message.nullifier_root,
+                index as u32
+            ));
+        }
+
+        assert!(batch_paths(&message, &nullifiers[..2]).is_err());
+        assert!(batch_paths(&message, &[[1u8; 32], [3u8; 32], [2u8; 32]]).is_err());
+    }
+}
@@ -1,15 +1,242 @@
-use anyhow::Result;
+use std::collections::HashMap;
+
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use anyhow::anyhow;
+use solana_client::{
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
-    pubkey::Pubkey,
-    signature::Signature,
-    system_program,
-    sysvar::clock,
+    account::Account, hash::hash, instruction::Instruction, pubkey::Pubkey,
+    signature::Signature, system_program, sysvar::clock,
+};
+use untrace_common::{crypto, groth16::Circuit, NATIVE_ASSET_ID};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{AdminConfigAccount, PoolConfigAccount},
 };
-use untrace_common::crypto;
 
+use crate::error::{ClientError, Result};
+use crate::admin::{find_admin_config_address, find_verifier_address};
+use crate::events::{decode_u64_return, find_event_authority_address};
+use crate::merkle_sync::MembershipProof;
+use crate::proof_cache::WITHDRAW_CIRCUIT;
 use crate::UntraceClient;
 
+/// Recipient of a privacy pool withdrawal with instruction data `data`,
+/// or `None` if it is not a withdrawal
+pub fn decode_withdraw_recipient(data: &[u8]) -> Option<Pubkey> {
+    let mut args = data.strip_prefix(&instruction::Withdraw::DISCRIMINATOR)?;
+    instruction::Withdraw::deserialize(&mut args)
+        .ok()
+        .map(|withdraw| withdraw.recipient)
+}
+
+/// Derive the PDA of a privacy pool
+pub fn find_pool_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"privacy_pool", &pool_id.to_le_bytes()], program_id)
+}
+
+/// Derive the PDA holding a deposit commitment
+pub fn find_commitment_address(
+    program_id: &Pubkey,
+    pool_id: u64,
+    commitment: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"commitment", &pool_id.to_le_bytes(), commitment],
+        program_id,
+    )
+}
+
+/// Derive the PDA of a pool's commitment tree
+pub fn find_merkle_tree_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"merkle_tree", &pool_id.to_le_bytes()], program_id)
+}
+
+/// Derive the PDA marking a pool nullifier as spent
+pub fn find_nullifier_address(
+    program_id: &Pubkey,
+    pool_id: u64,
+    nullifier: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"nullifier", &pool_id.to_le_bytes(), nullifier],
+        program_id,
+    )
+}
+
+/// Derive the PDA holding a pool's deposit limits
+pub fn find_pool_config_address(program_id: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pool_config", &pool_id.to_le_bytes()], program_id)
+}
+
+/// Derive the PDA tracking an address's deposits into a pool this epoch
+pub fn find_deposit_tracker_address(
+    program_id: &Pubkey,
+    pool_id: u64,
+    depositor: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"deposit_tracker", &pool_id.to_le_bytes(), depositor.as_ref()],
+        program_id,
+    )
+}
+
+/// Depth of the on-chain commitment tree
+pub const MERKLE_TREE_DEPTH: usize = 20;
+
+/// Number of recent roots kept by the on-chain tree
+pub const ROOT_HISTORY_SIZE: usize = 32;
+
+// Field offsets of the zero-copy tree account (after the discriminator)
+const TREE_ROOT_INDEX_OFFSET: usize = 8 + 8 + 8;
+const TREE_ROOTS_OFFSET: usize = TREE_ROOT_INDEX_OFFSET + 8 + 2 * MERKLE_TREE_DEPTH * 32;
+
+/// Read the most recent root from raw merkle tree account data
+pub fn decode_current_root(data: &[u8]) -> anyhow::Result<[u8; 32]> {
+    if data.len() < TREE_ROOTS_OFFSET + ROOT_HISTORY_SIZE * 32 {
+        return Err(anyhow!("Truncated merkle tree account"));
+    }
+
+    let root_index = u64::from_le_bytes(
+        data[TREE_ROOT_INDEX_OFFSET..TREE_ROOT_INDEX_OFFSET + 8].try_into()?,
+    ) as usize;
+    if root_index >= ROOT_HISTORY_SIZE {
+        return Err(anyhow!("Corrupt merkle tree root index"));
+    }
+
+    let start = TREE_ROOTS_OFFSET + root_index * 32;
+    Ok(data[start..start + 32].try_into()?)
+}
+
+/// Read every root still in the on-chain root history
+pub fn decode_root_history(data: &[u8]) -> anyhow::Result<Vec<[u8; 32]>> {
+    if data.len() < TREE_ROOTS_OFFSET + ROOT_HISTORY_SIZE * 32 {
+        return Err(anyhow!("Truncated merkle tree account"));
+    }
+
+    Ok(data[TREE_ROOTS_OFFSET..TREE_ROOTS_OFFSET + ROOT_HISTORY_SIZE * 32]
+        .chunks_exact(32)
+        .map(|root| root.try_into().unwrap())
+        .filter(|root| *root != [0u8; 32])
+        .collect())
+}
+
+/// Newest on-chain account layout version this client understands
+pub const SUPPORTED_ACCOUNT_VERSION: u8 = 1;
+
+/// Size of a pool account written before version fields existed
+const POOL_ACCOUNT_V0_LEN: usize = 8 + 8 + 32 + 8 + 8 + 32;
+
+/// Privacy pool state decoded from any supported account version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolAccount {
+    /// Layout version the account is stored with (0 = pre-versioning)
+    pub version: u8,
+    pub pool_id: u64,
+    pub commitment_root: [u8; 32],
+    pub commitment_count: u64,
+    pub min_pool_size: u64,
+    pub authority: Pubkey,
+}
+
+impl PoolAccount {
+    /// Whether the account should be upgraded with `migrate_pool`
+    pub fn needs_migration(&self) -> bool {
+        self.version < SUPPORTED_ACCOUNT_VERSION
+    }
+}
+
+/// Anchor account discriminator for `PrivacyPoolAccount`
+pub fn pool_account_discriminator() -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(b"account:PrivacyPoolAccount").to_bytes()[..8]);
+    discriminator
+}
+
+/// Decode raw pool account data, accepting the legacy unversioned layout
+pub fn decode_pool_account(data: &[u8]) -> anyhow::Result<PoolAccount> {
+    if data.len() < 8 || data[..8] != pool_account_discriminator() {
+        return Err(anyhow!("Not a privacy pool account"));
+    }
+
+    let (version, mut fields) = if data.len() == POOL_ACCOUNT_V0_LEN {
+        (0u8, &data[8..])
+    } else {
+        let version = *data.get(8).ok_or_else(|| anyhow!("Truncated pool account"))?;
+        (version, &data[9..])
+    };
+
+    if version > SUPPORTED_ACCOUNT_VERSION {
+        return Err(anyhow!("Unsupported pool account version {}", version));
+    }
+    if fields.len() < POOL_ACCOUNT_V0_LEN - 8 {
+        return Err(anyhow!("Truncated pool account"));
+    }
+
+    let mut take = |len: usize| {
+        let (head, tail) = fields.split_at(len);
+        fields = tail;
+        head
+    };
+
+    let pool_id = u64::from_le_bytes(take(8).try_into()?);
+    let commitment_root: [u8; 32] = take(32).try_into()?;
+    let commitment_count = u64::from_le_bytes(take(8).try_into()?);
+    let min_pool_size = u64::from_le_bytes(take(8).try_into()?);
+    let authority = Pubkey::try_from(take(32))?;
+
+    Ok(PoolAccount {
+        version,
+        pool_id,
+        commitment_root,
+        commitment_count,
+        min_pool_size,
+        authority,
+    })
+}
+
+/// Summary of a privacy pool for wallets choosing where to deposit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolInfo {
+    pub address: Pubkey,
+    pub pool_id: u64,
+    /// Only accepted deposit amount, when the pool's limits pin one
+    pub denomination: Option<u64>,
+    /// Asset held by the pool; privacy pools hold native SOL
+    pub asset: Pubkey,
+    /// Deposits so far, i.e. the pool's anonymity set size
+    pub commitment_count: u64,
+    pub min_pool_size: u64,
+    /// Whether deposits are paused protocol-wide
+    pub paused: bool,
+}
+
+impl PoolInfo {
+    /// Combine a pool with its deposit limits, if it has any
+    pub fn new(
+        address: Pubkey,
+        pool: &PoolAccount,
+        config: Option<&PoolConfigAccount>,
+        paused: bool,
+    ) -> Self {
+        let denomination = config
+            .filter(|config| config.min_deposit > 0 && config.min_deposit == config.max_deposit)
+            .map(|config| config.min_deposit);
+
+        Self {
+            address,
+            pool_id: pool.pool_id,
+            denomination,
+            asset: NATIVE_ASSET_ID,
+            commitment_count: pool.commitment_count,
+            min_pool_size: pool.min_pool_size,
+            paused,
+        }
+    }
+}
+
 pub struct PrivacyPoolClient<'a> {
     client: &'a UntraceClient,
 }
@@ -25,44 +252,193 @@ impl<'a> PrivacyPoolClient<'a> {
         pool_id: u64,
         min_pool_size: u64,
     ) -> Result<Signature> {
-        let (pool_pda, _bump) = Pubkey::find_program_address(
-            &[b"privacy_pool", &pool_id.to_le_bytes()],
-            &self.client.program_id,
+        let (pool_pda, _bump) = find_pool_address(&self.client.program_id, pool_id);
+        let (merkle_tree, _) = find_merkle_tree_address(&self.client.program_id, pool_id);
+        let (pool_config, _) = find_pool_config_address(&self.client.program_id, pool_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializePool {
+                privacy_pool: pool_pda,
+                merkle_tree,
+                pool_config,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializePool {
+                pool_id,
+                min_pool_size,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Create a default (unlimited) config for a pool created before pool
+    /// configs existed. Limits are changed through a timelocked admin action.
+    pub async fn initialize_pool_config(&self, pool_id: u64) -> Result<Signature> {
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+        let (pool_config, _) = find_pool_config_address(&self.client.program_id, pool_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializePoolConfig {
+                privacy_pool: pool_pda,
+                pool_config,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializePoolConfig {},
         );
 
-        let mut data = vec![0u8]; // Instruction discriminator
-        data.extend_from_slice(&pool_id.to_le_bytes());
-        data.extend_from_slice(&min_pool_size.to_le_bytes());
-
-        let instruction = Instruction {
-            program_id: self.client.program_id,
-            accounts: vec![
-                AccountMeta::new(pool_pda, false),
-                AccountMeta::new(self.client.payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-            data,
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Create the commitment tree of a pool created before trees existed,
+    /// such as one upgraded by `migrate_account`. Deposits and withdrawals
+    /// need it.
+    pub async fn initialize_merkle_tree(&self, pool_id: u64) -> Result<Signature> {
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+        let (merkle_tree, _) = find_merkle_tree_address(&self.client.program_id, pool_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeMerkleTree {
+                privacy_pool: pool_pda,
+                merkle_tree,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeMerkleTree {},
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Fetch the current commitment tree root of a pool
+    pub async fn get_current_root(&self, pool_id: u64) -> Result<[u8; 32]> {
+        let (merkle_tree, _) = find_merkle_tree_address(&self.client.program_id, pool_id);
+        let account = self.client.rpc_client.get_account(&merkle_tree).await?;
+        Ok(decode_current_root(&account.data)?)
+    }
+
+    /// Fetch and decode a pool account
+    pub async fn get_pool(&self, pool_id: u64) -> Result<PoolAccount> {
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+        let account = self.client.rpc_client.get_account(&pool_pda).await?;
+        Ok(decode_pool_account(&account.data)?)
+    }
+
+    /// Fetch a pool's deposit limits and withdrawal rules, reusing a
+    /// recently fetched copy
+    pub async fn get_pool_config(&self, pool_id: u64) -> Result<PoolConfigAccount> {
+        let (pool_config, _) = find_pool_config_address(&self.client.program_id, pool_id);
+        let account = self.client.get_account_cached(&pool_config).await?;
+        PoolConfigAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("Invalid pool config account: {}", e).into())
+    }
+
+    /// List every privacy pool of the program, ordered by pool ID
+    pub async fn list_pools(&self) -> Result<Vec<PoolInfo>> {
+        let pools = self.program_accounts(&pool_account_discriminator()).await?;
+        let configs: HashMap<u64, PoolConfigAccount> = self
+            .program_accounts(&PoolConfigAccount::DISCRIMINATOR)
+            .await?
+            .into_iter()
+            .filter_map(|(_, account)| {
+                PoolConfigAccount::try_deserialize(&mut account.data.as_slice()).ok()
+            })
+            .map(|config| (config.pool_id, config))
+            .collect();
+
+        let (admin_config, _) = find_admin_config_address(&self.client.program_id);
+        let admin_account = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&admin_config, self.client.config.commitment)
+            .await?
+            .value;
+        let paused = match admin_account {
+            Some(account) => {
+                AdminConfigAccount::try_deserialize(&mut account.data.as_slice())
+                    .map_err(|e| anyhow!("Invalid admin config account: {}", e))?
+                    .paused
+            }
+            None => false,
         };
 
+        let mut infos: Vec<PoolInfo> = pools
+            .into_iter()
+            .filter_map(|(address, account)| {
+                let pool = decode_pool_account(&account.data).ok()?;
+                Some(PoolInfo::new(
+                    address,
+                    &pool,
+                    configs.get(&pool.pool_id),
+                    paused,
+                ))
+            })
+            .collect();
+        infos.sort_by_key(|info| info.pool_id);
+
+        Ok(infos)
+    }
+
+    /// Program accounts whose data starts with an account discriminator
+    async fn program_accounts(&self, discriminator: &[u8]) -> Result<Vec<(Pubkey, Account)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                0,
+                discriminator,
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                commitment: Some(self.client.config.commitment),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+
+        Ok(self
+            .client
+            .rpc_client
+            .get_program_accounts_with_config(&self.client.program_id, config)
+            .await?)
+    }
+
+    /// Upgrade a pool account stored with a previous layout
+    pub async fn migrate_pool(&self, pool_id: u64) -> Result<Signature> {
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::MigrateAccount {
+                target: pool_pda,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::MigrateAccount {},
+        );
+
         self.client.send_transaction(vec![instruction]).await
     }
 
-    /// Deposit funds into privacy pool
+    /// Deposit funds into privacy pool, returning the commitment, its
+    /// randomness and the leaf index the program assigned to it
     pub async fn deposit(
         &self,
         pool_id: u64,
         recipient: &Pubkey,
         amount: u64,
-    ) -> Result<(Signature, [u8; 32], [u8; 32])> {
+    ) -> Result<(Signature, [u8; 32], [u8; 32], u64)> {
         let (commitment, randomness) = self.client.generate_commitment(recipient, amount);
 
-        let (pool_pda, _) = Pubkey::find_program_address(
-            &[b"privacy_pool", &pool_id.to_le_bytes()],
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+        let (commitment_account, _) =
+            find_commitment_address(&self.client.program_id, pool_id, &commitment);
+        let (merkle_tree, _) = find_merkle_tree_address(&self.client.program_id, pool_id);
+        let (pool_config, _) = find_pool_config_address(&self.client.program_id, pool_id);
+        let (deposit_tracker, _) = find_deposit_tracker_address(
             &self.client.program_id,
+            pool_id,
+            &self.client.payer.pubkey(),
         );
 
-        let commitment_account = Pubkey::new_unique();
-
         // Encrypt the deposit data
         let mut plaintext = Vec::new();
         plaintext.extend_from_slice(&recipient.to_bytes());
@@ -73,73 +449,224 @@ impl<'a> PrivacyPoolClient<'a> {
         let (encrypted_data, _tag) = crypto::encrypt_data(&plaintext, &shared_secret, &nonce)
             .map_err(|e| anyhow::anyhow!(e))?;
 
-        let mut data = vec![1u8]; // Instruction discriminator
-        data.extend_from_slice(&commitment);
-        data.extend_from_slice(&(encrypted_data.len() as u32).to_le_bytes());
-        data.extend_from_slice(&encrypted_data);
-
-        let instruction = Instruction {
-            program_id: self.client.program_id,
-            accounts: vec![
-                AccountMeta::new(pool_pda, false),
-                AccountMeta::new(commitment_account, false),
-                AccountMeta::new(self.client.payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-            data,
-        };
+        let program_id = self.client.program_id;
+        let instruction = self.client.program_instruction(
+            accounts::Deposit {
+                privacy_pool: pool_pda,
+                commitment_account,
+                merkle_tree,
+                pool_config,
+                deposit_tracker,
+                depositor: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::Deposit {
+                commitment,
+                amount,
+                note_key: crypto::note_key(&recipient.to_bytes(), &randomness),
+                encrypted_data,
+            },
+        );
 
         let signature = self.client.send_transaction(vec![instruction]).await?;
+        let leaf_index = decode_u64_return(&self.client.get_return_data(&signature).await?)?;
 
-        Ok((signature, commitment, randomness))
+        Ok((signature, commitment, randomness, leaf_index))
     }
 
-    /// Withdraw funds from privacy pool
+    /// Withdraw the `amount` lamports deposited under `commitment`,
+    /// proving its membership with a proof from a synced `MerkleSync`
     pub async fn withdraw(
         &self,
         pool_id: u64,
         commitment: &[u8; 32],
         secret: &[u8],
+        amount: u64,
         recipient: &Pubkey,
+        proof: &MembershipProof,
     ) -> Result<Signature> {
+        let instruction = self
+            .withdraw_instruction(pool_id, commitment, secret, amount, recipient, proof)
+            .await?;
+
+        self.client
+            .send_paid_transaction(vec![instruction], &self.client.config)
+            .await
+    }
+
+    /// Withdrawal instruction of `withdraw`, for a transaction sent some
+    /// other way, such as one co-signed offline
+    pub async fn withdraw_instruction(
+        &self,
+        pool_id: u64,
+        commitment: &[u8; 32],
+        secret: &[u8],
+        amount: u64,
+        recipient: &Pubkey,
+        proof: &MembershipProof,
+    ) -> Result<Instruction> {
+        if !proof.verify(commitment) {
+            return Err(ClientError::InvalidNote(
+                "Membership proof does not match commitment".to_string(),
+            ));
+        }
+
+        // The program only accepts roots still in its history
+        let (merkle_tree, _) = find_merkle_tree_address(&self.client.program_id, pool_id);
+        let tree_account = self.client.rpc_client.get_account(&merkle_tree).await?;
+        if !decode_root_history(&tree_account.data)?.contains(&proof.root) {
+            return Err(ClientError::InvalidNote(
+                "Synced tree is stale; sync before withdrawing".to_string(),
+            ));
+        }
+
         let nullifier = self.client.generate_nullifier(secret, commitment);
 
-        let (pool_pda, _) = Pubkey::find_program_address(
-            &[b"privacy_pool", &pool_id.to_le_bytes()],
-            &self.client.program_id,
-        );
+        let (pool_pda, _) = find_pool_address(&self.client.program_id, pool_id);
+        let (nullifier_account, _) =
+            find_nullifier_address(&self.client.program_id, pool_id, &nullifier);
 
-        let nullifier_account = Pubkey::new_unique();
+        // The program checks the root against its history instead of
+        // re-hashing the path
+        let root = proof.root;
 
         // Generate ZK proof
+        let public_inputs =
+            crypto::withdraw_inputs(&root, amount, &recipient.to_bytes(), &nullifier);
         let mut secret_hash = [0u8; 32];
         secret_hash[..secret.len().min(32)].copy_from_slice(&secret[..secret.len().min(32)]);
-        let zk_proof = crypto::generate_zk_proof(commitment, &nullifier, &secret_hash);
-
-        // Generate merkle proof (simplified)
-        let merkle_proof = vec![[0u8; 32]; 10];
-
-        let mut data = vec![2u8]; // Instruction discriminator
-        data.extend_from_slice(&nullifier);
-        data.extend_from_slice(&recipient.to_bytes());
-        data.extend_from_slice(&(zk_proof.len() as u32).to_le_bytes());
-        data.extend_from_slice(&zk_proof);
-        data.extend_from_slice(&(merkle_proof.len() as u32).to_le_bytes());
-        for proof_element in merkle_proof {
-            data.extend_from_slice(&proof_element);
-        }
+        let zk_proof = self.client.proof_cache.get_or_prove(
+            WITHDRAW_CIRCUIT,
+            &[&public_inputs],
+            || crypto::generate_zk_proof(&public_inputs, &nullifier, &secret_hash),
+        )?;
+
+        Ok(self.client.program_instruction(
+            accounts::Withdraw {
+                privacy_pool: pool_pda,
+                merkle_tree,
+                nullifier_account,
+                recipient: *recipient,
+                withdrawer: self.client.rent_payer(),
+                system_program: system_program::id(),
+                verifier: find_verifier_address(&self.client.program_id, Circuit::Withdraw).0,
+            },
+            instruction::Withdraw {
+                nullifier,
+                recipient: *recipient,
+                amount,
+                zk_proof,
+                root,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commitment_address_is_deterministic() {
+        let program_id = Pubkey::new_unique();
+        let commitment = [7u8; 32];
+
+        let (first, bump) = find_commitment_address(&program_id, 1, &commitment);
+        let (second, _) = find_commitment_address(&program_id, 1, &commitment);
+        assert_eq!(first, second);
+
+        let (other_pool, _) = find_commitment_address(&program_id, 2, &commitment);
+        assert_ne!(first, other_pool);
 
-        let instruction = Instruction {
-            program_id: self.client.program_id,
-            accounts: vec![
-                AccountMeta::new(pool_pda, false),
-                AccountMeta::new(nullifier_account, false),
-                AccountMeta::new(self.client.payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-            data,
+        let expected = Pubkey::create_program_address(
+            &[b"commitment", &1u64.to_le_bytes(), &commitment, &[bump]],
+            &program_id,
+        )
+        .unwrap();
+        assert_eq!(first, expected);
+    }
+
+    fn encode_pool_fields(authority: &Pubkey) -> Vec<u8> {
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&9u64.to_le_bytes());
+        fields.extend_from_slice(&[1u8; 32]);
+        fields.extend_from_slice(&25u64.to_le_bytes());
+        fields.extend_from_slice(&10u64.to_le_bytes());
+        fields.extend_from_slice(&authority.to_bytes());
+        fields
+    }
+
+    #[test]
+    fn test_decode_legacy_and_current_pool_layouts() {
+        let authority = Pubkey::new_unique();
+
+        let mut legacy = pool_account_discriminator().to_vec();
+        legacy.extend(encode_pool_fields(&authority));
+        let decoded = decode_pool_account(&legacy).unwrap();
+        assert_eq!(decoded.version, 0);
+        assert!(decoded.needs_migration());
+
+        let mut current = pool_account_discriminator().to_vec();
+        current.push(SUPPORTED_ACCOUNT_VERSION);
+        current.extend(encode_pool_fields(&authority));
+        let upgraded = decode_pool_account(&current).unwrap();
+        assert!(!upgraded.needs_migration());
+
+        assert_eq!(decoded.pool_id, 9);
+        assert_eq!(decoded.commitment_count, upgraded.commitment_count);
+        assert_eq!(decoded.authority, upgraded.authority);
+
+        let mut future = pool_account_discriminator().to_vec();
+        future.push(SUPPORTED_ACCOUNT_VERSION + 1);
+        future.extend(encode_pool_fields(&authority));
+        assert!(decode_pool_account(&future).is_err());
+    }
+
+    #[test]
+    fn test_pool_info_denomination() {
+        let mut data = pool_account_discriminator().to_vec();
+        data.push(SUPPORTED_ACCOUNT_VERSION);
+        data.extend(encode_pool_fields(&Pubkey::new_unique()));
+        let pool = decode_pool_account(&data).unwrap();
+
+        let mut config = PoolConfigAccount {
+            version: 1,
+            pool_id: 9,
+            min_deposit: 1_000_000,
+            max_deposit: 1_000_000,
+            epoch_deposit_cap: 0,
+            bump: 255,
         };
+        let info = PoolInfo::new(Pubkey::new_unique(), &pool, Some(&config), true);
+        assert_eq!(info.denomination, Some(1_000_000));
+        assert_eq!(info.commitment_count, 25);
+        assert!(info.paused);
 
-        self.client.send_transaction(vec![instruction]).await
+        // A range of amounts, or no config at all, has no denomination
+        config.max_deposit = 0;
+        let info = PoolInfo::new(Pubkey::new_unique(), &pool, Some(&config), false);
+        assert_eq!(info.denomination, None);
+        assert_eq!(PoolInfo::new(info.address, &pool, None, false).denomination, None);
+    }
+
+    #[test]
+    fn test_decode_withdraw_recipient() {
+        let recipient = Pubkey::new_unique();
+        let withdraw = instruction::Withdraw {
+            nullifier: [3u8; 32],
+            recipient,
+            amount: 500,
+            zk_proof: vec![1, 2, 3],
+            root: [4u8; 32],
+        };
+        let data = anchor_lang::InstructionData::data(&withdraw);
+        assert_eq!(decode_withdraw_recipient(&data), Some(recipient));
+
+        let migrate = anchor_lang::InstructionData::data(&instruction::MigrateAccount {});
+        assert_eq!(decode_withdraw_recipient(&migrate), None);
+        assert_eq!(decode_withdraw_recipient(&data[..20]), None);
     }
 }
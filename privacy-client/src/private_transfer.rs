@@ -1,14 +1,158 @@
-use anyhow::Result;
+use std::ops::Range;
+
+use anyhow::anyhow;
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
-    pubkey::Pubkey,
-    signature::Signature,
-    system_program,
+    instruction::Instruction, message::Message, packet::PACKET_DATA_SIZE, pubkey::Pubkey,
+    signature::Signature, system_program,
+};
+use untrace_common::{crypto, groth16::Circuit, PrivacyLevel, ShieldedNote};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{MerkleTreeAccount, PrivateTransferArgs},
 };
-use untrace_common::{crypto, PrivacyLevel};
 
+use crate::admin::{find_admin_config_address, find_verifier_address};
+use crate::bridge_encryption::encrypt_for_recipient;
+use crate::cost::{DEFAULT_COMPUTE_UNIT_LIMIT, MAX_COMPUTE_UNIT_LIMIT};
+use crate::error::Result;
+use crate::fees::compute_budget_instructions;
+use crate::proof_cache::TRANSFER_CIRCUIT;
+use crate::shielded_pool::{
+    find_shielded_asset_address, find_shielded_commitment_address,
+    find_shielded_merkle_tree_address, find_shielded_nullifier_address, find_shielded_pool_address,
+    ShieldedPoolClient,
+};
+use crate::events::find_event_authority_address;
+use crate::scanner::encrypt_note;
 use crate::UntraceClient;
 
+/// Input and output notes per transfer, matching the on-chain circuit
+pub const TRANSFER_NOTES: usize = 2;
+
+/// Derive the PDA recording a private transfer, keyed by its first nullifier
+pub fn find_private_transfer_address(program_id: &Pubkey, nullifier: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"private_transfer", nullifier], program_id)
+}
+
+fn random_note(asset_id: Pubkey, amount: u64, owner: Pubkey) -> ShieldedNote {
+    let mut randomness = [0u8; 32];
+    rand::Rng::fill(&mut rand::thread_rng(), &mut randomness);
+
+    ShieldedNote {
+        asset_id,
+        amount,
+        owner,
+        randomness,
+    }
+}
+
+/// Pad `inputs` with zero-value dummy notes and split their value into a
+/// note for `recipient` and a change note for `change_owner`.
+///
+/// Returns `(inputs, [recipient_note, change_note])`.
+pub fn build_transfer_notes(
+    inputs: &[ShieldedNote],
+    recipient: &Pubkey,
+    amount: u64,
+    change_owner: &Pubkey,
+) -> anyhow::Result<(
+    [ShieldedNote; TRANSFER_NOTES],
+    [ShieldedNote; TRANSFER_NOTES],
+)> {
+    let asset_id = inputs
+        .first()
+        .ok_or_else(|| anyhow!("At least one input note is required"))?
+        .asset_id;
+    if inputs.len() > TRANSFER_NOTES {
+        return Err(anyhow!("At most {} input notes per transfer", TRANSFER_NOTES));
+    }
+    if inputs.iter().any(|note| note.asset_id != asset_id) {
+        return Err(anyhow!("Input notes must share one asset"));
+    }
+
+    let total = inputs
+        .iter()
+        .try_fold(0u64, |sum, note| sum.checked_add(note.amount))
+        .ok_or_else(|| anyhow!("Input note values overflow"))?;
+    let change = total
+        .checked_sub(amount)
+        .ok_or_else(|| anyhow!("Insufficient note value: {} < {}", total, amount))?;
+
+    let padded = [
+        inputs[0].clone(),
+        inputs
+            .get(1)
+            .cloned()
+            .unwrap_or_else(|| random_note(asset_id, 0, *change_owner)),
+    ];
+    let outputs = [
+        random_note(asset_id, amount, *recipient),
+        random_note(asset_id, change, *change_owner),
+    ];
+
+    Ok((padded, outputs))
+}
+
+/// Serialized size of a transaction carrying `instructions`, signed by
+/// every required signer
+fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    // Short-vec signature count, then one signature per required signer
+    1 + 64 * message.header.num_required_signatures as usize + message.serialize().len()
+}
+
+/// Split `instructions` into consecutive runs that each fit in one
+/// transaction after `budget` (the compute budget instructions), with at
+/// most `max_per_transaction` per run. An instruction too large to share a
+/// transaction gets one of its own.
+pub fn pack_instructions(
+    instructions: &[Instruction],
+    budget: &[Instruction],
+    payer: &Pubkey,
+    max_per_transaction: usize,
+) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < instructions.len() {
+        let mut end = start + 1;
+        while end < instructions.len() && end - start < max_per_transaction {
+            let candidate: Vec<Instruction> = budget
+                .iter()
+                .chain(&instructions[start..=end])
+                .cloned()
+                .collect();
+            if transaction_size(&candidate, payer) > PACKET_DATA_SIZE {
+                break;
+            }
+            end += 1;
+        }
+        runs.push(start..end);
+        start = end;
+    }
+    runs
+}
+
+/// One transfer of a `batch_transfer`
+#[derive(Debug, Clone)]
+pub struct BatchedTransfer {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    /// New notes `[recipient_note, change_note]`
+    pub outputs: [ShieldedNote; TRANSFER_NOTES],
+    /// `Completed` or `Failed` with its transaction, `Pending` if that
+    /// transaction was never sent because an earlier one failed
+    pub status: TransferStatus,
+}
+
+/// Transfers of a batch sent together in one transaction
+#[derive(Debug)]
+pub struct TransferGroup {
+    /// Signature of the confirmed transaction or why it failed; `None` if
+    /// it was never sent
+    pub result: Option<Result<Signature>>,
+    pub transfers: Vec<BatchedTransfer>,
+}
+
 pub struct PrivateTransferClient<'a> {
     client: &'a UntraceClient,
 }
@@ -18,37 +162,70 @@ impl<'a> PrivateTransferClient<'a> {
         Self { client }
     }
 
-    /// Execute a private transfer
+    /// Execute a private transfer by spending shielded `inputs`.
+    ///
+    /// Returns the new notes `[recipient_note, change_note]`; the caller keeps
+    /// the change note and delivers the other to the recipient.
     pub async fn transfer(
         &self,
+        inputs: &[ShieldedNote],
         recipient: &Pubkey,
         amount: u64,
         privacy_level: PrivacyLevel,
-    ) -> Result<Signature> {
-        let transfer_account = Pubkey::new_unique();
+    ) -> Result<(Signature, [ShieldedNote; TRANSFER_NOTES])> {
+        let mut tree = ShieldedPoolClient::new(self.client).get_tree().await?;
+        let (instruction, outputs) =
+            self.transfer_instruction(&mut tree, inputs, recipient, amount, privacy_level)?;
+        let signature = self
+            .client
+            .send_paid_transaction(vec![instruction], &self.client.config)
+            .await?;
 
-        // Generate recipient's ephemeral key
-        let mut recipient_ephemeral = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut recipient_ephemeral);
+        Ok((signature, outputs))
+    }
 
-        // Encrypt amount
-        let amount_bytes = amount.to_le_bytes();
-        let mut shared_secret = [0u8; 32];
-        rand::Rng::fill(&mut rand::thread_rng(), &mut shared_secret);
-        let nonce = [0u8; 12];
+    /// Build the instruction of a private transfer spending `inputs`, with
+    /// its new notes `[recipient_note, change_note]`. It is proven against
+    /// the current root of `tree`, a copy of the shielded pool's tree, which
+    /// then gets the new notes so a following transfer can spend them.
+    pub fn transfer_instruction(
+        &self,
+        tree: &mut MerkleTreeAccount,
+        inputs: &[ShieldedNote],
+        recipient: &Pubkey,
+        amount: u64,
+        privacy_level: PrivacyLevel,
+    ) -> Result<(Instruction, [ShieldedNote; TRANSFER_NOTES])> {
+        let payer = self.client.payer.pubkey();
+        let (inputs, outputs) = build_transfer_notes(inputs, recipient, amount, &payer)?;
+        let asset_id = outputs[0].asset_id;
 
-        let (encrypted_amount, _) = crypto::encrypt_data(&amount_bytes, &shared_secret, &nonce)
-            .map_err(|e| anyhow::anyhow!(e))?;
+        let nullifiers = [inputs[0].nullifier(), inputs[1].nullifier()];
+        let output_commitments = [outputs[0].commitment(), outputs[1].commitment()];
 
-        // Encrypt recipient
-        let recipient_bytes = recipient.to_bytes();
-        let (encrypted_recipient, _) = crypto::encrypt_data(&recipient_bytes, &shared_secret, &nonce)
-            .map_err(|e| anyhow::anyhow!(e))?;
+        // Encrypt amount and recipient to the recipient, each under its own
+        // ephemeral key
+        let encrypt = |plaintext: &[u8]| {
+            let mut ephemeral_secret = [0u8; 32];
+            rand::Rng::fill(&mut rand::thread_rng(), &mut ephemeral_secret);
+            encrypt_for_recipient(plaintext, &recipient.to_bytes(), &ephemeral_secret)
+        };
+        let encrypted_amount = encrypt(&amount.to_le_bytes())?;
+        let encrypted_recipient = encrypt(&recipient.to_bytes())?;
 
-        // Generate ZK proof
-        let commitment = crypto::generate_commitment(&recipient_bytes, amount, &shared_secret);
-        let nullifier = crypto::generate_nullifier(&shared_secret, &commitment);
-        let zk_proof = crypto::generate_zk_proof(&commitment, &nullifier, &shared_secret);
+        // Generate the balance proof over the spent and created notes
+        let root = tree.current_root();
+        let public_inputs = crypto::shielded_transfer_inputs(
+            &root,
+            &asset_id.to_bytes(),
+            &nullifiers,
+            &output_commitments,
+        );
+        let zk_proof = self.client.proof_cache.get_or_prove(
+            TRANSFER_CIRCUIT,
+            &[&public_inputs, &nullifiers[0]],
+            || crypto::generate_zk_proof(&public_inputs, &nullifiers[0], &inputs[0].randomness),
+        )?;
 
         let privacy_level_u8 = match privacy_level {
             PrivacyLevel::Basic => 0u8,
@@ -56,47 +233,144 @@ impl<'a> PrivateTransferClient<'a> {
             PrivacyLevel::Maximum => 2u8,
         };
 
-        let mut data = vec![3u8]; // Instruction discriminator
-        data.extend_from_slice(&(encrypted_amount.len() as u32).to_le_bytes());
-        data.extend_from_slice(&encrypted_amount);
-        data.extend_from_slice(&(encrypted_recipient.len() as u32).to_le_bytes());
-        data.extend_from_slice(&encrypted_recipient);
-        data.extend_from_slice(&(zk_proof.len() as u32).to_le_bytes());
-        data.extend_from_slice(&zk_proof);
-        data.push(privacy_level_u8);
-
-        let instruction = Instruction {
-            program_id: self.client.program_id,
-            accounts: vec![
-                AccountMeta::new(transfer_account, false),
-                AccountMeta::new(self.client.payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-            data,
-        };
+        // Each output note is encrypted to its owner for discovery by scanning
+        let encrypted_notes = outputs
+            .iter()
+            .map(|note| encrypt_note(note, Some(payer)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let program_id = self.client.program_id;
+        let instruction = self.client.program_instruction(
+            accounts::PrivateTransfer {
+                shielded_pool: find_shielded_pool_address(&program_id).0,
+                merkle_tree: find_shielded_merkle_tree_address(&program_id).0,
+                asset: find_shielded_asset_address(&program_id, &asset_id).0,
+                input_nullifier_0: find_shielded_nullifier_address(&program_id, &nullifiers[0]).0,
+                input_nullifier_1: find_shielded_nullifier_address(&program_id, &nullifiers[1]).0,
+                output_commitment_0: find_shielded_commitment_address(
+                    &program_id,
+                    &output_commitments[0],
+                )
+                .0,
+                output_commitment_1: find_shielded_commitment_address(
+                    &program_id,
+                    &output_commitments[1],
+                )
+                .0,
+                transfer_account: find_private_transfer_address(&program_id, &nullifiers[0]).0,
+                sender: self.client.rent_payer(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                verifier: find_verifier_address(&program_id, Circuit::ShieldedTransfer).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::PrivateTransfer {
+                args: PrivateTransferArgs {
+                    asset_id,
+                    root,
+                    nullifiers,
+                    output_commitments,
+                    encrypted_amount,
+                    encrypted_recipient,
+                    zk_proof,
+                    privacy_level: privacy_level_u8,
+                    encrypted_notes,
+                },
+            },
+        );
+        for commitment in output_commitments {
+            tree.insert(commitment).map_err(|e| anyhow!(e))?;
+        }
 
-        self.client.send_transaction(vec![instruction]).await
+        Ok((instruction, outputs))
     }
 
-    /// Execute a batch of private transfers for better anonymity
+    /// Execute a batch of private transfers, packing as many as fit into
+    /// each transaction so they land together rather than one by one. Each
+    /// transfer spends the change note of the previous one, so sending
+    /// stops at the first failed transaction.
+    ///
+    /// The client's `compute_unit_limit`, if set, is taken per transfer.
     pub async fn batch_transfer(
         &self,
+        inputs: &[ShieldedNote],
         transfers: Vec<(Pubkey, u64)>,
         privacy_level: PrivacyLevel,
-    ) -> Result<Vec<Signature>> {
-        let mut signatures = Vec::new();
-
+    ) -> Result<Vec<TransferGroup>> {
+        let mut tree = ShieldedPoolClient::new(self.client).get_tree().await?;
+        let mut instructions = Vec::new();
+        let mut batched = Vec::new();
+        let mut spendable = inputs.to_vec();
         for (recipient, amount) in transfers {
-            let sig = self.transfer(&recipient, amount, privacy_level).await?;
-            signatures.push(sig);
+            let (instruction, outputs) = self.transfer_instruction(
+                &mut tree,
+                &spendable,
+                &recipient,
+                amount,
+                privacy_level,
+            )?;
+            spendable = vec![outputs[1].clone()];
+            instructions.push(instruction);
+            batched.push(BatchedTransfer {
+                recipient,
+                amount,
+                outputs,
+                status: TransferStatus::Pending,
+            });
         }
 
-        Ok(signatures)
+        let config = &self.client.config;
+        let units_per_transfer = config
+            .compute_unit_limit
+            .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT)
+            .max(1);
+        let max_per_transaction = (MAX_COMPUTE_UNIT_LIMIT / units_per_transfer).max(1) as usize;
+        // Price is a placeholder; only the instructions' size matters here
+        let budget = compute_budget_instructions(config.compute_unit_limit, 1);
+        let runs = pack_instructions(
+            &instructions,
+            &budget,
+            &self.client.rent_payer(),
+            max_per_transaction,
+        );
+
+        let mut groups = Vec::with_capacity(runs.len());
+        let mut failed = false;
+        for run in runs {
+            let mut transfers = batched[run.clone()].to_vec();
+            let result = if failed {
+                None
+            } else {
+                let mut config = config.clone();
+                config.compute_unit_limit = config
+                    .compute_unit_limit
+                    .map(|limit| (limit * run.len() as u32).min(MAX_COMPUTE_UNIT_LIMIT));
+                let result = self
+                    .client
+                    .send_paid_transaction(instructions[run].to_vec(), &config)
+                    .await;
+
+                let status = if result.is_ok() {
+                    TransferStatus::Completed
+                } else {
+                    failed = true;
+                    TransferStatus::Failed
+                };
+                for transfer in &mut transfers {
+                    transfer.status = status;
+                }
+                Some(result)
+            };
+            groups.push(TransferGroup { result, transfers });
+        }
+
+        Ok(groups)
     }
 
     /// Query transfer status
     pub async fn get_transfer_status(&self, transfer_account: &Pubkey) -> Result<TransferStatus> {
-        let account = self.client.rpc_client.get_account(transfer_account)?;
+        let account = self.client.rpc_client.get_account(transfer_account).await?;
 
         if account.data.is_empty() {
             return Ok(TransferStatus::NotFound);
@@ -113,3 +387,69 @@ pub enum TransferStatus {
     Completed,
     Failed,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_notes_balance() {
+        let asset_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let inputs = [random_note(asset_id, 700, owner), random_note(asset_id, 500, owner)];
+
+        let (spent, outputs) = build_transfer_notes(&inputs, &recipient, 1_000, &owner).unwrap();
+        assert_eq!(spent[1].commitment(), inputs[1].commitment());
+        assert_eq!(outputs[0].owner, recipient);
+        assert_eq!(outputs[0].amount + outputs[1].amount, 1_200);
+        assert!(outputs.iter().all(|note| note.asset_id == asset_id));
+
+        // A single input is padded with a zero-value dummy note
+        let (spent, _) = build_transfer_notes(&inputs[..1], &recipient, 100, &owner).unwrap();
+        assert_eq!(spent[1].amount, 0);
+        assert_ne!(spent[0].nullifier(), spent[1].nullifier());
+    }
+
+    #[test]
+    fn test_pack_instructions_respects_limits() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let instruction = |len: usize| Instruction {
+            program_id,
+            accounts: Vec::new(),
+            data: vec![0u8; len],
+        };
+
+        let small: Vec<Instruction> = (0..5).map(|_| instruction(100)).collect();
+        assert_eq!(pack_instructions(&small, &[], &payer, 10), vec![0..5]);
+        assert_eq!(
+            pack_instructions(&small, &[], &payer, 2),
+            vec![0..2, 2..4, 4..5]
+        );
+
+        // Two of these fit in a packet, three do not
+        let large: Vec<Instruction> = (0..3).map(|_| instruction(500)).collect();
+        assert_eq!(pack_instructions(&large, &[], &payer, 10), vec![0..2, 2..3]);
+
+        // Oversized instructions still get a transaction of their own
+        let oversized = vec![instruction(2_000), instruction(100)];
+        assert_eq!(
+            pack_instructions(&oversized, &[], &payer, 10),
+            vec![0..1, 1..2]
+        );
+    }
+
+    #[test]
+    fn test_transfer_notes_reject_invalid_inputs() {
+        let owner = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let note = random_note(Pubkey::new_unique(), 100, owner);
+        let other_asset = random_note(Pubkey::new_unique(), 100, owner);
+
+        assert!(build_transfer_notes(&[], &recipient, 1, &owner).is_err());
+        let notes = std::slice::from_ref(&note);
+        assert!(build_transfer_notes(notes, &recipient, 101, &owner).is_err());
+        assert!(build_transfer_notes(&[note, other_asset], &recipient, 1, &owner).is_err());
+    }
+}
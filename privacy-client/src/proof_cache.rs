@@ -0,0 +1,200 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use sha3::{Digest, Sha3_256};
+
+use crate::error::Result;
+use crate::storage::ClientStorage;
+
+/// Circuit of privacy pool withdrawals
+pub const WITHDRAW_CIRCUIT: &str = "withdraw";
+/// Circuit of shielded pool transfers
+pub const TRANSFER_CIRCUIT: &str = "shielded_transfer";
+
+/// Proofs kept in memory by a default `UntraceClient`
+pub const DEFAULT_PROOF_CACHE_CAPACITY: usize = 64;
+
+type ProofKey = [u8; 32];
+
+#[derive(Default)]
+struct Entries {
+    proofs: HashMap<ProofKey, Vec<u8>>,
+    /// Keys from least to most recently used
+    order: VecDeque<ProofKey>,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &ProofKey) {
+        if let Some(position) = self.order.iter().position(|k| k == key) {
+            self.order.remove(position);
+        }
+        self.order.push_back(*key);
+    }
+
+    fn insert(&mut self, key: ProofKey, proof: Vec<u8>, capacity: usize) {
+        self.touch(&key);
+        self.proofs.insert(key, proof);
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.proofs.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Proofs already generated, keyed by circuit and public inputs, so a send
+/// retried after a failure does not prove the same statement again. Keeps
+/// the most recently used proofs in memory and, with storage, every proof
+/// on disk.
+pub struct ProofCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    storage: Option<Arc<dyn ClientStorage>>,
+}
+
+impl ProofCache {
+    /// In-memory cache of up to `capacity` proofs
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+            storage: None,
+        }
+    }
+
+    /// Cache that also persists proofs to `storage` across restarts
+    pub fn with_storage(capacity: usize, storage: Arc<dyn ClientStorage>) -> Self {
+        Self {
+            storage: Some(storage),
+            ..Self::new(capacity)
+        }
+    }
+
+    fn key(circuit: &str, public_inputs: &[&[u8]]) -> ProofKey {
+        let mut hasher = Sha3_256::new();
+        hasher.update((circuit.len() as u32).to_le_bytes());
+        hasher.update(circuit.as_bytes());
+        for input in public_inputs {
+            hasher.update((input.len() as u32).to_le_bytes());
+            hasher.update(input);
+        }
+        hasher.finalize().into()
+    }
+
+    fn storage_key(key: &ProofKey) -> String {
+        format!("proof-{}", bs58::encode(key).into_string())
+    }
+
+    /// Cached proof of `circuit` over `public_inputs`, if any
+    pub fn get(&self, circuit: &str, public_inputs: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+        let key = Self::key(circuit, public_inputs);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(proof) = entries.proofs.get(&key).cloned() {
+                entries.touch(&key);
+                return Ok(Some(proof));
+            }
+        }
+
+        let Some(storage) = &self.storage else {
+            return Ok(None);
+        };
+        let proof = storage.get(&Self::storage_key(&key))?;
+        if let Some(proof) = &proof {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(key, proof.clone(), self.capacity);
+        }
+        Ok(proof)
+    }
+
+    /// Cache `proof` of `circuit` over `public_inputs`
+    pub fn insert(&self, circuit: &str, public_inputs: &[&[u8]], proof: Vec<u8>) -> Result<()> {
+        let key = Self::key(circuit, public_inputs);
+        if let Some(storage) = &self.storage {
+            storage.put(&Self::storage_key(&key), &proof)?;
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, proof, self.capacity);
+        Ok(())
+    }
+
+    /// Cached proof of `circuit` over `public_inputs`, running `prove` and
+    /// caching its result on a miss
+    pub fn get_or_prove(
+        &self,
+        circuit: &str,
+        public_inputs: &[&[u8]],
+        prove: impl FnOnce() -> Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        if let Some(proof) = self.get(circuit, public_inputs)? {
+            return Ok(proof);
+        }
+
+        let proof = prove();
+        self.insert(circuit, public_inputs, proof.clone())?;
+        Ok(proof)
+    }
+
+    /// Number of proofs held in memory
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ProofCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROOF_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_proof_cache_evicts_and_persists() {
+        let storage: Arc<dyn ClientStorage> = Arc::new(MemoryStorage::new());
+        let cache = ProofCache::with_storage(2, storage.clone());
+
+        let mut proofs = 0;
+        let mut prove = |inputs: &[u8]| {
+            cache
+                .get_or_prove(WITHDRAW_CIRCUIT, &[inputs], || {
+                    proofs += 1;
+                    inputs.to_vec()
+                })
+                .unwrap()
+        };
+        assert_eq!(prove(b"a"), b"a");
+        assert_eq!(prove(b"a"), b"a");
+        prove(b"b");
+        prove(b"c");
+        assert_eq!(proofs, 3);
+        assert_eq!(cache.len(), 2);
+
+        // Same inputs under another circuit are a different statement
+        assert!(cache.get(TRANSFER_CIRCUIT, &[b"a"]).unwrap().is_none());
+
+        // Evicted from memory, but still on disk for a fresh cache
+        let reloaded = ProofCache::with_storage(2, storage);
+        assert_eq!(
+            reloaded.get(WITHDRAW_CIRCUIT, &[b"a"]).unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert!(ProofCache::new(2)
+            .get(WITHDRAW_CIRCUIT, &[b"a"])
+            .unwrap()
+            .is_none());
+    }
+}
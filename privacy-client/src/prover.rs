@@ -0,0 +1,129 @@
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::anyhow;
+use tokio::sync::{oneshot, Semaphore};
+use untrace_common::crypto;
+
+use crate::error::{ClientError, Result};
+
+/// Proof generation off the async runtime, on a dedicated thread pool.
+/// At most `queue_size` proofs are queued or running at once; submitting
+/// more waits for a slot instead of piling up work.
+pub struct ProverPool {
+    pool: rayon::ThreadPool,
+    slots: Arc<Semaphore>,
+    queue_size: usize,
+}
+
+/// Proof being generated by a `ProverPool`; await it for the proof
+pub struct ProofHandle {
+    receiver: oneshot::Receiver<std::thread::Result<Vec<u8>>>,
+}
+
+impl Future for ProofHandle {
+    type Output = Result<Vec<u8>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(Ok(proof)) => Ok(proof),
+                Ok(Err(_)) => Err(ClientError::ProofGeneration("Prover panicked".to_string())),
+                Err(_) => Err(ClientError::ProofGeneration(
+                    "Prover pool shut down".to_string(),
+                )),
+            })
+    }
+}
+
+impl ProverPool {
+    /// Pool of `threads` provers, or one per core when zero, holding up to
+    /// `queue_size` proofs in flight
+    pub fn new(threads: usize, queue_size: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("untrace-prover-{}", index))
+            .build()
+            .map_err(|e| anyhow!("Failed to start prover pool: {}", e))?;
+
+        let queue_size = queue_size.max(1);
+        Ok(Self {
+            pool,
+            slots: Arc::new(Semaphore::new(queue_size)),
+            queue_size,
+        })
+    }
+
+    /// Queue `prove` (witness generation and proving) once a slot is free
+    pub async fn submit(
+        &self,
+        prove: impl FnOnce() -> Vec<u8> + Send + 'static,
+    ) -> Result<ProofHandle> {
+        let slot = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| anyhow!(e))?;
+        let (sender, receiver) = oneshot::channel();
+
+        self.pool.spawn(move || {
+            let proof = panic::catch_unwind(AssertUnwindSafe(prove));
+            drop(slot);
+            // The caller may have dropped its handle
+            let _ = sender.send(proof);
+        });
+
+        Ok(ProofHandle { receiver })
+    }
+
+    /// Queue the proof of a privacy pool withdrawal
+    pub async fn prove_withdraw(
+        &self,
+        commitment: [u8; 32],
+        nullifier: [u8; 32],
+        secret: [u8; 32],
+    ) -> Result<ProofHandle> {
+        self.submit(move || crypto::generate_zk_proof(&commitment, &nullifier, &secret))
+            .await
+    }
+
+    /// Proofs queued or running
+    pub fn in_flight(&self) -> usize {
+        self.queue_size - self.slots.available_permits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_proofs_run_off_the_runtime() {
+        let prover = ProverPool::new(2, 4).unwrap();
+
+        let mut handles = Vec::new();
+        for i in 0..8u8 {
+            handles.push(
+                prover
+                    .prove_withdraw([i; 32], [1u8; 32], [2u8; 32])
+                    .await
+                    .unwrap(),
+            );
+        }
+        for (i, handle) in handles.into_iter().enumerate() {
+            let expected = crypto::generate_zk_proof(&[i as u8; 32], &[1u8; 32], &[2u8; 32]);
+            assert_eq!(handle.await.unwrap(), expected);
+        }
+        assert_eq!(prover.in_flight(), 0);
+
+        let failed = prover.submit(|| panic!("bad witness")).await.unwrap();
+        assert!(matches!(failed.await, Err(ClientError::ProofGeneration(_))));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::cross_chain::{CrossChainClient, TransferState};
+use crate::error::Result;
+use crate::evm::{EvmClient, EvmTxHash};
+use crate::storage::ClientStorage;
+
+/// How failed redeems of a transfer are retried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedeemRetryPolicy {
+    /// Attempts before a transfer waits for a manual `resume`
+    pub max_attempts: u32,
+    /// Delay after the first failure; doubled on each further failure
+    pub initial_backoff: Duration,
+    /// Longest delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RedeemRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RedeemRetryPolicy {
+    /// Delay after failed attempt number `attempts` (starting at 1)
+    pub fn delay(&self, attempts: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+            .min(self.max_backoff)
+    }
+}
+
+/// Redeem attempts of one outbound transfer, persisted so retries pick up
+/// where they stopped after a restart
+#[derive(Debug, Clone, PartialEq, Eq, Default, BorshSerialize, BorshDeserialize)]
+pub struct RedeemRetryState {
+    /// Attempts made since the last `resume`
+    pub attempts: u32,
+    /// Unix time in milliseconds before which no attempt is made
+    pub next_attempt_at: u64,
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+}
+
+impl RedeemRetryState {
+    /// Record a failed attempt at `now`, backing off per `policy`
+    pub fn record_failure(&mut self, error: String, now: u64, policy: &RedeemRetryPolicy) {
+        self.last_error = Some(error);
+        self.next_attempt_at = now.saturating_add(policy.delay(self.attempts).as_millis() as u64);
+    }
+
+    /// Whether every attempt `policy` allows has failed
+    pub fn is_exhausted(&self, policy: &RedeemRetryPolicy) -> bool {
+        self.attempts >= policy.max_attempts
+    }
+
+    /// How long to wait at `now` before the next attempt
+    pub fn wait(&self, now: u64) -> Duration {
+        Duration::from_millis(self.next_attempt_at.saturating_sub(now))
+    }
+}
+
+/// Outcome of driving a transfer's redeem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedeemOutcome {
+    /// Claimed on the destination; `tx` is set when this call claimed it
+    Redeemed { tx: Option<EvmTxHash> },
+    /// Not signed by the guardians yet, so nothing was attempted
+    NotAttested,
+    /// Refunded on Solana, or no such transfer
+    Failed { reason: String },
+    /// Every attempt failed; `resume` starts over
+    Exhausted { attempts: u32, last_error: String },
+}
+
+/// Claims outbound Wormhole transfers on their destination, retrying
+/// failed claims with exponential backoff. Each attempt first checks
+/// whether the transfer was already claimed, so a claim that landed after
+/// its attempt timed out is never sent twice.
+pub struct RedeemRetrier<'a> {
+    cross_chain: CrossChainClient<'a>,
+    destination: &'a EvmClient,
+    storage: Arc<dyn ClientStorage>,
+    policy: RedeemRetryPolicy,
+}
+
+impl<'a> RedeemRetrier<'a> {
+    pub fn new(
+        cross_chain: CrossChainClient<'a>,
+        destination: &'a EvmClient,
+        storage: Arc<dyn ClientStorage>,
+    ) -> Self {
+        Self {
+            cross_chain,
+            destination,
+            storage,
+            policy: RedeemRetryPolicy::default(),
+        }
+    }
+
+    /// Retry per `policy` instead of the default
+    pub fn with_policy(mut self, policy: RedeemRetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Persisted retry state of `transfer_id`, or `None` if no attempt
+    /// is outstanding
+    pub fn state(&self, transfer_id: &Pubkey) -> Result<Option<RedeemRetryState>> {
+        self.storage
+            .get(&storage_key(transfer_id))?
+            .map(|bytes| Ok(RedeemRetryState::try_from_slice(&bytes)?))
+            .transpose()
+    }
+
+    /// Claim the outbound transfer at `transfer_id` on the destination,
+    /// retrying until it is claimed or the policy's attempts run out.
+    /// Waits out the backoff left from before a restart.
+    pub async fn redeem(&self, transfer_id: &Pubkey) -> Result<RedeemOutcome> {
+        let mut state = self.state(transfer_id)?.unwrap_or_default();
+        loop {
+            if state.is_exhausted(&self.policy) {
+                return Ok(RedeemOutcome::Exhausted {
+                    attempts: state.attempts,
+                    last_error: state.last_error.unwrap_or_default(),
+                });
+            }
+            tokio::time::sleep(state.wait(now_millis())).await;
+
+            // Count the attempt before making it, so a crash mid-claim
+            // still backs off
+            state.attempts += 1;
+            self.save(transfer_id, &state)?;
+
+            match self.attempt(transfer_id).await {
+                Ok(Some(outcome)) => {
+                    self.storage.delete(&storage_key(transfer_id))?;
+                    return Ok(outcome);
+                }
+                Ok(None) => {
+                    // Waiting on the guardians is not a failed attempt
+                    state.attempts -= 1;
+                    self.save(transfer_id, &state)?;
+                    return Ok(RedeemOutcome::NotAttested);
+                }
+                Err(error) => {
+                    state.record_failure(error.to_string(), now_millis(), &self.policy);
+                    self.save(transfer_id, &state)?;
+                }
+            }
+        }
+    }
+
+    /// Retry a transfer whose attempts ran out, or whose backoff should be
+    /// skipped, starting over with a fresh set of attempts
+    pub async fn resume(&self, transfer_id: &Pubkey) -> Result<RedeemOutcome> {
+        let last_error = self.state(transfer_id)?.and_then(|state| state.last_error);
+        let state = RedeemRetryState {
+            last_error,
+            ..Default::default()
+        };
+        self.save(transfer_id, &state)?;
+        self.redeem(transfer_id).await
+    }
+
+    /// One idempotent attempt: `None` if the transfer is not attested yet
+    async fn attempt(&self, transfer_id: &Pubkey) -> Result<Option<RedeemOutcome>> {
+        let outcome = match self
+            .cross_chain
+            .transfer_state(transfer_id, self.destination)
+            .await?
+        {
+            TransferState::Initiated => return Ok(None),
+            TransferState::Redeemed | TransferState::Finalized => {
+                RedeemOutcome::Redeemed { tx: None }
+            }
+            TransferState::Failed { reason } => RedeemOutcome::Failed { reason },
+            TransferState::Attested { vaa } => RedeemOutcome::Redeemed {
+                tx: Some(self.destination.claim(&vaa).await?),
+            },
+        };
+        Ok(Some(outcome))
+    }
+
+    fn save(&self, transfer_id: &Pubkey, state: &RedeemRetryState) -> Result<()> {
+        Ok(self
+            .storage
+            .put(&storage_key(transfer_id), &state.try_to_vec()?)?)
+    }
+}
+
+/// Storage key of the retry state of `transfer_id`
+fn storage_key(transfer_id: &Pubkey) -> String {
+    format!("redeem_retry_{}", transfer_id)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_back_off_until_exhausted() {
+        let policy = RedeemRetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(3));
+
+        let mut state = RedeemRetryState::default();
+        assert_eq!(state.wait(1_000), Duration::ZERO);
+        for attempt in 1..=3 {
+            assert!(!state.is_exhausted(&policy));
+            state.attempts = attempt;
+            state.record_failure("gas price spiked".to_string(), 1_000, &policy);
+        }
+        assert!(state.is_exhausted(&policy));
+        assert_eq!(state.wait(1_000), Duration::from_secs(3));
+        assert_eq!(state.wait(10_000), Duration::ZERO);
+
+        let decoded = RedeemRetryState::try_from_slice(&state.try_to_vec().unwrap()).unwrap();
+        assert_eq!(decoded, state);
+        assert_eq!(decoded.last_error.as_deref(), Some("gas price spiked"));
+    }
+}
@@ -0,0 +1,151 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use untrace_common::{crypto, ShieldedNote};
+
+use crate::bridge_encryption::{decrypt_for_recipient, encrypt_for_recipient};
+use crate::error::{ClientError, Result};
+use crate::events::{parse_commitment_inserted, CommitmentInserted};
+use crate::shielded_pool::find_shielded_pool_address;
+use crate::UntraceClient;
+
+/// Plaintext of the note ciphertext attached to each shielded commitment
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct NotePlaintext {
+    pub note: ShieldedNote,
+    /// Key the sender chose to reveal to the recipient, if any
+    pub sender_hint: Option<Pubkey>,
+}
+
+/// Encrypt `note` to its owner under a fresh ephemeral key
+pub fn encrypt_note(note: &ShieldedNote, sender_hint: Option<Pubkey>) -> Result<Vec<u8>> {
+    let plaintext = NotePlaintext {
+        note: note.clone(),
+        sender_hint,
+    };
+    let mut ephemeral_secret = [0u8; 32];
+    rand::Rng::fill(&mut rand::thread_rng(), &mut ephemeral_secret);
+
+    encrypt_for_recipient(
+        &plaintext.try_to_vec()?,
+        &note.owner.to_bytes(),
+        &ephemeral_secret,
+    )
+    .map_err(|e| ClientError::InvalidNote(e.to_string()))
+}
+
+/// Viewing key of a wallet, able to decrypt every note sent to its pubkey
+pub fn viewing_key(keypair: &Keypair) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&keypair.to_bytes()[..32]);
+    crypto::viewing_key(&seed)
+}
+
+/// Note found on chain that belongs to the scanning wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredNote {
+    pub note: ShieldedNote,
+    pub sender_hint: Option<Pubkey>,
+    /// Leaf position of the note's commitment in the shielded pool's tree
+    pub leaf_index: u64,
+    pub commitment: [u8; 32],
+}
+
+/// Try to open the note of `event` with `viewing_key`. Notes that decrypt
+/// but do not open the event's commitment are rejected.
+pub fn try_decrypt_note(
+    event: &CommitmentInserted,
+    viewing_key: &[u8; 32],
+) -> Option<DiscoveredNote> {
+    let plaintext = decrypt_for_recipient(&event.encrypted_note, viewing_key)?;
+    let NotePlaintext { note, sender_hint } = NotePlaintext::try_from_slice(&plaintext).ok()?;
+    if note.commitment() != event.commitment {
+        return None;
+    }
+
+    Some(DiscoveredNote {
+        note,
+        sender_hint,
+        leaf_index: event.leaf_index,
+        commitment: event.commitment,
+    })
+}
+
+/// Finds incoming notes by trial-decrypting the shielded pool's deposit and
+/// transfer events with a wallet's viewing key
+pub struct NoteScanner {
+    viewing_key: [u8; 32],
+    /// Newest transaction already scanned, so scans only fetch what is new
+    pub last_signature: Option<Signature>,
+}
+
+impl NoteScanner {
+    pub fn new(viewing_key: [u8; 32]) -> Self {
+        Self {
+            viewing_key,
+            last_signature: None,
+        }
+    }
+
+    /// Scan transactions newer than the last scan, returning the notes
+    /// addressed to this wallet, oldest first
+    pub async fn scan(&mut self, client: &UntraceClient) -> Result<Vec<DiscoveredNote>> {
+        let (pool, _) = find_shielded_pool_address(&client.program_id);
+        let (instructions, newest) = client
+            .fetch_inner_instructions(&pool, self.last_signature, &client.program_id)
+            .await?;
+
+        let notes = instructions
+            .iter()
+            .filter_map(|data| parse_commitment_inserted(data))
+            .filter(|event| event.pool == pool)
+            .filter_map(|event| try_decrypt_note(&event, &self.viewing_key))
+            .collect();
+
+        if newest.is_some() {
+            self.last_signature = newest;
+        }
+
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn test_only_the_owner_discovers_a_note() {
+        let owner = Keypair::new();
+        let sender = Pubkey::new_unique();
+        let note = ShieldedNote {
+            asset_id: Pubkey::new_unique(),
+            amount: 250,
+            owner: owner.pubkey(),
+            randomness: [4u8; 32],
+        };
+        let event = CommitmentInserted {
+            pool: Pubkey::new_unique(),
+            leaf_index: 9,
+            commitment: note.commitment(),
+            encrypted_note: encrypt_note(&note, Some(sender)).unwrap(),
+        };
+
+        let found = try_decrypt_note(&event, &viewing_key(&owner)).unwrap();
+        assert_eq!(found.note, note);
+        assert_eq!(found.sender_hint, Some(sender));
+        assert_eq!(found.leaf_index, 9);
+
+        assert!(try_decrypt_note(&event, &viewing_key(&Keypair::new())).is_none());
+
+        // A valid note attached to a different commitment is not ours to spend
+        let mismatched = CommitmentInserted {
+            commitment: [1u8; 32],
+            ..event
+        };
+        assert!(try_decrypt_note(&mismatched, &viewing_key(&owner)).is_none());
+    }
+}
@@ -0,0 +1,276 @@
+use anchor_lang::AccountDeserialize;
+use anyhow::anyhow;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature, system_program};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use untrace_common::{crypto, groth16::Circuit, ShieldedNote, NATIVE_ASSET_ID};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{MerkleTreeAccount, ShieldedPoolAccount},
+};
+
+use crate::error::{ClientError, Result};
+use crate::admin::{find_admin_config_address, find_verifier_address};
+use crate::events::find_event_authority_address;
+use crate::scanner::encrypt_note;
+use crate::UntraceClient;
+
+/// Derive the PDA of the unified shielded pool
+pub fn find_shielded_pool_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"shielded_pool"], program_id)
+}
+
+/// Derive the PDA of the shielded pool's commitment tree
+pub fn find_shielded_merkle_tree_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"shielded_merkle_tree"], program_id)
+}
+
+/// Derive the PDA tracking one asset in the shielded pool
+pub fn find_shielded_asset_address(program_id: &Pubkey, asset_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"shielded_asset", asset_id.as_ref()], program_id)
+}
+
+/// Derive the PDA holding a shielded note commitment
+pub fn find_shielded_commitment_address(
+    program_id: &Pubkey,
+    commitment: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"shielded_commitment", commitment], program_id)
+}
+
+/// Derive the PDA marking a shielded nullifier as spent
+pub fn find_shielded_nullifier_address(program_id: &Pubkey, nullifier: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"shielded_nullifier", nullifier], program_id)
+}
+
+pub struct ShieldedPoolClient<'a> {
+    client: &'a UntraceClient,
+}
+
+impl<'a> ShieldedPoolClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self { client }
+    }
+
+    /// Initialize the shielded pool
+    pub async fn initialize(&self, min_pool_size: u64) -> Result<Signature> {
+        let (pool, _) = find_shielded_pool_address(&self.client.program_id);
+        let (merkle_tree, _) = find_shielded_merkle_tree_address(&self.client.program_id);
+
+        let instruction = self.client.program_instruction(
+            accounts::InitializeShieldedPool {
+                shielded_pool: pool,
+                merkle_tree,
+                authority: self.client.payer.pubkey(),
+                system_program: system_program::id(),
+            },
+            instruction::InitializeShieldedPool { min_pool_size },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Register an asset; SPL assets also get their vault token account
+    pub async fn register_asset(&self, asset_id: &Pubkey) -> Result<Signature> {
+        let (pool, _) = find_shielded_pool_address(&self.client.program_id);
+        let (asset, _) = find_shielded_asset_address(&self.client.program_id, asset_id);
+        let payer = self.client.payer.pubkey();
+
+        let mut instructions = vec![self.client.program_instruction(
+            accounts::RegisterAsset {
+                shielded_pool: pool,
+                asset,
+                authority: payer,
+                system_program: system_program::id(),
+            },
+            instruction::RegisterAsset {
+                asset_id: *asset_id,
+            },
+        )];
+
+        if *asset_id != NATIVE_ASSET_ID {
+            instructions.push(create_associated_token_account_idempotent(
+                &payer,
+                &asset,
+                asset_id,
+                &spl_token::id(),
+            ));
+        }
+
+        self.client.send_transaction(instructions).await
+    }
+
+    /// Deposit an asset, returning the note needed to withdraw it later
+    pub async fn deposit(
+        &self,
+        asset_id: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Result<(Signature, ShieldedNote)> {
+        let (instruction, note) =
+            self.deposit_instruction(asset_id, &self.client.payer.pubkey(), owner, amount)?;
+
+        let signature = self.client.send_transaction(vec![instruction]).await?;
+
+        Ok((signature, note))
+    }
+
+    /// Build a deposit of `amount` from `depositor` into a new note owned by
+    /// `owner`, without sending it
+    pub fn deposit_instruction(
+        &self,
+        asset_id: &Pubkey,
+        depositor: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Result<(Instruction, ShieldedNote)> {
+        let mut randomness = [0u8; 32];
+        rand::Rng::fill(&mut rand::thread_rng(), &mut randomness);
+
+        let note = ShieldedNote {
+            asset_id: *asset_id,
+            amount,
+            owner: *owner,
+            randomness,
+        };
+        let commitment = note.commitment();
+
+        let program_id = self.client.program_id;
+        let (pool, _) = find_shielded_pool_address(&program_id);
+        let (asset, _) = find_shielded_asset_address(&program_id, asset_id);
+        let (commitment_account, _) = find_shielded_commitment_address(&program_id, &commitment);
+
+        // Encrypt the note so the owner can find it by scanning
+        let encrypted_note = encrypt_note(&note, Some(*depositor))?;
+
+        // SPL assets move through token accounts; native SOL omits them
+        let (depositor_token_account, asset_vault, token_program) = if *asset_id == NATIVE_ASSET_ID
+        {
+            (None, None, None)
+        } else {
+            (
+                Some(get_associated_token_address(depositor, asset_id)),
+                Some(get_associated_token_address(&asset, asset_id)),
+                Some(spl_token::id()),
+            )
+        };
+
+        let instruction = self.client.program_instruction(
+            accounts::ShieldedDeposit {
+                shielded_pool: pool,
+                merkle_tree: find_shielded_merkle_tree_address(&program_id).0,
+                asset,
+                commitment_account,
+                depositor: *depositor,
+                depositor_token_account,
+                asset_vault,
+                token_program,
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::ShieldedDeposit {
+                asset_id: *asset_id,
+                amount,
+                commitment,
+                note_key: note.note_key(),
+                encrypted_note,
+            },
+        );
+
+        Ok((instruction, note))
+    }
+
+    /// Withdraw a note to `recipient`, revealing its asset and amount.
+    /// Fails early if the note was already spent, here or on a chain whose
+    /// nullifiers have been applied here.
+    pub async fn withdraw(&self, note: &ShieldedNote, recipient: &Pubkey) -> Result<Signature> {
+        let nullifier = note.nullifier();
+        if self.client.nullifier_sync().is_spent_locally(&nullifier).await? {
+            return Err(ClientError::NullifierSpent);
+        }
+
+        let program_id = self.client.program_id;
+        let (pool, _) = find_shielded_pool_address(&program_id);
+        // Prove against the current root; the program accepts it until
+        // enough newer notes push it out of its root history
+        let root = self.get_pool().await?.commitment_root;
+        let public_inputs = crypto::shielded_withdraw_inputs(
+            &root,
+            &note.asset_id.to_bytes(),
+            note.amount,
+            &recipient.to_bytes(),
+            &nullifier,
+        );
+        let zk_proof = crypto::generate_zk_proof(&public_inputs, &nullifier, &note.randomness);
+        let (asset, _) = find_shielded_asset_address(&program_id, &note.asset_id);
+        let (nullifier_account, _) = find_shielded_nullifier_address(&program_id, &nullifier);
+        let payer = self.client.payer.pubkey();
+
+        let mut instructions = Vec::new();
+        let (asset_vault, recipient_token_account, token_program) =
+            if note.asset_id == NATIVE_ASSET_ID {
+                (None, None, None)
+            } else {
+                // The recipient may not hold this token yet
+                instructions.push(create_associated_token_account_idempotent(
+                    &payer,
+                    recipient,
+                    &note.asset_id,
+                    &spl_token::id(),
+                ));
+                (
+                    Some(get_associated_token_address(&asset, &note.asset_id)),
+                    Some(get_associated_token_address(recipient, &note.asset_id)),
+                    Some(spl_token::id()),
+                )
+            };
+
+        instructions.push(self.client.program_instruction(
+            accounts::ShieldedWithdraw {
+                shielded_pool: pool,
+                merkle_tree: find_shielded_merkle_tree_address(&program_id).0,
+                asset,
+                nullifier_account,
+                recipient: *recipient,
+                withdrawer: payer,
+                asset_vault,
+                recipient_token_account,
+                token_program,
+                system_program: system_program::id(),
+                verifier: find_verifier_address(&program_id, Circuit::ShieldedWithdraw).0,
+            },
+            instruction::ShieldedWithdraw {
+                asset_id: note.asset_id,
+                amount: note.amount,
+                nullifier,
+                zk_proof,
+                root,
+            },
+        ));
+
+        self.client.send_transaction(instructions).await
+    }
+
+    /// Fetch and decode the shielded pool account
+    pub async fn get_pool(&self) -> Result<ShieldedPoolAccount> {
+        let (pool, _) = find_shielded_pool_address(&self.client.program_id);
+        let account = self.client.rpc_client.get_account(&pool).await?;
+        ShieldedPoolAccount::try_deserialize(&mut account.data.as_slice())
+            .map_err(|e| anyhow!("Invalid shielded pool account: {}", e).into())
+    }
+
+    /// Fetch the shielded pool's commitment tree, whose latest root new
+    /// transfers are proven against
+    pub async fn get_tree(&self) -> Result<MerkleTreeAccount> {
+        let (merkle_tree, _) = find_shielded_merkle_tree_address(&self.client.program_id);
+        let account = self.client.rpc_client.get_account(&merkle_tree).await?;
+        let data = account
+            .data
+            .get(8..MerkleTreeAccount::LEN)
+            .ok_or_else(|| anyhow!("Truncated merkle tree account"))?;
+        Ok(bytemuck::pod_read_unaligned(data))
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use solana_sdk::{pubkey::Pubkey, signer::null_signer::NullSigner, transaction::Transaction};
+
+use crate::{config::ClientConfig, error::Result, UntraceClient};
+
+/// Signer that keeps its key outside the client, such as a browser wallet
+/// behind an adapter, and is handed whole transactions to approve
+#[async_trait]
+pub trait TransactionSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+
+    /// Sign a bincode-serialized transaction, returning it with this
+    /// signer's signature in place
+    async fn sign_transaction(&self, transaction: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+impl UntraceClient {
+    /// Create a new Untrace client whose payer signs through `signer`, so
+    /// every transaction it sends is passed to `signer` after the client's
+    /// own signers
+    pub fn with_transaction_signer(
+        rpc_url: &str,
+        program_id: Pubkey,
+        signer: Arc<dyn TransactionSigner>,
+        config: ClientConfig,
+    ) -> Self {
+        let payer = Arc::new(NullSigner::new(&signer.pubkey()));
+        let mut client = Self::with_signer(rpc_url, program_id, payer, config);
+        client.transaction_signer = Some(signer);
+        client
+    }
+
+    /// Have the external signer, if any, add its signature to a
+    /// transaction the client has partially signed. The returned
+    /// transaction is rejected unless its message is unchanged and the
+    /// signature verifies.
+    pub(crate) async fn sign_externally(&self, transaction: Transaction) -> Result<Transaction> {
+        let Some(signer) = &self.transaction_signer else {
+            return Ok(transaction);
+        };
+        let pubkey = signer.pubkey();
+        let required = transaction.message.header.num_required_signatures as usize;
+        let Some(position) = transaction.message.account_keys[..required]
+            .iter()
+            .position(|key| *key == pubkey)
+        else {
+            return Ok(transaction);
+        };
+
+        let bytes = bincode::serialize(&transaction).map_err(anyhow::Error::from)?;
+        let signed: Transaction = bincode::deserialize(&signer.sign_transaction(bytes).await?)
+            .map_err(anyhow::Error::from)?;
+        if signed.message != transaction.message {
+            return Err(anyhow!("Wallet changed the transaction it was asked to sign").into());
+        }
+        let signature = signed
+            .signatures
+            .get(position)
+            .copied()
+            .filter(|signature| signature.verify(pubkey.as_ref(), &transaction.message_data()))
+            .ok_or_else(|| anyhow!("Wallet {} did not sign the transaction", pubkey))?;
+
+        // Only the wallet's own signature is taken, so it cannot drop or
+        // replace the others
+        let mut transaction = transaction;
+        transaction.signatures[position] = signature;
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        hash::Hash,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+
+    struct WalletSigner {
+        keypair: Keypair,
+        tamper: bool,
+    }
+
+    #[async_trait]
+    impl TransactionSigner for WalletSigner {
+        fn pubkey(&self) -> Pubkey {
+            self.keypair.pubkey()
+        }
+
+        async fn sign_transaction(&self, transaction: Vec<u8>) -> Result<Vec<u8>> {
+            let mut transaction: Transaction = bincode::deserialize(&transaction).unwrap();
+            if self.tamper {
+                transaction.message.recent_blockhash = Hash::new_unique();
+            }
+            let blockhash = transaction.message.recent_blockhash;
+            transaction.partial_sign(&[&self.keypair], blockhash);
+            Ok(bincode::serialize(&transaction).unwrap())
+        }
+    }
+
+    fn wallet_client(tamper: bool) -> UntraceClient {
+        UntraceClient::with_transaction_signer(
+            "http://localhost:8899",
+            Pubkey::new_unique(),
+            Arc::new(WalletSigner {
+                keypair: Keypair::new(),
+                tamper,
+            }),
+            ClientConfig::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sign_externally() {
+        let client = wallet_client(false);
+        let payer = client.payer.pubkey();
+        let instruction = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+        let transaction = Transaction::new_with_payer(&[instruction], Some(&payer));
+        let signed = client.sign_externally(transaction).await.unwrap();
+        assert!(signed.verify().is_ok());
+
+        // A wallet that rewrites what it signs is caught
+        let client = wallet_client(true);
+        let payer = client.payer.pubkey();
+        let instruction = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+        let transaction = Transaction::new_with_payer(&[instruction], Some(&payer));
+        assert!(client.sign_externally(transaction).await.is_err());
+    }
+}
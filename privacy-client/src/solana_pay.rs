@@ -0,0 +1,296 @@
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget,
+    instruction::AccountMeta,
+    message::Message,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::Transaction,
+};
+use untrace_common::ShieldedNote;
+
+use crate::error::{ClientError, Result};
+use crate::UntraceClient;
+
+/// SPL Memo program, which merchants may use to tag a payment
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TuiCFZSEQT5o39WZXrThHx");
+
+const URL_SCHEME: &str = "solana:";
+
+/// Merchant details returned by a transaction request `GET`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionRequestMetadata {
+    pub label: String,
+    /// URL of an SVG, PNG or WebP icon
+    pub icon: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransactionRequestBody {
+    account: String,
+}
+
+/// Transaction returned by a transaction request `POST`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionRequestResponse {
+    /// Base64 of the serialized transaction, fee payer set to the wallet
+    pub transaction: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Encode `link`, the merchant's HTTPS endpoint, as a Solana Pay
+/// transaction request URL
+pub fn transaction_request_url(link: &str) -> anyhow::Result<String> {
+    let parsed = Url::parse(link)?;
+    if parsed.scheme() != "https" {
+        bail!("Transaction request link must use https: {}", link);
+    }
+
+    // Links with a query must be encoded so it is not read as the
+    // solana: URL's own
+    if parsed.query().is_some() {
+        Ok(format!(
+            "{}{}",
+            URL_SCHEME,
+            utf8_percent_encode(link, NON_ALPHANUMERIC)
+        ))
+    } else {
+        Ok(format!("{}{}", URL_SCHEME, link))
+    }
+}
+
+/// HTTPS endpoint of a Solana Pay transaction request URL
+pub fn parse_transaction_request_url(url: &str) -> anyhow::Result<Url> {
+    let link = url
+        .strip_prefix(URL_SCHEME)
+        .ok_or_else(|| anyhow!("Not a Solana Pay URL: {}", url))?;
+    let link = percent_decode_str(link).decode_utf8()?;
+
+    let parsed = Url::parse(&link)?;
+    if parsed.scheme() != "https" {
+        bail!("Not a transaction request URL: {}", url);
+    }
+    Ok(parsed)
+}
+
+/// Check that a merchant's transaction is paid by `account` and only runs
+/// the privacy program, plus compute budget and memo instructions, so the
+/// payment cannot move funds outside the shielded pool
+pub fn check_private_transaction(
+    transaction: &Transaction,
+    account: &Pubkey,
+    program_id: &Pubkey,
+) -> anyhow::Result<()> {
+    let message = &transaction.message;
+    if message.account_keys.first() != Some(account) {
+        bail!("Transaction is not paid by {}", account);
+    }
+
+    let mut private = false;
+    for instruction in &message.instructions {
+        let invoked = message
+            .account_keys
+            .get(instruction.program_id_index as usize)
+            .ok_or_else(|| anyhow!("Instruction program index out of range"))?;
+        if invoked == program_id {
+            private = true;
+        } else if *invoked != compute_budget::id() && *invoked != MEMO_PROGRAM_ID {
+            bail!(
+                "Transaction invokes {} outside the privacy program",
+                invoked
+            );
+        }
+    }
+
+    if !private {
+        bail!("Transaction does not invoke the privacy program");
+    }
+    Ok(())
+}
+
+/// Solana Pay transaction requests, for wallets paying through the shielded
+/// pool and merchants building such payments
+pub struct SolanaPayClient<'a> {
+    client: &'a UntraceClient,
+    http: reqwest::Client,
+}
+
+impl<'a> SolanaPayClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self {
+            client,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetch the merchant's label and icon to show before paying
+    pub async fn fetch_metadata(&self, url: &str) -> Result<TransactionRequestMetadata> {
+        let link = parse_transaction_request_url(url)?;
+        let metadata = self
+            .http
+            .get(link)
+            .timeout(self.client.config.timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(metadata)
+    }
+
+    /// Fetch the merchant's transaction for the payer, with its message.
+    /// Fails unless the transaction stays inside the privacy program.
+    pub async fn fetch_transaction(&self, url: &str) -> Result<(Transaction, Option<String>)> {
+        let link = parse_transaction_request_url(url)?;
+        let payer = self.client.payer.pubkey();
+
+        let response: TransactionRequestResponse = self
+            .http
+            .post(link)
+            .timeout(self.client.config.timeout)
+            .json(&TransactionRequestBody {
+                account: payer.to_string(),
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(anyhow::Error::from)?
+            .json()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let bytes = STANDARD
+            .decode(&response.transaction)
+            .map_err(anyhow::Error::from)?;
+        let transaction: Transaction = bincode::deserialize(&bytes).map_err(anyhow::Error::from)?;
+        check_private_transaction(&transaction, &payer, &self.client.program_id)?;
+
+        Ok((transaction, response.message))
+    }
+
+    /// Fetch a transaction request, sign it as the payer alongside any
+    /// merchant signatures, and send and confirm it
+    pub async fn execute(&self, url: &str) -> Result<Signature> {
+        let (mut transaction, _) = self.fetch_transaction(url).await?;
+
+        // Keep the merchant's blockhash so its signatures stay valid
+        let recent_blockhash = transaction.message.recent_blockhash;
+        let signers: [&dyn Signer; 1] = [self.client.payer.as_ref()];
+        transaction
+            .try_partial_sign(&signers, recent_blockhash)
+            .map_err(anyhow::Error::from)?;
+        let transaction = self.client.sign_externally(transaction).await?;
+
+        match self
+            .client
+            .send_and_confirm(&transaction, &self.client.config)
+            .await?
+        {
+            Some(signature) => Ok(signature),
+            None => Err(ClientError::BlockhashExpired),
+        }
+    }
+
+    /// Answer a transaction request from `account` as a merchant: a deposit
+    /// of `amount` from `account` into a shielded note owned by `owner`.
+    /// `reference`, if given, is attached so the payment can be found with
+    /// `getSignaturesForAddress`. Keep the returned note to spend the
+    /// payment.
+    pub async fn build_payment(
+        &self,
+        account: &Pubkey,
+        asset_id: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+        reference: Option<Pubkey>,
+        message: Option<String>,
+    ) -> Result<(TransactionRequestResponse, ShieldedNote)> {
+        let (mut instruction, note) = self
+            .client
+            .shielded_pool()
+            .deposit_instruction(asset_id, account, owner, amount)?;
+        if let Some(reference) = reference {
+            instruction
+                .accounts
+                .push(AccountMeta::new_readonly(reference, false));
+        }
+
+        let instructions = self
+            .client
+            .with_compute_budget(vec![instruction], &self.client.config)
+            .await?;
+        let (recent_blockhash, _) = self
+            .client
+            .rpc_client
+            .get_latest_blockhash_with_commitment(self.client.config.commitment)
+            .await?;
+        let transaction = Transaction::new_unsigned(Message::new_with_blockhash(
+            &instructions,
+            Some(account),
+            &recent_blockhash,
+        ));
+
+        let bytes = bincode::serialize(&transaction).map_err(anyhow::Error::from)?;
+        Ok((
+            TransactionRequestResponse {
+                transaction: STANDARD.encode(bytes),
+                message,
+            },
+            note,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, instruction::Instruction, system_instruction};
+
+    #[test]
+    fn test_transaction_requests() {
+        let link = "https://shop.example/pay?order=42&item=mug";
+        let url = transaction_request_url(link).unwrap();
+        assert!(!url["solana:".len()..].contains('?'));
+        assert_eq!(parse_transaction_request_url(&url).unwrap().as_str(), link);
+        assert_eq!(
+            transaction_request_url("https://shop.example/pay").unwrap(),
+            "solana:https://shop.example/pay"
+        );
+        assert!(transaction_request_url("http://shop.example/pay").is_err());
+        assert!(parse_transaction_request_url(
+            "solana:9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin"
+        )
+        .is_err());
+
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let transaction = |instructions: &[Instruction]| {
+            Transaction::new_unsigned(Message::new_with_blockhash(
+                instructions,
+                Some(&payer),
+                &Hash::new_unique(),
+            ))
+        };
+        let private =
+            Instruction::new_with_bytes(program_id, &[1], vec![AccountMeta::new(payer, true)]);
+        let memo = Instruction::new_with_bytes(MEMO_PROGRAM_ID, b"order 42", vec![]);
+        let public = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+
+        let shielded = transaction(&[private.clone(), memo.clone()]);
+        assert!(check_private_transaction(&shielded, &payer, &program_id).is_ok());
+        assert!(check_private_transaction(&shielded, &Pubkey::new_unique(), &program_id).is_err());
+        assert!(
+            check_private_transaction(&transaction(&[private, public]), &payer, &program_id)
+                .is_err()
+        );
+        assert!(check_private_transaction(&transaction(&[memo]), &payer, &program_id).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use anchor_lang::Discriminator;
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::Transaction,
+};
+use std::{str::FromStr, time::Duration};
+use untrace_privacy_program::instruction;
+
+use crate::error::{ClientError, Result};
+use crate::{ClientConfig, UntraceClient};
+
+/// Relayer paying for transactions it co-signs, so a fresh account with no
+/// SOL can withdraw or transfer from the pools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSponsor {
+    /// Account paying the fee and the rent of the accounts created
+    pub fee_payer: Pubkey,
+    /// Endpoint taking a `SponsorRequest` and answering a `SponsorResponse`
+    pub url: String,
+}
+
+/// Transaction handed to a sponsor, signed by everyone but the sponsor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SponsorRequest {
+    /// Base64 of the serialized transaction
+    pub transaction: String,
+}
+
+/// Sponsor's answer once it signed and sent the transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SponsorResponse {
+    pub signature: String,
+}
+
+impl SponsorRequest {
+    pub fn new(transaction: &Transaction) -> anyhow::Result<Self> {
+        Ok(Self {
+            transaction: STANDARD.encode(bincode::serialize(transaction)?),
+        })
+    }
+
+    /// The transaction to co-sign, for the sponsor
+    pub fn decode(&self) -> anyhow::Result<Transaction> {
+        Ok(bincode::deserialize(&STANDARD.decode(&self.transaction)?)?)
+    }
+}
+
+impl FeeSponsor {
+    pub fn new(fee_payer: Pubkey, url: impl Into<String>) -> Self {
+        Self {
+            fee_payer,
+            url: url.into(),
+        }
+    }
+
+    /// Hand `transaction` to the sponsor to co-sign and send, returning its
+    /// signature. The sponsor does not wait for it to be confirmed.
+    pub async fn submit(
+        &self,
+        transaction: &Transaction,
+        timeout: Duration,
+    ) -> anyhow::Result<Signature> {
+        let response: SponsorResponse = reqwest::Client::new()
+            .post(&self.url)
+            .timeout(timeout)
+            .json(&SponsorRequest::new(transaction)?)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())?
+            .json()
+            .await?;
+        Ok(Signature::from_str(&response.signature)?)
+    }
+}
+
+/// Check that a transaction handed to `sponsor` only spends its SOL on
+/// what it agreed to pay for: it is the fee payer, and every instruction is
+/// a compute budget one or a withdrawal or private transfer of the privacy
+/// program, which take the sponsor as the rent payer of the accounts they
+/// create and nothing else
+pub fn check_sponsored_transaction(
+    transaction: &Transaction,
+    sponsor: &Pubkey,
+    program_id: &Pubkey,
+) -> anyhow::Result<()> {
+    let message = &transaction.message;
+    if message.account_keys.first() != Some(sponsor) {
+        bail!("Transaction is not paid by {}", sponsor);
+    }
+
+    for compiled in &message.instructions {
+        let invoked = message
+            .account_keys
+            .get(compiled.program_id_index as usize)
+            .ok_or_else(|| anyhow!("Instruction program index out of range"))?;
+        if *invoked == compute_budget::id() {
+            continue;
+        }
+        let sponsored = *invoked == *program_id
+            && (compiled
+                .data
+                .starts_with(&instruction::Withdraw::DISCRIMINATOR)
+                || compiled
+                    .data
+                    .starts_with(&instruction::PrivateTransfer::DISCRIMINATOR));
+        if !sponsored {
+            bail!(
+                "Transaction runs an instruction of {} the sponsor does not pay for",
+                invoked
+            );
+        }
+    }
+    Ok(())
+}
+
+impl UntraceClient {
+    /// Account paying the rent of the nullifiers and notes a withdrawal or
+    /// private transfer creates: the fee sponsor if there is one, else the
+    /// payer
+    pub fn rent_payer(&self) -> Pubkey {
+        match &self.config.fee_sponsor {
+            Some(sponsor) => sponsor.fee_payer,
+            None => self.payer.pubkey(),
+        }
+    }
+
+    /// Send a withdrawal or private transfer under `config`, through its
+    /// fee sponsor if it has one
+    pub(crate) async fn send_paid_transaction(
+        &self,
+        instructions: Vec<Instruction>,
+        config: &ClientConfig,
+    ) -> Result<Signature> {
+        match &config.fee_sponsor {
+            Some(sponsor) => {
+                self.send_sponsored_transaction(instructions, &[], sponsor, config)
+                    .await
+            }
+            None => {
+                self.send_transaction_with_config(instructions, &[], config)
+                    .await
+            }
+        }
+    }
+
+    /// Send `instructions` paid for by `sponsor`: sign them as the payer and
+    /// `signers` where the instructions need it, hand the transaction to the
+    /// sponsor to co-sign and send, and wait for it to be confirmed. A
+    /// transaction whose blockhash expires is re-signed as `config` allows.
+    pub async fn send_sponsored_transaction(
+        &self,
+        instructions: Vec<Instruction>,
+        signers: &[&dyn Signer],
+        sponsor: &FeeSponsor,
+        config: &ClientConfig,
+    ) -> Result<Signature> {
+        let mut all_signers: Vec<&dyn Signer> = vec![self.payer.as_ref()];
+        all_signers.extend_from_slice(signers);
+
+        let instructions = self.with_compute_budget(instructions, config).await?;
+        let message = Message::new(&instructions, Some(&sponsor.fee_payer));
+        // Withdrawals and transfers are authorized by their proofs, so the
+        // payer may not be a signer at all
+        let required = &message.account_keys[..message.header.num_required_signatures as usize];
+        all_signers.retain(|signer| required.contains(&signer.pubkey()));
+
+        let mut attempt = 0;
+        loop {
+            let recent_blockhash = self.latest_blockhash(config).await?;
+            let mut transaction = Transaction::new_unsigned(message.clone());
+            transaction
+                .try_partial_sign(&all_signers, recent_blockhash)
+                .map_err(anyhow::Error::from)?;
+            let transaction = self.sign_externally(transaction).await?;
+
+            let signature = sponsor.submit(&transaction, config.timeout).await?;
+            if let Some(signature) = self.confirm(&signature, &recent_blockhash, config).await? {
+                return Ok(signature);
+            }
+
+            if !config.refresh_blockhash {
+                return Err(ClientError::BlockhashExpired);
+            }
+            if attempt >= config.max_retries {
+                return Err(ClientError::NotConfirmed {
+                    attempts: attempt + 1,
+                });
+            }
+            attempt += 1;
+            tokio::time::sleep(config.retry_delay(attempt)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{instruction::AccountMeta, system_instruction};
+
+    fn program_call(program_id: &Pubkey, sponsor: &Pubkey, data: [u8; 8]) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![AccountMeta::new(*sponsor, true)],
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_sponsored_transaction_checks() {
+        let (sponsor, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let check = |instructions: &[Instruction], payer: &Pubkey| {
+            let transaction = Transaction::new_unsigned(Message::new(instructions, Some(payer)));
+            check_sponsored_transaction(&transaction, &sponsor, &program_id)
+        };
+
+        let withdraw = program_call(&program_id, &sponsor, instruction::Withdraw::DISCRIMINATOR);
+        let transfer = program_call(
+            &program_id,
+            &sponsor,
+            instruction::PrivateTransfer::DISCRIMINATOR,
+        );
+        let budget = compute_budget::ComputeBudgetInstruction::set_compute_unit_price(1);
+        assert!(check(&[budget, withdraw.clone(), transfer], &sponsor).is_ok());
+
+        // Paid by someone else
+        assert!(check(std::slice::from_ref(&withdraw), &Pubkey::new_unique()).is_err());
+        // Other privacy program instructions, and other programs
+        let deposit = program_call(&program_id, &sponsor, instruction::Deposit::DISCRIMINATOR);
+        assert!(check(&[withdraw.clone(), deposit], &sponsor).is_err());
+        let drain = system_instruction::transfer(&sponsor, &Pubkey::new_unique(), 1_000_000);
+        assert!(check(&[withdraw, drain], &sponsor).is_err());
+    }
+
+    #[test]
+    fn test_sponsor_request_round_trip() {
+        let payer = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer, &Pubkey::new_unique(), 5);
+        let transaction = Transaction::new_unsigned(Message::new(&[transfer], Some(&payer)));
+
+        let request = SponsorRequest::new(&transaction).unwrap();
+        let json = serde_json::to_string(&request).unwrap();
+        let decoded: SponsorRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.decode().unwrap(), transaction);
+    }
+}
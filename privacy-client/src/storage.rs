@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf, sync::Mutex};
+
+/// Key-value store for client state that should survive restarts, such as
+/// synced commitment trees
+pub trait ClientStorage: Send + Sync {
+    /// Read the value stored under `key`
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store `value` under `key`, replacing any previous value
+    fn put(&self, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Remove the value stored under `key`, if any
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Storage kept in memory for the life of the process
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClientStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Storage with one file per key under a directory
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// Store files under `dir`, creating it if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        // Keys become file names, so keep them to a safe alphabet
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(anyhow!("Invalid storage key {:?}", key));
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+impl ClientStorage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        // Write then rename so a crash never leaves a partial value
+        let path = self.path(key)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, value)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_storage_round_trip() {
+        let dir = std::env::temp_dir().join(format!("untrace-storage-{}", rand::random::<u64>()));
+        let storage = FileStorage::new(&dir).unwrap();
+
+        assert_eq!(storage.get("tree").unwrap(), None);
+        storage.put("tree", b"leaves").unwrap();
+        assert_eq!(storage.get("tree").unwrap(), Some(b"leaves".to_vec()));
+
+        storage.delete("tree").unwrap();
+        storage.delete("tree").unwrap();
+        assert_eq!(storage.get("tree").unwrap(), None);
+        assert!(storage.put("../escape", b"x").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{anyhow, Result};
+use futures::{stream, Stream, StreamExt};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcAccountInfoConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::UntraceClient;
+
+/// Wait before reconnecting a subscription whose websocket dropped
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Notifications buffered while a subscriber is busy
+const SUBSCRIPTION_BUFFER: usize = 64;
+
+/// Websocket URL of the RPC node at `rpc_url`, as the Solana CLI derives
+/// it: `ws` for `http` and `wss` for `https`, one port above an explicit
+/// RPC port
+pub fn websocket_url(rpc_url: &str) -> Result<String> {
+    let (scheme, rest) = if let Some(rest) = rpc_url.strip_prefix("https://") {
+        ("wss", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        ("ws", rest)
+    } else {
+        return Err(anyhow!("Not an HTTP RPC URL: {}", rpc_url));
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    // An IPv6 host without a port has colons but no port to parse
+    let authority = match authority.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(port) => format!("{}:{}", host, port.saturating_add(1)),
+            Err(_) => authority.to_string(),
+        },
+        None => authority.to_string(),
+    };
+    Ok(format!("{}://{}{}", scheme, authority, path))
+}
+
+/// What one subscription follows
+#[derive(Debug, Clone)]
+enum Subscription {
+    Account(Pubkey),
+    ProgramLogs(Pubkey),
+}
+
+/// Notification of a `Subscription`
+#[derive(Debug, Clone)]
+enum Notification {
+    Lamports(u64),
+    /// Signature of a transaction that succeeded
+    Transaction(String),
+}
+
+impl UntraceClient {
+    /// Websocket URL subscriptions connect to: the config's, or else the
+    /// one the primary RPC node serves
+    pub fn ws_url(&self) -> Result<String> {
+        match &self.config.ws_url {
+            Some(url) => Ok(url.clone()),
+            None => websocket_url(&self.rpc_client.url()),
+        }
+    }
+
+    /// Lamports of `address` each time its account changes, pushed over
+    /// the websocket. The subscription reconnects after `RECONNECT_DELAY`
+    /// when the websocket drops, yielding the error, and ends when the
+    /// stream is dropped.
+    pub fn subscribe_lamports(&self, address: Pubkey) -> Result<impl Stream<Item = Result<u64>>> {
+        let notifications = self.subscribe(Subscription::Account(address))?;
+        Ok(notifications.filter_map(|notification| async move {
+            match notification {
+                Ok(Notification::Lamports(lamports)) => Some(Ok(lamports)),
+                Ok(_) => None,
+                Err(error) => Some(Err(error)),
+            }
+        }))
+    }
+
+    /// Signature of each transaction calling the privacy program that
+    /// succeeds, pushed over the websocket, which reconnects as for
+    /// `subscribe_lamports`. Each one may have changed a pool's tree or
+    /// carried a note, so it is the cue to sync or scan.
+    pub fn subscribe_program_transactions(&self) -> Result<impl Stream<Item = Result<String>>> {
+        let notifications = self.subscribe(Subscription::ProgramLogs(self.program_id))?;
+        Ok(notifications.filter_map(|notification| async move {
+            match notification {
+                Ok(Notification::Transaction(signature)) => Some(Ok(signature)),
+                Ok(_) => None,
+                Err(error) => Some(Err(error)),
+            }
+        }))
+    }
+
+    /// Follow `subscription` on a task of its own, which owns the websocket
+    /// the notifications borrow, and hand them over a channel
+    fn subscribe(
+        &self,
+        subscription: Subscription,
+    ) -> Result<impl Stream<Item = Result<Notification>>> {
+        let ws_url = self.ws_url()?;
+        let commitment = self.config.commitment;
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let error = tokio::select! {
+                    result = forward(&ws_url, &subscription, commitment, &sender) => match result {
+                        Ok(()) => anyhow!("Websocket subscription to {} ended", ws_url),
+                        Err(error) => error,
+                    },
+                    _ = sender.closed() => return,
+                };
+                if sender.send(Err(error)).await.is_err() {
+                    return;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+        Ok(stream::unfold(receiver, |mut receiver| async move {
+            let notification = receiver.recv().await?;
+            Some((notification, receiver))
+        }))
+    }
+}
+
+/// Connect to `ws_url` and forward the notifications of `subscription`
+/// until the websocket drops
+async fn forward(
+    ws_url: &str,
+    subscription: &Subscription,
+    commitment: CommitmentConfig,
+    sender: &mpsc::Sender<Result<Notification>>,
+) -> Result<()> {
+    let client = PubsubClient::new(ws_url).await?;
+    match subscription {
+        Subscription::Account(address) => {
+            let config = RpcAccountInfoConfig {
+                commitment: Some(commitment),
+                ..RpcAccountInfoConfig::default()
+            };
+            let (mut updates, unsubscribe) =
+                client.account_subscribe(address, Some(config)).await?;
+            while let Some(update) = updates.next().await {
+                if sender
+                    .send(Ok(Notification::Lamports(update.value.lamports)))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            unsubscribe().await;
+        }
+        Subscription::ProgramLogs(program_id) => {
+            let filter = RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]);
+            let config = RpcTransactionLogsConfig {
+                commitment: Some(commitment),
+            };
+            let (mut updates, unsubscribe) = client.logs_subscribe(filter, config).await?;
+            while let Some(update) = updates.next().await {
+                if update.value.err.is_some() {
+                    continue;
+                }
+                if sender
+                    .send(Ok(Notification::Transaction(update.value.signature)))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            unsubscribe().await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(
+            websocket_url("https://api.devnet.solana.com").unwrap(),
+            "wss://api.devnet.solana.com"
+        );
+        assert_eq!(
+            websocket_url("http://localhost:8899").unwrap(),
+            "ws://localhost:8900"
+        );
+        assert_eq!(
+            websocket_url("https://rpc.example.com:443/key/abc").unwrap(),
+            "wss://rpc.example.com:444/key/abc"
+        );
+        assert_eq!(
+            websocket_url("http://[::1]:8899").unwrap(),
+            "ws://[::1]:8900"
+        );
+        assert!(websocket_url("localhost:8899").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::anyhow;
+
+use crate::cross_chain::SupportedChain;
+
+/// Decimals of SOL, the unit bridge fees are set in
+pub const SOL_DECIMALS: u8 = 9;
+
+/// A token as it exists on one chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub chain: SupportedChain,
+    /// Mint on Solana, contract on EVM chains; `None` for the chain's
+    /// native coin
+    pub address: Option<String>,
+    pub decimals: u8,
+    /// Issued by a bridge on this chain rather than natively
+    pub wrapped: bool,
+}
+
+impl TokenInfo {
+    pub fn new(
+        symbol: &str,
+        chain: SupportedChain,
+        address: Option<&str>,
+        decimals: u8,
+        wrapped: bool,
+    ) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            chain,
+            address: address.map(str::to_string),
+            decimals,
+            wrapped,
+        }
+    }
+
+    /// Whether `token` names this token, by symbol or by address
+    pub fn matches(&self, token: &str) -> bool {
+        if self.symbol.eq_ignore_ascii_case(token) {
+            return true;
+        }
+        match (&self.address, self.chain) {
+            (Some(address), SupportedChain::Solana) => address == token,
+            // EVM addresses are case-insensitive
+            (Some(address), _) => address.eq_ignore_ascii_case(token),
+            (None, _) => false,
+        }
+    }
+}
+
+/// Rescale `amount` from `from_decimals` to `to_decimals`. `None` if the
+/// result overflows, or if scaling down would drop a nonzero remainder.
+pub fn normalize_amount(amount: u128, from_decimals: u8, to_decimals: u8) -> Option<u128> {
+    if to_decimals >= from_decimals {
+        let factor = 10u128.checked_pow((to_decimals - from_decimals) as u32)?;
+        amount.checked_mul(factor)
+    } else {
+        let factor = 10u128.checked_pow((from_decimals - to_decimals) as u32)?;
+        amount.is_multiple_of(factor).then_some(amount / factor)
+    }
+}
+
+/// Tokens known on each supported chain, linking a token to its wrapped
+/// counterparts elsewhere by symbol, so bridged amounts can be rescaled
+/// between chains with different decimals
+#[derive(Debug, Clone)]
+pub struct TokenRegistry {
+    tokens: Vec<TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self { tokens: Vec::new() }
+    }
+
+    /// Add `token`, replacing any entry with its symbol on its chain
+    pub fn register(&mut self, token: TokenInfo) {
+        self.tokens.retain(|known| {
+            known.chain != token.chain || !known.symbol.eq_ignore_ascii_case(&token.symbol)
+        });
+        self.tokens.push(token);
+    }
+
+    pub fn tokens(&self) -> &[TokenInfo] {
+        &self.tokens
+    }
+
+    /// The token `token` (a symbol or address) names on `chain`
+    pub fn get(&self, chain: SupportedChain, token: &str) -> Option<&TokenInfo> {
+        self.tokens
+            .iter()
+            .find(|known| known.chain == chain && known.matches(token))
+    }
+
+    /// The same token as `token` on `chain`, native or wrapped
+    pub fn counterpart(&self, token: &TokenInfo, chain: SupportedChain) -> Option<&TokenInfo> {
+        self.get(chain, &token.symbol)
+    }
+
+    /// Destination token and amount, in its decimals, for `amount` of
+    /// `token` bridged from `source_chain` to `dest_chain`
+    pub fn convert(
+        &self,
+        source_chain: SupportedChain,
+        dest_chain: SupportedChain,
+        token: &str,
+        amount: u64,
+    ) -> anyhow::Result<(&TokenInfo, u128)> {
+        let source = self
+            .get(source_chain, token)
+            .ok_or_else(|| anyhow!("Unknown token {} on {:?}", token, source_chain))?;
+        let dest = self
+            .counterpart(source, dest_chain)
+            .ok_or_else(|| anyhow!("{} is not available on {:?}", source.symbol, dest_chain))?;
+        let dest_amount = normalize_amount(amount as u128, source.decimals, dest.decimals)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} {} cannot be represented in {} decimals on {:?}",
+                    amount,
+                    source.symbol,
+                    dest.decimals,
+                    dest_chain
+                )
+            })?;
+        Ok((dest, dest_amount))
+    }
+}
+
+impl Default for TokenRegistry {
+    /// Mainnet SOL, ETH and USDC, with their Wormhole-wrapped forms
+    fn default() -> Self {
+        use SupportedChain::*;
+
+        let mut registry = Self::new();
+        for token in [
+            TokenInfo::new("SOL", Solana, None, SOL_DECIMALS, false),
+            TokenInfo::new(
+                "SOL",
+                Ethereum,
+                Some("0xD31a59c85aE9D8edEFeC411D448f90841571b89c"),
+                SOL_DECIMALS,
+                true,
+            ),
+            TokenInfo::new("ETH", Ethereum, None, 18, false),
+            TokenInfo::new("ETH", Arbitrum, None, 18, false),
+            TokenInfo::new("ETH", Optimism, None, 18, false),
+            TokenInfo::new("ETH", Base, None, 18, false),
+            TokenInfo::new("ETH", ArbitrumNova, None, 18, false),
+            // Wormhole caps wrapped decimals at 8 on Solana
+            TokenInfo::new(
+                "ETH",
+                Solana,
+                Some("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs"),
+                8,
+                true,
+            ),
+            TokenInfo::new(
+                "USDC",
+                Solana,
+                Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+                6,
+                false,
+            ),
+            TokenInfo::new(
+                "USDC",
+                Ethereum,
+                Some("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+                6,
+                false,
+            ),
+            TokenInfo::new(
+                "USDC",
+                BinanceSmartChain,
+                Some("0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d"),
+                18,
+                false,
+            ),
+            TokenInfo::new(
+                "USDC",
+                Polygon,
+                Some("0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359"),
+                6,
+                false,
+            ),
+            TokenInfo::new(
+                "USDC",
+                Base,
+                Some("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+                6,
+                false,
+            ),
+        ] {
+            registry.register(token);
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+    #[test]
+    fn test_convert_between_decimals() {
+        let registry = TokenRegistry::default();
+        for token in registry.tokens() {
+            if let Some(address) = &token.address {
+                assert!(token.chain.parse_address(address).is_ok(), "{}", address);
+            }
+        }
+
+        let (usdc, amount) = registry
+            .convert(
+                SupportedChain::Solana,
+                SupportedChain::BinanceSmartChain,
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                2_500_000,
+            )
+            .unwrap();
+        assert_eq!(
+            usdc.address.as_deref(),
+            Some("0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d")
+        );
+        assert_eq!(amount, 2_500_000_000_000_000_000);
+
+        let (eth, amount) = registry
+            .convert(
+                SupportedChain::Ethereum,
+                SupportedChain::Solana,
+                "eth",
+                1_230_000_000_000,
+            )
+            .unwrap();
+        assert!(eth.wrapped);
+        assert_eq!(amount, 123);
+        // Below the 8 decimals wrapped ETH has on Solana
+        assert!(registry
+            .convert(SupportedChain::Ethereum, SupportedChain::Solana, "ETH", 1)
+            .is_err());
+        assert!(registry
+            .convert(
+                SupportedChain::Solana,
+                SupportedChain::Polygon,
+                "SOL",
+                LAMPORTS_PER_SOL
+            )
+            .is_err());
+    }
+}
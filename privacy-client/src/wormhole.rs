@@ -0,0 +1,389 @@
+use anyhow::{anyhow, bail};
+use borsh::{BorshDeserialize, BorshSerialize};
+use sha3::{Digest, Keccak256};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    secp256k1_program, system_program, sysvar,
+};
+
+/// Wormhole core bridge on mainnet-beta
+pub const MAINNET_CORE_BRIDGE: Pubkey = pubkey!("worm2ZoG2kUd4vFXhvjh93UUH596ayRfgQ2MgjNMTth");
+/// Wormhole testnet core bridge, deployed on devnet
+pub const DEVNET_CORE_BRIDGE: Pubkey = pubkey!("3u8hJUVTA4jH1wYAyUur7FFZVQ8H635K3tSHHF4ssjQ5");
+/// Core bridge of Wormhole's local development network
+pub const LOCALNET_CORE_BRIDGE: Pubkey = pubkey!("Bridge1p5gheXUvJ6jGWGeCsgPKgnE3YgdGKRVCMY9o");
+
+/// Most guardians in a guardian set
+pub const MAX_GUARDIANS: usize = 19;
+
+/// Guardian signatures checked per `VerifySignatures` transaction, so each
+/// fits the packet size
+pub const SIGNATURES_PER_TRANSACTION: usize = 7;
+
+const POST_VAA_INSTRUCTION: u8 = 2;
+const VERIFY_SIGNATURES_INSTRUCTION: u8 = 7;
+
+/// Secp256k1 precompile offsets entry
+const SECP256K1_OFFSETS_LEN: usize = 11;
+const ETH_ADDRESS_LEN: usize = 20;
+const SECP256K1_SIGNATURE_LEN: usize = 65;
+
+/// Where a client reaches Wormhole
+#[derive(Debug, Clone)]
+pub struct WormholeConfig {
+    /// Core bridge program on the client's cluster
+    pub core_bridge: Pubkey,
+    /// Guardian or Wormholescan API serving signed VAAs
+    pub guardian_rpc: String,
+}
+
+impl WormholeConfig {
+    pub fn mainnet() -> Self {
+        Self {
+            core_bridge: MAINNET_CORE_BRIDGE,
+            guardian_rpc: "https://api.wormholescan.io".to_string(),
+        }
+    }
+
+    pub fn devnet() -> Self {
+        Self {
+            core_bridge: DEVNET_CORE_BRIDGE,
+            guardian_rpc: "https://api.testnet.wormholescan.io".to_string(),
+        }
+    }
+
+    pub fn localnet() -> Self {
+        Self {
+            core_bridge: LOCALNET_CORE_BRIDGE,
+            guardian_rpc: "http://localhost:7071".to_string(),
+        }
+    }
+}
+
+/// Derive the PDA holding the privacy program's Wormhole settings
+pub fn find_wormhole_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"wormhole_config"], program_id)
+}
+
+/// Derive the PDA the privacy program posts Wormhole messages as
+pub fn find_emitter_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"emitter"], program_id)
+}
+
+/// Derive the PDA recording the trusted emitter on a Wormhole chain
+pub fn find_foreign_emitter_address(program_id: &Pubkey, wormhole_chain_id: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"foreign_emitter", &wormhole_chain_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the core bridge's config account
+pub fn find_bridge_address(core_bridge: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"Bridge"], core_bridge)
+}
+
+/// Derive the core bridge account collecting message fees
+pub fn find_fee_collector_address(core_bridge: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"fee_collector"], core_bridge)
+}
+
+/// Derive the core bridge's sequence tracker for `emitter`
+pub fn find_sequence_address(core_bridge: &Pubkey, emitter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"Sequence", emitter.as_ref()], core_bridge)
+}
+
+/// Derive the core bridge account of guardian set `index`
+pub fn find_guardian_set_address(core_bridge: &Pubkey, index: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"GuardianSet", &index.to_be_bytes()], core_bridge)
+}
+
+/// Derive the account the core bridge posts a VAA with `body_hash` to
+pub fn find_posted_vaa_address(core_bridge: &Pubkey, body_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"PostedVAA", body_hash], core_bridge)
+}
+
+/// One guardian's signature in a VAA
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuardianSignature {
+    /// Position of the guardian in the signing guardian set
+    pub guardian_index: u8,
+    /// r, s and recovery ID
+    pub signature: [u8; SECP256K1_SIGNATURE_LEN],
+}
+
+/// Verified action approval: a Wormhole message signed by the guardians
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vaa {
+    pub version: u8,
+    pub guardian_set_index: u32,
+    pub signatures: Vec<GuardianSignature>,
+    pub timestamp: u32,
+    pub nonce: u32,
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub sequence: u64,
+    pub consistency_level: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Vaa {
+    /// Decode a VAA from its wire format
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        let guardian_set_index = u32::from_be_bytes(reader.array()?);
+
+        let count = reader.u8()? as usize;
+        let mut signatures = Vec::with_capacity(count);
+        for _ in 0..count {
+            signatures.push(GuardianSignature {
+                guardian_index: reader.u8()?,
+                signature: reader.array()?,
+            });
+        }
+
+        Ok(Self {
+            version,
+            guardian_set_index,
+            signatures,
+            timestamp: u32::from_be_bytes(reader.array()?),
+            nonce: u32::from_be_bytes(reader.array()?),
+            emitter_chain: u16::from_be_bytes(reader.array()?),
+            emitter_address: reader.array()?,
+            sequence: u64::from_be_bytes(reader.array()?),
+            consistency_level: reader.u8()?,
+            payload: reader.0.to_vec(),
+        })
+    }
+
+    /// The signed part of the VAA
+    pub fn body(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(51 + self.payload.len());
+        body.extend_from_slice(&self.timestamp.to_be_bytes());
+        body.extend_from_slice(&self.nonce.to_be_bytes());
+        body.extend_from_slice(&self.emitter_chain.to_be_bytes());
+        body.extend_from_slice(&self.emitter_address);
+        body.extend_from_slice(&self.sequence.to_be_bytes());
+        body.push(self.consistency_level);
+        body.extend_from_slice(&self.payload);
+        body
+    }
+
+    /// Hash identifying the VAA; guardians sign its Keccak-256
+    pub fn body_hash(&self) -> [u8; 32] {
+        Keccak256::digest(self.body()).into()
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        if self.0.len() < N {
+            bail!("VAA is truncated");
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into()?)
+    }
+}
+
+#[derive(BorshDeserialize)]
+struct GuardianSetData {
+    _index: u32,
+    keys: Vec<[u8; ETH_ADDRESS_LEN]>,
+    _creation_time: u32,
+    _expiration_time: u32,
+}
+
+/// Ethereum addresses of the guardians in a core bridge guardian set account
+pub fn decode_guardian_keys(data: &[u8]) -> anyhow::Result<Vec<[u8; ETH_ADDRESS_LEN]>> {
+    let mut data = data;
+    let set = GuardianSetData::deserialize(&mut data)
+        .map_err(|e| anyhow!("Invalid guardian set account: {}", e))?;
+    Ok(set.keys)
+}
+
+/// Build a Secp256k1 precompile instruction checking guardian signatures
+/// over `body_hash`. Precompile offsets name instructions by position, so
+/// `instruction_index` must be where this lands in its transaction.
+pub fn guardian_secp256k1_instruction(
+    signatures: &[([u8; ETH_ADDRESS_LEN], [u8; SECP256K1_SIGNATURE_LEN])],
+    body_hash: &[u8; 32],
+    instruction_index: u8,
+) -> Instruction {
+    let entry_len = ETH_ADDRESS_LEN + SECP256K1_SIGNATURE_LEN;
+    let data_start = 1 + signatures.len() * SECP256K1_OFFSETS_LEN;
+    let message_offset = data_start + signatures.len() * entry_len;
+
+    let mut data = Vec::with_capacity(message_offset + body_hash.len());
+    data.push(signatures.len() as u8);
+    for i in 0..signatures.len() {
+        let eth_address_offset = data_start + i * entry_len;
+        let signature_offset = eth_address_offset + ETH_ADDRESS_LEN;
+
+        data.extend_from_slice(&(signature_offset as u16).to_le_bytes());
+        data.push(instruction_index);
+        data.extend_from_slice(&(eth_address_offset as u16).to_le_bytes());
+        data.push(instruction_index);
+        data.extend_from_slice(&(message_offset as u16).to_le_bytes());
+        data.extend_from_slice(&(body_hash.len() as u16).to_le_bytes());
+        data.push(instruction_index);
+    }
+    for (eth_address, signature) in signatures {
+        data.extend_from_slice(eth_address);
+        data.extend_from_slice(signature);
+    }
+    data.extend_from_slice(body_hash);
+
+    Instruction {
+        program_id: secp256k1_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+/// Build a core bridge `VerifySignatures` instruction, recording the
+/// signatures checked by the Secp256k1 instruction just before it.
+/// `signers[guardian]` is that guardian's position in the precompile
+/// instruction, or -1.
+pub fn verify_signatures_instruction(
+    core_bridge: &Pubkey,
+    payer: &Pubkey,
+    guardian_set_index: u32,
+    signature_set: &Pubkey,
+    signers: [i8; MAX_GUARDIANS],
+) -> Instruction {
+    let mut data = vec![VERIFY_SIGNATURES_INSTRUCTION];
+    data.extend(signers.iter().map(|s| *s as u8));
+
+    Instruction {
+        program_id: *core_bridge,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(
+                find_guardian_set_address(core_bridge, guardian_set_index).0,
+                false,
+            ),
+            AccountMeta::new(*signature_set, true),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    }
+}
+
+#[derive(BorshSerialize)]
+struct PostVaaData<'a> {
+    version: u8,
+    guardian_set_index: u32,
+    timestamp: u32,
+    nonce: u32,
+    emitter_chain: u16,
+    emitter_address: [u8; 32],
+    sequence: u64,
+    consistency_level: u8,
+    payload: &'a [u8],
+}
+
+/// Build a core bridge `PostVAA` instruction, posting `vaa` once
+/// `signature_set` holds a quorum of its signatures
+pub fn post_vaa_instruction(
+    core_bridge: &Pubkey,
+    payer: &Pubkey,
+    signature_set: &Pubkey,
+    vaa: &Vaa,
+) -> anyhow::Result<Instruction> {
+    let mut data = vec![POST_VAA_INSTRUCTION];
+    PostVaaData {
+        version: vaa.version,
+        guardian_set_index: vaa.guardian_set_index,
+        timestamp: vaa.timestamp,
+        nonce: vaa.nonce,
+        emitter_chain: vaa.emitter_chain,
+        emitter_address: vaa.emitter_address,
+        sequence: vaa.sequence,
+        consistency_level: vaa.consistency_level,
+        payload: &vaa.payload,
+    }
+    .serialize(&mut data)?;
+
+    Ok(Instruction {
+        program_id: *core_bridge,
+        accounts: vec![
+            AccountMeta::new_readonly(
+                find_guardian_set_address(core_bridge, vaa.guardian_set_index).0,
+                false,
+            ),
+            AccountMeta::new_readonly(find_bridge_address(core_bridge).0, false),
+            AccountMeta::new_readonly(*signature_set, false),
+            AccountMeta::new(
+                find_posted_vaa_address(core_bridge, &vaa.body_hash()).0,
+                false,
+            ),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vaa_round_trip() {
+        let vaa = Vaa {
+            version: 1,
+            guardian_set_index: 3,
+            signatures: vec![
+                GuardianSignature {
+                    guardian_index: 0,
+                    signature: [1u8; 65],
+                },
+                GuardianSignature {
+                    guardian_index: 4,
+                    signature: [2u8; 65],
+                },
+            ],
+            timestamp: 1_700_000_000,
+            nonce: 0,
+            emitter_chain: 2,
+            emitter_address: [7u8; 32],
+            sequence: 42,
+            consistency_level: 1,
+            payload: b"bridge message".to_vec(),
+        };
+
+        let mut bytes = vec![vaa.version];
+        bytes.extend_from_slice(&vaa.guardian_set_index.to_be_bytes());
+        bytes.push(vaa.signatures.len() as u8);
+        for signature in &vaa.signatures {
+            bytes.push(signature.guardian_index);
+            bytes.extend_from_slice(&signature.signature);
+        }
+        bytes.extend(vaa.body());
+
+        assert_eq!(Vaa::parse(&bytes).unwrap(), vaa);
+        assert!(Vaa::parse(&bytes[..bytes.len() - vaa.payload.len() - 1]).is_err());
+
+        // Each entry's message offset points at the shared body hash
+        let body_hash = vaa.body_hash();
+        let instruction =
+            guardian_secp256k1_instruction(&[([9u8; 20], [1u8; 65]); 2], &body_hash, 0);
+        let message_offset = u16::from_le_bytes([instruction.data[7], instruction.data[8]]);
+        assert_eq!(&instruction.data[message_offset as usize..], &body_hash);
+        assert_eq!(instruction.data.len(), 1 + 2 * 11 + 2 * 85 + 32);
+    }
+}
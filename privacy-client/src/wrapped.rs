@@ -0,0 +1,270 @@
+use anchor_lang::AccountDeserialize;
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, signature::Signature, system_program, sysvar,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use untrace_common::{UntraceError, WrappedTransferMessage};
+use untrace_privacy_program::{accounts, instruction, state::WrappedMintAccount};
+
+use crate::admin::find_admin_config_address;
+use crate::cross_chain::{
+    find_consumed_message_address, find_guardian_outbox_address, find_guardian_set_address,
+    guardian_signature_instruction, SupportedChain,
+};
+use crate::error::{ClientError, Result};
+use crate::events::find_event_authority_address;
+use crate::UntraceClient;
+
+/// Derive the registry PDA of the wrapped mint for `origin_asset` of
+/// `origin_chain`
+pub fn find_wrapped_mint_address(
+    program_id: &Pubkey,
+    origin_chain: SupportedChain,
+    origin_asset: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"wrapped_mint",
+            &origin_chain.to_u16().to_le_bytes(),
+            origin_asset,
+        ],
+        program_id,
+    )
+}
+
+/// Derive the SPL mint of the wrapped tokens registered at `wrapped_mint`
+pub fn find_wrapped_token_address(program_id: &Pubkey, wrapped_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"wrapped_token", wrapped_mint.as_ref()], program_id)
+}
+
+/// Wrapped representations of assets bridged in from other chains
+pub struct WrappedAssetClient<'a> {
+    client: &'a UntraceClient,
+}
+
+impl<'a> WrappedAssetClient<'a> {
+    pub fn new(client: &'a UntraceClient) -> Self {
+        Self { client }
+    }
+
+    /// Create the wrapped mint for `origin_asset` of `origin_chain`, with
+    /// `decimals`; signed by the guardian set authority
+    pub async fn register(
+        &self,
+        origin_chain: SupportedChain,
+        origin_asset: [u8; 32],
+        decimals: u8,
+    ) -> Result<Signature> {
+        let program_id = self.client.program_id;
+        let (wrapped_mint, _) = find_wrapped_mint_address(&program_id, origin_chain, &origin_asset);
+
+        let instruction = self.client.program_instruction(
+            accounts::RegisterWrappedMint {
+                guardian_set: find_guardian_set_address(&program_id).0,
+                wrapped_mint,
+                mint: find_wrapped_token_address(&program_id, &wrapped_mint).0,
+                authority: self.client.payer.pubkey(),
+                token_program: spl_token::id(),
+                system_program: system_program::id(),
+                rent: sysvar::rent::id(),
+            },
+            instruction::RegisterWrappedMint {
+                origin_chain: origin_chain.to_u16(),
+                origin_asset,
+                decimals,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+
+    /// Registry entry of the wrapped mint for `origin_asset`, or `None` if
+    /// it was never registered
+    pub async fn wrapped_mint(
+        &self,
+        origin_chain: SupportedChain,
+        origin_asset: &[u8; 32],
+    ) -> Result<Option<WrappedMintAccount>> {
+        let (address, _) =
+            find_wrapped_mint_address(&self.client.program_id, origin_chain, origin_asset);
+        let account = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&address, self.client.rpc_client.commitment())
+            .await?
+            .value;
+
+        account
+            .map(|account| {
+                WrappedMintAccount::try_deserialize(&mut account.data.as_slice())
+                    .map_err(|e| anyhow::Error::from(e).into())
+            })
+            .transpose()
+    }
+
+    /// Mint an inbound wrapped transfer to its recipient's associated token
+    /// account, created if needed, using guardian signatures over it. The
+    /// payer fronts the transaction and is paid the message's relayer fee.
+    pub async fn claim(
+        &self,
+        message: &WrappedTransferMessage,
+        guardian_signatures: &[(Pubkey, [u8; 64])],
+    ) -> Result<Signature> {
+        let program_id = self.client.program_id;
+        let digest = message.digest();
+        let (consumed_message, _) = find_consumed_message_address(&program_id, &digest);
+        let consumed = self
+            .client
+            .rpc_client
+            .get_account_with_commitment(&consumed_message, self.client.rpc_client.commitment())
+            .await?
+            .value;
+        if consumed.is_some_and(|account| !account.data.is_empty()) {
+            return Err(ClientError::Program(UntraceError::MessageAlreadyConsumed));
+        }
+
+        let origin_chain = require_chain(message.origin_chain)?;
+        let (wrapped_mint, _) =
+            find_wrapped_mint_address(&program_id, origin_chain, &message.origin_asset);
+        let (mint, _) = find_wrapped_token_address(&program_id, &wrapped_mint);
+        let recipient = Pubkey::new_from_array(message.recipient);
+        let recipient_token_account = get_associated_token_address(&recipient, &mint);
+        let payer = self.client.payer.pubkey();
+        let relayer_token_account =
+            (message.relayer_fee > 0).then(|| get_associated_token_address(&payer, &mint));
+
+        // Signature checks must precede the mint in the same transaction
+        let mut instructions: Vec<Instruction> = guardian_signatures
+            .iter()
+            .map(|(guardian, signature)| {
+                guardian_signature_instruction(guardian, signature, &digest)
+            })
+            .collect();
+        instructions.push(create_associated_token_account_idempotent(
+            &payer,
+            &recipient,
+            &mint,
+            &spl_token::id(),
+        ));
+        if relayer_token_account.is_some() {
+            instructions.push(create_associated_token_account_idempotent(
+                &payer,
+                &payer,
+                &mint,
+                &spl_token::id(),
+            ));
+        }
+        instructions.push(self.client.program_instruction(
+            accounts::MintWrappedTransfer {
+                guardian_set: find_guardian_set_address(&program_id).0,
+                wrapped_mint,
+                mint,
+                consumed_message,
+                recipient_token_account,
+                relayer_token_account,
+                payer,
+                instructions: sysvar::instructions::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+            },
+            instruction::MintWrappedTransfer {
+                message: message.clone(),
+            },
+        ));
+
+        self.client.send_transaction(instructions).await
+    }
+
+    /// Burn `amount` of the payer's wrapped tokens for `origin_asset`,
+    /// releasing the asset to `recipient` on its origin chain once the
+    /// guardian set signs the exit. With a `relayer_fee`, such as a quote's
+    /// `relayer_fee()`, a relayer claims it there and keeps that much, so
+    /// the recipient needs no gas of their own.
+    pub async fn burn(
+        &self,
+        origin_chain: SupportedChain,
+        origin_asset: &[u8; 32],
+        recipient: [u8; 32],
+        amount: u64,
+        relayer_fee: u64,
+    ) -> Result<Signature> {
+        if relayer_fee > amount {
+            return Err(ClientError::Program(UntraceError::RelayerFeeTooHigh));
+        }
+        let program_id = self.client.program_id;
+        let owner = self.client.payer.pubkey();
+        let (wrapped_mint, _) = find_wrapped_mint_address(&program_id, origin_chain, origin_asset);
+        let (mint, _) = find_wrapped_token_address(&program_id, &wrapped_mint);
+
+        let instruction = self.client.program_instruction(
+            accounts::BurnWrappedTokens {
+                wrapped_mint,
+                mint,
+                owner_token_account: get_associated_token_address(&owner, &mint),
+                guardian_outbox: find_guardian_outbox_address(&program_id).0,
+                owner,
+                token_program: spl_token::id(),
+                system_program: system_program::id(),
+                admin_config: find_admin_config_address(&program_id).0,
+                event_authority: find_event_authority_address(&program_id).0,
+                program: program_id,
+            },
+            instruction::BurnWrappedTokens {
+                recipient,
+                amount,
+                relayer_fee,
+            },
+        );
+
+        self.client.send_transaction(vec![instruction]).await
+    }
+}
+
+fn require_chain(chain_id: u16) -> Result<SupportedChain> {
+    SupportedChain::from_u16(chain_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown chain {}", chain_id).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use untrace_common::{BridgeMessage, SOLANA_CHAIN_ID};
+
+    #[test]
+    fn test_wrapped_mints_are_per_origin_asset() {
+        let program_id = Pubkey::new_unique();
+        let usdc = [1u8; 32];
+        let (ethereum_usdc, _) =
+            find_wrapped_mint_address(&program_id, SupportedChain::Ethereum, &usdc);
+        let (base_usdc, _) = find_wrapped_mint_address(&program_id, SupportedChain::Base, &usdc);
+        assert_ne!(ethereum_usdc, base_usdc);
+        assert_ne!(
+            find_wrapped_token_address(&program_id, &ethereum_usdc).0,
+            find_wrapped_token_address(&program_id, &base_usdc).0
+        );
+
+        // The same fields never sign as both kinds of message
+        let wrapped = WrappedTransferMessage {
+            source_chain: SupportedChain::Ethereum.to_u16(),
+            dest_chain: SOLANA_CHAIN_ID,
+            sequence: 4,
+            origin_chain: SupportedChain::Ethereum.to_u16(),
+            origin_asset: usdc,
+            recipient: [2u8; 32],
+            amount: 1_000_000,
+            relayer_fee: 0,
+        };
+        let bridge = BridgeMessage {
+            source_chain: wrapped.source_chain,
+            dest_chain: wrapped.dest_chain,
+            sequence: wrapped.sequence,
+            recipient: wrapped.recipient,
+            amount: wrapped.amount,
+            payload_hash: usdc,
+        };
+        assert_ne!(wrapped.digest(), bridge.digest());
+    }
+}
@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+use spl_account_compression::{
+    state::{
+        merkle_tree_get_size, ConcurrentMerkleTreeHeader, CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1,
+    },
+    zero_copy::ZeroCopy,
+    ConcurrentMerkleTree,
+};
+use untrace_common::UntraceError;
+
+use crate::state::{COMPRESSED_TREE_BUFFER_SIZE, COMPRESSED_TREE_DEPTH};
+
+type CompressedTree = ConcurrentMerkleTree<
+    { COMPRESSED_TREE_DEPTH as usize },
+    { COMPRESSED_TREE_BUFFER_SIZE as usize },
+>;
+
+/// Check that `root` is one of the roots still held in the changelog buffer
+/// of a compressed pool's tree.
+///
+/// Like the flat pool's root history, this lets a withdrawal be proven
+/// off-chain against a recent root without revealing its leaf.
+pub fn is_recent_root(
+    merkle_tree: &AccountInfo,
+    authority: &Pubkey,
+    root: &[u8; 32],
+) -> Result<bool> {
+    let data = merkle_tree.try_borrow_data()?;
+    require!(
+        data.len() > CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1,
+        UntraceError::InvalidMerkleTree
+    );
+
+    let (header_bytes, rest) = data.split_at(CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1);
+    let header = ConcurrentMerkleTreeHeader::try_from_slice(header_bytes)?;
+    header.assert_valid_authority(authority)?;
+
+    // Only one tree shape is supported so the layout is known at compile time
+    require!(
+        header.get_max_depth() == COMPRESSED_TREE_DEPTH
+            && header.get_max_buffer_size() == COMPRESSED_TREE_BUFFER_SIZE,
+        UntraceError::InvalidMerkleTree
+    );
+
+    let tree_size = merkle_tree_get_size(&header)?;
+    require!(rest.len() >= tree_size, UntraceError::InvalidMerkleTree);
+    let tree = CompressedTree::load_bytes(&rest[..tree_size])?;
+
+    let populated = (tree.buffer_size as usize).min(tree.change_logs.len());
+    Ok(*root != [0u8; 32]
+        && tree.change_logs[..populated]
+            .iter()
+            .any(|change_log| change_log.root == *root))
+}
@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+use untrace_common::{BridgeMessage, WrappedTransferMessage};
+
+/// Emitted through a self-CPI whenever a commitment is appended to a pool's
+/// tree. Unlike `msg!` logs, CPI data is never truncated, so indexers can
+/// rebuild trees and recover notes from it.
+#[event]
+pub struct CommitmentInserted {
+    /// Pool account the commitment was appended to
+    pub pool: Pubkey,
+    /// Leaf position of the commitment in the pool's tree
+    pub leaf_index: u64,
+    pub commitment: [u8; 32],
+    /// Note encrypted for its owner
+    pub encrypted_note: Vec<u8>,
+}
+
+/// Emitted through a self-CPI when an outbound transfer is left for the
+/// guardian set, which signs `message` for its destination
+#[event]
+pub struct GuardianMessagePosted {
+    /// Key naming the transfer's `OutboundTransferAccount`
+    pub transfer: Pubkey,
+    pub message: BridgeMessage,
+}
+
+/// Emitted through a self-CPI when wrapped tokens are burned to exit, for
+/// the guardian set to sign `message` releasing them on their origin chain
+#[event]
+pub struct WrappedTokensBurned {
+    pub message: WrappedTransferMessage,
+}
@@ -0,0 +1,110 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+};
+use untrace_common::UntraceError;
+
+/// Ed25519 precompile header: signature count + padding
+const ED25519_HEADER_LEN: usize = 2;
+/// Size of one signature offsets entry in the precompile data
+const ED25519_OFFSETS_LEN: usize = 14;
+const PUBKEY_LEN: usize = 32;
+
+/// Check `guardians` can attest with `quorum`: a non-empty set of at most
+/// `MAX_GUARDIANS` distinct keys, of which `quorum` is at least one and at
+/// most all. A key listed twice would count twice towards the quorum.
+pub fn check_guardian_set(guardians: &[Pubkey], quorum: u8) -> Result<()> {
+    require!(
+        !guardians.is_empty() && guardians.len() <= untrace_common::MAX_GUARDIANS,
+        UntraceError::InvalidGuardianSet
+    );
+    require!(
+        quorum > 0 && quorum as usize <= guardians.len(),
+        UntraceError::InvalidGuardianSet
+    );
+    for (index, guardian) in guardians.iter().enumerate() {
+        require!(
+            !guardians[..index].contains(guardian),
+            UntraceError::InvalidGuardianSet
+        );
+    }
+    Ok(())
+}
+
+/// Count the distinct guardians whose signature over `digest` was checked by
+/// Ed25519 precompile instructions earlier in the same transaction.
+pub fn count_guardian_signatures(
+    instructions: &AccountInfo,
+    guardians: &[Pubkey],
+    digest: &[u8; 32],
+) -> Result<usize> {
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let mut signed = vec![false; guardians.len()];
+
+    for index in 0..current_index {
+        let instruction = load_instruction_at_checked(index, instructions)?;
+        if instruction.program_id != ed25519_program::ID {
+            continue;
+        }
+
+        for (signer, message) in parse_ed25519_instruction(&instruction.data)? {
+            if message != digest.as_slice() {
+                continue;
+            }
+            if let Some(position) = guardians.iter().position(|g| *g == signer) {
+                signed[position] = true;
+            }
+        }
+    }
+
+    Ok(signed.iter().filter(|s| **s).count())
+}
+
+/// Extract (public key, message) pairs from Ed25519 precompile data.
+///
+/// Only self-contained instructions are accepted: every offset must point
+/// into the precompile instruction itself, otherwise the verified bytes are
+/// not the ones we read here.
+fn parse_ed25519_instruction(data: &[u8]) -> Result<Vec<(Pubkey, &[u8])>> {
+    require!(
+        data.len() >= ED25519_HEADER_LEN,
+        UntraceError::InvalidGuardianSignature
+    );
+
+    let count = data[0] as usize;
+    let mut entries = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let start = ED25519_HEADER_LEN + i * ED25519_OFFSETS_LEN;
+        let offsets = data
+            .get(start..start + ED25519_OFFSETS_LEN)
+            .ok_or(UntraceError::InvalidGuardianSignature)?;
+        let read = |at: usize| u16::from_le_bytes([offsets[at], offsets[at + 1]]);
+
+        let signature_ix = read(2);
+        let pubkey_offset = read(4) as usize;
+        let pubkey_ix = read(6);
+        let message_offset = read(8) as usize;
+        let message_size = read(10) as usize;
+        let message_ix = read(12);
+
+        require!(
+            signature_ix == u16::MAX && pubkey_ix == u16::MAX && message_ix == u16::MAX,
+            UntraceError::InvalidGuardianSignature
+        );
+
+        let pubkey = data
+            .get(pubkey_offset..pubkey_offset + PUBKEY_LEN)
+            .ok_or(UntraceError::InvalidGuardianSignature)?;
+        let message = data
+            .get(message_offset..message_offset + message_size)
+            .ok_or(UntraceError::InvalidGuardianSignature)?;
+
+        let mut key = [0u8; PUBKEY_LEN];
+        key.copy_from_slice(pubkey);
+        entries.push((Pubkey::new_from_array(key), message));
+    }
+
+    Ok(entries)
+}
@@ -1,4 +1,11 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions as instructions_sysvar;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+use spl_account_compression::{program::SplAccountCompression, Noop};
+use untrace_common::{
+    crypto, groth16::Circuit, layerzero::LzReceiveParams, BridgeMessage, NullifierRootMessage,
+    UntraceError, WrappedTransferMessage,
+};
 use crate::state::*;
 
 #[derive(Accounts)]
@@ -13,6 +20,24 @@ pub struct InitializePool<'info> {
     )]
     pub privacy_pool: Account<'info, PrivacyPoolAccount>,
 
+    #[account(
+        init,
+        payer = authority,
+        space = MerkleTreeAccount::LEN,
+        seeds = [b"merkle_tree", pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PoolConfigAccount::LEN,
+        seeds = [b"pool_config", pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pool_config: Account<'info, PoolConfigAccount>,
+
     #[account(mut)]
     pub authority: Signer<'info>,
 
@@ -20,54 +45,230 @@ pub struct InitializePool<'info> {
 }
 
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+pub struct InitializePoolConfig<'info> {
+    #[account(has_one = authority)]
+    pub privacy_pool: Account<'info, PrivacyPoolAccount>,
+
+    // For pools created before configs existed; starts without limits
+    #[account(
+        init,
+        payer = authority,
+        space = PoolConfigAccount::LEN,
+        seeds = [b"pool_config", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pool_config: Account<'info, PoolConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMerkleTree<'info> {
+    #[account(mut, has_one = authority)]
+    pub privacy_pool: Account<'info, PrivacyPoolAccount>,
+
+    // For pools created before trees existed, including migrated ones
+    #[account(
+        init,
+        payer = authority,
+        space = MerkleTreeAccount::LEN,
+        seeds = [b"merkle_tree", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
     #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32])]
+pub struct Deposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"privacy_pool", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
     pub privacy_pool: Account<'info, PrivacyPoolAccount>,
 
     #[account(
         init,
         payer = depositor,
-        space = CommitmentAccount::LEN
+        space = CommitmentAccount::LEN,
+        seeds = [
+            b"commitment",
+            privacy_pool.pool_id.to_le_bytes().as_ref(),
+            commitment.as_ref()
+        ],
+        bump
     )]
     pub commitment_account: Account<'info, CommitmentAccount>,
 
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"pool_config", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump = pool_config.bump
+    )]
+    pub pool_config: Account<'info, PoolConfigAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = DepositTrackerAccount::LEN,
+        seeds = [
+            b"deposit_tracker",
+            privacy_pool.pool_id.to_le_bytes().as_ref(),
+            depositor.key().as_ref()
+        ],
+        bump
+    )]
+    pub deposit_tracker: Account<'info, DepositTrackerAccount>,
+
     #[account(mut)]
     pub depositor: Signer<'info>,
 
     pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
 }
 
 #[derive(Accounts)]
+#[instruction(nullifier: [u8; 32], recipient_key: Pubkey)]
 pub struct Withdraw<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"privacy_pool", privacy_pool.pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
     pub privacy_pool: Account<'info, PrivacyPoolAccount>,
 
+    // Read-only root lookup; no tree hashing happens on withdraw
+    #[account(seeds = [b"merkle_tree", privacy_pool.pool_id.to_le_bytes().as_ref()], bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    // PDA per nullifier, so a second spend fails at `init`
     #[account(
         init,
         payer = withdrawer,
-        space = NullifierAccount::LEN
+        space = NullifierAccount::LEN,
+        seeds = [
+            b"nullifier",
+            privacy_pool.pool_id.to_le_bytes().as_ref(),
+            nullifier.as_ref()
+        ],
+        bump
     )]
     pub nullifier_account: Account<'info, NullifierAccount>,
 
+    /// CHECK: receives the withdrawal; its key is a public input of the proof
+    #[account(mut, address = recipient_key)]
+    pub recipient: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub withdrawer: Signer<'info>,
 
     pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"verifier", [Circuit::Withdraw as u8].as_ref()],
+        bump = verifier.bump
+    )]
+    pub verifier: Box<Account<'info, VerifierAccount>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
+#[instruction(args: PrivateTransferArgs)]
 pub struct PrivateTransfer<'info> {
+    #[account(mut, seeds = [b"shielded_pool"], bump = shielded_pool.bump)]
+    pub shielded_pool: Account<'info, ShieldedPoolAccount>,
+
+    #[account(mut, seeds = [b"shielded_merkle_tree"], bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(seeds = [b"shielded_asset", args.asset_id.as_ref()], bump = asset.bump)]
+    pub asset: Account<'info, ShieldedAssetAccount>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = NullifierAccount::LEN,
+        seeds = [b"shielded_nullifier", args.nullifiers[0].as_ref()],
+        bump
+    )]
+    pub input_nullifier_0: Box<Account<'info, NullifierAccount>>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = NullifierAccount::LEN,
+        seeds = [b"shielded_nullifier", args.nullifiers[1].as_ref()],
+        bump
+    )]
+    pub input_nullifier_1: Box<Account<'info, NullifierAccount>>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = ShieldedCommitmentAccount::LEN,
+        seeds = [b"shielded_commitment", args.output_commitments[0].as_ref()],
+        bump
+    )]
+    pub output_commitment_0: Box<Account<'info, ShieldedCommitmentAccount>>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = ShieldedCommitmentAccount::LEN,
+        seeds = [b"shielded_commitment", args.output_commitments[1].as_ref()],
+        bump
+    )]
+    pub output_commitment_1: Box<Account<'info, ShieldedCommitmentAccount>>,
+
+    // Keyed by the first nullifier, which is unique per transfer
     #[account(
         init,
         payer = sender,
-        space = 8 + 256 + 256 + 256 + 1 + 32 + 8
+        space = PrivateTransferAccount::LEN,
+        seeds = [b"private_transfer", args.nullifiers[0].as_ref()],
+        bump
     )]
-    pub transfer_account: Account<'info, PrivateTransferAccount>,
+    pub transfer_account: Box<Account<'info, PrivateTransferAccount>>,
 
     #[account(mut)]
     pub sender: Signer<'info>,
 
     pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        seeds = [b"verifier", [Circuit::ShieldedTransfer as u8].as_ref()],
+        bump = verifier.bump
+    )]
+    pub verifier: Box<Account<'info, VerifierAccount>>,
 }
 
 #[derive(Accounts)]
@@ -75,7 +276,7 @@ pub struct CrossChainTransfer<'info> {
     #[account(
         init,
         payer = sender,
-        space = 8 + 2 + 2 + 512 + 32 + 12 + 16 + 32 + 8 + 1
+        space = 8 + 1 + 2 + 2 + 512 + 32 + 12 + 16 + 32 + 8 + 1
     )]
     pub bridge_account: Account<'info, CrossChainBridgeAccount>,
 
@@ -84,3 +285,1357 @@ pub struct CrossChainTransfer<'info> {
 
     pub system_program: Program<'info, System>,
 }
+
+#[derive(Accounts)]
+pub struct InitializeGuardianSet<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = GuardianSetAccount::LEN,
+        seeds = [b"guardian_set"],
+        bump
+    )]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = BridgeVaultAccount::LEN,
+        seeds = [b"bridge_vault"],
+        bump
+    )]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    // Only the protocol authority sets the first guardian set; later ones
+    // go through a timelocked `AdminAction::SetGuardianSet`
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(message: BridgeMessage)]
+pub struct CompleteBridgeTransfer<'info> {
+    #[account(seeds = [b"guardian_set"], bump = guardian_set.bump)]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    // Created on first redemption; an existing record means a replay
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ConsumedMessageAccount::LEN,
+        seeds = [b"consumed_message", message.digest().as_ref()],
+        bump
+    )]
+    pub consumed_message: Account<'info, ConsumedMessageAccount>,
+
+    /// CHECK: must match the recipient attested in the message
+    #[account(mut, address = Pubkey::new_from_array(message.recipient))]
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: instructions sysvar, used to inspect Ed25519 precompile calls
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWormhole<'info> {
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = WormholeConfigAccount::LEN,
+        seeds = [b"wormhole_config"],
+        bump
+    )]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    /// CHECK: PDA signing outbound Wormhole messages; holds no data
+    #[account(seeds = [b"emitter"], bump)]
+    pub emitter: UncheckedAccount<'info>,
+
+    // Outbound transfers start without volume caps
+    #[account(
+        init,
+        payer = authority,
+        space = BridgeConfigAccount::LEN,
+        seeds = [b"bridge_config"],
+        bump
+    )]
+    pub bridge_config: Account<'info, BridgeConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeBridgeConfig<'info> {
+    #[account(
+        seeds = [b"wormhole_config"],
+        bump = wormhole_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    // For deployments whose Wormhole config predates bridge limits
+    #[account(
+        init,
+        payer = authority,
+        space = BridgeConfigAccount::LEN,
+        seeds = [b"bridge_config"],
+        bump
+    )]
+    pub bridge_config: Account<'info, BridgeConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(chain: u16)]
+pub struct RegisterForeignEmitter<'info> {
+    #[account(seeds = [b"wormhole_config"], bump = wormhole_config.bump)]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    // First registration only; replacing an emitter is a timelocked
+    // `SetForeignEmitter` action
+    #[account(
+        init,
+        payer = authority,
+        space = ForeignEmitterAccount::LEN,
+        seeds = [b"foreign_emitter", chain.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub foreign_emitter: Account<'info, ForeignEmitterAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PostBridgeMessage<'info> {
+    #[account(seeds = [b"wormhole_config"], bump = wormhole_config.bump)]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    #[account(mut, seeds = [b"bridge_config"], bump = bridge_config.bump)]
+    pub bridge_config: Account<'info, BridgeConfigAccount>,
+
+    /// CHECK: core bridge config, read for the message fee
+    #[account(
+        mut,
+        seeds = [b"Bridge"],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_bridge: UncheckedAccount<'info>,
+
+    /// New message account, initialized by the core bridge
+    #[account(mut)]
+    pub wormhole_message: Signer<'info>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = OutboundTransferAccount::LEN,
+        seeds = [b"outbound_transfer", wormhole_message.key().as_ref()],
+        bump
+    )]
+    pub outbound_transfer: Account<'info, OutboundTransferAccount>,
+
+    /// CHECK: PDA signing outbound Wormhole messages
+    #[account(seeds = [b"emitter"], bump = wormhole_config.emitter_bump)]
+    pub emitter: UncheckedAccount<'info>,
+
+    /// CHECK: emitter sequence tracker, read for the message sequence
+    #[account(
+        mut,
+        seeds = [b"Sequence", emitter.key().as_ref()],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_sequence: UncheckedAccount<'info>,
+
+    /// CHECK: receives the message fee; checked by the core bridge
+    #[account(
+        mut,
+        seeds = [b"fee_collector"],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_fee_collector: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// CHECK: the configured core bridge
+    #[account(address = wormhole_config.core_bridge)]
+    pub wormhole_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct PostGuardianMessage<'info> {
+    #[account(seeds = [b"guardian_set"], bump = guardian_set.bump)]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = GuardianOutboxAccount::LEN,
+        seeds = [b"guardian_outbox"],
+        bump
+    )]
+    pub guardian_outbox: Account<'info, GuardianOutboxAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    #[account(mut, seeds = [b"bridge_config"], bump = bridge_config.bump)]
+    pub bridge_config: Account<'info, BridgeConfigAccount>,
+
+    /// Fresh key naming the transfer, as a Wormhole message account does
+    pub transfer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = OutboundTransferAccount::LEN,
+        seeds = [b"outbound_transfer", transfer.key().as_ref()],
+        bump
+    )]
+    pub outbound_transfer: Account<'info, OutboundTransferAccount>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(origin_chain: u16, origin_asset: [u8; 32], decimals: u8)]
+pub struct RegisterWrappedMint<'info> {
+    #[account(
+        seeds = [b"guardian_set"],
+        bump = guardian_set.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = WrappedMintAccount::LEN,
+        seeds = [b"wrapped_mint", origin_chain.to_le_bytes().as_ref(), origin_asset.as_ref()],
+        bump
+    )]
+    pub wrapped_mint: Account<'info, WrappedMintAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = decimals,
+        mint::authority = wrapped_mint,
+        seeds = [b"wrapped_token", wrapped_mint.key().as_ref()],
+        bump
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(message: WrappedTransferMessage)]
+pub struct MintWrappedTransfer<'info> {
+    #[account(seeds = [b"guardian_set"], bump = guardian_set.bump)]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"wrapped_mint",
+            message.origin_chain.to_le_bytes().as_ref(),
+            message.origin_asset.as_ref()
+        ],
+        bump = wrapped_mint.bump
+    )]
+    pub wrapped_mint: Account<'info, WrappedMintAccount>,
+
+    #[account(mut, address = wrapped_mint.mint)]
+    pub mint: Account<'info, Mint>,
+
+    // Created on first redemption; an existing record means a replay
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ConsumedMessageAccount::LEN,
+        seeds = [b"consumed_message", message.digest().as_ref()],
+        bump
+    )]
+    pub consumed_message: Account<'info, ConsumedMessageAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = Pubkey::new_from_array(message.recipient)
+    )]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+
+    // Paid the message's relayer fee; required when it is not zero
+    #[account(mut, token::mint = mint, token::authority = payer)]
+    pub relayer_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: instructions sysvar, used to inspect Ed25519 precompile calls
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct BurnWrappedTokens<'info> {
+    #[account(mut)]
+    pub wrapped_mint: Account<'info, WrappedMintAccount>,
+
+    #[account(mut, address = wrapped_mint.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut, token::mint = mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = GuardianOutboxAccount::LEN,
+        seeds = [b"guardian_outbox"],
+        bump
+    )]
+    pub guardian_outbox: Account<'info, GuardianOutboxAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RefundBridgeTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"outbound_transfer", outbound_transfer.message.as_ref()],
+        bump = outbound_transfer.bump,
+        has_one = sender @ UntraceError::Unauthorized
+    )]
+    pub outbound_transfer: Account<'info, OutboundTransferAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    #[account(seeds = [b"guardian_set"], bump = guardian_set.bump)]
+    pub guardian_set: Account<'info, GuardianSetAccount>,
+
+    /// CHECK: instructions sysvar, used to inspect Ed25519 precompile calls
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(message: BridgeMessage)]
+pub struct RedeemWormholeTransfer<'info> {
+    #[account(seeds = [b"wormhole_config"], bump = wormhole_config.bump)]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    /// CHECK: VAA verified and posted by the core bridge; decoded and
+    /// matched against `message`
+    #[account(owner = wormhole_config.core_bridge)]
+    pub posted_vaa: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"foreign_emitter", foreign_emitter.chain.to_le_bytes().as_ref()],
+        bump = foreign_emitter.bump
+    )]
+    pub foreign_emitter: Account<'info, ForeignEmitterAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    // Shared with guardian-attested claims, so a message redeems only once
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ConsumedMessageAccount::LEN,
+        seeds = [b"consumed_message", message.digest().as_ref()],
+        bump
+    )]
+    pub consumed_message: Account<'info, ConsumedMessageAccount>,
+
+    /// CHECK: must match the recipient attested in the message
+    #[account(mut, address = Pubkey::new_from_array(message.recipient))]
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLayerZero<'info> {
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = LayerZeroConfigAccount::LEN,
+        seeds = [b"layerzero_config"],
+        bump
+    )]
+    pub layerzero_config: Account<'info, LayerZeroConfigAccount>,
+
+    /// CHECK: LayerZero endpoint the program registers with as an OApp
+    #[account(executable)]
+    pub endpoint_program: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(eid: u32)]
+pub struct SetLayerZeroPeer<'info> {
+    #[account(seeds = [b"layerzero_config"], bump = layerzero_config.bump)]
+    pub layerzero_config: Account<'info, LayerZeroConfigAccount>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    // First peer only; replacing a peer is a timelocked `SetLayerZeroPeer`
+    // action
+    #[account(
+        init,
+        payer = authority,
+        space = LayerZeroPeerAccount::LEN,
+        seeds = [b"layerzero_peer", eid.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub peer: Account<'info, LayerZeroPeerAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PostLayerZeroMessage<'info> {
+    #[account(mut, seeds = [b"layerzero_config"], bump = layerzero_config.bump)]
+    pub layerzero_config: Account<'info, LayerZeroConfigAccount>,
+
+    #[account(seeds = [b"layerzero_peer", peer.eid.to_le_bytes().as_ref()], bump = peer.bump)]
+    pub peer: Account<'info, LayerZeroPeerAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    #[account(mut, seeds = [b"bridge_config"], bump = bridge_config.bump)]
+    pub bridge_config: Account<'info, BridgeConfigAccount>,
+
+    /// Fresh key naming the transfer, as a Wormhole message account does
+    pub transfer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = OutboundTransferAccount::LEN,
+        seeds = [b"outbound_transfer", transfer.key().as_ref()],
+        bump
+    )]
+    pub outbound_transfer: Account<'info, OutboundTransferAccount>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    /// CHECK: the configured endpoint
+    #[account(address = layerzero_config.endpoint)]
+    pub endpoint_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: LzReceiveParams)]
+pub struct LzReceive<'info> {
+    #[account(seeds = [b"layerzero_config"], bump = layerzero_config.bump)]
+    pub layerzero_config: Account<'info, LayerZeroConfigAccount>,
+
+    #[account(
+        seeds = [b"layerzero_peer", params.src_eid.to_le_bytes().as_ref()],
+        bump = peer.bump
+    )]
+    pub peer: Account<'info, LayerZeroPeerAccount>,
+
+    #[account(mut, seeds = [b"bridge_vault"], bump = bridge_vault.bump)]
+    pub bridge_vault: Account<'info, BridgeVaultAccount>,
+
+    // Shared with the Wormhole and guardian paths, so a message redeems
+    // only once whichever backend carried it
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ConsumedMessageAccount::LEN,
+        seeds = [
+            b"consumed_message",
+            crypto::bridge_message_digest(&params.message).as_ref()
+        ],
+        bump
+    )]
+    pub consumed_message: Account<'info, ConsumedMessageAccount>,
+
+    /// CHECK: matched against the recipient in the message by the handler
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: the configured endpoint
+    #[account(address = layerzero_config.endpoint)]
+    pub endpoint_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+// Not gated by the pause, so spends keep reaching the other chains while
+// the protocol is paused
+#[derive(Accounts)]
+pub struct PostNullifierRoot<'info> {
+    #[account(seeds = [b"wormhole_config"], bump = wormhole_config.bump)]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = NullifierSyncAccount::LEN,
+        seeds = [b"nullifier_sync"],
+        bump
+    )]
+    pub nullifier_sync: Account<'info, NullifierSyncAccount>,
+
+    /// CHECK: core bridge config, read for the message fee
+    #[account(
+        mut,
+        seeds = [b"Bridge"],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_bridge: UncheckedAccount<'info>,
+
+    /// New message account, initialized by the core bridge
+    #[account(mut)]
+    pub wormhole_message: Signer<'info>,
+
+    /// CHECK: PDA signing outbound Wormhole messages
+    #[account(seeds = [b"emitter"], bump = wormhole_config.emitter_bump)]
+    pub emitter: UncheckedAccount<'info>,
+
+    /// CHECK: emitter sequence tracker, advanced by the core bridge
+    #[account(
+        mut,
+        seeds = [b"Sequence", emitter.key().as_ref()],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_sequence: UncheckedAccount<'info>,
+
+    /// CHECK: receives the message fee; checked by the core bridge
+    #[account(
+        mut,
+        seeds = [b"fee_collector"],
+        bump,
+        seeds::program = wormhole_config.core_bridge
+    )]
+    pub wormhole_fee_collector: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// CHECK: the configured core bridge
+    #[account(address = wormhole_config.core_bridge)]
+    pub wormhole_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    // Remaining accounts: the shielded nullifier PDA of each nullifier in
+    // the batch, in order
+}
+
+#[derive(Accounts)]
+#[instruction(message: NullifierRootMessage)]
+pub struct ReceiveNullifierRoot<'info> {
+    #[account(seeds = [b"wormhole_config"], bump = wormhole_config.bump)]
+    pub wormhole_config: Account<'info, WormholeConfigAccount>,
+
+    /// CHECK: VAA verified and posted by the core bridge; decoded and
+    /// matched against `message`
+    #[account(owner = wormhole_config.core_bridge)]
+    pub posted_vaa: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"foreign_emitter", foreign_emitter.chain.to_le_bytes().as_ref()],
+        bump = foreign_emitter.bump
+    )]
+    pub foreign_emitter: Account<'info, ForeignEmitterAccount>,
+
+    // One account per batch, so a root is only received once
+    #[account(
+        init,
+        payer = payer,
+        space = NullifierRootAccount::LEN,
+        seeds = [
+            b"nullifier_root",
+            message.source_chain.to_le_bytes().as_ref(),
+            message.batch.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub nullifier_root: Account<'info, NullifierRootAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(nullifier: [u8; 32])]
+pub struct ApplyRemoteNullifier<'info> {
+    #[account(
+        seeds = [
+            b"nullifier_root",
+            nullifier_root.source_chain.to_le_bytes().as_ref(),
+            nullifier_root.batch.to_le_bytes().as_ref()
+        ],
+        bump = nullifier_root.bump
+    )]
+    pub nullifier_root: Account<'info, NullifierRootAccount>,
+
+    // The PDA local spends create, so the note can no longer exit here
+    #[account(
+        init,
+        payer = payer,
+        space = NullifierAccount::LEN,
+        seeds = [b"shielded_nullifier", nullifier.as_ref()],
+        bump
+    )]
+    pub nullifier_account: Account<'info, NullifierAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeShieldedPool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = ShieldedPoolAccount::LEN,
+        seeds = [b"shielded_pool"],
+        bump
+    )]
+    pub shielded_pool: Account<'info, ShieldedPoolAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = MerkleTreeAccount::LEN,
+        seeds = [b"shielded_merkle_tree"],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(asset_id: Pubkey)]
+pub struct RegisterAsset<'info> {
+    #[account(
+        mut,
+        seeds = [b"shielded_pool"],
+        bump = shielded_pool.bump,
+        has_one = authority
+    )]
+    pub shielded_pool: Account<'info, ShieldedPoolAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = ShieldedAssetAccount::LEN,
+        seeds = [b"shielded_asset", asset_id.as_ref()],
+        bump
+    )]
+    pub asset: Account<'info, ShieldedAssetAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(asset_id: Pubkey, amount: u64, commitment: [u8; 32])]
+pub struct ShieldedDeposit<'info> {
+    #[account(mut, seeds = [b"shielded_pool"], bump = shielded_pool.bump)]
+    pub shielded_pool: Account<'info, ShieldedPoolAccount>,
+
+    #[account(mut, seeds = [b"shielded_merkle_tree"], bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(mut, seeds = [b"shielded_asset", asset_id.as_ref()], bump = asset.bump)]
+    pub asset: Account<'info, ShieldedAssetAccount>,
+
+    #[account(
+        init,
+        payer = depositor,
+        space = ShieldedCommitmentAccount::LEN,
+        seeds = [b"shielded_commitment", commitment.as_ref()],
+        bump
+    )]
+    pub commitment_account: Account<'info, ShieldedCommitmentAccount>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    // SPL assets only: source token account and the asset vault
+    #[account(mut, token::mint = asset_id, token::authority = depositor)]
+    pub depositor_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = asset_id, token::authority = asset)]
+    pub asset_vault: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(asset_id: Pubkey, amount: u64, nullifier: [u8; 32])]
+pub struct ShieldedWithdraw<'info> {
+    #[account(mut, seeds = [b"shielded_pool"], bump = shielded_pool.bump)]
+    pub shielded_pool: Account<'info, ShieldedPoolAccount>,
+
+    // Read-only root lookup, as in `Withdraw`
+    #[account(seeds = [b"shielded_merkle_tree"], bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(mut, seeds = [b"shielded_asset", asset_id.as_ref()], bump = asset.bump)]
+    pub asset: Account<'info, ShieldedAssetAccount>,
+
+    #[account(
+        init,
+        payer = withdrawer,
+        space = NullifierAccount::LEN,
+        seeds = [b"shielded_nullifier", nullifier.as_ref()],
+        bump
+    )]
+    pub nullifier_account: Account<'info, NullifierAccount>,
+
+    /// CHECK: receives native SOL withdrawals, and owns the token account
+    /// of SPL ones; its key is a public input of the withdrawal proof
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub withdrawer: Signer<'info>,
+
+    // SPL assets only: the asset vault and the recipient's token account
+    #[account(mut, token::mint = asset_id, token::authority = asset)]
+    pub asset_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = asset_id, token::authority = recipient)]
+    pub recipient_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"verifier", [Circuit::ShieldedWithdraw as u8].as_ref()],
+        bump = verifier.bump
+    )]
+    pub verifier: Box<Account<'info, VerifierAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    /// CHECK: decoded manually since the stored layout may predate versioning
+    #[account(mut, owner = crate::ID)]
+    pub target: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: u64)]
+pub struct InitializeCompressedPool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = CompressedPoolAccount::LEN,
+        seeds = [b"compressed_pool", pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub compressed_pool: Account<'info, CompressedPoolAccount>,
+
+    /// CHECK: pre-allocated by the client and owned by the compression
+    /// program, which validates its size when initializing the tree
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub compression_program: Program<'info, SplAccountCompression>,
+    pub log_wrapper: Program<'info, Noop>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CompressedDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"compressed_pool", compressed_pool.pool_id.to_le_bytes().as_ref()],
+        bump = compressed_pool.bump
+    )]
+    pub compressed_pool: Account<'info, CompressedPoolAccount>,
+
+    /// CHECK: bound to the pool and validated by the compression program
+    #[account(mut, address = compressed_pool.merkle_tree)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    pub compression_program: Program<'info, SplAccountCompression>,
+    pub log_wrapper: Program<'info, Noop>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        constraint = !admin_config.paused @ UntraceError::ProtocolPaused
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(nullifier: [u8; 32], recipient_key: Pubkey)]
+pub struct CompressedWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"compressed_pool", compressed_pool.pool_id.to_le_bytes().as_ref()],
+        bump = compressed_pool.bump
+    )]
+    pub compressed_pool: Account<'info, CompressedPoolAccount>,
+
+    /// CHECK: bound to the pool; the header is checked in `is_recent_root`
+    #[account(address = compressed_pool.merkle_tree, owner = SplAccountCompression::id())]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = withdrawer,
+        space = NullifierAccount::LEN,
+        seeds = [
+            b"compressed_nullifier",
+            compressed_pool.pool_id.to_le_bytes().as_ref(),
+            nullifier.as_ref()
+        ],
+        bump
+    )]
+    pub nullifier_account: Account<'info, NullifierAccount>,
+
+    /// CHECK: receives the withdrawal; its key is a public input of the proof
+    #[account(mut, address = recipient_key)]
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub withdrawer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"verifier", [Circuit::CompressedWithdraw as u8].as_ref()],
+        bump = verifier.bump
+    )]
+    pub verifier: Box<Account<'info, VerifierAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAdmin<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = AdminConfigAccount::LEN,
+        seeds = [b"admin_config"],
+        bump
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(mut, seeds = [b"admin_config"], bump = admin_config.bump)]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(action_id: u64)]
+pub struct QueueAdminAction<'info> {
+    #[account(seeds = [b"admin_config"], bump = admin_config.bump)]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = TimelockAccount::LEN,
+        seeds = [b"timelock", action_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock: Account<'info, TimelockAccount>,
+
+    // Pool changes only: the pool whose authority is proposing
+    pub privacy_pool: Option<Account<'info, PrivacyPoolAccount>>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAdminAction<'info> {
+    #[account(mut, seeds = [b"admin_config"], bump = admin_config.bump)]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"timelock", timelock.action_id.to_le_bytes().as_ref()],
+        bump = timelock.bump,
+        has_one = proposer,
+        close = proposer
+    )]
+    pub timelock: Account<'info, TimelockAccount>,
+
+    // Pool changes only: the config being updated
+    #[account(mut)]
+    pub pool_config: Option<Account<'info, PoolConfigAccount>>,
+
+    // Bridge limit changes only
+    #[account(mut, seeds = [b"bridge_config"], bump = bridge_config.bump)]
+    pub bridge_config: Option<Account<'info, BridgeConfigAccount>>,
+
+    // Guardian set rotations only
+    #[account(mut, seeds = [b"guardian_set"], bump = guardian_set.bump)]
+    pub guardian_set: Option<Account<'info, GuardianSetAccount>>,
+
+    // Emitter replacements only: the registration being replaced
+    #[account(mut)]
+    pub foreign_emitter: Option<Account<'info, ForeignEmitterAccount>>,
+
+    // LayerZero peer replacements only: the peer being replaced
+    #[account(mut)]
+    pub layerzero_peer: Option<Account<'info, LayerZeroPeerAccount>>,
+
+    // Verifying key replacements only: the verifier being updated
+    #[account(
+        mut,
+        seeds = [b"verifier", [verifier.circuit as u8].as_ref()],
+        bump = verifier.bump
+    )]
+    pub verifier: Option<Box<Account<'info, VerifierAccount>>>,
+
+    /// CHECK: receives the timelock rent back; bound by `has_one`
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(circuit: Circuit)]
+pub struct InitializeVerifier<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = VerifierAccount::LEN,
+        seeds = [b"verifier", [circuit as u8].as_ref()],
+        bump
+    )]
+    pub verifier: Account<'info, VerifierAccount>,
+
+    // Only the protocol authority sets the first key; later ones go
+    // through a timelocked `AdminAction::SetVerifyingKey`
+    #[account(
+        seeds = [b"admin_config"],
+        bump = admin_config.bump,
+        has_one = authority @ UntraceError::Unauthorized
+    )]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAdminAction<'info> {
+    #[account(seeds = [b"admin_config"], bump = admin_config.bump)]
+    pub admin_config: Account<'info, AdminConfigAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"timelock", timelock.action_id.to_le_bytes().as_ref()],
+        bump = timelock.bump,
+        has_one = proposer,
+        close = proposer
+    )]
+    pub timelock: Account<'info, TimelockAccount>,
+
+    /// CHECK: receives the timelock rent back; bound by `has_one`
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+
+    /// Proposer or guardian
+    pub canceller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenSwapGuard<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = SwapGuardAccount::LEN,
+        seeds = [b"swap_guard", owner.key().as_ref(), token_account.key().as_ref()],
+        bump
+    )]
+    pub swap_guard: Account<'info, SwapGuardAccount>,
+
+    /// Account the swap pays its output into
+    #[account(token::authority = owner)]
+    pub token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSwapGuard<'info> {
+    #[account(
+        mut,
+        seeds = [b"swap_guard", owner.key().as_ref(), token_account.key().as_ref()],
+        bump = swap_guard.bump,
+        has_one = owner,
+        has_one = token_account,
+        close = owner
+    )]
+    pub swap_guard: Account<'info, SwapGuardAccount>,
+
+    pub token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct CommitOrder<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = OrderCommitmentAccount::LEN,
+        seeds = [b"order_commitment", owner.key().as_ref(), order_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order_commitment: Account<'info, OrderCommitmentAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order_commitment",
+            owner.key().as_ref(),
+            order_commitment.order_id.to_le_bytes().as_ref()
+        ],
+        bump = order_commitment.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub order_commitment: Account<'info, OrderCommitmentAccount>,
+
+    /// CHECK: receives the commitment's rent; matched by `has_one`
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseOrderCommitment<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order_commitment",
+            owner.key().as_ref(),
+            order_commitment.order_id.to_le_bytes().as_ref()
+        ],
+        bump = order_commitment.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub order_commitment: Account<'info, OrderCommitmentAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(intent_id: u64)]
+pub struct LockIntent<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = TimeLockedIntentAccount::LEN,
+        seeds = [b"intent", owner.key().as_ref(), intent_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub intent: Account<'info, TimeLockedIntentAccount>,
+
+    #[account(seeds = [b"intent_vault", intent.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteIntent<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"intent",
+            owner.key().as_ref(),
+            intent.intent_id.to_le_bytes().as_ref()
+        ],
+        bump = intent.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub intent: Account<'info, TimeLockedIntentAccount>,
+
+    // Lamports the instruction leaves in the vault go back to the owner
+    #[account(
+        mut,
+        seeds = [b"intent_vault", intent.key().as_ref()],
+        bump = intent.vault_bump
+    )]
+    pub vault: SystemAccount<'info>,
+
+    /// CHECK: receives the intent's rent; matched by `has_one`
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelIntent<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"intent",
+            owner.key().as_ref(),
+            intent.intent_id.to_le_bytes().as_ref()
+        ],
+        bump = intent.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub intent: Account<'info, TimeLockedIntentAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_vault", intent.key().as_ref()],
+        bump = intent.vault_bump
+    )]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
@@ -1,14 +1,29 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+};
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::token;
+use spl_account_compression::cpi as compression_cpi;
 use untrace_common::{
-    crypto, Commitment, EncryptedTransaction, PrivacyLevel, PrivacyPool, PrivateTransfer,
-    UntraceError,
+    crypto,
+    groth16::{self, Circuit, VerifyingKey},
+    layerzero, wormhole, AdminAction, BridgeMessage, BridgeProtocol, BridgeRefundMessage,
+    Commitment, EncryptedTransaction, NullifierRootMessage, PrivacyLevel, PrivacyPool,
+    PrivateTransfer, UntraceError, WrappedTransferMessage, NATIVE_ASSET_ID, SOLANA_CHAIN_ID,
 };
 
 declare_id!("UnTrAcE1111111111111111111111111111111111111");
 
+pub mod compression;
+pub mod events;
+pub mod guardian;
 pub mod instructions;
 pub mod state;
 
+use events::*;
 use instructions::*;
 use state::*;
 
@@ -23,58 +38,149 @@ pub mod untrace_privacy_program {
         min_pool_size: u64,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.privacy_pool;
+        pool.version = ACCOUNT_VERSION;
         pool.pool_id = pool_id;
         pool.commitment_root = [0u8; 32];
         pool.commitment_count = 0;
         pool.min_pool_size = min_pool_size;
         pool.authority = ctx.accounts.authority.key();
 
+        let mut tree = ctx.accounts.merkle_tree.load_init()?;
+        tree.initialize(pool_id, ctx.bumps.merkle_tree);
+        pool.commitment_root = tree.roots[0];
+
+        // Pools start without deposit limits
+        let config = &mut ctx.accounts.pool_config;
+        config.version = ACCOUNT_VERSION;
+        config.pool_id = pool_id;
+        config.bump = ctx.bumps.pool_config;
+
         msg!("Privacy pool {} initialized", pool_id);
         Ok(())
     }
 
+    /// Create an unrestricted config for a pool that predates configs.
+    /// Limits are then changed through a timelocked `ConfigurePool` action.
+    pub fn initialize_pool_config(ctx: Context<InitializePoolConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.pool_config;
+        config.version = ACCOUNT_VERSION;
+        config.pool_id = ctx.accounts.privacy_pool.pool_id;
+        config.bump = ctx.bumps.pool_config;
+
+        msg!("Pool {} config initialized", config.pool_id);
+        Ok(())
+    }
+
+    /// Create the commitment tree of a pool that predates trees, such as
+    /// a migrated legacy pool. Earlier commitments were never in a tree a
+    /// withdrawal could be proven against, so the tree starts empty.
+    pub fn initialize_merkle_tree(ctx: Context<InitializeMerkleTree>) -> Result<()> {
+        let pool = &mut ctx.accounts.privacy_pool;
+        let mut tree = ctx.accounts.merkle_tree.load_init()?;
+        tree.initialize(pool.pool_id, ctx.bumps.merkle_tree);
+        pool.commitment_root = tree.roots[0];
+
+        msg!("Pool {} merkle tree initialized", pool.pool_id);
+        Ok(())
+    }
+
     /// Deposit funds into privacy pool (create commitment)
     pub fn deposit(
         ctx: Context<Deposit>,
         commitment: [u8; 32],
+        amount: u64,
+        note_key: [u8; 32],
         encrypted_data: Vec<u8>,
-    ) -> Result<()> {
-        let pool = &mut ctx.accounts.privacy_pool;
-        let commitment_account = &mut ctx.accounts.commitment_account;
+    ) -> Result<u64> {
+        // The commitment account is a PDA over (pool_id, commitment), so `init`
+        // already rejects duplicates; this ties the note to what is deposited,
+        // so it cannot later be withdrawn for more.
+        require!(
+            commitment == crypto::generate_commitment(amount, &note_key),
+            UntraceError::CommitmentMismatch
+        );
+
+        // Enforce the pool's deposit bounds
+        let config = &ctx.accounts.pool_config;
+        require!(
+            amount > 0 && amount >= config.min_deposit,
+            UntraceError::DepositBelowMinimum
+        );
+        require!(
+            config.max_deposit == 0 || amount <= config.max_deposit,
+            UntraceError::DepositAboveMaximum
+        );
 
-        // Verify commitment doesn't already exist
+        // Rate-limit per address; the tracker resets when the epoch rolls over
+        let epoch = Clock::get()?.epoch;
+        let tracker = &mut ctx.accounts.deposit_tracker;
+        if tracker.version == 0 || tracker.epoch != epoch {
+            tracker.version = ACCOUNT_VERSION;
+            tracker.depositor = ctx.accounts.depositor.key();
+            tracker.epoch = epoch;
+            tracker.deposited = 0;
+            tracker.bump = ctx.bumps.deposit_tracker;
+        }
+        let deposited = tracker
+            .deposited
+            .checked_add(amount)
+            .ok_or(UntraceError::EpochDepositCapExceeded)?;
         require!(
-            commitment_account.commitment == [0u8; 32],
-            UntraceError::CommitmentExists
+            config.epoch_deposit_cap == 0 || deposited <= config.epoch_deposit_cap,
+            UntraceError::EpochDepositCapExceeded
         );
+        tracker.deposited = deposited;
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.privacy_pool.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let pool = &mut ctx.accounts.privacy_pool;
+        let commitment_account = &mut ctx.accounts.commitment_account;
 
         // Store commitment
+        commitment_account.version = ACCOUNT_VERSION;
         commitment_account.commitment = commitment;
         commitment_account.nullifier = [0u8; 32]; // Not yet spent
         commitment_account.timestamp = Clock::get()?.unix_timestamp;
         commitment_account.pool_id = pool.pool_id;
+        commitment_account.bump = ctx.bumps.commitment_account;
+
+        // Append to the pool's commitment tree
+        let (leaf_index, new_root) = ctx.accounts.merkle_tree.load_mut()?.insert(commitment)?;
 
         // Update pool state
         pool.commitment_count += 1;
-
-        // Update merkle root (simplified - in production use proper merkle tree)
-        let mut new_root = pool.commitment_root;
-        for i in 0..32 {
-            new_root[i] ^= commitment[i];
-        }
         pool.commitment_root = new_root;
 
-        msg!("Deposit committed to pool {}", pool.pool_id);
-        Ok(())
+        msg!("Deposit committed to pool {} at leaf {}", pool.pool_id, leaf_index);
+        emit_cpi!(CommitmentInserted {
+            pool: pool.key(),
+            leaf_index,
+            commitment,
+            encrypted_note: encrypted_data,
+        });
+
+        // Returned so the depositor can store the leaf with its note
+        Ok(leaf_index)
     }
 
-    /// Withdraw funds from privacy pool (nullify commitment)
+    /// Withdraw funds from privacy pool (nullify commitment) and pay
+    /// `amount` lamports to `recipient`
     pub fn withdraw(
         ctx: Context<Withdraw>,
         nullifier: [u8; 32],
         recipient: Pubkey,
+        amount: u64,
         zk_proof: Vec<u8>,
-        merkle_proof: Vec<[u8; 32]>,
+        root: [u8; 32],
     ) -> Result<()> {
         let pool = &ctx.accounts.privacy_pool;
         let nullifier_account = &mut ctx.accounts.nullifier_account;
@@ -85,19 +191,36 @@ pub mod untrace_privacy_program {
             UntraceError::InsufficientPoolSize
         );
 
-        // Verify nullifier not already used
+        // Membership is proven off-chain against `root`; on-chain we only
+        // check the root is recent instead of re-hashing a merkle path
         require!(
-            nullifier_account.is_used == false,
-            UntraceError::NullifierUsed
+            ctx.accounts.merkle_tree.load()?.is_known_root(&root),
+            UntraceError::UnknownMerkleRoot
         );
 
-        // Verify ZK proof (simplified)
+        // The Groth16 proof's public input hashes the root, amount,
+        // recipient and nullifier, so none of them can be swapped for
+        // another after it is made
+        let public_inputs =
+            crypto::withdraw_inputs(&root, amount, &recipient.to_bytes(), &nullifier);
         require!(
-            crypto::verify_zk_proof(&zk_proof, &[0u8; 32], &nullifier),
+            groth16::verify(&ctx.accounts.verifier.key, &zk_proof, &public_inputs),
             UntraceError::InvalidZKProof
         );
 
+        // Paid from the pool's lamports above its rent-exempt minimum
+        let pool_info = ctx.accounts.privacy_pool.to_account_info();
+        let rent_floor = Rent::get()?.minimum_balance(pool_info.data_len());
+        require!(
+            pool_info.lamports().saturating_sub(rent_floor) >= amount,
+            UntraceError::InsufficientPoolBalance
+        );
+        let recipient_info = ctx.accounts.recipient.to_account_info();
+        **pool_info.try_borrow_mut_lamports()? -= amount;
+        **recipient_info.try_borrow_mut_lamports()? += amount;
+
         // Mark nullifier as used
+        nullifier_account.version = ACCOUNT_VERSION;
         nullifier_account.nullifier = nullifier;
         nullifier_account.is_used = true;
         nullifier_account.timestamp = Clock::get()?.unix_timestamp;
@@ -107,14 +230,24 @@ pub mod untrace_privacy_program {
     }
 
     /// Execute private transfer
+    /// Move value between shielded notes: spend the input notes, create the
+    /// output notes, and record the encrypted details for the recipient.
+    /// Balances never leave the shielded pool.
     pub fn private_transfer(
         ctx: Context<PrivateTransfer>,
-        encrypted_amount: Vec<u8>,
-        encrypted_recipient: Vec<u8>,
-        zk_proof: Vec<u8>,
-        privacy_level: u8,
+        args: PrivateTransferArgs,
     ) -> Result<()> {
-        let transfer_account = &mut ctx.accounts.transfer_account;
+        let PrivateTransferArgs {
+            asset_id,
+            root,
+            nullifiers,
+            output_commitments,
+            encrypted_amount,
+            encrypted_recipient,
+            zk_proof,
+            privacy_level,
+            encrypted_notes,
+        } = args;
 
         // Convert privacy level
         let level = match privacy_level {
@@ -124,21 +257,113 @@ pub mod untrace_privacy_program {
             _ => return Err(UntraceError::InvalidPrivacyLevel.into()),
         };
 
-        // Verify ZK proof
         require!(
-            zk_proof.len() >= 32,
+            output_commitments.iter().all(|c| *c != [0u8; 32]),
+            UntraceError::InvalidInstruction
+        );
+        // One note ciphertext per output, for its owner to find by scanning
+        require!(
+            encrypted_notes.len() == SHIELDED_TRANSFER_NOTES,
+            UntraceError::InvalidInstruction
+        );
+        // Stored in the transfer account, which has room for this much
+        require!(
+            [&encrypted_amount, &encrypted_recipient, &zk_proof]
+                .iter()
+                .all(|field| field.len() <= MAX_PRIVATE_TRANSFER_FIELD_LEN),
+            UntraceError::InvalidInstruction
+        );
+
+        let pool = &ctx.accounts.shielded_pool;
+        require!(
+            pool.commitment_count >= pool.min_pool_size,
+            UntraceError::InsufficientPoolSize
+        );
+        require!(
+            ctx.accounts.merkle_tree.load()?.is_known_root(&root),
+            UntraceError::UnknownMerkleRoot
+        );
+
+        // The Groth16 proof shows the inputs are unspent notes of
+        // `asset_id` in the pool under `root` and that input and output
+        // values balance; it is checked before any nullifier is spent
+        let public_inputs = crypto::shielded_transfer_inputs(
+            &root,
+            &asset_id.to_bytes(),
+            &nullifiers,
+            &output_commitments,
+        );
+        require!(
+            groth16::verify(&ctx.accounts.verifier.key, &zk_proof, &public_inputs),
             UntraceError::InvalidZKProof
         );
 
+        // Spend inputs; each nullifier PDA can only be created once
+        let timestamp = Clock::get()?.unix_timestamp;
+        for (account, nullifier) in [
+            &mut ctx.accounts.input_nullifier_0,
+            &mut ctx.accounts.input_nullifier_1,
+        ]
+        .into_iter()
+        .zip(nullifiers)
+        {
+            account.version = ACCOUNT_VERSION;
+            account.nullifier = nullifier;
+            account.is_used = true;
+            account.timestamp = timestamp;
+        }
+
+        // Create outputs
+        let pool_key = ctx.accounts.shielded_pool.key();
+        let bumps = [ctx.bumps.output_commitment_0, ctx.bumps.output_commitment_1];
+        let mut leaf_indices = [0u64; SHIELDED_TRANSFER_NOTES];
+        let mut tree = ctx.accounts.merkle_tree.load_mut()?;
+        for (((account, commitment), bump), leaf_index) in [
+            &mut ctx.accounts.output_commitment_0,
+            &mut ctx.accounts.output_commitment_1,
+        ]
+        .into_iter()
+        .zip(output_commitments)
+        .zip(bumps)
+        .zip(leaf_indices.iter_mut())
+        {
+            *leaf_index = ctx
+                .accounts
+                .shielded_pool
+                .append_commitment(&mut tree, commitment)?;
+            account.version = ACCOUNT_VERSION;
+            account.commitment = commitment;
+            account.leaf_index = *leaf_index;
+            account.timestamp = timestamp;
+            account.bump = bump;
+        }
+
         // Store encrypted transfer
+        let transfer_account = &mut ctx.accounts.transfer_account;
+        transfer_account.version = ACCOUNT_VERSION;
         transfer_account.encrypted_amount = encrypted_amount;
         transfer_account.encrypted_recipient = encrypted_recipient;
         transfer_account.zk_proof = zk_proof;
         transfer_account.privacy_level = level;
         transfer_account.sender = ctx.accounts.sender.key();
-        transfer_account.timestamp = Clock::get()?.unix_timestamp;
+        transfer_account.timestamp = timestamp;
+        transfer_account.asset_id = asset_id;
+        transfer_account.nullifiers = nullifiers;
+        transfer_account.output_commitments = output_commitments;
 
         msg!("Private transfer executed with {:?} privacy", level);
+        for ((leaf_index, commitment), encrypted_note) in leaf_indices
+            .into_iter()
+            .zip(output_commitments)
+            .zip(encrypted_notes)
+        {
+            emit_cpi!(CommitmentInserted {
+                pool: pool_key,
+                leaf_index,
+                commitment,
+                encrypted_note,
+            });
+        }
         Ok(())
     }
 
@@ -154,6 +379,7 @@ pub mod untrace_privacy_program {
     ) -> Result<()> {
         let bridge_account = &mut ctx.accounts.bridge_account;
 
+        bridge_account.version = ACCOUNT_VERSION;
         bridge_account.source_chain = source_chain;
         bridge_account.dest_chain = dest_chain;
         bridge_account.encrypted_data = encrypted_data;
@@ -171,4 +397,1989 @@ pub mod untrace_privacy_program {
         );
         Ok(())
     }
+
+    /// Initialize the bridge guardian set and its vault
+    pub fn initialize_guardian_set(
+        ctx: Context<InitializeGuardianSet>,
+        guardians: Vec<Pubkey>,
+        quorum: u8,
+    ) -> Result<()> {
+        guardian::check_guardian_set(&guardians, quorum)?;
+
+        let guardian_set = &mut ctx.accounts.guardian_set;
+        guardian_set.version = ACCOUNT_VERSION;
+        guardian_set.authority = ctx.accounts.authority.key();
+        guardian_set.guardians = guardians;
+        guardian_set.quorum = quorum;
+        guardian_set.bump = ctx.bumps.guardian_set;
+
+        let bridge_vault = &mut ctx.accounts.bridge_vault;
+        bridge_vault.version = ACCOUNT_VERSION;
+        bridge_vault.bump = ctx.bumps.bridge_vault;
+
+        msg!(
+            "Guardian set initialized: {} of {}",
+            quorum,
+            guardian_set.guardians.len()
+        );
+        Ok(())
+    }
+
+    /// Register the verifying key proofs of `circuit` are checked against
+    pub fn initialize_verifier(
+        ctx: Context<InitializeVerifier>,
+        circuit: Circuit,
+        key: VerifyingKey,
+    ) -> Result<()> {
+        let verifier = &mut ctx.accounts.verifier;
+        verifier.version = ACCOUNT_VERSION;
+        verifier.circuit = circuit;
+        verifier.key = key;
+        verifier.bump = ctx.bumps.verifier;
+
+        msg!("Verifier for {:?} initialized", circuit);
+        Ok(())
+    }
+
+    /// Complete an inbound bridge transfer attested by the guardian set
+    pub fn complete_bridge_transfer(
+        ctx: Context<CompleteBridgeTransfer>,
+        message: BridgeMessage,
+    ) -> Result<()> {
+        let guardian_set = &ctx.accounts.guardian_set;
+
+        require!(
+            message.dest_chain == SOLANA_CHAIN_ID,
+            UntraceError::InvalidInstruction
+        );
+
+        let digest = message.digest();
+
+        // Each attested message can only be redeemed once
+        require!(
+            ctx.accounts.consumed_message.consumed_at == 0,
+            UntraceError::MessageAlreadyConsumed
+        );
+
+        // Guardians sign the message digest via Ed25519 precompile instructions
+        let signatures = guardian::count_guardian_signatures(
+            &ctx.accounts.instructions.to_account_info(),
+            &guardian_set.guardians,
+            &digest,
+        )?;
+        require!(
+            signatures >= guardian_set.quorum as usize,
+            UntraceError::GuardianQuorumNotReached
+        );
+
+        release_bridge_transfer(
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.recipient.to_account_info(),
+            &mut ctx.accounts.consumed_message,
+            ctx.bumps.consumed_message,
+            &message,
+        )?;
+
+        msg!(
+            "Bridge transfer {} from chain {} completed",
+            message.sequence,
+            message.source_chain
+        );
+        Ok(())
+    }
+
+    /// Configure the Wormhole core bridge carrying bridge messages
+    pub fn initialize_wormhole(
+        ctx: Context<InitializeWormhole>,
+        core_bridge: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.wormhole_config;
+        config.version = ACCOUNT_VERSION;
+        config.authority = ctx.accounts.authority.key();
+        config.core_bridge = core_bridge;
+        config.bump = ctx.bumps.wormhole_config;
+        config.emitter_bump = ctx.bumps.emitter;
+
+        let bridge_config = &mut ctx.accounts.bridge_config;
+        bridge_config.version = ACCOUNT_VERSION;
+        bridge_config.bump = ctx.bumps.bridge_config;
+
+        msg!("Wormhole core bridge set to {}", core_bridge);
+        Ok(())
+    }
+
+    /// Create an uncapped bridge config for a deployment that predates
+    /// bridge limits. Caps are then set through timelocked
+    /// `SetBridgeLimit` actions.
+    pub fn initialize_bridge_config(ctx: Context<InitializeBridgeConfig>) -> Result<()> {
+        let bridge_config = &mut ctx.accounts.bridge_config;
+        bridge_config.version = ACCOUNT_VERSION;
+        bridge_config.bump = ctx.bumps.bridge_config;
+
+        msg!("Bridge config initialized");
+        Ok(())
+    }
+
+    /// Trust `address` as the bridge's emitter on Wormhole chain `chain`.
+    /// Replacing it later goes through the timelock.
+    pub fn register_foreign_emitter(
+        ctx: Context<RegisterForeignEmitter>,
+        chain: u16,
+        address: [u8; 32],
+    ) -> Result<()> {
+        require!(
+            is_foreign_wormhole_chain(chain),
+            UntraceError::InvalidInstruction
+        );
+
+        let emitter = &mut ctx.accounts.foreign_emitter;
+        emitter.version = ACCOUNT_VERSION;
+        emitter.chain = chain;
+        emitter.address = address;
+        emitter.bump = ctx.bumps.foreign_emitter;
+
+        msg!("Foreign emitter registered for Wormhole chain {}", chain);
+        Ok(())
+    }
+
+    /// Lock `amount` lamports in the bridge vault and post the outbound
+    /// transfer as a Wormhole message
+    pub fn post_bridge_message(
+        ctx: Context<PostBridgeMessage>,
+        dest_chain: u16,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(
+            dest_chain != SOLANA_CHAIN_ID && wormhole::to_wormhole_chain_id(dest_chain).is_some(),
+            UntraceError::InvalidInstruction
+        );
+        lock_outbound_amount(
+            &mut ctx.accounts.bridge_config,
+            &ctx.accounts.sender.to_account_info(),
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            dest_chain,
+            amount,
+        )?;
+
+        let accounts = &ctx.accounts;
+        let sequence = emitter_sequence(&accounts.wormhole_sequence)?;
+        let message = BridgeMessage {
+            source_chain: SOLANA_CHAIN_ID,
+            dest_chain,
+            sequence,
+            recipient,
+            amount,
+            payload_hash,
+        };
+        let payload = message
+            .try_to_vec()
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+
+        post_wormhole_message(
+            accounts.wormhole_config.emitter_bump,
+            [
+                accounts.wormhole_bridge.to_account_info(),
+                accounts.wormhole_message.to_account_info(),
+                accounts.emitter.to_account_info(),
+                accounts.wormhole_sequence.to_account_info(),
+                accounts.sender.to_account_info(),
+                accounts.wormhole_fee_collector.to_account_info(),
+                accounts.clock.to_account_info(),
+                accounts.rent.to_account_info(),
+                accounts.system_program.to_account_info(),
+                accounts.wormhole_program.to_account_info(),
+            ],
+            &payload,
+        )?;
+
+        let outbound_transfer = &mut ctx.accounts.outbound_transfer;
+        outbound_transfer.version = ACCOUNT_VERSION;
+        outbound_transfer.sender = ctx.accounts.sender.key();
+        outbound_transfer.message = ctx.accounts.wormhole_message.key();
+        outbound_transfer.dest_chain = dest_chain;
+        outbound_transfer.sequence = sequence;
+        outbound_transfer.amount = amount;
+        outbound_transfer.posted_at = Clock::get()?.unix_timestamp;
+        outbound_transfer.refunded_at = 0;
+        outbound_transfer.protocol = BridgeProtocol::Wormhole;
+        outbound_transfer.bump = ctx.bumps.outbound_transfer;
+
+        msg!("Bridge message {} posted to chain {}", sequence, dest_chain);
+        Ok(())
+    }
+
+    /// Lock `amount` lamports in the bridge vault and leave the outbound
+    /// transfer for the guardian set, which picks up the emitted message and
+    /// signs it for the destination
+    pub fn post_guardian_message(
+        ctx: Context<PostGuardianMessage>,
+        dest_chain: u16,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(dest_chain != SOLANA_CHAIN_ID, UntraceError::InvalidInstruction);
+        require!(amount > 0, UntraceError::InvalidInstruction);
+
+        lock_outbound_amount(
+            &mut ctx.accounts.bridge_config,
+            &ctx.accounts.sender.to_account_info(),
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            dest_chain,
+            amount,
+        )?;
+
+        let outbox = &mut ctx.accounts.guardian_outbox;
+        let sequence = outbox.next_sequence;
+        outbox.version = ACCOUNT_VERSION;
+        outbox.next_sequence = sequence
+            .checked_add(1)
+            .ok_or(UntraceError::InvalidInstruction)?;
+        outbox.bump = ctx.bumps.guardian_outbox;
+
+        let outbound_transfer = &mut ctx.accounts.outbound_transfer;
+        outbound_transfer.version = ACCOUNT_VERSION;
+        outbound_transfer.sender = ctx.accounts.sender.key();
+        outbound_transfer.message = ctx.accounts.transfer.key();
+        outbound_transfer.dest_chain = dest_chain;
+        outbound_transfer.sequence = sequence;
+        outbound_transfer.amount = amount;
+        outbound_transfer.posted_at = Clock::get()?.unix_timestamp;
+        outbound_transfer.refunded_at = 0;
+        outbound_transfer.protocol = BridgeProtocol::Guardian;
+        outbound_transfer.bump = ctx.bumps.outbound_transfer;
+
+        msg!("Guardian message {} posted to chain {}", sequence, dest_chain);
+        emit_cpi!(GuardianMessagePosted {
+            transfer: ctx.accounts.transfer.key(),
+            message: BridgeMessage {
+                source_chain: SOLANA_CHAIN_ID,
+                dest_chain,
+                sequence,
+                recipient,
+                amount,
+                payload_hash,
+            },
+        });
+        Ok(())
+    }
+
+    /// Create the wrapped mint representing `origin_asset` of `origin_chain`
+    /// and record it in the registry
+    pub fn register_wrapped_mint(
+        ctx: Context<RegisterWrappedMint>,
+        origin_chain: u16,
+        origin_asset: [u8; 32],
+        decimals: u8,
+    ) -> Result<()> {
+        require!(origin_chain != SOLANA_CHAIN_ID, UntraceError::InvalidAsset);
+
+        let wrapped_mint = &mut ctx.accounts.wrapped_mint;
+        wrapped_mint.version = ACCOUNT_VERSION;
+        wrapped_mint.origin_chain = origin_chain;
+        wrapped_mint.origin_asset = origin_asset;
+        wrapped_mint.mint = ctx.accounts.mint.key();
+        wrapped_mint.decimals = decimals;
+        wrapped_mint.outstanding = 0;
+        wrapped_mint.bump = ctx.bumps.wrapped_mint;
+
+        msg!(
+            "Wrapped mint {} registered for chain {}",
+            wrapped_mint.mint,
+            origin_chain
+        );
+        Ok(())
+    }
+
+    /// Mint the wrapped tokens of an inbound transfer attested by the
+    /// guardian set to its recipient
+    pub fn mint_wrapped_transfer(
+        ctx: Context<MintWrappedTransfer>,
+        message: WrappedTransferMessage,
+    ) -> Result<()> {
+        require!(
+            message.dest_chain == SOLANA_CHAIN_ID,
+            UntraceError::InvalidInstruction
+        );
+        require!(
+            ctx.accounts.consumed_message.consumed_at == 0,
+            UntraceError::MessageAlreadyConsumed
+        );
+        require!(
+            message.relayer_fee <= message.amount,
+            UntraceError::RelayerFeeTooHigh
+        );
+
+        let digest = message.digest();
+        let signatures = guardian::count_guardian_signatures(
+            &ctx.accounts.instructions.to_account_info(),
+            &ctx.accounts.guardian_set.guardians,
+            &digest,
+        )?;
+        require!(
+            signatures >= ctx.accounts.guardian_set.quorum as usize,
+            UntraceError::GuardianQuorumNotReached
+        );
+
+        let wrapped_mint = &ctx.accounts.wrapped_mint;
+        let origin_chain = wrapped_mint.origin_chain.to_le_bytes();
+        let bump = [wrapped_mint.bump];
+        let seeds: &[&[u8]] = &[
+            b"wrapped_mint",
+            origin_chain.as_ref(),
+            wrapped_mint.origin_asset.as_ref(),
+            &bump,
+        ];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.recipient_token_account.to_account_info(),
+                    authority: ctx.accounts.wrapped_mint.to_account_info(),
+                },
+                &[seeds],
+            ),
+            message.amount - message.relayer_fee,
+        )?;
+
+        // The relayer fronted the claim, so it takes its fee from the amount
+        if message.relayer_fee > 0 {
+            let relayer_token_account = ctx
+                .accounts
+                .relayer_token_account
+                .as_ref()
+                .ok_or(UntraceError::InvalidInstruction)?;
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::MintTo {
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: relayer_token_account.to_account_info(),
+                        authority: ctx.accounts.wrapped_mint.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                message.relayer_fee,
+            )?;
+        }
+
+        let wrapped_mint = &mut ctx.accounts.wrapped_mint;
+        wrapped_mint.outstanding = wrapped_mint
+            .outstanding
+            .checked_add(message.amount)
+            .ok_or(UntraceError::InvalidInstruction)?;
+
+        let consumed_message = &mut ctx.accounts.consumed_message;
+        consumed_message.version = ACCOUNT_VERSION;
+        consumed_message.digest = digest;
+        consumed_message.consumed_at = Clock::get()?.unix_timestamp;
+        consumed_message.bump = ctx.bumps.consumed_message;
+
+        msg!(
+            "Wrapped transfer {} from chain {} minted",
+            message.sequence,
+            message.source_chain
+        );
+        Ok(())
+    }
+
+    /// Burn `amount` wrapped tokens to release the asset they represent to
+    /// `recipient` on its origin chain, leaving the exit for the guardian
+    /// set to sign. `relayer_fee` of it goes to whoever claims it there.
+    pub fn burn_wrapped_tokens(
+        ctx: Context<BurnWrappedTokens>,
+        recipient: [u8; 32],
+        amount: u64,
+        relayer_fee: u64,
+    ) -> Result<()> {
+        require!(amount > 0, UntraceError::InvalidInstruction);
+        require!(relayer_fee <= amount, UntraceError::RelayerFeeTooHigh);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let wrapped_mint = &mut ctx.accounts.wrapped_mint;
+        wrapped_mint.outstanding = wrapped_mint
+            .outstanding
+            .checked_sub(amount)
+            .ok_or(UntraceError::InsufficientAssetBalance)?;
+
+        let outbox = &mut ctx.accounts.guardian_outbox;
+        let sequence = outbox.next_sequence;
+        outbox.version = ACCOUNT_VERSION;
+        outbox.next_sequence = sequence
+            .checked_add(1)
+            .ok_or(UntraceError::InvalidInstruction)?;
+        outbox.bump = ctx.bumps.guardian_outbox;
+
+        let message = WrappedTransferMessage {
+            source_chain: SOLANA_CHAIN_ID,
+            dest_chain: wrapped_mint.origin_chain,
+            sequence,
+            origin_chain: wrapped_mint.origin_chain,
+            origin_asset: wrapped_mint.origin_asset,
+            recipient,
+            amount,
+            relayer_fee,
+        };
+        msg!(
+            "Wrapped tokens burned for chain {}, sequence {}",
+            message.dest_chain,
+            sequence
+        );
+        emit_cpi!(WrappedTokensBurned { message });
+        Ok(())
+    }
+
+    /// Return an outbound transfer's locked lamports to its sender once it
+    /// has gone unclaimed for `BRIDGE_REFUND_TIMEOUT` and a quorum of the
+    /// guardian set, signing in Ed25519 precompile instructions earlier in
+    /// the transaction, attests it expired on its destination unredeemed
+    pub fn refund_bridge_transfer(ctx: Context<RefundBridgeTransfer>) -> Result<()> {
+        let outbound_transfer = &mut ctx.accounts.outbound_transfer;
+        require!(
+            outbound_transfer.refunded_at == 0,
+            UntraceError::TransferAlreadyRefunded
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= outbound_transfer
+                .posted_at
+                .saturating_add(BRIDGE_REFUND_TIMEOUT),
+            UntraceError::RefundNotAvailable
+        );
+
+        // The timeout alone cannot tell whether the destination redeemed it
+        let refund = BridgeRefundMessage {
+            dest_chain: outbound_transfer.dest_chain,
+            sequence: outbound_transfer.sequence,
+            transfer: outbound_transfer.message.to_bytes(),
+            amount: outbound_transfer.amount,
+        };
+        let signatures = guardian::count_guardian_signatures(
+            &ctx.accounts.instructions.to_account_info(),
+            &ctx.accounts.guardian_set.guardians,
+            &refund.digest(),
+        )?;
+        require!(
+            signatures >= ctx.accounts.guardian_set.quorum as usize,
+            UntraceError::GuardianQuorumNotReached
+        );
+
+        pay_from_bridge_vault(
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.sender.to_account_info(),
+            outbound_transfer.amount,
+        )?;
+        outbound_transfer.refunded_at = now;
+
+        msg!(
+            "Bridge transfer {} to chain {} refunded",
+            outbound_transfer.sequence,
+            outbound_transfer.dest_chain
+        );
+        Ok(())
+    }
+
+    /// Complete an inbound bridge transfer carried by a VAA the core bridge
+    /// has verified and posted
+    pub fn redeem_wormhole_transfer(
+        ctx: Context<RedeemWormholeTransfer>,
+        message: BridgeMessage,
+    ) -> Result<()> {
+        require!(
+            message.dest_chain == SOLANA_CHAIN_ID,
+            UntraceError::InvalidInstruction
+        );
+        require!(
+            ctx.accounts.consumed_message.consumed_at == 0,
+            UntraceError::MessageAlreadyConsumed
+        );
+
+        let payload = message
+            .try_to_vec()
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+        verify_posted_vaa(
+            &ctx.accounts.posted_vaa,
+            &ctx.accounts.foreign_emitter,
+            message.source_chain,
+            &payload,
+        )?;
+
+        release_bridge_transfer(
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.recipient.to_account_info(),
+            &mut ctx.accounts.consumed_message,
+            ctx.bumps.consumed_message,
+            &message,
+        )?;
+
+        msg!(
+            "Wormhole transfer {} from chain {} redeemed",
+            message.sequence,
+            message.source_chain
+        );
+        Ok(())
+    }
+
+    /// Point the program at a LayerZero endpoint and register it there as
+    /// an OApp. The endpoint's `register_oapp` accounts follow as remaining
+    /// accounts.
+    pub fn initialize_layerzero<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitializeLayerZero<'info>>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.layerzero_config;
+        config.version = ACCOUNT_VERSION;
+        config.authority = ctx.accounts.authority.key();
+        config.endpoint = ctx.accounts.endpoint_program.key();
+        config.next_sequence = 0;
+        config.bump = ctx.bumps.layerzero_config;
+
+        let data = layerzero::instruction_data(
+            "register_oapp",
+            &layerzero::RegisterOAppParams {
+                delegate: ctx.accounts.authority.key(),
+            },
+        )
+        .map_err(|_| UntraceError::InvalidInstruction)?;
+        invoke_endpoint(
+            &ctx.accounts.layerzero_config.to_account_info(),
+            ctx.accounts.layerzero_config.bump,
+            &ctx.accounts.endpoint_program,
+            ctx.remaining_accounts,
+            data,
+        )?;
+
+        msg!("LayerZero endpoint set to {}", ctx.accounts.endpoint_program.key());
+        Ok(())
+    }
+
+    /// Trust `address` as the bridge's OApp on LayerZero endpoint `eid`.
+    /// Replacing it later goes through the timelock.
+    pub fn set_layerzero_peer(
+        ctx: Context<SetLayerZeroPeer>,
+        eid: u32,
+        address: [u8; 32],
+    ) -> Result<()> {
+        require!(is_foreign_layerzero_eid(eid), UntraceError::InvalidInstruction);
+
+        let peer = &mut ctx.accounts.peer;
+        peer.version = ACCOUNT_VERSION;
+        peer.eid = eid;
+        peer.address = address;
+        peer.bump = ctx.bumps.peer;
+
+        msg!("LayerZero peer set for endpoint {}", eid);
+        Ok(())
+    }
+
+    /// Lock `amount` lamports in the bridge vault and send the outbound
+    /// transfer to the destination's peer through LayerZero. The endpoint's
+    /// `send` accounts, its send library's included, follow as remaining
+    /// accounts; the sender pays the library's fee, at most `native_fee`.
+    pub fn post_layerzero_message<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PostLayerZeroMessage<'info>>,
+        dest_chain: u16,
+        recipient: [u8; 32],
+        amount: u64,
+        payload_hash: [u8; 32],
+        native_fee: u64,
+        options: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            dest_chain != SOLANA_CHAIN_ID
+                && layerzero::to_layerzero_eid(dest_chain) == Some(ctx.accounts.peer.eid),
+            UntraceError::InvalidInstruction
+        );
+        require!(amount > 0, UntraceError::InvalidInstruction);
+
+        lock_outbound_amount(
+            &mut ctx.accounts.bridge_config,
+            &ctx.accounts.sender.to_account_info(),
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            dest_chain,
+            amount,
+        )?;
+
+        let config = &mut ctx.accounts.layerzero_config;
+        let sequence = config.next_sequence;
+        config.next_sequence = sequence
+            .checked_add(1)
+            .ok_or(UntraceError::InvalidInstruction)?;
+
+        let message = BridgeMessage {
+            source_chain: SOLANA_CHAIN_ID,
+            dest_chain,
+            sequence,
+            recipient,
+            amount,
+            payload_hash,
+        };
+        let payload = message
+            .try_to_vec()
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+        let data = layerzero::instruction_data(
+            "send",
+            &layerzero::SendParams {
+                dst_eid: ctx.accounts.peer.eid,
+                receiver: ctx.accounts.peer.address,
+                message: payload,
+                options,
+                native_fee,
+                lz_token_fee: 0,
+            },
+        )
+        .map_err(|_| UntraceError::InvalidInstruction)?;
+        invoke_endpoint(
+            &ctx.accounts.layerzero_config.to_account_info(),
+            ctx.accounts.layerzero_config.bump,
+            &ctx.accounts.endpoint_program,
+            ctx.remaining_accounts,
+            data,
+        )?;
+
+        let outbound_transfer = &mut ctx.accounts.outbound_transfer;
+        outbound_transfer.version = ACCOUNT_VERSION;
+        outbound_transfer.sender = ctx.accounts.sender.key();
+        outbound_transfer.message = ctx.accounts.transfer.key();
+        outbound_transfer.dest_chain = dest_chain;
+        outbound_transfer.sequence = sequence;
+        outbound_transfer.amount = amount;
+        outbound_transfer.posted_at = Clock::get()?.unix_timestamp;
+        outbound_transfer.refunded_at = 0;
+        outbound_transfer.protocol = BridgeProtocol::LayerZero;
+        outbound_transfer.bump = ctx.bumps.outbound_transfer;
+
+        msg!("LayerZero message {} sent to chain {}", sequence, dest_chain);
+        Ok(())
+    }
+
+    /// Release an inbound transfer delivered by LayerZero from a trusted
+    /// peer, clearing the verified packet at the endpoint so it cannot be
+    /// delivered again. The endpoint's `clear` accounts follow as remaining
+    /// accounts.
+    pub fn lz_receive<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LzReceive<'info>>,
+        params: layerzero::LzReceiveParams,
+    ) -> Result<()> {
+        require!(
+            params.sender == ctx.accounts.peer.address,
+            UntraceError::UnknownEmitter
+        );
+        require!(
+            ctx.accounts.consumed_message.consumed_at == 0,
+            UntraceError::MessageAlreadyConsumed
+        );
+
+        let message = BridgeMessage::try_from_slice(&params.message)
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+        require!(
+            message.dest_chain == SOLANA_CHAIN_ID
+                && layerzero::from_layerzero_eid(params.src_eid) == Some(message.source_chain),
+            UntraceError::InvalidInstruction
+        );
+        require_keys_eq!(
+            ctx.accounts.recipient.key(),
+            Pubkey::new_from_array(message.recipient),
+            UntraceError::InvalidInstruction
+        );
+
+        let config = ctx.accounts.layerzero_config.to_account_info();
+        let data = layerzero::instruction_data("clear", &params.clear_params(config.key()))
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+        invoke_endpoint(
+            &config,
+            ctx.accounts.layerzero_config.bump,
+            &ctx.accounts.endpoint_program,
+            ctx.remaining_accounts,
+            data,
+        )?;
+
+        release_bridge_transfer(
+            &ctx.accounts.bridge_vault.to_account_info(),
+            &ctx.accounts.recipient.to_account_info(),
+            &mut ctx.accounts.consumed_message,
+            ctx.bumps.consumed_message,
+            &message,
+        )?;
+
+        msg!(
+            "LayerZero transfer {} from chain {} received",
+            message.sequence,
+            message.source_chain
+        );
+        Ok(())
+    }
+
+    /// Post the root of a batch of shielded nullifiers spent here to the
+    /// other chains, which then refuse to let those notes exit. The
+    /// nullifiers' PDAs follow as remaining accounts, proving each spent.
+    pub fn post_nullifier_root<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PostNullifierRoot<'info>>,
+        nullifiers: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        require!(
+            !nullifiers.is_empty() && nullifiers.len() <= MAX_NULLIFIER_BATCH,
+            UntraceError::InvalidInstruction
+        );
+        require!(
+            ctx.remaining_accounts.len() == nullifiers.len(),
+            UntraceError::InvalidInstruction
+        );
+        for (nullifier, info) in nullifiers.iter().zip(ctx.remaining_accounts) {
+            let (expected, _) =
+                Pubkey::find_program_address(&[b"shielded_nullifier", nullifier], &crate::ID);
+            require!(info.key() == expected, UntraceError::InvalidInstruction);
+            let spent: Account<NullifierAccount> = Account::try_from(info)?;
+            require!(
+                spent.is_used && spent.nullifier == *nullifier,
+                UntraceError::InvalidInstruction
+            );
+        }
+
+        let sync = &mut ctx.accounts.nullifier_sync;
+        if sync.version == 0 {
+            sync.version = ACCOUNT_VERSION;
+            sync.bump = ctx.bumps.nullifier_sync;
+        }
+        let message = NullifierRootMessage {
+            source_chain: SOLANA_CHAIN_ID,
+            batch: sync.next_batch,
+            nullifier_root: crypto::merkle_root(&nullifiers),
+            nullifier_count: nullifiers.len() as u32,
+        };
+        sync.next_batch += 1;
+        let payload = message
+            .try_to_vec()
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+
+        let accounts = &ctx.accounts;
+        post_wormhole_message(
+            accounts.wormhole_config.emitter_bump,
+            [
+                accounts.wormhole_bridge.to_account_info(),
+                accounts.wormhole_message.to_account_info(),
+                accounts.emitter.to_account_info(),
+                accounts.wormhole_sequence.to_account_info(),
+                accounts.payer.to_account_info(),
+                accounts.wormhole_fee_collector.to_account_info(),
+                accounts.clock.to_account_info(),
+                accounts.rent.to_account_info(),
+                accounts.system_program.to_account_info(),
+                accounts.wormhole_program.to_account_info(),
+            ],
+            &payload,
+        )?;
+
+        msg!(
+            "Nullifier root {} posted with {} nullifiers",
+            message.batch,
+            message.nullifier_count
+        );
+        Ok(())
+    }
+
+    /// Store a nullifier root another chain posted, carried by a VAA the
+    /// core bridge has verified and posted
+    pub fn receive_nullifier_root(
+        ctx: Context<ReceiveNullifierRoot>,
+        message: NullifierRootMessage,
+    ) -> Result<()> {
+        require!(
+            message.source_chain != SOLANA_CHAIN_ID && message.nullifier_count > 0,
+            UntraceError::InvalidInstruction
+        );
+        let payload = message
+            .try_to_vec()
+            .map_err(|_| UntraceError::InvalidInstruction)?;
+        verify_posted_vaa(
+            &ctx.accounts.posted_vaa,
+            &ctx.accounts.foreign_emitter,
+            message.source_chain,
+            &payload,
+        )?;
+
+        let root = &mut ctx.accounts.nullifier_root;
+        root.version = ACCOUNT_VERSION;
+        root.source_chain = message.source_chain;
+        root.batch = message.batch;
+        root.nullifier_root = message.nullifier_root;
+        root.nullifier_count = message.nullifier_count;
+        root.received_at = Clock::get()?.unix_timestamp;
+        root.bump = ctx.bumps.nullifier_root;
+
+        msg!(
+            "Nullifier root {} from chain {} received",
+            message.batch,
+            message.source_chain
+        );
+        Ok(())
+    }
+
+    /// Mark a nullifier spent on another chain as spent here too, proven by
+    /// its path to a received nullifier root. Anyone may apply it.
+    pub fn apply_remote_nullifier(
+        ctx: Context<ApplyRemoteNullifier>,
+        nullifier: [u8; 32],
+        leaf_index: u32,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let root = &ctx.accounts.nullifier_root;
+        // A path of the batch's full depth, so an inner node cannot pass as
+        // a leaf
+        require!(
+            leaf_index < root.nullifier_count
+                && proof.len() == crypto::merkle_depth(root.nullifier_count as usize),
+            UntraceError::InvalidMerkleProof
+        );
+        require!(
+            crypto::verify_merkle_proof(&nullifier, &proof, &root.nullifier_root, leaf_index),
+            UntraceError::InvalidMerkleProof
+        );
+
+        let nullifier_account = &mut ctx.accounts.nullifier_account;
+        nullifier_account.version = ACCOUNT_VERSION;
+        nullifier_account.nullifier = nullifier;
+        nullifier_account.is_used = true;
+        nullifier_account.timestamp = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Nullifier from chain {} batch {} applied",
+            root.source_chain,
+            root.batch
+        );
+        Ok(())
+    }
+
+    /// Initialize the unified multi-asset shielded pool
+    pub fn initialize_shielded_pool(
+        ctx: Context<InitializeShieldedPool>,
+        min_pool_size: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.shielded_pool;
+        pool.version = ACCOUNT_VERSION;
+        pool.authority = ctx.accounts.authority.key();
+        pool.commitment_count = 0;
+        pool.min_pool_size = min_pool_size;
+        pool.asset_count = 0;
+        pool.bump = ctx.bumps.shielded_pool;
+
+        let mut tree = ctx.accounts.merkle_tree.load_init()?;
+        tree.initialize(SHIELDED_TREE_ID, ctx.bumps.merkle_tree);
+        pool.commitment_root = tree.roots[0];
+
+        msg!("Shielded pool initialized");
+        Ok(())
+    }
+
+    /// Register an asset that can be shielded in the pool
+    pub fn register_asset(ctx: Context<RegisterAsset>, asset_id: Pubkey) -> Result<()> {
+        let asset = &mut ctx.accounts.asset;
+        asset.version = ACCOUNT_VERSION;
+        asset.asset_id = asset_id;
+        asset.total_deposited = 0;
+        asset.total_withdrawn = 0;
+        asset.bump = ctx.bumps.asset;
+
+        ctx.accounts.shielded_pool.asset_count += 1;
+
+        msg!("Asset {} registered in shielded pool", asset_id);
+        Ok(())
+    }
+
+    /// Deposit an asset into the shielded pool, creating a note commitment
+    pub fn shielded_deposit(
+        ctx: Context<ShieldedDeposit>,
+        asset_id: Pubkey,
+        amount: u64,
+        commitment: [u8; 32],
+        note_key: [u8; 32],
+        encrypted_note: Vec<u8>,
+    ) -> Result<u64> {
+        require!(amount > 0, UntraceError::InvalidInstruction);
+        // The note must be worth what is deposited, so it cannot later be
+        // withdrawn for more
+        let expected =
+            crypto::generate_asset_commitment(&asset_id.to_bytes(), amount, &note_key);
+        require!(commitment == expected, UntraceError::CommitmentMismatch);
+
+        if asset_id == NATIVE_ASSET_ID {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.depositor.to_account_info(),
+                        to: ctx.accounts.shielded_pool.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        } else {
+            let (source, vault, token_program) = match (
+                &ctx.accounts.depositor_token_account,
+                &ctx.accounts.asset_vault,
+                &ctx.accounts.token_program,
+            ) {
+                (Some(source), Some(vault), Some(token_program)) => (source, vault, token_program),
+                _ => return Err(UntraceError::InvalidAsset.into()),
+            };
+
+            token::transfer(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    token::Transfer {
+                        from: source.to_account_info(),
+                        to: vault.to_account_info(),
+                        authority: ctx.accounts.depositor.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        }
+
+        let commitment_account = &mut ctx.accounts.commitment_account;
+        commitment_account.version = ACCOUNT_VERSION;
+        commitment_account.commitment = commitment;
+        let mut tree = ctx.accounts.merkle_tree.load_mut()?;
+        commitment_account.leaf_index = ctx
+            .accounts
+            .shielded_pool
+            .append_commitment(&mut tree, commitment)?;
+        commitment_account.timestamp = Clock::get()?.unix_timestamp;
+        commitment_account.bump = ctx.bumps.commitment_account;
+
+        let asset = &mut ctx.accounts.asset;
+        asset.total_deposited = asset
+            .total_deposited
+            .checked_add(amount)
+            .ok_or(UntraceError::InvalidInstruction)?;
+
+        let leaf_index = commitment_account.leaf_index;
+        msg!(
+            "Shielded deposit of asset {} at leaf {} ({} byte note)",
+            asset_id,
+            leaf_index,
+            encrypted_note.len()
+        );
+        emit_cpi!(CommitmentInserted {
+            pool: ctx.accounts.shielded_pool.key(),
+            leaf_index,
+            commitment,
+            encrypted_note,
+        });
+
+        Ok(leaf_index)
+    }
+
+    /// Withdraw an asset from the shielded pool; the asset and amount are public
+    pub fn shielded_withdraw(
+        ctx: Context<ShieldedWithdraw>,
+        asset_id: Pubkey,
+        amount: u64,
+        nullifier: [u8; 32],
+        zk_proof: Vec<u8>,
+        root: [u8; 32],
+    ) -> Result<()> {
+        let pool = &ctx.accounts.shielded_pool;
+
+        require!(
+            pool.commitment_count >= pool.min_pool_size,
+            UntraceError::InsufficientPoolSize
+        );
+        require!(
+            ctx.accounts.merkle_tree.load()?.is_known_root(&root),
+            UntraceError::UnknownMerkleRoot
+        );
+
+        // The Groth16 proof's public input hashes the root, asset, amount,
+        // recipient and nullifier, so none of them can be swapped for
+        // another after it is made
+        let public_inputs = crypto::shielded_withdraw_inputs(
+            &root,
+            &asset_id.to_bytes(),
+            amount,
+            &ctx.accounts.recipient.key().to_bytes(),
+            &nullifier,
+        );
+        require!(
+            groth16::verify(&ctx.accounts.verifier.key, &zk_proof, &public_inputs),
+            UntraceError::InvalidZKProof
+        );
+
+        let asset = &ctx.accounts.asset;
+        let withdrawn = asset
+            .total_withdrawn
+            .checked_add(amount)
+            .filter(|withdrawn| *withdrawn <= asset.total_deposited)
+            .ok_or(UntraceError::InsufficientAssetBalance)?;
+
+        if asset_id == NATIVE_ASSET_ID {
+            let pool_info = ctx.accounts.shielded_pool.to_account_info();
+            let rent_floor = Rent::get()?.minimum_balance(ShieldedPoolAccount::LEN);
+            require!(
+                pool_info.lamports().saturating_sub(rent_floor) >= amount,
+                UntraceError::InsufficientAssetBalance
+            );
+
+            let recipient = ctx.accounts.recipient.to_account_info();
+            **pool_info.try_borrow_mut_lamports()? -= amount;
+            **recipient.try_borrow_mut_lamports()? += amount;
+        } else {
+            let (vault, destination, token_program) = match (
+                &ctx.accounts.asset_vault,
+                &ctx.accounts.recipient_token_account,
+                &ctx.accounts.token_program,
+            ) {
+                (Some(vault), Some(destination), Some(token_program)) => {
+                    (vault, destination, token_program)
+                }
+                _ => return Err(UntraceError::InvalidAsset.into()),
+            };
+
+            let bump = [ctx.accounts.asset.bump];
+            let signer_seeds: &[&[&[u8]]] = &[&[b"shielded_asset", asset_id.as_ref(), &bump]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    token::Transfer {
+                        from: vault.to_account_info(),
+                        to: destination.to_account_info(),
+                        authority: ctx.accounts.asset.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                amount,
+            )?;
+        }
+
+        let nullifier_account = &mut ctx.accounts.nullifier_account;
+        nullifier_account.version = ACCOUNT_VERSION;
+        nullifier_account.nullifier = nullifier;
+        nullifier_account.is_used = true;
+        nullifier_account.timestamp = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.asset.total_withdrawn = withdrawn;
+
+        msg!("Shielded withdrawal of asset {}", asset_id);
+        Ok(())
+    }
+
+    /// Upgrade an account written with a previous layout to the current version
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        let target = ctx.accounts.target.to_account_info();
+
+        let legacy_pool = {
+            let data = target.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == PrivacyPoolAccount::DISCRIMINATOR,
+                UntraceError::InvalidInstruction
+            );
+            require!(
+                data.len() == legacy::PrivacyPoolAccountV0::LEN,
+                UntraceError::AccountAlreadyMigrated
+            );
+            legacy::PrivacyPoolAccountV0::deserialize(&mut &data[8..])?
+        };
+
+        require_keys_eq!(
+            legacy_pool.authority,
+            ctx.accounts.authority.key(),
+            UntraceError::Unauthorized
+        );
+
+        // Top up rent for the larger layout before growing the account
+        let rent_due = Rent::get()?
+            .minimum_balance(PrivacyPoolAccount::LEN)
+            .saturating_sub(target.lamports());
+        if rent_due > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.authority.to_account_info(),
+                        to: target.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+        target.realloc(PrivacyPoolAccount::LEN, false)?;
+
+        let pool = PrivacyPoolAccount {
+            version: ACCOUNT_VERSION,
+            pool_id: legacy_pool.pool_id,
+            commitment_root: legacy_pool.commitment_root,
+            commitment_count: legacy_pool.commitment_count,
+            min_pool_size: legacy_pool.min_pool_size,
+            authority: legacy_pool.authority,
+        };
+        let mut data = target.try_borrow_mut_data()?;
+        pool.try_serialize(&mut &mut data[..])?;
+
+        msg!("Pool {} migrated to version {}", pool.pool_id, ACCOUNT_VERSION);
+        Ok(())
+    }
+
+    /// Initialize a pool backed by a concurrent merkle tree
+    pub fn initialize_compressed_pool(
+        ctx: Context<InitializeCompressedPool>,
+        pool_id: u64,
+        min_pool_size: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.compressed_pool;
+        pool.version = ACCOUNT_VERSION;
+        pool.pool_id = pool_id;
+        pool.merkle_tree = ctx.accounts.merkle_tree.key();
+        pool.commitment_count = 0;
+        pool.min_pool_size = min_pool_size;
+        pool.authority = ctx.accounts.authority.key();
+        pool.bump = ctx.bumps.compressed_pool;
+
+        // The pool PDA becomes the tree authority, so only this program can append
+        let pool_id_bytes = pool_id.to_le_bytes();
+        let seeds: &[&[u8]] = &[b"compressed_pool", &pool_id_bytes, &[pool.bump]];
+        compression_cpi::init_empty_merkle_tree(
+            CpiContext::new_with_signer(
+                ctx.accounts.compression_program.to_account_info(),
+                compression_cpi::accounts::Initialize {
+                    merkle_tree: ctx.accounts.merkle_tree.to_account_info(),
+                    authority: pool.to_account_info(),
+                    noop: ctx.accounts.log_wrapper.to_account_info(),
+                },
+                &[seeds],
+            ),
+            COMPRESSED_TREE_DEPTH,
+            COMPRESSED_TREE_BUFFER_SIZE,
+        )?;
+
+        msg!("Compressed pool {} initialized", pool_id);
+        Ok(())
+    }
+
+    /// Deposit `amount` lamports into a compressed pool, appending the
+    /// commitment as a leaf of the pool's concurrent tree.
+    ///
+    /// The compression program logs a changelog through the noop program,
+    /// from which indexers reconstruct the leaves.
+    pub fn compressed_deposit(
+        ctx: Context<CompressedDeposit>,
+        commitment: [u8; 32],
+        amount: u64,
+        note_key: [u8; 32],
+        encrypted_data: Vec<u8>,
+    ) -> Result<()> {
+        // Same note as a privacy pool's, so it cannot be withdrawn for more
+        require!(
+            commitment == crypto::generate_commitment(amount, &note_key),
+            UntraceError::CommitmentMismatch
+        );
+        require!(amount > 0, UntraceError::DepositBelowMinimum);
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.compressed_pool.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let pool = &ctx.accounts.compressed_pool;
+        let pool_id_bytes = pool.pool_id.to_le_bytes();
+        let seeds: &[&[u8]] = &[b"compressed_pool", &pool_id_bytes, &[pool.bump]];
+        compression_cpi::append(
+            CpiContext::new_with_signer(
+                ctx.accounts.compression_program.to_account_info(),
+                compression_cpi::accounts::Modify {
+                    merkle_tree: ctx.accounts.merkle_tree.to_account_info(),
+                    authority: pool.to_account_info(),
+                    noop: ctx.accounts.log_wrapper.to_account_info(),
+                },
+                &[seeds],
+            ),
+            commitment,
+        )?;
+
+        let pool = &mut ctx.accounts.compressed_pool;
+        let leaf_index = pool.commitment_count;
+        pool.commitment_count += 1;
+
+        msg!("Deposit committed to compressed pool {} at leaf {}", pool.pool_id, leaf_index);
+        emit_cpi!(CommitmentInserted {
+            pool: pool.key(),
+            leaf_index,
+            commitment,
+            encrypted_note: encrypted_data,
+        });
+        Ok(())
+    }
+
+    /// Withdraw `amount` lamports from a compressed pool to `recipient`,
+    /// against a root still in the tree's changelog buffer
+    pub fn compressed_withdraw(
+        ctx: Context<CompressedWithdraw>,
+        nullifier: [u8; 32],
+        recipient: Pubkey,
+        amount: u64,
+        zk_proof: Vec<u8>,
+        root: [u8; 32],
+    ) -> Result<()> {
+        let pool = &ctx.accounts.compressed_pool;
+
+        require!(
+            pool.commitment_count >= pool.min_pool_size,
+            UntraceError::InsufficientPoolSize
+        );
+
+        require!(
+            compression::is_recent_root(&ctx.accounts.merkle_tree, &pool.key(), &root)?,
+            UntraceError::UnknownMerkleRoot
+        );
+
+        // Same statement as a privacy pool withdrawal, under its own key
+        let public_inputs =
+            crypto::withdraw_inputs(&root, amount, &recipient.to_bytes(), &nullifier);
+        require!(
+            groth16::verify(&ctx.accounts.verifier.key, &zk_proof, &public_inputs),
+            UntraceError::InvalidZKProof
+        );
+
+        let pool_info = ctx.accounts.compressed_pool.to_account_info();
+        let rent_floor = Rent::get()?.minimum_balance(pool_info.data_len());
+        require!(
+            pool_info.lamports().saturating_sub(rent_floor) >= amount,
+            UntraceError::InsufficientPoolBalance
+        );
+        let recipient_info = ctx.accounts.recipient.to_account_info();
+        **pool_info.try_borrow_mut_lamports()? -= amount;
+        **recipient_info.try_borrow_mut_lamports()? += amount;
+
+        let nullifier_account = &mut ctx.accounts.nullifier_account;
+        nullifier_account.version = ACCOUNT_VERSION;
+        nullifier_account.nullifier = nullifier;
+        nullifier_account.is_used = true;
+        nullifier_account.timestamp = Clock::get()?.unix_timestamp;
+
+        msg!("Withdrawal processed for compressed pool {}", pool.pool_id);
+        Ok(())
+    }
+
+    /// Set up protocol admin state with an emergency guardian
+    pub fn initialize_admin(
+        ctx: Context<InitializeAdmin>,
+        guardian: Pubkey,
+        timelock_delay: i64,
+    ) -> Result<()> {
+        require!(
+            (MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&timelock_delay),
+            UntraceError::InvalidTimelockDelay
+        );
+
+        let admin = &mut ctx.accounts.admin_config;
+        admin.version = ACCOUNT_VERSION;
+        admin.authority = ctx.accounts.authority.key();
+        admin.guardian = guardian;
+        admin.paused = false;
+        admin.timelock_delay = timelock_delay;
+        admin.bump = ctx.bumps.admin_config;
+
+        msg!("Admin initialized with {}s timelock", timelock_delay);
+        Ok(())
+    }
+
+    /// Pause immediately; callable by the guardian or the authority
+    pub fn pause(ctx: Context<SetPaused>) -> Result<()> {
+        let admin = &mut ctx.accounts.admin_config;
+        let signer = ctx.accounts.signer.key();
+        require!(
+            signer == admin.guardian || signer == admin.authority,
+            UntraceError::Unauthorized
+        );
+
+        admin.paused = true;
+
+        msg!("Protocol paused by {}", signer);
+        Ok(())
+    }
+
+    /// Resume; only the authority may unpause
+    pub fn unpause(ctx: Context<SetPaused>) -> Result<()> {
+        let admin = &mut ctx.accounts.admin_config;
+        require_keys_eq!(
+            ctx.accounts.signer.key(),
+            admin.authority,
+            UntraceError::Unauthorized
+        );
+
+        admin.paused = false;
+
+        msg!("Protocol unpaused");
+        Ok(())
+    }
+
+    /// Queue an admin action to run once the timelock delay has passed
+    pub fn queue_admin_action(
+        ctx: Context<QueueAdminAction>,
+        action_id: u64,
+        action: AdminAction,
+    ) -> Result<()> {
+        let admin = &ctx.accounts.admin_config;
+        let proposer = ctx.accounts.proposer.key();
+
+        match &action {
+            AdminAction::ConfigurePool {
+                pool_id,
+                min_deposit,
+                max_deposit,
+                ..
+            } => {
+                let pool = ctx
+                    .accounts
+                    .privacy_pool
+                    .as_ref()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                require!(pool.pool_id == *pool_id, UntraceError::InvalidInstruction);
+                require_keys_eq!(pool.authority, proposer, UntraceError::Unauthorized);
+                require!(
+                    *max_deposit == 0 || min_deposit <= max_deposit,
+                    UntraceError::InvalidInstruction
+                );
+            }
+            AdminAction::SetTimelockDelay { delay } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+                require!(
+                    (MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(delay),
+                    UntraceError::InvalidTimelockDelay
+                );
+            }
+            AdminAction::SetBridgeLimit { dest_chain, .. } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+                require!(
+                    *dest_chain != SOLANA_CHAIN_ID
+                        && (*dest_chain as usize) < MAX_BRIDGE_CHAINS
+                        && wormhole::to_wormhole_chain_id(*dest_chain).is_some(),
+                    UntraceError::InvalidInstruction
+                );
+            }
+            AdminAction::SetGuardianSet { guardians, quorum } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+                guardian::check_guardian_set(guardians, *quorum)?;
+            }
+            AdminAction::SetForeignEmitter { chain, .. } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+                require!(
+                    is_foreign_wormhole_chain(*chain),
+                    UntraceError::InvalidInstruction
+                );
+            }
+            AdminAction::SetLayerZeroPeer { eid, .. } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+                require!(is_foreign_layerzero_eid(*eid), UntraceError::InvalidInstruction);
+            }
+            AdminAction::TransferAuthority { .. }
+            | AdminAction::SetGuardian { .. }
+            | AdminAction::SetVerifyingKey { .. } => {
+                require_keys_eq!(admin.authority, proposer, UntraceError::Unauthorized);
+            }
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let timelock = &mut ctx.accounts.timelock;
+        timelock.version = ACCOUNT_VERSION;
+        timelock.action_id = action_id;
+        timelock.action = action;
+        timelock.proposer = proposer;
+        timelock.queued_at = now;
+        timelock.eta = now
+            .checked_add(admin.timelock_delay)
+            .ok_or(UntraceError::InvalidTimelockDelay)?;
+        timelock.bump = ctx.bumps.timelock;
+
+        msg!("Admin action {} queued, executable at {}", action_id, timelock.eta);
+        Ok(())
+    }
+
+    /// Apply a queued action whose delay has passed; anyone may call this
+    pub fn execute_admin_action(ctx: Context<ExecuteAdminAction>) -> Result<()> {
+        let timelock = &ctx.accounts.timelock;
+        require!(
+            Clock::get()?.unix_timestamp >= timelock.eta,
+            UntraceError::TimeLockNotExpired
+        );
+
+        let admin = &mut ctx.accounts.admin_config;
+
+        // Drop protocol actions queued by an authority that has since been replaced
+        if !matches!(timelock.action, AdminAction::ConfigurePool { .. }) {
+            require_keys_eq!(timelock.proposer, admin.authority, UntraceError::Unauthorized);
+        }
+
+        match timelock.action.clone() {
+            AdminAction::ConfigurePool {
+                pool_id,
+                min_deposit,
+                max_deposit,
+                epoch_deposit_cap,
+            } => {
+                let config = ctx
+                    .accounts
+                    .pool_config
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                require!(config.pool_id == pool_id, UntraceError::InvalidInstruction);
+                config.min_deposit = min_deposit;
+                config.max_deposit = max_deposit;
+                config.epoch_deposit_cap = epoch_deposit_cap;
+            }
+            AdminAction::SetBridgeLimit {
+                dest_chain,
+                epoch_cap,
+            } => {
+                let bridge_config = ctx
+                    .accounts
+                    .bridge_config
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                let limit = bridge_config
+                    .limits
+                    .get_mut(dest_chain as usize)
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                limit.epoch_cap = epoch_cap;
+            }
+            AdminAction::SetGuardianSet { guardians, quorum } => {
+                let guardian_set = ctx
+                    .accounts
+                    .guardian_set
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                guardian_set.guardians = guardians;
+                guardian_set.quorum = quorum;
+            }
+            AdminAction::SetForeignEmitter { chain, address } => {
+                let emitter = ctx
+                    .accounts
+                    .foreign_emitter
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                require!(emitter.chain == chain, UntraceError::InvalidInstruction);
+                emitter.address = address;
+            }
+            AdminAction::SetLayerZeroPeer { eid, address } => {
+                let peer = ctx
+                    .accounts
+                    .layerzero_peer
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                require!(peer.eid == eid, UntraceError::InvalidInstruction);
+                peer.address = address;
+            }
+            AdminAction::SetVerifyingKey { circuit, key } => {
+                let verifier = ctx
+                    .accounts
+                    .verifier
+                    .as_mut()
+                    .ok_or(UntraceError::InvalidInstruction)?;
+                require!(verifier.circuit == circuit, UntraceError::InvalidInstruction);
+                verifier.key = *key;
+            }
+            AdminAction::TransferAuthority { new_authority } => admin.authority = new_authority,
+            AdminAction::SetGuardian { guardian } => admin.guardian = guardian,
+            AdminAction::SetTimelockDelay { delay } => admin.timelock_delay = delay,
+        }
+
+        msg!("Admin action {} executed", timelock.action_id);
+        Ok(())
+    }
+
+    /// Drop a queued action; callable by its proposer or the guardian
+    pub fn cancel_admin_action(ctx: Context<CancelAdminAction>) -> Result<()> {
+        let canceller = ctx.accounts.canceller.key();
+        require!(
+            canceller == ctx.accounts.timelock.proposer
+                || canceller == ctx.accounts.admin_config.guardian,
+            UntraceError::Unauthorized
+        );
+
+        msg!("Admin action {} cancelled", ctx.accounts.timelock.action_id);
+        Ok(())
+    }
+
+    /// Record the output account's balance ahead of a swap later in the
+    /// transaction; `close_swap_guard` after it checks the gain
+    pub fn open_swap_guard(ctx: Context<OpenSwapGuard>, min_out: u64) -> Result<()> {
+        let guard = &mut ctx.accounts.swap_guard;
+        guard.version = ACCOUNT_VERSION;
+        guard.owner = ctx.accounts.owner.key();
+        guard.token_account = ctx.accounts.token_account.key();
+        guard.start_balance = ctx.accounts.token_account.amount;
+        guard.min_out = min_out;
+        guard.bump = ctx.bumps.swap_guard;
+        Ok(())
+    }
+
+    /// Revert the transaction unless the output account gained at least
+    /// the owner's signed minimum since `open_swap_guard`
+    pub fn close_swap_guard(ctx: Context<CloseSwapGuard>) -> Result<()> {
+        let guard = &ctx.accounts.swap_guard;
+        let gained = ctx
+            .accounts
+            .token_account
+            .amount
+            .saturating_sub(guard.start_balance);
+        require!(gained >= guard.min_out, UntraceError::SlippageExceeded);
+
+        msg!("Swap output {} meets minimum {}", gained, guard.min_out);
+        Ok(())
+    }
+
+    /// Commit to a private order's payload by its hash, to be revealed from
+    /// `reveal_slot` until `expires_at`
+    pub fn commit_order(
+        ctx: Context<CommitOrder>,
+        order_id: u64,
+        payload_hash: [u8; 32],
+        reveal_slot: u64,
+        expires_at: u64,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        require!(
+            reveal_slot >= slot.saturating_add(MIN_ORDER_REVEAL_DELAY)
+                && expires_at > reveal_slot,
+            UntraceError::InvalidTimelockDelay
+        );
+
+        let commitment = &mut ctx.accounts.order_commitment;
+        commitment.version = ACCOUNT_VERSION;
+        commitment.owner = ctx.accounts.owner.key();
+        commitment.order_id = order_id;
+        commitment.payload_hash = payload_hash;
+        commitment.commit_slot = slot;
+        commitment.reveal_slot = reveal_slot;
+        commitment.expires_at = expires_at;
+        commitment.bump = ctx.bumps.order_commitment;
+
+        msg!("Order {} committed, revealable at slot {}", order_id, reveal_slot);
+        Ok(())
+    }
+
+    /// Reveal a committed order's payload once its reveal slot is reached.
+    /// Anyone holding the payload and salt may reveal it; the commitment is
+    /// closed to its owner.
+    pub fn reveal_order(
+        ctx: Context<RevealOrder>,
+        salt: [u8; 32],
+        payload: Vec<u8>,
+    ) -> Result<()> {
+        let commitment = &ctx.accounts.order_commitment;
+        let slot = Clock::get()?.slot;
+        require!(
+            slot >= commitment.reveal_slot,
+            UntraceError::TimeLockNotExpired
+        );
+        require!(slot < commitment.expires_at, UntraceError::OrderExpired);
+        require!(
+            crypto::order_payload_hash(
+                &commitment.owner.to_bytes(),
+                commitment.order_id,
+                &salt,
+                &payload
+            ) == commitment.payload_hash,
+            UntraceError::OrderPayloadMismatch
+        );
+
+        msg!(
+            "Order {} committed at slot {} revealed",
+            commitment.order_id,
+            commitment.commit_slot
+        );
+        Ok(())
+    }
+
+    /// Withdraw an unrevealed order, or reclaim the rent of an expired one
+    pub fn close_order_commitment(_ctx: Context<CloseOrderCommitment>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Lock the instruction hashed to `intent_hash` until `unlock_slot`.
+    /// The owner then moves what the instruction spends into the intent's
+    /// vault, which nothing can move out before the unlock slot.
+    pub fn lock_intent(
+        ctx: Context<LockIntent>,
+        intent_id: u64,
+        intent_hash: [u8; 32],
+        unlock_slot: u64,
+    ) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        require!(
+            unlock_slot >= slot.saturating_add(MIN_INTENT_LOCK_DELAY),
+            UntraceError::InvalidTimelockDelay
+        );
+
+        let intent = &mut ctx.accounts.intent;
+        intent.version = ACCOUNT_VERSION;
+        intent.owner = ctx.accounts.owner.key();
+        intent.intent_id = intent_id;
+        intent.intent_hash = intent_hash;
+        intent.lock_slot = slot;
+        intent.unlock_slot = unlock_slot;
+        intent.bump = ctx.bumps.intent;
+        intent.vault_bump = ctx.bumps.vault;
+
+        msg!("Intent {} locked until slot {}", intent_id, unlock_slot);
+        Ok(())
+    }
+
+    /// Run a locked intent's instruction once its unlock slot is reached,
+    /// signed by the intent's vault. The remaining accounts are the
+    /// program it calls followed by the accounts it passes. Anyone holding
+    /// the instruction and salt may execute it; what it leaves in the
+    /// vault and the intent's rent go back to the owner.
+    pub fn execute_intent<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteIntent<'info>>,
+        salt: [u8; 32],
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let intent = &ctx.accounts.intent;
+        require!(
+            Clock::get()?.slot >= intent.unlock_slot,
+            UntraceError::TimeLockNotExpired
+        );
+
+        let (program, accounts) = ctx
+            .remaining_accounts
+            .split_first()
+            .ok_or(UntraceError::InvalidInstruction)?;
+        let keys: Vec<[u8; 32]> = accounts
+            .iter()
+            .map(|account| account.key().to_bytes())
+            .collect();
+        require!(
+            crypto::intent_hash(
+                &intent.owner.to_bytes(),
+                intent.intent_id,
+                &salt,
+                &program.key().to_bytes(),
+                &keys,
+                &data
+            ) == intent.intent_hash,
+            UntraceError::IntentMismatch
+        );
+
+        let intent_key = intent.key();
+        invoke_as_vault(&intent_key, intent.vault_bump, ctx.remaining_accounts, data)?;
+        sweep_vault(
+            &intent_key,
+            intent.vault_bump,
+            &ctx.accounts.vault,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+        )?;
+
+        msg!(
+            "Intent {} locked at slot {} executed",
+            intent.intent_id,
+            intent.lock_slot
+        );
+        Ok(())
+    }
+
+    /// Withdraw a locked intent once its unlock slot is reached, instead of
+    /// executing it. When remaining accounts are given, `data` runs first
+    /// as an instruction signed by the vault, as in `execute_intent`, such
+    /// as to return the vault's tokens. The vault's lamports and the
+    /// intent's rent go back to the owner.
+    pub fn cancel_intent<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelIntent<'info>>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let intent = &ctx.accounts.intent;
+        require!(
+            Clock::get()?.slot >= intent.unlock_slot,
+            UntraceError::TimeLockNotExpired
+        );
+
+        let intent_key = intent.key();
+        if !ctx.remaining_accounts.is_empty() {
+            invoke_as_vault(&intent_key, intent.vault_bump, ctx.remaining_accounts, data)?;
+        }
+        sweep_vault(
+            &intent_key,
+            intent.vault_bump,
+            &ctx.accounts.vault,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+        )?;
+
+        msg!("Intent {} cancelled", intent.intent_id);
+        Ok(())
+    }
+}
+
+/// Call the program first in `accounts` with the rest and `data`, signed
+/// by the vault of `intent`
+fn invoke_as_vault<'info>(
+    intent: &Pubkey,
+    vault_bump: u8,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let (program, metas) = accounts
+        .split_first()
+        .ok_or(UntraceError::InvalidInstruction)?;
+    // Calling back into this program could execute another intent or
+    // reuse this one's accounts
+    require!(
+        program.executable && program.key() != crate::ID,
+        UntraceError::InvalidInstruction
+    );
+
+    let bump = [vault_bump];
+    let seeds: &[&[u8]] = &[b"intent_vault", intent.as_ref(), &bump];
+    let vault = Pubkey::create_program_address(seeds, &crate::ID)
+        .map_err(|_| UntraceError::InvalidInstruction)?;
+    let instruction = Instruction {
+        program_id: program.key(),
+        accounts: metas
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer || account.key() == vault,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    invoke_signed(&instruction, accounts, &[seeds])?;
+    Ok(())
+}
+
+/// Move every lamport left in the vault of `intent` to `owner`
+fn sweep_vault<'info>(
+    intent: &Pubkey,
+    vault_bump: u8,
+    vault: &SystemAccount<'info>,
+    owner: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let lamports = vault.lamports();
+    if lamports == 0 {
+        return Ok(());
+    }
+    let bump = [vault_bump];
+    let seeds: &[&[u8]] = &[b"intent_vault", intent.as_ref(), &bump];
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: vault.to_account_info(),
+                to: owner.clone(),
+            },
+            &[seeds],
+        ),
+        lamports,
+    )
+}
+
+/// Whether `chain` is a Wormhole chain other than Solana that the protocol
+/// bridges to, and so may have an emitter registered
+fn is_foreign_wormhole_chain(chain: u16) -> bool {
+    chain != wormhole::WORMHOLE_SOLANA_CHAIN_ID && wormhole::from_wormhole_chain_id(chain).is_some()
+}
+
+/// Whether `eid` is a LayerZero endpoint other than Solana's that the
+/// protocol bridges to, and so may have a peer set
+fn is_foreign_layerzero_eid(eid: u32) -> bool {
+    eid != layerzero::SOLANA_EID && layerzero::from_layerzero_eid(eid).is_some()
+}
+
+/// Sequence the core bridge assigns the emitter's next message. The tracker
+/// holds its current value, and does not exist before the first message.
+fn emitter_sequence(sequence_tracker: &AccountInfo) -> Result<u64> {
+    Ok(sequence_tracker
+        .try_borrow_data()?
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u64::from_le_bytes))
+}
+
+/// Pay the core bridge's message fee from the payer and post `payload` as
+/// the program's emitter. `accounts` are the bridge config, new message,
+/// emitter, sequence tracker, payer, fee collector, clock, rent, system
+/// program and core bridge program, in that order.
+fn post_wormhole_message<'info>(
+    emitter_bump: u8,
+    accounts: [AccountInfo<'info>; 10],
+    payload: &[u8],
+) -> Result<()> {
+    let [bridge, message, emitter, sequence, payer, fee_collector, clock, rent, system, program] =
+        &accounts;
+
+    let fee = wormhole::message_fee(&bridge.try_borrow_data()?)
+        .ok_or(UntraceError::InvalidInstruction)?;
+    if fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: fee_collector.clone(),
+                },
+            ),
+            fee,
+        )?;
+    }
+
+    let instruction = Instruction {
+        program_id: program.key(),
+        accounts: vec![
+            AccountMeta::new(bridge.key(), false),
+            AccountMeta::new(message.key(), true),
+            AccountMeta::new_readonly(emitter.key(), true),
+            AccountMeta::new(sequence.key(), false),
+            AccountMeta::new(payer.key(), true),
+            AccountMeta::new(fee_collector.key(), false),
+            AccountMeta::new_readonly(clock.key(), false),
+            AccountMeta::new_readonly(rent.key(), false),
+            AccountMeta::new_readonly(system.key(), false),
+        ],
+        data: wormhole::post_message_data(0, payload, wormhole::CONSISTENCY_FINALIZED),
+    };
+    invoke_signed(&instruction, &accounts, &[&[b"emitter", &[emitter_bump]]])?;
+    Ok(())
+}
+
+/// Check a VAA posted by the core bridge came from the registered emitter
+/// on `source_chain` and carries `payload`
+fn verify_posted_vaa(
+    posted_vaa: &AccountInfo,
+    emitter: &ForeignEmitterAccount,
+    source_chain: u16,
+    payload: &[u8],
+) -> Result<()> {
+    let vaa = wormhole::MessageData::from_account(
+        &posted_vaa.try_borrow_data()?,
+        wormhole::POSTED_VAA_MAGIC,
+    )
+    .ok_or(UntraceError::InvalidVaa)?;
+
+    require!(
+        vaa.emitter_chain == emitter.chain && vaa.emitter_address == emitter.address,
+        UntraceError::UnknownEmitter
+    );
+    require!(
+        wormhole::from_wormhole_chain_id(vaa.emitter_chain) == Some(source_chain),
+        UntraceError::InvalidVaa
+    );
+    require!(vaa.payload == payload, UntraceError::InvalidVaa);
+    Ok(())
+}
+
+/// Count `amount` against `dest_chain`'s outbound cap for this epoch, then
+/// move it from the sender into the bridge vault
+fn lock_outbound_amount<'info>(
+    bridge_config: &mut BridgeConfigAccount,
+    sender: &AccountInfo<'info>,
+    vault: &AccountInfo<'info>,
+    system: &AccountInfo<'info>,
+    dest_chain: u16,
+    amount: u64,
+) -> Result<()> {
+    // The count resets each epoch
+    let epoch = Clock::get()?.epoch;
+    let limit = bridge_config
+        .limits
+        .get_mut(dest_chain as usize)
+        .ok_or(UntraceError::InvalidInstruction)?;
+    require!(
+        limit.record(epoch, amount),
+        UntraceError::BridgeRateLimitExceeded
+    );
+
+    system_program::transfer(
+        CpiContext::new(
+            system.clone(),
+            system_program::Transfer {
+                from: sender.clone(),
+                to: vault.clone(),
+            },
+        ),
+        amount,
+    )
+}
+
+/// Call the LayerZero endpoint as the program's OApp, signing as `config`.
+/// `accounts` are the endpoint instruction's, in its order.
+fn invoke_endpoint<'info>(
+    config: &AccountInfo<'info>,
+    config_bump: u8,
+    endpoint: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: endpoint.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer || account.key() == config.key(),
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut infos = accounts.to_vec();
+    infos.push(endpoint.clone());
+    invoke_signed(&instruction, &infos, &[&[b"layerzero_config", &[config_bump]]])?;
+    Ok(())
+}
+
+/// Pay out an attested inbound transfer from the bridge vault, keeping the
+/// vault rent exempt, and record its message as consumed
+fn release_bridge_transfer(
+    vault: &AccountInfo,
+    recipient: &AccountInfo,
+    consumed_message: &mut Account<ConsumedMessageAccount>,
+    bump: u8,
+    message: &BridgeMessage,
+) -> Result<()> {
+    pay_from_bridge_vault(vault, recipient, message.amount)?;
+
+    consumed_message.version = ACCOUNT_VERSION;
+    consumed_message.digest = message.digest();
+    consumed_message.consumed_at = Clock::get()?.unix_timestamp;
+    consumed_message.bump = bump;
+    Ok(())
+}
+
+/// Move `amount` lamports out of the bridge vault, keeping it rent exempt
+fn pay_from_bridge_vault(vault: &AccountInfo, recipient: &AccountInfo, amount: u64) -> Result<()> {
+    let rent_floor = Rent::get()?.minimum_balance(BridgeVaultAccount::LEN);
+    let available = vault.lamports().saturating_sub(rent_floor);
+    require!(
+        available >= amount,
+        UntraceError::InsufficientBridgeLiquidity
+    );
+
+    **vault.try_borrow_mut_lamports()? -= amount;
+    **recipient.try_borrow_mut_lamports()? += amount;
+    Ok(())
 }
@@ -1,8 +1,68 @@
 use anchor_lang::prelude::*;
-use untrace_common::PrivacyLevel;
+use untrace_common::{
+    crypto,
+    groth16::{Circuit, VerifyingKey},
+    AdminAction, BridgeProtocol, PrivacyLevel, UntraceError,
+};
+
+/// Layout version written into every account created by this program
+pub const ACCOUNT_VERSION: u8 = 1;
+
+/// Depth of each pool's commitment tree (about one million leaves)
+pub const MERKLE_TREE_DEPTH: usize = 20;
+
+/// Number of recent roots a withdrawal proof may be built against
+pub const ROOT_HISTORY_SIZE: usize = 32;
+
+/// Shortest delay admin actions may be queued with (one day)
+pub const MIN_TIMELOCK_DELAY: i64 = 24 * 60 * 60;
+
+/// Longest delay admin actions may be queued with (thirty days), so a
+/// misconfigured delay cannot lock the admin out
+pub const MAX_TIMELOCK_DELAY: i64 = 30 * 24 * 60 * 60;
+
+/// How long an outbound bridge transfer stays claimable before its sender
+/// may refund it (seven days). The refund also needs the guardian set to
+/// attest the destination expired the message unredeemed, so a transfer is
+/// never both claimed and refunded.
+pub const BRIDGE_REFUND_TIMEOUT: i64 = 7 * 24 * 60 * 60;
+
+/// Input and output notes per shielded transfer; unused slots carry
+/// zero-value dummy notes
+pub const SHIELDED_TRANSFER_NOTES: usize = 2;
+
+/// `pool_id` of the shielded pool's commitment tree, which no privacy pool
+/// can take
+pub const SHIELDED_TREE_ID: u64 = u64::MAX;
+
+/// Largest encrypted amount, encrypted recipient and proof a private
+/// transfer may store
+pub const MAX_PRIVATE_TRANSFER_FIELD_LEN: usize = 256;
+
+/// Depth of a compressed pool's concurrent tree (about 16 million leaves)
+pub const COMPRESSED_TREE_DEPTH: u32 = 24;
+
+/// Changelog buffer of a compressed pool's tree, i.e. how many recent roots
+/// a withdrawal proof may be built against
+pub const COMPRESSED_TREE_BUFFER_SIZE: u32 = 64;
+
+/// Most shielded nullifiers one outbound nullifier root may cover
+pub const MAX_NULLIFIER_BATCH: usize = 16;
+
+/// Bridge message chain IDs the bridge config holds outbound limits for
+pub const MAX_BRIDGE_CHAINS: usize = 16;
+
+/// Fewest slots between committing an order and revealing it, so an order
+/// is never revealed in the block it was committed in
+pub const MIN_ORDER_REVEAL_DELAY: u64 = 2;
+
+/// Fewest slots an intent is locked for, so it never executes in the block
+/// it was locked in
+pub const MIN_INTENT_LOCK_DELAY: u64 = 2;
 
 #[account]
 pub struct PrivacyPoolAccount {
+    pub version: u8,
     pub pool_id: u64,
     pub commitment_root: [u8; 32],
     pub commitment_count: u64,
@@ -12,14 +72,17 @@ pub struct PrivacyPoolAccount {
 
 #[account]
 pub struct CommitmentAccount {
+    pub version: u8,
     pub commitment: [u8; 32],
     pub nullifier: [u8; 32],
     pub timestamp: i64,
     pub pool_id: u64,
+    pub bump: u8,
 }
 
 #[account]
 pub struct NullifierAccount {
+    pub version: u8,
     pub nullifier: [u8; 32],
     pub is_used: bool,
     pub timestamp: i64,
@@ -27,16 +90,24 @@ pub struct NullifierAccount {
 
 #[account]
 pub struct PrivateTransferAccount {
+    pub version: u8,
     pub encrypted_amount: Vec<u8>,
     pub encrypted_recipient: Vec<u8>,
     pub zk_proof: Vec<u8>,
     pub privacy_level: PrivacyLevel,
     pub sender: Pubkey,
     pub timestamp: i64,
+    /// Asset moved between the shielded notes below
+    pub asset_id: Pubkey,
+    /// Nullifiers of the spent input notes
+    pub nullifiers: [[u8; 32]; SHIELDED_TRANSFER_NOTES],
+    /// Commitments of the created output notes
+    pub output_commitments: [[u8; 32]; SHIELDED_TRANSFER_NOTES],
 }
 
 #[account]
 pub struct CrossChainBridgeAccount {
+    pub version: u8,
     pub source_chain: u16,
     pub dest_chain: u16,
     pub encrypted_data: Vec<u8>,
@@ -48,8 +119,373 @@ pub struct CrossChainBridgeAccount {
     pub status: u8, // 0=pending, 1=completed, 2=failed
 }
 
+#[account]
+pub struct GuardianSetAccount {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub guardians: Vec<Pubkey>,
+    pub quorum: u8,
+    pub bump: u8,
+}
+
+/// Groth16 verifying key proofs of `circuit` are checked against
+#[account]
+pub struct VerifierAccount {
+    pub version: u8,
+    pub circuit: Circuit,
+    pub key: VerifyingKey,
+    pub bump: u8,
+}
+
+#[account]
+pub struct BridgeVaultAccount {
+    pub version: u8,
+    pub bump: u8,
+}
+
+#[account]
+pub struct ConsumedMessageAccount {
+    pub version: u8,
+    pub digest: [u8; 32],
+    pub consumed_at: i64,
+    pub bump: u8,
+}
+
+/// Outbound transfer locked in the bridge vault by `post_bridge_message`,
+/// `post_layerzero_message` or `post_guardian_message`
+#[account]
+pub struct OutboundTransferAccount {
+    pub version: u8,
+    pub sender: Pubkey,
+    /// Core bridge message account carrying the transfer, or the key
+    /// naming a LayerZero or guardian transfer
+    pub message: Pubkey,
+    pub dest_chain: u16,
+    pub sequence: u64,
+    pub amount: u64,
+    pub posted_at: i64,
+    /// When the sender took the transfer back; 0 while it may be claimed
+    pub refunded_at: i64,
+    pub protocol: BridgeProtocol,
+    pub bump: u8,
+}
+
+/// Registry entry tying the wrapped mint at `mint` to the asset it
+/// represents. Signs as the mint's authority.
+#[account]
+pub struct WrappedMintAccount {
+    pub version: u8,
+    pub origin_chain: u16,
+    /// Token address on the origin chain, left-padded to 32 bytes
+    pub origin_asset: [u8; 32],
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Wrapped tokens minted and not burned yet, which the origin chain
+    /// holds locked
+    pub outstanding: u64,
+    pub bump: u8,
+}
+
+/// Numbers the outbound transfers left for the guardian set
+#[account]
+pub struct GuardianOutboxAccount {
+    pub version: u8,
+    /// Sequence of the next guardian message
+    pub next_sequence: u64,
+    pub bump: u8,
+}
+
+/// Numbers the nullifier roots this chain posts to the others
+#[account]
+pub struct NullifierSyncAccount {
+    pub version: u8,
+    /// Batch number of the next root posted
+    pub next_batch: u64,
+    pub bump: u8,
+}
+
+/// Root of a batch of shielded nullifiers spent on another chain, received
+/// through Wormhole. Each nullifier proven against it is marked spent here.
+#[account]
+pub struct NullifierRootAccount {
+    pub version: u8,
+    /// Bridge message chain ID the nullifiers were spent on
+    pub source_chain: u16,
+    pub batch: u64,
+    pub nullifier_root: [u8; 32],
+    pub nullifier_count: u32,
+    pub received_at: i64,
+    pub bump: u8,
+}
+
+/// Arguments of `private_transfer`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PrivateTransferArgs {
+    /// Asset moved between the notes
+    pub asset_id: Pubkey,
+    /// Recent root of the shielded pool's tree the inputs are proven under
+    pub root: [u8; 32],
+    pub nullifiers: [[u8; 32]; SHIELDED_TRANSFER_NOTES],
+    pub output_commitments: [[u8; 32]; SHIELDED_TRANSFER_NOTES],
+    /// Amount and recipient, encrypted to the recipient
+    pub encrypted_amount: Vec<u8>,
+    pub encrypted_recipient: Vec<u8>,
+    pub zk_proof: Vec<u8>,
+    /// `PrivacyLevel` as 0 (basic), 1 (enhanced) or 2 (maximum)
+    pub privacy_level: u8,
+    /// One note ciphertext per output, for its owner to find by scanning
+    pub encrypted_notes: Vec<Vec<u8>>,
+}
+
+/// Outbound volume cap of one destination chain, and what was sent there
+/// in the epoch it was last used
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutboundLimit {
+    /// Most lamports bridged out per epoch; 0 disables the cap
+    pub epoch_cap: u64,
+    pub epoch: u64,
+    pub sent: u64,
+}
+
+impl OutboundLimit {
+    pub const LEN: usize = 8 + // epoch_cap
+        8 + // epoch
+        8; // sent
+
+    /// Lamports that may still be sent in `epoch`, or `None` without a cap
+    pub fn remaining(&self, epoch: u64) -> Option<u64> {
+        if self.epoch_cap == 0 {
+            return None;
+        }
+        let sent = if self.epoch == epoch { self.sent } else { 0 };
+        Some(self.epoch_cap.saturating_sub(sent))
+    }
+
+    /// Count `amount` as sent in `epoch`, unless it would exceed the cap
+    pub fn record(&mut self, epoch: u64, amount: u64) -> bool {
+        if self.epoch != epoch {
+            self.epoch = epoch;
+            self.sent = 0;
+        }
+        match self.sent.checked_add(amount) {
+            Some(sent) if self.epoch_cap == 0 || sent <= self.epoch_cap => {
+                self.sent = sent;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Per-epoch outbound bridge limits, indexed by bridge message chain ID.
+/// Caps change through a timelocked `SetBridgeLimit` action.
+#[account]
+pub struct BridgeConfigAccount {
+    pub version: u8,
+    pub limits: [OutboundLimit; MAX_BRIDGE_CHAINS],
+    pub bump: u8,
+}
+
+#[account]
+pub struct WormholeConfigAccount {
+    pub version: u8,
+    pub authority: Pubkey,
+    /// Wormhole core bridge program on this cluster
+    pub core_bridge: Pubkey,
+    pub bump: u8,
+    /// Bump of the PDA that emits outbound messages
+    pub emitter_bump: u8,
+}
+
+/// The program's LayerZero settings. The account is the OApp registered
+/// with the endpoint, and signs what the program sends and clears there.
+#[account]
+pub struct LayerZeroConfigAccount {
+    pub version: u8,
+    pub authority: Pubkey,
+    /// LayerZero endpoint program on this cluster
+    pub endpoint: Pubkey,
+    /// Sequence of the next outbound bridge message
+    pub next_sequence: u64,
+    pub bump: u8,
+}
+
+/// OApp trusted to send bridge messages from one LayerZero endpoint ID
+#[account]
+pub struct LayerZeroPeerAccount {
+    pub version: u8,
+    pub eid: u32,
+    pub address: [u8; 32],
+    pub bump: u8,
+}
+
+#[account]
+pub struct ForeignEmitterAccount {
+    pub version: u8,
+    /// Wormhole chain ID of the emitter
+    pub chain: u16,
+    pub address: [u8; 32],
+    pub bump: u8,
+}
+
+#[account]
+pub struct ShieldedPoolAccount {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub commitment_root: [u8; 32],
+    pub commitment_count: u64,
+    pub min_pool_size: u64,
+    pub asset_count: u32,
+    pub bump: u8,
+}
+
+#[account]
+pub struct ShieldedAssetAccount {
+    pub version: u8,
+    pub asset_id: Pubkey,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct ShieldedCommitmentAccount {
+    pub version: u8,
+    pub commitment: [u8; 32],
+    pub leaf_index: u64,
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+/// Protocol admin state.
+///
+/// The guardian can pause instantly; everything else the authority does goes
+/// through a `TimelockAccount` so users can exit before it takes effect.
+#[account]
+pub struct AdminConfigAccount {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub guardian: Pubkey,
+    /// Blocks deposits, transfers and bridge claims; withdrawals stay open
+    pub paused: bool,
+    pub timelock_delay: i64,
+    pub bump: u8,
+}
+
+/// Admin action waiting out its delay
+#[account]
+pub struct TimelockAccount {
+    pub version: u8,
+    pub action_id: u64,
+    pub action: AdminAction,
+    pub proposer: Pubkey,
+    pub queued_at: i64,
+    /// Earliest time the action may execute
+    pub eta: i64,
+    pub bump: u8,
+}
+
+/// Deposit limits of a privacy pool; a zero value disables that limit
+#[account]
+pub struct PoolConfigAccount {
+    pub version: u8,
+    pub pool_id: u64,
+    pub min_deposit: u64,
+    pub max_deposit: u64,
+    /// Most a single address may deposit per epoch
+    pub epoch_deposit_cap: u64,
+    pub bump: u8,
+}
+
+/// Running total of one address's deposits into a pool in the current epoch
+#[account]
+pub struct DepositTrackerAccount {
+    pub version: u8,
+    pub depositor: Pubkey,
+    pub epoch: u64,
+    pub deposited: u64,
+    pub bump: u8,
+}
+
+/// Balance of a swap's output account before the swap, checked against the
+/// owner's minimum once the swap has run
+#[account]
+pub struct SwapGuardAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub token_account: Pubkey,
+    pub start_balance: u64,
+    pub min_out: u64,
+    pub bump: u8,
+}
+
+/// Hash of a private order's payload, committed before the payload is
+/// known and checked against it at the reveal
+#[account]
+pub struct OrderCommitmentAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub payload_hash: [u8; 32],
+    pub commit_slot: u64,
+    /// First slot the payload is accepted at
+    pub reveal_slot: u64,
+    /// First slot it no longer is
+    pub expires_at: u64,
+    pub bump: u8,
+}
+
+/// Hash of an instruction its owner locked until `unlock_slot`, which
+/// `execute_intent` only runs from then on, signed by the intent's vault:
+/// a system account at `[b"intent_vault", intent]` only this program signs
+/// for.
+#[account]
+pub struct TimeLockedIntentAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub intent_id: u64,
+    pub intent_hash: [u8; 32],
+    pub lock_slot: u64,
+    pub unlock_slot: u64,
+    pub bump: u8,
+    pub vault_bump: u8,
+}
+
+/// Privacy pool whose commitments live as leaves of an spl-account-compression
+/// tree instead of individual accounts
+#[account]
+pub struct CompressedPoolAccount {
+    pub version: u8,
+    pub pool_id: u64,
+    pub merkle_tree: Pubkey,
+    pub commitment_count: u64,
+    pub min_pool_size: u64,
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+/// Incremental commitment tree of a privacy pool.
+///
+/// Zero-copy so withdrawals can check a root without deserializing the
+/// whole account.
+#[account(zero_copy)]
+pub struct MerkleTreeAccount {
+    pub pool_id: u64,
+    pub next_index: u64,
+    pub current_root_index: u64,
+    /// Hashes of empty subtrees per level, computed once at initialization
+    pub zeros: [[u8; 32]; MERKLE_TREE_DEPTH],
+    /// Rightmost filled node per level
+    pub filled_subtrees: [[u8; 32]; MERKLE_TREE_DEPTH],
+    pub roots: [[u8; 32]; ROOT_HISTORY_SIZE],
+    pub version: u8,
+    pub bump: u8,
+    pub _padding: [u8; 6],
+}
+
 impl PrivacyPoolAccount {
     pub const LEN: usize = 8 + // discriminator
+        1 + // version
         8 + // pool_id
         32 + // commitment_root
         8 + // commitment_count
@@ -59,15 +495,364 @@ impl PrivacyPoolAccount {
 
 impl CommitmentAccount {
     pub const LEN: usize = 8 + // discriminator
+        1 + // version
         32 + // commitment
         32 + // nullifier
         8 + // timestamp
-        8; // pool_id
+        8 + // pool_id
+        1; // bump
 }
 
 impl NullifierAccount {
     pub const LEN: usize = 8 + // discriminator
+        1 + // version
         32 + // nullifier
         1 + // is_used
         8; // timestamp
 }
+
+impl GuardianSetAccount {
+    pub const MAX_GUARDIANS: usize = untrace_common::MAX_GUARDIANS;
+
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // authority
+        4 + 32 * Self::MAX_GUARDIANS + // guardians
+        1 + // quorum
+        1; // bump
+}
+
+impl VerifierAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        1 + // circuit
+        VerifyingKey::LEN + // key
+        1; // bump
+}
+
+impl BridgeVaultAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        1; // bump
+}
+
+impl ConsumedMessageAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // digest
+        8 + // consumed_at
+        1; // bump
+}
+
+impl OutboundTransferAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // sender
+        32 + // message
+        2 + // dest_chain
+        8 + // sequence
+        8 + // amount
+        8 + // posted_at
+        8 + // refunded_at
+        1 + // protocol
+        1; // bump
+}
+
+impl WrappedMintAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        2 + // origin_chain
+        32 + // origin_asset
+        32 + // mint
+        1 + // decimals
+        8 + // outstanding
+        1; // bump
+}
+
+impl GuardianOutboxAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        8 + // next_sequence
+        1; // bump
+}
+
+impl NullifierSyncAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        8 + // next_batch
+        1; // bump
+}
+
+impl NullifierRootAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        2 + // source_chain
+        8 + // batch
+        32 + // nullifier_root
+        4 + // nullifier_count
+        8 + // received_at
+        1; // bump
+}
+
+impl BridgeConfigAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        OutboundLimit::LEN * MAX_BRIDGE_CHAINS + // limits
+        1; // bump
+}
+
+impl WormholeConfigAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // authority
+        32 + // core_bridge
+        1 + // bump
+        1; // emitter_bump
+}
+
+impl LayerZeroConfigAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // authority
+        32 + // endpoint
+        8 + // next_sequence
+        1; // bump
+}
+
+impl LayerZeroPeerAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        4 + // eid
+        32 + // address
+        1; // bump
+}
+
+impl ForeignEmitterAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        2 + // chain
+        32 + // address
+        1; // bump
+}
+
+impl ShieldedPoolAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // authority
+        32 + // commitment_root
+        8 + // commitment_count
+        8 + // min_pool_size
+        4 + // asset_count
+        1; // bump
+}
+
+impl ShieldedPoolAccount {
+    /// Insert a new note commitment into the pool's `tree`, returning its
+    /// leaf index
+    pub fn append_commitment(
+        &mut self,
+        tree: &mut MerkleTreeAccount,
+        commitment: [u8; 32],
+    ) -> Result<u64> {
+        let (leaf_index, root) = tree.insert(commitment)?;
+        self.commitment_count += 1;
+        self.commitment_root = root;
+        Ok(leaf_index)
+    }
+}
+
+impl PrivateTransferAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        4 + MAX_PRIVATE_TRANSFER_FIELD_LEN + // encrypted_amount
+        4 + MAX_PRIVATE_TRANSFER_FIELD_LEN + // encrypted_recipient
+        4 + MAX_PRIVATE_TRANSFER_FIELD_LEN + // zk_proof
+        1 + // privacy_level
+        32 + // sender
+        8 + // timestamp
+        32 + // asset_id
+        32 * SHIELDED_TRANSFER_NOTES + // nullifiers
+        32 * SHIELDED_TRANSFER_NOTES; // output_commitments
+}
+
+impl ShieldedAssetAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // asset_id
+        8 + // total_deposited
+        8 + // total_withdrawn
+        1; // bump
+}
+
+impl ShieldedCommitmentAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // commitment
+        8 + // leaf_index
+        8 + // timestamp
+        1; // bump
+}
+
+impl AdminConfigAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // authority
+        32 + // guardian
+        1 + // paused
+        8 + // timelock_delay
+        1; // bump
+}
+
+impl TimelockAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        8 + // action_id
+        AdminAction::MAX_LEN + // action
+        32 + // proposer
+        8 + // queued_at
+        8 + // eta
+        1; // bump
+}
+
+impl PoolConfigAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        8 + // pool_id
+        8 + // min_deposit
+        8 + // max_deposit
+        8 + // epoch_deposit_cap
+        1; // bump
+}
+
+impl DepositTrackerAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // depositor
+        8 + // epoch
+        8 + // deposited
+        1; // bump
+}
+
+impl SwapGuardAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // owner
+        32 + // token_account
+        8 + // start_balance
+        8 + // min_out
+        1; // bump
+}
+
+impl OrderCommitmentAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // owner
+        8 + // order_id
+        32 + // payload_hash
+        8 + // commit_slot
+        8 + // reveal_slot
+        8 + // expires_at
+        1; // bump
+}
+
+impl TimeLockedIntentAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        32 + // owner
+        8 + // intent_id
+        32 + // intent_hash
+        8 + // lock_slot
+        8 + // unlock_slot
+        1 + // bump
+        1; // vault_bump
+}
+
+impl CompressedPoolAccount {
+    pub const LEN: usize = 8 + // discriminator
+        1 + // version
+        8 + // pool_id
+        32 + // merkle_tree
+        8 + // commitment_count
+        8 + // min_pool_size
+        32 + // authority
+        1; // bump
+}
+
+impl MerkleTreeAccount {
+    pub const LEN: usize = 8 + std::mem::size_of::<MerkleTreeAccount>();
+
+    /// Set up an empty tree
+    pub fn initialize(&mut self, pool_id: u64, bump: u8) {
+        self.pool_id = pool_id;
+        self.version = ACCOUNT_VERSION;
+        self.bump = bump;
+
+        let mut zero = [0u8; 32];
+        for level in 0..MERKLE_TREE_DEPTH {
+            self.zeros[level] = zero;
+            self.filled_subtrees[level] = zero;
+            zero = crypto::merkle_hash_pair(&zero, &zero);
+        }
+        self.roots[0] = zero;
+    }
+
+    /// Root after the latest insertion
+    pub fn current_root(&self) -> [u8; 32] {
+        self.roots[self.current_root_index as usize]
+    }
+
+    /// Append a leaf, returning its index and the new root
+    pub fn insert(&mut self, leaf: [u8; 32]) -> Result<(u64, [u8; 32])> {
+        let leaf_index = self.next_index;
+        require!(
+            leaf_index < (1u64 << MERKLE_TREE_DEPTH),
+            UntraceError::MerkleTreeFull
+        );
+
+        let mut current_index = leaf_index;
+        let mut current = leaf;
+        for level in 0..MERKLE_TREE_DEPTH {
+            current = if current_index & 1 == 0 {
+                self.filled_subtrees[level] = current;
+                crypto::merkle_hash_pair(&current, &self.zeros[level])
+            } else {
+                crypto::merkle_hash_pair(&self.filled_subtrees[level], &current)
+            };
+            current_index /= 2;
+        }
+
+        self.current_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE as u64;
+        self.roots[self.current_root_index as usize] = current;
+        self.next_index += 1;
+
+        Ok((leaf_index, current))
+    }
+
+    /// Whether `root` is one of the recent tree roots
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        *root != [0u8; 32] && self.roots.iter().any(|r| r == root)
+    }
+}
+
+/// Account layouts from before version fields were introduced
+pub mod legacy {
+    use super::*;
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    pub struct PrivacyPoolAccountV0 {
+        pub pool_id: u64,
+        pub commitment_root: [u8; 32],
+        pub commitment_count: u64,
+        pub min_pool_size: u64,
+        pub authority: Pubkey,
+    }
+
+    impl PrivacyPoolAccountV0 {
+        pub const LEN: usize = 8 + // discriminator
+            8 + // pool_id
+            32 + // commitment_root
+            8 + // commitment_count
+            8 + // min_pool_size
+            32; // authority
+    }
+}
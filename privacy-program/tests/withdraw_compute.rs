@@ -0,0 +1,240 @@
+//! Compute-unit benchmark for the withdraw path.
+//!
+//! Runs against the compiled program, so it only runs under `cargo test-sbf`;
+//! plain `cargo test` still builds it to keep it in step with the program.
+
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use untrace_common::{
+    crypto,
+    groth16::{self, Circuit, VerifyingKey, G1_GENERATOR, G2_GENERATOR},
+};
+use untrace_privacy_program::{
+    accounts, instruction,
+    state::{MerkleTreeAccount, MIN_TIMELOCK_DELAY},
+    ID,
+};
+
+/// Upper bound on CUs a withdraw may consume; keep well below the 200k
+/// default. The Groth16 pairing check takes about 73k of it.
+const WITHDRAW_COMPUTE_BUDGET: u64 = 140_000;
+
+const POOL_ID: u64 = 1;
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &ID).0
+}
+
+fn g1_point(scalar: u8) -> [u8; 64] {
+    let mut bytes = [0u8; 32];
+    bytes[31] = scalar;
+    groth16::g1_mul(&G1_GENERATOR, &bytes).unwrap()
+}
+
+/// Verifying key with every G2 term the generator, for which a proof of
+/// any statement can be built without a circuit
+fn test_verifying_key() -> VerifyingKey {
+    VerifyingKey {
+        alpha_g1: g1_point(3),
+        beta_g2: G2_GENERATOR,
+        gamma_g2: G2_GENERATOR,
+        delta_g2: G2_GENERATOR,
+        ic: [g1_point(5), g1_point(11)],
+    }
+}
+
+/// Proof of `inputs_hash` under `test_verifying_key`: C = A - alpha - vk_x
+fn test_proof(key: &VerifyingKey, inputs_hash: &[u8; 32]) -> Vec<u8> {
+    let term = groth16::g1_mul(&key.ic[1], &groth16::public_input(inputs_hash)).unwrap();
+    let vk_x = groth16::g1_add(&key.ic[0], &term).unwrap();
+    let a = g1_point(7);
+    let a_less_alpha = groth16::g1_add(&a, &groth16::g1_neg(&key.alpha_g1)).unwrap();
+    let c = groth16::g1_add(&a_less_alpha, &groth16::g1_neg(&vk_x)).unwrap();
+
+    let mut proof = Vec::with_capacity(groth16::PROOF_LEN);
+    proof.extend_from_slice(&a);
+    proof.extend_from_slice(&G2_GENERATOR);
+    proof.extend_from_slice(&c);
+    proof
+}
+
+async fn process(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: Hash,
+    instruction: Instruction,
+) {
+    let tx = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(tx).await.unwrap();
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "test-sbf"), ignore = "needs the SBF build, run with cargo test-sbf")]
+async fn test_withdraw_stays_within_compute_budget() {
+    let program_test = ProgramTest::new("untrace_privacy_program", ID, None);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let pool_id = POOL_ID.to_le_bytes();
+    let privacy_pool = pda(&[b"privacy_pool", &pool_id]);
+    let merkle_tree = pda(&[b"merkle_tree", &pool_id]);
+    let pool_config = pda(&[b"pool_config", &pool_id]);
+    let admin_config = pda(&[b"admin_config"]);
+
+    let initialize_admin = Instruction {
+        program_id: ID,
+        accounts: accounts::InitializeAdmin {
+            admin_config,
+            authority: payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::InitializeAdmin {
+            guardian: payer.pubkey(),
+            timelock_delay: MIN_TIMELOCK_DELAY,
+        }
+        .data(),
+    };
+    process(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        initialize_admin,
+    )
+    .await;
+
+    let key = test_verifying_key();
+    let verifier = pda(&[b"verifier", &[Circuit::Withdraw as u8]]);
+    let initialize_verifier = Instruction {
+        program_id: ID,
+        accounts: accounts::InitializeVerifier {
+            verifier,
+            admin_config,
+            authority: payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::InitializeVerifier {
+            circuit: Circuit::Withdraw,
+            key: key.clone(),
+        }
+        .data(),
+    };
+    process(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        initialize_verifier,
+    )
+    .await;
+
+    let initialize = Instruction {
+        program_id: ID,
+        accounts: accounts::InitializePool {
+            privacy_pool,
+            merkle_tree,
+            pool_config,
+            authority: payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::InitializePool {
+            pool_id: POOL_ID,
+            min_pool_size: 1,
+        }
+        .data(),
+    };
+    process(&mut banks_client, &payer, recent_blockhash, initialize).await;
+
+    let amount = 1_000_000;
+    let note_key = crypto::note_key(&payer.pubkey().to_bytes(), &[7u8; 32]);
+    let commitment = crypto::generate_commitment(amount, &note_key);
+    let deposit = Instruction {
+        program_id: ID,
+        accounts: accounts::Deposit {
+            privacy_pool,
+            commitment_account: pda(&[b"commitment", &pool_id, &commitment]),
+            merkle_tree,
+            pool_config,
+            deposit_tracker: pda(&[b"deposit_tracker", &pool_id, payer.pubkey().as_ref()]),
+            depositor: payer.pubkey(),
+            system_program: system_program::ID,
+            admin_config,
+            event_authority: pda(&[b"__event_authority"]),
+            program: ID,
+        }
+        .to_account_metas(None),
+        data: instruction::Deposit {
+            commitment,
+            amount,
+            note_key,
+            encrypted_data: vec![],
+        }
+        .data(),
+    };
+    process(&mut banks_client, &payer, recent_blockhash, deposit).await;
+
+    // Read the root the deposit produced, as a client would
+    let tree_account = banks_client
+        .get_account(merkle_tree)
+        .await
+        .unwrap()
+        .unwrap();
+    let tree =
+        bytemuck::from_bytes::<MerkleTreeAccount>(&tree_account.data[8..MerkleTreeAccount::LEN]);
+    let root = tree.roots[tree.current_root_index as usize];
+
+    let secret = [9u8; 32];
+    let nullifier = crypto::generate_nullifier(&secret, &commitment);
+    let recipient = Pubkey::new_unique();
+    let public_inputs = crypto::withdraw_inputs(&root, amount, &recipient.to_bytes(), &nullifier);
+    let withdraw = Instruction {
+        program_id: ID,
+        accounts: accounts::Withdraw {
+            privacy_pool,
+            merkle_tree,
+            nullifier_account: pda(&[b"nullifier", &pool_id, &nullifier]),
+            recipient,
+            withdrawer: payer.pubkey(),
+            system_program: system_program::ID,
+            verifier,
+        }
+        .to_account_metas(None),
+        data: instruction::Withdraw {
+            nullifier,
+            recipient,
+            amount,
+            zk_proof: test_proof(&key, &public_inputs),
+            root,
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[withdraw],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+
+    let simulation = banks_client.simulate_transaction(tx).await.unwrap();
+    assert!(matches!(simulation.result, Some(Ok(()))));
+
+    let units_consumed = simulation.simulation_details.unwrap().units_consumed;
+    assert!(
+        units_consumed <= WITHDRAW_COMPUTE_BUDGET,
+        "withdraw consumed {} CUs, budget is {}",
+        units_consumed,
+        WITHDRAW_COMPUTE_BUDGET
+    );
+}
@@ -1,14 +1,80 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use solana_sdk::pubkey::Pubkey;
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+use untrace_privacy_client::TransactionSigner;
+
+/// Change in a wallet adapter's connection, for its listeners
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterEvent {
+    Connected,
+    /// The user switched the wallet to another account
+    AccountChanged(Pubkey),
+    /// Disconnected, by the app or from the wallet's side
+    Disconnected,
+}
+
+/// Called with each event of the adapter it is registered with
+pub type AdapterListener = Box<dyn Fn(&AdapterEvent) + Send + Sync>;
+
+/// What a wallet behind an adapter can do beyond signing one legacy
+/// transaction, so callers can tell before asking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AdapterFeatures {
+    /// Signs several transactions behind one approval
+    /// (`signAllTransactions`); otherwise each is approved on its own
+    pub sign_all_transactions: bool,
+    /// Signs v0 transactions, which may load accounts from lookup tables
+    pub versioned_transactions: bool,
+    pub sign_message: bool,
+}
+
+/// Listeners of one adapter, which the adapter notifies as its connection
+/// changes
+#[derive(Default)]
+pub struct AdapterEvents {
+    listeners: Mutex<Vec<AdapterListener>>,
+}
+
+impl Debug for AdapterEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdapterEvents")
+            .field("listeners", &self.listeners.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl AdapterEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, listener: AdapterListener) {
+        self.listeners.lock().unwrap().push(listener);
+    }
+
+    /// Call every listener with `event`
+    pub fn emit(&self, event: AdapterEvent) {
+        for listener in self.listeners.lock().unwrap().iter() {
+            listener(&event);
+        }
+    }
+}
 
 /// Trait for wallet adapters (Phantom, Solflare, etc.)
+#[async_trait]
 pub trait WalletAdapter: Debug + Send + Sync {
+    /// What the wallet supports
+    fn features(&self) -> AdapterFeatures;
+
     /// Connect to the wallet
-    fn connect(&self) -> Result<()>;
+    async fn connect(&mut self) -> Result<()>;
 
     /// Disconnect from the wallet
-    fn disconnect(&self) -> Result<()>;
+    async fn disconnect(&mut self) -> Result<()>;
 
     /// Get the connected wallet's public key
     fn get_public_key(&self) -> Result<Pubkey>;
@@ -16,11 +82,59 @@ pub trait WalletAdapter: Debug + Send + Sync {
     /// Check if wallet is connected
     fn is_connected(&self) -> bool;
 
+    /// Call `listener` on each connection, account change and disconnect
+    fn on_event(&self, listener: AdapterListener);
+
     /// Sign a transaction
-    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>>;
+    async fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>>;
+
+    /// Sign several transactions, behind one approval if the wallet
+    /// supports `sign_all_transactions`, else one by one
+    async fn sign_all_transactions(&self, transactions: &[Vec<u8>]) -> Result<Vec<Vec<u8>>> {
+        let mut signed = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            signed.push(self.sign_transaction(transaction).await?);
+        }
+        Ok(signed)
+    }
 
     /// Sign a message
-    fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>>;
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Connected adapter signing as the privacy client's payer, which is
+/// handed every transaction the client sends
+#[derive(Debug, Clone)]
+pub struct AdapterSigner {
+    adapter: Arc<dyn WalletAdapter>,
+    pubkey: Pubkey,
+}
+
+impl AdapterSigner {
+    /// Sign as the account `adapter` is connected to now
+    pub fn new(adapter: Arc<dyn WalletAdapter>) -> Result<Self> {
+        let pubkey = adapter.get_public_key()?;
+        Ok(Self { adapter, pubkey })
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for AdapterSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    /// Fails once the wallet has switched accounts, as the transaction was
+    /// built for the account it was connected to
+    async fn sign_transaction(
+        &self,
+        transaction: Vec<u8>,
+    ) -> untrace_privacy_client::Result<Vec<u8>> {
+        if self.adapter.get_public_key().ok() != Some(self.pubkey) {
+            return Err(anyhow!("Wallet is no longer connected as {}", self.pubkey).into());
+        }
+        Ok(self.adapter.sign_transaction(&transaction).await?)
+    }
 }
 
 /// Phantom wallet adapter
@@ -28,6 +142,13 @@ pub trait WalletAdapter: Debug + Send + Sync {
 pub struct PhantomAdapter {
     connected: bool,
     public_key: Option<Pubkey>,
+    events: AdapterEvents,
+}
+
+impl Default for PhantomAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PhantomAdapter {
@@ -35,20 +156,35 @@ impl PhantomAdapter {
         Self {
             connected: false,
             public_key: None,
+            events: AdapterEvents::new(),
         }
     }
 }
 
+#[async_trait]
 impl WalletAdapter for PhantomAdapter {
-    fn connect(&self) -> Result<()> {
+    fn features(&self) -> AdapterFeatures {
+        AdapterFeatures {
+            sign_all_transactions: true,
+            versioned_transactions: true,
+            sign_message: true,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<()> {
         // In a real implementation, this would use browser APIs
         // to connect to the Phantom wallet extension
         println!("Connecting to Phantom wallet...");
+        self.connected = true;
+        self.events.emit(AdapterEvent::Connected);
         Ok(())
     }
 
-    fn disconnect(&self) -> Result<()> {
+    async fn disconnect(&mut self) -> Result<()> {
         println!("Disconnecting from Phantom wallet...");
+        self.connected = false;
+        self.public_key = None;
+        self.events.emit(AdapterEvent::Disconnected);
         Ok(())
     }
 
@@ -61,12 +197,16 @@ impl WalletAdapter for PhantomAdapter {
         self.connected
     }
 
-    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+    fn on_event(&self, listener: AdapterListener) {
+        self.events.subscribe(listener);
+    }
+
+    async fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
         // In production, this would call Phantom's sign API
         Ok(transaction.to_vec())
     }
 
-    fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
         // In production, this would call Phantom's sign message API
         Ok(message.to_vec())
     }
@@ -77,6 +217,13 @@ impl WalletAdapter for PhantomAdapter {
 pub struct SolflareAdapter {
     connected: bool,
     public_key: Option<Pubkey>,
+    events: AdapterEvents,
+}
+
+impl Default for SolflareAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SolflareAdapter {
@@ -84,18 +231,33 @@ impl SolflareAdapter {
         Self {
             connected: false,
             public_key: None,
+            events: AdapterEvents::new(),
         }
     }
 }
 
+#[async_trait]
 impl WalletAdapter for SolflareAdapter {
-    fn connect(&self) -> Result<()> {
+    fn features(&self) -> AdapterFeatures {
+        AdapterFeatures {
+            sign_all_transactions: true,
+            versioned_transactions: true,
+            sign_message: true,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<()> {
         println!("Connecting to Solflare wallet...");
+        self.connected = true;
+        self.events.emit(AdapterEvent::Connected);
         Ok(())
     }
 
-    fn disconnect(&self) -> Result<()> {
+    async fn disconnect(&mut self) -> Result<()> {
         println!("Disconnecting from Solflare wallet...");
+        self.connected = false;
+        self.public_key = None;
+        self.events.emit(AdapterEvent::Disconnected);
         Ok(())
     }
 
@@ -108,11 +270,15 @@ impl WalletAdapter for SolflareAdapter {
         self.connected
     }
 
-    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+    fn on_event(&self, listener: AdapterListener) {
+        self.events.subscribe(listener);
+    }
+
+    async fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
         Ok(transaction.to_vec())
     }
 
-    fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
         Ok(message.to_vec())
     }
 }
@@ -123,6 +289,7 @@ pub struct Web3Adapter {
     wallet_type: String,
     connected: bool,
     public_key: Option<Pubkey>,
+    events: AdapterEvents,
 }
 
 impl Web3Adapter {
@@ -131,18 +298,34 @@ impl Web3Adapter {
             wallet_type,
             connected: false,
             public_key: None,
+            events: AdapterEvents::new(),
         }
     }
 }
 
+#[async_trait]
 impl WalletAdapter for Web3Adapter {
-    fn connect(&self) -> Result<()> {
+    /// Only what every wallet offers, since the wallet behind it is not
+    /// known
+    fn features(&self) -> AdapterFeatures {
+        AdapterFeatures {
+            sign_message: true,
+            ..AdapterFeatures::default()
+        }
+    }
+
+    async fn connect(&mut self) -> Result<()> {
         println!("Connecting to {} wallet...", self.wallet_type);
+        self.connected = true;
+        self.events.emit(AdapterEvent::Connected);
         Ok(())
     }
 
-    fn disconnect(&self) -> Result<()> {
+    async fn disconnect(&mut self) -> Result<()> {
         println!("Disconnecting from {} wallet...", self.wallet_type);
+        self.connected = false;
+        self.public_key = None;
+        self.events.emit(AdapterEvent::Disconnected);
         Ok(())
     }
 
@@ -155,11 +338,48 @@ impl WalletAdapter for Web3Adapter {
         self.connected
     }
 
-    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+    fn on_event(&self, listener: AdapterListener) {
+        self.events.subscribe(listener);
+    }
+
+    async fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
         Ok(transaction.to_vec())
     }
 
-    fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
         Ok(message.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_adapter_connection_events() {
+        let mut adapter = PhantomAdapter::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let listener_seen = seen.clone();
+        adapter.on_event(Box::new(move |event| {
+            listener_seen.lock().unwrap().push(event.clone())
+        }));
+
+        adapter.connect().await.unwrap();
+        assert!(adapter.is_connected());
+        adapter.disconnect().await.unwrap();
+        assert!(!adapter.is_connected());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![AdapterEvent::Connected, AdapterEvent::Disconnected]
+        );
+
+        // Wallets without `signAllTransactions` still sign each one
+        let generic = Web3Adapter::new("Backpack".to_string());
+        assert!(!generic.features().sign_all_transactions);
+        let transactions = vec![vec![1u8], vec![2u8]];
+        assert_eq!(
+            generic.sign_all_transactions(&transactions).await.unwrap(),
+            transactions
+        );
+    }
+}
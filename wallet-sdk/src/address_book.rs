@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// What the user knows about an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressTag {
+    /// Known to the user, such as their own or a friend's
+    Trusted,
+    /// Deposit address at an exchange, which credits transparent transfers
+    /// only
+    Exchange,
+    /// Never to receive shielded funds
+    DoNotSendShielded,
+}
+
+/// Labeled address in the address book
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    /// Solana, EVM or Bitcoin address
+    pub address: String,
+    pub label: String,
+    pub tags: Vec<AddressTag>,
+}
+
+impl Contact {
+    pub fn has_tag(&self, tag: AddressTag) -> bool {
+        self.tags.contains(&tag)
+    }
+}
+
+/// Why shielded funds should not go to an address
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RecipientWarning {
+    /// An exchange would not see a shielded deposit, and un-shielding into
+    /// it links the funds to the account it belongs to
+    #[error("{label} is an exchange deposit address and cannot receive shielded funds")]
+    Exchange { label: String },
+
+    #[error("{label} is marked to never receive shielded funds")]
+    DoNotSendShielded { label: String },
+}
+
+/// Labeled addresses, kept encrypted in `SecureStorage`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBook {
+    /// Contacts by normalized address
+    contacts: BTreeMap<String, Contact>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `address` as `label`, or relabel it if already present,
+    /// keeping its tags
+    pub fn add(&mut self, address: &str, label: &str) -> Result<&mut Contact> {
+        let address = normalize_address(address)?;
+        let contact = self
+            .contacts
+            .entry(address.clone())
+            .or_insert_with(|| Contact {
+                address,
+                label: String::new(),
+                tags: Vec::new(),
+            });
+        contact.label = label.to_string();
+        Ok(contact)
+    }
+
+    /// Remove `address`, returning its contact if it was present
+    pub fn remove(&mut self, address: &str) -> Option<Contact> {
+        self.contacts.remove(&normalize_address(address).ok()?)
+    }
+
+    /// Contact of `address`, if present
+    pub fn get(&self, address: &str) -> Option<&Contact> {
+        self.contacts.get(&normalize_address(address).ok()?)
+    }
+
+    /// Contacts, by address
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.values()
+    }
+
+    /// Mark `address`, which must already be a contact, with `tag`
+    pub fn tag(&mut self, address: &str, tag: AddressTag) -> Result<()> {
+        let contact = self.get_mut(address)?;
+        if !contact.has_tag(tag) {
+            contact.tags.push(tag);
+        }
+        Ok(())
+    }
+
+    pub fn untag(&mut self, address: &str, tag: AddressTag) -> Result<()> {
+        self.get_mut(address)?.tags.retain(|t| *t != tag);
+        Ok(())
+    }
+
+    /// Why shielded funds should not be sent to `address`, if the user
+    /// tagged it. `Trusted` does not override the other tags.
+    pub fn shielded_send_warning(&self, address: &str) -> Option<RecipientWarning> {
+        let contact = self.get(address)?;
+        let label = contact.label.clone();
+        if contact.has_tag(AddressTag::DoNotSendShielded) {
+            Some(RecipientWarning::DoNotSendShielded { label })
+        } else if contact.has_tag(AddressTag::Exchange) {
+            Some(RecipientWarning::Exchange { label })
+        } else {
+            None
+        }
+    }
+
+    fn get_mut(&mut self, address: &str) -> Result<&mut Contact> {
+        self.contacts
+            .get_mut(&normalize_address(address)?)
+            .ok_or_else(|| anyhow!("{} is not in the address book", address))
+    }
+}
+
+/// `address` in the form contacts are keyed by. EVM addresses are hex, so
+/// their case is dropped; base58 and bech32 addresses are kept as given.
+pub(crate) fn normalize_address(address: &str) -> Result<String> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err(anyhow!("Address must not be empty"));
+    }
+    if address.starts_with("0x") || address.starts_with("0X") {
+        Ok(address.to_lowercase())
+    } else {
+        Ok(address.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exchange_addresses_warn() {
+        let mut book = AddressBook::new();
+        let exchange = "0xAbC0000000000000000000000000000000000001";
+        book.add(exchange, "Exchange deposit").unwrap();
+        book.add("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin", "Alice")
+            .unwrap();
+        assert_eq!(book.shielded_send_warning(exchange), None);
+
+        book.tag(&exchange.to_lowercase(), AddressTag::Exchange)
+            .unwrap();
+        book.tag(exchange, AddressTag::Trusted).unwrap();
+        assert_eq!(
+            book.shielded_send_warning(exchange),
+            Some(RecipientWarning::Exchange {
+                label: "Exchange deposit".to_string()
+            })
+        );
+        assert!(book.tag("unknown", AddressTag::Trusted).is_err());
+
+        // Relabeling keeps the tags
+        book.add(exchange, "Old exchange").unwrap();
+        assert_eq!(book.get(exchange).unwrap().tags.len(), 2);
+        book.untag(exchange, AddressTag::Exchange).unwrap();
+        assert_eq!(book.shielded_send_warning(exchange), None);
+
+        assert_eq!(book.remove(exchange).unwrap().label, "Old exchange");
+        assert_eq!(book.contacts().count(), 1);
+    }
+}
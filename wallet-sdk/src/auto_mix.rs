@@ -0,0 +1,248 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use untrace_privacy_client::PoolInfo;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits and pacing of the auto-mix scheduler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoMixConfig {
+    /// Lamports always left transparent, for fees and rent
+    pub reserve: u64,
+    /// Most lamports deposited per UTC day
+    pub daily_limit: u64,
+    /// Most deposits per UTC day
+    pub max_daily_deposits: u32,
+    /// Shortest wait before each deposit
+    pub min_delay: Duration,
+    /// Longest wait before each deposit
+    pub max_delay: Duration,
+}
+
+impl Default for AutoMixConfig {
+    /// 0.01 SOL reserve, at most 10 SOL in 10 deposits a day, each after
+    /// ten minutes to two hours
+    fn default() -> Self {
+        Self {
+            reserve: 10_000_000,
+            daily_limit: 10_000_000_000,
+            max_daily_deposits: 10,
+            min_delay: Duration::from_secs(10 * 60),
+            max_delay: Duration::from_secs(2 * 60 * 60),
+        }
+    }
+}
+
+impl AutoMixConfig {
+    /// Wait before the next deposit, uniform between the delays so
+    /// deposits cannot be matched to incoming funds by timing
+    pub fn random_delay(&self) -> Duration {
+        if self.max_delay <= self.min_delay {
+            return self.min_delay;
+        }
+        rand::thread_rng().gen_range(self.min_delay..=self.max_delay)
+    }
+}
+
+/// Deposits the scheduler made on one UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// Days since the Unix epoch
+    pub day: u64,
+    pub deposited: u64,
+    pub deposits: u32,
+}
+
+impl DailyUsage {
+    /// Lamports still allowed today at Unix time `now`, once the deposit
+    /// count allows another; `0` when either limit is reached
+    pub fn remaining(&self, config: &AutoMixConfig, now: u64) -> u64 {
+        if self.day != now / SECONDS_PER_DAY {
+            return config.daily_limit;
+        }
+        if self.deposits >= config.max_daily_deposits {
+            return 0;
+        }
+        config.daily_limit.saturating_sub(self.deposited)
+    }
+
+    /// Count a deposit of `amount` made at Unix time `now`
+    pub fn record(&mut self, amount: u64, now: u64) {
+        let day = now / SECONDS_PER_DAY;
+        if self.day != day {
+            *self = Self {
+                day,
+                ..Self::default()
+            };
+        }
+        self.deposited = self.deposited.saturating_add(amount);
+        self.deposits += 1;
+    }
+}
+
+/// Pool for the next deposit out of `available` lamports: the largest
+/// denomination that fits `available` and `remaining` among pools open to
+/// deposits with an anonymity set of at least `min_pool_size`, the most
+/// used pool on a tie. Pools without a fixed denomination are skipped, as
+/// an odd amount would stand out.
+pub fn choose_pool(
+    pools: &[PoolInfo],
+    available: u64,
+    remaining: u64,
+    min_pool_size: u64,
+) -> Option<&PoolInfo> {
+    let budget = available.min(remaining);
+    pools
+        .iter()
+        .filter(|pool| !pool.paused && pool.commitment_count >= min_pool_size)
+        .filter(|pool| {
+            pool.denomination
+                .is_some_and(|amount| amount > 0 && amount <= budget)
+        })
+        .max_by_key(|pool| (pool.denomination, pool.commitment_count))
+}
+
+/// Whether the scheduler is depositing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AutoMixState {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// What the scheduler has done, for display
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutoMixStatus {
+    pub today: DailyUsage,
+    /// Signature of the last deposit made
+    pub last_deposit: Option<String>,
+    /// Why the last attempt failed, cleared by the next deposit
+    pub last_error: Option<String>,
+}
+
+/// Control of a running auto-mix task. Dropping it stops the task once
+/// its current wait or deposit ends.
+#[derive(Debug)]
+pub struct AutoMixHandle {
+    pub(crate) state: watch::Sender<AutoMixState>,
+    pub(crate) status: Arc<Mutex<AutoMixStatus>>,
+    pub(crate) task: JoinHandle<()>,
+}
+
+impl AutoMixHandle {
+    /// Stop depositing until `resume`; a deposit already sent completes
+    pub fn pause(&self) {
+        self.state.send_replace(AutoMixState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.state.send_replace(AutoMixState::Running);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow() == AutoMixState::Paused
+    }
+
+    pub fn status(&self) -> AutoMixStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stop the task and wait for it to exit, after any deposit in flight
+    pub fn stop(self) {
+        self.state.send_replace(AutoMixState::Stopped);
+        self.task.join().ok();
+    }
+}
+
+/// Unix time now, in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Wait `delay` while the scheduler runs, or until it is paused or
+/// stopped. Returns whether the full delay passed while running.
+pub(crate) async fn wait_running(
+    state: &mut watch::Receiver<AutoMixState>,
+    delay: Duration,
+) -> bool {
+    // While paused, wait for the next change of state
+    while *state.borrow_and_update() == AutoMixState::Paused {
+        if state.changed().await.is_err() {
+            return false;
+        }
+    }
+    if is_stopped(state) {
+        return false;
+    }
+    let elapsed = tokio::select! {
+        _ = tokio::time::sleep(delay) => true,
+        // Any change restarts the wait, so a resume draws a new delay
+        _ = state.changed() => false,
+    };
+    elapsed && *state.borrow() == AutoMixState::Running
+}
+
+/// Whether the scheduler was stopped, or its handle dropped
+pub(crate) fn is_stopped(state: &watch::Receiver<AutoMixState>) -> bool {
+    state.has_changed().is_err() || *state.borrow() == AutoMixState::Stopped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use untrace_common::NATIVE_ASSET_ID;
+
+    fn pool(pool_id: u64, denomination: Option<u64>, commitment_count: u64) -> PoolInfo {
+        PoolInfo {
+            address: Pubkey::new_unique(),
+            pool_id,
+            denomination,
+            asset: NATIVE_ASSET_ID,
+            commitment_count,
+            min_pool_size: 0,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn test_choose_pool() {
+        let pools = vec![
+            pool(0, Some(100), 50),
+            pool(1, Some(10), 50),
+            pool(2, Some(10), 80),
+            pool(3, None, 500),
+            pool(4, Some(1_000), 2),
+        ];
+        assert_eq!(choose_pool(&pools, 5_000, 5_000, 10).unwrap().pool_id, 0);
+        // The daily limit caps the denomination, and ties go to the most used
+        assert_eq!(choose_pool(&pools, 5_000, 50, 10).unwrap().pool_id, 2);
+        assert_eq!(choose_pool(&pools, 5_000, 5_000, 0).unwrap().pool_id, 4);
+        assert!(choose_pool(&pools, 9, 5_000, 10).is_none());
+    }
+
+    #[test]
+    fn test_daily_usage_resets_each_day() {
+        let config = AutoMixConfig {
+            daily_limit: 100,
+            max_daily_deposits: 2,
+            ..AutoMixConfig::default()
+        };
+        let mut usage = DailyUsage::default();
+        let now = 3 * SECONDS_PER_DAY + 5;
+        usage.record(60, now);
+        assert_eq!(usage.remaining(&config, now), 40);
+        usage.record(10, now + 1);
+        assert_eq!(usage.remaining(&config, now + 1), 0);
+        assert_eq!(usage.remaining(&config, now + SECONDS_PER_DAY), 100);
+    }
+}
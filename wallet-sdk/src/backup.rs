@@ -0,0 +1,365 @@
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use untrace_common::ShieldedNote;
+use untrace_privacy_client::scanner::viewing_key;
+
+use crate::{
+    address_book::AddressBook,
+    history::Activity,
+    keystore::{open_keystore, seal_keystore, KdfParams, Keystore, KeystoreError},
+    mnemonic::HdSeed,
+    storage::DepositNote,
+};
+
+/// Version of the backup archive `encrypt_backup` writes
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Marks a JSON document as a wallet backup archive
+const BACKUP_FORMAT: &str = "untrace-wallet-backup";
+
+/// Keys every account of a backed-up wallet comes from
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BackupKeys {
+    /// Hex BIP39 seed, for wallets with a recovery phrase
+    Seed(String),
+    /// Hex keypair of a wallet's single imported account
+    Keypair(String),
+}
+
+/// One account's shielded funds as kept in a backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountBackup {
+    pub account: u32,
+    pub public_key: Pubkey,
+    /// Hex key the account's incoming notes are found with, for
+    /// watch-only scanning
+    pub viewing_key: String,
+    /// Unspent notes
+    #[serde(with = "borsh_hex")]
+    pub notes: Vec<ShieldedNote>,
+    pub deposits: Vec<DepositNote>,
+}
+
+/// Everything a wallet keeps, as sealed in a backup archive
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletBackup {
+    /// Unix time the backup was made
+    pub created_at: i64,
+    keys: BackupKeys,
+    /// Recovery phrase as the wallet stores it, still encrypted with the
+    /// wallet's password
+    pub encrypted_recovery_phrase: Option<String>,
+    pub accounts: Vec<AccountBackup>,
+    pub address_book: AddressBook,
+    /// History entries, oldest first
+    pub history: Vec<Activity>,
+}
+
+impl fmt::Debug for WalletBackup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WalletBackup")
+            .field("created_at", &self.created_at)
+            .field("accounts", &self.accounts)
+            .field("history", &self.history.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WalletBackup {
+    /// Backup of the keys of a wallet with a recovery phrase, or else of
+    /// the single account of `keypair`, holding nothing else yet
+    pub(crate) fn new(seed: Option<&HdSeed>, keypair: &Keypair) -> Self {
+        let keys = match seed {
+            Some(seed) => BackupKeys::Seed(hex::encode(seed.to_bytes())),
+            None => BackupKeys::Keypair(hex::encode(keypair.to_bytes())),
+        };
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            created_at,
+            keys,
+            encrypted_recovery_phrase: None,
+            accounts: Vec::new(),
+            address_book: AddressBook::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// First account of the backed-up wallet, which names its storage
+    pub fn public_key(&self) -> Result<Pubkey> {
+        Ok(self.keypair(0)?.pubkey())
+    }
+
+    /// Seed the accounts derive from, for wallets with a recovery phrase
+    pub(crate) fn seed(&self) -> Result<Option<HdSeed>> {
+        match &self.keys {
+            BackupKeys::Seed(seed) => {
+                let bytes = decode_key(seed)?;
+                let seed = bytes
+                    .try_into()
+                    .map_err(|_| KeystoreError::Malformed("seed length".to_string()))?;
+                Ok(Some(HdSeed::from_bytes(seed)))
+            }
+            BackupKeys::Keypair(_) => Ok(None),
+        }
+    }
+
+    /// Keypair of `account`
+    pub(crate) fn keypair(&self, account: u32) -> Result<Keypair> {
+        match (&self.keys, self.seed()?) {
+            (_, Some(seed)) => seed.derive_account(account),
+            (BackupKeys::Keypair(keypair), None) if account == 0 => {
+                Keypair::from_bytes(&decode_key(keypair)?)
+                    .map_err(|_| KeystoreError::Malformed("keypair".to_string()).into())
+            }
+            _ => Err(KeystoreError::Malformed(format!(
+                "account {} of a wallet without a recovery phrase",
+                account
+            ))
+            .into()),
+        }
+    }
+
+    /// Add `account` with its unspent `notes` and `deposits`
+    pub(crate) fn add_account(
+        &mut self,
+        account: u32,
+        notes: Vec<ShieldedNote>,
+        deposits: Vec<DepositNote>,
+    ) -> Result<()> {
+        let keypair = self.keypair(account)?;
+        self.accounts.push(AccountBackup {
+            account,
+            public_key: keypair.pubkey(),
+            viewing_key: hex::encode(viewing_key(&keypair)),
+            notes,
+            deposits,
+        });
+        Ok(())
+    }
+
+    /// Check each account's public and viewing keys are the ones its keys
+    /// derive, so a restore never files notes under the wrong account
+    fn check(&self) -> Result<()> {
+        let mut seen = HashSet::new();
+        for account in &self.accounts {
+            if !seen.insert(account.account) {
+                return Err(KeystoreError::Malformed(format!(
+                    "account {} appears twice",
+                    account.account
+                ))
+                .into());
+            }
+            let keypair = self.keypair(account.account)?;
+            if keypair.pubkey() != account.public_key
+                || hex::encode(viewing_key(&keypair)) != account.viewing_key
+            {
+                return Err(KeystoreError::Malformed(format!(
+                    "account {} does not match its keys",
+                    account.account
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parts of a backup `UntraceWallet::restore_backup` brings back besides
+/// the keys, which always are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Accounts whose notes, deposits and history are restored; `None` is
+    /// every one
+    pub accounts: Option<Vec<u32>>,
+    pub notes: bool,
+    pub deposits: bool,
+    pub address_book: bool,
+    pub history: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            accounts: None,
+            notes: true,
+            deposits: true,
+            address_book: true,
+            history: true,
+        }
+    }
+}
+
+impl RestoreOptions {
+    pub fn includes(&self, account: u32) -> bool {
+        self.accounts
+            .as_ref()
+            .is_none_or(|accounts| accounts.contains(&account))
+    }
+}
+
+/// Backup archive as stored: a keystore sealing the backup's JSON, whose
+/// AES-GCM tag fails decryption if any byte of the sealed backup changed
+#[derive(Serialize, Deserialize)]
+struct BackupArchive {
+    format: String,
+    version: u32,
+    keystore: Keystore,
+}
+
+/// Encrypt `backup` under `password` with the default KDF cost
+pub fn encrypt_backup(backup: &WalletBackup, password: &str) -> Result<String> {
+    encrypt_backup_with(backup, password, KdfParams::default())
+}
+
+/// Encrypt `backup` under `password` as a versioned archive, sealed as
+/// `encrypt_keystore_with` seals a secret
+pub fn encrypt_backup_with(
+    backup: &WalletBackup,
+    password: &str,
+    params: KdfParams,
+) -> Result<String> {
+    let archive = BackupArchive {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        keystore: seal_keystore(&serde_json::to_vec(backup)?, password, params)?,
+    };
+    Ok(serde_json::to_string(&archive)?)
+}
+
+/// Decrypt an archive from `encrypt_backup` and check its accounts against
+/// its keys. A wrong password or a tampered archive fails with
+/// `KeystoreError::WrongPassword`.
+pub fn decrypt_backup(archive: &str, password: &str) -> Result<WalletBackup> {
+    let archive: BackupArchive =
+        serde_json::from_str(archive).map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+    if archive.format != BACKUP_FORMAT {
+        return Err(KeystoreError::Malformed(format!("not a backup: {}", archive.format)).into());
+    }
+    if archive.version != BACKUP_VERSION {
+        return Err(KeystoreError::UnsupportedVersion(archive.version).into());
+    }
+
+    let contents = open_keystore(&archive.keystore, password)?;
+    let backup: WalletBackup =
+        serde_json::from_slice(&contents).map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+    backup.check()?;
+    Ok(backup)
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(key).map_err(|_| KeystoreError::Malformed("key is not hex".to_string()))
+}
+
+/// Notes as hex of their Borsh encoding, as `SecureStorage` keeps them
+mod borsh_hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        notes: &[ShieldedNote],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = notes.try_to_vec().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<ShieldedNote>, D::Error> {
+        let bytes = hex::decode(<String as Deserialize>::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)?;
+        Vec::<ShieldedNote>::try_from_slice(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::ActivityKind;
+    use untrace_common::NATIVE_ASSET_ID;
+
+    const PARAMS: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    fn backup() -> WalletBackup {
+        let seed = HdSeed::from_bytes([3u8; 64]);
+        let mut backup = WalletBackup::new(Some(&seed), &Keypair::new());
+        let note = ShieldedNote {
+            asset_id: NATIVE_ASSET_ID,
+            amount: 5,
+            owner: seed.derive_account(1).unwrap().pubkey(),
+            randomness: [7u8; 32],
+        };
+        backup.add_account(0, Vec::new(), Vec::new()).unwrap();
+        backup.add_account(1, vec![note], Vec::new()).unwrap();
+        backup.history.push(Activity::new(
+            1,
+            ActivityKind::Deposit,
+            "sig".to_string(),
+            5,
+        ));
+        backup
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let backup = backup();
+        let archive = encrypt_backup_with(&backup, "hunter2", PARAMS).unwrap();
+        assert!(!archive.contains(&backup.accounts[1].viewing_key));
+
+        let restored = decrypt_backup(&archive, "hunter2").unwrap();
+        assert_eq!(restored, backup);
+        assert_eq!(
+            restored.public_key().unwrap(),
+            backup.accounts[0].public_key
+        );
+
+        let err = decrypt_backup(&archive, "hunter3").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KeystoreError>(),
+            Some(&KeystoreError::WrongPassword)
+        );
+
+        let mut archive: serde_json::Value = serde_json::from_str(&archive).unwrap();
+        archive["version"] = 2.into();
+        let err = decrypt_backup(&archive.to_string(), "hunter2").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KeystoreError>(),
+            Some(&KeystoreError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn test_backup_rejects_mismatched_accounts() {
+        let mut backup = backup();
+        backup.accounts[1].public_key = Pubkey::new_unique();
+        let archive = encrypt_backup_with(&backup, "hunter2", PARAMS).unwrap();
+        assert!(matches!(
+            decrypt_backup(&archive, "hunter2")
+                .unwrap_err()
+                .downcast_ref::<KeystoreError>(),
+            Some(KeystoreError::Malformed(_))
+        ));
+
+        // A keypair wallet has no accounts past the first
+        let mut single = WalletBackup::new(None, &Keypair::new());
+        single.add_account(0, Vec::new(), Vec::new()).unwrap();
+        assert!(single.add_account(1, Vec::new(), Vec::new()).is_err());
+    }
+}
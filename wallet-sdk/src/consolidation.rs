@@ -0,0 +1,216 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+use tokio::sync::watch;
+use untrace_common::ShieldedNote;
+
+use crate::{
+    auto_mix::AutoMixState,
+    note_selection::{SpendableNote, DEFAULT_LINK_WINDOW},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What counts as dust and how often the consolidation task may merge it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsolidationConfig {
+    /// Notes worth less than this, in the asset's base units, are merged
+    pub dust_threshold: u64,
+    /// Time the active account must have been without activity before a
+    /// merge, so merges are not timed next to its own transfers
+    pub idle_period: Duration,
+    /// Most merges per UTC day
+    pub max_daily_merges: u32,
+    /// Shortest wait before each merge
+    pub min_delay: Duration,
+    /// Longest wait before each merge
+    pub max_delay: Duration,
+    /// Seconds during which notes out of one transaction are not merged
+    /// together, as with `PrivacyMax`
+    pub link_window: i64,
+}
+
+impl Default for ConsolidationConfig {
+    /// Notes under 0.01 SOL or token units, at most 4 merges a day, each
+    /// after one to six hours and an hour without activity
+    fn default() -> Self {
+        Self {
+            dust_threshold: 10_000_000,
+            idle_period: Duration::from_secs(60 * 60),
+            max_daily_merges: 4,
+            min_delay: Duration::from_secs(60 * 60),
+            max_delay: Duration::from_secs(6 * 60 * 60),
+            link_window: DEFAULT_LINK_WINDOW,
+        }
+    }
+}
+
+impl ConsolidationConfig {
+    /// Wait before the next merge, uniform between the delays so merges
+    /// cannot be matched to the notes' arrival by timing
+    pub fn random_delay(&self) -> Duration {
+        if self.max_delay <= self.min_delay {
+            return self.min_delay;
+        }
+        rand::thread_rng().gen_range(self.min_delay..=self.max_delay)
+    }
+}
+
+/// Merges the consolidation task made on one UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeUsage {
+    /// Days since the Unix epoch
+    pub day: u64,
+    pub merges: u32,
+}
+
+impl MergeUsage {
+    /// Whether another merge is allowed today at Unix time `now`
+    pub fn allows(&self, config: &ConsolidationConfig, now: u64) -> bool {
+        self.day != now / SECONDS_PER_DAY || self.merges < config.max_daily_merges
+    }
+
+    /// Count a merge made at Unix time `now`
+    pub fn record(&mut self, now: u64) {
+        let day = now / SECONDS_PER_DAY;
+        if self.day != day {
+            *self = Self { day, merges: 0 };
+        }
+        self.merges += 1;
+    }
+}
+
+/// Two dust notes to merge at Unix time `now`: of the asset with the most
+/// dust, the two smallest that did not come out of one transaction within
+/// the link window. `None` when no asset has two such notes.
+pub fn choose_merge(
+    notes: &[SpendableNote],
+    config: &ConsolidationConfig,
+    now: i64,
+) -> Option<[ShieldedNote; 2]> {
+    let mut dust: HashMap<Pubkey, Vec<&SpendableNote>> = HashMap::new();
+    for note in notes {
+        if note.note.amount > 0 && note.note.amount < config.dust_threshold {
+            dust.entry(note.note.asset_id).or_default().push(note);
+        }
+    }
+
+    let mut assets: Vec<Vec<&SpendableNote>> = dust.into_values().collect();
+    assets.sort_by_key(|notes| std::cmp::Reverse(notes.len()));
+    assets.into_iter().find_map(|mut notes| {
+        notes.sort_by_key(|note| note.note.amount);
+        notes.iter().enumerate().find_map(|(i, first)| {
+            notes[i + 1..]
+                .iter()
+                .find(|second| !first.recently_linked(second, config.link_window, now))
+                .map(|second| [first.note.clone(), second.note.clone()])
+        })
+    })
+}
+
+/// What the consolidation task has done, for display
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsolidationStatus {
+    pub today: MergeUsage,
+    /// Signature of the last merge made
+    pub last_merge: Option<String>,
+    /// Why the last attempt failed, cleared by the next merge
+    pub last_error: Option<String>,
+}
+
+/// Control of a running consolidation task. Dropping it stops the task
+/// once its current wait or merge ends.
+#[derive(Debug)]
+pub struct ConsolidationHandle {
+    pub(crate) state: watch::Sender<AutoMixState>,
+    pub(crate) status: Arc<Mutex<ConsolidationStatus>>,
+    pub(crate) task: JoinHandle<()>,
+}
+
+impl ConsolidationHandle {
+    /// Stop merging until `resume`; a merge already sent completes
+    pub fn pause(&self) {
+        self.state.send_replace(AutoMixState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.state.send_replace(AutoMixState::Running);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow() == AutoMixState::Paused
+    }
+
+    pub fn status(&self) -> ConsolidationStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stop the task and wait for it to exit, after any merge in flight
+    pub fn stop(self) {
+        self.state.send_replace(AutoMixState::Stopped);
+        self.task.join().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::NoteOrigin;
+
+    fn note(asset_id: Pubkey, amount: u64, signature: &str) -> SpendableNote {
+        SpendableNote {
+            note: ShieldedNote {
+                asset_id,
+                amount,
+                owner: Pubkey::new_unique(),
+                randomness: [amount as u8; 32],
+            },
+            origin: Some(NoteOrigin {
+                signature: signature.to_string(),
+                received_at: 100,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_choose_merge() {
+        let (sol, token) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = ConsolidationConfig {
+            dust_threshold: 100,
+            link_window: 1_000,
+            ..ConsolidationConfig::default()
+        };
+        let notes = vec![
+            note(sol, 50, "a"),
+            note(sol, 500, "b"),
+            note(token, 10, "c"),
+            note(token, 20, "c"),
+            note(token, 30, "d"),
+        ];
+        let amounts = |merge: Option<[ShieldedNote; 2]>| merge.map(|[a, b]| (a.amount, b.amount));
+
+        // The token has the most dust, and its two smallest notes are linked
+        assert_eq!(amounts(choose_merge(&notes, &config, 500)), Some((10, 30)));
+        assert_eq!(
+            amounts(choose_merge(&notes, &config, 5_000)),
+            Some((10, 20))
+        );
+        // A single dust note of an asset is left alone
+        assert_eq!(amounts(choose_merge(&notes[..3], &config, 500)), None);
+
+        let mut usage = MergeUsage::default();
+        let now = 3 * SECONDS_PER_DAY;
+        for _ in 0..config.max_daily_merges {
+            assert!(usage.allows(&config, now));
+            usage.record(now);
+        }
+        assert!(!usage.allows(&config, now));
+        assert!(usage.allows(&config, now + SECONDS_PER_DAY));
+    }
+}
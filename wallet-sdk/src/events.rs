@@ -0,0 +1,190 @@
+use std::{collections::HashSet, thread::JoinHandle};
+use tokio::sync::{broadcast, watch};
+use untrace_common::ShieldedNote;
+use untrace_privacy_client::{BridgeEvent, PoolInfo};
+
+use crate::{
+    adapter::AdapterEvent,
+    history::ActivityKind,
+    storage::{DepositNote, DepositStatus},
+};
+
+/// Events kept for a subscriber that falls behind; older ones are dropped
+/// and its next `recv` reports how many it missed
+const EVENT_CAPACITY: usize = 256;
+
+/// Something that changed in the wallet, for a UI to react to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletEvent {
+    /// Transparent balance of the active account, in lamports
+    BalanceChanged { lamports: u64 },
+    /// Note sent to the active account, already kept in storage
+    NoteReceived { note: ShieldedNote, leaf_index: u64 },
+    /// Transaction the wallet sent landed
+    TransferConfirmed {
+        kind: ActivityKind,
+        signature: String,
+        amount: u64,
+    },
+    /// One of the wallet's outbound bridge transfers advanced
+    BridgeStatusChanged(BridgeEvent),
+    /// Adapter connected as `name`, or the Ledger, connected, switched
+    /// account or disconnected
+    AdapterChanged { name: String, event: AdapterEvent },
+    /// Pool holding an unspent deposit of the active account has fewer
+    /// deposits than the config's `min_pool_size`, so withdrawing from it
+    /// would hide little
+    PoolAnonymityLow {
+        pool_id: u64,
+        commitment_count: u64,
+        min_pool_size: u64,
+    },
+    /// Wallet locked, by `lock` or after sitting idle, or unlocked
+    LockChanged { locked: bool },
+}
+
+/// Fans the wallet's events out to every subscriber
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<WalletEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Events from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<WalletEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Send `event` to the current subscribers, if any
+    pub fn emit(&self, event: WalletEvent) {
+        self.sender.send(event).ok();
+    }
+}
+
+/// Control of the task following the chain for wallet events. Dropping it
+/// stops the task once its current step ends.
+#[derive(Debug)]
+pub struct EventWatcher {
+    pub(crate) stop: watch::Sender<bool>,
+    pub(crate) task: JoinHandle<()>,
+}
+
+impl EventWatcher {
+    /// Stop the task and wait for it to exit
+    pub fn stop(self) {
+        self.stop.send_replace(true);
+        self.task.join().ok();
+    }
+}
+
+/// `PoolAnonymityLow` events for the pools in `pools` holding an unspent
+/// deposit in `deposits` with fewer than `min_pool_size` deposits, leaving
+/// out those in `warned`. `warned` is updated to the pools now too small,
+/// so each is reported once until it grows and shrinks again.
+pub fn low_anonymity_events(
+    pools: &[PoolInfo],
+    deposits: &[DepositNote],
+    min_pool_size: u64,
+    warned: &mut HashSet<u64>,
+) -> Vec<WalletEvent> {
+    let held: HashSet<u64> = deposits
+        .iter()
+        .filter(|deposit| deposit.status != DepositStatus::Spent)
+        .map(|deposit| deposit.pool_id)
+        .collect();
+    let small: Vec<&PoolInfo> = pools
+        .iter()
+        .filter(|pool| held.contains(&pool.pool_id) && pool.commitment_count < min_pool_size)
+        .collect();
+
+    let events = small
+        .iter()
+        .filter(|pool| !warned.contains(&pool.pool_id))
+        .map(|pool| WalletEvent::PoolAnonymityLow {
+            pool_id: pool.pool_id,
+            commitment_count: pool.commitment_count,
+            min_pool_size,
+        })
+        .collect();
+    *warned = small.iter().map(|pool| pool.pool_id).collect();
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use untrace_common::NATIVE_ASSET_ID;
+
+    fn pool(pool_id: u64, commitment_count: u64) -> PoolInfo {
+        PoolInfo {
+            address: Pubkey::new_unique(),
+            pool_id,
+            denomination: Some(100),
+            asset: NATIVE_ASSET_ID,
+            commitment_count,
+            min_pool_size: 0,
+            paused: false,
+        }
+    }
+
+    fn deposit(pool_id: u64, status: DepositStatus) -> DepositNote {
+        let mut deposit = DepositNote::new(pool_id, [1; 32], [2; 32], 0, 100, Pubkey::new_unique());
+        deposit.status = status;
+        deposit
+    }
+
+    #[test]
+    fn test_low_anonymity_reported_once() {
+        let deposits = vec![
+            deposit(0, DepositStatus::Unspent),
+            deposit(1, DepositStatus::Spent),
+            deposit(2, DepositStatus::Unspent),
+        ];
+        let mut warned = HashSet::new();
+        let pools = vec![pool(0, 3), pool(1, 3), pool(2, 50)];
+        assert_eq!(
+            low_anonymity_events(&pools, &deposits, 10, &mut warned),
+            vec![WalletEvent::PoolAnonymityLow {
+                pool_id: 0,
+                commitment_count: 3,
+                min_pool_size: 10
+            }]
+        );
+        assert!(low_anonymity_events(&pools, &deposits, 10, &mut warned).is_empty());
+
+        // Once the pool grows past the minimum it can be reported again
+        let grown = vec![pool(0, 12), pool(2, 50)];
+        assert!(low_anonymity_events(&grown, &deposits, 10, &mut warned).is_empty());
+        assert_eq!(
+            low_anonymity_events(&pools, &deposits, 10, &mut warned).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_event_bus_fans_out() {
+        let bus = EventBus::new();
+        bus.emit(WalletEvent::BalanceChanged { lamports: 1 });
+        let (mut first, mut second) = (bus.subscribe(), bus.subscribe());
+        bus.emit(WalletEvent::BalanceChanged { lamports: 2 });
+        for receiver in [&mut first, &mut second] {
+            assert_eq!(
+                receiver.try_recv().unwrap(),
+                WalletEvent::BalanceChanged { lamports: 2 }
+            );
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::signature::Keypair;
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{address_book::AddressBook, keystore::SealingKey};
+
+/// Kind of wallet operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Enum))]
+pub enum ActivityKind {
+    Deposit,
+    Withdrawal,
+    PrivateTransfer,
+    Bridge,
+}
+
+/// Where an operation stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Enum))]
+pub enum ActivityStatus {
+    /// Sent, but not final yet, such as a bridge transfer not yet claimed
+    /// on its destination
+    Pending,
+    Confirmed,
+    /// Failed or refunded
+    Failed,
+}
+
+/// One entry of the wallet's activity feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Record))]
+pub struct Activity {
+    /// Increases with each entry recorded
+    pub id: u64,
+    /// Wallet account the operation was made from
+    pub account: u32,
+    pub kind: ActivityKind,
+    pub status: ActivityStatus,
+    /// Solana transaction signature
+    pub signature: String,
+    /// Amount in the asset's base units
+    pub amount: u64,
+    /// SPL mint of the asset, or `None` for SOL
+    pub mint: Option<String>,
+    /// Address on the other side: the transfer or bridge recipient, or the
+    /// withdrawal's recipient
+    pub counterparty: Option<String>,
+    /// Label of `counterparty` in the address book, filled in by queries
+    /// and never stored
+    pub label: Option<String>,
+    /// Wormhole chain ID of a bridge's destination
+    pub chain: Option<u16>,
+    /// Pool of a deposit or withdrawal
+    pub pool_id: Option<u64>,
+    /// Unix time the entry was recorded
+    pub timestamp: i64,
+}
+
+impl Activity {
+    /// Confirmed entry of `kind` for `signature`, recorded now; `id` is
+    /// assigned when it is recorded
+    pub fn new(account: u32, kind: ActivityKind, signature: String, amount: u64) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            id: 0,
+            account,
+            kind,
+            status: ActivityStatus::Confirmed,
+            signature,
+            amount,
+            mint: None,
+            counterparty: None,
+            label: None,
+            chain: None,
+            pool_id: None,
+            timestamp,
+        }
+    }
+
+    pub fn with_status(mut self, status: ActivityStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_counterparty(mut self, counterparty: impl Into<String>) -> Self {
+        self.counterparty = Some(counterparty.into());
+        self
+    }
+
+    pub fn with_chain(mut self, chain: u16) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub fn with_pool(mut self, pool_id: u64) -> Self {
+        self.pool_id = Some(pool_id);
+        self
+    }
+
+    pub fn with_mint(mut self, mint: impl Into<String>) -> Self {
+        self.mint = Some(mint.into());
+        self
+    }
+}
+
+/// Which entries a query returns; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryFilter {
+    pub account: Option<u32>,
+    pub kind: Option<ActivityKind>,
+    pub status: Option<ActivityStatus>,
+    pub counterparty: Option<String>,
+    /// Earliest Unix time, inclusive
+    pub since: Option<i64>,
+    /// Latest Unix time, inclusive
+    pub until: Option<i64>,
+    /// Most entries to return, newest first
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, activity: &Activity) -> bool {
+        self.account
+            .is_none_or(|account| activity.account == account)
+            && self.kind.is_none_or(|kind| activity.kind == kind)
+            && self.status.is_none_or(|status| activity.status == status)
+            && self
+                .counterparty
+                .as_ref()
+                .is_none_or(|counterparty| activity.counterparty.as_ref() == Some(counterparty))
+            && self.since.is_none_or(|since| activity.timestamp >= since)
+            && self.until.is_none_or(|until| activity.timestamp <= until)
+    }
+}
+
+/// Local record of the wallet's operations, kept in a sled database so an
+/// activity feed survives restarts
+#[derive(Debug, Clone)]
+pub struct TransactionHistory {
+    db: sled::Db,
+    /// Sealed entries by big-endian ID, so iteration is in recording order
+    activities: sled::Tree,
+    /// Key entries are encrypted under, so the database never holds the
+    /// wallet's amounts and counterparties in plaintext
+    sealing_key: SealingKey,
+}
+
+impl TransactionHistory {
+    /// History in the database at `path`, created if needed. Entries are
+    /// encrypted under a key derived from `keypair`, so only this wallet
+    /// can read them back.
+    pub fn open(path: impl AsRef<Path>, keypair: &Keypair) -> Result<Self> {
+        let sealing_key = SealingKey::derive(keypair.secret().as_bytes());
+        Self::from_db(sled::open(path)?, sealing_key)
+    }
+
+    /// History kept in memory, lost when the process exits
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Self::from_db(db, SealingKey::random())
+    }
+
+    fn from_db(db: sled::Db, sealing_key: SealingKey) -> Result<Self> {
+        Ok(Self {
+            activities: db.open_tree("activities")?,
+            db,
+            sealing_key,
+        })
+    }
+
+    /// Record `activity`, returning the ID it was given
+    pub fn record(&self, mut activity: Activity) -> Result<u64> {
+        activity.id = self.db.generate_id()?;
+        activity.label = None;
+        self.put(&activity)?;
+        Ok(activity.id)
+    }
+
+    /// Move entry `id` to `status`, such as a bridge transfer once claimed
+    pub fn set_status(&self, id: u64, status: ActivityStatus) -> Result<()> {
+        let mut activity = self
+            .activities
+            .get(id.to_be_bytes())?
+            .map(|value| self.unseal(&value))
+            .transpose()?
+            .ok_or_else(|| anyhow!("No activity with ID {}", id))?;
+        activity.status = status;
+        self.put(&activity)
+    }
+
+    /// Entry `id`, if recorded, labeled from `book`
+    pub fn get(&self, id: u64, book: &AddressBook) -> Result<Option<Activity>> {
+        self.activities
+            .get(id.to_be_bytes())?
+            .map(|value| Ok(labeled(self.unseal(&value)?, book)))
+            .transpose()
+    }
+
+    /// Entries matching `filter`, newest first, labeled from `book`
+    pub fn query(&self, filter: &HistoryFilter, book: &AddressBook) -> Result<Vec<Activity>> {
+        let mut matching = Vec::new();
+        for entry in self.activities.iter().rev() {
+            if filter.limit.is_some_and(|limit| matching.len() >= limit) {
+                break;
+            }
+            let (_, value) = entry?;
+            let activity = self.unseal(&value)?;
+            if filter.matches(&activity) {
+                matching.push(labeled(activity, book));
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Entries matching `filter` as a JSON array
+    pub fn export_json(&self, filter: &HistoryFilter, book: &AddressBook) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.query(filter, book)?)?)
+    }
+
+    /// Entries matching `filter` as CSV with a header row
+    pub fn export_csv(&self, filter: &HistoryFilter, book: &AddressBook) -> Result<String> {
+        let mut csv = String::from(concat!(
+            "id,account,kind,status,timestamp,amount,signature,",
+            "counterparty,label,chain,pool_id,mint\n"
+        ));
+        for activity in self.query(filter, book)? {
+            let fields = [
+                activity.id.to_string(),
+                activity.account.to_string(),
+                format!("{:?}", activity.kind),
+                format!("{:?}", activity.status),
+                activity.timestamp.to_string(),
+                activity.amount.to_string(),
+                activity.signature,
+                activity.counterparty.unwrap_or_default(),
+                activity.label.unwrap_or_default(),
+                activity
+                    .chain
+                    .map(|chain| chain.to_string())
+                    .unwrap_or_default(),
+                activity
+                    .pool_id
+                    .map(|pool| pool.to_string())
+                    .unwrap_or_default(),
+                activity.mint.unwrap_or_default(),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        Ok(csv)
+    }
+
+    fn put(&self, activity: &Activity) -> Result<()> {
+        let sealed = self.sealing_key.seal(&serde_json::to_vec(activity)?)?;
+        self.activities.insert(activity.id.to_be_bytes(), sealed)?;
+        self.activities.flush()?;
+        Ok(())
+    }
+
+    fn unseal(&self, value: &[u8]) -> Result<Activity> {
+        Ok(serde_json::from_slice(&self.sealing_key.open(value)?)?)
+    }
+}
+
+/// `activity` with its counterparty's label in `book`. Labels are looked up
+/// on read, so renaming a contact renames it everywhere.
+fn labeled(mut activity: Activity, book: &AddressBook) -> Activity {
+    activity.label = activity
+        .counterparty
+        .as_ref()
+        .and_then(|counterparty| book.get(counterparty))
+        .map(|contact| contact.label.clone());
+    activity
+}
+
+/// `field` quoted if it holds a comma, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_query_labels_and_export() {
+        let history = TransactionHistory::temporary().unwrap();
+        let deposit = history
+            .record(Activity::new(0, ActivityKind::Deposit, "sig1".to_string(), 5).with_pool(1))
+            .unwrap();
+        let bridge = history
+            .record(
+                Activity::new(0, ActivityKind::Bridge, "sig2".to_string(), 7)
+                    .with_status(ActivityStatus::Pending)
+                    .with_counterparty("0xabc")
+                    .with_chain(2),
+            )
+            .unwrap();
+        history
+            .record(Activity::new(
+                1,
+                ActivityKind::Withdrawal,
+                "sig3".to_string(),
+                5,
+            ))
+            .unwrap();
+
+        let mut book = AddressBook::new();
+        let all = history.query(&HistoryFilter::default(), &book).unwrap();
+        assert_eq!(
+            all.iter()
+                .map(|activity| activity.signature.as_str())
+                .collect::<Vec<_>>(),
+            ["sig3", "sig2", "sig1"]
+        );
+        let account_zero = HistoryFilter {
+            account: Some(0),
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(history.query(&account_zero, &book).unwrap()[0].id, bridge);
+
+        book.add("0xABC", "Alice, exchange").unwrap();
+        history
+            .set_status(bridge, ActivityStatus::Confirmed)
+            .unwrap();
+        let pending = HistoryFilter {
+            status: Some(ActivityStatus::Pending),
+            ..Default::default()
+        };
+        assert!(history.query(&pending, &book).unwrap().is_empty());
+        let confirmed = history.get(bridge, &book).unwrap().unwrap();
+        assert_eq!(confirmed.label.as_deref(), Some("Alice, exchange"));
+        assert_eq!(
+            history.get(deposit, &book).unwrap().unwrap().pool_id,
+            Some(1)
+        );
+
+        // Entries are sealed before they reach the database
+        for entry in history.activities.iter() {
+            let (_, value) = entry.unwrap();
+            assert!(!value.windows(4).any(|window| window == b"sig2"));
+        }
+
+        let csv = history
+            .export_csv(&HistoryFilter::default(), &book)
+            .unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.contains(",sig2,0xabc,\"Alice, exchange\",2,,\n"));
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Result};
+use keyring::Entry;
+
+use crate::storage::StorageBackend;
+
+/// Backend in the OS keychain: the macOS Keychain, Windows Credential
+/// Manager or the Secret Service on Linux. Each key is one entry under
+/// `service`, so secrets survive restarts and are encrypted at rest by
+/// the OS rather than written to files.
+#[derive(Debug, Clone)]
+pub struct KeychainBackend {
+    service: String,
+}
+
+impl KeychainBackend {
+    /// Keep entries under `service`, which separates one wallet's secrets
+    /// from another's
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+
+    fn entry(&self, key: &str) -> Result<Entry> {
+        Entry::new(&self.service, key).map_err(|e| anyhow!("Keychain entry {}: {}", key, e))
+    }
+}
+
+impl StorageBackend for KeychainBackend {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let encoded = match self.entry(key)?.get_password() {
+            Ok(encoded) => encoded,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(anyhow!("Failed to read keychain entry {}: {}", key, e)),
+        };
+        let value = bs58::decode(encoded)
+            .into_vec()
+            .map_err(|e| anyhow!("Corrupt keychain entry {}: {}", key, e))?;
+        Ok(Some(value))
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        // Keychain entries hold text, so values are stored base58-encoded
+        self.entry(key)?
+            .set_password(&bs58::encode(value).into_string())
+            .map_err(|e| anyhow!("Failed to write keychain entry {}: {}", key, e))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match self.entry(key)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to delete keychain entry {}: {}", key, e)),
+        }
+    }
+}
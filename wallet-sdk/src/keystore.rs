@@ -0,0 +1,340 @@
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, KeyInit},
+    Aes256Gcm,
+};
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::signature::{keypair_from_seed, Keypair, Signer};
+use thiserror::Error;
+
+/// Version of the keystore envelope `encrypt_keystore` writes
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF: &str = "argon2id";
+const CIPHER: &str = "aes-256-gcm";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Most KDF cost a keystore may ask for, so a crafted one cannot make
+/// `decrypt_keystore` allocate gigabytes or run for hours: 1 GiB, 16
+/// passes and 16 lanes, well above the defaults
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+/// Domain separating the storage key from other uses of the wallet key
+const SEALING_KEY_DOMAIN: &[u8] = b"UNTRACE_WALLET_STORAGE";
+
+/// Why a keystore could not be opened
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum KeystoreError {
+    /// The password is wrong, or the keystore was tampered with
+    #[error("Wrong password")]
+    WrongPassword,
+
+    #[error("Keystore version {0} is not supported")]
+    UnsupportedVersion(u32),
+
+    #[error("Malformed keystore: {0}")]
+    Malformed(String),
+}
+
+/// Cost of deriving a keystore's key from its password
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub m_cost: u32,
+    /// Passes over the memory
+    pub t_cost: u32,
+    /// Lanes
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// Check the costs are within what `decrypt_keystore` will spend
+    pub fn check(&self) -> Result<(), KeystoreError> {
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(KeystoreError::Malformed(format!(
+                "KDF parameters exceed {} KiB, {} passes or {} lanes",
+                MAX_M_COST, MAX_T_COST, MAX_P_COST
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    /// RFC 9106's recommendation for memory-constrained hosts: 64 MiB,
+    /// three passes, four lanes
+    fn default() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 4,
+        }
+    }
+}
+
+/// Password-encrypted secret, stored as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    /// Hex-encoded KDF salt
+    pub salt: String,
+    pub cipher: String,
+    /// Hex-encoded AES-GCM nonce
+    pub nonce: String,
+    /// Hex-encoded ciphertext, including the authentication tag
+    pub ciphertext: String,
+}
+
+/// Encrypt `secret` under `password` with the default KDF cost
+pub fn encrypt_keystore(secret: &[u8], password: &str) -> Result<String> {
+    encrypt_keystore_with(secret, password, KdfParams::default())
+}
+
+/// Encrypt `secret` under `password`: Argon2id derives an AES-256-GCM key
+/// from the password and a random salt, which seals the secret under a
+/// random nonce
+pub fn encrypt_keystore_with(secret: &[u8], password: &str, params: KdfParams) -> Result<String> {
+    Ok(serde_json::to_string(&seal_keystore(secret, password, params)?)?)
+}
+
+/// `Keystore` sealing `secret` under `password`, for formats that embed
+/// one
+pub(crate) fn seal_keystore(secret: &[u8], password: &str, params: KdfParams) -> Result<Keystore> {
+    if password.is_empty() {
+        return Err(anyhow!("Password must not be empty"));
+    }
+    params.check()?;
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = derive_key(password, &salt, &params)?;
+    let ciphertext = Aes256Gcm::new(GenericArray::from_slice(&key))
+        .encrypt(GenericArray::from_slice(&nonce), secret)
+        .map_err(|_| anyhow!("Keystore encryption failed"))?;
+
+    Ok(Keystore {
+        version: KEYSTORE_VERSION,
+        kdf: KDF.to_string(),
+        kdf_params: params,
+        salt: hex::encode(salt),
+        cipher: CIPHER.to_string(),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+/// Decrypt a keystore from `encrypt_keystore`. A wrong password fails
+/// with `KeystoreError::WrongPassword`.
+pub fn decrypt_keystore(keystore: &str, password: &str) -> Result<Vec<u8>> {
+    let keystore: Keystore =
+        serde_json::from_str(keystore).map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+    open_keystore(&keystore, password)
+}
+
+/// Secret of a `Keystore` from `seal_keystore`
+pub(crate) fn open_keystore(keystore: &Keystore, password: &str) -> Result<Vec<u8>> {
+    if keystore.version != KEYSTORE_VERSION {
+        return Err(KeystoreError::UnsupportedVersion(keystore.version).into());
+    }
+    if keystore.kdf != KDF || keystore.cipher != CIPHER {
+        return Err(KeystoreError::Malformed(format!(
+            "unknown {} with {}",
+            keystore.kdf, keystore.cipher
+        ))
+        .into());
+    }
+    let salt = decode_field("salt", &keystore.salt)?;
+    let nonce = decode_field("nonce", &keystore.nonce)?;
+    let ciphertext = decode_field("ciphertext", &keystore.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(KeystoreError::Malformed("nonce length".to_string()).into());
+    }
+    keystore.kdf_params.check()?;
+
+    let key = derive_key(password, &salt, &keystore.kdf_params)?;
+    Aes256Gcm::new(GenericArray::from_slice(&key))
+        .decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| KeystoreError::WrongPassword.into())
+}
+
+/// Whether `encrypted` is a keystore rather than the base58 XOR export
+/// of earlier versions
+pub fn is_keystore(encrypted: &str) -> bool {
+    encrypted.trim_start().starts_with('{')
+}
+
+/// Keypair of a Solana CLI keypair file such as `id.json`: an unencrypted
+/// JSON array of the 64 bytes of the secret key followed by the public key
+pub fn keypair_from_json(json: &str) -> Result<Keypair> {
+    let bytes: Vec<u8> = serde_json::from_str(json)
+        .map_err(|e| anyhow!("Not a Solana keypair file: {}", e))?;
+    if bytes.len() != 64 {
+        return Err(anyhow!("Solana keypair file holds {} bytes, expected 64", bytes.len()));
+    }
+    let keypair = keypair_from_seed(&bytes[..32])
+        .map_err(|e| anyhow!("Invalid secret key: {}", e))?;
+    if keypair.pubkey().as_ref() != &bytes[32..] {
+        return Err(anyhow!("Public key of the keypair file does not match its secret key"));
+    }
+    Ok(keypair)
+}
+
+/// `keypair` in the Solana CLI keypair file format `keypair_from_json`
+/// reads. The secret key is not encrypted.
+pub fn keypair_to_json(keypair: &Keypair) -> String {
+    serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap_or_default()
+}
+
+/// Key records are sealed under before they reach a storage backend
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct SealingKey([u8; 32]);
+
+impl std::fmt::Debug for SealingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SealingKey(..)")
+    }
+}
+
+impl SealingKey {
+    /// Key derived from the wallet's secret key, so the same wallet opens
+    /// its records after a restart
+    pub(crate) fn derive(secret: &[u8]) -> Self {
+        Self(
+            Sha256::new()
+                .chain_update(SEALING_KEY_DOMAIN)
+                .chain_update(secret)
+                .finalize()
+                .into(),
+        )
+    }
+
+    /// Key of keystore version `version`, which each rotation bumps. The
+    /// first is the one `derive` gives.
+    pub(crate) fn derive_version(secret: &[u8], version: u32) -> Self {
+        if version <= 1 {
+            return Self::derive(secret);
+        }
+        Self(
+            Sha256::new()
+                .chain_update(SEALING_KEY_DOMAIN)
+                .chain_update(version.to_le_bytes())
+                .chain_update(secret)
+                .finalize()
+                .into(),
+        )
+    }
+
+    /// Key for records that only live as long as the process
+    pub(crate) fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// A random nonce, then `plaintext` encrypted with AES-256-GCM
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut sealed = vec![0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut sealed);
+        let ciphertext = Aes256Gcm::new(GenericArray::from_slice(&self.0))
+            .encrypt(GenericArray::from_slice(&sealed), plaintext)
+            .map_err(|_| anyhow!("Record encryption failed"))?;
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Plaintext of a record from `seal`
+    pub(crate) fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("Sealed record is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        Aes256Gcm::new(GenericArray::from_slice(&self.0))
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Sealed record was written by another wallet or tampered with"))
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], params: &KdfParams) -> Result<[u8; 32]> {
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| KeystoreError::Malformed(format!("KDF parameters: {}", e)))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(value).map_err(|_| KeystoreError::Malformed(format!("{} is not hex", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore_round_trip() {
+        // Cheap parameters keep the test fast
+        let params = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let keystore = encrypt_keystore_with(&[7u8; 64], "hunter2", params).unwrap();
+        assert!(is_keystore(&keystore));
+        assert_eq!(
+            decrypt_keystore(&keystore, "hunter2").unwrap(),
+            vec![7u8; 64]
+        );
+
+        let err = decrypt_keystore(&keystore, "hunter3").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KeystoreError>(),
+            Some(&KeystoreError::WrongPassword)
+        );
+
+        // Salt and nonce are fresh each time
+        let again = encrypt_keystore_with(&[7u8; 64], "hunter2", params).unwrap();
+        assert_ne!(keystore, again);
+
+        let mut future: Keystore = serde_json::from_str(&keystore).unwrap();
+        future.version = 2;
+        let err = decrypt_keystore(&serde_json::to_string(&future).unwrap(), "hunter2");
+        assert_eq!(
+            err.unwrap_err().downcast_ref::<KeystoreError>(),
+            Some(&KeystoreError::UnsupportedVersion(2))
+        );
+
+        let mut costly: Keystore = serde_json::from_str(&keystore).unwrap();
+        costly.kdf_params.m_cost = u32::MAX;
+        let err = decrypt_keystore(&serde_json::to_string(&costly).unwrap(), "hunter2");
+        assert!(matches!(
+            err.unwrap_err().downcast_ref::<KeystoreError>(),
+            Some(KeystoreError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_solana_keypair_json() {
+        let keypair = Keypair::new();
+        let json = keypair_to_json(&keypair);
+        assert!(json.starts_with('[') && !is_keystore(&json));
+        assert_eq!(keypair_from_json(&json).unwrap().pubkey(), keypair.pubkey());
+
+        // Another key's public half, or a truncated file, is rejected
+        let mut bytes = keypair.to_bytes().to_vec();
+        bytes[32..].copy_from_slice(Keypair::new().pubkey().as_ref());
+        assert!(keypair_from_json(&serde_json::to_string(&bytes).unwrap()).is_err());
+        assert!(keypair_from_json(&serde_json::to_string(&bytes[..32]).unwrap()).is_err());
+    }
+}
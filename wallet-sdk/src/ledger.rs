@@ -0,0 +1,670 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    compute_budget,
+    derivation_path::DerivationPath,
+    message::VersionedMessage,
+    offchain_message::OffchainMessage,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Signature, Signer, SignerError},
+    stake, system_program,
+    transaction::VersionedTransaction,
+    vote,
+};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::adapter::{
+    AdapterEvent, AdapterEvents, AdapterFeatures, AdapterListener, WalletAdapter,
+};
+
+/// Ledger USB vendor ID
+const LEDGER_VID: u16 = 0x2c97;
+/// HID usage page of the Ledger's APDU interface
+const LEDGER_USAGE_PAGE: u16 = 0xffa0;
+
+const HID_PACKET_SIZE: usize = 64;
+const CHANNEL: [u8; 2] = [0x01, 0x01];
+const APDU_TAG: u8 = 0x05;
+const APDU_CLA: u8 = 0xe0;
+const MAX_CHUNK_SIZE: usize = 255;
+
+const INS_GET_APP_CONFIGURATION: u8 = 0x04;
+const INS_GET_PUBKEY: u8 = 0x05;
+const INS_SIGN_MESSAGE: u8 = 0x06;
+const INS_SIGN_OFFCHAIN_MESSAGE: u8 = 0x07;
+
+const P1_NON_CONFIRM: u8 = 0x00;
+const P1_CONFIRM: u8 = 0x01;
+const P2_EXTEND: u8 = 0x01;
+const P2_MORE: u8 = 0x02;
+
+const STATUS_OK: u16 = 0x9000;
+
+/// Programs whose instructions the Solana app displays. A message calling
+/// any other program, such as the privacy program, can only be signed
+/// blind, from the hash the device shows.
+const CLEAR_SIGNED_PROGRAMS: [Pubkey; 9] = [
+    system_program::ID,
+    stake::program::ID,
+    vote::program::ID,
+    compute_budget::ID,
+    pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+    pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"),
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"),
+    pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"),
+    pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo"),
+];
+
+/// Why the Ledger refused or failed a request
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LedgerError {
+    #[error("No Ledger device found; connect and unlock it")]
+    DeviceNotFound,
+
+    #[error("Ledger is not connected")]
+    NotConnected,
+
+    #[error("Ledger is locked; unlock it with its PIN")]
+    Locked,
+
+    #[error("Open the Solana app on the Ledger")]
+    AppNotOpen,
+
+    #[error("Update the Solana app on the Ledger to 1.0 or later")]
+    AppOutdated,
+
+    #[error("Rejected on the Ledger")]
+    UserRejected,
+
+    /// The message needs blind signing and the adapter was not allowed to
+    /// ask for it
+    #[error("Signing a call to {program} needs blind signing, which is not allowed")]
+    BlindSigningNotAllowed { program: Pubkey },
+
+    /// The message needs blind signing and it is off in the app's settings
+    #[error("Enable blind signing in the Solana app's settings on the Ledger")]
+    BlindSigningDisabled,
+
+    #[error("Ledger returned status {0:#06x}")]
+    Status(u16),
+
+    #[error("Ledger protocol error: {0}")]
+    Protocol(&'static str),
+
+    #[error("Ledger transport error: {0}")]
+    Transport(String),
+}
+
+impl LedgerError {
+    fn from_status(status: u16) -> Self {
+        match status {
+            0x5515 | 0x6b0c => Self::Locked,
+            0x6700 | 0x6d00 | 0x6e00 | 0x6e01 => Self::AppNotOpen,
+            0x6808 => Self::BlindSigningDisabled,
+            0x6985 => Self::UserRejected,
+            status => Self::Status(status),
+        }
+    }
+}
+
+impl From<LedgerError> for SignerError {
+    fn from(error: LedgerError) -> Self {
+        match error {
+            LedgerError::DeviceNotFound => SignerError::NoDeviceFound,
+            LedgerError::NotConnected | LedgerError::Transport(_) => {
+                SignerError::Connection(error.to_string())
+            }
+            LedgerError::UserRejected => SignerError::UserCancel(error.to_string()),
+            error => SignerError::Protocol(error.to_string()),
+        }
+    }
+}
+
+/// Channel that carries 64-byte HID packets to and from a Ledger
+pub trait LedgerTransport: Send {
+    /// Send one packet
+    fn write(&self, packet: &[u8; HID_PACKET_SIZE]) -> Result<(), LedgerError>;
+
+    /// Wait for the next packet of a response
+    fn read(&self) -> Result<[u8; HID_PACKET_SIZE], LedgerError>;
+}
+
+impl LedgerTransport for hidapi::HidDevice {
+    fn write(&self, packet: &[u8; HID_PACKET_SIZE]) -> Result<(), LedgerError> {
+        // Windows expects the report ID ahead of the packet
+        let mut report = Vec::with_capacity(HID_PACKET_SIZE + 1);
+        if cfg!(windows) {
+            report.push(0);
+        }
+        report.extend_from_slice(packet);
+        let written = hidapi::HidDevice::write(self, &report)
+            .map_err(|e| LedgerError::Transport(e.to_string()))?;
+        if written < report.len() {
+            return Err(LedgerError::Protocol("short write"));
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<[u8; HID_PACKET_SIZE], LedgerError> {
+        let mut packet = [0u8; HID_PACKET_SIZE];
+        let read = hidapi::HidDevice::read(self, &mut packet)
+            .map_err(|e| LedgerError::Transport(e.to_string()))?;
+        if read < 7 {
+            return Err(LedgerError::Protocol("short read"));
+        }
+        Ok(packet)
+    }
+}
+
+/// Settings of the Solana app that affect signing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedgerAppConfig {
+    pub blind_signing_enabled: bool,
+    /// Solana app version as major, minor, patch
+    pub version: (u8, u8, u8),
+}
+
+struct Connection {
+    transport: Box<dyn LedgerTransport>,
+    pubkey: Pubkey,
+}
+
+/// Ledger hardware wallet running the Solana app, signing on-device for
+/// the key at one derivation path. It is a `Signer`, so an `UntraceClient`
+/// or `UntraceWallet` can pay and sign with it like with a keypair.
+pub struct LedgerAdapter {
+    derivation_path: DerivationPath,
+    allow_blind_signing: bool,
+    connection: Mutex<Option<Connection>>,
+    events: AdapterEvents,
+}
+
+impl std::fmt::Debug for LedgerAdapter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LedgerAdapter")
+            .field("derivation_path", &self.derivation_path)
+            .field("allow_blind_signing", &self.allow_blind_signing)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+impl LedgerAdapter {
+    /// Adapter for the key at `derivation_path`
+    pub fn new(derivation_path: DerivationPath) -> Self {
+        Self {
+            derivation_path,
+            allow_blind_signing: false,
+            connection: Mutex::new(None),
+            events: AdapterEvents::new(),
+        }
+    }
+
+    /// Adapter for `account` at `m/44'/501'/account'/0'`, the path a
+    /// recovery phrase's accounts use in this wallet
+    pub fn for_account(account: u32) -> Self {
+        Self::new(DerivationPath::new_bip44(Some(account), Some(0)))
+    }
+
+    /// Let the adapter ask for blind signatures of messages the device
+    /// cannot display, which every deposit and withdrawal is. Off by
+    /// default, so such a message fails before reaching the device.
+    pub fn with_blind_signing(mut self) -> Self {
+        self.allow_blind_signing = true;
+        self
+    }
+
+    pub fn derivation_path(&self) -> &DerivationPath {
+        &self.derivation_path
+    }
+
+    /// Connect over `transport` instead of the first Ledger found on USB.
+    /// Reconnecting to a device holding another key, such as one restored
+    /// from a different recovery phrase, is an account change.
+    pub fn connect_transport(&self, transport: Box<dyn LedgerTransport>) -> Result<()> {
+        if app_config(transport.as_ref())?.version.0 < 1 {
+            return Err(LedgerError::AppOutdated.into());
+        }
+        let pubkey = get_pubkey(transport.as_ref(), &self.derivation_path, false)?;
+        let previous = self
+            .connection
+            .lock()
+            .unwrap()
+            .replace(Connection { transport, pubkey })
+            .map(|connection| connection.pubkey);
+        match previous {
+            None => self.events.emit(AdapterEvent::Connected),
+            Some(previous) if previous != pubkey => {
+                self.events.emit(AdapterEvent::AccountChanged(pubkey))
+            }
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Drop the connection to the device, which signers sharing the
+    /// adapter see too
+    pub fn close(&self) {
+        if self.connection.lock().unwrap().take().is_some() {
+            self.events.emit(AdapterEvent::Disconnected);
+        }
+    }
+
+    /// Current settings of the Solana app
+    pub fn app_config(&self) -> Result<LedgerAppConfig, LedgerError> {
+        self.with_connection(|connection| app_config(connection.transport.as_ref()))
+    }
+
+    /// Show the address on the device and wait for the user to confirm
+    /// it matches the one on screen
+    pub fn verify_address(&self) -> Result<Pubkey, LedgerError> {
+        self.with_connection(|connection| {
+            get_pubkey(connection.transport.as_ref(), &self.derivation_path, true)
+        })
+    }
+
+    /// Sign a serialized transaction message on the device, after the
+    /// user approves it there
+    pub fn sign_message_bytes(&self, message: &[u8]) -> Result<Signature, LedgerError> {
+        let parsed: VersionedMessage = bincode::deserialize(message)
+            .map_err(|_| LedgerError::Protocol("not a transaction message"))?;
+        self.with_connection(|connection| {
+            let transport = connection.transport.as_ref();
+            if let Some(program) = blind_signed_program(&parsed) {
+                if !self.allow_blind_signing {
+                    return Err(LedgerError::BlindSigningNotAllowed { program });
+                }
+                if !app_config(transport)?.blind_signing_enabled {
+                    return Err(LedgerError::BlindSigningDisabled);
+                }
+            }
+            sign(transport, INS_SIGN_MESSAGE, &self.derivation_path, message)
+        })
+    }
+
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, LedgerError>,
+    ) -> Result<T, LedgerError> {
+        let connection = self.connection.lock().unwrap();
+        f(connection.as_ref().ok_or(LedgerError::NotConnected)?)
+    }
+}
+
+#[async_trait]
+impl WalletAdapter for LedgerAdapter {
+    /// Transactions are approved on the device one at a time
+    fn features(&self) -> AdapterFeatures {
+        AdapterFeatures {
+            sign_all_transactions: false,
+            versioned_transactions: true,
+            sign_message: true,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        if self.is_connected() {
+            return Ok(());
+        }
+        let api = hidapi::HidApi::new()?;
+        let device = api
+            .device_list()
+            .find(|device| {
+                device.vendor_id() == LEDGER_VID
+                    && (device.usage_page() == LEDGER_USAGE_PAGE || device.interface_number() == 0)
+            })
+            .ok_or(LedgerError::DeviceNotFound)?
+            .open_device(&api)?;
+        self.connect_transport(Box::new(device))
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+
+    fn get_public_key(&self) -> Result<Pubkey> {
+        Ok(self.with_connection(|connection| Ok(connection.pubkey))?)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.lock().unwrap().is_some()
+    }
+
+    fn on_event(&self, listener: AdapterListener) {
+        self.events.subscribe(listener);
+    }
+
+    /// Sign a bincode-serialized `VersionedTransaction`, returning it with
+    /// the Ledger's signature in place
+    async fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+        let mut transaction: VersionedTransaction = bincode::deserialize(transaction)?;
+        let pubkey = self.get_public_key()?;
+        let signers = usize::from(transaction.message.header().num_required_signatures);
+        let position = transaction.message.static_account_keys()[..signers]
+            .iter()
+            .position(|key| *key == pubkey)
+            .ok_or_else(|| anyhow::anyhow!("Transaction is not signed by {}", pubkey))?;
+
+        let signature = self.sign_message_bytes(&transaction.message.serialize())?;
+        transaction.signatures.resize(signers, Signature::default());
+        transaction.signatures[position] = signature;
+        Ok(bincode::serialize(&transaction)?)
+    }
+
+    /// Sign `message` as a Solana off-chain message, which the device
+    /// displays before signing
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let message = OffchainMessage::new(0, message)
+            .and_then(|message| message.serialize())
+            .map_err(|e| anyhow::anyhow!("Invalid off-chain message: {}", e))?;
+        let signature = self.with_connection(|connection| {
+            sign(
+                connection.transport.as_ref(),
+                INS_SIGN_OFFCHAIN_MESSAGE,
+                &self.derivation_path,
+                &message,
+            )
+        })?;
+        Ok(signature.as_ref().to_vec())
+    }
+}
+
+impl Signer for LedgerAdapter {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.with_connection(|connection| Ok(connection.pubkey))?)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        Ok(self.sign_message_bytes(message)?)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+/// Hash the Solana app shows when blind signing `message`, for the user
+/// to compare with the one their wallet displays
+pub fn blind_signing_hash(message: &[u8]) -> String {
+    bs58::encode(Sha256::digest(message)).into_string()
+}
+
+/// First program `message` calls that the device cannot display
+fn blind_signed_program(message: &VersionedMessage) -> Option<Pubkey> {
+    let keys = message.static_account_keys();
+    message
+        .instructions()
+        .iter()
+        .filter_map(|instruction| keys.get(usize::from(instruction.program_id_index)))
+        .find(|program| !CLEAR_SIGNED_PROGRAMS.contains(program))
+        .copied()
+}
+
+fn app_config(transport: &dyn LedgerTransport) -> Result<LedgerAppConfig, LedgerError> {
+    let config = exchange(transport, INS_GET_APP_CONFIGURATION, P1_NON_CONFIRM, 0, &[])
+        // Apps before 1.0 only answer the deprecated instruction set
+        .map_err(|error| match error {
+            LedgerError::Status(_) => LedgerError::AppOutdated,
+            error => error,
+        })?;
+    if config.len() != 5 {
+        return Err(LedgerError::Protocol("unexpected app configuration size"));
+    }
+    Ok(LedgerAppConfig {
+        blind_signing_enabled: config[0] != 0,
+        version: (config[2], config[3], config[4]),
+    })
+}
+
+fn get_pubkey(
+    transport: &dyn LedgerTransport,
+    derivation_path: &DerivationPath,
+    confirm: bool,
+) -> Result<Pubkey, LedgerError> {
+    let p1 = if confirm { P1_CONFIRM } else { P1_NON_CONFIRM };
+    let key = exchange(
+        transport,
+        INS_GET_PUBKEY,
+        p1,
+        0,
+        &serialize_path(derivation_path),
+    )?;
+    Pubkey::try_from(key.as_slice()).map_err(|_| LedgerError::Protocol("unexpected key size"))
+}
+
+/// Sign `data` with `ins`, split into APDUs of at most 255 bytes
+fn sign(
+    transport: &dyn LedgerTransport,
+    ins: u8,
+    derivation_path: &DerivationPath,
+    data: &[u8],
+) -> Result<Signature, LedgerError> {
+    if data.len() > usize::from(u16::MAX) {
+        return Err(LedgerError::Protocol("message too long"));
+    }
+    let mut payload = vec![1];
+    payload.extend(serialize_path(derivation_path));
+    payload.extend_from_slice(data);
+
+    let chunks: Vec<&[u8]> = payload.chunks(MAX_CHUNK_SIZE).collect();
+    let mut response = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let mut p2 = 0;
+        if index > 0 {
+            p2 |= P2_EXTEND;
+        }
+        if index + 1 < chunks.len() {
+            p2 |= P2_MORE;
+        }
+        response = exchange(transport, ins, P1_CONFIRM, p2, chunk)?;
+    }
+    Signature::try_from(response.as_slice())
+        .map_err(|_| LedgerError::Protocol("unexpected signature size"))
+}
+
+/// Derivation path as the Solana app reads it: the number of indexes,
+/// then each hardened index big-endian
+fn serialize_path(derivation_path: &DerivationPath) -> Vec<u8> {
+    let path = derivation_path.path();
+    let mut serialized = vec![path.len() as u8];
+    for index in path {
+        serialized.extend_from_slice(&index.to_bits().to_be_bytes());
+    }
+    serialized
+}
+
+/// Send one APDU and return its response data, failing on any status but
+/// success
+fn exchange(
+    transport: &dyn LedgerTransport,
+    ins: u8,
+    p1: u8,
+    p2: u8,
+    data: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    for packet in apdu_packets(ins, p1, p2, data) {
+        transport.write(&packet)?;
+    }
+
+    let mut response = Vec::new();
+    let mut length = 0;
+    for sequence in 0..=u16::MAX {
+        let packet = transport.read()?;
+        if packet[..3] != [CHANNEL[0], CHANNEL[1], APDU_TAG]
+            || u16::from_be_bytes([packet[3], packet[4]]) != sequence
+        {
+            return Err(LedgerError::Protocol("unexpected packet header"));
+        }
+        let body = if sequence == 0 {
+            length = usize::from(u16::from_be_bytes([packet[5], packet[6]]));
+            &packet[7..]
+        } else {
+            &packet[5..]
+        };
+        response.extend_from_slice(body);
+        if response.len() >= length {
+            break;
+        }
+    }
+    response.truncate(length);
+
+    if response.len() < 2 {
+        return Err(LedgerError::Protocol("missing status word"));
+    }
+    let status = u16::from_be_bytes([response[length - 2], response[length - 1]]);
+    if status != STATUS_OK {
+        return Err(LedgerError::from_status(status));
+    }
+    response.truncate(length - 2);
+    Ok(response)
+}
+
+/// HID packets carrying one APDU: a channel and sequence header on each,
+/// and the APDU's length ahead of it in the first
+fn apdu_packets(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<[u8; HID_PACKET_SIZE]> {
+    let mut apdu = ((data.len() + 5) as u16).to_be_bytes().to_vec();
+    apdu.extend_from_slice(&[APDU_CLA, ins, p1, p2, data.len() as u8]);
+    apdu.extend_from_slice(data);
+
+    apdu.chunks(HID_PACKET_SIZE - 5)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut packet = [0u8; HID_PACKET_SIZE];
+            packet[..3].copy_from_slice(&[CHANNEL[0], CHANNEL[1], APDU_TAG]);
+            packet[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            packet[5..5 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, instruction::Instruction, message::Message, system_instruction};
+    use std::{collections::VecDeque, sync::Arc};
+
+    /// Device that answers each APDU with the next queued response
+    struct FakeLedger {
+        written: Arc<Mutex<Vec<[u8; HID_PACKET_SIZE]>>>,
+        responses: Mutex<VecDeque<[u8; HID_PACKET_SIZE]>>,
+    }
+
+    /// Packets of a response carrying `data` and `status`
+    fn response(data: &[u8], status: u16) -> Vec<[u8; HID_PACKET_SIZE]> {
+        let mut body = ((data.len() + 2) as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(data);
+        body.extend_from_slice(&status.to_be_bytes());
+        body.chunks(HID_PACKET_SIZE - 5)
+            .enumerate()
+            .map(|(sequence, chunk)| {
+                let mut packet = [0u8; HID_PACKET_SIZE];
+                packet[..3].copy_from_slice(&[0x01, 0x01, APDU_TAG]);
+                packet[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+                packet[5..5 + chunk.len()].copy_from_slice(chunk);
+                packet
+            })
+            .collect()
+    }
+
+    impl LedgerTransport for FakeLedger {
+        fn write(&self, packet: &[u8; HID_PACKET_SIZE]) -> Result<(), LedgerError> {
+            self.written.lock().unwrap().push(*packet);
+            Ok(())
+        }
+
+        fn read(&self) -> Result<[u8; HID_PACKET_SIZE], LedgerError> {
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or(LedgerError::Protocol("no response queued"))
+        }
+    }
+
+    #[test]
+    fn test_ledger_signing_and_blind_signing_guard() {
+        let pubkey = Pubkey::new_unique();
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let blind_signing_off = response(&[0, 0, 1, 6, 0], STATUS_OK);
+        let fake = FakeLedger {
+            written: written.clone(),
+            responses: Mutex::new(
+                [
+                    blind_signing_off.clone(),
+                    response(pubkey.as_ref(), STATUS_OK),
+                    response(&[7u8; 64], STATUS_OK),
+                    blind_signing_off,
+                ]
+                .concat()
+                .into(),
+            ),
+        };
+        let ledger = LedgerAdapter::for_account(2);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        ledger.on_event(Box::new(move |event| seen.lock().unwrap().push(event.clone())));
+        ledger.connect_transport(Box::new(fake)).unwrap();
+        assert_eq!(ledger.pubkey(), pubkey);
+        assert_eq!(*events.lock().unwrap(), vec![AdapterEvent::Connected]);
+
+        // The key is asked for at m/44'/501'/2'/0'
+        let get_pubkey = written.lock().unwrap()[1];
+        assert_eq!(
+            get_pubkey[5..12],
+            [0, 22, APDU_CLA, INS_GET_PUBKEY, 0, 0, 17]
+        );
+        assert_eq!(get_pubkey[12], 4);
+        assert_eq!(get_pubkey[21..25], (2u32 | 1 << 31).to_be_bytes());
+
+        // A plain transfer is displayed on the device and signed there
+        let transfer = Message::new(
+            &[system_instruction::transfer(
+                &pubkey,
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&pubkey),
+        );
+        let signature = Signer::sign_message(&ledger, &transfer.serialize());
+        assert_eq!(signature, Signature::from([7u8; 64]));
+
+        // A privacy program call needs blind signing, refused unless the
+        // adapter allows it and the app has it on
+        let program = Pubkey::new_unique();
+        let mut deposit = Message::new(
+            &[Instruction::new_with_bytes(program, &[1], vec![])],
+            Some(&pubkey),
+        );
+        deposit.recent_blockhash = Hash::new_unique();
+        assert_eq!(
+            ledger.sign_message_bytes(&deposit.serialize()),
+            Err(LedgerError::BlindSigningNotAllowed { program })
+        );
+        let ledger = LedgerAdapter {
+            allow_blind_signing: true,
+            ..ledger
+        };
+        assert_eq!(
+            ledger.sign_message_bytes(&deposit.serialize()),
+            Err(LedgerError::BlindSigningDisabled)
+        );
+    }
+
+    #[test]
+    fn test_long_apdus_span_packets() {
+        let data = [9u8; 100];
+        let packets = apdu_packets(INS_SIGN_MESSAGE, P1_CONFIRM, P2_MORE, &data);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1][3..5], [0, 1]);
+        assert_eq!(packets[0][5..7], 105u16.to_be_bytes());
+
+        let mut rebuilt = packets[0][12..].to_vec();
+        rebuilt.extend_from_slice(&packets[1][5..5 + data.len() - rebuilt.len()]);
+        assert_eq!(rebuilt, data);
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer, SignerError},
+};
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    events::{EventBus, WalletEvent},
+    keystore::{decrypt_keystore, encrypt_keystore},
+    mnemonic::HdSeed,
+};
+
+/// How often the auto-lock watcher checks whether the wallet sat idle
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Refusal of an operation that needs the wallet's keys while it is
+/// locked; `unlock` it first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Wallet is locked")]
+pub struct WalletLocked;
+
+/// Keys of an unlocked wallet
+#[derive(Debug)]
+pub(crate) struct Keys {
+    /// Keypair of the active account
+    pub(crate) keypair: Keypair,
+    /// Seed the accounts derive from, for wallets with a recovery phrase
+    pub(crate) seed: Option<HdSeed>,
+}
+
+/// Holder of the wallet's keys. Locking drops them, which zeroizes them,
+/// and keeps only a keystore of them under the unlock password.
+#[derive(Debug)]
+pub(crate) struct KeyVault {
+    state: Mutex<VaultState>,
+}
+
+#[derive(Debug)]
+struct VaultState {
+    /// `None` while locked
+    keys: Option<Keys>,
+    /// Public key of the active account, known while locked too
+    pubkey: Pubkey,
+    /// Account of `pubkey`, derived again from the seed on unlock
+    account: u32,
+    auto_lock: Option<AutoLock>,
+    /// Whether an auto-lock watcher thread is running
+    watching: bool,
+    last_used: Instant,
+}
+
+#[derive(Debug)]
+struct AutoLock {
+    /// Keystore of the seed, or of the keypair if there is none
+    sealed: String,
+    sealed_seed: bool,
+    idle_timeout: Duration,
+}
+
+impl KeyVault {
+    pub(crate) fn new(keypair: Keypair, seed: Option<HdSeed>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(VaultState {
+                pubkey: keypair.pubkey(),
+                keys: Some(Keys { keypair, seed }),
+                account: 0,
+                auto_lock: None,
+                watching: false,
+                last_used: Instant::now(),
+            }),
+        })
+    }
+
+    /// Public key of the active account
+    pub(crate) fn pubkey(&self) -> Pubkey {
+        self.state.lock().unwrap().pubkey
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        self.state.lock().unwrap().keys.is_none()
+    }
+
+    /// Run `use_keys` on the keys, counting as use of the wallet. Fails with
+    /// `WalletLocked` while locked.
+    pub(crate) fn with_keys<T>(&self, use_keys: impl FnOnce(&Keys) -> Result<T>) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+        state.last_used = Instant::now();
+        use_keys(state.keys.as_ref().ok_or(WalletLocked)?)
+    }
+
+    /// Count the present as use of the wallet, failing if it is locked
+    pub(crate) fn touch(&self) -> Result<()> {
+        self.with_keys(|_| Ok(()))
+    }
+
+    /// Make `keypair`, of `account`, the one signed with
+    pub(crate) fn set_active(&self, account: u32, keypair: Keypair) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let keys = state.keys.as_mut().ok_or(WalletLocked)?;
+        state.pubkey = keypair.pubkey();
+        keys.keypair = keypair;
+        state.account = account;
+        Ok(())
+    }
+
+    /// Seal the keys under `password`, so they can be locked, and lock them
+    /// once `idle_timeout` passes without use, reporting it on `events`
+    pub(crate) fn enable_auto_lock(
+        self: &Arc<Self>,
+        password: &str,
+        idle_timeout: Duration,
+        events: EventBus,
+    ) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let keys = state.keys.as_ref().ok_or(WalletLocked)?;
+        state.auto_lock = Some(seal(keys, password, idle_timeout)?);
+        state.last_used = Instant::now();
+        if !state.watching {
+            state.watching = true;
+            let vault = Arc::downgrade(self);
+            std::thread::spawn(move || watch_idle(vault, events));
+        }
+        Ok(())
+    }
+
+    /// Stop locking on idle and let go of the sealed keys
+    pub(crate) fn disable_auto_lock(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.keys.as_ref().ok_or(WalletLocked)?;
+        state.auto_lock = None;
+        Ok(())
+    }
+
+    /// Seal the keys under `password` instead, if auto-lock is on
+    pub(crate) fn reseal(&self, password: &str) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let keys = state.keys.as_ref().ok_or(WalletLocked)?;
+        if let Some(auto_lock) = &state.auto_lock {
+            state.auto_lock = Some(seal(keys, password, auto_lock.idle_timeout)?);
+        }
+        Ok(())
+    }
+
+    /// Drop the keys. Returns whether they were held.
+    pub(crate) fn lock(&self) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if state.auto_lock.is_none() {
+            return Err(anyhow!(
+                "Auto-lock is not enabled, so the wallet could not be unlocked"
+            ));
+        }
+        Ok(state.keys.take().is_some())
+    }
+
+    /// Open the sealed keys with `password`. Returns whether they were
+    /// locked. A wrong password fails with `KeystoreError::WrongPassword`.
+    pub(crate) fn unlock(&self, password: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if state.keys.is_some() {
+            return Ok(false);
+        }
+        let auto_lock = state
+            .auto_lock
+            .as_ref()
+            .ok_or_else(|| anyhow!("Auto-lock is not enabled"))?;
+        let secret = Zeroizing::new(decrypt_keystore(&auto_lock.sealed, password)?);
+        let keys = if auto_lock.sealed_seed {
+            let seed = secret
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("Sealed seed has {} bytes", secret.len()))?;
+            let seed = HdSeed::from_bytes(seed);
+            Keys {
+                keypair: seed.derive_account(state.account)?,
+                seed: Some(seed),
+            }
+        } else {
+            Keys {
+                keypair: Keypair::from_bytes(&secret)
+                    .map_err(|e| anyhow!("Failed to create keypair: {}", e))?,
+                seed: None,
+            }
+        };
+        state.keys = Some(keys);
+        state.last_used = Instant::now();
+        Ok(true)
+    }
+
+    /// Lock the keys if auto-lock is on and they sat unused for its
+    /// timeout. Returns whether they were locked now, or `None` once
+    /// auto-lock is off.
+    fn lock_if_idle(&self) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        let idle_timeout = state.auto_lock.as_ref()?.idle_timeout;
+        let idle = state.keys.is_some() && state.last_used.elapsed() >= idle_timeout;
+        if idle {
+            state.keys = None;
+        }
+        Some(idle)
+    }
+}
+
+/// Auto-lock after `idle_timeout`, with a keystore of the seed, or else
+/// of the keypair, under `password`
+fn seal(keys: &Keys, password: &str, idle_timeout: Duration) -> Result<AutoLock> {
+    let (secret, sealed_seed) = match &keys.seed {
+        Some(seed) => (Zeroizing::new(seed.to_bytes()), true),
+        None => (Zeroizing::new(keys.keypair.to_bytes()), false),
+    };
+    Ok(AutoLock {
+        sealed: encrypt_keystore(secret.as_ref(), password)?,
+        sealed_seed,
+        idle_timeout,
+    })
+}
+
+/// Lock `vault` whenever it sits idle, until auto-lock is turned off or
+/// the wallet is dropped
+fn watch_idle(vault: Weak<KeyVault>, events: EventBus) {
+    loop {
+        std::thread::sleep(IDLE_CHECK_INTERVAL);
+        let Some(vault) = vault.upgrade() else {
+            return;
+        };
+        match vault.lock_if_idle() {
+            Some(true) => events.emit(WalletEvent::LockChanged { locked: true }),
+            Some(false) => {}
+            None => {
+                vault.state.lock().unwrap().watching = false;
+                return;
+            }
+        }
+    }
+}
+
+/// Signer of the vault's active account, for the privacy client, which
+/// fails while the wallet is locked
+#[derive(Debug, Clone)]
+pub(crate) struct VaultSigner(pub(crate) Arc<KeyVault>);
+
+impl Signer for VaultSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.0.pubkey())
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.0
+            .with_keys(|keys| Ok(keys.keypair.sign_message(message)))
+            .map_err(|e| SignerError::Custom(e.to_string()))
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::KeystoreError;
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let seed = HdSeed::from_bytes([3u8; 64]);
+        let vault = KeyVault::new(seed.derive_account(0).unwrap(), Some(seed.clone()));
+        vault
+            .set_active(2, seed.derive_account(2).unwrap())
+            .unwrap();
+        let pubkey = vault.pubkey();
+        assert!(vault.lock().is_err());
+
+        vault
+            .enable_auto_lock("hunter2", Duration::from_secs(60), EventBus::new())
+            .unwrap();
+        assert!(vault.lock().unwrap());
+        assert!(vault.is_locked());
+        assert_eq!(vault.pubkey(), pubkey);
+        let signer = VaultSigner(vault.clone());
+        assert!(signer.try_sign_message(b"message").is_err());
+        let err = vault.touch().unwrap_err();
+        assert_eq!(err.downcast_ref::<WalletLocked>(), Some(&WalletLocked));
+
+        let err = vault.unlock("hunter3").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KeystoreError>(),
+            Some(&KeystoreError::WrongPassword)
+        );
+        // The active account is derived again from the seed
+        assert!(vault.unlock("hunter2").unwrap());
+        assert!(!vault.unlock("hunter2").unwrap());
+        assert_eq!(vault.lock_if_idle(), Some(false));
+        let signature = signer.try_sign_message(b"message").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"message"));
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use solana_sdk::{
+    derivation_path::DerivationPath,
+    signature::{keypair_from_seed, keypair_from_seed_and_derivation_path, Keypair},
+};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+/// Why a recovery phrase was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MnemonicError {
+    #[error("Recovery phrase has {0} words, expected 12, 15, 18, 21 or 24")]
+    InvalidWordCount(usize),
+
+    /// Word `position` (from 1) is not in the BIP39 English word list
+    #[error("Word {position} ({word:?}) is not a BIP39 word")]
+    UnknownWord { position: usize, word: String },
+
+    /// Every word is valid but the phrase was mistyped or reordered
+    #[error("Recovery phrase checksum does not match")]
+    InvalidChecksum,
+}
+
+/// Length of a generated recovery phrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Enum))]
+pub enum WordCount {
+    /// 128 bits of entropy
+    Words12,
+    /// 256 bits of entropy
+    Words24,
+}
+
+/// New random recovery phrase of `words` English words
+pub fn generate_mnemonic(words: WordCount) -> String {
+    let mnemonic_type = match words {
+        WordCount::Words12 => MnemonicType::Words12,
+        WordCount::Words24 => MnemonicType::Words24,
+    };
+    Mnemonic::new(mnemonic_type, Language::English).into_phrase()
+}
+
+/// Check that `phrase` is a BIP39 English recovery phrase: a valid length,
+/// known words and a matching checksum. Case and spacing are ignored.
+pub fn validate_mnemonic(phrase: &str) -> std::result::Result<(), MnemonicError> {
+    parse_mnemonic(phrase).map(|_| ())
+}
+
+fn parse_mnemonic(phrase: &str) -> std::result::Result<Mnemonic, MnemonicError> {
+    let normalized = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    if MnemonicType::for_word_count(normalized.len()).is_err() {
+        return Err(MnemonicError::InvalidWordCount(normalized.len()));
+    }
+    let wordmap = Language::English.wordmap();
+    if let Some(position) = normalized
+        .iter()
+        .position(|word| wordmap.get_bits(word).is_err())
+    {
+        return Err(MnemonicError::UnknownWord {
+            position: position + 1,
+            word: normalized[position].clone(),
+        });
+    }
+
+    // Length and words are valid, so only the checksum is left to fail
+    Mnemonic::from_phrase(&normalized.join(" "), Language::English)
+        .map_err(|_| MnemonicError::InvalidChecksum)
+}
+
+/// BIP39 seed of a recovery phrase, from which every account of the
+/// wallet is derived. Zeroized when dropped.
+#[derive(Clone)]
+pub struct HdSeed([u8; 64]);
+
+impl Drop for HdSeed {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for HdSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HdSeed(..)")
+    }
+}
+
+impl HdSeed {
+    /// Seed of `phrase` with the optional BIP39 `passphrase`
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self> {
+        let mnemonic = parse_mnemonic(phrase)?;
+        let mut seed = [0u8; 64];
+        seed.copy_from_slice(Seed::new(&mnemonic, passphrase).as_bytes());
+        Ok(Self(seed))
+    }
+
+    /// Seed kept in backups, from `to_bytes`
+    pub(crate) fn from_bytes(seed: [u8; 64]) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn to_bytes(&self) -> [u8; 64] {
+        self.0
+    }
+
+    /// Keypair of `account` at `m/44'/501'/account'/0'`, the path Solana
+    /// wallets derive accounts at
+    pub fn derive_account(&self, account: u32) -> Result<Keypair> {
+        if account >= 1 << 31 {
+            return Err(anyhow!("Account index {} is out of range", account));
+        }
+        keypair_from_seed_and_derivation_path(
+            &self.0,
+            Some(DerivationPath::new_bip44(Some(account), Some(0))),
+        )
+        .map_err(|e| anyhow!("Failed to derive keypair: {}", e))
+    }
+
+    /// Keypair the Solana CLI gives this seed. Without a `derivation_path`
+    /// it is the one `solana-keygen new` and `recover` make by default, from
+    /// the first 32 bytes of the seed. With one, such as `m/44'/501'/0'/0'`,
+    /// it is the keypair at that path, every index hardened as the CLI
+    /// reads `--derivation-path`.
+    pub fn derive_cli(&self, derivation_path: Option<&str>) -> Result<Keypair> {
+        let keypair = match derivation_path {
+            None => keypair_from_seed(&self.0),
+            Some(path) => {
+                let path = DerivationPath::from_absolute_path_str(path)
+                    .map_err(|e| anyhow!("Invalid derivation path {:?}: {}", path, e))?;
+                keypair_from_seed_and_derivation_path(&self.0, Some(path))
+            }
+        };
+        keypair.map_err(|e| anyhow!("Failed to derive keypair: {}", e))
+    }
+}
+
+/// Keypair of `phrase` with the optional BIP39 `passphrase`, at the first
+/// account Solana wallets derive, `m/44'/501'/0'/0'`
+pub fn keypair_from_mnemonic(phrase: &str, passphrase: &str) -> Result<Keypair> {
+    HdSeed::from_mnemonic(phrase, passphrase)?.derive_account(0)
+}
+
+/// Keypair the Solana CLI restores from `phrase` and `passphrase`, as
+/// `HdSeed::derive_cli` derives it
+pub fn keypair_from_cli_mnemonic(
+    phrase: &str,
+    passphrase: &str,
+    derivation_path: Option<&str>,
+) -> Result<Keypair> {
+    HdSeed::from_mnemonic(phrase, passphrase)?.derive_cli(derivation_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn test_mnemonic_derivation_and_errors() {
+        let phrase = generate_mnemonic(WordCount::Words24);
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert_eq!(validate_mnemonic(&phrase), Ok(()));
+        assert_eq!(generate_mnemonic(WordCount::Words12).split(' ').count(), 12);
+
+        // Case and spacing do not change the key; the passphrase does
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon about";
+        let keypair = keypair_from_mnemonic(phrase, "").unwrap();
+        let shouted = format!("  {}  ", phrase.to_uppercase().replace(' ', "   "));
+        assert_eq!(
+            keypair_from_mnemonic(&shouted, "").unwrap().pubkey(),
+            keypair.pubkey()
+        );
+        assert_ne!(
+            keypair_from_mnemonic(phrase, "TREZOR").unwrap().pubkey(),
+            keypair.pubkey()
+        );
+
+        assert_eq!(
+            validate_mnemonic("abandon abandon abandon"),
+            Err(MnemonicError::InvalidWordCount(3))
+        );
+        assert_eq!(
+            validate_mnemonic(&phrase.replacen("abandon", "abandonn", 2)),
+            Err(MnemonicError::UnknownWord {
+                position: 1,
+                word: "abandonn".to_string(),
+            })
+        );
+        assert_eq!(
+            validate_mnemonic(&phrase.replace("about", "abandon")),
+            Err(MnemonicError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn test_accounts_derive_distinct_keys() {
+        let phrase = generate_mnemonic(WordCount::Words12);
+        let seed = HdSeed::from_mnemonic(&phrase, "").unwrap();
+        assert_eq!(
+            seed.derive_account(0).unwrap().pubkey(),
+            keypair_from_mnemonic(&phrase, "").unwrap().pubkey()
+        );
+        assert_eq!(
+            seed.derive_account(1).unwrap().pubkey(),
+            seed.derive_account(1).unwrap().pubkey()
+        );
+        assert_ne!(
+            seed.derive_account(0).unwrap().pubkey(),
+            seed.derive_account(1).unwrap().pubkey()
+        );
+        assert!(seed.derive_account(1 << 31).is_err());
+    }
+
+    #[test]
+    fn test_cli_derivation() {
+        use solana_sdk::signature::keypair_from_seed_phrase_and_passphrase;
+
+        // What `solana-keygen recover` restores without a derivation path
+        let phrase = generate_mnemonic(WordCount::Words12);
+        assert_eq!(
+            keypair_from_cli_mnemonic(&phrase, "pass", None).unwrap().pubkey(),
+            keypair_from_seed_phrase_and_passphrase(&phrase, "pass").unwrap().pubkey()
+        );
+        let seed = HdSeed::from_mnemonic(&phrase, "").unwrap();
+        assert_eq!(
+            seed.derive_cli(Some("m/44'/501'/1'/0'")).unwrap().pubkey(),
+            seed.derive_account(1).unwrap().pubkey()
+        );
+        assert_ne!(
+            seed.derive_cli(None).unwrap().pubkey(),
+            seed.derive_account(0).unwrap().pubkey()
+        );
+        assert!(seed.derive_cli(Some("44/501")).is_err());
+    }
+}
@@ -0,0 +1,663 @@
+use anyhow::anyhow;
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+use untrace_common::{PrivacyLevel, ShieldedNote};
+use untrace_privacy_client::FeeSponsor;
+
+use crate::{
+    backup::{decrypt_backup, RestoreOptions},
+    history::{Activity, ActivityKind, ActivityStatus, HistoryFilter},
+    lock::WalletLocked,
+    mnemonic::{generate_mnemonic, keypair_from_mnemonic, WordCount},
+    payment_request::PaymentRequest,
+    storage::{DepositNote, DepositStatus, SecureStorage, StorageBackend},
+    wallet::{RefundableTransfer, UntraceWallet, WalletConfig},
+};
+
+/// Error surfaced to Swift and Kotlin
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum MobileError {
+    #[error("{message}")]
+    Wallet { message: String },
+
+    /// The app's `PlatformKeystore` failed
+    #[error("Keystore error: {message}")]
+    Keystore { message: String },
+
+    /// The wallet is locked; `unlock` it first
+    #[error("Wallet is locked")]
+    Locked,
+}
+
+impl From<anyhow::Error> for MobileError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.is::<WalletLocked>() {
+            return MobileError::Locked;
+        }
+        MobileError::Wallet {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<uniffi::UnexpectedUniFFICallbackError> for MobileError {
+    fn from(error: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        MobileError::Keystore {
+            message: error.reason,
+        }
+    }
+}
+
+/// Secure key-value store the app implements over the iOS Keychain or the
+/// Android Keystore. Entries of one wallet share a `service`.
+#[uniffi::export(with_foreign)]
+pub trait PlatformKeystore: Send + Sync {
+    fn get(&self, service: String, key: String) -> Result<Option<Vec<u8>>, MobileError>;
+
+    fn put(&self, service: String, key: String, value: Vec<u8>) -> Result<(), MobileError>;
+
+    fn delete(&self, service: String, key: String) -> Result<(), MobileError>;
+}
+
+/// Backend over the app's `PlatformKeystore`, keeping one wallet's entries
+/// under `service`
+#[derive(Clone)]
+pub struct PlatformKeystoreBackend {
+    keystore: Arc<dyn PlatformKeystore>,
+    service: String,
+}
+
+impl fmt::Debug for PlatformKeystoreBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlatformKeystoreBackend")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+impl PlatformKeystoreBackend {
+    pub fn new(keystore: Arc<dyn PlatformKeystore>, service: impl Into<String>) -> Self {
+        Self {
+            keystore,
+            service: service.into(),
+        }
+    }
+}
+
+impl StorageBackend for PlatformKeystoreBackend {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.keystore.get(self.service.clone(), key.to_string())?)
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        Ok(self
+            .keystore
+            .put(self.service.clone(), key.to_string(), value.to_vec())?)
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<()> {
+        Ok(self
+            .keystore
+            .delete(self.service.clone(), key.to_string())?)
+    }
+}
+
+/// `PrivacyLevel` for the bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum MobilePrivacyLevel {
+    Basic,
+    Enhanced,
+    Maximum,
+}
+
+impl From<MobilePrivacyLevel> for PrivacyLevel {
+    fn from(level: MobilePrivacyLevel) -> Self {
+        match level {
+            MobilePrivacyLevel::Basic => PrivacyLevel::Basic,
+            MobilePrivacyLevel::Enhanced => PrivacyLevel::Enhanced,
+            MobilePrivacyLevel::Maximum => PrivacyLevel::Maximum,
+        }
+    }
+}
+
+impl From<PrivacyLevel> for MobilePrivacyLevel {
+    fn from(level: PrivacyLevel) -> Self {
+        match level {
+            PrivacyLevel::Basic => MobilePrivacyLevel::Basic,
+            PrivacyLevel::Enhanced => MobilePrivacyLevel::Enhanced,
+            PrivacyLevel::Maximum => MobilePrivacyLevel::Maximum,
+        }
+    }
+}
+
+/// `WalletConfig` for the bindings. Secrets go to the `PlatformKeystore`
+/// the wallet is opened with, in place of the OS keychain.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MobileWalletConfig {
+    pub default_privacy_level: MobilePrivacyLevel,
+    pub anti_mev_enabled: bool,
+    pub rpc_url: String,
+    pub program_id: String,
+    pub auto_mix_enabled: bool,
+    pub min_pool_size: u64,
+    /// Cluster as in the CLI, such as `mainnet-beta` or `devnet`
+    pub cluster: Option<String>,
+    /// Prefix of the keystore service each wallet's entries are kept under
+    pub keystore_service: String,
+    /// Directory in the app's container the history is kept in; kept in
+    /// memory only when unset
+    pub history_path: Option<String>,
+    /// Relayer paying the fees of withdrawals and private transfers
+    pub fee_sponsor: Option<MobileFeeSponsor>,
+}
+
+/// `FeeSponsor` for the bindings
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct MobileFeeSponsor {
+    /// Base58 public key of the account paying
+    pub fee_payer: String,
+    pub url: String,
+}
+
+/// Default config pointed at `cluster`, such as `devnet`
+#[uniffi::export]
+pub fn mobile_wallet_config(cluster: String) -> Result<MobileWalletConfig, MobileError> {
+    let config = WalletConfig::for_cluster(cluster.parse()?);
+    Ok(MobileWalletConfig {
+        default_privacy_level: config.default_privacy_level.into(),
+        anti_mev_enabled: config.anti_mev_enabled,
+        rpc_url: config.rpc_url,
+        program_id: config.program_id,
+        auto_mix_enabled: config.auto_mix_enabled,
+        min_pool_size: config.min_pool_size,
+        cluster: Some(cluster),
+        keystore_service: "untrace-wallet".to_string(),
+        history_path: None,
+        fee_sponsor: None,
+    })
+}
+
+impl MobileWalletConfig {
+    /// Config of the wallet whose first account is `pubkey`, keeping its
+    /// secrets in `keystore`
+    fn into_config(
+        self,
+        keystore: Arc<dyn PlatformKeystore>,
+        pubkey: &Pubkey,
+    ) -> Result<WalletConfig, MobileError> {
+        let service = format!("{}/{}", self.keystore_service, pubkey);
+        Ok(WalletConfig {
+            default_privacy_level: self.default_privacy_level.into(),
+            anti_mev_enabled: self.anti_mev_enabled,
+            rpc_url: self.rpc_url,
+            program_id: self.program_id,
+            auto_mix_enabled: self.auto_mix_enabled,
+            min_pool_size: self.min_pool_size,
+            cluster: self.cluster.map(|cluster| cluster.parse()).transpose()?,
+            keychain_service: None,
+            history_path: self.history_path.map(PathBuf::from),
+            storage_backend: Some(Arc::new(PlatformKeystoreBackend::new(keystore, service))),
+            fee_sponsor: self
+                .fee_sponsor
+                .map(|sponsor| {
+                    parse_pubkey(&sponsor.fee_payer)
+                        .map(|fee_payer| FeeSponsor::new(fee_payer, sponsor.url))
+                })
+                .transpose()?,
+        })
+    }
+}
+
+/// `ShieldedNote` for the bindings; `randomness` is the note's 32-byte
+/// secret
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct MobileNote {
+    /// SPL mint, or the all-zero native asset ID for SOL
+    pub asset_id: String,
+    pub amount: u64,
+    pub owner: String,
+    pub randomness: Vec<u8>,
+}
+
+impl From<ShieldedNote> for MobileNote {
+    fn from(note: ShieldedNote) -> Self {
+        Self {
+            asset_id: note.asset_id.to_string(),
+            amount: note.amount,
+            owner: note.owner.to_string(),
+            randomness: note.randomness.to_vec(),
+        }
+    }
+}
+
+impl TryFrom<MobileNote> for ShieldedNote {
+    type Error = MobileError;
+
+    fn try_from(note: MobileNote) -> Result<Self, MobileError> {
+        Ok(ShieldedNote {
+            asset_id: parse_pubkey(&note.asset_id)?,
+            amount: note.amount,
+            owner: parse_pubkey(&note.owner)?,
+            randomness: note
+                .randomness
+                .try_into()
+                .map_err(|_| anyhow!("Note randomness must be 32 bytes"))?,
+        })
+    }
+}
+
+/// `DepositNote` for the bindings, without its randomness, which never
+/// leaves storage
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct MobileDeposit {
+    pub pool_id: u64,
+    /// Hex commitment to pass to `withdraw_from_pool`
+    pub commitment: String,
+    pub leaf_index: u64,
+    pub amount: u64,
+    pub recipient: String,
+    pub status: DepositStatus,
+    pub created_at: i64,
+}
+
+impl From<DepositNote> for MobileDeposit {
+    fn from(deposit: DepositNote) -> Self {
+        Self {
+            pool_id: deposit.pool_id,
+            commitment: hex::encode(deposit.commitment),
+            leaf_index: deposit.leaf_index,
+            amount: deposit.amount,
+            recipient: deposit.recipient.to_string(),
+            status: deposit.status,
+            created_at: deposit.created_at,
+        }
+    }
+}
+
+/// `HistoryFilter` for the bindings
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct MobileHistoryFilter {
+    pub account: Option<u32>,
+    pub kind: Option<ActivityKind>,
+    pub status: Option<ActivityStatus>,
+    pub counterparty: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+impl From<MobileHistoryFilter> for HistoryFilter {
+    fn from(filter: MobileHistoryFilter) -> Self {
+        Self {
+            account: filter.account,
+            kind: filter.kind,
+            status: filter.status,
+            counterparty: filter.counterparty,
+            since: filter.since,
+            until: filter.until,
+            limit: filter.limit.map(|limit| limit as usize),
+        }
+    }
+}
+
+/// Private transfer sent, with the note to deliver to the recipient
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MobileTransfer {
+    pub signature: String,
+    pub recipient_note: MobileNote,
+}
+
+/// New recovery phrase of `words` words, for `MobileWallet::from_mnemonic`
+#[uniffi::export]
+pub fn generate_recovery_phrase(words: WordCount) -> String {
+    generate_mnemonic(words)
+}
+
+/// `UntraceWallet` for Swift and Kotlin, safe to share across threads.
+/// Calls that reach the network are async; the rest read local storage.
+#[derive(uniffi::Object)]
+pub struct MobileWallet {
+    wallet: RwLock<UntraceWallet>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MobileWallet {
+    /// Restore the wallet of a BIP39 recovery phrase, keeping its secrets
+    /// in `keystore` and the phrase encrypted with `password`
+    #[uniffi::constructor]
+    pub fn from_mnemonic(
+        phrase: String,
+        passphrase: String,
+        password: String,
+        config: MobileWalletConfig,
+        keystore: Arc<dyn PlatformKeystore>,
+    ) -> Result<Arc<Self>, MobileError> {
+        let pubkey = keypair_from_mnemonic(&phrase, &passphrase)?.pubkey();
+        let config = config.into_config(keystore, &pubkey)?;
+        let wallet = UntraceWallet::from_mnemonic(&phrase, &passphrase, &password, config)?;
+        Ok(Self::open(wallet))
+    }
+
+    /// Open the wallet of a keystore from `export_encrypted`
+    #[uniffi::constructor]
+    pub fn import_encrypted(
+        encrypted: String,
+        password: String,
+        config: MobileWalletConfig,
+        keystore: Arc<dyn PlatformKeystore>,
+    ) -> Result<Arc<Self>, MobileError> {
+        let keypair = SecureStorage::new()?.import_wallet(&encrypted, &password)?;
+        let config = config.into_config(keystore, &keypair.pubkey())?;
+        Ok(Self::open(UntraceWallet::from_keypair(keypair, config)?))
+    }
+
+    /// Open the wallet of an archive from `export_backup`, restoring all
+    /// of it
+    #[uniffi::constructor]
+    pub fn restore_backup(
+        archive: String,
+        password: String,
+        config: MobileWalletConfig,
+        keystore: Arc<dyn PlatformKeystore>,
+    ) -> Result<Arc<Self>, MobileError> {
+        let backup = decrypt_backup(&archive, &password)?;
+        let config = config.into_config(keystore, &backup.public_key()?)?;
+        let wallet = UntraceWallet::from_backup(&backup, &RestoreOptions::default(), config)?;
+        Ok(Self::open(wallet))
+    }
+
+    pub fn public_key(&self) -> String {
+        self.read().public_key().to_string()
+    }
+
+    pub fn recovery_phrase(&self, password: String) -> Result<String, MobileError> {
+        Ok(self.read().recovery_phrase(&password)?)
+    }
+
+    pub fn export_encrypted(&self, password: String) -> Result<String, MobileError> {
+        Ok(self.read().export_encrypted(&password)?)
+    }
+
+    /// Change the password the recovery phrase is kept under
+    pub fn change_password(
+        &self,
+        old_password: String,
+        new_password: String,
+    ) -> Result<(), MobileError> {
+        Ok(self
+            .wallet
+            .blocking_write()
+            .change_password(&old_password, &new_password)?)
+    }
+
+    /// Keys, notes, deposits, address book and history as one archive
+    /// encrypted with `password`
+    pub fn export_backup(&self, password: String) -> Result<String, MobileError> {
+        Ok(self.read().export_backup(&password)?)
+    }
+
+    /// Lock the wallet after `idle_seconds` without signing, until `unlock`
+    pub fn enable_auto_lock(&self, password: String, idle_seconds: u64) -> Result<(), MobileError> {
+        Ok(self.read().enable_auto_lock(&password, Duration::from_secs(idle_seconds))?)
+    }
+
+    pub fn disable_auto_lock(&self) -> Result<(), MobileError> {
+        Ok(self.read().disable_auto_lock()?)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.read().is_locked()
+    }
+
+    pub fn lock(&self) -> Result<(), MobileError> {
+        Ok(self.read().lock()?)
+    }
+
+    pub fn unlock(&self, password: String) -> Result<(), MobileError> {
+        Ok(self.read().unlock(&password)?)
+    }
+
+    /// Connect to the cluster; the async calls fail until this is done
+    pub async fn init_privacy_client(&self) -> Result<(), MobileError> {
+        Ok(self.wallet.write().await.init_privacy_client()?)
+    }
+
+    /// Switch to `account`, deriving accounts up to it as needed
+    pub async fn switch_account(&self, account: u32) -> Result<String, MobileError> {
+        let mut wallet = self.wallet.write().await;
+        while wallet.accounts().len() <= account as usize {
+            wallet.create_account()?;
+        }
+        wallet.switch_account(account)?;
+        Ok(wallet.public_key().to_string())
+    }
+
+    /// Transparent balance in lamports
+    pub async fn get_balance(&self) -> Result<u64, MobileError> {
+        Ok(self.wallet.read().await.get_balance().await?)
+    }
+
+    /// Deposit `amount` lamports into pool `pool_id`, withdrawable to
+    /// `recipient`, returning the signature; the deposit is kept in storage
+    pub async fn deposit_to_pool(
+        &self,
+        pool_id: u64,
+        recipient: String,
+        amount: u64,
+    ) -> Result<String, MobileError> {
+        let recipient = parse_pubkey(&recipient)?;
+        let wallet = self.wallet.read().await;
+        let (signature, _, _) = wallet.deposit_to_pool(pool_id, &recipient, amount).await?;
+        Ok(signature)
+    }
+
+    /// Withdraw the stored deposit of hex `commitment` to `recipient`
+    pub async fn withdraw_from_pool(
+        &self,
+        pool_id: u64,
+        commitment: String,
+        recipient: String,
+    ) -> Result<String, MobileError> {
+        let commitment: [u8; 32] = hex::decode(&commitment)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid commitment: {}", commitment))?;
+        let recipient = parse_pubkey(&recipient)?;
+        let wallet = self.wallet.read().await;
+        Ok(wallet
+            .withdraw_from_pool(pool_id, &commitment, &recipient)
+            .await?)
+    }
+
+    /// Send `amount` of `asset` privately from the stored notes
+    pub async fn send_private_transfer(
+        &self,
+        asset: String,
+        recipient: String,
+        amount: u64,
+        privacy_level: Option<MobilePrivacyLevel>,
+    ) -> Result<MobileTransfer, MobileError> {
+        let (asset, recipient) = (parse_pubkey(&asset)?, parse_pubkey(&recipient)?);
+        let wallet = self.wallet.read().await;
+        let (signature, note) = wallet
+            .send_private_token_transfer(&asset, &recipient, amount, privacy_level.map(Into::into))
+            .await?;
+        Ok(MobileTransfer {
+            signature,
+            recipient_note: note.into(),
+        })
+    }
+
+    /// Pay a `solana:` payment request URL, such as one scanned from a
+    /// merchant's QR code
+    pub async fn pay_request(&self, url: String) -> Result<MobileTransfer, MobileError> {
+        let request = PaymentRequest::parse(&url)?;
+        let wallet = self.wallet.read().await;
+        let (signature, note) = wallet.pay_request(&request).await?;
+        Ok(MobileTransfer {
+            signature,
+            recipient_note: note.into(),
+        })
+    }
+
+    pub async fn send_cross_chain_transfer(
+        &self,
+        dest_chain: u16,
+        recipient: String,
+        recipient_key: String,
+        amount: u64,
+        token: String,
+    ) -> Result<String, MobileError> {
+        let wallet = self.wallet.read().await;
+        Ok(wallet
+            .send_cross_chain_transfer(dest_chain, &recipient, &recipient_key, amount, &token)
+            .await?)
+    }
+
+    pub async fn refundable_bridge_transfers(
+        &self,
+    ) -> Result<Vec<RefundableTransfer>, MobileError> {
+        let wallet = self.wallet.read().await;
+        Ok(wallet.refundable_bridge_transfers().await?)
+    }
+
+    pub async fn refund_bridge_transfer(&self, address: String) -> Result<String, MobileError> {
+        let wallet = self.wallet.read().await;
+        Ok(wallet.refund_bridge_transfer(&address).await?)
+    }
+
+    /// Unspent notes of the active account
+    pub fn notes(&self) -> Result<Vec<MobileNote>, MobileError> {
+        let notes = self.read().notes()?;
+        Ok(notes.into_iter().map(Into::into).collect())
+    }
+
+    /// Keep a note received by the active account
+    pub fn save_note(&self, note: MobileNote) -> Result<(), MobileError> {
+        Ok(self.read().save_note(note.try_into()?)?)
+    }
+
+    /// Value of the active account's notes of `asset`
+    pub fn shielded_balance(&self, asset: String) -> Result<u64, MobileError> {
+        Ok(self.read().shielded_balance(&parse_pubkey(&asset)?)?)
+    }
+
+    /// Pool deposits of the active account
+    pub fn deposits(&self) -> Result<Vec<MobileDeposit>, MobileError> {
+        let deposits = self.read().deposits()?;
+        Ok(deposits.into_iter().map(Into::into).collect())
+    }
+
+    /// History entries matching `filter`, newest first
+    pub fn activity(&self, filter: MobileHistoryFilter) -> Result<Vec<Activity>, MobileError> {
+        Ok(self.read().activity(&filter.into())?)
+    }
+
+    pub fn export_history_csv(&self, filter: MobileHistoryFilter) -> Result<String, MobileError> {
+        let wallet = self.read();
+        let book = wallet.address_book()?;
+        Ok(wallet.history().export_csv(&filter.into(), &book)?)
+    }
+
+    pub fn export_history_json(&self, filter: MobileHistoryFilter) -> Result<String, MobileError> {
+        let wallet = self.read();
+        let book = wallet.address_book()?;
+        Ok(wallet.history().export_json(&filter.into(), &book)?)
+    }
+}
+
+impl MobileWallet {
+    fn open(wallet: UntraceWallet) -> Arc<Self> {
+        Arc::new(Self {
+            wallet: RwLock::new(wallet),
+        })
+    }
+
+    /// The wallet, for the calls that never await. The bindings make them
+    /// from the app's threads, outside any Tokio runtime, where blocking is
+    /// allowed.
+    fn read(&self) -> tokio::sync::RwLockReadGuard<'_, UntraceWallet> {
+        self.wallet.blocking_read()
+    }
+}
+
+fn parse_pubkey(address: &str) -> Result<Pubkey, MobileError> {
+    Ok(address
+        .parse()
+        .map_err(|_| anyhow!("Invalid address: {}", address))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, sync::Mutex};
+
+    #[derive(Default)]
+    struct FakeKeystore {
+        entries: Mutex<HashMap<(String, String), Vec<u8>>>,
+    }
+
+    impl PlatformKeystore for FakeKeystore {
+        fn get(&self, service: String, key: String) -> Result<Option<Vec<u8>>, MobileError> {
+            Ok(self.entries.lock().unwrap().get(&(service, key)).cloned())
+        }
+
+        fn put(&self, service: String, key: String, value: Vec<u8>) -> Result<(), MobileError> {
+            self.entries.lock().unwrap().insert((service, key), value);
+            Ok(())
+        }
+
+        fn delete(&self, service: String, key: String) -> Result<(), MobileError> {
+            self.entries.lock().unwrap().remove(&(service, key));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_mobile_wallet_keeps_secrets_in_platform_keystore() {
+        let keystore = Arc::new(FakeKeystore::default());
+        let phrase = generate_recovery_phrase(WordCount::Words12);
+        let config = mobile_wallet_config("devnet".to_string()).unwrap();
+        let wallet = MobileWallet::from_mnemonic(
+            phrase.clone(),
+            String::new(),
+            "password".to_string(),
+            config.clone(),
+            keystore.clone(),
+        )
+        .unwrap();
+
+        let owner = wallet.public_key();
+        let note = MobileNote {
+            asset_id: untrace_common::NATIVE_ASSET_ID.to_string(),
+            amount: 7,
+            owner: owner.clone(),
+            randomness: vec![3; 32],
+        };
+        wallet.save_note(note.clone()).unwrap();
+        assert!(keystore
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .all(|(service, _)| *service == format!("untrace-wallet/{}", owner)));
+
+        // Reopening the same phrase reads the same entries back
+        let reopened = MobileWallet::from_mnemonic(
+            phrase,
+            String::new(),
+            "password".to_string(),
+            config,
+            keystore,
+        )
+        .unwrap();
+        assert_eq!(reopened.notes().unwrap(), vec![note]);
+
+        let short = MobileNote {
+            randomness: vec![3; 31],
+            ..reopened.notes().unwrap().remove(0)
+        };
+        assert!(reopened.save_note(short).is_err());
+    }
+}
@@ -0,0 +1,540 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::{
+    compute_budget,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction::{self, SystemInstruction},
+    system_program,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use untrace_privacy_client::privacy_pool::decode_withdraw_recipient;
+
+/// Most co-signers a multisig can have, as many as an SPL token multisig
+/// takes
+pub const MAX_SIGNERS: usize = spl_token::instruction::MAX_SIGNERS;
+
+/// M-of-N multisig its co-signers share. Its authority is an SPL token
+/// multisig account, which owns the multisig's token accounts and which
+/// its pool deposits are made out to, so the deposits' commitments bind it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigConfig {
+    pub authority: Pubkey,
+    /// Co-signers needed to approve a transaction
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+impl MultisigConfig {
+    pub fn new(authority: Pubkey, threshold: u8, signers: Vec<Pubkey>) -> Result<Self> {
+        if signers.is_empty() || signers.len() > MAX_SIGNERS {
+            bail!(
+                "A multisig takes 1 to {} signers, not {}",
+                MAX_SIGNERS,
+                signers.len()
+            );
+        }
+        if threshold == 0 || threshold as usize > signers.len() {
+            bail!(
+                "Threshold {} is not between 1 and the {} signers",
+                threshold,
+                signers.len()
+            );
+        }
+        let mut unique = signers.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != signers.len() {
+            bail!("Multisig signers repeat");
+        }
+
+        Ok(Self {
+            authority,
+            threshold,
+            signers,
+        })
+    }
+
+    /// Instructions creating the authority account, with `lamports` of
+    /// rent paid by `payer`
+    pub fn create_instructions(&self, payer: &Pubkey, lamports: u64) -> Result<Vec<Instruction>> {
+        let signers: Vec<&Pubkey> = self.signers.iter().collect();
+        Ok(vec![
+            system_instruction::create_account(
+                payer,
+                &self.authority,
+                lamports,
+                spl_token::state::Multisig::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_multisig(
+                &spl_token::id(),
+                &self.authority,
+                &signers,
+                self.threshold,
+            )?,
+        ])
+    }
+
+    /// Token account of the multisig for `mint`, which its transparent
+    /// sends spend
+    pub fn token_account(&self, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address(&self.authority, mint)
+    }
+
+    /// Instructions sending `amount` of `mint` to `recipient` out of the
+    /// multisig's token account, paid by `payer` and approved by
+    /// `cosigners`. SOL is sent as wrapped SOL, since a system account
+    /// cannot have more than one owner.
+    pub fn send_instructions(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+        cosigners: &[Pubkey],
+    ) -> Result<Vec<Instruction>> {
+        self.check_cosigners(cosigners)?;
+        let cosigners: Vec<&Pubkey> = cosigners.iter().collect();
+        Ok(vec![
+            create_associated_token_account_idempotent(payer, recipient, mint, &spl_token::id()),
+            spl_token::instruction::transfer(
+                &spl_token::id(),
+                &self.token_account(mint),
+                &get_associated_token_address(recipient, mint),
+                &self.authority,
+                &cosigners,
+                amount,
+            )?,
+        ])
+    }
+
+    /// Pool withdrawal `withdraw` with `cosigners` added as its signers, so
+    /// the transaction carrying it, and the proof in it, only lands once
+    /// they approve
+    pub fn cosign_withdrawal(
+        &self,
+        mut withdraw: Instruction,
+        cosigners: &[Pubkey],
+    ) -> Result<Instruction> {
+        self.check_cosigners(cosigners)?;
+        withdraw.accounts.extend(
+            cosigners
+                .iter()
+                .map(|cosigner| AccountMeta::new_readonly(*cosigner, true)),
+        );
+        Ok(withdraw)
+    }
+
+    /// Check `cosigners` are distinct signers of the multisig, enough of
+    /// them to approve
+    fn check_cosigners(&self, cosigners: &[Pubkey]) -> Result<()> {
+        for (position, cosigner) in cosigners.iter().enumerate() {
+            if !self.signers.contains(cosigner) {
+                bail!("{} is not a signer of the multisig", cosigner);
+            }
+            if cosigners[..position].contains(cosigner) {
+                bail!("Co-signer {} repeats", cosigner);
+            }
+        }
+        if cosigners.len() < self.threshold as usize {
+            bail!(
+                "Multisig needs {} co-signers, not {}",
+                self.threshold,
+                cosigners.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// What a signing request asks the co-signers to approve
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultisigAction {
+    /// Transparent send out of the multisig's token account for `mint`
+    Send {
+        mint: Pubkey,
+        recipient: Pubkey,
+        amount: u64,
+    },
+    /// Withdrawal of a pool deposit made out to the multisig's authority
+    Withdraw {
+        pool_id: u64,
+        commitment: [u8; 32],
+        recipient: Pubkey,
+    },
+}
+
+/// Transaction waiting on the co-signers of a multisig. It is passed
+/// between them with `export` and `import`, signed by each with `sign`,
+/// and once `missing_signers` is empty `assemble` gives the transaction to
+/// submit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub config: MultisigConfig,
+    pub action: MultisigAction,
+    /// Co-signers the transaction waits on
+    pub cosigners: Vec<Pubkey>,
+    /// Transaction with the signatures collected so far, against a durable
+    /// nonce so it stays valid while they are
+    #[serde(with = "transaction_base58")]
+    pub transaction: Transaction,
+}
+
+impl SigningRequest {
+    /// Request for `cosigners` to approve `transaction`, which is checked
+    /// to do `action` and nothing else. `program_id` is the privacy
+    /// program's.
+    pub fn new(
+        config: MultisigConfig,
+        action: MultisigAction,
+        cosigners: Vec<Pubkey>,
+        transaction: Transaction,
+        program_id: &Pubkey,
+    ) -> Result<Self> {
+        let request = Self {
+            config,
+            action,
+            cosigners,
+            transaction,
+        };
+        request.check(program_id)?;
+        Ok(request)
+    }
+
+    /// JSON to hand to the other co-signers
+    pub fn export(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Request another co-signer exported, checked as `new` checks it
+    pub fn import(exported: &str, program_id: &Pubkey) -> Result<Self> {
+        let request: Self = serde_json::from_str(exported)?;
+        request.check(program_id)?;
+        Ok(request)
+    }
+
+    /// Sign as `signer`, one of the co-signers or the fee payer
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<()> {
+        let pubkey = signer.pubkey();
+        let position = self
+            .transaction
+            .get_signing_keypair_positions(&[pubkey])?
+            .first()
+            .copied()
+            .flatten()
+            .ok_or_else(|| anyhow!("Transaction does not need {}'s signature", pubkey))?;
+        self.transaction.signatures[position] =
+            signer.try_sign_message(&self.transaction.message_data())?;
+        Ok(())
+    }
+
+    /// Take the signatures `other`, the same request signed by other
+    /// co-signers, has and this one lacks
+    pub fn merge(&mut self, other: &SigningRequest) -> Result<()> {
+        if other.transaction.message != self.transaction.message {
+            bail!("Signing requests are for different transactions");
+        }
+        let data = self.transaction.message_data();
+        let keys = &self.transaction.message.account_keys;
+        for (position, signature) in other.transaction.signatures.iter().enumerate() {
+            if signature.verify(keys[position].as_ref(), &data) {
+                self.transaction.signatures[position] = *signature;
+            }
+        }
+        Ok(())
+    }
+
+    /// Signers whose signature the transaction still lacks
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        let data = self.transaction.message_data();
+        let keys = &self.transaction.message.account_keys;
+        self.transaction
+            .signatures
+            .iter()
+            .zip(keys)
+            .filter(|(signature, key)| !signature.verify(key.as_ref(), &data))
+            .map(|(_, key)| *key)
+            .collect()
+    }
+
+    /// Durable nonce account the transaction is built against
+    pub fn nonce_account(&self) -> Result<Pubkey> {
+        let message = &self.transaction.message;
+        message
+            .instructions
+            .first()
+            .and_then(|advance| advance.accounts.first())
+            .and_then(|&index| message.account_keys.get(index as usize))
+            .copied()
+            .ok_or_else(|| anyhow!("Transaction is not against a durable nonce"))
+    }
+
+    /// The transaction, once every signer it needs has signed
+    pub fn assemble(&self) -> Result<Transaction> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            bail!("Transaction still waits on {} signers", missing.len());
+        }
+        Ok(self.transaction.clone())
+    }
+
+    /// Check the transaction advances a durable nonce, needs every
+    /// co-signer's signature and otherwise only does `action`
+    fn check(&self, program_id: &Pubkey) -> Result<()> {
+        self.config.check_cosigners(&self.cosigners)?;
+        let message = &self.transaction.message;
+        if self.transaction.signatures.len() != message.header.num_required_signatures as usize {
+            bail!("Transaction has the wrong number of signatures");
+        }
+        let required = &message.account_keys[..message.header.num_required_signatures as usize];
+        if let Some(cosigner) = self.cosigners.iter().find(|key| !required.contains(key)) {
+            bail!("Transaction does not need {}'s signature", cosigner);
+        }
+
+        let mut instructions = decompile(message)?;
+        let advances_nonce = instructions.first().is_some_and(|instruction| {
+            instruction.program_id == system_program::id()
+                && bincode::deserialize::<SystemInstruction>(&instruction.data).ok()
+                    == Some(SystemInstruction::AdvanceNonceAccount)
+        });
+        if !advances_nonce {
+            bail!("Transaction is not against a durable nonce");
+        }
+        instructions.remove(0);
+        instructions.retain(|instruction| instruction.program_id != compute_budget::id());
+
+        let matches = match &self.action {
+            MultisigAction::Send {
+                mint,
+                recipient,
+                amount,
+            } => {
+                let expected = self.config.send_instructions(
+                    &message.account_keys[0],
+                    mint,
+                    recipient,
+                    *amount,
+                    &self.cosigners,
+                )?;
+                instructions.len() == expected.len()
+                    && instructions.iter().zip(&expected).all(|(found, expected)| {
+                        found.program_id == expected.program_id
+                            && found.data == expected.data
+                            && keys(found).eq(keys(expected))
+                    })
+            }
+            MultisigAction::Withdraw { recipient, .. } => match instructions.as_slice() {
+                [withdraw] => {
+                    withdraw.program_id == *program_id
+                        && decode_withdraw_recipient(&withdraw.data) == Some(*recipient)
+                        && self
+                            .cosigners
+                            .iter()
+                            .all(|cosigner| keys(withdraw).any(|key| key == cosigner))
+                }
+                _ => false,
+            },
+        };
+        if !matches {
+            bail!("Transaction does not do what the request asks to approve");
+        }
+        Ok(())
+    }
+}
+
+fn keys(instruction: &Instruction) -> impl Iterator<Item = &Pubkey> {
+    instruction.accounts.iter().map(|meta| &meta.pubkey)
+}
+
+/// Instructions of `message` with their accounts looked up
+fn decompile(message: &Message) -> Result<Vec<Instruction>> {
+    let key = |index: u8| {
+        message
+            .account_keys
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Instruction account index out of range"))
+    };
+    message
+        .instructions
+        .iter()
+        .map(|compiled| {
+            let accounts = compiled
+                .accounts
+                .iter()
+                .map(|&index| {
+                    Ok(AccountMeta {
+                        pubkey: key(index)?,
+                        is_signer: message.is_signer(index as usize),
+                        is_writable: message.is_writable(index as usize),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok(Instruction {
+                program_id: key(compiled.program_id_index)?,
+                accounts,
+                data: compiled.data.clone(),
+            })
+        })
+        .collect()
+}
+
+mod transaction_base58 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        transaction: &Transaction,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = bincode::serialize(transaction).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&bs58::encode(bytes).into_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Transaction, D::Error> {
+        let bytes = bs58::decode(String::deserialize(deserializer)?)
+            .into_vec()
+            .map_err(serde::de::Error::custom)?;
+        bincode::deserialize(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, signature::Keypair};
+    use untrace_privacy_client::nonce::nonce_transaction;
+
+    fn multisig(threshold: u8) -> (MultisigConfig, Vec<Keypair>) {
+        let keypairs: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+        let signers = keypairs.iter().map(|keypair| keypair.pubkey()).collect();
+        let config = MultisigConfig::new(Pubkey::new_unique(), threshold, signers).unwrap();
+        (config, keypairs)
+    }
+
+    fn send_request(
+        config: &MultisigConfig,
+        payer: &Pubkey,
+        cosigners: &[Pubkey],
+        action: MultisigAction,
+        sent_amount: u64,
+    ) -> Result<SigningRequest> {
+        let nonce_account = Pubkey::new_unique();
+        let MultisigAction::Send {
+            mint, recipient, ..
+        } = action
+        else {
+            unreachable!()
+        };
+        let instructions =
+            config.send_instructions(payer, &mint, &recipient, sent_amount, cosigners)?;
+        let transaction = nonce_transaction(
+            &instructions,
+            payer,
+            &nonce_account,
+            payer,
+            Hash::new_unique(),
+        );
+        SigningRequest::new(
+            config.clone(),
+            action,
+            cosigners.to_vec(),
+            transaction,
+            &Pubkey::new_unique(),
+        )
+    }
+
+    #[test]
+    fn test_multisig_send_collects_signatures() {
+        let (config, keypairs) = multisig(2);
+        let payer = Keypair::new();
+        let cosigners = [keypairs[0].pubkey(), keypairs[2].pubkey()];
+        let action = MultisigAction::Send {
+            mint: spl_token::native_mint::id(),
+            recipient: Pubkey::new_unique(),
+            amount: 5_000,
+        };
+        let program_id = Pubkey::new_unique();
+
+        let mut request =
+            send_request(&config, &payer.pubkey(), &cosigners, action, 5_000).unwrap();
+        request.sign(&payer).unwrap();
+        assert!(request.sign(&keypairs[1]).is_err());
+        let mut missing = request.missing_signers();
+        missing.sort_unstable();
+        let mut expected = cosigners.to_vec();
+        expected.sort_unstable();
+        assert_eq!(missing, expected);
+
+        // Each co-signer signs the exported request on their own
+        let exported = request.export().unwrap();
+        let mut first = SigningRequest::import(&exported, &program_id).unwrap();
+        first.sign(&keypairs[0]).unwrap();
+        let mut second = SigningRequest::import(&exported, &program_id).unwrap();
+        second.sign(&keypairs[2]).unwrap();
+
+        assert!(request.assemble().is_err());
+        request.merge(&first).unwrap();
+        request.merge(&second).unwrap();
+        assert!(request.missing_signers().is_empty());
+        let transaction = request.assemble().unwrap();
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[test]
+    fn test_multisig_rejects_invalid() {
+        let (config, keypairs) = multisig(2);
+        let pubkeys: Vec<Pubkey> = keypairs.iter().map(|keypair| keypair.pubkey()).collect();
+        assert!(MultisigConfig::new(Pubkey::new_unique(), 0, pubkeys.clone()).is_err());
+        assert!(MultisigConfig::new(Pubkey::new_unique(), 4, pubkeys.clone()).is_err());
+        let repeated = vec![pubkeys[0], pubkeys[0]];
+        assert!(MultisigConfig::new(Pubkey::new_unique(), 1, repeated).is_err());
+
+        let payer = Pubkey::new_unique();
+        let action = MultisigAction::Send {
+            mint: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: 100,
+        };
+        // Too few co-signers, or someone outside the multisig
+        assert!(send_request(&config, &payer, &pubkeys[..1], action.clone(), 100).is_err());
+        let outsider = [pubkeys[0], Pubkey::new_unique()];
+        assert!(send_request(&config, &payer, &outsider, action.clone(), 100).is_err());
+        // A transaction sending more than the request shows co-signers
+        assert!(send_request(&config, &payer, &pubkeys[..2], action.clone(), 1_000).is_err());
+
+        // A withdrawal must pay the recipient it names
+        let program_id = Pubkey::new_unique();
+        let withdraw = Instruction::new_with_bytes(program_id, &[0; 8], vec![]);
+        let withdraw = config.cosign_withdrawal(withdraw, &pubkeys[..2]).unwrap();
+        let transaction = nonce_transaction(
+            &[withdraw],
+            &payer,
+            &Pubkey::new_unique(),
+            &payer,
+            Hash::new_unique(),
+        );
+        let action = MultisigAction::Withdraw {
+            pool_id: 0,
+            commitment: [1; 32],
+            recipient: Pubkey::new_unique(),
+        };
+        let request = SigningRequest::new(
+            config,
+            action,
+            pubkeys[..2].to_vec(),
+            transaction,
+            &program_id,
+        );
+        assert!(request.is_err());
+    }
+}
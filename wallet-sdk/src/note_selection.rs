@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    fmt::Debug,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use untrace_common::ShieldedNote;
+
+use crate::storage::NoteOrigin;
+
+/// Seconds after which notes that came out of the same transaction may be
+/// spent together by `PrivacyMax`
+pub const DEFAULT_LINK_WINDOW: i64 = 24 * 60 * 60;
+
+/// Unspent note offered to a `NoteSelector`, with where it came from if
+/// the wallet knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpendableNote {
+    pub note: ShieldedNote,
+    pub origin: Option<NoteOrigin>,
+}
+
+impl SpendableNote {
+    /// Whether `self` and `other` came out of one transaction, and the
+    /// newer of them within `window` seconds of `now`
+    pub(crate) fn recently_linked(&self, other: &SpendableNote, window: i64, now: i64) -> bool {
+        match (&self.origin, &other.origin) {
+            (Some(a), Some(b)) => {
+                a.signature == b.signature && a.received_at.max(b.received_at) > now - window
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Chooses which notes pay for a spend. Fewer notes mean a smaller proof
+/// and fee, but which notes are spent together is what an observer of
+/// timing and amounts could link.
+pub trait NoteSelector: Debug + Send + Sync {
+    /// Notes of `asset_id` from `notes`, which are oldest first, covering
+    /// `amount` with at most `max` of them
+    fn select(
+        &self,
+        notes: &[SpendableNote],
+        asset_id: &Pubkey,
+        amount: u64,
+        max: usize,
+    ) -> Result<Vec<ShieldedNote>>;
+}
+
+/// The smallest single note that covers the amount, else the largest
+/// notes first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FewestNotes;
+
+impl NoteSelector for FewestNotes {
+    fn select(
+        &self,
+        notes: &[SpendableNote],
+        asset_id: &Pubkey,
+        amount: u64,
+        max: usize,
+    ) -> Result<Vec<ShieldedNote>> {
+        let mut candidates = candidates(notes, asset_id);
+        candidates.sort_by_key(|note| note.note.amount);
+
+        if let Some(note) = candidates.iter().find(|note| note.note.amount >= amount) {
+            return Ok(vec![note.note.clone()]);
+        }
+        let selected: Vec<&SpendableNote> = candidates.into_iter().rev().take(max).collect();
+        covering(selected, asset_id, amount)
+    }
+}
+
+/// The oldest notes first, so none are left sitting in the wallet long
+/// enough to stand out. Falls back to `FewestNotes` when the oldest `max`
+/// notes do not cover the amount.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OldestFirst;
+
+impl NoteSelector for OldestFirst {
+    fn select(
+        &self,
+        notes: &[SpendableNote],
+        asset_id: &Pubkey,
+        amount: u64,
+        max: usize,
+    ) -> Result<Vec<ShieldedNote>> {
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for note in candidates(notes, asset_id).into_iter().take(max) {
+            total = total.saturating_add(note.note.amount);
+            selected.push(note.note.clone());
+            if total >= amount {
+                return Ok(selected);
+            }
+        }
+        FewestNotes.select(notes, asset_id, amount, max)
+    }
+}
+
+/// A single note whenever one covers the amount, and otherwise never two
+/// notes that came out of the same transaction within `link_window`
+/// seconds, which spending together would tie to one owner. Fails rather
+/// than merge them.
+#[derive(Debug, Clone, Copy)]
+pub struct PrivacyMax {
+    pub link_window: i64,
+}
+
+impl Default for PrivacyMax {
+    fn default() -> Self {
+        Self {
+            link_window: DEFAULT_LINK_WINDOW,
+        }
+    }
+}
+
+impl PrivacyMax {
+    fn select_at(
+        &self,
+        notes: &[SpendableNote],
+        asset_id: &Pubkey,
+        amount: u64,
+        max: usize,
+        now: i64,
+    ) -> Result<Vec<ShieldedNote>> {
+        let mut candidates = candidates(notes, asset_id);
+        candidates.sort_by_key(|note| note.note.amount);
+        if let Some(note) = candidates.iter().find(|note| note.note.amount >= amount) {
+            return Ok(vec![note.note.clone()]);
+        }
+
+        let mut selected: Vec<&SpendableNote> = Vec::new();
+        let mut total = 0u64;
+        for note in candidates.into_iter().rev() {
+            if selected.len() == max || total >= amount {
+                break;
+            }
+            if selected
+                .iter()
+                .any(|other| note.recently_linked(other, self.link_window, now))
+            {
+                continue;
+            }
+            total = total.saturating_add(note.note.amount);
+            selected.push(note);
+        }
+        if total < amount && FewestNotes.select(notes, asset_id, amount, max).is_ok() {
+            return Err(anyhow!(
+                "Covering {} of {} needs notes from one recent transaction spent together",
+                amount,
+                asset_id
+            ));
+        }
+        covering(selected, asset_id, amount)
+    }
+}
+
+impl NoteSelector for PrivacyMax {
+    fn select(
+        &self,
+        notes: &[SpendableNote],
+        asset_id: &Pubkey,
+        amount: u64,
+        max: usize,
+    ) -> Result<Vec<ShieldedNote>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        self.select_at(notes, asset_id, amount, max, now)
+    }
+}
+
+/// Notes of `asset_id` with value, in their given order
+fn candidates<'a>(notes: &'a [SpendableNote], asset_id: &Pubkey) -> Vec<&'a SpendableNote> {
+    notes
+        .iter()
+        .filter(|note| note.note.asset_id == *asset_id && note.note.amount > 0)
+        .collect()
+}
+
+fn covering(
+    selected: Vec<&SpendableNote>,
+    asset_id: &Pubkey,
+    amount: u64,
+) -> Result<Vec<ShieldedNote>> {
+    let total = selected
+        .iter()
+        .fold(0u64, |sum, note| sum.saturating_add(note.note.amount));
+    if total < amount {
+        return Err(anyhow!(
+            "Insufficient shielded balance: {} notes of {} hold {}, {} needed",
+            selected.len(),
+            asset_id,
+            total,
+            amount
+        ));
+    }
+    Ok(selected.into_iter().map(|note| note.note.clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(asset_id: Pubkey, amount: u64, origin: Option<(&str, i64)>) -> SpendableNote {
+        SpendableNote {
+            note: ShieldedNote {
+                asset_id,
+                amount,
+                owner: Pubkey::new_unique(),
+                randomness: [amount as u8; 32],
+            },
+            origin: origin.map(|(signature, received_at)| NoteOrigin {
+                signature: signature.to_string(),
+                received_at,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_selectors() {
+        let mint = Pubkey::new_unique();
+        let notes = vec![
+            note(mint, 10, Some(("a", 100))),
+            note(mint, 30, Some(("b", 200))),
+            note(mint, 20, Some(("b", 200))),
+        ];
+        let amounts = |selected: Vec<ShieldedNote>| -> Vec<u64> {
+            selected.iter().map(|note| note.amount).collect()
+        };
+
+        assert_eq!(
+            amounts(FewestNotes.select(&notes, &mint, 25, 2).unwrap()),
+            vec![30]
+        );
+        assert_eq!(
+            amounts(OldestFirst.select(&notes, &mint, 25, 2).unwrap()),
+            vec![10, 30]
+        );
+        // The oldest two fall short, so the largest two are taken
+        assert_eq!(
+            amounts(OldestFirst.select(&notes, &mint, 45, 2).unwrap()),
+            vec![30, 20]
+        );
+
+        // The 30 and 20 notes came out of one transaction
+        let privacy = PrivacyMax { link_window: 1_000 };
+        assert_eq!(
+            amounts(privacy.select_at(&notes, &mint, 35, 2, 500).unwrap()),
+            vec![30, 10]
+        );
+        assert!(privacy.select_at(&notes, &mint, 45, 2, 500).is_err());
+        // Once the link is old enough they may be spent together
+        assert_eq!(
+            amounts(privacy.select_at(&notes, &mint, 45, 2, 5_000).unwrap()),
+            vec![30, 20]
+        );
+    }
+}
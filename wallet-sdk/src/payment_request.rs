@@ -0,0 +1,277 @@
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use untrace_common::{PrivacyLevel, NATIVE_ASSET_ID};
+
+use crate::tokens::{format_amount, parse_amount};
+
+/// Decimals of SOL amounts, in lamports
+pub const SOL_DECIMALS: u8 = 9;
+
+/// Query parameter carrying the privacy level, which other Solana Pay
+/// wallets ignore
+pub const PRIVACY_LEVEL_PARAM: &str = "privacy-level";
+
+const URL_SCHEME: &str = "solana:";
+
+/// Merchant's request for a shielded payment, carried as a Solana Pay
+/// transfer request URL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub recipient: Pubkey,
+    /// Amount in whole SOL or tokens as the URL writes it, such as `1.5`;
+    /// `None` leaves it to the payer
+    pub amount: Option<String>,
+    /// Mint paid in; `None` is SOL
+    pub spl_token: Option<Pubkey>,
+    /// Keys the merchant tells its payments apart by
+    pub reference: Vec<Pubkey>,
+    /// Who is asking, such as the shop's name
+    pub label: Option<String>,
+    /// What the payment is for, shown to the payer
+    pub message: Option<String>,
+    pub memo: Option<String>,
+    /// Privacy level the merchant wants the payment sent at
+    pub privacy_level: Option<PrivacyLevel>,
+}
+
+impl PaymentRequest {
+    pub fn new(recipient: Pubkey) -> Self {
+        Self {
+            recipient,
+            amount: None,
+            spl_token: None,
+            reference: Vec::new(),
+            label: None,
+            message: None,
+            memo: None,
+            privacy_level: None,
+        }
+    }
+
+    /// Ask for `amount` base units of a mint with `decimals` decimals
+    pub fn with_amount(mut self, amount: u64, decimals: u8) -> Self {
+        self.amount = Some(format_amount(amount, decimals));
+        self
+    }
+
+    pub fn with_token(mut self, mint: Pubkey) -> Self {
+        self.spl_token = Some(mint);
+        self
+    }
+
+    pub fn with_reference(mut self, reference: Pubkey) -> Self {
+        self.reference.push(reference);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn with_privacy_level(mut self, level: PrivacyLevel) -> Self {
+        self.privacy_level = Some(level);
+        self
+    }
+
+    /// Shielded asset the payment is in: the SPL mint, or
+    /// `NATIVE_ASSET_ID` for SOL
+    pub fn asset_id(&self) -> Pubkey {
+        self.spl_token.unwrap_or(NATIVE_ASSET_ID)
+    }
+
+    /// Amount in base units of an asset with `decimals` decimals
+    pub fn base_units(&self, decimals: u8) -> Result<Option<u64>> {
+        self.amount
+            .as_deref()
+            .map(|amount| parse_amount(amount, decimals))
+            .transpose()
+    }
+
+    /// Encode as a `solana:` URL
+    pub fn to_url(&self) -> String {
+        let mut params: Vec<(&str, String)> = Vec::new();
+        if let Some(amount) = &self.amount {
+            params.push(("amount", amount.clone()));
+        }
+        if let Some(mint) = &self.spl_token {
+            params.push(("spl-token", mint.to_string()));
+        }
+        for reference in &self.reference {
+            params.push(("reference", reference.to_string()));
+        }
+        for (key, value) in [
+            ("label", &self.label),
+            ("message", &self.message),
+            ("memo", &self.memo),
+        ] {
+            if let Some(value) = value {
+                params.push((
+                    key,
+                    utf8_percent_encode(value, NON_ALPHANUMERIC).to_string(),
+                ));
+            }
+        }
+        if let Some(level) = self.privacy_level {
+            params.push((PRIVACY_LEVEL_PARAM, privacy_level_name(level).to_string()));
+        }
+
+        let query: Vec<String> = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        if query.is_empty() {
+            format!("{}{}", URL_SCHEME, self.recipient)
+        } else {
+            format!("{}{}?{}", URL_SCHEME, self.recipient, query.join("&"))
+        }
+    }
+
+    /// Decode a `solana:` transfer request URL. Unknown parameters are
+    /// ignored, as Solana Pay asks of wallets.
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix(URL_SCHEME)
+            .ok_or_else(|| anyhow!("Not a Solana Pay URL: {}", url))?;
+        let (recipient, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if recipient.starts_with("https") {
+            bail!("Transaction request URL, not a payment request: {}", url);
+        }
+
+        let mut request = Self::new(
+            recipient
+                .parse()
+                .map_err(|_| anyhow!("Invalid recipient: {}", recipient))?,
+        );
+        let mut seen = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode_str(value).decode_utf8()?.into_owned();
+            if key != "reference" {
+                if seen.contains(&key) {
+                    bail!("Payment request repeats {}", key);
+                }
+                seen.push(key);
+            }
+
+            match key {
+                "amount" => {
+                    let (whole, fraction) = value.split_once('.').unwrap_or((&value, ""));
+                    let digits = whole.bytes().chain(fraction.bytes());
+                    if whole.is_empty() || !digits.clone().all(|b| b.is_ascii_digit()) {
+                        bail!("Invalid amount: {}", value);
+                    }
+                    request.amount = Some(value);
+                }
+                "spl-token" => request.spl_token = Some(parse_key(&value)?),
+                "reference" => request.reference.push(parse_key(&value)?),
+                "label" => request.label = Some(value),
+                "message" => request.message = Some(value),
+                "memo" => request.memo = Some(value),
+                PRIVACY_LEVEL_PARAM => {
+                    request.privacy_level = Some(match value.as_str() {
+                        "basic" => PrivacyLevel::Basic,
+                        "enhanced" => PrivacyLevel::Enhanced,
+                        "maximum" => PrivacyLevel::Maximum,
+                        _ => bail!("Unknown privacy level: {}", value),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+
+    /// QR code of the URL as an SVG at least `size` pixels square, for
+    /// the payer to scan
+    pub fn qr_svg(&self, size: u32) -> Result<String> {
+        Ok(QrCode::new(self.to_url())?
+            .render::<svg::Color>()
+            .min_dimensions(size, size)
+            .build())
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_url())
+    }
+}
+
+fn privacy_level_name(level: PrivacyLevel) -> &'static str {
+    match level {
+        PrivacyLevel::Basic => "basic",
+        PrivacyLevel::Enhanced => "enhanced",
+        PrivacyLevel::Maximum => "maximum",
+    }
+}
+
+fn parse_key(value: &str) -> Result<Pubkey> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid public key: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payment_request_round_trip() {
+        let request = PaymentRequest::new(Pubkey::new_unique())
+            .with_amount(1_500_000, 6)
+            .with_token(Pubkey::new_unique())
+            .with_reference(Pubkey::new_unique())
+            .with_reference(Pubkey::new_unique())
+            .with_label("Coffee & Co")
+            .with_message("Order #42")
+            .with_privacy_level(PrivacyLevel::Maximum);
+
+        let url = request.to_url();
+        assert!(url.contains("amount=1.5&"));
+        assert!(!url.contains(' ') && !url.contains('#'));
+        let parsed = PaymentRequest::parse(&url).unwrap();
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.base_units(6).unwrap(), Some(1_500_000));
+
+        let bare = PaymentRequest::new(request.recipient);
+        assert_eq!(bare.to_url(), format!("solana:{}", request.recipient));
+        assert_eq!(PaymentRequest::parse(&bare.to_url()).unwrap(), bare);
+        assert!(request.qr_svg(256).unwrap().contains("<svg"));
+    }
+
+    #[test]
+    fn test_payment_request_rejects_invalid() {
+        let recipient = Pubkey::new_unique();
+        for url in [
+            format!("bitcoin:{}", recipient),
+            "solana:not-a-key".to_string(),
+            "solana:https://shop.example/pay".to_string(),
+            format!("solana:{}?amount=-1", recipient),
+            format!("solana:{}?amount=1&amount=2", recipient),
+            format!("solana:{}?privacy-level=total", recipient),
+        ] {
+            assert!(PaymentRequest::parse(&url).is_err(), "{}", url);
+        }
+
+        // Other wallets' parameters are ignored
+        let url = format!("solana:{}?amount=0.01&redirect=x", recipient);
+        let request = PaymentRequest::parse(&url).unwrap();
+        assert_eq!(request.base_units(SOL_DECIMALS).unwrap(), Some(10_000_000));
+        assert_eq!(request.asset_id(), NATIVE_ASSET_ID);
+    }
+}
@@ -0,0 +1,322 @@
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use untrace_common::PrivacyLevel;
+
+use crate::address_book::normalize_address;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Kind of transfer out of the wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpendKind {
+    PrivateTransfer,
+    Bridge,
+    Withdrawal,
+}
+
+/// Transfer the policy is asked to allow before it is built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpendRequest {
+    pub kind: SpendKind,
+    /// SPL mint, or `NATIVE_ASSET_ID` for SOL
+    pub asset: Pubkey,
+    /// Amount in the asset's base units
+    pub amount: u64,
+    pub recipient: String,
+    /// Privacy level of a private transfer
+    pub privacy_level: Option<PrivacyLevel>,
+}
+
+/// Limits on spending one asset, in its base units
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssetLimits {
+    /// Most spent per UTC day
+    pub daily_limit: Option<u64>,
+    /// Amounts of at least this need the confirmation callback's approval
+    pub confirm_above: Option<u64>,
+    /// Private transfers of at least `.0` need privacy level `.1` or higher
+    pub privacy_thresholds: Vec<(u64, PrivacyLevel)>,
+}
+
+/// Rules every transfer out of the wallet must pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpendingPolicy {
+    /// Limits by asset; assets without an entry are unlimited
+    pub limits: HashMap<Pubkey, AssetLimits>,
+    /// When set, the only recipients allowed
+    pub allowlist: Option<Vec<String>>,
+    /// Recipients never allowed
+    pub denylist: Vec<String>,
+}
+
+/// Why the policy refused a transfer
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PolicyViolation {
+    #[error("{recipient} is on the deny list")]
+    RecipientDenied { recipient: String },
+
+    #[error("{recipient} is not on the allow list")]
+    RecipientNotAllowed { recipient: String },
+
+    #[error("Daily limit of {limit} exceeded: {spent} spent today, {amount} more requested")]
+    DailyLimitExceeded { limit: u64, spent: u64, amount: u64 },
+
+    #[error("Transfers of {amount} need privacy level {required:?} or higher")]
+    PrivacyLevelTooLow { required: PrivacyLevel, amount: u64 },
+
+    /// The transfer needed confirmation and the callback declined it, or
+    /// none was set
+    #[error("Transfer of {amount} was not confirmed")]
+    NotConfirmed { amount: u64 },
+}
+
+/// Asked to approve transfers over an asset's `confirm_above`, such as
+/// by prompting the user
+pub type ConfirmationCallback = Arc<dyn Fn(&SpendRequest) -> bool + Send + Sync>;
+
+/// Applies a `SpendingPolicy`, tracking what was spent today
+#[derive(Default)]
+pub struct PolicyEngine {
+    policy: SpendingPolicy,
+    confirmation: Option<ConfirmationCallback>,
+    /// Day since the Unix epoch and amount spent on it, by asset
+    spent: Mutex<HashMap<Pubkey, (u64, u64)>>,
+}
+
+impl fmt::Debug for PolicyEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolicyEngine")
+            .field("policy", &self.policy)
+            .field("confirmation", &self.confirmation.is_some())
+            .finish()
+    }
+}
+
+impl PolicyEngine {
+    pub fn new(policy: SpendingPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    pub fn policy(&self) -> &SpendingPolicy {
+        &self.policy
+    }
+
+    /// Apply `policy` from now on, keeping what was spent today
+    pub fn set_policy(&mut self, policy: SpendingPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn set_confirmation(&mut self, confirmation: Option<ConfirmationCallback>) {
+        self.confirmation = confirmation;
+    }
+
+    /// Amount of `asset` spent on the UTC day of Unix time `now`
+    pub fn spent_today(&self, asset: &Pubkey, now: u64) -> u64 {
+        match self.spent.lock().unwrap().get(asset) {
+            Some((day, spent)) if *day == now / SECONDS_PER_DAY => *spent,
+            _ => 0,
+        }
+    }
+
+    /// Check `request` against the policy at Unix time `now`, asking for
+    /// confirmation if its amount needs it, and count it against today's
+    /// limit. A transfer that is then not sent must be `release`d.
+    pub fn authorize(&self, request: &SpendRequest, now: u64) -> Result<(), PolicyViolation> {
+        let recipient = normalize_address(&request.recipient).ok();
+        let listed = |list: &[String]| {
+            list.iter()
+                .any(|entry| normalize_address(entry).ok() == recipient)
+        };
+        if listed(&self.policy.denylist) {
+            return Err(PolicyViolation::RecipientDenied {
+                recipient: request.recipient.clone(),
+            });
+        }
+        if let Some(allowlist) = &self.policy.allowlist {
+            if !listed(allowlist) {
+                return Err(PolicyViolation::RecipientNotAllowed {
+                    recipient: request.recipient.clone(),
+                });
+            }
+        }
+
+        let Some(limits) = self.policy.limits.get(&request.asset) else {
+            return Ok(());
+        };
+        if let Some(level) = request.privacy_level {
+            let required = limits
+                .privacy_thresholds
+                .iter()
+                .filter(|(threshold, _)| request.amount >= *threshold)
+                .map(|(_, required)| *required)
+                .max();
+            if let Some(required) = required.filter(|required| level < *required) {
+                return Err(PolicyViolation::PrivacyLevelTooLow {
+                    required,
+                    amount: request.amount,
+                });
+            }
+        }
+        // Check the limit before asking, so the user is not prompted for a
+        // transfer that would be refused anyway
+        check_daily_limit(limits, request, self.spent_today(&request.asset, now))?;
+        if limits
+            .confirm_above
+            .is_some_and(|threshold| request.amount >= threshold)
+        {
+            let confirmed = self
+                .confirmation
+                .as_ref()
+                .is_some_and(|confirm| confirm(request));
+            if !confirmed {
+                return Err(PolicyViolation::NotConfirmed {
+                    amount: request.amount,
+                });
+            }
+        }
+
+        // Check again under the lock, as another transfer may have been
+        // authorized while the callback ran
+        let mut spent = self.spent.lock().unwrap();
+        let day = now / SECONDS_PER_DAY;
+        let entry = spent.entry(request.asset).or_insert((day, 0));
+        if entry.0 != day {
+            *entry = (day, 0);
+        }
+        check_daily_limit(limits, request, entry.1)?;
+        entry.1 = entry.1.saturating_add(request.amount);
+        Ok(())
+    }
+
+    /// Uncount an authorized `request` that was not sent
+    pub fn release(&self, request: &SpendRequest, now: u64) {
+        let mut spent = self.spent.lock().unwrap();
+        if let Some((day, amount)) = spent.get_mut(&request.asset) {
+            if *day == now / SECONDS_PER_DAY {
+                *amount = amount.saturating_sub(request.amount);
+            }
+        }
+    }
+}
+
+/// Check that `request` fits the asset's daily limit, `spent` being what
+/// was already spent today
+fn check_daily_limit(
+    limits: &AssetLimits,
+    request: &SpendRequest,
+    spent: u64,
+) -> Result<(), PolicyViolation> {
+    let Some(limit) = limits.daily_limit else {
+        return Ok(());
+    };
+    if spent.saturating_add(request.amount) > limit {
+        return Err(PolicyViolation::DailyLimitExceeded {
+            limit,
+            spent,
+            amount: request.amount,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use untrace_common::NATIVE_ASSET_ID;
+
+    fn transfer(amount: u64, level: PrivacyLevel) -> SpendRequest {
+        SpendRequest {
+            kind: SpendKind::PrivateTransfer,
+            asset: NATIVE_ASSET_ID,
+            amount,
+            recipient: "0xAbC".to_string(),
+            privacy_level: Some(level),
+        }
+    }
+
+    #[test]
+    fn test_policy_limits_and_confirmation() {
+        let mut limits = HashMap::new();
+        limits.insert(
+            NATIVE_ASSET_ID,
+            AssetLimits {
+                daily_limit: Some(100),
+                confirm_above: Some(50),
+                privacy_thresholds: vec![(10, PrivacyLevel::Enhanced), (40, PrivacyLevel::Maximum)],
+            },
+        );
+        let mut engine = PolicyEngine::new(SpendingPolicy {
+            limits,
+            ..SpendingPolicy::default()
+        });
+        let now = 1_000;
+
+        engine
+            .authorize(&transfer(5, PrivacyLevel::Basic), now)
+            .unwrap();
+        assert_eq!(
+            engine.authorize(&transfer(45, PrivacyLevel::Enhanced), now),
+            Err(PolicyViolation::PrivacyLevelTooLow {
+                required: PrivacyLevel::Maximum,
+                amount: 45
+            })
+        );
+        assert_eq!(
+            engine.authorize(&transfer(60, PrivacyLevel::Maximum), now),
+            Err(PolicyViolation::NotConfirmed { amount: 60 })
+        );
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = asked.clone();
+        engine.set_confirmation(Some(Arc::new(move |_: &SpendRequest| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            true
+        })));
+        let large = transfer(60, PrivacyLevel::Maximum);
+        engine.authorize(&large, now).unwrap();
+        assert_eq!(engine.spent_today(&NATIVE_ASSET_ID, now), 65);
+
+        // Over the limit fails without prompting, until a send is released
+        assert!(matches!(
+            engine.authorize(&large, now),
+            Err(PolicyViolation::DailyLimitExceeded { spent: 65, .. })
+        ));
+        assert_eq!(asked.load(Ordering::SeqCst), 1);
+        engine.release(&large, now);
+        engine.authorize(&large, now).unwrap();
+        assert_eq!(
+            engine.spent_today(&NATIVE_ASSET_ID, now + SECONDS_PER_DAY),
+            0
+        );
+    }
+
+    #[test]
+    fn test_policy_recipient_lists() {
+        let mut engine = PolicyEngine::new(SpendingPolicy {
+            denylist: vec!["0xabc".to_string()],
+            ..SpendingPolicy::default()
+        });
+        assert!(matches!(
+            engine.authorize(&transfer(1, PrivacyLevel::Basic), 0),
+            Err(PolicyViolation::RecipientDenied { .. })
+        ));
+
+        engine.set_policy(SpendingPolicy {
+            allowlist: Some(vec!["0xdef".to_string()]),
+            ..SpendingPolicy::default()
+        });
+        assert!(matches!(
+            engine.authorize(&transfer(1, PrivacyLevel::Basic), 0),
+            Err(PolicyViolation::RecipientNotAllowed { .. })
+        ));
+    }
+}
@@ -0,0 +1,374 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use solana_sdk::{
+    compute_budget, message::Message, pubkey::Pubkey, signature::Signer,
+    system_instruction::SystemInstruction, system_program, transaction::Transaction,
+};
+use spl_token::instruction::TokenInstruction;
+use std::{fmt::Debug, sync::Arc};
+use untrace_privacy_client::{ProgramInstruction, TransactionSigner};
+
+/// Lamports charged for each signature a transaction carries
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Compute units each instruction is allowed when the transaction sets no
+/// limit, and the most a transaction may use
+const DEFAULT_UNITS_PER_INSTRUCTION: u64 = 200_000;
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+
+/// Called with each transaction's preview before it is signed, which goes
+/// ahead only if it returns `true`
+#[derive(Clone)]
+pub struct PreviewHandler(Arc<dyn Fn(&TransactionPreview) -> bool + Send + Sync>);
+
+impl Debug for PreviewHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreviewHandler")
+    }
+}
+
+impl PreviewHandler {
+    pub fn new(handler: impl Fn(&TransactionPreview) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(handler))
+    }
+
+    pub fn approves(&self, preview: &TransactionPreview) -> bool {
+        (self.0)(preview)
+    }
+}
+
+/// How much of who paid whom a transaction puts on chain, from least to
+/// most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PrivacyImpact {
+    /// Moves no funds to anyone else, such as creating an account
+    None,
+    /// Spends shielded notes into new ones, with amounts and recipient
+    /// encrypted
+    Shielded,
+    /// Depositor and amount are public, but later withdrawals cannot be
+    /// tied to the deposit
+    Shielding,
+    /// Recipient is public, but not which deposit paid them
+    Unshielding,
+    /// Sender, recipient and amount are public and linked
+    Public,
+    /// Calls a program whose effect is not known
+    Unknown,
+}
+
+/// One instruction of a transaction, as a user should see it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreviewAction {
+    SolTransfer {
+        from: Pubkey,
+        to: Pubkey,
+        lamports: u64,
+    },
+    /// SPL token transfer between token accounts, signed by `authority`
+    TokenTransfer {
+        authority: Pubkey,
+        destination: Pubkey,
+        amount: u64,
+        /// Only known for `TransferChecked`
+        mint: Option<Pubkey>,
+    },
+    Untrace(ProgramInstruction),
+    /// Account creation, nonce advances and other bookkeeping by the
+    /// system, token and associated token programs
+    Setup {
+        program_id: Pubkey,
+    },
+    Unknown {
+        program_id: Pubkey,
+    },
+}
+
+impl PreviewAction {
+    pub fn privacy_impact(&self) -> PrivacyImpact {
+        match self {
+            Self::SolTransfer { .. } | Self::TokenTransfer { .. } => PrivacyImpact::Public,
+            Self::Untrace(instruction) => match instruction {
+                ProgramInstruction::Deposit { .. }
+                | ProgramInstruction::ShieldedDeposit { .. }
+                | ProgramInstruction::CompressedDeposit => PrivacyImpact::Shielding,
+                ProgramInstruction::Withdraw { .. }
+                | ProgramInstruction::ShieldedWithdraw { .. }
+                | ProgramInstruction::CompressedWithdraw { .. } => PrivacyImpact::Unshielding,
+                ProgramInstruction::PrivateTransfer { .. }
+                | ProgramInstruction::CrossChainTransfer { .. } => PrivacyImpact::Shielded,
+            },
+            Self::Setup { .. } => PrivacyImpact::None,
+            Self::Unknown { .. } => PrivacyImpact::Unknown,
+        }
+    }
+
+    /// Account funds go to, when the instruction names one
+    pub fn recipient(&self) -> Option<Pubkey> {
+        match self {
+            Self::SolTransfer { to, .. } => Some(*to),
+            Self::TokenTransfer { destination, .. } => Some(*destination),
+            Self::Untrace(
+                ProgramInstruction::Withdraw { recipient }
+                | ProgramInstruction::ShieldedWithdraw { recipient, .. }
+                | ProgramInstruction::CompressedWithdraw { recipient },
+            ) => Some(*recipient),
+            _ => None,
+        }
+    }
+}
+
+/// What a transaction does, decoded from its message for the user to
+/// acknowledge before it is signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionPreview {
+    pub fee_payer: Pubkey,
+    /// Network fee in lamports: the signature fee and any priority fee
+    pub fee: u64,
+    /// Every instruction but the compute budget ones, which only set
+    /// the fee
+    pub actions: Vec<PreviewAction>,
+}
+
+impl TransactionPreview {
+    /// Decode `message`, whose instructions to `program_id` are the
+    /// privacy program's
+    pub fn decode(message: &Message, program_id: &Pubkey) -> Self {
+        let mut unit_limit = None;
+        let mut unit_price = 0u64;
+        let mut actions = Vec::new();
+
+        for instruction in &message.instructions {
+            // Such a transaction fails to load, so there is nothing to show
+            let Some(program) = message
+                .account_keys
+                .get(instruction.program_id_index as usize)
+            else {
+                continue;
+            };
+            let program = *program;
+            let accounts: Vec<Pubkey> = instruction
+                .accounts
+                .iter()
+                .filter_map(|index| message.account_keys.get(*index as usize).copied())
+                .collect();
+            let data = instruction.data.as_slice();
+
+            if program == compute_budget::id() {
+                // `SetComputeUnitLimit(u32)` and `SetComputeUnitPrice(u64)`
+                match data.split_first() {
+                    Some((2, limit)) => unit_limit = limit.try_into().ok().map(u32::from_le_bytes),
+                    Some((3, price)) => unit_price = price.try_into().map_or(0, u64::from_le_bytes),
+                    _ => {}
+                }
+                continue;
+            }
+            actions.push(decode_action(&program, &accounts, data, program_id));
+        }
+
+        let units = unit_limit.map_or(
+            (DEFAULT_UNITS_PER_INSTRUCTION * actions.len() as u64).min(MAX_COMPUTE_UNITS),
+            u64::from,
+        );
+        // Priority fee is in micro-lamports per compute unit
+        let priority_fee = (units as u128 * unit_price as u128).div_ceil(1_000_000) as u64;
+        let signatures = message.header.num_required_signatures as u64;
+
+        Self {
+            fee_payer: message.account_keys.first().copied().unwrap_or_default(),
+            fee: signatures * LAMPORTS_PER_SIGNATURE + priority_fee,
+            actions,
+        }
+    }
+
+    /// Most revealing impact of any of the actions
+    pub fn privacy_impact(&self) -> PrivacyImpact {
+        self.actions
+            .iter()
+            .map(PreviewAction::privacy_impact)
+            .max()
+            .unwrap_or(PrivacyImpact::None)
+    }
+
+    /// Accounts receiving funds, in instruction order
+    pub fn recipients(&self) -> Vec<Pubkey> {
+        self.actions
+            .iter()
+            .filter_map(PreviewAction::recipient)
+            .collect()
+    }
+}
+
+fn decode_action(
+    program: &Pubkey,
+    accounts: &[Pubkey],
+    data: &[u8],
+    untrace_program: &Pubkey,
+) -> PreviewAction {
+    let setup = PreviewAction::Setup {
+        program_id: *program,
+    };
+    if program == untrace_program {
+        return match ProgramInstruction::decode(accounts, data) {
+            Some(instruction) => PreviewAction::Untrace(instruction),
+            None => setup,
+        };
+    }
+
+    if *program == system_program::id() {
+        return match bincode::deserialize(data) {
+            Ok(SystemInstruction::Transfer { lamports }) if accounts.len() >= 2 => {
+                PreviewAction::SolTransfer {
+                    from: accounts[0],
+                    to: accounts[1],
+                    lamports,
+                }
+            }
+            // Transfers must be shown, so one that cannot be read is not
+            // waved through as bookkeeping
+            Ok(SystemInstruction::Transfer { .. }) | Err(_) => PreviewAction::Unknown {
+                program_id: *program,
+            },
+            Ok(_) => setup,
+        };
+    }
+
+    if *program == spl_token::id() {
+        return match TokenInstruction::unpack(data) {
+            Ok(TokenInstruction::Transfer { amount }) if accounts.len() >= 3 => {
+                PreviewAction::TokenTransfer {
+                    authority: accounts[2],
+                    destination: accounts[1],
+                    amount,
+                    mint: None,
+                }
+            }
+            Ok(TokenInstruction::TransferChecked { amount, .. }) if accounts.len() >= 4 => {
+                PreviewAction::TokenTransfer {
+                    authority: accounts[3],
+                    destination: accounts[2],
+                    amount,
+                    mint: Some(accounts[1]),
+                }
+            }
+            Ok(
+                TokenInstruction::InitializeAccount
+                | TokenInstruction::InitializeAccount2 { .. }
+                | TokenInstruction::InitializeAccount3 { .. }
+                | TokenInstruction::InitializeMultisig { .. }
+                | TokenInstruction::InitializeMultisig2 { .. }
+                | TokenInstruction::SyncNative,
+            ) => setup,
+            _ => PreviewAction::Unknown {
+                program_id: *program,
+            },
+        };
+    }
+
+    if *program == spl_associated_token_account::id() {
+        return setup;
+    }
+    PreviewAction::Unknown {
+        program_id: *program,
+    }
+}
+
+/// Key the wallet pays and signs with
+pub(crate) enum Payer {
+    Local(Arc<dyn Signer + Send + Sync>),
+    External(Arc<dyn TransactionSigner>),
+}
+
+/// Payer that signs a transaction only once the handler has seen its
+/// preview and approved it
+pub(crate) struct PreviewSigner {
+    payer: Payer,
+    program_id: Pubkey,
+    handler: PreviewHandler,
+}
+
+impl PreviewSigner {
+    pub(crate) fn new(payer: Payer, program_id: Pubkey, handler: PreviewHandler) -> Self {
+        Self {
+            payer,
+            program_id,
+            handler,
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for PreviewSigner {
+    fn pubkey(&self) -> Pubkey {
+        match &self.payer {
+            Payer::Local(signer) => signer.pubkey(),
+            Payer::External(signer) => signer.pubkey(),
+        }
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: Vec<u8>,
+    ) -> untrace_privacy_client::Result<Vec<u8>> {
+        let mut decoded: Transaction =
+            bincode::deserialize(&transaction).map_err(anyhow::Error::from)?;
+        let preview = TransactionPreview::decode(&decoded.message, &self.program_id);
+        if !self.handler.approves(&preview) {
+            return Err(anyhow!("Transaction was not approved").into());
+        }
+
+        match &self.payer {
+            Payer::Local(signer) => {
+                let blockhash = decoded.message.recent_blockhash;
+                let signers: [&dyn Signer; 1] = [signer.as_ref()];
+                decoded
+                    .try_partial_sign(&signers, blockhash)
+                    .map_err(anyhow::Error::from)?;
+                Ok(bincode::serialize(&decoded).map_err(anyhow::Error::from)?)
+            }
+            Payer::External(signer) => signer.sign_transaction(transaction).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction};
+
+    #[test]
+    fn test_transaction_preview() {
+        let payer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let unknown = Pubkey::new_unique();
+        let message = Message::new(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                ComputeBudgetInstruction::set_compute_unit_price(50_000),
+                solana_sdk::system_instruction::transfer(&payer, &recipient, 42),
+                Instruction::new_with_bytes(unknown, &[1, 2], vec![]),
+            ],
+            Some(&payer),
+        );
+
+        let preview = TransactionPreview::decode(&message, &Pubkey::new_unique());
+        assert_eq!(preview.fee_payer, payer);
+        // 100k units at 0.05 lamports each on top of one signature
+        assert_eq!(preview.fee, LAMPORTS_PER_SIGNATURE + 5_000);
+        assert_eq!(
+            preview.actions,
+            vec![
+                PreviewAction::SolTransfer {
+                    from: payer,
+                    to: recipient,
+                    lamports: 42,
+                },
+                PreviewAction::Unknown {
+                    program_id: unknown,
+                },
+            ]
+        );
+        assert_eq!(preview.recipients(), vec![recipient]);
+        assert_eq!(preview.privacy_impact(), PrivacyImpact::Unknown);
+    }
+}
@@ -1,62 +1,443 @@
 use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use solana_sdk::signature::Keypair;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use untrace_common::ShieldedNote;
+
+#[cfg(not(target_arch = "wasm32"))]
+use untrace_anti_mev::OrderKeyVault;
+
+use solana_sdk::pubkey::Pubkey;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{auto_mix::DailyUsage, consolidation::MergeUsage};
+use crate::{
+    address_book::AddressBook,
+    keystore::{decrypt_keystore, encrypt_keystore, is_keystore, KeystoreError, SealingKey},
+    mnemonic::validate_mnemonic,
+};
+
+/// Key-value store `SecureStorage` keeps wallet secrets in
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Read the value stored under `key`
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store `value` under `key`, replacing any previous value
+    fn put(&self, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Remove the value stored under `key`, if any
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Backend kept in memory for the life of the process
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
 
 /// Secure storage for wallet data
 #[derive(Debug)]
 pub struct SecureStorage {
-    /// Encrypted commitments and secrets
-    commitments: HashMap<String, StoredCommitment>,
+    /// Where deposits, notes and the recovery phrase are kept
+    backend: Arc<dyn StorageBackend>,
+    /// Key deposits, notes and the address book are encrypted under before
+    /// reaching `backend`
+    sealing_key: SealingKey,
+    /// Held while an account's deposit index or notes are rewritten
+    write_lock: Mutex<()>,
     /// Encrypted keypairs
     keypairs: HashMap<String, Vec<u8>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct StoredCommitment {
-    commitment: [u8; 32],
-    randomness: [u8; 32],
-    timestamp: i64,
+/// Where a deposit is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Enum))]
+pub enum DepositStatus {
+    /// In the pool and withdrawable
+    Unspent,
+    /// A withdrawal was sent but not confirmed; withdrawing again is safe,
+    /// as the pool rejects a second spend of the same note
+    Withdrawing,
+    /// Withdrawn
+    Spent,
+}
+
+/// Deposit in a privacy pool, with the secret needed to withdraw it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositNote {
+    pub pool_id: u64,
+    pub commitment: [u8; 32],
+    /// Randomness the commitment hides; withdrawing proves knowledge of it
+    pub randomness: [u8; 32],
+    /// Position of the commitment in the pool's Merkle tree
+    pub leaf_index: u64,
+    /// Lamports deposited
+    pub amount: u64,
+    /// Account the withdrawal is committed to
+    pub recipient: Pubkey,
+    pub status: DepositStatus,
+    /// Unix time the deposit was stored
+    pub created_at: i64,
+}
+
+/// Transaction a stored note came out of, which ties it to the notes
+/// created alongside it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteOrigin {
+    pub signature: String,
+    /// Unix time the note was stored
+    pub received_at: i64,
+}
+
+impl NoteOrigin {
+    /// Origin of a note received now from transaction `signature`
+    pub fn new(signature: impl Into<String>) -> Self {
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            signature: signature.into(),
+            received_at,
+        }
+    }
+}
+
+impl DepositNote {
+    /// Unspent deposit of `amount` to `recipient`, made now
+    pub fn new(
+        pool_id: u64,
+        commitment: [u8; 32],
+        randomness: [u8; 32],
+        leaf_index: u64,
+        amount: u64,
+        recipient: Pubkey,
+    ) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            pool_id,
+            commitment,
+            randomness,
+            leaf_index,
+            amount,
+            recipient,
+            status: DepositStatus::Unspent,
+            created_at,
+        }
+    }
 }
 
 impl SecureStorage {
+    /// Storage kept in memory, lost when the process exits
     pub fn new() -> Result<Self> {
         Ok(Self {
-            commitments: HashMap::new(),
+            backend: Arc::new(MemoryBackend::new()),
+            sealing_key: SealingKey::random(),
+            write_lock: Mutex::new(()),
+            keypairs: HashMap::new(),
+        })
+    }
+
+    /// Storage that keeps its secrets in `backend`, such as a
+    /// `KeychainBackend` so they survive restarts. Deposits and notes are
+    /// encrypted under a key derived from `keypair`, so the backend never
+    /// holds them in plaintext and only this wallet can read them back.
+    pub fn with_backend(backend: Arc<dyn StorageBackend>, keypair: &Keypair) -> Result<Self> {
+        let version = keystore_version(backend.as_ref())?;
+        Ok(Self {
+            sealing_key: SealingKey::derive_version(keypair.secret().as_bytes(), version),
+            backend,
+            write_lock: Mutex::new(()),
             keypairs: HashMap::new(),
         })
     }
 
-    /// Store a commitment with its randomness
-    pub fn store_commitment(&self, commitment: &[u8; 32], randomness: &[u8; 32]) -> Result<()> {
-        // In production, this would use secure OS keychain/keystore
-        println!("Storing commitment securely");
+    /// Version of the key records are sealed under, bumped by each
+    /// `rotate_password`
+    pub fn keystore_version(&self) -> Result<u32> {
+        keystore_version(self.backend.as_ref())
+    }
+
+    /// Change the password the seed phrase is kept under from
+    /// `old_password` to `new_password`, storing it as a keystore, and
+    /// re-seal every record under the next keystore version's key, derived
+    /// from `keypair`, the wallet's first account. Either everything moves
+    /// to the new key and password or, if a write fails, what was written
+    /// is put back and the storage stays as it was.
+    pub fn rotate_password(
+        &mut self,
+        keypair: &Keypair,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<()> {
+        if new_password.is_empty() {
+            return Err(anyhow!("Password must not be empty"));
+        }
+        let secret = keypair.secret().as_bytes();
+        let version = self.keystore_version()?;
+        if SealingKey::derive_version(secret, version) != self.sealing_key {
+            return Err(anyhow!("Keypair is not the one this storage was opened with"));
+        }
+
+        // A phrase XORed with the password carries no checksum, so a wrong
+        // password only shows as one that does not decode or validate
+        let phrase = match self.encrypted_seed_phrase()? {
+            Some(encrypted) if is_keystore(&encrypted) => {
+                Some(self.retrieve_seed_phrase(&encrypted, old_password)?)
+            }
+            Some(encrypted) => Some(
+                self.retrieve_seed_phrase(&encrypted, old_password)
+                    .ok()
+                    .filter(|phrase| validate_mnemonic(phrase).is_ok())
+                    .ok_or(KeystoreError::WrongPassword)?,
+            ),
+            None => None,
+        };
+
+        let sealing_key = SealingKey::derive_version(secret, version + 1);
+        let mut writes = Vec::new();
+        for key in self.sealed_keys()? {
+            if let Some(value) = self.get_sealed(&key)? {
+                writes.push((key, sealing_key.seal(&value)?));
+            }
+        }
+        if let Some(phrase) = phrase {
+            let keystore = encrypt_keystore(phrase.as_bytes(), new_password)?;
+            writes.push((SEED_PHRASE_KEY.to_string(), keystore.into_bytes()));
+        }
+        writes.push((
+            KEYSTORE_VERSION_KEY.to_string(),
+            serde_json::to_vec(&(version + 1))?,
+        ));
+
+        self.put_all(&writes)?;
+        self.sealing_key = sealing_key;
+        Ok(())
+    }
+
+    /// Store `deposit` of `account`, replacing any earlier record of its
+    /// commitment
+    pub fn store_deposit(&self, account: u32, deposit: &DepositNote) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.put_sealed(
+            &deposit_key(account, &deposit.commitment),
+            &serde_json::to_vec(deposit)?,
+        )?;
+
+        // The keychain cannot list its entries, so keep an index of them
+        self.track_account(account)?;
+        let mut commitments = self.deposit_commitments(account)?;
+        if !commitments.contains(&deposit.commitment) {
+            commitments.push(deposit.commitment);
+            self.backend.put(
+                &deposit_index_key(account),
+                &serde_json::to_vec(&commitments)?,
+            )?;
+        }
         Ok(())
     }
 
-    /// Get secret for a commitment
-    pub fn get_secret(&self, commitment: &[u8; 32]) -> Result<Vec<u8>> {
-        // In production, retrieve from secure storage
-        Ok(commitment.to_vec())
+    /// Deposit of `account` with `commitment`, if stored
+    pub fn deposit(&self, account: u32, commitment: &[u8; 32]) -> Result<Option<DepositNote>> {
+        self.get_sealed(&deposit_key(account, commitment))?
+            .map(|deposit| Ok(serde_json::from_slice(&deposit)?))
+            .transpose()
     }
 
-    /// Export wallet (encrypted with password)
-    pub fn export_wallet(&self, keypair: &Keypair, password: &str) -> Result<String> {
-        // Simple XOR encryption for demonstration
-        // In production, use proper encryption like AES-GCM with PBKDF2
-        let keypair_bytes = keypair.to_bytes();
-        let password_bytes = password.as_bytes();
+    /// Deposits of `account`, oldest first
+    pub fn deposits(&self, account: u32) -> Result<Vec<DepositNote>> {
+        let mut deposits = Vec::new();
+        for commitment in self.deposit_commitments(account)? {
+            deposits.extend(self.deposit(account, &commitment)?);
+        }
+        Ok(deposits)
+    }
 
-        let mut encrypted = Vec::new();
-        for (i, byte) in keypair_bytes.iter().enumerate() {
-            encrypted.push(byte ^ password_bytes[i % password_bytes.len()]);
+    /// Move the deposit of `account` with `commitment` to `status`
+    pub fn set_deposit_status(
+        &self,
+        account: u32,
+        commitment: &[u8; 32],
+        status: DepositStatus,
+    ) -> Result<DepositNote> {
+        let mut deposit = self
+            .deposit(account, commitment)?
+            .ok_or_else(|| anyhow!("No deposit with this commitment on account {}", account))?;
+        deposit.status = status;
+        self.store_deposit(account, &deposit)?;
+        Ok(deposit)
+    }
+
+    fn deposit_commitments(&self, account: u32) -> Result<Vec<[u8; 32]>> {
+        match self.backend.get(&deposit_index_key(account))? {
+            Some(index) => Ok(serde_json::from_slice(&index)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Keep `note` among the unspent notes of `account`
+    pub fn store_note(&self, account: u32, note: ShieldedNote) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.track_account(account)?;
+        let mut notes = self.notes(account)?;
+        if !notes.contains(&note) {
+            notes.push(note);
+            self.put_sealed(&notes_key(account), &notes.try_to_vec()?)?;
+        }
+        Ok(())
+    }
+
+    /// Unspent notes of `account`
+    pub fn notes(&self, account: u32) -> Result<Vec<ShieldedNote>> {
+        match self.get_sealed(&notes_key(account))? {
+            Some(notes) => Ok(Vec::<ShieldedNote>::try_from_slice(&notes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Forget the note of `account` with `commitment`, once spent
+    pub fn remove_note(&self, account: u32, commitment: &[u8; 32]) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut notes = self.notes(account)?;
+        notes.retain(|note| note.commitment() != *commitment);
+        self.put_sealed(&notes_key(account), &notes.try_to_vec()?)?;
+
+        let mut origins = self.note_origins(account)?;
+        if origins.remove(&hex::encode(commitment)).is_some() {
+            self.put_sealed(&note_origins_key(account), &serde_json::to_vec(&origins)?)?;
+        }
+        Ok(())
+    }
+
+    /// Remember that `account`'s note with `commitment` came from
+    /// `origin`
+    pub fn store_note_origin(
+        &self,
+        account: u32,
+        commitment: &[u8; 32],
+        origin: NoteOrigin,
+    ) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.track_account(account)?;
+        let mut origins = self.note_origins(account)?;
+        origins.insert(hex::encode(commitment), origin);
+        self.put_sealed(&note_origins_key(account), &serde_json::to_vec(&origins)?)
+    }
+
+    /// Origins of `account`'s notes, by hex commitment. Notes stored
+    /// without one, such as those found by scanning, have none.
+    pub fn note_origins(&self, account: u32) -> Result<HashMap<String, NoteOrigin>> {
+        match self.get_sealed(&note_origins_key(account))? {
+            Some(origins) => Ok(serde_json::from_slice(&origins)?),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Address book shared by the wallet's accounts, empty if none was
+    /// stored
+    pub fn address_book(&self) -> Result<AddressBook> {
+        match self.get_sealed(ADDRESS_BOOK_KEY)? {
+            Some(book) => Ok(serde_json::from_slice(&book)?),
+            None => Ok(AddressBook::new()),
+        }
+    }
+
+    /// Replace the stored address book with `book`
+    pub fn store_address_book(&self, book: &AddressBook) -> Result<()> {
+        self.put_sealed(ADDRESS_BOOK_KEY, &serde_json::to_vec(book)?)
+    }
+
+    /// What auto-mix deposited on its last active day, so a restart does
+    /// not reset its daily limits
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn auto_mix_usage(&self) -> Result<DailyUsage> {
+        match self.get_sealed(AUTO_MIX_USAGE_KEY)? {
+            Some(usage) => Ok(serde_json::from_slice(&usage)?),
+            None => Ok(DailyUsage::default()),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn store_auto_mix_usage(&self, usage: &DailyUsage) -> Result<()> {
+        self.put_sealed(AUTO_MIX_USAGE_KEY, &serde_json::to_vec(usage)?)
+    }
+
+    /// Merges note consolidation made on its last active day
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn consolidation_usage(&self) -> Result<MergeUsage> {
+        match self.get_sealed(CONSOLIDATION_USAGE_KEY)? {
+            Some(usage) => Ok(serde_json::from_slice(&usage)?),
+            None => Ok(MergeUsage::default()),
         }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn store_consolidation_usage(&self, usage: &MergeUsage) -> Result<()> {
+        self.put_sealed(CONSOLIDATION_USAGE_KEY, &serde_json::to_vec(usage)?)
+    }
 
-        Ok(bs58::encode(&encrypted).into_string())
+    /// Export wallet as a keystore encrypted with password
+    pub fn export_wallet(&self, keypair: &Keypair, password: &str) -> Result<String> {
+        encrypt_keystore(&keypair.to_bytes(), password)
     }
 
-    /// Import wallet (decrypt with password)
+    /// Import wallet (decrypt with password), from a keystore or the XOR
+    /// export of earlier versions
     pub fn import_wallet(&self, encrypted: &str, password: &str) -> Result<Keypair> {
+        if !is_keystore(encrypted) {
+            return self.import_legacy_wallet(encrypted, password);
+        }
+        let decrypted = decrypt_keystore(encrypted, password)?;
+        Keypair::from_bytes(&decrypted)
+            .map_err(|e| anyhow!("Failed to create keypair: {}", e))
+    }
+
+    /// Re-encrypt an export of earlier versions as a keystore
+    pub fn migrate_export(&self, encrypted: &str, password: &str) -> Result<String> {
+        let keypair = self.import_wallet(encrypted, password)?;
+        self.export_wallet(&keypair, password)
+    }
+
+    /// Import the base58 XOR export of earlier versions. It carries no
+    /// checksum, so a wrong password only shows as a bad keypair.
+    fn import_legacy_wallet(&self, encrypted: &str, password: &str) -> Result<Keypair> {
+        if password.is_empty() {
+            return Err(anyhow!("Password must not be empty"));
+        }
         let encrypted_bytes = bs58::decode(encrypted)
             .into_vec()
             .map_err(|e| anyhow!("Failed to decode: {}", e))?;
@@ -78,6 +459,9 @@ impl SecureStorage {
 
     /// Store encrypted seed phrase
     pub fn store_seed_phrase(&mut self, seed_phrase: &str, password: &str) -> Result<String> {
+        if password.is_empty() {
+            return Err(anyhow!("Password must not be empty"));
+        }
         let seed_bytes = seed_phrase.as_bytes();
         let password_bytes = password.as_bytes();
 
@@ -87,11 +471,31 @@ impl SecureStorage {
         }
 
         let encoded = bs58::encode(&encrypted).into_string();
+        self.backend.put(SEED_PHRASE_KEY, encoded.as_bytes())?;
         Ok(encoded)
     }
 
-    /// Retrieve seed phrase
+    /// Seed phrase last stored, still encrypted
+    pub fn encrypted_seed_phrase(&self) -> Result<Option<String>> {
+        self.backend
+            .get(SEED_PHRASE_KEY)?
+            .map(|encoded| String::from_utf8(encoded).map_err(Into::into))
+            .transpose()
+    }
+
+    /// Keep `encrypted`, from `encrypted_seed_phrase`, as the stored seed
+    /// phrase, such as when restoring a backup
+    pub fn restore_encrypted_seed_phrase(&self, encrypted: &str) -> Result<()> {
+        self.backend.put(SEED_PHRASE_KEY, encrypted.as_bytes())
+    }
+
+    /// Retrieve seed phrase, kept as a keystore once the password was
+    /// rotated or else XORed with it
     pub fn retrieve_seed_phrase(&self, encrypted: &str, password: &str) -> Result<String> {
+        if is_keystore(encrypted) {
+            return String::from_utf8(decrypt_keystore(encrypted, password)?)
+                .map_err(|e| anyhow!("Failed to decode seed phrase: {}", e));
+        }
         let encrypted_bytes = bs58::decode(encrypted)
             .into_vec()
             .map_err(|e| anyhow!("Failed to decode: {}", e))?;
@@ -108,15 +512,178 @@ impl SecureStorage {
     }
 
     /// Clear all stored data
-    pub fn clear(&mut self) {
-        self.commitments.clear();
+    pub fn clear(&mut self) -> Result<()> {
+        for account in self.stored_accounts()? {
+            for commitment in self.deposit_commitments(account)? {
+                self.backend.delete(&deposit_key(account, &commitment))?;
+            }
+            self.backend.delete(&deposit_index_key(account))?;
+            self.backend.delete(&notes_key(account))?;
+        }
         self.keypairs.clear();
+        for name in self.order_key_names()? {
+            self.backend.delete(&order_key(&name))?;
+        }
+        self.backend.delete(ORDER_KEYS_KEY)?;
+        self.backend.delete(ACCOUNTS_KEY)?;
+        self.backend.delete(ADDRESS_BOOK_KEY)?;
+        self.backend.delete(AUTO_MIX_USAGE_KEY)?;
+        self.backend.delete(SEED_PHRASE_KEY)
+    }
+
+    fn put_sealed(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.backend.put(key, &self.sealing_key.seal(value)?)
+    }
+
+    fn get_sealed(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.backend
+            .get(key)?
+            .map(|sealed| self.sealing_key.open(&sealed))
+            .transpose()
+    }
+
+    /// Keys of every sealed record: each account's deposits and notes, the
+    /// address book, auto-mix's usage and the anti-MEV order keys
+    fn sealed_keys(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for account in self.stored_accounts()? {
+            for commitment in self.deposit_commitments(account)? {
+                keys.push(deposit_key(account, &commitment));
+            }
+            keys.push(notes_key(account));
+            keys.push(note_origins_key(account));
+        }
+        keys.push(ADDRESS_BOOK_KEY.to_string());
+        keys.push(AUTO_MIX_USAGE_KEY.to_string());
+        keys.push(CONSOLIDATION_USAGE_KEY.to_string());
+        keys.extend(self.order_key_names()?.iter().map(|name| order_key(name)));
+        Ok(keys)
+    }
+
+    /// Names of the order keys kept, which the keychain cannot list
+    fn order_key_names(&self) -> Result<Vec<String>> {
+        match self.backend.get(ORDER_KEYS_KEY)? {
+            Some(names) => Ok(serde_json::from_slice(&names)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Write each of `writes`, or, if one fails, put back what the ones
+    /// before it replaced
+    fn put_all(&self, writes: &[(String, Vec<u8>)]) -> Result<()> {
+        let mut replaced = Vec::with_capacity(writes.len());
+        for (key, value) in writes {
+            let written = self.backend.get(key).and_then(|previous| {
+                replaced.push((key, previous));
+                self.backend.put(key, value)
+            });
+            if let Err(error) = written {
+                for (key, previous) in replaced.iter().rev() {
+                    let restored = match previous {
+                        Some(previous) => self.backend.put(key, previous),
+                        None => self.backend.delete(key),
+                    };
+                    if let Err(restore_error) = restored {
+                        log::error!("Could not roll back {}: {}", key, restore_error);
+                    }
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Accounts anything was stored for
+    fn stored_accounts(&self) -> Result<Vec<u32>> {
+        match self.backend.get(ACCOUNTS_KEY)? {
+            Some(accounts) => Ok(serde_json::from_slice(&accounts)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Remember that `account` has stored data, for `clear`
+    fn track_account(&self, account: u32) -> Result<()> {
+        let mut accounts = self.stored_accounts()?;
+        if !accounts.contains(&account) {
+            accounts.push(account);
+            self.backend.put(ACCOUNTS_KEY, &serde_json::to_vec(&accounts)?)?;
+        }
+        Ok(())
+    }
+}
+
+const ACCOUNTS_KEY: &str = "accounts";
+const ORDER_KEYS_KEY: &str = "order_keys";
+const SEED_PHRASE_KEY: &str = "seed_phrase";
+const ADDRESS_BOOK_KEY: &str = "address_book";
+const AUTO_MIX_USAGE_KEY: &str = "auto_mix_usage";
+const CONSOLIDATION_USAGE_KEY: &str = "consolidation_usage";
+/// Kept in plaintext, since opening the storage needs it before any key
+const KEYSTORE_VERSION_KEY: &str = "keystore_version";
+
+/// Keystore version of the storage in `backend`; the first until a
+/// rotation stores one
+fn keystore_version(backend: &dyn StorageBackend) -> Result<u32> {
+    match backend.get(KEYSTORE_VERSION_KEY)? {
+        Some(version) => Ok(serde_json::from_slice(&version)?),
+        None => Ok(1),
+    }
+}
+
+/// Key of `account`'s deposit of `commitment`, so accounts never see
+/// each other's
+fn deposit_key(account: u32, commitment: &[u8; 32]) -> String {
+    format!("deposit_{}_{}", account, bs58::encode(commitment).into_string())
+}
+
+fn deposit_index_key(account: u32) -> String {
+    format!("deposits_{}", account)
+}
+
+fn notes_key(account: u32) -> String {
+    format!("notes_{}", account)
+}
+
+fn note_origins_key(account: u32) -> String {
+    format!("note_origins_{}", account)
+}
+
+fn order_key(name: &str) -> String {
+    format!("order_key/{}", name)
+}
+
+/// Keys of the anti-MEV `OrderKeyStore`, sealed like every other record
+/// and re-sealed with them when the password is rotated
+#[cfg(not(target_arch = "wasm32"))]
+impl OrderKeyVault for SecureStorage {
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        self.get_sealed(&order_key(name))
+    }
+
+    fn put(&self, name: &str, value: &[u8]) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.put_sealed(&order_key(name), value)?;
+        let mut names = self.order_key_names()?;
+        if !names.iter().any(|kept| kept == name) {
+            names.push(name.to_string());
+            self.backend.put(ORDER_KEYS_KEY, &serde_json::to_vec(&names)?)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.backend.delete(&order_key(name))?;
+        let mut names = self.order_key_names()?;
+        names.retain(|kept| kept != name);
+        self.backend.put(ORDER_KEYS_KEY, &serde_json::to_vec(&names)?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use solana_sdk::signature::Signer;
 
     #[test]
     fn test_export_import_wallet() {
@@ -131,6 +698,23 @@ mod tests {
             keypair.to_bytes(),
             imported.to_bytes()
         );
+        assert!(storage.import_wallet(&encrypted, "wrong_password").is_err());
+
+        // Exports from before the keystore still import, and migrate to it
+        let password_bytes = password.as_bytes();
+        let legacy: Vec<u8> = keypair
+            .to_bytes()
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ password_bytes[i % password_bytes.len()])
+            .collect();
+        let legacy = bs58::encode(legacy).into_string();
+        let migrated = storage.migrate_export(&legacy, password).unwrap();
+        assert!(crate::keystore::is_keystore(&migrated));
+        assert_eq!(
+            storage.import_wallet(&migrated, password).unwrap().to_bytes(),
+            keypair.to_bytes()
+        );
     }
 
     #[test]
@@ -144,4 +728,148 @@ mod tests {
 
         assert_eq!(seed, decrypted);
     }
+
+    #[test]
+    fn test_deposits_persist_per_account() {
+        let backend = Arc::new(MemoryBackend::new());
+        let keypair = Keypair::new();
+        let mut storage = SecureStorage::with_backend(backend.clone(), &keypair).unwrap();
+        let deposit = DepositNote::new(3, [1u8; 32], [2u8; 32], 7, 1_000, keypair.pubkey());
+        storage.store_deposit(0, &deposit).unwrap();
+
+        assert_eq!(storage.deposit(0, &[1u8; 32]).unwrap(), Some(deposit.clone()));
+        assert_eq!(storage.deposit(1, &[1u8; 32]).unwrap(), None);
+        assert!(storage.deposits(1).unwrap().is_empty());
+
+        // Everything lives in the backend, so a new storage for the same
+        // wallet sees it after a restart, and nothing there is plaintext
+        let reopened = SecureStorage::with_backend(backend.clone(), &keypair).unwrap();
+        let spent = reopened
+            .set_deposit_status(0, &[1u8; 32], DepositStatus::Spent)
+            .unwrap();
+        assert_eq!(storage.deposits(0).unwrap(), vec![spent]);
+        let entries = backend.entries.lock().unwrap().clone();
+        assert!(!entries
+            .values()
+            .any(|value| value.windows(10).any(|window| window == b"randomness")));
+
+        let other = SecureStorage::with_backend(backend.clone(), &Keypair::new()).unwrap();
+        assert!(other.deposit(0, &[1u8; 32]).is_err());
+
+        let mut book = AddressBook::new();
+        book.add("0xabc", "Exchange").unwrap();
+        storage.store_address_book(&book).unwrap();
+        assert_eq!(reopened.address_book().unwrap(), book);
+        let mut usage = DailyUsage::default();
+        usage.record(500, 1_000);
+        storage.store_auto_mix_usage(&usage).unwrap();
+        assert_eq!(reopened.auto_mix_usage().unwrap(), usage);
+
+        storage.clear().unwrap();
+        assert!(backend.entries.lock().unwrap().is_empty());
+    }
+
+    /// Backend whose put fails once `puts_left` runs out, as a write
+    /// dropping part way through would
+    #[derive(Debug)]
+    struct FailingBackend {
+        inner: Arc<MemoryBackend>,
+        puts_left: Mutex<usize>,
+    }
+
+    impl StorageBackend for FailingBackend {
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+            let mut puts_left = self.puts_left.lock().unwrap();
+            if *puts_left == 0 {
+                *puts_left = usize::MAX;
+                return Err(anyhow!("Backend unavailable"));
+            }
+            *puts_left -= 1;
+            self.inner.put(key, value)
+        }
+
+        fn delete(&self, key: &str) -> Result<()> {
+            self.inner.delete(key)
+        }
+    }
+
+    #[test]
+    fn test_rotate_password() {
+        let seed = crate::mnemonic::generate_mnemonic(crate::mnemonic::WordCount::Words12);
+        let keypair = Keypair::new();
+        let inner = Arc::new(MemoryBackend::new());
+        let failing = Arc::new(FailingBackend {
+            inner: inner.clone(),
+            puts_left: Mutex::new(usize::MAX),
+        });
+        let mut storage = SecureStorage::with_backend(failing.clone(), &keypair).unwrap();
+        storage.store_seed_phrase(&seed, "old").unwrap();
+        let deposit = DepositNote::new(3, [1u8; 32], [2u8; 32], 7, 1_000, keypair.pubkey());
+        storage.store_deposit(0, &deposit).unwrap();
+        let mut book = AddressBook::new();
+        book.add("0xabc", "Exchange").unwrap();
+        storage.store_address_book(&book).unwrap();
+
+        let error = storage.rotate_password(&keypair, "wrong", "new").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<KeystoreError>(),
+            Some(KeystoreError::WrongPassword)
+        ));
+        assert!(storage.rotate_password(&Keypair::new(), "old", "new").is_err());
+
+        // A write failing part way leaves every record as it was
+        let before = inner.entries.lock().unwrap().clone();
+        *failing.puts_left.lock().unwrap() = 2;
+        assert!(storage.rotate_password(&keypair, "old", "new").is_err());
+        assert_eq!(*inner.entries.lock().unwrap(), before);
+        assert_eq!(storage.keystore_version().unwrap(), 1);
+        assert_eq!(storage.deposit(0, &[1u8; 32]).unwrap(), Some(deposit.clone()));
+
+        storage.rotate_password(&keypair, "old", "new").unwrap();
+        assert_eq!(storage.keystore_version().unwrap(), 2);
+        assert_eq!(storage.deposit(0, &[1u8; 32]).unwrap(), Some(deposit.clone()));
+        let reopened = SecureStorage::with_backend(inner.clone(), &keypair).unwrap();
+        assert_eq!(reopened.deposit(0, &[1u8; 32]).unwrap(), Some(deposit));
+        assert_eq!(reopened.address_book().unwrap(), book);
+        let encrypted = reopened.encrypted_seed_phrase().unwrap().unwrap();
+        assert!(is_keystore(&encrypted));
+        assert_eq!(reopened.retrieve_seed_phrase(&encrypted, "new").unwrap(), seed);
+        assert!(reopened.retrieve_seed_phrase(&encrypted, "old").is_err());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_order_key_vault() {
+        let keypair = Keypair::new();
+        let backend = Arc::new(MemoryBackend::new());
+        let mut storage = SecureStorage::with_backend(backend.clone(), &keypair).unwrap();
+        let seed = crate::mnemonic::generate_mnemonic(crate::mnemonic::WordCount::Words12);
+        storage.store_seed_phrase(&seed, "old").unwrap();
+        OrderKeyVault::put(&storage, "order_key_01", b"key one").unwrap();
+        OrderKeyVault::put(&storage, "order_key_02", b"key two").unwrap();
+        assert!(!backend
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .any(|value| value.windows(7).any(|window| window == b"key one")));
+
+        storage.rotate_password(&keypair, "old", "new").unwrap();
+        let reopened = SecureStorage::with_backend(backend.clone(), &keypair).unwrap();
+        assert_eq!(
+            OrderKeyVault::get(&reopened, "order_key_01").unwrap(),
+            Some(b"key one".to_vec())
+        );
+
+        OrderKeyVault::delete(&storage, "order_key_01").unwrap();
+        assert_eq!(OrderKeyVault::get(&storage, "order_key_01").unwrap(), None);
+        assert_eq!(storage.order_key_names().unwrap(), vec!["order_key_02"]);
+        storage.clear().unwrap();
+        assert_eq!(OrderKeyVault::get(&reopened, "order_key_02").unwrap(), None);
+        assert!(reopened.order_key_names().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{program_pack::Pack, pubkey, pubkey::Pubkey};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::collections::BTreeMap;
+use untrace_common::ShieldedNote;
+use untrace_privacy_client::{cross_chain::SupportedChain, TokenRegistry};
+
+use crate::note_selection::{FewestNotes, NoteSelector, SpendableNote};
+
+/// Metaplex Token Metadata program, which holds the name and symbol of
+/// most mints
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey =
+    pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// Offset of the owner in an SPL token account, after its mint
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+
+/// Most accounts one `getMultipleAccounts` call returns
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// What a wallet shows for a mint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+}
+
+impl TokenMetadata {
+    /// Symbol if known, else the mint address
+    pub fn display_symbol(&self) -> String {
+        self.symbol.clone().unwrap_or_else(|| self.mint.to_string())
+    }
+
+    /// `amount` in whole tokens, such as `1.5` for 1_500_000 of a
+    /// six-decimal mint
+    pub fn format_amount(&self, amount: u64) -> String {
+        format_amount(amount, self.decimals)
+    }
+}
+
+/// SPL tokens a wallet holds of one mint, across its token accounts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub metadata: TokenMetadata,
+    /// Base units, summed over `token_accounts`
+    pub amount: u64,
+    pub token_accounts: Vec<Pubkey>,
+}
+
+/// `amount` base units of a mint with `decimals` decimals, in whole
+/// tokens without trailing zeros
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// `amount` in whole tokens, such as `1.5`, in base units of a mint with
+/// `decimals` decimals. Fails on more decimals than the mint has.
+pub fn parse_amount(amount: &str, decimals: u8) -> Result<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = whole.bytes().chain(fraction.bytes());
+    if whole.len() + fraction.len() == 0 || !digits.clone().all(|b| b.is_ascii_digit()) {
+        return Err(anyhow!("Invalid amount: {}", amount));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(anyhow!("Amount {} has more than {} decimals", amount, decimals));
+    }
+
+    let base_units = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    base_units
+        .parse()
+        .map_err(|_| anyhow!("Amount {} is too large", amount))
+}
+
+/// Derive the Metaplex metadata account of `mint`
+pub fn find_metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            TOKEN_METADATA_PROGRAM_ID.as_ref(),
+            mint.as_ref(),
+        ],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Name and symbol of a Metaplex metadata account. They follow its key
+/// byte, update authority and mint, as Borsh strings padded with NULs.
+pub fn decode_metadata(data: &[u8]) -> Option<(String, String)> {
+    let mut rest = data.get(1 + 32 + 32..)?;
+    let name = read_padded_string(&mut rest)?;
+    let symbol = read_padded_string(&mut rest)?;
+    Some((name, symbol))
+}
+
+fn read_padded_string(data: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let bytes = data.get(4..4usize.checked_add(len)?)?;
+    *data = &data[4 + len..];
+    let value = String::from_utf8_lossy(bytes);
+    Some(value.trim_end_matches('\0').trim().to_string())
+}
+
+/// Notes of `asset_id` to spend for `amount`, at most `max` of them: the
+/// smallest single note that covers it, else the largest notes first
+pub fn select_notes(
+    notes: &[ShieldedNote],
+    asset_id: &Pubkey,
+    amount: u64,
+    max: usize,
+) -> Result<Vec<ShieldedNote>> {
+    let notes: Vec<SpendableNote> = notes
+        .iter()
+        .map(|note| SpendableNote {
+            note: note.clone(),
+            origin: None,
+        })
+        .collect();
+    FewestNotes.select(&notes, asset_id, amount, max)
+}
+
+/// SPL Token accounts owned by `owner`. Token-2022 accounts are not
+/// included, as the shielded pool only moves SPL Token mints.
+pub async fn find_token_accounts(
+    rpc: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<(Pubkey, TokenAccount)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(TokenAccount::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                TOKEN_ACCOUNT_OWNER_OFFSET,
+                owner.as_ref(),
+            )),
+        ]),
+        // Token accounts are too large for the default base58 encoding
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    rpc.get_program_accounts_with_config(&spl_token::id(), config)
+        .await?
+        .into_iter()
+        .map(|(address, account)| Ok((address, TokenAccount::unpack(&account.data)?)))
+        .collect()
+}
+
+/// Metadata of each of `mints`: decimals from the mint, and the symbol
+/// `registry` knows it by, falling back to its Metaplex metadata
+pub async fn resolve_metadata(
+    rpc: &RpcClient,
+    registry: &TokenRegistry,
+    mints: &[Pubkey],
+) -> Result<Vec<TokenMetadata>> {
+    let mut addresses = mints.to_vec();
+    addresses.extend(mints.iter().map(find_metadata_address));
+    let mut accounts = Vec::with_capacity(addresses.len());
+    for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        accounts.extend(rpc.get_multiple_accounts(chunk).await?);
+    }
+    let (mint_accounts, metadata_accounts) = accounts.split_at(mints.len());
+
+    mints
+        .iter()
+        .zip(mint_accounts)
+        .zip(metadata_accounts)
+        .map(|((mint, mint_account), metadata_account)| {
+            let mint_account = mint_account
+                .as_ref()
+                .ok_or_else(|| anyhow!("Mint {} not found", mint))?;
+            let decimals = Mint::unpack(&mint_account.data)?.decimals;
+            let (name, symbol) = metadata_account
+                .as_ref()
+                .and_then(|account| decode_metadata(&account.data))
+                .unzip();
+            let known = registry
+                .get(SupportedChain::Solana, &mint.to_string())
+                .map(|token| token.symbol.clone());
+            Ok(TokenMetadata {
+                mint: *mint,
+                decimals,
+                symbol: known.or(symbol).filter(|symbol| !symbol.is_empty()),
+                name: name.filter(|name| !name.is_empty()),
+            })
+        })
+        .collect()
+}
+
+/// Balances of every mint `owner` holds a token account for, by mint
+pub async fn token_balances(
+    rpc: &RpcClient,
+    registry: &TokenRegistry,
+    owner: &Pubkey,
+) -> Result<Vec<TokenBalance>> {
+    let mut by_mint: BTreeMap<Pubkey, (u64, Vec<Pubkey>)> = BTreeMap::new();
+    for (address, account) in find_token_accounts(rpc, owner).await? {
+        let (amount, token_accounts) = by_mint.entry(account.mint).or_default();
+        *amount = amount.saturating_add(account.amount);
+        token_accounts.push(address);
+    }
+
+    let mints: Vec<Pubkey> = by_mint.keys().copied().collect();
+    let metadata = resolve_metadata(rpc, registry, &mints).await?;
+    Ok(metadata
+        .into_iter()
+        .zip(by_mint.into_values())
+        .map(|(metadata, (amount, token_accounts))| TokenBalance {
+            metadata,
+            amount,
+            token_accounts,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_amount_and_metadata() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(42, 6), "0.000042");
+        assert_eq!(format_amount(7_000_000_000, 9), "7");
+        assert_eq!(format_amount(12, 0), "12");
+        assert_eq!(parse_amount("1.5", 6).unwrap(), 1_500_000);
+        assert_eq!(parse_amount("0.000042", 6).unwrap(), 42);
+        assert_eq!(parse_amount("7", 9).unwrap(), 7_000_000_000);
+        assert!(parse_amount("0.0000001", 6).is_err());
+        assert!(parse_amount("1e3", 6).is_err());
+        assert!(parse_amount(".", 6).is_err());
+
+        let mut data = vec![4u8];
+        data.extend([0u8; 64]);
+        for (value, padded_len) in [("USD Coin", 32), ("USDC", 10)] {
+            data.extend((padded_len as u32).to_le_bytes());
+            let mut padded = value.as_bytes().to_vec();
+            padded.resize(padded_len, 0);
+            data.extend(padded);
+        }
+        assert_eq!(
+            decode_metadata(&data),
+            Some(("USD Coin".to_string(), "USDC".to_string()))
+        );
+        assert_eq!(decode_metadata(&data[..70]), None);
+    }
+
+    #[test]
+    fn test_select_notes() {
+        let mint = Pubkey::new_unique();
+        let note = |asset_id: Pubkey, amount: u64| ShieldedNote {
+            asset_id,
+            amount,
+            owner: Pubkey::new_unique(),
+            randomness: [amount as u8; 32],
+        };
+        let notes = vec![
+            note(mint, 5),
+            note(mint, 30),
+            note(mint, 10),
+            note(Pubkey::new_unique(), 100),
+        ];
+
+        assert_eq!(
+            select_notes(&notes, &mint, 8, 2).unwrap(),
+            vec![notes[2].clone()]
+        );
+        assert_eq!(
+            select_notes(&notes, &mint, 35, 2).unwrap(),
+            vec![notes[1].clone(), notes[2].clone()]
+        );
+        assert!(select_notes(&notes, &mint, 45, 2).is_err());
+    }
+}
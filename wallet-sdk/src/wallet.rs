@@ -0,0 +1,2031 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use futures::StreamExt;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{broadcast, watch};
+use untrace_common::{PrivacyLevel, ShieldedNote, NATIVE_ASSET_ID};
+use untrace_privacy_client::{
+    scanner::viewing_key, Cluster, CostEstimate, FeeSponsor, MemoryStorage, MerkleSync,
+    AntiMevMiddleware, NoteScanner, Operation, PrivateTransferClient, SendMiddleware,
+    UntraceClient,
+};
+
+use crate::{
+    adapter::{AdapterListener, AdapterSigner, WalletAdapter},
+    address_book::{AddressBook, RecipientWarning},
+    auto_mix::{self, AutoMixConfig, AutoMixHandle, AutoMixStatus},
+    consolidation::{self, ConsolidationConfig, ConsolidationHandle, ConsolidationStatus},
+    backup::{self, RestoreOptions, WalletBackup},
+    events::{self, EventBus, EventWatcher, WalletEvent},
+    history::{Activity, ActivityKind, ActivityStatus, HistoryFilter, TransactionHistory},
+    keychain::KeychainBackend,
+    keystore,
+    ledger::LedgerAdapter,
+    lock::{KeyVault, VaultSigner},
+    mnemonic::{generate_mnemonic, keypair_from_cli_mnemonic, HdSeed, WordCount},
+    note_selection::{FewestNotes, NoteSelector, SpendableNote},
+    multisig::{MultisigAction, MultisigConfig, SigningRequest},
+    payment_request::{PaymentRequest, SOL_DECIMALS},
+    preview::{Payer, PreviewHandler, PreviewSigner},
+    policy::{PolicyEngine, SpendKind, SpendRequest, SpendingPolicy},
+    storage::{
+        DepositNote, DepositStatus, MemoryBackend, NoteOrigin, SecureStorage, StorageBackend,
+    },
+    tokens::{self, TokenBalance, TokenMetadata},
+};
+
+/// Name the connected Ledger's `AdapterChanged` events carry
+pub const LEDGER_ADAPTER_NAME: &str = "ledger";
+
+/// UntraceOS Wallet - Privacy-focused Web3 wallet
+#[derive(Debug)]
+pub struct UntraceWallet {
+    /// Keypair of the active account, and the seed for wallets with a
+    /// recovery phrase, dropped while the wallet is locked
+    keys: Arc<KeyVault>,
+    /// Public key of each account, indexed by account number
+    accounts: Vec<Pubkey>,
+    /// Account the wallet signs and stores notes for
+    active_account: u32,
+    /// Ledger that signs instead of the keypair while connected
+    ledger: Option<Arc<LedgerAdapter>>,
+    /// Privacy client
+    privacy_client: Option<UntraceClient>,
+    /// Connected adapters (Phantom, Solflare, etc.)
+    adapters: HashMap<String, Arc<dyn WalletAdapter>>,
+    /// Adapter, and the account it was connected as, that signs instead of
+    /// the keypair when selected
+    signing_adapter: Option<(String, Pubkey)>,
+    /// Shown each transaction before it is signed, when set
+    preview_handler: Option<PreviewHandler>,
+    /// Sends transactions while `anti_mev_enabled`, instead of the default
+    /// `AntiMevMiddleware`
+    send_middleware: Option<Arc<dyn SendMiddleware>>,
+    /// Picks the notes private transfers and withdrawals spend
+    note_selector: Arc<dyn NoteSelector>,
+    /// Wallet configuration
+    config: WalletConfig,
+    /// Secure storage for keys and secrets
+    storage: SecureStorage,
+    /// Synced pool commitment trees
+    tree_storage: Arc<MemoryStorage>,
+    /// Record of the wallet's deposits, withdrawals, transfers and bridges
+    history: TransactionHistory,
+    /// Rules transfers out of the wallet must pass
+    policy: PolicyEngine,
+    /// Where the wallet's events go out to subscribers
+    events: EventBus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletConfig {
+    /// Default privacy level for transactions
+    pub default_privacy_level: PrivacyLevel,
+    /// Enable anti-MEV protection
+    pub anti_mev_enabled: bool,
+    /// RPC endpoint URL
+    pub rpc_url: String,
+    /// Program ID for privacy protocol
+    pub program_id: String,
+    /// Allow `start_auto_mix` to deposit incoming funds into privacy pools
+    pub auto_mix_enabled: bool,
+    /// Fewest deposits a pool needs, as its anonymity set, before auto-mix
+    /// deposits into it
+    pub min_pool_size: u64,
+    /// Cluster whose fee settings the privacy client uses
+    #[serde(default)]
+    pub cluster: Option<Cluster>,
+    /// OS keychain service secrets are kept under, so they survive
+    /// restarts; kept in memory only when unset
+    #[serde(default)]
+    pub keychain_service: Option<String>,
+    /// Directory the transaction history is kept in; kept in memory only
+    /// when unset
+    #[serde(default)]
+    pub history_path: Option<PathBuf>,
+    /// Backend secrets are kept in instead of `keychain_service`, such as
+    /// the platform keystore the mobile bindings are given
+    #[serde(skip)]
+    pub storage_backend: Option<Arc<dyn StorageBackend>>,
+    /// Relayer paying the fees of withdrawals and private transfers, so
+    /// accounts without SOL can send them
+    #[serde(default)]
+    pub fee_sponsor: Option<FeeSponsor>,
+}
+
+impl WalletConfig {
+    /// Default config pointed at `cluster`'s RPC URL and program
+    pub fn for_cluster(cluster: Cluster) -> Self {
+        let preset = cluster.config();
+        Self {
+            default_privacy_level: PrivacyLevel::Enhanced,
+            anti_mev_enabled: true,
+            rpc_url: preset.rpc_url,
+            program_id: preset.program_id.to_string(),
+            auto_mix_enabled: true,
+            min_pool_size: 10,
+            cluster: Some(cluster),
+            keychain_service: None,
+            history_path: None,
+            storage_backend: None,
+            fee_sponsor: None,
+        }
+    }
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self::for_cluster(Cluster::MainnetBeta)
+    }
+}
+
+/// Outbound bridge transfer that was never claimed and can be refunded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "mobile", derive(uniffi::Record))]
+pub struct RefundableTransfer {
+    /// Account to pass to `refund_bridge_transfer`
+    pub address: String,
+    pub dest_chain: u16,
+    /// Wormhole sequence of the transfer's message
+    pub sequence: u64,
+    /// Lamports returned by the refund
+    pub amount: u64,
+    /// Unix time the transfer was posted
+    pub posted_at: i64,
+}
+
+impl UntraceWallet {
+    /// Create a new wallet
+    pub fn new(config: WalletConfig) -> Result<Self> {
+        let keypair = Keypair::new();
+        let storage = open_storage(&config, &keypair)?;
+        let history = open_history(&config, &keypair)?;
+
+        Ok(Self {
+            accounts: vec![keypair.pubkey()],
+            keys: KeyVault::new(keypair, None),
+            active_account: 0,
+            ledger: None,
+            privacy_client: None,
+            adapters: HashMap::new(),
+            signing_adapter: None,
+            preview_handler: None,
+            send_middleware: None,
+            note_selector: Arc::new(FewestNotes),
+            config,
+            storage,
+            tree_storage: Arc::new(MemoryStorage::new()),
+            history,
+            policy: PolicyEngine::default(),
+            events: EventBus::new(),
+        })
+    }
+
+    /// Create wallet from existing keypair
+    pub fn from_keypair(keypair: Keypair, config: WalletConfig) -> Result<Self> {
+        let storage = open_storage(&config, &keypair)?;
+        let history = open_history(&config, &keypair)?;
+
+        Ok(Self {
+            accounts: vec![keypair.pubkey()],
+            keys: KeyVault::new(keypair, None),
+            active_account: 0,
+            ledger: None,
+            privacy_client: None,
+            adapters: HashMap::new(),
+            signing_adapter: None,
+            preview_handler: None,
+            send_middleware: None,
+            note_selector: Arc::new(FewestNotes),
+            config,
+            storage,
+            tree_storage: Arc::new(MemoryStorage::new()),
+            history,
+            policy: PolicyEngine::default(),
+            events: EventBus::new(),
+        })
+    }
+
+    /// Create a wallet with a new recovery phrase of `words` words and the
+    /// optional BIP39 `passphrase`. The phrase is kept encrypted with
+    /// `password` and returned once, for the user to write down.
+    pub fn generate_with_mnemonic(
+        words: WordCount,
+        passphrase: &str,
+        password: &str,
+        config: WalletConfig,
+    ) -> Result<(Self, String)> {
+        let phrase = generate_mnemonic(words);
+        let wallet = Self::from_mnemonic(&phrase, passphrase, password, config)?;
+        Ok((wallet, phrase))
+    }
+
+    /// Restore the wallet of a BIP39 recovery phrase, at the account Solana
+    /// wallets derive first, keeping the phrase encrypted with `password`.
+    /// An invalid phrase fails with a `MnemonicError`.
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+        password: &str,
+        config: WalletConfig,
+    ) -> Result<Self> {
+        let seed = HdSeed::from_mnemonic(phrase, passphrase)?;
+        let keypair = seed.derive_account(0)?;
+        let mut storage = open_storage(&config, &keypair)?;
+        let history = open_history(&config, &keypair)?;
+        let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+        storage.store_seed_phrase(&normalized.to_lowercase(), password)?;
+
+        Ok(Self {
+            accounts: vec![keypair.pubkey()],
+            keys: KeyVault::new(keypair, Some(seed)),
+            active_account: 0,
+            ledger: None,
+            privacy_client: None,
+            adapters: HashMap::new(),
+            signing_adapter: None,
+            preview_handler: None,
+            send_middleware: None,
+            note_selector: Arc::new(FewestNotes),
+            config,
+            storage,
+            tree_storage: Arc::new(MemoryStorage::new()),
+            history,
+            policy: PolicyEngine::default(),
+            events: EventBus::new(),
+        })
+    }
+
+    /// Recovery phrase the wallet was created from, decrypted with
+    /// `password`
+    pub fn recovery_phrase(&self, password: &str) -> Result<String> {
+        let encrypted = self.storage.encrypted_seed_phrase()?
+            .ok_or_else(|| anyhow!("Wallet was not created from a recovery phrase"))?;
+        self.storage.retrieve_seed_phrase(&encrypted, password)
+    }
+
+    /// Change the password the recovery phrase is kept under, re-sealing
+    /// the stored deposits, notes and address book under a new key. The
+    /// transaction history keeps its own key and is left as it is.
+    pub fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<()> {
+        let keypair = self.keys.with_keys(|keys| match &keys.seed {
+            Some(seed) => seed.derive_account(0),
+            None => Ok(Keypair::from_bytes(&keys.keypair.to_bytes())?),
+        })?;
+        self.storage.rotate_password(&keypair, old_password, new_password)?;
+        self.keys.reseal(new_password)
+    }
+
+    /// Lock the wallet whenever it goes `idle_timeout` without signing,
+    /// dropping its keys from memory until `unlock(password)`. The keys are
+    /// kept sealed under `password` meanwhile; for a wallet with a recovery
+    /// phrase it should be the wallet password. Lock changes are sent out
+    /// as `LockChanged` events.
+    pub fn enable_auto_lock(&self, password: &str, idle_timeout: Duration) -> Result<()> {
+        self.keys.enable_auto_lock(password, idle_timeout, self.events.clone())
+    }
+
+    /// Stop locking the wallet, which must be unlocked
+    pub fn disable_auto_lock(&self) -> Result<()> {
+        self.keys.disable_auto_lock()
+    }
+
+    /// Whether the wallet is locked, so operations that sign fail with
+    /// `WalletLocked`
+    pub fn is_locked(&self) -> bool {
+        self.keys.is_locked()
+    }
+
+    /// Drop the wallet's keys from memory now. Needs auto-lock enabled,
+    /// which keeps them sealed for `unlock`.
+    pub fn lock(&self) -> Result<()> {
+        if self.keys.lock()? {
+            self.events.emit(WalletEvent::LockChanged { locked: true });
+        }
+        Ok(())
+    }
+
+    /// Open the keys sealed by `enable_auto_lock` with `password`, which
+    /// fails with `KeystoreError::WrongPassword` if it is not the one given
+    pub fn unlock(&self, password: &str) -> Result<()> {
+        if self.keys.unlock(password)? {
+            self.events.emit(WalletEvent::LockChanged { locked: false });
+        }
+        Ok(())
+    }
+
+    /// Privacy client, for an operation that signs. Fails with
+    /// `WalletLocked` while the wallet is locked, and otherwise counts as
+    /// use of the wallet.
+    fn signing_client(&self) -> Result<&UntraceClient> {
+        self.keys.touch()?;
+        self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))
+    }
+
+    /// Accounts of the wallet, as account number and public key
+    pub fn accounts(&self) -> Vec<(u32, Pubkey)> {
+        (0..).zip(self.accounts.iter().copied()).collect()
+    }
+
+    /// Account the wallet currently uses
+    pub fn active_account(&self) -> u32 {
+        self.active_account
+    }
+
+    /// Derive the next account from the recovery phrase, without switching
+    /// to it
+    pub fn create_account(&mut self) -> Result<(u32, Pubkey)> {
+        let account = self.accounts.len() as u32;
+        let pubkey = self.keys.with_keys(|keys| {
+            let seed = keys.seed.as_ref()
+                .ok_or_else(|| anyhow!("Wallet was not created from a recovery phrase"))?;
+            Ok(seed.derive_account(account)?.pubkey())
+        })?;
+        self.accounts.push(pubkey);
+        Ok((account, pubkey))
+    }
+
+    /// Sign, deposit and keep notes as `account` from now on, reconnecting
+    /// the privacy client if it was initialized
+    pub fn switch_account(&mut self, account: u32) -> Result<()> {
+        if account as usize >= self.accounts.len() {
+            return Err(anyhow!("Unknown account {}", account));
+        }
+        if account == self.active_account {
+            return Ok(());
+        }
+        let keypair = self.keys.with_keys(|keys| {
+            let seed = keys.seed.as_ref()
+                .ok_or_else(|| anyhow!("Wallet was not created from a recovery phrase"))?;
+            seed.derive_account(account)
+        })?;
+        self.keys.set_active(account, keypair)?;
+        self.active_account = account;
+        if self.privacy_client.is_some() {
+            self.init_privacy_client()?;
+        }
+        Ok(())
+    }
+
+    /// Keep a shielded note received by the active account
+    pub fn save_note(&self, note: ShieldedNote) -> Result<()> {
+        self.storage.store_note(self.active_account, note)
+    }
+
+    /// Unspent shielded notes of the active account
+    pub fn notes(&self) -> Result<Vec<ShieldedNote>> {
+        self.storage.notes(self.active_account)
+    }
+
+    /// Unspent notes of the active account, oldest first, with the
+    /// transactions they came from where known
+    pub fn spendable_notes(&self) -> Result<Vec<SpendableNote>> {
+        let mut origins = self.storage.note_origins(self.active_account)?;
+        Ok(self
+            .notes()?
+            .into_iter()
+            .map(|note| SpendableNote {
+                origin: origins.remove(&hex::encode(note.commitment())),
+                note,
+            })
+            .collect())
+    }
+
+    /// Pick the notes private transfers and withdrawals spend with
+    /// `selector` instead of `FewestNotes`
+    pub fn set_note_selector(&mut self, selector: impl NoteSelector + 'static) {
+        self.note_selector = Arc::new(selector);
+    }
+
+    /// Keep `note`, which came out of the transaction `signature`
+    fn keep_note(&self, note: ShieldedNote, signature: &str) -> Result<()> {
+        let commitment = note.commitment();
+        self.save_note(note)?;
+        self.storage.store_note_origin(self.active_account, &commitment, NoteOrigin::new(signature))
+    }
+
+    /// Address book shared by the wallet's accounts
+    pub fn address_book(&self) -> Result<AddressBook> {
+        self.storage.address_book()
+    }
+
+    /// Keep `book` as the wallet's address book. History entries are
+    /// labeled from it when they are read.
+    pub fn save_address_book(&self, book: &AddressBook) -> Result<()> {
+        self.storage.store_address_book(book)
+    }
+
+    /// Why shielded funds should not go to `address`, from its tags in the
+    /// address book. Private and cross-chain transfers to such an address
+    /// fail with this warning, so a UI can show it before the user confirms
+    /// and offer to edit the contact instead.
+    pub fn recipient_warning(&self, address: &str) -> Result<Option<RecipientWarning>> {
+        Ok(self.storage.address_book()?.shielded_send_warning(address))
+    }
+
+    /// Initialize privacy client
+    pub fn init_privacy_client(&mut self) -> Result<()> {
+        let program_id = self.config.program_id.parse::<Pubkey>()
+            .map_err(|e| anyhow!("Invalid program ID: {}", e))?;
+
+        let mut client_config = self.config.cluster
+            .map(|cluster| cluster.config().client_config)
+            .unwrap_or_default();
+        client_config.fee_sponsor = self.config.fee_sponsor.clone();
+
+        let signing_adapter = self
+            .signing_adapter
+            .as_ref()
+            .and_then(|(name, _)| self.adapters.get(name));
+        let payer = match (signing_adapter, &self.ledger) {
+            (Some(adapter), _) => Payer::External(Arc::new(AdapterSigner::new(adapter.clone())?)),
+            (None, Some(ledger)) => Payer::Local(ledger.clone()),
+            (None, None) => Payer::Local(Arc::new(VaultSigner(self.keys.clone()))),
+        };
+        let rpc_url = &self.config.rpc_url;
+        let mut client = match (payer, &self.preview_handler) {
+            (payer, Some(handler)) => {
+                let signer = PreviewSigner::new(payer, program_id, handler.clone());
+                UntraceClient::with_transaction_signer(
+                    rpc_url,
+                    program_id,
+                    Arc::new(signer),
+                    client_config,
+                )
+            }
+            (Payer::External(signer), None) => {
+                UntraceClient::with_transaction_signer(rpc_url, program_id, signer, client_config)
+            }
+            (Payer::Local(payer), None) => {
+                UntraceClient::with_signer(rpc_url, program_id, payer, client_config)
+            }
+        };
+
+        if self.config.anti_mev_enabled {
+            let middleware = self.send_middleware.clone()
+                .unwrap_or_else(|| Arc::new(AntiMevMiddleware::for_rpc(rpc_url)));
+            client.middleware = Some(middleware);
+        }
+
+        self.privacy_client = Some(client);
+        Ok(())
+    }
+
+    /// Connect to external wallet adapter (Phantom, Solflare, etc.). Its
+    /// account changes and disconnects are sent out as `AdapterChanged`
+    /// events under `name`. Replacing the signing adapter goes back to
+    /// signing with the wallet's own keys.
+    pub async fn connect_adapter(
+        &mut self,
+        name: String,
+        mut adapter: Box<dyn WalletAdapter>,
+    ) -> Result<()> {
+        adapter.on_event(self.forward_adapter_events(&name));
+        adapter.connect().await?;
+        self.stop_signing_with(&name)?;
+        if let Some(previous) = self.adapters.insert(name, Arc::from(adapter)) {
+            close_adapter(previous).await?;
+        }
+        Ok(())
+    }
+
+    /// Disconnect and drop the adapter connected as `name`, going back to
+    /// the wallet's own keys if it was signing
+    pub async fn disconnect_adapter(&mut self, name: &str) -> Result<()> {
+        let adapter = self.adapters.remove(name)
+            .ok_or_else(|| anyhow!("No adapter connected as {}", name))?;
+        self.stop_signing_with(name)?;
+        close_adapter(adapter).await
+    }
+
+    /// Pay for and sign deposits, withdrawals and transfers through the
+    /// adapter connected as `name`, which is handed each transaction to
+    /// approve, or with the wallet's own keys again for `None`. Reconnects
+    /// the privacy client if it was initialized. Once the wallet behind
+    /// the adapter switches accounts, sends fail until it is selected again.
+    pub fn set_signing_adapter(&mut self, name: Option<&str>) -> Result<()> {
+        self.signing_adapter = match name {
+            Some(name) => {
+                let adapter = self.adapters.get(name)
+                    .ok_or_else(|| anyhow!("No adapter connected as {}", name))?;
+                Some((name.to_string(), adapter.get_public_key()?))
+            }
+            None => None,
+        };
+        if self.privacy_client.is_some() {
+            self.init_privacy_client()?;
+        }
+        Ok(())
+    }
+
+    /// Have `handler` acknowledge a `TransactionPreview` of every
+    /// transaction before the wallet signs it, whichever key signs, or sign
+    /// without asking again for `None`. A transaction the handler rejects
+    /// fails without being signed or sent. Reconnects the privacy client
+    /// if it was initialized.
+    pub fn set_preview_handler(&mut self, handler: Option<PreviewHandler>) -> Result<()> {
+        self.preview_handler = handler;
+        if self.privacy_client.is_some() {
+            self.init_privacy_client()?;
+        }
+        Ok(())
+    }
+
+    /// Send through `middleware` while `anti_mev_enabled` is set, such as
+    /// an `AntiMevMiddleware` with private endpoints, or through the
+    /// default one, which jitters sends to the wallet's RPC node, for
+    /// `None`. Reconnects the privacy client if it was initialized.
+    pub fn set_send_middleware(
+        &mut self,
+        middleware: Option<Arc<dyn SendMiddleware>>,
+    ) -> Result<()> {
+        self.send_middleware = middleware;
+        if self.privacy_client.is_some() {
+            self.init_privacy_client()?;
+        }
+        Ok(())
+    }
+
+    /// Name of the adapter signing instead of the wallet's own keys
+    pub fn signing_adapter(&self) -> Option<&str> {
+        self.signing_adapter.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Release the privacy client's hold on the adapter `name` if it signs
+    fn stop_signing_with(&mut self, name: &str) -> Result<()> {
+        if self.signing_adapter() == Some(name) {
+            self.set_signing_adapter(None)?;
+        }
+        Ok(())
+    }
+
+    /// Adapter connected as `name`
+    pub fn adapter(&self, name: &str) -> Option<&dyn WalletAdapter> {
+        self.adapters.get(name).map(|adapter| adapter.as_ref())
+    }
+
+    /// Sign deposits, withdrawals and transfers on `ledger` instead of
+    /// with the wallet's own keys, reconnecting the privacy client if it
+    /// was initialized. Returns the Ledger account's public key.
+    pub async fn connect_ledger(&mut self, mut ledger: LedgerAdapter) -> Result<Pubkey> {
+        ledger.on_event(self.forward_adapter_events(LEDGER_ADAPTER_NAME));
+        ledger.connect().await?;
+        let pubkey = ledger.get_public_key()?;
+        self.ledger = Some(Arc::new(ledger));
+        if self.privacy_client.is_some() {
+            self.init_privacy_client()?;
+        }
+        Ok(pubkey)
+    }
+
+    /// Go back to signing with the wallet's own keys
+    pub fn disconnect_ledger(&mut self) -> Result<()> {
+        if let Some(ledger) = self.ledger.take() {
+            ledger.close();
+            if self.privacy_client.is_some() {
+                self.init_privacy_client()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn forward_adapter_events(&self, name: &str) -> AdapterListener {
+        let (events, name) = (self.events.clone(), name.to_string());
+        Box::new(move |event| {
+            events.emit(WalletEvent::AdapterChanged {
+                name: name.clone(),
+                event: event.clone(),
+            })
+        })
+    }
+
+    /// Get public key, the signing adapter's or connected Ledger's if
+    /// there is one
+    pub fn public_key(&self) -> Pubkey {
+        if let Some((_, pubkey)) = &self.signing_adapter {
+            return *pubkey;
+        }
+        match &self.ledger {
+            Some(ledger) => ledger.pubkey(),
+            None => self.keys.pubkey(),
+        }
+    }
+
+    /// Send private transaction by spending shielded `notes`.
+    /// Returns the signature and the new `[recipient_note, change_note]`.
+    pub async fn send_private_transaction(
+        &self,
+        notes: &[ShieldedNote],
+        recipient: &Pubkey,
+        amount: u64,
+        privacy_level: Option<PrivacyLevel>,
+    ) -> Result<(String, [ShieldedNote; 2])> {
+        let client = self.signing_client()?;
+
+        if let Some(warning) = self.recipient_warning(&recipient.to_string())? {
+            return Err(warning.into());
+        }
+
+        let level = privacy_level.unwrap_or(self.config.default_privacy_level);
+
+        let request = SpendRequest {
+            kind: SpendKind::PrivateTransfer,
+            asset: notes.first().map_or(NATIVE_ASSET_ID, |note| note.asset_id),
+            amount,
+            recipient: recipient.to_string(),
+            privacy_level: Some(level),
+        };
+        let (signature, outputs) = self
+            .with_policy(&request, async {
+                Ok(client.private_transfer().transfer(notes, recipient, amount, level).await?)
+            })
+            .await?;
+
+        let mut activity = Activity::new(
+            self.active_account,
+            ActivityKind::PrivateTransfer,
+            signature.to_string(),
+            amount,
+        )
+        .with_counterparty(recipient.to_string());
+        if outputs[0].asset_id != NATIVE_ASSET_ID {
+            activity = activity.with_mint(outputs[0].asset_id.to_string());
+        }
+        self.record_activity(activity);
+
+        Ok((signature.to_string(), outputs))
+    }
+
+    /// Send `amount` of the SPL token `mint` privately, spending the active
+    /// account's stored notes of it and keeping the change note.
+    /// Returns the signature and the note to deliver to the recipient.
+    pub async fn send_private_token_transfer(
+        &self,
+        mint: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+        privacy_level: Option<PrivacyLevel>,
+    ) -> Result<(String, ShieldedNote)> {
+        use untrace_privacy_client::private_transfer::TRANSFER_NOTES;
+
+        let inputs =
+            self.note_selector.select(&self.spendable_notes()?, mint, amount, TRANSFER_NOTES)?;
+        let (signature, [recipient_note, change_note]) = self
+            .send_private_transaction(&inputs, recipient, amount, privacy_level)
+            .await?;
+
+        for input in &inputs {
+            self.storage.remove_note(self.active_account, &input.commitment())?;
+        }
+        if change_note.amount > 0 {
+            self.keep_note(change_note, &signature)?;
+        }
+        Ok((signature, recipient_note))
+    }
+
+    /// Withdraw `amount` of the SPL token `mint`, or SOL for
+    /// `NATIVE_ASSET_ID`, from the active account's shielded notes to
+    /// `recipient`, spending the notes the note selector picks. A
+    /// withdrawal pays out one whole note, so unless a note holds exactly
+    /// `amount` the picked notes are first split by a private transfer to
+    /// the active account, whose change note is kept. Returns the
+    /// withdrawal's signature.
+    pub async fn withdraw_token(
+        &self,
+        mint: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+    ) -> Result<String> {
+        use untrace_privacy_client::private_transfer::TRANSFER_NOTES;
+
+        let client = self.signing_client()?;
+
+        let inputs =
+            self.note_selector.select(&self.spendable_notes()?, mint, amount, TRANSFER_NOTES)?;
+        let request = SpendRequest {
+            kind: SpendKind::Withdrawal,
+            asset: *mint,
+            amount,
+            recipient: recipient.to_string(),
+            privacy_level: None,
+        };
+        let signature = self
+            .with_policy(&request, async {
+                let note = match inputs.as_slice() {
+                    [note] if note.amount == amount => note.clone(),
+                    _ => {
+                        let level = self.config.default_privacy_level;
+                        let (signature, [exact, change]) = client
+                            .private_transfer()
+                            .transfer(&inputs, &self.public_key(), amount, level)
+                            .await?;
+                        for input in &inputs {
+                            self.storage.remove_note(self.active_account, &input.commitment())?;
+                        }
+                        let signature = signature.to_string();
+                        self.keep_note(exact.clone(), &signature)?;
+                        if change.amount > 0 {
+                            self.keep_note(change, &signature)?;
+                        }
+                        exact
+                    }
+                };
+
+                let signature = client.shielded_pool().withdraw(&note, recipient).await?;
+                self.storage.remove_note(self.active_account, &note.commitment())?;
+                Ok(signature)
+            })
+            .await?;
+
+        let mut activity = Activity::new(
+            self.active_account,
+            ActivityKind::Withdrawal,
+            signature.to_string(),
+            amount,
+        )
+        .with_counterparty(recipient.to_string());
+        if *mint != NATIVE_ASSET_ID {
+            activity = activity.with_mint(mint.to_string());
+        }
+        self.record_activity(activity);
+
+        Ok(signature.to_string())
+    }
+
+    /// Pay `request` privately from the active account's notes, as
+    /// `send_private_token_transfer` does, at the higher of its privacy
+    /// level and the default one. Its reference and memo are not put on
+    /// chain, where they would tie the payment to the request.
+    /// Returns the signature and the note to deliver to the recipient.
+    pub async fn pay_request(&self, request: &PaymentRequest) -> Result<(String, ShieldedNote)> {
+        let mint = request.asset_id();
+        let decimals = if mint == NATIVE_ASSET_ID {
+            SOL_DECIMALS
+        } else {
+            self.token_metadata(&mint).await?.decimals
+        };
+        let amount = request
+            .base_units(decimals)?
+            .ok_or_else(|| anyhow!("Payment request has no amount"))?;
+
+        let default = self.config.default_privacy_level;
+        let level = request.privacy_level.map_or(default, |level| level.max(default));
+        self.send_private_token_transfer(&mint, &request.recipient, amount, Some(level))
+            .await
+    }
+
+    /// Shield `amount` of the SPL token `mint` into a note of the active
+    /// account, which is kept in storage to spend later
+    pub async fn deposit_token(
+        &self,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(String, ShieldedNote)> {
+        let client = self.signing_client()?;
+
+        let (signature, note) = client
+            .shielded_pool()
+            .deposit(mint, &self.public_key(), amount)
+            .await?;
+
+        // Without the note the deposit can never be spent, so keep it
+        // before anything else can fail
+        self.keep_note(note.clone(), &signature.to_string())?;
+
+        let activity =
+            Activity::new(self.active_account, ActivityKind::Deposit, signature.to_string(), amount)
+                .with_mint(mint.to_string());
+        self.record_activity(activity);
+
+        Ok((signature.to_string(), note))
+    }
+
+    /// Send cross-chain private transfer, encrypted for `recipient_key`,
+    /// the encryption key the recipient published on the destination chain
+    pub async fn send_cross_chain_transfer(
+        &self,
+        dest_chain: u16,
+        recipient: &str,
+        recipient_key: &str,
+        amount: u64,
+        token: &str,
+    ) -> Result<String> {
+        let client = self.signing_client()?;
+
+        use untrace_privacy_client::{cross_chain::SupportedChain, EncryptionKey};
+
+        if let Some(warning) = self.recipient_warning(recipient)? {
+            return Err(warning.into());
+        }
+
+        let source = SupportedChain::Solana;
+        let dest = SupportedChain::from_u16(dest_chain)
+            .filter(|chain| *chain != source)
+            .ok_or_else(|| anyhow!("Unsupported chain"))?;
+        let recipient_key = EncryptionKey::parse(dest, recipient_key)?;
+
+        let asset = match client.token_registry.get(source, token) {
+            Some(info) => match &info.address {
+                Some(mint) => mint.parse().map_err(|_| anyhow!("Invalid mint {}", mint))?,
+                None => NATIVE_ASSET_ID,
+            },
+            None => return Err(anyhow!("Unknown token {} on Solana", token)),
+        };
+        let request = SpendRequest {
+            kind: SpendKind::Bridge,
+            asset,
+            amount,
+            recipient: recipient.to_string(),
+            privacy_level: None,
+        };
+        let signature = self
+            .with_policy(&request, async {
+                Ok(client
+                    .cross_chain()
+                    .bridge_transfer(source, dest, recipient, &recipient_key, amount, token)
+                    .await?)
+            })
+            .await?;
+
+        // Pending until the transfer is claimed on the destination chain
+        let activity =
+            Activity::new(self.active_account, ActivityKind::Bridge, signature.to_string(), amount)
+                .with_status(ActivityStatus::Pending)
+                .with_counterparty(recipient)
+                .with_chain(dest_chain);
+        self.record_activity(activity);
+
+        Ok(signature.to_string())
+    }
+
+    /// Lamports that may still be bridged to `dest_chain` this epoch, or
+    /// `None` when the chain is uncapped, so a transfer over the cap can be
+    /// flagged before it is sent
+    pub async fn bridge_capacity(&self, dest_chain: u16) -> Result<Option<u64>> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        use untrace_privacy_client::cross_chain::SupportedChain;
+
+        let dest = SupportedChain::from_u16(dest_chain)
+            .filter(|chain| *chain != SupportedChain::Solana)
+            .ok_or_else(|| anyhow!("Unsupported chain"))?;
+
+        Ok(client.cross_chain().remaining_capacity(dest).await?)
+    }
+
+    /// Outbound bridge transfers that went unclaimed long enough to be
+    /// refunded
+    pub async fn refundable_bridge_transfers(&self) -> Result<Vec<RefundableTransfer>> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        let transfers = client.cross_chain().refundable_transfers().await?;
+        Ok(transfers
+            .into_iter()
+            .map(|(address, transfer)| RefundableTransfer {
+                address: address.to_string(),
+                dest_chain: transfer.dest_chain,
+                sequence: transfer.sequence,
+                amount: transfer.amount,
+                posted_at: transfer.posted_at,
+            })
+            .collect())
+    }
+
+    /// Refund an unclaimed bridge transfer back to this wallet, once the
+    /// guardian set attests it expired on its destination
+    pub async fn refund_bridge_transfer(&self, address: &str) -> Result<String> {
+        let client = self.signing_client()?;
+
+        let address: Pubkey = address.parse()
+            .map_err(|_| anyhow!("Invalid transfer address: {}", address))?;
+        let signature = client.cross_chain().refund(&address).await?;
+
+        Ok(signature.to_string())
+    }
+
+    /// Burn `amount` sats of this wallet's wrapped BTC to exit to the
+    /// Taproot `address` on Bitcoin, where custody pays out the amount less
+    /// `relayer_fee`
+    pub async fn exit_to_bitcoin(
+        &self,
+        address: &str,
+        amount: u64,
+        relayer_fee: u64,
+    ) -> Result<String> {
+        let client = self.signing_client()?;
+
+        use untrace_privacy_client::{
+            bitcoin::BTC_ASSET,
+            cross_chain::SupportedChain,
+            wrapped::{find_wrapped_mint_address, find_wrapped_token_address},
+        };
+
+        let recipient = SupportedChain::Bitcoin.parse_address(address)?;
+        let (wrapped_mint, _) =
+            find_wrapped_mint_address(&client.program_id, SupportedChain::Bitcoin, &BTC_ASSET);
+        let request = SpendRequest {
+            kind: SpendKind::Bridge,
+            asset: find_wrapped_token_address(&client.program_id, &wrapped_mint).0,
+            amount,
+            recipient: address.to_string(),
+            privacy_level: None,
+        };
+        let signature = self
+            .with_policy(&request, async {
+                Ok(client
+                    .wrapped_assets()
+                    .burn(
+                        SupportedChain::Bitcoin,
+                        &BTC_ASSET,
+                        recipient.to_bytes32(),
+                        amount,
+                        relayer_fee,
+                    )
+                    .await?)
+            })
+            .await?;
+
+        // Pending until custody pays out on Bitcoin
+        let activity =
+            Activity::new(self.active_account, ActivityKind::Bridge, signature.to_string(), amount)
+                .with_status(ActivityStatus::Pending)
+                .with_counterparty(address)
+                .with_chain(SupportedChain::Bitcoin.to_u16());
+        self.record_activity(activity);
+
+        Ok(signature.to_string())
+    }
+
+    /// Deposit to privacy pool
+    pub async fn deposit_to_pool(
+        &self,
+        pool_id: u64,
+        recipient: &Pubkey,
+        amount: u64,
+    ) -> Result<(String, [u8; 32], [u8; 32])> {
+        let client = self.signing_client()?;
+
+        let (signature, commitment, randomness, leaf_index) = client
+            .privacy_pool()
+            .deposit(pool_id, recipient, amount)
+            .await?;
+
+        // Without the randomness the deposit can never be withdrawn, so
+        // keep it before anything else can fail
+        let deposit =
+            DepositNote::new(pool_id, commitment, randomness, leaf_index, amount, *recipient);
+        self.storage.store_deposit(self.active_account, &deposit)?;
+
+        let activity =
+            Activity::new(self.active_account, ActivityKind::Deposit, signature.to_string(), amount)
+                .with_counterparty(recipient.to_string())
+                .with_pool(pool_id);
+        self.record_activity(activity);
+
+        Ok((signature.to_string(), commitment, randomness))
+    }
+
+    /// Withdraw from privacy pool
+    pub async fn withdraw_from_pool(
+        &self,
+        pool_id: u64,
+        commitment: &[u8; 32],
+        recipient: &Pubkey,
+    ) -> Result<String> {
+        let client = self.signing_client()?;
+
+        let account = self.active_account;
+        let deposit = self.storage.deposit(account, commitment)?
+            .ok_or_else(|| anyhow!("No deposit with this commitment on account {}", account))?;
+        if deposit.pool_id != pool_id {
+            return Err(anyhow!("Deposit is in pool {}, not {}", deposit.pool_id, pool_id));
+        }
+        if deposit.status == DepositStatus::Spent {
+            return Err(anyhow!("Deposit was already withdrawn"));
+        }
+        let request = SpendRequest {
+            kind: SpendKind::Withdrawal,
+            asset: NATIVE_ASSET_ID,
+            amount: deposit.amount,
+            recipient: recipient.to_string(),
+            privacy_level: None,
+        };
+        let now = auto_mix::unix_now();
+        self.policy.authorize(&request, now)?;
+        if let Err(error) =
+            self.storage.set_deposit_status(account, commitment, DepositStatus::Withdrawing)
+        {
+            self.policy.release(&request, now);
+            return Err(error);
+        }
+
+        let result = async {
+            // Catch up with the pool's tree to prove the commitment is in it
+            let mut sync =
+                MerkleSync::load(&client.program_id, pool_id, self.tree_storage.clone())?;
+            sync.sync(client).await?;
+            let proof = sync.proof(commitment)?;
+
+            let signature = client
+                .privacy_pool()
+                .withdraw(
+                    pool_id,
+                    commitment,
+                    &deposit.randomness,
+                    deposit.amount,
+                    recipient,
+                    &proof,
+                )
+                .await?;
+            Ok::<_, anyhow::Error>(signature)
+        }
+        .await;
+
+        match result {
+            Ok(signature) => {
+                self.storage.set_deposit_status(account, commitment, DepositStatus::Spent)?;
+                let activity = Activity::new(
+                    account,
+                    ActivityKind::Withdrawal,
+                    signature.to_string(),
+                    deposit.amount,
+                )
+                .with_counterparty(recipient.to_string())
+                .with_pool(pool_id);
+                self.record_activity(activity);
+                Ok(signature.to_string())
+            }
+            Err(error) => {
+                self.policy.release(&request, now);
+                self.storage.set_deposit_status(account, commitment, DepositStatus::Unspent)?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Create an M-of-N multisig of `signers`, `threshold` of whom approve
+    /// each of its transactions, paying the rent of its authority account.
+    /// The config returned is what the co-signers share.
+    pub async fn create_multisig(
+        &self,
+        threshold: u8,
+        signers: Vec<Pubkey>,
+    ) -> Result<MultisigConfig> {
+        let client = self.signing_client()?;
+
+        let authority = Keypair::new();
+        let config = MultisigConfig::new(authority.pubkey(), threshold, signers)?;
+        let lamports = client
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Multisig::LEN)
+            .await?;
+        let instructions = config.create_instructions(&client.payer.pubkey(), lamports)?;
+        client.send_transaction_with_signers(instructions, &[&authority]).await?;
+        Ok(config)
+    }
+
+    /// Propose sending `amount` of `mint` from the multisig's token account
+    /// to `recipient`, for `cosigners` to approve. The transaction is built
+    /// against `nonce_account`, a durable nonce whose authority is the
+    /// wallet, and comes back signed by the wallet as its fee payer.
+    pub async fn propose_multisig_send(
+        &self,
+        config: &MultisigConfig,
+        mint: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+        cosigners: &[Pubkey],
+        nonce_account: &Pubkey,
+    ) -> Result<SigningRequest> {
+        let client = self.signing_client()?;
+
+        let instructions =
+            config.send_instructions(&client.payer.pubkey(), mint, recipient, amount, cosigners)?;
+        let action = MultisigAction::Send {
+            mint: *mint,
+            recipient: *recipient,
+            amount,
+        };
+        self.propose_multisig(client, config, action, instructions, cosigners, nonce_account)
+            .await
+    }
+
+    /// Propose withdrawing the active account's deposit of `commitment`,
+    /// made out to the multisig's authority, to `recipient`, for
+    /// `cosigners` to approve. The withdrawal carries them as signers, so
+    /// its proof only lands with their signatures; the transaction is built
+    /// as for `propose_multisig_send`.
+    pub async fn propose_multisig_withdrawal(
+        &self,
+        config: &MultisigConfig,
+        pool_id: u64,
+        commitment: &[u8; 32],
+        recipient: &Pubkey,
+        cosigners: &[Pubkey],
+        nonce_account: &Pubkey,
+    ) -> Result<SigningRequest> {
+        let client = self.signing_client()?;
+        if client.config.fee_sponsor.is_some() {
+            return Err(anyhow!("Multisig withdrawals are paid by the wallet, not a fee sponsor"));
+        }
+
+        let account = self.active_account;
+        let deposit = self.storage.deposit(account, commitment)?
+            .ok_or_else(|| anyhow!("No deposit with this commitment on account {}", account))?;
+        if deposit.pool_id != pool_id {
+            return Err(anyhow!("Deposit is in pool {}, not {}", deposit.pool_id, pool_id));
+        }
+        if deposit.recipient != config.authority {
+            return Err(anyhow!("Deposit is not made out to the multisig"));
+        }
+        if deposit.status == DepositStatus::Spent {
+            return Err(anyhow!("Deposit was already withdrawn"));
+        }
+
+        let mut sync = MerkleSync::load(&client.program_id, pool_id, self.tree_storage.clone())?;
+        sync.sync(client).await?;
+        let proof = sync.proof(commitment)?;
+        let withdraw = client
+            .privacy_pool()
+            .withdraw_instruction(
+                pool_id,
+                commitment,
+                &deposit.randomness,
+                deposit.amount,
+                recipient,
+                &proof,
+            )
+            .await?;
+
+        let instructions = vec![config.cosign_withdrawal(withdraw, cosigners)?];
+        let action = MultisigAction::Withdraw {
+            pool_id,
+            commitment: *commitment,
+            recipient: *recipient,
+        };
+        self.propose_multisig(client, config, action, instructions, cosigners, nonce_account)
+            .await
+    }
+
+    async fn propose_multisig(
+        &self,
+        client: &UntraceClient,
+        config: &MultisigConfig,
+        action: MultisigAction,
+        instructions: Vec<Instruction>,
+        cosigners: &[Pubkey],
+        nonce_account: &Pubkey,
+    ) -> Result<SigningRequest> {
+        let payer = client.payer.pubkey();
+        let transaction = client
+            .nonce()
+            .build_transaction(instructions, nonce_account, &payer)
+            .await?;
+        let mut request = SigningRequest::new(
+            config.clone(),
+            action,
+            cosigners.to_vec(),
+            transaction,
+            &client.program_id,
+        )?;
+        sign_request(client, &mut request).await?;
+        Ok(request)
+    }
+
+    /// Approve a signing request another co-signer exported, once it is
+    /// checked to do what it asks to approve, returning it signed by the
+    /// active account to pass on
+    pub async fn sign_multisig_request(&self, exported: &str) -> Result<SigningRequest> {
+        let client = self.signing_client()?;
+
+        let mut request = SigningRequest::import(exported, &client.program_id)?;
+        sign_request(client, &mut request).await?;
+        Ok(request)
+    }
+
+    /// Submit a signing request every signer has signed, with signatures
+    /// collected on separate copies first merged into it by
+    /// `SigningRequest::merge`
+    pub async fn submit_multisig(&self, request: &SigningRequest) -> Result<String> {
+        let client = self.signing_client()?;
+
+        let transaction = request.assemble()?;
+        let signature = client
+            .nonce()
+            .send_transaction(&transaction, &request.nonce_account()?)
+            .await?;
+
+        if let MultisigAction::Withdraw { pool_id, commitment, recipient } = &request.action {
+            let account = self.active_account;
+            if let Some(deposit) = self.storage.deposit(account, commitment)? {
+                self.storage.set_deposit_status(account, commitment, DepositStatus::Spent)?;
+                let activity = Activity::new(
+                    account,
+                    ActivityKind::Withdrawal,
+                    signature.to_string(),
+                    deposit.amount,
+                )
+                .with_counterparty(recipient.to_string())
+                .with_pool(*pool_id);
+                self.record_activity(activity);
+            }
+        }
+        Ok(signature.to_string())
+    }
+
+    /// Deposit the active account's transparent SOL into denominated
+    /// privacy pools in the background, one pool denomination at a time
+    /// after a random delay, within `config`'s daily limits, which count
+    /// from storage so a restart does not reset them. Only pools with at
+    /// least `min_pool_size` deposits are used. Needs the privacy client
+    /// initialized and `auto_mix_enabled` set; with a Ledger connected or
+    /// an adapter signing every deposit would wait for approval, so it
+    /// refuses.
+    pub fn start_auto_mix(self: &Arc<Self>, config: AutoMixConfig) -> Result<AutoMixHandle> {
+        use auto_mix::{is_stopped, wait_running, AutoMixState};
+
+        if !self.config.auto_mix_enabled {
+            return Err(anyhow!("Auto-mix is disabled in the wallet config"));
+        }
+        if self.privacy_client.is_none() {
+            return Err(anyhow!("Privacy client not initialized"));
+        }
+        if self.ledger.is_some() {
+            return Err(anyhow!("Auto-mix cannot run while a Ledger is connected"));
+        }
+        if self.signing_adapter.is_some() {
+            return Err(anyhow!("Auto-mix cannot run while an adapter signs"));
+        }
+
+        // The client's send futures are not `Send`, so the scheduler gets a
+        // thread and runtime of its own rather than a task on the caller's
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let (state, mut receiver) = watch::channel(AutoMixState::Running);
+        let status = Arc::new(Mutex::new(AutoMixStatus {
+            today: self.storage.auto_mix_usage()?,
+            ..AutoMixStatus::default()
+        }));
+        let wallet = self.clone();
+        let task_status = status.clone();
+        let task = std::thread::spawn(move || {
+            runtime.block_on(async {
+                while !is_stopped(&receiver) {
+                    if !wait_running(&mut receiver, config.random_delay()).await {
+                        continue;
+                    }
+                    let result = wallet.auto_mix_deposit(&config, &task_status).await;
+                    let mut status = task_status.lock().unwrap();
+                    match result {
+                        Ok(Some(signature)) => {
+                            status.last_deposit = Some(signature);
+                            status.last_error = None;
+                        }
+                        Ok(None) => {}
+                        Err(e) => status.last_error = Some(e.to_string()),
+                    }
+                }
+            })
+        });
+
+        Ok(AutoMixHandle { state, status, task })
+    }
+
+    /// Make the next auto-mix deposit, if the balance and today's limits
+    /// allow one. Returns its signature.
+    async fn auto_mix_deposit(
+        &self,
+        config: &AutoMixConfig,
+        status: &Mutex<AutoMixStatus>,
+    ) -> Result<Option<String>> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        let now = auto_mix::unix_now();
+        let remaining = status.lock().unwrap().today.remaining(config, now);
+        if remaining == 0 {
+            return Ok(None);
+        }
+        let available = self.get_balance().await?.saturating_sub(config.reserve);
+        let pools = client.privacy_pool().list_pools().await?;
+        let Some(pool) =
+            auto_mix::choose_pool(&pools, available, remaining, self.config.min_pool_size)
+        else {
+            return Ok(None);
+        };
+        let (pool_id, amount) = (pool.pool_id, pool.denomination.unwrap_or_default());
+
+        let (signature, _, _) = self.deposit_to_pool(pool_id, &self.public_key(), amount).await?;
+        let today = {
+            let mut status = status.lock().unwrap();
+            status.today.record(amount, now);
+            status.today
+        };
+        self.storage.store_auto_mix_usage(&today)?;
+        Ok(Some(signature))
+    }
+
+    /// Start merging the active account's dust notes in the background,
+    /// two at a time by a private transfer to itself, while the account
+    /// has been idle for `config.idle_period`. Merges wait a random delay
+    /// and are limited per day, and never join notes that came out of one
+    /// recent transaction, so the merges do not link the notes themselves.
+    /// They move no value out of the wallet and are not recorded in the
+    /// history.
+    pub fn start_consolidation(
+        self: &Arc<Self>,
+        config: ConsolidationConfig,
+    ) -> Result<ConsolidationHandle> {
+        use auto_mix::{is_stopped, wait_running, AutoMixState};
+
+        if self.privacy_client.is_none() {
+            return Err(anyhow!("Privacy client not initialized"));
+        }
+        if self.ledger.is_some() {
+            return Err(anyhow!("Consolidation cannot run while a Ledger is connected"));
+        }
+        if self.signing_adapter.is_some() {
+            return Err(anyhow!("Consolidation cannot run while an adapter signs"));
+        }
+
+        // Like auto-mix, the task needs a thread and runtime of its own
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let (state, mut receiver) = watch::channel(AutoMixState::Running);
+        let status = Arc::new(Mutex::new(ConsolidationStatus {
+            today: self.storage.consolidation_usage()?,
+            ..ConsolidationStatus::default()
+        }));
+        let wallet = self.clone();
+        let task_status = status.clone();
+        let task = std::thread::spawn(move || {
+            runtime.block_on(async {
+                while !is_stopped(&receiver) {
+                    if !wait_running(&mut receiver, config.random_delay()).await {
+                        continue;
+                    }
+                    let result = wallet.consolidate_dust(&config, &task_status).await;
+                    let mut status = task_status.lock().unwrap();
+                    match result {
+                        Ok(Some(signature)) => {
+                            status.last_merge = Some(signature);
+                            status.last_error = None;
+                        }
+                        Ok(None) => {}
+                        Err(e) => status.last_error = Some(e.to_string()),
+                    }
+                }
+            })
+        });
+
+        Ok(ConsolidationHandle { state, status, task })
+    }
+
+    /// Merge two dust notes of the active account, if it has been idle and
+    /// today's limit allows. Returns the merge's signature.
+    async fn consolidate_dust(
+        &self,
+        config: &ConsolidationConfig,
+        status: &Mutex<ConsolidationStatus>,
+    ) -> Result<Option<String>> {
+        let client = self.signing_client()?;
+
+        let now = auto_mix::unix_now();
+        if !status.lock().unwrap().today.allows(config, now) {
+            return Ok(None);
+        }
+        let filter = HistoryFilter {
+            account: Some(self.active_account),
+            since: Some(now.saturating_sub(config.idle_period.as_secs()) as i64),
+            limit: Some(1),
+            ..HistoryFilter::default()
+        };
+        if !self.history.query(&filter, &AddressBook::default())?.is_empty() {
+            return Ok(None);
+        }
+        let Some(inputs) =
+            consolidation::choose_merge(&self.spendable_notes()?, config, now as i64)
+        else {
+            return Ok(None);
+        };
+
+        let amount = inputs.iter().map(|note| note.amount).sum();
+        let (signature, [merged, _]) = client
+            .private_transfer()
+            .transfer(&inputs, &self.public_key(), amount, self.config.default_privacy_level)
+            .await?;
+        for input in &inputs {
+            self.storage.remove_note(self.active_account, &input.commitment())?;
+        }
+        let signature = signature.to_string();
+        self.keep_note(merged, &signature)?;
+
+        let today = {
+            let mut status = status.lock().unwrap();
+            status.today.record(now);
+            status.today
+        };
+        self.storage.store_consolidation_usage(&today)?;
+        Ok(Some(signature))
+    }
+
+    /// Pool deposits of the active account, oldest first, with their
+    /// status
+    pub fn deposits(&self) -> Result<Vec<DepositNote>> {
+        self.storage.deposits(self.active_account)
+    }
+
+    /// Rules transfers out of the wallet are checked against
+    pub fn spending_policy(&self) -> &SpendingPolicy {
+        self.policy.policy()
+    }
+
+    /// Check every private transfer, bridge and withdrawal against `policy`
+    /// before it is built. A refused transfer fails with a
+    /// `PolicyViolation`.
+    pub fn set_spending_policy(&mut self, policy: SpendingPolicy) {
+        self.policy.set_policy(policy);
+    }
+
+    /// Ask `confirm` to approve transfers over an asset's `confirm_above`;
+    /// without it they are refused
+    pub fn set_spend_confirmation(
+        &mut self,
+        confirm: impl Fn(&SpendRequest) -> bool + Send + Sync + 'static,
+    ) {
+        self.policy.set_confirmation(Some(Arc::new(confirm)));
+    }
+
+    /// Amount of `asset` sent today, counted against its daily limit
+    pub fn spent_today(&self, asset: &Pubkey) -> u64 {
+        self.policy.spent_today(asset, auto_mix::unix_now())
+    }
+
+    /// Run `send` if the policy allows `request`, uncounting it from
+    /// today's spending if it fails
+    async fn with_policy<T>(
+        &self,
+        request: &SpendRequest,
+        send: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let now = auto_mix::unix_now();
+        self.policy.authorize(request, now)?;
+        let result = send.await;
+        if result.is_err() {
+            self.policy.release(request, now);
+        }
+        result
+    }
+
+    /// Local record of the wallet's operations, for an activity feed
+    pub fn history(&self) -> &TransactionHistory {
+        &self.history
+    }
+
+    /// History entries matching `filter`, newest first, with their
+    /// counterparties' labels from the address book
+    pub fn activity(&self, filter: &HistoryFilter) -> Result<Vec<Activity>> {
+        self.history.query(filter, &self.storage.address_book()?)
+    }
+
+    /// Add `activity` to the history. The transaction already landed, so
+    /// failing to record it must not fail the operation; it is logged
+    /// instead.
+    fn record_activity(&self, activity: Activity) {
+        let (kind, signature, amount) =
+            (activity.kind, activity.signature.clone(), activity.amount);
+        let confirmed = activity.status == ActivityStatus::Confirmed;
+        if let Err(error) = self.history.record(activity) {
+            log::warn!("Could not record {:?} {} in the history: {}", kind, signature, error);
+        }
+        if confirmed {
+            self.events.emit(WalletEvent::TransferConfirmed { kind, signature, amount });
+        }
+    }
+
+    /// Events of the wallet from now on. Transfers it sends are reported
+    /// as they land; the rest needs `watch_events` running.
+    pub fn subscribe(&self) -> broadcast::Receiver<WalletEvent> {
+        self.events.subscribe()
+    }
+
+    /// Follow the chain in the background for the active account's events:
+    /// its balance changing and notes sent to it, over the client's
+    /// websocket subscriptions, its outbound bridge transfers advancing,
+    /// and the pools holding its deposits staying under `min_pool_size`.
+    /// Notes found are kept in storage. Needs the privacy client
+    /// initialized.
+    pub fn watch_events(self: &Arc<Self>) -> Result<EventWatcher> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+        client.ws_url()?;
+
+        // As for auto-mix, the watcher gets a thread and runtime of its own
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let (stop, mut stopped) = watch::channel(false);
+        let wallet = self.clone();
+        let task = std::thread::spawn(move || {
+            runtime.block_on(async {
+                if let Err(error) = wallet.follow_events(&mut stopped).await {
+                    log::warn!("Wallet event watcher stopped: {}", error);
+                }
+            })
+        });
+
+        Ok(EventWatcher { stop, task })
+    }
+
+    async fn follow_events(&self, stopped: &mut watch::Receiver<bool>) -> Result<()> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        let cross_chain = client.cross_chain();
+        let mut balances = pin!(client.subscribe_lamports(self.public_key())?);
+        let mut transactions = pin!(client.subscribe_program_transactions()?);
+        let mut bridges = pin!(cross_chain.subscribe_events(&[]));
+        let mut scanner =
+            NoteScanner::new(self.keys.with_keys(|keys| Ok(viewing_key(&keys.keypair)))?);
+        let mut warned = HashSet::new();
+
+        // Catch up on what arrived while nothing was watching
+        self.scan_for_events(client, &mut scanner, &mut warned).await;
+        loop {
+            tokio::select! {
+                // Stopped, or the handle dropped
+                _ = stopped.changed() => return Ok(()),
+                Some(update) = balances.next() => match update {
+                    Ok(lamports) => self.events.emit(WalletEvent::BalanceChanged { lamports }),
+                    Err(error) => log::warn!("Balance subscription: {}", error),
+                },
+                // Any transaction of the program may carry a note or a deposit
+                Some(update) = transactions.next() => match update {
+                    Ok(_) => self.scan_for_events(client, &mut scanner, &mut warned).await,
+                    Err(error) => log::warn!("Program subscription: {}", error),
+                },
+                Some(event) = bridges.next() => match event {
+                    Ok(event) => self.events.emit(WalletEvent::BridgeStatusChanged(event)),
+                    Err(error) => log::warn!("Bridge tracking: {}", error),
+                },
+            }
+        }
+    }
+
+    /// Keep and report notes sent to the active account since the last
+    /// scan, and report the pools holding its deposits that are too small
+    async fn scan_for_events(
+        &self,
+        client: &UntraceClient,
+        scanner: &mut NoteScanner,
+        warned: &mut HashSet<u64>,
+    ) {
+        match scanner.scan(client).await {
+            Ok(found) => {
+                let known = self.notes().unwrap_or_default();
+                for discovered in found.into_iter().filter(|found| !known.contains(&found.note)) {
+                    match self.save_note(discovered.note.clone()) {
+                        Ok(()) => self.events.emit(WalletEvent::NoteReceived {
+                            note: discovered.note,
+                            leaf_index: discovered.leaf_index,
+                        }),
+                        Err(error) => log::warn!("Could not keep a received note: {}", error),
+                    }
+                }
+            }
+            Err(error) => log::warn!("Note scan failed: {}", error),
+        }
+
+        match client.privacy_pool().list_pools().await {
+            Ok(pools) => {
+                let deposits = self.deposits().unwrap_or_default();
+                let min_size = self.config.min_pool_size;
+                for event in events::low_anonymity_events(&pools, &deposits, min_size, warned) {
+                    self.events.emit(event);
+                }
+            }
+            Err(error) => log::warn!("Could not list pools: {}", error),
+        }
+    }
+
+    /// Get wallet balance, in lamports of SOL
+    pub async fn get_balance(&self) -> Result<u64> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        let balance = client.rpc_client.get_balance(&self.public_key()).await?;
+        Ok(balance)
+    }
+
+    /// SPL token balances of the wallet, one per mint it holds a token
+    /// account for, with each mint's symbol and decimals for display
+    pub async fn get_token_balances(&self) -> Result<Vec<TokenBalance>> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        tokens::token_balances(&client.rpc_client, &client.token_registry, &self.public_key()).await
+    }
+
+    /// Decimals, symbol and name of `mint`
+    pub async fn token_metadata(&self, mint: &Pubkey) -> Result<TokenMetadata> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        let mut metadata =
+            tokens::resolve_metadata(&client.rpc_client, &client.token_registry, &[*mint]).await?;
+        Ok(metadata.remove(0))
+    }
+
+    /// Value of the active account's stored notes of `asset_id`, an SPL
+    /// mint or `NATIVE_ASSET_ID` for SOL
+    pub fn shielded_balance(&self, asset_id: &Pubkey) -> Result<u64> {
+        Ok(self.notes()?
+            .iter()
+            .filter(|note| note.asset_id == *asset_id)
+            .fold(0, |sum, note| sum.saturating_add(note.amount)))
+    }
+
+    /// Itemized cost of a deposit, withdrawal or bridge, to show before the
+    /// user confirms it
+    pub async fn estimate_cost(&self, operation: Operation) -> Result<CostEstimate> {
+        let client = self.privacy_client.as_ref()
+            .ok_or_else(|| anyhow!("Privacy client not initialized"))?;
+
+        Ok(client.estimate_cost(operation).await?)
+    }
+
+    /// Export wallet as a JSON keystore encrypted with `password`
+    pub fn export_encrypted(&self, password: &str) -> Result<String> {
+        self.keys.with_keys(|keys| self.storage.export_wallet(&keys.keypair, password))
+    }
+
+    /// Back up everything the wallet keeps: its keys, each account's
+    /// viewing key, notes and deposits, the address book and the history,
+    /// as one archive encrypted with `password`
+    pub fn export_backup(&self, password: &str) -> Result<String> {
+        let mut backup =
+            self.keys.with_keys(|keys| Ok(WalletBackup::new(keys.seed.as_ref(), &keys.keypair)))?;
+        backup.encrypted_recovery_phrase = self.storage.encrypted_seed_phrase()?;
+        for (account, _) in self.accounts() {
+            backup.add_account(
+                account,
+                self.storage.notes(account)?,
+                self.storage.deposits(account)?,
+            )?;
+        }
+        backup.address_book = self.storage.address_book()?;
+        backup.history = self
+            .history
+            .query(&HistoryFilter::default(), &AddressBook::new())?;
+        backup.history.reverse();
+        backup::encrypt_backup(&backup, password)
+    }
+
+    /// Open the wallet of a backup from `backup::decrypt_backup`, restoring
+    /// the parts `options` pick
+    pub fn from_backup(
+        backup: &WalletBackup,
+        options: &RestoreOptions,
+        config: WalletConfig,
+    ) -> Result<Self> {
+        let keypair = backup.keypair(0)?;
+        let storage = open_storage(&config, &keypair)?;
+        let history = open_history(&config, &keypair)?;
+
+        let mut wallet = Self {
+            accounts: vec![keypair.pubkey()],
+            keys: KeyVault::new(keypair, backup.seed()?),
+            active_account: 0,
+            ledger: None,
+            privacy_client: None,
+            adapters: HashMap::new(),
+            signing_adapter: None,
+            preview_handler: None,
+            send_middleware: None,
+            note_selector: Arc::new(FewestNotes),
+            config,
+            storage,
+            tree_storage: Arc::new(MemoryStorage::new()),
+            history,
+            policy: PolicyEngine::default(),
+            events: EventBus::new(),
+        };
+        wallet.restore_backup(backup, options)?;
+        Ok(wallet)
+    }
+
+    /// Add the parts of `backup` that `options` pick to what the wallet
+    /// holds, deriving its accounts as needed. What the wallet already has
+    /// wins: deposits and contacts it knows keep their state, and history
+    /// entries it has are not added again.
+    pub fn restore_backup(
+        &mut self,
+        backup: &WalletBackup,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        if backup.public_key()? != self.accounts[0] {
+            return Err(anyhow!("Backup is of another wallet"));
+        }
+        if let Some(encrypted) = &backup.encrypted_recovery_phrase {
+            if self.storage.encrypted_seed_phrase()?.is_none() {
+                self.storage.restore_encrypted_seed_phrase(encrypted)?;
+            }
+        }
+
+        for account in backup.accounts.iter().filter(|account| options.includes(account.account)) {
+            while self.accounts.len() <= account.account as usize {
+                self.create_account()?;
+            }
+            if options.notes {
+                for note in &account.notes {
+                    self.storage.store_note(account.account, note.clone())?;
+                }
+            }
+            if options.deposits {
+                for deposit in &account.deposits {
+                    if self.storage.deposit(account.account, &deposit.commitment)?.is_none() {
+                        self.storage.store_deposit(account.account, deposit)?;
+                    }
+                }
+            }
+        }
+
+        if options.address_book {
+            let mut book = self.storage.address_book()?;
+            for contact in backup.address_book.contacts() {
+                if book.get(&contact.address).is_none() {
+                    book.add(&contact.address, &contact.label)?.tags = contact.tags.clone();
+                }
+            }
+            self.storage.store_address_book(&book)?;
+        }
+
+        if options.history {
+            let recorded: HashSet<(u32, String)> = self
+                .history
+                .query(&HistoryFilter::default(), &AddressBook::new())?
+                .into_iter()
+                .map(|activity| (activity.account, activity.signature))
+                .collect();
+            for activity in &backup.history {
+                let key = (activity.account, activity.signature.clone());
+                if options.includes(activity.account) && !recorded.contains(&key) {
+                    self.history.record(activity.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Import wallet from a keystore, or an export of earlier versions. A
+    /// wrong password for a keystore fails with `KeystoreError::WrongPassword`.
+    pub fn import_encrypted(encrypted: &str, password: &str, config: WalletConfig) -> Result<Self> {
+        let keypair = SecureStorage::new()?.import_wallet(encrypted, password)?;
+        let storage = open_storage(&config, &keypair)?;
+        let history = open_history(&config, &keypair)?;
+
+        Ok(Self {
+            accounts: vec![keypair.pubkey()],
+            keys: KeyVault::new(keypair, None),
+            active_account: 0,
+            ledger: None,
+            privacy_client: None,
+            adapters: HashMap::new(),
+            signing_adapter: None,
+            preview_handler: None,
+            send_middleware: None,
+            note_selector: Arc::new(FewestNotes),
+            config,
+            storage,
+            tree_storage: Arc::new(MemoryStorage::new()),
+            history,
+            policy: PolicyEngine::default(),
+            events: EventBus::new(),
+        })
+    }
+
+    /// Import the unencrypted keypair file of the Solana CLI (`id.json`),
+    /// whose account becomes the wallet's only one
+    pub fn from_solana_keypair(json: &str, config: WalletConfig) -> Result<Self> {
+        Self::from_keypair(keystore::keypair_from_json(json)?, config)
+    }
+
+    /// Import the Solana CLI keypair file at `path`, such as
+    /// `~/.config/solana/id.json`
+    pub fn from_solana_keypair_file(path: impl AsRef<Path>, config: WalletConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_solana_keypair(&json, config)
+    }
+
+    /// Restore the keypair the Solana CLI derives from a recovery phrase,
+    /// by default the one `solana-keygen new` and `recover` make, or the
+    /// one at `derivation_path` when the CLI was given one. Unlike
+    /// `from_mnemonic` the phrase is not kept and no further accounts can
+    /// be derived, since the CLI's default keypair is not on the path
+    /// other Solana wallets derive accounts at.
+    pub fn from_solana_cli_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+        derivation_path: Option<&str>,
+        config: WalletConfig,
+    ) -> Result<Self> {
+        let keypair = keypair_from_cli_mnemonic(phrase, passphrase, derivation_path)?;
+        Self::from_keypair(keypair, config)
+    }
+
+    /// Active account's keypair in the Solana CLI keypair file format, for
+    /// `solana` and `solana-keygen` to use. The secret key is not
+    /// encrypted.
+    pub fn export_solana_keypair(&self) -> Result<String> {
+        self.keys.with_keys(|keys| Ok(keystore::keypair_to_json(&keys.keypair)))
+    }
+}
+
+/// Storage for the wallet whose first account is `keypair`'s, in the
+/// config's backend, or else the OS keychain if the config names a service
+fn open_storage(config: &WalletConfig, keypair: &Keypair) -> Result<SecureStorage> {
+    let backend: Arc<dyn StorageBackend> = if let Some(backend) = &config.storage_backend {
+        backend.clone()
+    } else if let Some(service) = &config.keychain_service {
+        Arc::new(KeychainBackend::new(format!("{}/{}", service, keypair.pubkey())))
+    } else {
+        Arc::new(MemoryBackend::new())
+    };
+    SecureStorage::with_backend(backend, keypair)
+}
+
+/// Transaction history at the config's path, or in memory, sealed under
+/// the key of the wallet whose first account is `keypair`'s
+fn open_history(config: &WalletConfig, keypair: &Keypair) -> Result<TransactionHistory> {
+    match &config.history_path {
+        Some(path) => TransactionHistory::open(path, keypair),
+        None => TransactionHistory::temporary(),
+    }
+}
+
+/// Sign `request` as the client's payer, through its transaction signer
+/// if it has one, which previews the transaction or hands it to an adapter
+async fn sign_request(client: &UntraceClient, request: &mut SigningRequest) -> Result<()> {
+    let Some(signer) = &client.transaction_signer else {
+        return request.sign(client.payer.as_ref());
+    };
+    let transaction = bincode::serialize(&request.transaction)?;
+    let mut signed = request.clone();
+    signed.transaction = bincode::deserialize(&signer.sign_transaction(transaction).await?)?;
+    // Only valid signatures are merged in, so check that the signer's is
+    request.merge(&signed)?;
+    if request.missing_signers().contains(&signer.pubkey()) {
+        return Err(anyhow!("{} did not sign the request", signer.pubkey()));
+    }
+    Ok(())
+}
+
+/// Disconnect an adapter the wallet has let go of, which only fails if a
+/// privacy client still signs through it
+async fn close_adapter(mut adapter: Arc<dyn WalletAdapter>) -> Result<()> {
+    match Arc::get_mut(&mut adapter) {
+        Some(adapter) => adapter.disconnect().await,
+        None => Err(anyhow!("Adapter is still signing transactions")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mnemonic::MnemonicError;
+
+    #[test]
+    fn test_wallet_creation() {
+        let config = WalletConfig::default();
+        let wallet = UntraceWallet::new(config).unwrap();
+
+        let pubkey = wallet.public_key();
+        assert_ne!(pubkey, Pubkey::default());
+    }
+
+    #[test]
+    fn test_wallet_export_import() {
+        let config = WalletConfig::default();
+        let wallet = UntraceWallet::new(config.clone()).unwrap();
+        let original_pubkey = wallet.public_key();
+
+        let password = "test_password_123";
+        let encrypted = wallet.export_encrypted(password).unwrap();
+
+        let imported_wallet = UntraceWallet::import_encrypted(&encrypted, password, config).unwrap();
+        let imported_pubkey = imported_wallet.public_key();
+
+        assert_eq!(original_pubkey, imported_pubkey);
+    }
+
+    #[test]
+    fn test_wallet_backup_restore() {
+        let config = WalletConfig::default();
+        let (mut wallet, _) =
+            UntraceWallet::generate_with_mnemonic(WordCount::Words12, "", "pw", config.clone())
+                .unwrap();
+        let (_, second) = wallet.create_account().unwrap();
+        wallet.switch_account(1).unwrap();
+        let note = ShieldedNote {
+            asset_id: untrace_common::NATIVE_ASSET_ID,
+            amount: 5,
+            owner: second,
+            randomness: [7u8; 32],
+        };
+        wallet.save_note(note.clone()).unwrap();
+        let mut book = AddressBook::new();
+        book.add(&Pubkey::new_unique().to_string(), "Alice").unwrap();
+        wallet.save_address_book(&book).unwrap();
+        wallet.record_activity(Activity::new(1, ActivityKind::PrivateTransfer, "sig".into(), 5));
+
+        let archive = wallet.export_backup("backup-pw").unwrap();
+        let backup = backup::decrypt_backup(&archive, "backup-pw").unwrap();
+
+        let options = RestoreOptions {
+            history: false,
+            ..RestoreOptions::default()
+        };
+        let mut restored = UntraceWallet::from_backup(&backup, &options, config).unwrap();
+        assert_eq!(restored.accounts(), wallet.accounts());
+        assert_eq!(restored.recovery_phrase("pw").unwrap(), wallet.recovery_phrase("pw").unwrap());
+        assert_eq!(restored.address_book().unwrap(), book);
+        assert!(restored.activity(&HistoryFilter::default()).unwrap().is_empty());
+        restored.switch_account(1).unwrap();
+        assert_eq!(restored.notes().unwrap(), vec![note]);
+
+        // Restoring the rest later adds the history once
+        restored.restore_backup(&backup, &RestoreOptions::default()).unwrap();
+        restored.restore_backup(&backup, &RestoreOptions::default()).unwrap();
+        assert_eq!(restored.activity(&HistoryFilter::default()).unwrap().len(), 1);
+
+        let mut other = UntraceWallet::new(WalletConfig::default()).unwrap();
+        assert!(other.restore_backup(&backup, &options).is_err());
+    }
+
+    #[test]
+    fn test_wallet_from_mnemonic() {
+        let config = WalletConfig::default();
+        let (wallet, phrase) =
+            UntraceWallet::generate_with_mnemonic(WordCount::Words12, "", "pw", config.clone())
+                .unwrap();
+        assert_eq!(wallet.recovery_phrase("pw").unwrap(), phrase);
+
+        let restored = UntraceWallet::from_mnemonic(&phrase, "", "other", config.clone()).unwrap();
+        assert_eq!(restored.public_key(), wallet.public_key());
+
+        let err = UntraceWallet::from_mnemonic("not a phrase", "", "pw", config).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<MnemonicError>(),
+            Some(&MnemonicError::InvalidWordCount(3))
+        );
+    }
+
+    #[test]
+    fn test_wallet_accounts() {
+        let config = WalletConfig::default();
+        let (mut wallet, phrase) =
+            UntraceWallet::generate_with_mnemonic(WordCount::Words12, "", "pw", config.clone())
+                .unwrap();
+        let first = wallet.public_key();
+        let (account, second) = wallet.create_account().unwrap();
+        assert_eq!(account, 1);
+        assert_eq!(wallet.accounts(), vec![(0, first), (1, second)]);
+        assert_eq!(wallet.public_key(), first);
+
+        let note = |owner| ShieldedNote {
+            asset_id: untrace_common::NATIVE_ASSET_ID,
+            amount: 5,
+            owner,
+            randomness: [7u8; 32],
+        };
+        wallet.save_note(note(first)).unwrap();
+        wallet.switch_account(1).unwrap();
+        assert_eq!(wallet.public_key(), second);
+        assert!(wallet.notes().unwrap().is_empty());
+        wallet.save_note(note(second)).unwrap();
+        wallet.switch_account(0).unwrap();
+        assert_eq!(wallet.notes().unwrap(), vec![note(first)]);
+        assert!(wallet.switch_account(2).is_err());
+
+        // The same phrase derives the same accounts
+        let mut restored = UntraceWallet::from_mnemonic(&phrase, "", "pw", config.clone()).unwrap();
+        assert_eq!(restored.create_account().unwrap(), (1, second));
+
+        let mut plain = UntraceWallet::new(config).unwrap();
+        assert!(plain.create_account().is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use std::rc::Rc;
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::{CustomEvent, CustomEventInit};
+
+use crate::wasm::{into_js_error, js_error, WasmWallet};
+
+/// Version of the Wallet Standard and of each feature the wallet implements
+const STANDARD_VERSION: &str = "1.0.0";
+
+/// Event a wallet dispatches to announce itself to apps already loaded
+const REGISTER_EVENT: &str = "wallet-standard:register-wallet";
+
+/// Event an app dispatches when it loads, asking wallets to register again
+const APP_READY_EVENT: &str = "wallet-standard:app-ready";
+
+/// Features listed on the wallet's account
+const ACCOUNT_FEATURES: [&str; 4] = [
+    "solana:signAndSendTransaction",
+    "solana:signTransaction",
+    "solana:signMessage",
+    "untrace:privacy",
+];
+
+#[wasm_bindgen(inline_js = "export function variadic(method) { \
+    return (...inputs) => method(inputs); }")]
+extern "C" {
+    /// Function passing all its arguments to `method` as one array, since
+    /// the standard's sign methods take any number of inputs
+    fn variadic(method: &JsValue) -> JsValue;
+}
+
+/// Register `wallet` as a Wallet Standard wallet named `name` on `chain`
+/// (`solana:mainnet`, `solana:devnet`, ...), so dapps discover it like any
+/// other wallet. `icon` is the data URI they show for it. Besides
+/// `standard:connect`, `standard:disconnect`, `standard:events` and the
+/// `solana:` sign features, it offers `untrace:privacy`, whose
+/// `recipientWarning(address)` tells why shielded funds should not go to
+/// an address and whose `deposits()` lists pool deposits as JSON. Returns
+/// the registered wallet object.
+#[wasm_bindgen(js_name = registerWalletStandard)]
+pub fn register_wallet_standard(
+    wallet: WasmWallet,
+    name: String,
+    icon: String,
+    chain: String,
+) -> Result<JsValue, JsError> {
+    let window = web_sys::window()
+        .ok_or_else(|| JsError::new("The Wallet Standard is only available on a page"))?;
+    let wallet = Rc::new(wallet);
+    let account = account(&wallet, &chain).map_err(into_js_error)?;
+    let standard = object(&[
+        ("version", STANDARD_VERSION.into()),
+        ("name", name.into()),
+        ("icon", icon.into()),
+        ("chains", Array::of1(&chain.as_str().into()).into()),
+        ("accounts", Array::of1(&account).into()),
+        (
+            "features",
+            features(wallet, account, chain)
+                .map_err(into_js_error)?
+                .into(),
+        ),
+    ])
+    .map_err(into_js_error)?;
+
+    let register = {
+        let standard = standard.clone();
+        Closure::<dyn Fn(JsValue)>::new(move |api: JsValue| {
+            let register = Reflect::get(&api, &"register".into())
+                .ok()
+                .and_then(|register| register.dyn_into::<Function>().ok());
+            if let Some(register) = register {
+                if let Err(err) = register.call1(&api, &standard) {
+                    log::warn!("Wallet Standard registration failed: {:?}", err);
+                }
+            }
+        })
+        .into_js_value()
+    };
+
+    let init = CustomEventInit::new();
+    init.set_detail(&register);
+    let event =
+        CustomEvent::new_with_event_init_dict(REGISTER_EVENT, &init).map_err(js_into_error)?;
+    window.dispatch_event(&event).map_err(js_into_error)?;
+
+    // Apps loaded after the wallet announce themselves and are answered
+    // the same way
+    let app_ready = Closure::<dyn Fn(CustomEvent)>::new(move |event: CustomEvent| {
+        let register: &Function = register.unchecked_ref();
+        if let Err(err) = register.call1(&JsValue::NULL, &event.detail()) {
+            log::warn!("Wallet Standard registration failed: {:?}", err);
+        }
+    })
+    .into_js_value();
+    window
+        .add_event_listener_with_callback(APP_READY_EVENT, app_ready.unchecked_ref())
+        .map_err(js_into_error)?;
+
+    Ok(standard.into())
+}
+
+/// The wallet's one account, with the features it can be used with
+fn account(wallet: &WasmWallet, chain: &str) -> Result<JsValue> {
+    let features: Array = ACCOUNT_FEATURES
+        .iter()
+        .map(|f| JsValue::from_str(f))
+        .collect();
+    let account = object(&[
+        ("address", wallet.public_key().into()),
+        (
+            "publicKey",
+            Uint8Array::from(wallet.pubkey().as_ref()).into(),
+        ),
+        ("chains", Array::of1(&chain.into()).into()),
+        ("features", features.into()),
+    ])?;
+    Ok(account.into())
+}
+
+fn features(wallet: Rc<WasmWallet>, account: JsValue, chain: String) -> Result<Object> {
+    let supported_versions: JsValue = Array::of2(&"legacy".into(), &0.into()).into();
+
+    // The account is always available while the wallet is open, so
+    // connecting only hands it over and it never changes
+    let accounts: JsValue = object(&[("accounts", Array::of1(&account).into())])?.into();
+    let connect = Closure::<dyn Fn(JsValue) -> Promise>::new(move |_input: JsValue| {
+        Promise::resolve(&accounts)
+    });
+    let disconnect = Closure::<dyn Fn() -> Promise>::new(|| Promise::resolve(&JsValue::UNDEFINED));
+    let on = Closure::<dyn Fn(JsValue, JsValue) -> JsValue>::new(|_event, _listener| {
+        Closure::<dyn Fn()>::new(|| {}).into_js_value()
+    });
+
+    let sign_and_send = {
+        let (wallet, chain) = (wallet.clone(), chain.clone());
+        sign_method(move |inputs| {
+            let (wallet, chain) = (wallet.clone(), chain.clone());
+            async move {
+                let mut outputs = Vec::with_capacity(inputs.len());
+                for input in inputs {
+                    check_input(&wallet, &input, &chain)?;
+                    let signed = wallet.sign_wire_transaction(&bytes(&input, "transaction")?)?;
+                    let signature = wallet.rpc().send_wire_transaction(&signed).await?;
+                    outputs.push(object(&[(
+                        "signature",
+                        Uint8Array::from(signature.as_ref()).into(),
+                    )])?);
+                }
+                Ok(outputs)
+            }
+        })
+    };
+    let sign_transaction = {
+        let (wallet, chain) = (wallet.clone(), chain.clone());
+        sign_method(move |inputs| {
+            let (wallet, chain) = (wallet.clone(), chain.clone());
+            async move {
+                inputs
+                    .iter()
+                    .map(|input| {
+                        check_input(&wallet, input, &chain)?;
+                        let signed = wallet.sign_wire_transaction(&bytes(input, "transaction")?)?;
+                        object(&[(
+                            "signedTransaction",
+                            Uint8Array::from(signed.as_slice()).into(),
+                        )])
+                    })
+                    .collect()
+            }
+        })
+    };
+    let sign_message = {
+        let wallet = wallet.clone();
+        sign_method(move |inputs| {
+            let wallet = wallet.clone();
+            async move {
+                inputs
+                    .iter()
+                    .map(|input| {
+                        check_account(&wallet, input)?;
+                        let message = bytes(input, "message")?;
+                        let signature = wallet.sign_message(&message);
+                        object(&[
+                            ("signedMessage", Uint8Array::from(message.as_slice()).into()),
+                            ("signature", Uint8Array::from(signature.as_ref()).into()),
+                        ])
+                    })
+                    .collect()
+            }
+        })
+    };
+
+    let recipient_warning = {
+        let wallet = wallet.clone();
+        Closure::<dyn Fn(String) -> Promise>::new(move |address: String| {
+            match wallet.recipient_warning(&address) {
+                Ok(warning) => {
+                    let warning: JsValue = warning.map_or(JsValue::NULL, JsValue::from);
+                    Promise::resolve(&warning)
+                }
+                Err(err) => Promise::reject(&into_js_error(err).into()),
+            }
+        })
+    };
+    let deposits = Closure::<dyn Fn() -> Promise>::new(move || match wallet.deposits() {
+        Ok(deposits) => Promise::resolve(&JsValue::from(deposits)),
+        Err(err) => Promise::reject(&err.into()),
+    });
+
+    object(&[
+        (
+            "standard:connect",
+            feature(&[("connect", connect.into_js_value())])?,
+        ),
+        (
+            "standard:disconnect",
+            feature(&[("disconnect", disconnect.into_js_value())])?,
+        ),
+        ("standard:events", feature(&[("on", on.into_js_value())])?),
+        (
+            "solana:signAndSendTransaction",
+            feature(&[
+                ("supportedTransactionVersions", supported_versions.clone()),
+                ("signAndSendTransaction", sign_and_send),
+            ])?,
+        ),
+        (
+            "solana:signTransaction",
+            feature(&[
+                ("supportedTransactionVersions", supported_versions),
+                ("signTransaction", sign_transaction),
+            ])?,
+        ),
+        (
+            "solana:signMessage",
+            feature(&[("signMessage", sign_message)])?,
+        ),
+        (
+            "untrace:privacy",
+            feature(&[
+                ("recipientWarning", recipient_warning.into_js_value()),
+                ("deposits", deposits.into_js_value()),
+            ])?,
+        ),
+    ])
+}
+
+/// A sign method taking any number of inputs and resolving to one output
+/// for each, or rejecting if any input fails
+fn sign_method<F, Fut>(sign: F) -> JsValue
+where
+    F: Fn(Vec<JsValue>) -> Fut + 'static,
+    Fut: std::future::Future<Output = Result<Vec<Object>>> + 'static,
+{
+    let method = Closure::<dyn Fn(Array) -> Promise>::new(move |inputs: Array| {
+        let outputs = sign(inputs.to_vec());
+        future_to_promise(async move {
+            let outputs = outputs.await.map_err(into_js_error)?;
+            Ok(outputs.into_iter().collect::<Array>().into())
+        })
+    });
+    variadic(&method.into_js_value())
+}
+
+fn feature(members: &[(&str, JsValue)]) -> Result<JsValue> {
+    let mut members = members.to_vec();
+    members.insert(0, ("version", STANDARD_VERSION.into()));
+    Ok(object(&members)?.into())
+}
+
+fn object(members: &[(&str, JsValue)]) -> Result<Object> {
+    let object = Object::new();
+    for (key, value) in members {
+        Reflect::set(&object, &(*key).into(), value).map_err(js_error)?;
+    }
+    Ok(object)
+}
+
+/// Bytes of the `Uint8Array` field `field` of a sign input
+fn bytes(input: &JsValue, field: &str) -> Result<Vec<u8>> {
+    Reflect::get(input, &field.into())
+        .map_err(js_error)?
+        .dyn_into::<Uint8Array>()
+        .map(|bytes| bytes.to_vec())
+        .map_err(|_| anyhow!("Input has no {} bytes", field))
+}
+
+/// Reject inputs for another account or chain than the wallet's
+fn check_input(wallet: &WasmWallet, input: &JsValue, chain: &str) -> Result<()> {
+    check_account(wallet, input)?;
+    let input_chain = Reflect::get(input, &"chain".into()).map_err(js_error)?;
+    match input_chain.as_string() {
+        Some(input_chain) if input_chain != chain => {
+            Err(anyhow!("Wallet is not on {}", input_chain))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_account(wallet: &WasmWallet, input: &JsValue) -> Result<()> {
+    let account = Reflect::get(input, &"account".into()).map_err(js_error)?;
+    let address = Reflect::get(&account, &"address".into()).map_err(js_error)?;
+    if address.as_string() != Some(wallet.public_key()) {
+        return Err(anyhow!("Input is not for account {}", wallet.public_key()));
+    }
+    Ok(())
+}
+
+fn js_into_error(value: JsValue) -> JsError {
+    into_js_error(js_error(value))
+}
@@ -0,0 +1,556 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use js_sys::{Array, Promise, Reflect, Uint8Array};
+use serde_json::{json, Value};
+use solana_sdk::{
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::{Transaction, VersionedTransaction},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Mutex},
+};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    IdbDatabase, IdbFactory, IdbRequest, IdbTransactionMode, Request, RequestInit, Response,
+    Window, WorkerGlobalScope,
+};
+
+use crate::{
+    address_book::AddressBook,
+    mnemonic::{generate_mnemonic, HdSeed, WordCount},
+    storage::{SecureStorage, StorageBackend},
+};
+
+/// Object store every entry of an `IndexedDbBackend` is kept in
+const ENTRIES_STORE: &str = "entries";
+
+/// Browser global the wallet runs under: a page, or the service worker of
+/// an extension, which has no `window`
+enum Scope {
+    Window(Window),
+    Worker(WorkerGlobalScope),
+}
+
+impl Scope {
+    fn current() -> Self {
+        let global = js_sys::global();
+        if Reflect::has(&global, &JsValue::from_str("document")).unwrap_or(false) {
+            Scope::Window(global.unchecked_into())
+        } else {
+            Scope::Worker(global.unchecked_into())
+        }
+    }
+
+    fn indexed_db(&self) -> Result<IdbFactory> {
+        let factory = match self {
+            Scope::Window(window) => window.indexed_db(),
+            Scope::Worker(worker) => worker.indexed_db(),
+        };
+        factory
+            .map_err(js_error)?
+            .ok_or_else(|| anyhow!("IndexedDB is not available"))
+    }
+
+    fn fetch(&self, request: &Request) -> Promise {
+        match self {
+            Scope::Window(window) => window.fetch_with_request(request),
+            Scope::Worker(worker) => worker.fetch_with_request(request),
+        }
+    }
+}
+
+/// Write waiting to reach IndexedDB; `None` deletes the key
+type PendingWrite = (String, Option<Vec<u8>>);
+
+#[derive(Default)]
+struct IndexedDbState {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+    /// Writes in the order they were made, and whether a task is draining
+    /// them
+    pending: Mutex<(VecDeque<PendingWrite>, bool)>,
+}
+
+/// Backend keeping entries in an IndexedDB database, so a browser wallet's
+/// storage survives reloads. IndexedDB only answers asynchronously, so
+/// `open` reads every entry into memory; reads are served from there, and
+/// writes update it at once and reach the database in the background, in
+/// order.
+#[derive(Clone)]
+pub struct IndexedDbBackend {
+    database: String,
+    state: Arc<IndexedDbState>,
+}
+
+impl fmt::Debug for IndexedDbBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexedDbBackend")
+            .field("database", &self.database)
+            .finish()
+    }
+}
+
+impl IndexedDbBackend {
+    /// Backend over database `database`, created if needed, with its
+    /// entries loaded
+    pub async fn open(database: &str) -> Result<Self> {
+        let db = open_database(database).await?;
+        let store = db
+            .transaction_with_str_and_mode(ENTRIES_STORE, IdbTransactionMode::Readonly)
+            .and_then(|transaction| transaction.object_store(ENTRIES_STORE))
+            .map_err(js_error)?;
+        let keys: Array = settle(&store.get_all_keys().map_err(js_error)?)
+            .await?
+            .unchecked_into();
+        let values: Array = settle(&store.get_all().map_err(js_error)?)
+            .await?
+            .unchecked_into();
+        db.close();
+
+        let entries = keys
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(key, value)| {
+                let value = value.dyn_into::<Uint8Array>().ok()?;
+                Some((key.as_string()?, value.to_vec()))
+            })
+            .collect();
+        Ok(Self {
+            database: database.to_string(),
+            state: Arc::new(IndexedDbState {
+                entries: Mutex::new(entries),
+                ..IndexedDbState::default()
+            }),
+        })
+    }
+
+    fn queue(&self, key: &str, value: Option<Vec<u8>>) {
+        let mut pending = self.state.pending.lock().unwrap();
+        pending.0.push_back((key.to_string(), value));
+        if pending.1 {
+            return;
+        }
+        pending.1 = true;
+
+        let backend = self.clone();
+        spawn_local(async move {
+            loop {
+                let write = {
+                    let mut pending = backend.state.pending.lock().unwrap();
+                    match pending.0.pop_front() {
+                        Some(write) => write,
+                        None => {
+                            pending.1 = false;
+                            return;
+                        }
+                    }
+                };
+                if let Err(error) = backend.write(write).await {
+                    log::error!("IndexedDB write to {} failed: {}", backend.database, error);
+                }
+            }
+        });
+    }
+
+    async fn write(&self, (key, value): PendingWrite) -> Result<()> {
+        let db = open_database(&self.database).await?;
+        let store = db
+            .transaction_with_str_and_mode(ENTRIES_STORE, IdbTransactionMode::Readwrite)
+            .and_then(|transaction| transaction.object_store(ENTRIES_STORE))
+            .map_err(js_error)?;
+        let key = JsValue::from_str(&key);
+        let request = match value {
+            Some(value) => store.put_with_key(&Uint8Array::from(value.as_slice()), &key),
+            None => store.delete(&key),
+        };
+        let result = settle(&request.map_err(js_error)?).await;
+        db.close();
+        result.map(|_| ())
+    }
+}
+
+impl StorageBackend for IndexedDbBackend {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.state.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.state
+            .entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        self.queue(key, Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.state.entries.lock().unwrap().remove(key);
+        self.queue(key, None);
+        Ok(())
+    }
+}
+
+/// Database `name`, with its object store created the first time
+async fn open_database(name: &str) -> Result<IdbDatabase> {
+    let request = Scope::current()
+        .indexed_db()?
+        .open_with_u32(name, 1)
+        .map_err(js_error)?;
+    let upgrading = request.clone();
+    let on_upgrade = Closure::once_into_js(move || {
+        if let Ok(db) = upgrading.result() {
+            let db: IdbDatabase = db.unchecked_into();
+            if !db.object_store_names().contains(ENTRIES_STORE) {
+                db.create_object_store(ENTRIES_STORE).ok();
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+    Ok(settle(&request).await?.unchecked_into())
+}
+
+/// Result of `request` once it succeeds
+async fn settle(request: &IdbRequest) -> Result<JsValue> {
+    let request = request.clone();
+    let promise = Promise::new(&mut |resolve, reject| {
+        let succeeded = request.clone();
+        let on_success = Closure::once_into_js(move || {
+            let result = succeeded.result().unwrap_or(JsValue::UNDEFINED);
+            resolve.call1(&JsValue::UNDEFINED, &result).ok();
+        });
+        let failed = request.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = failed
+                .error()
+                .ok()
+                .flatten()
+                .map(JsValue::from)
+                .unwrap_or(JsValue::UNDEFINED);
+            reject.call1(&JsValue::UNDEFINED, &error).ok();
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+/// Solana JSON-RPC over the browser's `fetch`, for the calls a browser
+/// wallet makes; the privacy client's `RpcClient` does not build for wasm
+#[derive(Debug, Clone)]
+pub struct FetchRpc {
+    url: String,
+}
+
+impl FetchRpc {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Lamports held by `address`
+    pub async fn get_balance(&self, address: &Pubkey) -> Result<u64> {
+        let result = self
+            .call("getBalance", json!([address.to_string()]))
+            .await?;
+        result["value"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("Malformed getBalance response"))
+    }
+
+    pub async fn get_latest_blockhash(&self) -> Result<Hash> {
+        let result = self.call("getLatestBlockhash", json!([])).await?;
+        result["value"]["blockhash"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Malformed getLatestBlockhash response"))?
+            .parse()
+            .map_err(|_| anyhow!("Malformed blockhash"))
+    }
+
+    /// Data of the account at `address`, or `None` if it does not exist
+    pub async fn get_account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self
+            .call(
+                "getAccountInfo",
+                json!([address.to_string(), { "encoding": "base64" }]),
+            )
+            .await?;
+        if result["value"].is_null() {
+            return Ok(None);
+        }
+        let data = result["value"]["data"][0]
+            .as_str()
+            .ok_or_else(|| anyhow!("Malformed getAccountInfo response"))?;
+        Ok(Some(STANDARD.decode(data)?))
+    }
+
+    /// Submit the signed `transaction`, returning its signature
+    pub async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        self.send_wire_transaction(&bincode::serialize(transaction)?)
+            .await
+    }
+
+    /// Submit a signed transaction already in wire format, legacy or
+    /// versioned, returning its signature
+    pub async fn send_wire_transaction(&self, transaction: &[u8]) -> Result<Signature> {
+        let encoded = STANDARD.encode(transaction);
+        let result = self
+            .call(
+                "sendTransaction",
+                json!([encoded, { "encoding": "base64" }]),
+            )
+            .await?;
+        result
+            .as_str()
+            .ok_or_else(|| anyhow!("Malformed sendTransaction response"))?
+            .parse()
+            .map_err(|_| anyhow!("Malformed transaction signature"))
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let init = RequestInit::new();
+        init.set_method("POST");
+        init.set_body(&JsValue::from_str(&body.to_string()));
+        let request = Request::new_with_str_and_init(&self.url, &init).map_err(js_error)?;
+        request
+            .headers()
+            .set("Content-Type", "application/json")
+            .map_err(js_error)?;
+
+        let response: Response = JsFuture::from(Scope::current().fetch(&request))
+            .await
+            .map_err(js_error)?
+            .unchecked_into();
+        if !response.ok() {
+            return Err(anyhow!("{} failed with HTTP {}", method, response.status()));
+        }
+        let text = JsFuture::from(response.text().map_err(js_error)?)
+            .await
+            .map_err(js_error)?
+            .as_string()
+            .unwrap_or_default();
+
+        let mut reply: Value = serde_json::from_str(&text)?;
+        if let Some(error) = reply.get("error") {
+            return Err(anyhow!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        Ok(reply["result"].take())
+    }
+}
+
+/// Wallet for browsers and extensions: keys, encrypted storage in
+/// IndexedDB and transparent transfers over `fetch`. Pool deposits and
+/// withdrawals stay with the native `UntraceWallet`, as Merkle sync and the
+/// withdrawal proofs live in the privacy client.
+#[wasm_bindgen]
+pub struct WasmWallet {
+    keypair: Keypair,
+    storage: SecureStorage,
+    rpc: FetchRpc,
+}
+
+#[wasm_bindgen]
+impl WasmWallet {
+    /// Restore the wallet of a BIP39 recovery phrase, keeping the phrase
+    /// encrypted with `password` in the IndexedDB database named after
+    /// `database` and the wallet's address
+    #[wasm_bindgen(js_name = fromMnemonic)]
+    pub async fn from_mnemonic(
+        phrase: String,
+        passphrase: String,
+        password: String,
+        rpc_url: String,
+        database: String,
+    ) -> Result<WasmWallet, JsError> {
+        let keypair = HdSeed::from_mnemonic(&phrase, &passphrase)
+            .and_then(|seed| seed.derive_account(0))
+            .map_err(into_js_error)?;
+        let mut wallet = Self::open(keypair, &rpc_url, &database).await?;
+        let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+        wallet
+            .storage
+            .store_seed_phrase(&normalized.to_lowercase(), &password)
+            .map_err(into_js_error)?;
+        Ok(wallet)
+    }
+
+    /// Open the wallet of a keystore from `exportEncrypted`
+    #[wasm_bindgen(js_name = importEncrypted)]
+    pub async fn import_encrypted(
+        keystore: String,
+        password: String,
+        rpc_url: String,
+        database: String,
+    ) -> Result<WasmWallet, JsError> {
+        let keypair = SecureStorage::new()
+            .and_then(|storage| storage.import_wallet(&keystore, &password))
+            .map_err(into_js_error)?;
+        Self::open(keypair, &rpc_url, &database).await
+    }
+
+    async fn open(keypair: Keypair, rpc_url: &str, database: &str) -> Result<Self, JsError> {
+        let name = format!("{}/{}", database, keypair.pubkey());
+        let backend = IndexedDbBackend::open(&name).await.map_err(into_js_error)?;
+        Ok(Self {
+            storage: SecureStorage::with_backend(Arc::new(backend), &keypair)
+                .map_err(into_js_error)?,
+            keypair,
+            rpc: FetchRpc::new(rpc_url),
+        })
+    }
+
+    #[wasm_bindgen(js_name = publicKey)]
+    pub fn public_key(&self) -> String {
+        self.keypair.pubkey().to_string()
+    }
+
+    /// Recovery phrase the wallet was restored from, decrypted with
+    /// `password`
+    #[wasm_bindgen(js_name = recoveryPhrase)]
+    pub fn recovery_phrase(&self, password: &str) -> Result<String, JsError> {
+        let encrypted = self
+            .storage
+            .encrypted_seed_phrase()
+            .map_err(into_js_error)?
+            .ok_or_else(|| JsError::new("Wallet was not restored from a recovery phrase"))?;
+        self.storage
+            .retrieve_seed_phrase(&encrypted, password)
+            .map_err(into_js_error)
+    }
+
+    /// Keystore of the wallet's keypair, encrypted with `password`
+    #[wasm_bindgen(js_name = exportEncrypted)]
+    pub fn export_encrypted(&self, password: &str) -> Result<String, JsError> {
+        self.storage
+            .export_wallet(&self.keypair, password)
+            .map_err(into_js_error)
+    }
+
+    /// Transparent balance in lamports
+    pub async fn balance(&self) -> Result<u64, JsError> {
+        self.rpc
+            .get_balance(&self.keypair.pubkey())
+            .await
+            .map_err(into_js_error)
+    }
+
+    /// Send `lamports` of transparent SOL to `recipient`, returning the
+    /// transaction signature
+    #[wasm_bindgen(js_name = sendSol)]
+    pub async fn send_sol(&self, recipient: String, lamports: u64) -> Result<String, JsError> {
+        let recipient: Pubkey = recipient
+            .parse()
+            .map_err(|_| JsError::new(&format!("Invalid recipient: {}", recipient)))?;
+        let blockhash = self
+            .rpc
+            .get_latest_blockhash()
+            .await
+            .map_err(into_js_error)?;
+        let payer = self.keypair.pubkey();
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer, &recipient, lamports)],
+            Some(&payer),
+            &[&self.keypair],
+            blockhash,
+        );
+        let signature = self
+            .rpc
+            .send_transaction(&transaction)
+            .await
+            .map_err(into_js_error)?;
+        Ok(signature.to_string())
+    }
+
+    /// Deposits the native wallet stored for the first account, as JSON
+    pub fn deposits(&self) -> Result<String, JsError> {
+        let deposits = self.storage.deposits(0).map_err(into_js_error)?;
+        serde_json::to_string(&deposits).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Address book, as JSON
+    #[wasm_bindgen(js_name = addressBook)]
+    pub fn address_book(&self) -> Result<String, JsError> {
+        let book = self.storage.address_book().map_err(into_js_error)?;
+        serde_json::to_string(&book).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Replace the address book with `book`, JSON as `addressBook` returns
+    #[wasm_bindgen(js_name = setAddressBook)]
+    pub fn set_address_book(&self, book: &str) -> Result<(), JsError> {
+        let book: AddressBook =
+            serde_json::from_str(book).map_err(|e| JsError::new(&e.to_string()))?;
+        self.storage
+            .store_address_book(&book)
+            .map_err(into_js_error)
+    }
+}
+
+impl WasmWallet {
+    pub(crate) fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub(crate) fn rpc(&self) -> &FetchRpc {
+        &self.rpc
+    }
+
+    /// Add the wallet's signature to a wire-format transaction, legacy or
+    /// v0, leaving any other signer's in place
+    pub(crate) fn sign_wire_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+        let mut transaction: VersionedTransaction = bincode::deserialize(transaction)?;
+        let pubkey = self.keypair.pubkey();
+        let required = transaction.message.header().num_required_signatures as usize;
+        let position = transaction
+            .message
+            .static_account_keys()
+            .iter()
+            .take(required)
+            .position(|key| *key == pubkey)
+            .ok_or_else(|| anyhow!("Transaction does not need {} to sign", pubkey))?;
+
+        transaction.signatures.resize(required, Signature::default());
+        transaction.signatures[position] =
+            self.keypair.sign_message(&transaction.message.serialize());
+        Ok(bincode::serialize(&transaction)?)
+    }
+
+    pub(crate) fn sign_message(&self, message: &[u8]) -> Signature {
+        self.keypair.sign_message(message)
+    }
+
+    /// Why shielded funds should not go to `address`, from the address book
+    pub(crate) fn recipient_warning(&self, address: &str) -> Result<Option<String>> {
+        let book = self.storage.address_book()?;
+        Ok(book
+            .shielded_send_warning(address)
+            .map(|warning| warning.to_string()))
+    }
+}
+
+/// New 12-word recovery phrase for `WasmWallet.fromMnemonic`
+#[wasm_bindgen(js_name = generateMnemonic)]
+pub fn generate_recovery_phrase() -> String {
+    generate_mnemonic(WordCount::Words12)
+}
+
+pub(crate) fn js_error(value: JsValue) -> anyhow::Error {
+    anyhow!(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+}
+
+pub(crate) fn into_js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&error.to_string())
+}